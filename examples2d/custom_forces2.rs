@@ -6,7 +6,7 @@ use rapier2d::geometry::ColliderSet;
 use rapier_testbed2d::Testbed;
 use salva2d::integrations::rapier::{FluidsPipeline, FluidsRenderingMode, FluidsTestbedPlugin};
 use salva2d::object::{Boundary, Fluid};
-use salva2d::solver::NonPressureForce;
+use salva2d::solver::{NonPressureForce, OtherFluids};
 use std::f32;
 
 const PARTICLE_RADIUS: f32 = 0.025;
@@ -71,6 +71,7 @@ impl NonPressureForce for CustomForceField {
         _fluid_fluid_contacts: &salva2d::geometry::ParticlesContacts,
         _fluid_boundaries_contacts: &salva2d::geometry::ParticlesContacts,
         fluid: &mut Fluid,
+        _other_fluids: &OtherFluids,
         _boundaries: &[Boundary],
         _densities: &[f32],
     ) {
@@ -6,7 +6,7 @@ use rapier3d::geometry::ColliderSet;
 use rapier_testbed3d::{Testbed, TestbedApp};
 use salva3d::integrations::rapier::{FluidsPipeline, FluidsRenderingMode, FluidsTestbedPlugin};
 use salva3d::object::{Boundary, Fluid};
-use salva3d::solver::NonPressureForce;
+use salva3d::solver::{NonPressureForce, OtherFluids};
 use std::f32;
 
 #[path = "./helper.rs"]
@@ -76,6 +76,7 @@ impl NonPressureForce for CustomForceField {
         _fluid_fluid_contacts: &salva3d::geometry::ParticlesContacts,
         _fluid_boundaries_contacts: &salva3d::geometry::ParticlesContacts,
         fluid: &mut Fluid,
+        _other_fluids: &OtherFluids,
         _boundaries: &[Boundary],
         _densities: &[f32],
     ) {
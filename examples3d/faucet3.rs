@@ -101,7 +101,7 @@ pub fn init_world(testbed: &mut Testbed) {
             }
         }
 
-        fluid.add_particles(&particles, Some(&velocities));
+        fluid.add_particles(&particles, Some(&velocities), None);
     });
 
     /*
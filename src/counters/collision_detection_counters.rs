@@ -6,6 +6,10 @@ use std::fmt::{Display, Formatter, Result};
 pub struct CollisionDetectionCounters {
     /// Number of contacts detected.
     pub ncontacts: usize,
+    /// Number of times the neighbor lists have been recomputed.
+    ///
+    /// See `ContactManager::set_neighbor_list_skin`.
+    pub neighbor_list_updates: usize,
     /// Time spent updating the boundary particles.
     pub boundary_update_time: Timer,
     /// Time spent for the broad-phase of the collision detection.
@@ -14,6 +18,8 @@ pub struct CollisionDetectionCounters {
     pub neighborhood_search_time: Timer,
     /// Time spent to sort the contacts.
     pub contact_sorting_time: Timer,
+    /// Time spent to sort the fluid particles.
+    pub particle_sorting_time: Timer,
 }
 
 impl CollisionDetectionCounters {
@@ -21,10 +27,12 @@ impl CollisionDetectionCounters {
     pub fn new() -> Self {
         CollisionDetectionCounters {
             ncontacts: 0,
+            neighbor_list_updates: 0,
             boundary_update_time: Timer::new(),
             grid_insertion_time: Timer::new(),
             neighborhood_search_time: Timer::new(),
             contact_sorting_time: Timer::new(),
+            particle_sorting_time: Timer::new(),
         }
     }
 
@@ -34,6 +42,7 @@ impl CollisionDetectionCounters {
         self.grid_insertion_time.enable();
         self.neighborhood_search_time.enable();
         self.contact_sorting_time.enable();
+        self.particle_sorting_time.enable();
     }
 
     /// Disables all the performance counters for collision detection.
@@ -42,21 +51,29 @@ impl CollisionDetectionCounters {
         self.grid_insertion_time.disable();
         self.neighborhood_search_time.disable();
         self.contact_sorting_time.disable();
+        self.particle_sorting_time.disable();
     }
 
     /// Resets all the counters to zero for collision detection.
     pub fn reset(&mut self) {
         self.ncontacts = 0;
+        self.neighbor_list_updates = 0;
         self.boundary_update_time.reset();
         self.grid_insertion_time.reset();
         self.neighborhood_search_time.reset();
         self.contact_sorting_time.reset();
+        self.particle_sorting_time.reset();
     }
 }
 
 impl Display for CollisionDetectionCounters {
     fn fmt(&self, f: &mut Formatter) -> Result {
         writeln!(f, "Number of contacts: {}", self.ncontacts)?;
+        writeln!(
+            f,
+            "Number of neighbor list updates: {}",
+            self.neighbor_list_updates
+        )?;
         writeln!(f, "Boundary update time: {}", self.boundary_update_time)?;
         writeln!(f, "Grid insertion time: {}", self.grid_insertion_time)?;
         writeln!(
@@ -64,6 +81,7 @@ impl Display for CollisionDetectionCounters {
             "Neighborhood search time: {}",
             self.neighborhood_search_time
         )?;
-        writeln!(f, "Contact sorting time: {}", self.contact_sorting_time)
+        writeln!(f, "Contact sorting time: {}", self.contact_sorting_time)?;
+        writeln!(f, "Particle sorting time: {}", self.particle_sorting_time)
     }
 }
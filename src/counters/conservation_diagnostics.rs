@@ -0,0 +1,299 @@
+use crate::math::{Real, Vector};
+use crate::object::Fluid;
+use std::collections::VecDeque;
+use std::fmt::{Display, Formatter, Result};
+
+/// Conserved quantities of one fluid at the end of a time step.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub struct FluidConservationDiagnostics {
+    /// The total mass of the particles of the fluid.
+    pub mass: Real,
+    /// The total linear momentum of the particles of the fluid.
+    pub momentum: Vector<Real>,
+    /// The total kinetic energy of the particles of the fluid.
+    pub kinetic_energy: Real,
+    /// The total gravitational potential energy of the particles of the fluid, relative to the
+    /// origin.
+    pub potential_energy: Real,
+    /// The average relative compression of the particles of the fluid.
+    ///
+    /// The relative compression of a particle is `density / rest_density - 1` if it is positive, and
+    /// zero otherwise, so the particles of the free surface, which lack neighbors, don't hide the
+    /// compression of the others.
+    pub avg_density_error: Real,
+    /// The maximum relative compression of the particles of the fluid.
+    pub max_density_error: Real,
+}
+
+/// Energy, mass, and momentum of the fluids at the end of a time step.
+///
+/// These can be recorded over time (see `ConservationHistory`) to detect drifts, e.g., a loss of
+/// energy caused by numerical dissipation or a gain caused by an instability, and to validate
+/// changes of the solvers quantitatively.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub struct ConservationDiagnostics {
+    /// The simulation time at which the diagnostics were computed.
+    pub time: Real,
+    /// The total mass of all the fluids.
+    pub total_mass: Real,
+    /// The total kinetic energy of all the fluids.
+    pub kinetic_energy: Real,
+    /// The total gravitational potential energy of all the fluids, relative to the origin.
+    pub potential_energy: Real,
+    /// The average relative compression of all the fluid particles.
+    ///
+    /// See `FluidConservationDiagnostics::avg_density_error`.
+    pub avg_density_error: Real,
+    /// The maximum relative compression of all the fluid particles.
+    pub max_density_error: Real,
+    /// The diagnostics of each fluid, in the same order as `LiquidWorld::fluids().as_slice()`.
+    pub fluids: Vec<FluidConservationDiagnostics>,
+}
+
+impl ConservationDiagnostics {
+    /// Computes the diagnostics of the given fluids at the given simulation time.
+    ///
+    /// The potential energy of each fluid is computed with its effective gravity (see
+    /// `Fluid::effective_gravity`). The density errors are computed from `densities`, which are
+    /// typically given by `LiquidWorld::particle_densities`. The density errors of a fluid are
+    /// zero if its densities are missing or don't match its particles.
+    pub fn compute(
+        time: Real,
+        gravity: &Vector<Real>,
+        fluids: &[Fluid],
+        densities: &[Vec<Real>],
+    ) -> Self {
+        let mut result = ConservationDiagnostics {
+            time,
+            total_mass: 0.0,
+            kinetic_energy: 0.0,
+            potential_energy: 0.0,
+            avg_density_error: 0.0,
+            max_density_error: 0.0,
+            fluids: Vec::with_capacity(fluids.len()),
+        };
+        let mut num_particles = 0;
+
+        for (fluid_id, fluid) in fluids.iter().enumerate() {
+            let gravity = fluid.effective_gravity(gravity);
+            let mut diagnostics = FluidConservationDiagnostics {
+                mass: 0.0,
+                momentum: Vector::zeros(),
+                kinetic_energy: 0.0,
+                potential_energy: 0.0,
+                avg_density_error: 0.0,
+                max_density_error: 0.0,
+            };
+
+            for i in 0..fluid.num_particles() {
+                let mass = fluid.particle_mass(i);
+                let velocity = &fluid.velocities[i];
+                diagnostics.mass += mass;
+                diagnostics.momentum += velocity * mass;
+                diagnostics.kinetic_energy += mass * velocity.norm_squared() / 2.0;
+                diagnostics.potential_energy -= mass * gravity.dot(&fluid.positions[i].coords);
+            }
+
+            if let Some(densities) = densities
+                .get(fluid_id)
+                .filter(|densities| densities.len() == fluid.num_particles())
+            {
+                let mut total_error = 0.0;
+
+                for (i, density) in densities.iter().enumerate() {
+                    let error = (density / fluid.rest_density(i) - 1.0).max(0.0);
+                    total_error += error;
+                    diagnostics.max_density_error = diagnostics.max_density_error.max(error);
+                }
+
+                if !densities.is_empty() {
+                    diagnostics.avg_density_error = total_error / densities.len() as Real;
+                }
+
+                result.avg_density_error += total_error;
+                num_particles += densities.len();
+            }
+
+            result.total_mass += diagnostics.mass;
+            result.kinetic_energy += diagnostics.kinetic_energy;
+            result.potential_energy += diagnostics.potential_energy;
+            result.max_density_error = result.max_density_error.max(diagnostics.max_density_error);
+            result.fluids.push(diagnostics);
+        }
+
+        if num_particles != 0 {
+            result.avg_density_error /= num_particles as Real;
+        }
+
+        result
+    }
+
+    /// The sum of the kinetic and potential energies of all the fluids.
+    pub fn total_energy(&self) -> Real {
+        self.kinetic_energy + self.potential_energy
+    }
+
+    /// The total linear momentum of all the fluids.
+    pub fn total_momentum(&self) -> Vector<Real> {
+        self.fluids
+            .iter()
+            .fold(Vector::zeros(), |acc, fluid| acc + fluid.momentum)
+    }
+}
+
+impl Display for ConservationDiagnostics {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        writeln!(f, "Time: {}", self.time)?;
+        writeln!(f, "Total mass: {}", self.total_mass)?;
+        writeln!(f, "Kinetic energy: {}", self.kinetic_energy)?;
+        writeln!(f, "Potential energy: {}", self.potential_energy)?;
+        writeln!(f, "Average density error: {}", self.avg_density_error)?;
+        writeln!(f, "Max density error: {}", self.max_density_error)?;
+        writeln!(f, "Total momentum: {:?}", self.total_momentum())
+    }
+}
+
+/// A ring buffer of the conservation diagnostics of the last time steps.
+///
+/// Once the buffer is full, recording new diagnostics discards the oldest ones.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub struct ConservationHistory {
+    capacity: usize,
+    entries: VecDeque<ConservationDiagnostics>,
+}
+
+impl ConservationHistory {
+    /// Creates an empty history keeping the diagnostics of the last `capacity` time steps.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// The maximum number of diagnostics kept by this history.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Sets the maximum number of diagnostics kept by this history, discarding the oldest ones
+    /// that don't fit anymore.
+    ///
+    /// Nothing is recorded if the capacity is zero.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+
+        while self.entries.len() > capacity {
+            let _ = self.entries.pop_front();
+        }
+    }
+
+    /// Records the given diagnostics, discarding the oldest ones if this history is full.
+    pub fn push(&mut self, diagnostics: ConservationDiagnostics) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        if self.entries.len() == self.capacity {
+            let _ = self.entries.pop_front();
+        }
+
+        self.entries.push_back(diagnostics);
+    }
+
+    /// Removes all the recorded diagnostics.
+    pub fn clear(&mut self) {
+        self.entries.clear()
+    }
+
+    /// The number of recorded diagnostics.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether no diagnostics are recorded.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The recorded diagnostics, from the oldest to the most recent.
+    pub fn iter(&self) -> impl Iterator<Item = &ConservationDiagnostics> {
+        self.entries.iter()
+    }
+
+    /// The oldest recorded diagnostics.
+    pub fn oldest(&self) -> Option<&ConservationDiagnostics> {
+        self.entries.front()
+    }
+
+    /// The most recent recorded diagnostics.
+    pub fn latest(&self) -> Option<&ConservationDiagnostics> {
+        self.entries.back()
+    }
+
+    /// The variation of the total energy between the oldest and the most recent diagnostics.
+    pub fn energy_drift(&self) -> Option<Real> {
+        Some(self.latest()?.total_energy() - self.oldest()?.total_energy())
+    }
+
+    /// The variation of the total mass between the oldest and the most recent diagnostics.
+    pub fn mass_drift(&self) -> Option<Real> {
+        Some(self.latest()?.total_mass - self.oldest()?.total_mass)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ConservationDiagnostics, ConservationHistory};
+    use crate::math::{Point, Real, Vector};
+    use crate::object::Fluid;
+
+    #[test]
+    fn history_keeps_the_last_diagnostics() {
+        let mut fluid = Fluid::new(
+            vec![Point::origin(), Point::from(Vector::y() * 2.0)],
+            0.1,
+            1000.0,
+            Default::default(),
+        );
+        fluid.velocities[0] = Vector::x() * 3.0;
+        let mass = fluid.particle_mass(0);
+        let gravity = Vector::y() * -10.0;
+        let mut history = ConservationHistory::new(2);
+
+        for k in 0..3 {
+            fluid.positions[1].y += k as Real;
+            let densities = vec![vec![1100.0, 900.0]];
+            history.push(ConservationDiagnostics::compute(
+                k as Real,
+                &gravity,
+                std::slice::from_ref(&fluid),
+                &densities,
+            ));
+        }
+
+        assert_eq!(history.len(), 2);
+        let latest = history.latest().unwrap();
+        assert_eq!(history.oldest().unwrap().time, 1.0);
+        assert_eq!(latest.total_mass, 2.0 * mass);
+        assert_eq!(latest.total_momentum(), Vector::x() * 3.0 * mass);
+        assert_eq!(latest.kinetic_energy, 4.5 * mass);
+        assert_eq!(latest.potential_energy, 50.0 * mass);
+        assert!((latest.max_density_error - 0.1).abs() < 1.0e-5);
+        assert!((latest.avg_density_error - 0.05).abs() < 1.0e-5);
+        assert_eq!(history.mass_drift(), Some(0.0));
+        assert!((history.energy_drift().unwrap() - 20.0 * mass).abs() < 1.0e-3 * mass);
+    }
+}
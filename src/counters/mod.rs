@@ -3,18 +3,31 @@
 use std::fmt::{Display, Formatter, Result};
 
 pub use self::collision_detection_counters::CollisionDetectionCounters;
+pub use self::conservation_diagnostics::{
+    ConservationDiagnostics, ConservationHistory, FluidConservationDiagnostics,
+};
 pub use self::solver_counters::SolverCounters;
+pub use self::solver_statistics::SolverStatistics;
 pub use self::stages_counters::StagesCounters;
+pub use self::step_statistics::StepStatistics;
 pub use self::timer::Timer;
 
 mod collision_detection_counters;
+mod conservation_diagnostics;
 mod solver_counters;
+mod solver_statistics;
 mod stages_counters;
+mod step_statistics;
 mod timer;
 
 /// Aggregation of all the performances counters tracked by salva.
+///
+/// The timers are disabled by default, and can be enabled or disabled at any time with
+/// `Counters::enable` and `Counters::disable`, e.g., through the `counters` field of the liquid
+/// world. They are reset at the beginning of each time step.
 #[derive(Clone, Copy)]
 pub struct Counters {
+    enabled: bool,
     /// Total number of substeps performed.
     pub nsubsteps: usize,
     /// Timer for a whole timestep.
@@ -27,21 +40,30 @@ pub struct Counters {
     pub cd: CollisionDetectionCounters,
     /// Counters of the constraints resolution and force computation stage.
     pub solver: SolverCounters,
+    /// Statistics about the particle motion during the last time step.
+    pub stats: StepStatistics,
 }
 
 impl Counters {
     /// Create a new set of counters initialized to zero.
     pub fn new() -> Self {
         Counters {
+            enabled: false,
             nsubsteps: 0,
             step_time: Timer::new(),
             custom: Timer::new(),
             stages: StagesCounters::new(),
             cd: CollisionDetectionCounters::new(),
             solver: SolverCounters::new(),
+            stats: StepStatistics::new(),
         }
     }
 
+    /// Whether the timers are enabled.
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
     /// Resets to zero all the counters.
     pub fn reset(&mut self) {
         self.nsubsteps = 0;
@@ -50,10 +72,12 @@ impl Counters {
         self.stages.reset();
         self.cd.reset();
         self.solver.reset();
+        self.stats.reset();
     }
 
     /// Enable all the counters.
     pub fn enable(&mut self) {
+        self.enabled = true;
         self.step_time.enable();
         self.custom.enable();
         self.stages.enable();
@@ -63,6 +87,7 @@ impl Counters {
 
     /// Disable all the counters.
     pub fn disable(&mut self) {
+        self.enabled = false;
         self.step_time.disable();
         self.custom.disable();
         self.stages.disable();
@@ -78,6 +103,7 @@ impl Display for Counters {
         self.stages.fmt(f)?;
         self.cd.fmt(f)?;
         self.solver.fmt(f)?;
+        self.stats.fmt(f)?;
         writeln!(f, "Custom timer: {}", self.custom)
     }
 }
@@ -1,45 +1,101 @@
 use crate::counters::Timer;
+use crate::math::Real;
 use std::fmt::{Display, Formatter, Result};
 
 /// Performance counters related to constraints resolution.
 #[derive(Default, Clone, Copy)]
 pub struct SolverCounters {
+    /// Time spent for the evaluation of the SPH kernels at each contact.
+    pub kernel_evaluation_time: Timer,
+    /// Time spent for the computation of the densities of the fluid and boundary particles.
+    pub density_computation_time: Timer,
     /// Time spent for the resolution of non-pressure forces.
     pub non_pressure_resolution_time: Timer,
     /// Time spent for the resolution of pressure forces.
     pub pressure_resolution_time: Timer,
+    /// Time spent for the resolution of the divergence-free condition.
+    ///
+    /// This is zero for pressure solvers that don't enforce it, unless a `DivergenceSolver` is
+    /// set on the liquid world.
+    pub divergence_resolution_time: Timer,
+    /// Time spent for the computation of the timestep length and the integration of the
+    /// accelerations and velocities of the particles.
+    pub integration_time: Timer,
+    /// Total number of pressure iterations executed during all the substeps.
+    pub num_pressure_iterations: usize,
+    /// Average density error (in percents) after the pressure resolution of the last substep.
+    pub density_error: Real,
+    /// Number of substeps during which the pressure resolution reached its maximum number of
+    /// iterations without meeting its error tolerance.
+    pub num_unconverged_pressure_solves: usize,
+    /// Total number of divergence iterations executed during all the substeps.
+    pub num_divergence_iterations: usize,
+    /// Average divergence error after the divergence resolution of the last substep.
+    pub divergence_error: Real,
 }
 
 impl SolverCounters {
     /// Creates a new counter initialized to zero.
     pub fn new() -> Self {
         SolverCounters {
+            kernel_evaluation_time: Timer::new(),
+            density_computation_time: Timer::new(),
             non_pressure_resolution_time: Timer::new(),
             pressure_resolution_time: Timer::new(),
+            divergence_resolution_time: Timer::new(),
+            integration_time: Timer::new(),
+            num_pressure_iterations: 0,
+            density_error: 0.0,
+            num_unconverged_pressure_solves: 0,
+            num_divergence_iterations: 0,
+            divergence_error: 0.0,
         }
     }
 
     /// Enables all the counters for the solver.
     pub fn enable(&mut self) {
+        self.kernel_evaluation_time.enable();
+        self.density_computation_time.enable();
         self.non_pressure_resolution_time.enable();
         self.pressure_resolution_time.enable();
+        self.divergence_resolution_time.enable();
+        self.integration_time.enable();
     }
 
     /// Disables all the counters for the solver.
     pub fn disable(&mut self) {
+        self.kernel_evaluation_time.disable();
+        self.density_computation_time.disable();
         self.non_pressure_resolution_time.disable();
         self.pressure_resolution_time.disable();
+        self.divergence_resolution_time.disable();
+        self.integration_time.disable();
     }
 
     /// Resets to zero all the counters for the solver.
     pub fn reset(&mut self) {
+        self.kernel_evaluation_time.reset();
+        self.density_computation_time.reset();
         self.non_pressure_resolution_time.reset();
         self.pressure_resolution_time.reset();
+        self.divergence_resolution_time.reset();
+        self.integration_time.reset();
+        self.num_pressure_iterations = 0;
+        self.density_error = 0.0;
+        self.num_unconverged_pressure_solves = 0;
+        self.num_divergence_iterations = 0;
+        self.divergence_error = 0.0;
     }
 }
 
 impl Display for SolverCounters {
     fn fmt(&self, f: &mut Formatter) -> Result {
+        writeln!(f, "Kernel evaluation time: {}", self.kernel_evaluation_time)?;
+        writeln!(
+            f,
+            "Density computation time: {}",
+            self.density_computation_time
+        )?;
         writeln!(
             f,
             "Non-pressure resolution time: {}",
@@ -49,6 +105,29 @@ impl Display for SolverCounters {
             f,
             "Pressure resolution time: {}",
             self.pressure_resolution_time
-        )
+        )?;
+        writeln!(
+            f,
+            "Divergence resolution time: {}",
+            self.divergence_resolution_time
+        )?;
+        writeln!(f, "Integration time: {}", self.integration_time)?;
+        writeln!(
+            f,
+            "Num pressure iterations: {}",
+            self.num_pressure_iterations
+        )?;
+        writeln!(f, "Density error: {}", self.density_error)?;
+        writeln!(
+            f,
+            "Num unconverged pressure solves: {}",
+            self.num_unconverged_pressure_solves
+        )?;
+        writeln!(
+            f,
+            "Num divergence iterations: {}",
+            self.num_divergence_iterations
+        )?;
+        writeln!(f, "Divergence error: {}", self.divergence_error)
     }
 }
@@ -0,0 +1,66 @@
+use crate::counters::Counters;
+use crate::math::Real;
+
+/// Summary of the work performed by the solver during the last time step.
+///
+/// The timings are only measured if the counters have been enabled with `Counters::enable`.
+/// Otherwise, they are zero.
+#[derive(Default, Clone, Copy, Debug)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub struct SolverStatistics {
+    /// Number of substeps performed.
+    pub num_substeps: usize,
+    /// Total number of pressure iterations executed during all the substeps.
+    pub num_pressure_iterations: usize,
+    /// Average relative density error after the pressure resolution of the last substep.
+    pub density_error: Real,
+    /// Number of substeps during which the pressure resolution did not converge.
+    pub num_unconverged_pressure_solves: usize,
+    /// Total number of divergence iterations executed during all the substeps.
+    ///
+    /// This is always zero for solvers that don't perform a divergence resolution.
+    pub num_divergence_iterations: usize,
+    /// Average divergence error after the divergence resolution of the last substep.
+    pub divergence_error: Real,
+    /// Time spent for the collision detection, in milliseconds.
+    pub collision_detection_time: f64,
+    /// Time spent for the evaluation of the SPH kernels, in milliseconds.
+    pub kernel_evaluation_time: f64,
+    /// Time spent for the computation of the densities, in milliseconds.
+    pub density_computation_time: f64,
+    /// Time spent for the resolution of non-pressure forces, in milliseconds.
+    pub non_pressure_resolution_time: f64,
+    /// Time spent for the resolution of pressure forces, in milliseconds.
+    pub pressure_resolution_time: f64,
+    /// Time spent for the resolution of the divergence-free condition, in milliseconds.
+    pub divergence_resolution_time: f64,
+    /// Time spent for the integration of the particles, in milliseconds.
+    pub integration_time: f64,
+    /// Time spent for the whole time step, in milliseconds.
+    pub step_time: f64,
+}
+
+impl SolverStatistics {
+    /// Extracts the solver statistics from the given performance counters.
+    pub fn from_counters(counters: &Counters) -> Self {
+        SolverStatistics {
+            num_substeps: counters.nsubsteps,
+            num_pressure_iterations: counters.solver.num_pressure_iterations,
+            density_error: counters.solver.density_error,
+            num_unconverged_pressure_solves: counters.solver.num_unconverged_pressure_solves,
+            num_divergence_iterations: counters.solver.num_divergence_iterations,
+            divergence_error: counters.solver.divergence_error,
+            collision_detection_time: counters.stages.collision_detection_time.time(),
+            kernel_evaluation_time: counters.solver.kernel_evaluation_time.time(),
+            density_computation_time: counters.solver.density_computation_time.time(),
+            non_pressure_resolution_time: counters.solver.non_pressure_resolution_time.time(),
+            pressure_resolution_time: counters.solver.pressure_resolution_time.time(),
+            divergence_resolution_time: counters.solver.divergence_resolution_time.time(),
+            integration_time: counters.solver.integration_time.time(),
+            step_time: counters.step_time.time(),
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use crate::math::Real;
+use crate::object::Fluid;
+use std::fmt::{Display, Formatter, Result};
+
+/// Aggregate statistics about the motion of fluid particles during one time step.
+///
+/// These are updated after each substep and can be used to detect instabilities
+/// or to drive dynamic quality scaling.
+#[derive(Default, Clone, Copy, Debug)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub struct StepStatistics {
+    /// The maximum speed of any fluid particle at the end of a substep.
+    pub max_speed: Real,
+    /// The maximum distance traveled by any fluid particle during a single substep,
+    /// divided by the particle radius.
+    pub max_relative_displacement: Real,
+    /// The number of times a particle traveled further than allowed by the CFL condition
+    /// during a substep.
+    pub num_cfl_violations: usize,
+    /// The number of times a particle with a non-finite position or velocity was reset by the
+    /// simulation guards (see `SimulationGuards`).
+    pub num_nan_particles: usize,
+    /// The number of times the speed or the displacement of a particle was clamped by the
+    /// simulation guards (see `SimulationGuards`).
+    pub num_clamped_particles: usize,
+}
+
+impl StepStatistics {
+    /// Creates a new set of statistics initialized to zero.
+    pub fn new() -> Self {
+        StepStatistics {
+            max_speed: 0.0,
+            max_relative_displacement: 0.0,
+            num_cfl_violations: 0,
+            num_nan_particles: 0,
+            num_clamped_particles: 0,
+        }
+    }
+
+    /// Resets all the statistics to zero.
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Accumulates the statistics of a substep of length `dt` that has just been executed.
+    ///
+    /// A particle violates the CFL condition if it travels further than `cfl_coeff` times
+    /// the particle diameter during the substep.
+    pub fn update(&mut self, dt: Real, particle_radius: Real, cfl_coeff: Real, fluids: &[Fluid]) {
+        let max_displacement = cfl_coeff * particle_radius * na::convert::<_, Real>(2.0);
+
+        for fluid in fluids {
+            for vel in &fluid.velocities {
+                let speed = vel.norm();
+                let displacement = speed * dt;
+
+                self.max_speed = self.max_speed.max(speed);
+                self.max_relative_displacement = self
+                    .max_relative_displacement
+                    .max(displacement / particle_radius);
+
+                if displacement > max_displacement {
+                    self.num_cfl_violations += 1;
+                }
+            }
+        }
+    }
+}
+
+impl Display for StepStatistics {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        writeln!(f, "Max particle speed: {}", self.max_speed)?;
+        writeln!(
+            f,
+            "Max relative displacement: {}",
+            self.max_relative_displacement
+        )?;
+        writeln!(f, "Num CFL violations: {}", self.num_cfl_violations)?;
+        writeln!(f, "Num NaN particles: {}", self.num_nan_particles)?;
+        writeln!(f, "Num clamped particles: {}", self.num_clamped_particles)
+    }
+}
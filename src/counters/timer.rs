@@ -58,7 +58,7 @@ impl Timer {
         }
     }
 
-    /// The measured time between the last `.start()` and `.pause()` calls.
+    /// The measured time between the last `.start()` and `.pause()` calls, in milliseconds.
     pub fn time(&self) -> f64 {
         self.time
     }
@@ -66,6 +66,6 @@ impl Timer {
 
 impl Display for Timer {
     fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
-        write!(f, "{}s", self.time)
+        write!(f, "{}ms", self.time)
     }
 }
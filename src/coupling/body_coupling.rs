@@ -0,0 +1,220 @@
+use crate::coupling::CouplingManager;
+use crate::geometry::NeighborSearch;
+use crate::math::{AngularVector, Isometry, Point, Real, Vector};
+use crate::object::{BoundaryHandle, BoundarySet, Fluid, ForceClearingPolicy};
+use crate::TimestepManager;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// The state of a rigid-body simulated by an external physics engine.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct BodyState {
+    /// The world-space position of the body.
+    pub position: Isometry<Real>,
+    /// The center of mass of the body, in its local-space.
+    pub local_center_of_mass: Point<Real>,
+    /// The linear velocity of the body.
+    pub linear_velocity: Vector<Real>,
+    /// The angular velocity of the body.
+    pub angular_velocity: AngularVector<Real>,
+    /// Whether the body is affected by forces.
+    ///
+    /// The fluids don't apply any force to the boundaries of non-dynamic bodies.
+    pub is_dynamic: bool,
+}
+
+impl BodyState {
+    /// The state of a static body at the given position.
+    pub fn fixed(position: Isometry<Real>) -> Self {
+        Self {
+            position,
+            local_center_of_mass: Point::origin(),
+            linear_velocity: Vector::zeros(),
+            angular_velocity: AngularVector::zeros(),
+            is_dynamic: false,
+        }
+    }
+
+    /// The world-space center of mass of the body.
+    pub fn center_of_mass(&self) -> Point<Real> {
+        self.position * self.local_center_of_mass
+    }
+
+    /// The velocity of the body at the given world-space point.
+    pub fn velocity_at_point(&self, point: &Point<Real>) -> Vector<Real> {
+        let arm = point - self.center_of_mass();
+
+        #[cfg(feature = "dim2")]
+        {
+            self.linear_velocity + Vector::new(-arm.y, arm.x) * self.angular_velocity.x
+        }
+
+        #[cfg(feature = "dim3")]
+        {
+            self.linear_velocity + self.angular_velocity.cross(&arm)
+        }
+    }
+}
+
+struct BodyCouplingEntry {
+    boundary: BoundaryHandle,
+    local_points: Vec<Point<Real>>,
+    state: BodyState,
+    linear_impulse: Vector<Real>,
+    angular_impulse: AngularVector<Real>,
+}
+
+/// Coupling between boundaries and the rigid-bodies of an arbitrary physics engine.
+///
+/// This is useful for physics engines without a dedicated integration, e.g., nphysics or an
+/// in-house engine. Each body is represented by a boundary sampled by a fixed set of local-space
+/// points. Before each step, the state of the bodies must be provided with
+/// `Self::set_body_state`. After the step, the impulses applied by the fluids to each body can be
+/// retrieved with `Self::take_impulses` and applied to the body by the external engine.
+pub struct BodyCouplingSet<Handle: Copy + Eq + Hash> {
+    entries: HashMap<Handle, BodyCouplingEntry>,
+}
+
+impl<Handle: Copy + Eq + Hash> BodyCouplingSet<Handle> {
+    /// Create a new body coupling set.
+    pub fn new() -> Self {
+        Self {
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Register a coupling between a boundary and a body.
+    ///
+    /// The boundary particles are placed at the given `local_points` transformed by the position
+    /// of the body. There can be only up to one coupling per body. If a coupling already exists
+    /// for this body, the handle of the previously coupled boundary is returned.
+    ///
+    /// The force clearing policy of the boundary is set to `ForceClearingPolicy::EverySubstep`
+    /// during the next step because the forces are transmitted to the body after each substep.
+    pub fn register_coupling(
+        &mut self,
+        body: Handle,
+        boundary: BoundaryHandle,
+        local_points: Vec<Point<Real>>,
+        state: BodyState,
+    ) -> Option<BoundaryHandle> {
+        let old = self.entries.insert(
+            body,
+            BodyCouplingEntry {
+                boundary,
+                local_points,
+                state,
+                linear_impulse: Vector::zeros(),
+                angular_impulse: AngularVector::zeros(),
+            },
+        );
+
+        old.map(|e| e.boundary)
+    }
+
+    /// Unregister a coupling between a boundary and a body.
+    ///
+    /// Note that this does not remove the boundary itself from the liquid world.
+    /// Returns the handle of the boundary this body was coupled with.
+    pub fn unregister_coupling(&mut self, body: Handle) -> Option<BoundaryHandle> {
+        self.entries.remove(&body).map(|e| e.boundary)
+    }
+
+    /// Sets the current state of a coupled body.
+    ///
+    /// Returns `false` if there is no coupling registered for this body.
+    pub fn set_body_state(&mut self, body: Handle, state: BodyState) -> bool {
+        if let Some(entry) = self.entries.get_mut(&body) {
+            entry.state = state;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// The linear impulse, and the angular impulse relative to the center of mass, applied by the
+    /// fluids to the given body since the last call to this method.
+    ///
+    /// The impulses are reset to zero by this call. Returns `None` if there is no coupling
+    /// registered for this body.
+    pub fn take_impulses(&mut self, body: Handle) -> Option<(Vector<Real>, AngularVector<Real>)> {
+        self.entries.get_mut(&body).map(|entry| {
+            let result = (entry.linear_impulse, entry.angular_impulse);
+            entry.linear_impulse = Vector::zeros();
+            entry.angular_impulse = AngularVector::zeros();
+            result
+        })
+    }
+}
+
+impl<Handle: Copy + Eq + Hash> Default for BodyCouplingSet<Handle> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Handle: Copy + Eq + Hash> CouplingManager for BodyCouplingSet<Handle> {
+    fn update_boundaries(
+        &mut self,
+        _: &TimestepManager,
+        _: Real,
+        _: Real,
+        _: &dyn NeighborSearch,
+        _: &mut [Fluid],
+        boundaries: &mut BoundarySet,
+    ) {
+        for entry in self.entries.values() {
+            if let Some(boundary) = boundaries.get_mut(entry.boundary) {
+                // The forces are transmitted as impulses after each substep, so they must not
+                // accumulate across substeps.
+                if boundary.force_clearing_policy != ForceClearingPolicy::EverySubstep {
+                    boundary.force_clearing_policy = ForceClearingPolicy::EverySubstep;
+                    boundary.clear_forces(false);
+                }
+
+                boundary.enable_forces(entry.state.is_dynamic);
+                boundary.positions.clear();
+                boundary.velocities.clear();
+
+                for pt in &entry.local_points {
+                    let world_pt = entry.state.position * pt;
+                    boundary.positions.push(world_pt);
+                    boundary
+                        .velocities
+                        .push(entry.state.velocity_at_point(&world_pt));
+                }
+
+                boundary
+                    .volumes
+                    .resize(entry.local_points.len(), na::zero::<Real>());
+            }
+        }
+    }
+
+    fn transmit_forces(&mut self, timestep: &TimestepManager, boundaries: &BoundarySet) {
+        for entry in self.entries.values_mut() {
+            if let Some(boundary) = boundaries.get(entry.boundary) {
+                if let Some(forces) = &boundary.forces {
+                    let forces = forces.read().unwrap();
+                    let center_of_mass = entry.state.center_of_mass();
+
+                    for (pos, force) in boundary.positions.iter().zip(forces.iter()) {
+                        let impulse = force * timestep.dt();
+                        let arm = pos - center_of_mass;
+                        entry.linear_impulse += impulse;
+
+                        #[cfg(feature = "dim2")]
+                        {
+                            entry.angular_impulse += AngularVector::new(arm.perp(&impulse));
+                        }
+
+                        #[cfg(feature = "dim3")]
+                        {
+                            entry.angular_impulse += arm.cross(&impulse);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
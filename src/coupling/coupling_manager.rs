@@ -1,4 +1,4 @@
-use crate::geometry::{HGrid, HGridEntry};
+use crate::geometry::NeighborSearch;
 use crate::math::Real;
 use crate::object::{BoundarySet, Fluid};
 use crate::TimestepManager;
@@ -18,7 +18,7 @@ pub trait CouplingManager {
         timestep: &TimestepManager,
         h: Real,
         particle_radius: Real,
-        hgrid: &HGrid<HGridEntry>,
+        neighbor_search: &dyn NeighborSearch,
         fluids: &mut [Fluid],
         boundaries: &mut BoundarySet,
     );
@@ -33,7 +33,7 @@ impl CouplingManager for () {
         _: &TimestepManager,
         _: Real,
         _: Real,
-        _: &HGrid<HGridEntry>,
+        _: &dyn NeighborSearch,
         _: &mut [Fluid],
         _: &mut BoundarySet,
     ) {
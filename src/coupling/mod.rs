@@ -1,4 +1,7 @@
 //! Two-way coupling between rigid-bodies and fluids.
 
+pub use self::body_coupling::{BodyCouplingSet, BodyState};
 pub use self::coupling_manager::CouplingManager;
+
+mod body_coupling;
 mod coupling_manager;
@@ -0,0 +1,113 @@
+use crate::geometry::HGridEntry;
+use crate::object::{Boundary, Fluid};
+
+/// The identifier of a fluid or a boundary, as given to a `ContactFilter`.
+///
+/// The indices are the contiguous indices of the objects in the `FluidSet` and the
+/// `BoundarySet`. They can be converted to handles with
+/// `ContiguousArena::get_from_contiguous_index`.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum ContactModel {
+    /// The fluid with the given contiguous index.
+    Fluid(usize),
+    /// The boundary with the given contiguous index.
+    Boundary(usize),
+}
+
+/// User-defined filtering of the contacts between particles.
+///
+/// This complements the `InteractionGroups` of fluids and boundaries: a contact is only
+/// generated if it is allowed by both the interaction groups and the contact filter. This can be
+/// used, e.g., for one-way membranes or for walls only blocking some of the particles.
+///
+/// The order of the models and particles given to the filter is unspecified, except that fluids
+/// always come before boundaries, so the filter must be symmetric. A particle is never filtered
+/// out of its contact with itself.
+pub trait ContactFilter: Send + Sync {
+    /// Whether the particles of `model1` may be in contact with the particles of `model2`.
+    ///
+    /// This is evaluated once per model pair each time the contacts are computed.
+    fn filter_model_pair(&self, _model1: ContactModel, _model2: ContactModel) -> bool {
+        true
+    }
+
+    /// Whether the two given particles, closer than the kernel radius, may be in contact.
+    ///
+    /// This is only called if the pair of models of these particles is allowed by
+    /// `Self::filter_model_pair`.
+    fn filter_particle_pair(
+        &self,
+        _fluids: &[Fluid],
+        _boundaries: &[Boundary],
+        _particle1: HGridEntry,
+        _particle2: HGridEntry,
+    ) -> bool {
+        true
+    }
+}
+
+// The model pairs allowed by a contact filter, evaluated once per contact update.
+pub(crate) struct ContactFilterContext<'a> {
+    filter: &'a dyn ContactFilter,
+    num_fluids: usize,
+    num_models: usize,
+    allowed_model_pairs: Vec<bool>,
+}
+
+impl<'a> ContactFilterContext<'a> {
+    pub fn new(filter: &'a dyn ContactFilter, fluids: &[Fluid], boundaries: &[Boundary]) -> Self {
+        let num_fluids = fluids.len();
+        let num_models = fluids.len() + boundaries.len();
+        let model = |i: usize| {
+            if i < num_fluids {
+                ContactModel::Fluid(i)
+            } else {
+                ContactModel::Boundary(i - num_fluids)
+            }
+        };
+
+        let mut allowed_model_pairs = vec![true; num_models * num_models];
+
+        for i in 0..num_models {
+            for j in i..num_models {
+                let allowed = filter.filter_model_pair(model(i), model(j));
+                allowed_model_pairs[i * num_models + j] = allowed;
+                allowed_model_pairs[j * num_models + i] = allowed;
+            }
+        }
+
+        Self {
+            filter,
+            num_fluids,
+            num_models,
+            allowed_model_pairs,
+        }
+    }
+
+    fn model_index(&self, entry: HGridEntry) -> usize {
+        match entry {
+            HGridEntry::FluidParticle(i, _) => i,
+            HGridEntry::BoundaryParticle(i, _) => self.num_fluids + i,
+        }
+    }
+
+    pub fn test(
+        &self,
+        fluids: &[Fluid],
+        boundaries: &[Boundary],
+        particle1: HGridEntry,
+        particle2: HGridEntry,
+    ) -> bool {
+        let model1 = self.model_index(particle1);
+        let model2 = self.model_index(particle2);
+
+        if model1 == model2 && particle1.into_tuple().1 == particle2.into_tuple().1 {
+            return true;
+        }
+
+        self.allowed_model_pairs[model1 * self.num_models + model2]
+            && self
+                .filter
+                .filter_particle_pair(fluids, boundaries, particle1, particle2)
+    }
+}
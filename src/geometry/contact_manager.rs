@@ -1,10 +1,20 @@
 use crate::counters::Counters;
-use crate::geometry::{self, HGrid, HGridEntry, ParticlesContacts};
-use crate::math::Real;
+use crate::geometry::{
+    self, ContactFilter, ContactQuery, HGrid, HGridEntry, NeighborSearch, ParticlesContacts,
+    PeriodicDomain,
+};
+use crate::math::{Point, Real};
 use crate::object::Boundary;
 use crate::object::Fluid;
 
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
 /// Structure responsible for computing and grouping all the contact between fluid and boundary particles.
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
 pub struct ContactManager {
     /// All contacts detected between pairs of fluid partices.
     pub fluid_fluid_contacts: Vec<ParticlesContacts>,
@@ -12,6 +22,74 @@ pub struct ContactManager {
     pub fluid_boundary_contacts: Vec<ParticlesContacts>,
     /// All contacts detected between two boundary particles.
     pub boundary_boundary_contacts: Vec<ParticlesContacts>,
+    #[cfg_attr(feature = "serde-serialize", serde(skip))]
+    contact_filter: Option<Box<dyn ContactFilter>>,
+    periodic_domain: Option<PeriodicDomain>,
+    sort_contacts: bool,
+    neighbor_list_skin: Option<Real>,
+    #[cfg_attr(feature = "serde-serialize", serde(skip))]
+    neighbor_lists: Option<NeighborLists>,
+}
+
+// The contacts computed with a radius enlarged by the skin distance, and the positions of the
+// particles when they were computed.
+struct NeighborLists {
+    grid: HGrid<HGridEntry>,
+    fluid_fluid_contacts: Vec<ParticlesContacts>,
+    fluid_boundary_contacts: Vec<ParticlesContacts>,
+    boundary_boundary_contacts: Vec<ParticlesContacts>,
+    fluid_positions: Vec<Vec<Point<Real>>>,
+    boundary_positions: Vec<Vec<Point<Real>>>,
+}
+
+impl NeighborLists {
+    fn new(radius: Real) -> Self {
+        Self {
+            grid: HGrid::new(radius),
+            fluid_fluid_contacts: Vec::new(),
+            fluid_boundary_contacts: Vec::new(),
+            boundary_boundary_contacts: Vec::new(),
+            fluid_positions: Vec::new(),
+            boundary_positions: Vec::new(),
+        }
+    }
+
+    // The lists remain valid as long as no particle moved by more than half the skin distance
+    // since they were computed: no pair of particles outside of the lists can have come closer
+    // than the kernel radius.
+    fn is_valid(
+        &self,
+        skin: Real,
+        fluids: &[Fluid],
+        boundaries: &[Boundary],
+        periodic_domain: Option<&PeriodicDomain>,
+    ) -> bool {
+        let max_displacement2 = skin * skin / na::convert::<_, Real>(4.0);
+        let has_moved = |p0: &Point<Real>, p: &Point<Real>| {
+            let p = periodic_domain.map_or(*p, |domain| domain.closest_image(p0, p));
+            na::distance_squared(p0, &p) > max_displacement2
+        };
+        let all_close = |positions0: &[Point<Real>], positions: &[Point<Real>]| {
+            positions0.len() == positions.len()
+                && !par_iter!(positions0)
+                    .zip(par_iter!(positions))
+                    .any(|(p0, p)| has_moved(p0, p))
+        };
+
+        self.fluid_positions.len() == fluids.len()
+            && self.boundary_positions.len() == boundaries.len()
+            && fluids
+                .iter()
+                .zip(&self.fluid_positions)
+                .all(|(fluid, positions0)| all_close(positions0, &fluid.positions))
+            && boundaries
+                .iter()
+                .zip(&self.boundary_positions)
+                .all(|(boundary, positions0)| {
+                    // The virtual particles of density maps are not part of the lists.
+                    boundary.density_map().is_some() || all_close(positions0, &boundary.positions)
+                })
+    }
 }
 
 impl ContactManager {
@@ -21,6 +99,11 @@ impl ContactManager {
             fluid_fluid_contacts: Vec::new(),
             fluid_boundary_contacts: Vec::new(),
             boundary_boundary_contacts: Vec::new(),
+            contact_filter: None,
+            periodic_domain: None,
+            sort_contacts: false,
+            neighbor_list_skin: None,
+            neighbor_lists: None,
         }
     }
 
@@ -44,24 +127,221 @@ impl ContactManager {
                 .sum::<usize>()
     }
 
-    /// Computes all the contacts between the particles inserted on the provided spacial grid.
+    /// Sets the user-defined filter of the contacts computed by this manager.
+    ///
+    /// There is no filter by default.
+    pub fn set_contact_filter(&mut self, filter: Option<Box<dyn ContactFilter>>) {
+        self.contact_filter = filter;
+        self.invalidate_neighbor_lists();
+    }
+
+    /// The user-defined filter of the contacts computed by this manager.
+    pub fn contact_filter(&self) -> Option<&dyn ContactFilter> {
+        self.contact_filter.as_deref()
+    }
+
+    /// Sets the periodic domain the contacts are computed in.
+    ///
+    /// The domain is not periodic by default.
+    pub fn set_periodic_domain(&mut self, domain: Option<PeriodicDomain>) {
+        self.periodic_domain = domain;
+        self.invalidate_neighbor_lists();
+    }
+
+    /// The periodic domain the contacts are computed in.
+    pub fn periodic_domain(&self) -> Option<&PeriodicDomain> {
+        self.periodic_domain.as_ref()
+    }
+
+    /// Sets whether the contacts of each particle are sorted after being computed.
+    ///
+    /// The contacts are detected in parallel, so their order depends on the scheduling of the
+    /// threads. Sorting them by `(j_model, j)` makes the order in which the solvers accumulate the
+    /// contributions of the neighbors, hence the simulation results, reproducible. The contacts are
+    /// not sorted by default.
+    pub fn set_contact_sorting(&mut self, enabled: bool) {
+        self.sort_contacts = enabled;
+    }
+
+    /// Whether the contacts of each particle are sorted after being computed.
+    pub fn sorts_contacts(&self) -> bool {
+        self.sort_contacts
+    }
+
+    /// Sets the skin distance of the Verlet neighbor lists reused across contact updates.
+    ///
+    /// With a skin distance, the neighbors of each particle are searched within the kernel radius
+    /// enlarged by the skin, and stored into neighbor lists. The next contact updates only
+    /// extract the particles closer than the kernel radius from these lists, until a particle
+    /// moves by more than half the skin distance, or the number of particles changes. This cuts
+    /// the cost of the neighbor search for slow-moving or settled fluids, at the cost of longer
+    /// lists to filter at each update. A skin of about 10% to 40% of the kernel radius is usually
+    /// a good trade-off.
+    ///
+    /// The interaction groups of the fluids and boundaries, and the contact filter, are only
+    /// tested when the lists are recomputed: call `Self::invalidate_neighbor_lists` after
+    /// modifying them. The neighbor lists are computed with an internal spacial grid instead of
+    /// the neighbor search structure given to `Self::update_contacts`. They are disabled (`None`)
+    /// by default.
+    pub fn set_neighbor_list_skin(&mut self, skin: Option<Real>) {
+        self.neighbor_list_skin = skin;
+        self.invalidate_neighbor_lists();
+    }
+
+    /// The skin distance of the neighbor lists, if enabled.
+    pub fn neighbor_list_skin(&self) -> Option<Real> {
+        self.neighbor_list_skin
+    }
+
+    /// Forces the neighbor lists to be recomputed at the next contact update.
+    ///
+    /// This must be called whenever particles are reordered or replaced without changing their
+    /// number, e.g., after removing and adding the same number of particles.
+    pub fn invalidate_neighbor_lists(&mut self) {
+        self.neighbor_lists = None;
+    }
+
+    /// Computes all the contacts between the particles inserted into `neighbor_search`.
+    ///
+    /// The fluid particles marked as inactive by `Fluid::active_particles` are not given any
+    /// contact, but remain neighbors of the other particles.
     pub fn update_contacts(
         &mut self,
         counters: &mut Counters,
         h: Real,
         fluids: &[Fluid],
         boundaries: &[Boundary],
-        hgrid: &HGrid<HGridEntry>,
+        neighbor_search: &dyn NeighborSearch,
     ) {
-        geometry::compute_contacts(
-            counters,
+        if let Some(skin) = self.neighbor_list_skin {
+            self.update_contacts_from_neighbor_lists(counters, h, skin, fluids, boundaries);
+        } else {
+            let query = ContactQuery {
+                h,
+                fluids,
+                boundaries,
+                filter: self.contact_filter.as_deref(),
+                periodic_domain: self.periodic_domain.as_ref(),
+            };
+            neighbor_search.compute_contacts(
+                counters,
+                &query,
+                &mut self.fluid_fluid_contacts,
+                &mut self.fluid_boundary_contacts,
+                &mut self.boundary_boundary_contacts,
+            );
+        }
+
+        for (fluid, (fluid_fluid, fluid_boundary)) in fluids.iter().zip(
+            self.fluid_fluid_contacts
+                .iter_mut()
+                .zip(self.fluid_boundary_contacts.iter_mut()),
+        ) {
+            if fluid.active_particles.len() != fluid.num_particles() {
+                continue;
+            }
+
+            par_iter_mut!(fluid_fluid.contacts_mut())
+                .zip(par_iter_mut!(fluid_boundary.contacts_mut()))
+                .zip(par_iter!(fluid.active_particles))
+                .for_each(|((fluid_fluid, fluid_boundary), is_active)| {
+                    if !*is_active {
+                        fluid_fluid.get_mut().unwrap().clear();
+                        fluid_boundary.get_mut().unwrap().clear();
+                    }
+                });
+        }
+
+        if self.sort_contacts {
+            counters.cd.contact_sorting_time.resume();
+
+            for contacts in self
+                .fluid_fluid_contacts
+                .iter_mut()
+                .chain(self.fluid_boundary_contacts.iter_mut())
+                .chain(self.boundary_boundary_contacts.iter_mut())
+            {
+                par_iter_mut!(contacts.contacts_mut()).for_each(|particle_contacts| {
+                    particle_contacts
+                        .get_mut()
+                        .unwrap()
+                        .sort_unstable_by_key(|c| (c.j_model, c.j))
+                });
+            }
+
+            counters.cd.contact_sorting_time.pause();
+        }
+    }
+
+    fn update_contacts_from_neighbor_lists(
+        &mut self,
+        counters: &mut Counters,
+        h: Real,
+        skin: Real,
+        fluids: &[Fluid],
+        boundaries: &[Boundary],
+    ) {
+        let radius = geometry::max_kernel_radius(h, fluids) + skin;
+        let query = ContactQuery {
             h,
-            &fluids,
-            &boundaries,
+            fluids,
+            boundaries,
+            filter: self.contact_filter.as_deref(),
+            periodic_domain: self.periodic_domain.as_ref(),
+        };
+        let lists = match &mut self.neighbor_lists {
+            Some(lists)
+                if lists.grid.cell_width() == radius
+                    && lists.is_valid(skin, fluids, boundaries, query.periodic_domain) =>
+            {
+                lists
+            }
+            lists => {
+                counters.cd.neighbor_list_updates += 1;
+                let lists = lists.insert(NeighborLists::new(radius));
+
+                counters.cd.grid_insertion_time.resume();
+                geometry::insert_fluids_to_grid(fluids, &mut lists.grid);
+                geometry::insert_boundaries_to_grid(boundaries, &mut lists.grid);
+                counters.cd.grid_insertion_time.pause();
+
+                geometry::compute_contacts_with_margin(
+                    counters,
+                    &query,
+                    skin,
+                    &mut lists.fluid_fluid_contacts,
+                    &mut lists.fluid_boundary_contacts,
+                    &mut lists.boundary_boundary_contacts,
+                    &lists.grid,
+                );
+
+                lists.fluid_positions = fluids.iter().map(|f| f.positions.clone()).collect();
+                lists.boundary_positions = boundaries
+                    .iter()
+                    .map(|b| {
+                        if b.density_map().is_some() {
+                            Vec::new()
+                        } else {
+                            b.positions.clone()
+                        }
+                    })
+                    .collect();
+                lists
+            }
+        };
+
+        counters.cd.neighborhood_search_time.resume();
+        geometry::filter_neighbor_lists(
+            &query,
+            [
+                &lists.fluid_fluid_contacts,
+                &lists.fluid_boundary_contacts,
+                &lists.boundary_boundary_contacts,
+            ],
             &mut self.fluid_fluid_contacts,
             &mut self.fluid_boundary_contacts,
             &mut self.boundary_boundary_contacts,
-            hgrid,
         );
+        counters.cd.neighborhood_search_time.pause();
     }
 }
@@ -1,5 +1,6 @@
 use crate::counters::Counters;
-use crate::geometry::HGrid;
+use crate::geometry::contact_filter::ContactFilterContext;
+use crate::geometry::{ContactFilter, HGrid, NeighborSearch, PeriodicDomain};
 use crate::math::{Point, Real, Vector};
 use crate::object::Boundary;
 use crate::object::Fluid;
@@ -11,6 +12,10 @@ use rayon::prelude::*;
 
 #[derive(Copy, Clone, Debug)]
 /// A particle inserted on a spacial grid.
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
 pub enum HGridEntry {
     /// A fluid particle with its fluid ID and particle ID.
     FluidParticle(usize, usize),
@@ -37,6 +42,10 @@ impl HGridEntry {
 /// only result in a force applied by the particle `j` to the particle `i`. The force applied by
 /// `i` on `j` will result from another contacts.
 /// In other words, for each par of distinct fluid particles, there will be be two symmetric contacts.
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
 pub struct Contact {
     /// The index of the first particle involved in this contact.
     pub i: usize,
@@ -80,6 +89,10 @@ impl Contact {
 
 #[derive(Debug)]
 /// The set of contacts affecting the particles of a single fluid.
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
 pub struct ParticlesContacts {
     // All the particle contact for one model.
     // `self.contacts[i]` contains all the contacts involving the particle `i`.
@@ -124,80 +137,209 @@ impl ParticlesContacts {
     }
 
     /// Apply a permutation to this set of contacts.
-    pub fn apply_permutation(&mut self, _permutation: &[usize]) {
-        unimplemented!()
+    ///
+    /// After this call, `self.contacts()[k]` contains the contacts that affected the particle
+    /// `permutation[k]`. The permutation may contain only a subset of the particles, in which
+    /// case the contacts of the particles missing from the permutation are removed. The particle
+    /// indices of contacts between particles of the same fluid are remapped accordingly, and
+    /// those involving a removed particle are removed too.
+    pub fn apply_permutation(&mut self, permutation: &[usize]) {
+        let mut new_indices = vec![usize::MAX; self.contacts.len()];
+
+        for (k, i) in permutation.iter().enumerate() {
+            new_indices[*i] = k;
+        }
+
+        let mut old_contacts = std::mem::take(&mut self.contacts);
+        self.contacts = permutation
+            .iter()
+            .map(|i| {
+                let mut contacts = std::mem::take(old_contacts[*i].get_mut().unwrap());
+                contacts.retain_mut(|c| {
+                    c.i = new_indices[c.i];
+
+                    if c.is_same_model_contact() {
+                        c.j = new_indices[c.j];
+                    }
+
+                    c.j != usize::MAX
+                });
+                RwLock::new(contacts)
+            })
+            .collect();
     }
 }
 
 /// Insert all the particles from the given fluids into the `grid`.
 pub fn insert_fluids_to_grid(fluids: &[Fluid], grid: &mut HGrid<HGridEntry>) {
     for (fluid_id, fluid) in fluids.iter().enumerate() {
-        for (particle_id, point) in fluid.positions.iter().enumerate() {
-            grid.insert(&point, HGridEntry::FluidParticle(fluid_id, particle_id));
-        }
+        grid.insert_all(&fluid.positions, |particle_id| {
+            HGridEntry::FluidParticle(fluid_id, particle_id)
+        });
     }
 }
 
 /// Insert all the particles from the given boundaries into the `grid`.
+///
+/// The virtual particles of boundaries represented by density maps are not inserted: their
+/// contacts are generated directly by `compute_contacts`.
 pub fn insert_boundaries_to_grid(boundaries: &[Boundary], grid: &mut HGrid<HGridEntry>) {
     for (boundary_id, boundary) in boundaries.iter().enumerate() {
-        for (particle_id, point) in boundary.positions.iter().enumerate() {
-            grid.insert(
-                &point,
-                HGridEntry::BoundaryParticle(boundary_id, particle_id),
-            );
+        if boundary.density_map().is_some() {
+            continue;
+        }
+
+        grid.insert_all(&boundary.positions, |particle_id| {
+            HGridEntry::BoundaryParticle(boundary_id, particle_id)
+        });
+    }
+}
+
+/// The largest kernel radius of the given fluids and of the boundaries, `h` being the kernel
+/// radius of the liquid world.
+///
+/// This is the width of the cells of a spacial grid able to find all the contacts.
+pub fn max_kernel_radius(h: Real, fluids: &[Fluid]) -> Real {
+    fluids
+        .iter()
+        .map(|fluid| fluid.kernel_radius(h))
+        .fold(h, Real::max)
+}
+
+/// The particles and parameters of a contact computation.
+#[derive(Copy, Clone)]
+pub struct ContactQuery<'a> {
+    /// The kernel radius of the liquid world.
+    ///
+    /// This is the kernel radius of the boundaries and of the fluids with the same resolution as
+    /// the liquid world (see `Fluid::kernel_radius`).
+    pub h: Real,
+    /// The fluids whose particles are inserted into the neighbor search structure.
+    pub fluids: &'a [Fluid],
+    /// The boundaries whose particles are inserted into the neighbor search structure.
+    pub boundaries: &'a [Boundary],
+    /// If set, only the contacts allowed by this filter are computed.
+    pub filter: Option<&'a dyn ContactFilter>,
+    /// If set, the contacts between particles close to opposite faces of this domain are
+    /// computed too.
+    pub periodic_domain: Option<&'a PeriodicDomain>,
+}
+
+impl<'a> ContactQuery<'a> {
+    /// A query for all the contacts between the particles of `fluids` and `boundaries`, without
+    /// filter nor periodic domain.
+    pub fn new(h: Real, fluids: &'a [Fluid], boundaries: &'a [Boundary]) -> Self {
+        Self {
+            h,
+            fluids,
+            boundaries,
+            filter: None,
+            periodic_domain: None,
         }
     }
 }
 
+// The distance under which two particles are in contact: the average of the kernel radii of
+// their fluids or boundaries, enlarged by `margin`. Averaging the radii keeps the contacts
+// symmetric between fluids with different resolutions.
+#[derive(Copy, Clone)]
+struct ContactRadius<'a> {
+    h: Real,
+    margin: Real,
+    max_kernel_radius: Real,
+    fluids: &'a [Fluid],
+}
+
+impl<'a> ContactRadius<'a> {
+    fn new(h: Real, margin: Real, fluids: &'a [Fluid]) -> Self {
+        Self {
+            h,
+            margin,
+            max_kernel_radius: max_kernel_radius(h, fluids),
+            fluids,
+        }
+    }
+
+    fn kernel_radius(&self, model: usize, is_boundary: bool) -> Real {
+        if is_boundary {
+            self.h
+        } else {
+            self.fluids[model].kernel_radius(self.h)
+        }
+    }
+
+    fn pair(
+        &self,
+        model_i: usize,
+        is_boundary_i: bool,
+        model_j: usize,
+        is_boundary_j: bool,
+    ) -> Real {
+        (self.kernel_radius(model_i, is_boundary_i) + self.kernel_radius(model_j, is_boundary_j))
+            * na::convert::<_, Real>(0.5)
+            + self.margin
+    }
+
+    // The largest distance at which the given particle may be in contact with another particle.
+    fn max_for(&self, entry: HGridEntry) -> Real {
+        let (model, _, is_boundary) = entry.into_tuple();
+        (self.kernel_radius(model, is_boundary) + self.max_kernel_radius)
+            * na::convert::<_, Real>(0.5)
+            + self.margin
+    }
+
+    fn max(&self) -> Real {
+        self.max_kernel_radius + self.margin
+    }
+}
+
 /// Compute all the contacts between the particles inserted in `grid`.
+///
+/// Two particles are in contact if they are closer than the average of the kernel radii of their
+/// fluids or boundaries (see `ContactQuery::h`). The cells of `grid` must not be smaller than the
+/// largest kernel radius.
 pub fn compute_contacts(
     counters: &mut Counters,
-    h: Real,
-    fluids: &[Fluid],
-    boundaries: &[Boundary],
+    query: &ContactQuery,
+    fluid_fluid_contacts: &mut Vec<ParticlesContacts>,
+    fluid_boundary_contacts: &mut Vec<ParticlesContacts>,
+    boundary_boundary_contacts: &mut Vec<ParticlesContacts>,
+    grid: &HGrid<HGridEntry>,
+) {
+    compute_contacts_with_margin(
+        counters,
+        query,
+        na::zero::<Real>(),
+        fluid_fluid_contacts,
+        fluid_boundary_contacts,
+        boundary_boundary_contacts,
+        grid,
+    )
+}
+
+// Same as `compute_contacts`, with the contact distances enlarged by `margin`.
+pub(crate) fn compute_contacts_with_margin(
+    counters: &mut Counters,
+    query: &ContactQuery,
+    margin: Real,
     fluid_fluid_contacts: &mut Vec<ParticlesContacts>,
     fluid_boundary_contacts: &mut Vec<ParticlesContacts>,
     boundary_boundary_contacts: &mut Vec<ParticlesContacts>,
     grid: &HGrid<HGridEntry>,
 ) {
+    let (fluids, boundaries) = (query.fluids, query.boundaries);
+    let radius = ContactRadius::new(query.h, margin, fluids);
     // Needed so the loop in -1..=1 bellow works.
-    assert_eq!(h, grid.cell_width());
+    assert!(grid.cell_width() >= radius.max());
     counters.cd.neighborhood_search_time.resume();
 
-    fluid_fluid_contacts.resize_with(fluids.len(), || ParticlesContacts::new());
-    fluid_boundary_contacts.resize_with(fluids.len(), || ParticlesContacts::new());
-    boundary_boundary_contacts.resize_with(boundaries.len(), || ParticlesContacts::new());
-
-    for (fluid, contacts) in fluids.iter().zip(fluid_fluid_contacts.iter_mut()) {
-        contacts
-            .contacts
-            .iter_mut()
-            .for_each(|c| c.write().unwrap().clear());
-        contacts
-            .contacts
-            .resize_with(fluid.num_particles(), || RwLock::new(Vec::new()))
-    }
-
-    for (fluid, contacts) in fluids.iter().zip(fluid_boundary_contacts.iter_mut()) {
-        contacts
-            .contacts
-            .iter_mut()
-            .for_each(|c| c.write().unwrap().clear());
-        contacts
-            .contacts
-            .resize_with(fluid.num_particles(), || RwLock::new(Vec::new()))
-    }
-
-    for (boundary, contacts) in boundaries.iter().zip(boundary_boundary_contacts.iter_mut()) {
-        contacts
-            .contacts
-            .iter_mut()
-            .for_each(|c| c.write().unwrap().clear());
-        contacts
-            .contacts
-            .resize_with(boundary.num_particles(), || RwLock::new(Vec::new()))
-    }
+    reset_contacts(
+        fluids,
+        boundaries,
+        fluid_fluid_contacts,
+        fluid_boundary_contacts,
+        boundary_boundary_contacts,
+    );
 
     #[cfg(feature = "dim2")]
     let neighbours: [(i64, i64); 5] = [(0, 0), (0, 1), (1, -1), (1, 0), (1, 1)];
@@ -219,6 +361,11 @@ pub fn compute_contacts(
         (1, 1, 1),
     ];
 
+    let filter = query
+        .filter
+        .map(|filter| ContactFilterContext::new(filter, fluids, boundaries));
+    let filter = filter.as_ref();
+
     par_iter!(grid.inner_table()).for_each(|(curr_cell, curr_particles)| {
         for &val in neighbours.iter() {
             #[cfg(feature = "dim2")]
@@ -233,38 +380,318 @@ pub fn compute_contacts(
             };
             if let Some(neighbor_particles) = grid.cell(&neighbor_cell) {
                 compute_contacts_for_pair_of_cells(
-                    h,
+                    &radius,
                     fluids,
                     boundaries,
-                    fluid_fluid_contacts,
-                    fluid_boundary_contacts,
-                    boundary_boundary_contacts,
+                    [
+                        fluid_fluid_contacts,
+                        fluid_boundary_contacts,
+                        boundary_boundary_contacts,
+                    ],
                     curr_cell,
                     curr_particles,
                     &neighbor_cell,
                     neighbor_particles,
+                    filter,
                 );
             }
         }
     });
 
+    if let Some(domain) = query.periodic_domain {
+        compute_periodic_contacts(
+            &radius,
+            fluids,
+            boundaries,
+            [
+                fluid_fluid_contacts,
+                fluid_boundary_contacts,
+                boundary_boundary_contacts,
+            ],
+            grid,
+            domain,
+            filter,
+        );
+    }
+
+    add_density_map_contacts(fluids, boundaries, fluid_boundary_contacts, filter);
     counters.cd.neighborhood_search_time.pause();
 }
 
-fn compute_contacts_for_pair_of_cells(
-    h: Real,
+/// Compute all the contacts between the particles inserted in `neighbor_search`.
+///
+/// This is the same as `compute_contacts`, but the neighbors of each particle are searched
+/// individually with `NeighborSearch::particles_in_aabb`, so it works with any neighbor search
+/// structure.
+pub fn compute_contacts_with_neighbor_search<N: NeighborSearch + ?Sized>(
+    counters: &mut Counters,
+    query: &ContactQuery,
+    fluid_fluid_contacts: &mut Vec<ParticlesContacts>,
+    fluid_boundary_contacts: &mut Vec<ParticlesContacts>,
+    boundary_boundary_contacts: &mut Vec<ParticlesContacts>,
+    neighbor_search: &N,
+) {
+    let (fluids, boundaries) = (query.fluids, query.boundaries);
+    counters.cd.neighborhood_search_time.resume();
+
+    reset_contacts(
+        fluids,
+        boundaries,
+        fluid_fluid_contacts,
+        fluid_boundary_contacts,
+        boundary_boundary_contacts,
+    );
+
+    let filter = query
+        .filter
+        .map(|filter| ContactFilterContext::new(filter, fluids, boundaries));
+    let filter = filter.as_ref();
+    let radius = ContactRadius::new(query.h, na::zero::<Real>(), fluids);
+    let entries = inserted_entries(fluids, boundaries);
+    // Using the same cell for a particle and its neighbors computes each contact once, from
+    // each of its particles.
+    let cell = Point::origin();
+
+    par_iter!(entries).for_each(|entry| {
+        let point = entry_position(fluids, boundaries, *entry);
+        let extents = Vector::repeat(radius.max_for(*entry));
+        let neighbors: Vec<_> = neighbor_search
+            .particles_in_aabb(&(point - extents), &(point + extents))
+            .collect();
+
+        compute_contacts_for_pair_of_cells(
+            &radius,
+            fluids,
+            boundaries,
+            [
+                fluid_fluid_contacts,
+                fluid_boundary_contacts,
+                boundary_boundary_contacts,
+            ],
+            &cell,
+            &[*entry],
+            &cell,
+            &neighbors,
+            filter,
+        );
+    });
+
+    if let Some(domain) = query.periodic_domain {
+        compute_periodic_contacts(
+            &radius,
+            fluids,
+            boundaries,
+            [
+                fluid_fluid_contacts,
+                fluid_boundary_contacts,
+                boundary_boundary_contacts,
+            ],
+            neighbor_search,
+            domain,
+            filter,
+        );
+    }
+
+    add_density_map_contacts(fluids, boundaries, fluid_boundary_contacts, filter);
+    counters.cd.neighborhood_search_time.pause();
+}
+
+// Resizes the contact sets to the number of particles, and removes all their contacts.
+fn reset_contacts(
+    fluids: &[Fluid],
+    boundaries: &[Boundary],
+    fluid_fluid_contacts: &mut Vec<ParticlesContacts>,
+    fluid_boundary_contacts: &mut Vec<ParticlesContacts>,
+    boundary_boundary_contacts: &mut Vec<ParticlesContacts>,
+) {
+    fluid_fluid_contacts.resize_with(fluids.len(), ParticlesContacts::new);
+    fluid_boundary_contacts.resize_with(fluids.len(), ParticlesContacts::new);
+    boundary_boundary_contacts.resize_with(boundaries.len(), ParticlesContacts::new);
+
+    for (fluid, contacts) in fluids.iter().zip(fluid_fluid_contacts.iter_mut()) {
+        contacts
+            .contacts
+            .iter_mut()
+            .for_each(|c| c.write().unwrap().clear());
+        contacts
+            .contacts
+            .resize_with(fluid.num_particles(), || RwLock::new(Vec::new()))
+    }
+
+    for (fluid, contacts) in fluids.iter().zip(fluid_boundary_contacts.iter_mut()) {
+        contacts
+            .contacts
+            .iter_mut()
+            .for_each(|c| c.write().unwrap().clear());
+        contacts
+            .contacts
+            .resize_with(fluid.num_particles(), || RwLock::new(Vec::new()))
+    }
+
+    for (boundary, contacts) in boundaries.iter().zip(boundary_boundary_contacts.iter_mut()) {
+        contacts
+            .contacts
+            .iter_mut()
+            .for_each(|c| c.write().unwrap().clear());
+        contacts
+            .contacts
+            .resize_with(boundary.num_particles(), || RwLock::new(Vec::new()))
+    }
+}
+
+// All the particles inserted into a neighbor search structure.
+fn inserted_entries(fluids: &[Fluid], boundaries: &[Boundary]) -> Vec<HGridEntry> {
+    let fluid_entries = fluids.iter().enumerate().flat_map(|(fluid_id, fluid)| {
+        (0..fluid.num_particles()).map(move |i| HGridEntry::FluidParticle(fluid_id, i))
+    });
+    let boundary_entries = boundaries
+        .iter()
+        .enumerate()
+        .filter(|(_, boundary)| boundary.density_map().is_none())
+        .flat_map(|(boundary_id, boundary)| {
+            (0..boundary.num_particles()).map(move |i| HGridEntry::BoundaryParticle(boundary_id, i))
+        });
+
+    fluid_entries.chain(boundary_entries).collect()
+}
+
+fn entry_position(fluids: &[Fluid], boundaries: &[Boundary], entry: HGridEntry) -> Point<Real> {
+    match entry {
+        HGridEntry::FluidParticle(fluid_id, i) => fluids[fluid_id].positions[i],
+        HGridEntry::BoundaryParticle(boundary_id, i) => boundaries[boundary_id].positions[i],
+    }
+}
+
+/// Extracts the contacts closer than their kernel radius from neighbor lists computed with a
+/// larger radius.
+///
+/// The neighbor lists must have been computed with `compute_contacts` for the same fluids and
+/// boundaries, with the same particle counts. The contacts with the virtual particles of density
+/// map boundaries are recomputed instead, since these particles are regenerated at each substep.
+pub(crate) fn filter_neighbor_lists(
+    query: &ContactQuery,
+    neighbor_lists: [&[ParticlesContacts]; 3],
+    fluid_fluid_contacts: &mut Vec<ParticlesContacts>,
+    fluid_boundary_contacts: &mut Vec<ParticlesContacts>,
+    boundary_boundary_contacts: &mut Vec<ParticlesContacts>,
+) {
+    let (fluids, boundaries, periodic_domain) =
+        (query.fluids, query.boundaries, query.periodic_domain);
+    let [fluid_fluid_lists, fluid_boundary_lists, boundary_boundary_lists] = neighbor_lists;
+    let radius = ContactRadius::new(query.h, na::zero::<Real>(), fluids);
+    let fluid_position = |model: usize, i: usize| fluids[model].positions[i];
+    let boundary_position = |model: usize, i: usize| boundaries[model].positions[i];
+    let is_close = |pi: &Point<Real>, pj: &Point<Real>, r: Real| {
+        let pj = periodic_domain.map_or(*pj, |domain| domain.closest_image(pi, pj));
+        na::distance_squared(pi, &pj) <= r * r
+    };
+
+    let filter_lists = |lists: &[ParticlesContacts],
+                        contacts: &mut Vec<ParticlesContacts>,
+                        (is_boundary_i, is_boundary_j): (bool, bool),
+                        position_i: &(dyn Fn(usize, usize) -> Point<Real> + Sync),
+                        position_j: &(dyn Fn(usize, usize) -> Point<Real> + Sync),
+                        skip_model_j: &(dyn Fn(usize) -> bool + Sync)| {
+        contacts.resize_with(lists.len(), ParticlesContacts::new);
+
+        for (list, contacts) in lists.iter().zip(contacts.iter_mut()) {
+            contacts
+                .contacts
+                .resize_with(list.contacts.len(), || RwLock::new(Vec::new()));
+
+            par_iter_mut!(contacts.contacts)
+                .zip(par_iter!(list.contacts))
+                .for_each(|(contacts, list)| {
+                    let contacts = contacts.get_mut().unwrap();
+                    contacts.clear();
+                    contacts.extend(list.read().unwrap().iter().filter(|c| {
+                        !skip_model_j(c.j_model)
+                            && is_close(
+                                &position_i(c.i_model, c.i),
+                                &position_j(c.j_model, c.j),
+                                radius.pair(c.i_model, is_boundary_i, c.j_model, is_boundary_j),
+                            )
+                    }));
+                });
+        }
+    };
+
+    filter_lists(
+        fluid_fluid_lists,
+        fluid_fluid_contacts,
+        (false, false),
+        &fluid_position,
+        &fluid_position,
+        &|_| false,
+    );
+    filter_lists(
+        fluid_boundary_lists,
+        fluid_boundary_contacts,
+        (false, true),
+        &fluid_position,
+        &boundary_position,
+        &|j_model| boundaries[j_model].density_map().is_some(),
+    );
+    filter_lists(
+        boundary_boundary_lists,
+        boundary_boundary_contacts,
+        (true, true),
+        &boundary_position,
+        &boundary_position,
+        &|_| false,
+    );
+
+    let filter = query
+        .filter
+        .map(|filter| ContactFilterContext::new(filter, fluids, boundaries));
+    add_density_map_contacts(fluids, boundaries, fluid_boundary_contacts, filter.as_ref());
+}
+
+// Each virtual particle of a density map boundary is only in contact with the fluid particle it
+// was generated for.
+fn add_density_map_contacts(
     fluids: &[Fluid],
     boundaries: &[Boundary],
-    fluid_fluid_contacts: &[ParticlesContacts],
     fluid_boundary_contacts: &[ParticlesContacts],
-    boundary_boundary_contacts: &[ParticlesContacts],
+    filter: Option<&ContactFilterContext>,
+) {
+    for (boundary_id, boundary) in boundaries.iter().enumerate() {
+        for (k, (fluid_id, i)) in boundary.density_map_particles.iter().enumerate() {
+            let entry_i = HGridEntry::FluidParticle(*fluid_id, *i);
+            let entry_j = HGridEntry::BoundaryParticle(boundary_id, k);
+
+            if filter.is_none_or(|f| f.test(fluids, boundaries, entry_i, entry_j)) {
+                fluid_boundary_contacts[*fluid_id].contacts[*i]
+                    .write()
+                    .unwrap()
+                    .push(Contact {
+                        i_model: *fluid_id,
+                        j_model: boundary_id,
+                        i: *i,
+                        j: k,
+                        weight: na::zero::<Real>(),
+                        gradient: Vector::zeros(),
+                    });
+            }
+        }
+    }
+}
+
+fn compute_contacts_for_pair_of_cells(
+    radius: &ContactRadius,
+    fluids: &[Fluid],
+    boundaries: &[Boundary],
+    contacts: [&[ParticlesContacts]; 3],
     curr_cell: &Point<i64>,
     curr_particles: &[HGridEntry],
     neighbor_cell: &Point<i64>,
     neighbor_particles: &[HGridEntry],
+    filter: Option<&ContactFilterContext>,
 ) {
-    for entry in curr_particles {
-        match entry {
+    let [fluid_fluid_contacts, fluid_boundary_contacts, boundary_boundary_contacts] = contacts;
+
+    for entry_i in curr_particles {
+        match entry_i {
             HGridEntry::BoundaryParticle(boundary_i, particle_i) => {
                 for entry in neighbor_particles {
                     // NOTE: we are not interested by boundary-fluid contacts.
@@ -281,8 +708,12 @@ fn compute_contacts_for_pair_of_cells(
 
                             let pi = &bi.positions[*particle_i];
                             let pj = &bj.positions[*particle_j];
+                            let r = radius.pair(*boundary_i, true, *boundary_j, true);
 
-                            if na::distance_squared(pi, pj) <= h * h {
+                            if na::distance_squared(pi, pj) <= r * r
+                                && filter
+                                    .is_none_or(|f| f.test(fluids, boundaries, *entry_i, *entry))
+                            {
                                 let contact = Contact {
                                     i_model: *boundary_i,
                                     j_model: *boundary_j,
@@ -318,8 +749,12 @@ fn compute_contacts_for_pair_of_cells(
                             }
                             let pi = &boundaries[*boundary_i].positions[*particle_i];
                             let pj = &fluids[*fluid_j].positions[*particle_j];
+                            let r = radius.pair(*fluid_j, false, *boundary_i, true);
 
-                            if na::distance_squared(pi, pj) <= h * h {
+                            if na::distance_squared(pi, pj) <= r * r
+                                && filter
+                                    .is_none_or(|f| f.test(fluids, boundaries, *entry, *entry_i))
+                            {
                                 let contact = Contact {
                                     i_model: *fluid_j,
                                     j_model: *boundary_i,
@@ -362,9 +797,12 @@ fn compute_contacts_for_pair_of_cells(
                         }
                         fluids[fluid_j].positions[particle_j]
                     };
+                    let r = radius.pair(*fluid_i, false, fluid_j, is_boundary_j);
 
-                    if na::distance_squared(&pi, &pj) <= h * h {
-                        assert!(na::distance_squared(&pj, &pi) <= h * h);
+                    if na::distance_squared(&pi, &pj) <= r * r
+                        && filter.is_none_or(|f| f.test(fluids, boundaries, *entry_i, *entry))
+                    {
+                        assert!(na::distance_squared(&pj, &pi) <= r * r);
                         let contact = Contact {
                             i_model: *fluid_i,
                             j_model: fluid_j,
@@ -399,6 +837,116 @@ fn compute_contacts_for_pair_of_cells(
     }
 }
 
+// Computes the contacts between particles close to opposite faces of a periodic domain, by
+// searching the neighbors of the periodic images of each particle.
+fn compute_periodic_contacts<N: NeighborSearch + ?Sized>(
+    radius: &ContactRadius,
+    fluids: &[Fluid],
+    boundaries: &[Boundary],
+    contacts: [&[ParticlesContacts]; 3],
+    neighbor_search: &N,
+    domain: &PeriodicDomain,
+    filter: Option<&ContactFilterContext>,
+) {
+    let [fluid_fluid_contacts, fluid_boundary_contacts, boundary_boundary_contacts] = contacts;
+    let shifts = domain.image_shifts();
+    let entries = inserted_entries(fluids, boundaries);
+
+    par_iter!(entries).for_each(|entry_i| {
+        let (model_i, particle_i, is_boundary_i) = entry_i.into_tuple();
+        let max_radius = radius.max_for(*entry_i);
+        let extents = Vector::repeat(max_radius);
+        let (pi, groups_i) = if is_boundary_i {
+            let bi = &boundaries[model_i];
+            (bi.positions[particle_i], bi.interaction_groups)
+        } else {
+            let fi = &fluids[model_i];
+            (fi.positions[particle_i], fi.interaction_groups)
+        };
+
+        for shift in &shifts {
+            // Only one of two opposite shifts finds the contacts between two fluid particles,
+            // or between two boundary particles. The other contact is obtained by flipping it.
+            let first_nonzero = shift.iter().find(|e| **e != na::zero::<Real>()).unwrap();
+            let is_lower_shift = *first_nonzero < na::zero::<Real>();
+            let image = pi + shift;
+
+            if (0..shift.len()).any(|k| {
+                shift[k] != na::zero::<Real>()
+                    && (image[k] < domain.mins[k] - max_radius
+                        || image[k] > domain.maxs[k] + max_radius)
+            }) {
+                continue;
+            }
+
+            for entry_j in neighbor_search.particles_in_aabb(&(image - extents), &(image + extents))
+            {
+                let (model_j, particle_j, is_boundary_j) = entry_j.into_tuple();
+
+                match (is_boundary_i, is_boundary_j) {
+                    // Handled from the fluid particle.
+                    (true, false) => continue,
+                    (false, true) => {}
+                    _ => {
+                        if !is_lower_shift {
+                            continue;
+                        }
+                    }
+                }
+
+                let (pj, groups_j) = if is_boundary_j {
+                    let bj = &boundaries[model_j];
+                    (bj.positions[particle_j], bj.interaction_groups)
+                } else {
+                    let fj = &fluids[model_j];
+                    (fj.positions[particle_j], fj.interaction_groups)
+                };
+
+                if (model_i != model_j || is_boundary_i != is_boundary_j)
+                    && !groups_i.test(groups_j)
+                {
+                    continue;
+                }
+
+                let r = radius.pair(model_i, is_boundary_i, model_j, is_boundary_j);
+
+                if na::distance_squared(&image, &pj) > r * r
+                    || !filter.is_none_or(|f| f.test(fluids, boundaries, *entry_i, entry_j))
+                {
+                    continue;
+                }
+
+                let contact = Contact {
+                    i_model: model_i,
+                    j_model: model_j,
+                    i: particle_i,
+                    j: particle_j,
+                    weight: na::zero::<Real>(),
+                    gradient: Vector::zeros(),
+                };
+
+                let (contacts_i, contacts_j) = match (is_boundary_i, is_boundary_j) {
+                    (false, false) => (fluid_fluid_contacts, Some(fluid_fluid_contacts)),
+                    (false, true) => (fluid_boundary_contacts, None),
+                    _ => (boundary_boundary_contacts, Some(boundary_boundary_contacts)),
+                };
+
+                contacts_i[model_i].contacts[particle_i]
+                    .write()
+                    .unwrap()
+                    .push(contact);
+
+                if let Some(contacts_j) = contacts_j {
+                    contacts_j[model_j].contacts[particle_j]
+                        .write()
+                        .unwrap()
+                        .push(contact.flip());
+                }
+            }
+        }
+    });
+}
+
 /// Compute all the contacts between the particles of a single fluid object.
 pub fn compute_self_contacts(h: Real, fluid: &Fluid, contacts: &mut ParticlesContacts) {
     contacts
@@ -0,0 +1,348 @@
+use crate::kernel::Kernel;
+use crate::math::{Point, Real, Vector, DIM};
+#[cfg(feature = "parry")]
+use parry::shape::Shape;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use std::ops::{Add, Mul};
+
+// The number of quadrature samples per kernel radius used to integrate the kernel.
+const QUADRATURE_RESOLUTION: i64 = 6;
+
+/// The contribution of a static boundary to the density of the fluids, precomputed on a grid.
+///
+/// This implements the density maps of Koschier & Bender (2017): instead of sampling a boundary
+/// with particles, its contribution to the density of a fluid particle, and the gradient of this
+/// contribution, are precomputed at the nodes of a regular grid from the signed distance function
+/// of the boundary. This avoids the bumpiness of particle-sampled boundaries, and needs much less
+/// memory for large static geometries.
+///
+/// The boundary density at a point is the integral, over the solid, of the kernel centered at this
+/// point. It is zero at a distance larger than the kernel radius from the solid, and one deep
+/// inside of it. A density map is attached to a boundary with `Boundary::with_density_map`.
+#[derive(Clone, Debug)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub struct DensityMap {
+    origin: Point<Real>,
+    cell_width: Real,
+    resolution: Vector<usize>,
+    particle_radius: Real,
+    kernel_radius: Real,
+    distances: Vec<Real>,
+    densities: Vec<Real>,
+    density_gradients: Vec<Vector<Real>>,
+}
+
+impl DensityMap {
+    /// Computes the density map of the solid described by the signed distance function `sdf`.
+    ///
+    /// The signed distance must be negative inside of the solid. The map covers the box from
+    /// `mins` to `maxs`, enlarged by the kernel radius, with grid nodes `cell_width` apart. The
+    /// kernel `K`, `particle_radius`, and `kernel_radius` should match the density kernel, the
+    /// particle radius, and the kernel radius of the liquid world. The solid is dilated by
+    /// `particle_radius` so the fluid particles rest one particle radius away from its surface,
+    /// like they would with a particle-sampled boundary.
+    ///
+    /// The kernel is integrated numerically, so `sdf` is evaluated a few hundred times (about a
+    /// thousand times in 3D) for each node close to the surface of the solid.
+    pub fn new<K: Kernel>(
+        mins: &Point<Real>,
+        maxs: &Point<Real>,
+        cell_width: Real,
+        particle_radius: Real,
+        kernel_radius: Real,
+        sdf: impl Fn(&Point<Real>) -> Real + Sync,
+    ) -> Self {
+        let margin = kernel_radius + particle_radius;
+        let origin = mins - Vector::repeat(margin);
+        let resolution = (maxs - mins + Vector::repeat(margin * na::convert::<_, Real>(2.0)))
+            .map(|e| na::try_convert::<Real, f64>(e / cell_width).unwrap().ceil() as usize + 1);
+        let num_nodes = resolution.iter().product();
+        let mut map = Self {
+            origin,
+            cell_width,
+            resolution,
+            particle_radius,
+            kernel_radius,
+            distances: vec![na::zero::<Real>(); num_nodes],
+            densities: vec![na::zero::<Real>(); num_nodes],
+            density_gradients: vec![Vector::zeros(); num_nodes],
+        };
+
+        // The quadrature samples of the kernel, normalized so the density is exactly one deep
+        // inside of the solid.
+        let spacing = kernel_radius / na::convert::<_, Real>(QUADRATURE_RESOLUTION as f64);
+        let mut samples = Vec::new();
+
+        for id in 0..(2 * QUADRATURE_RESOLUTION + 1).pow(DIM as u32) {
+            let mut offset = Vector::zeros();
+            let mut rest = id;
+
+            for k in 0..DIM {
+                let coord = rest % (2 * QUADRATURE_RESOLUTION + 1) - QUADRATURE_RESOLUTION;
+                offset[k] = spacing * na::convert::<_, Real>(coord as f64);
+                rest /= 2 * QUADRATURE_RESOLUTION + 1;
+            }
+
+            let weight = K::apply(offset, kernel_radius);
+
+            if weight > na::zero::<Real>() {
+                samples.push((offset, weight));
+            }
+        }
+
+        let total_weight = samples
+            .iter()
+            .fold(na::zero::<Real>(), |acc, (_, w)| acc + *w);
+
+        let node_positions: Vec<_> = (0..num_nodes).map(|i| map.node_position(i)).collect();
+
+        par_iter_mut!(map.distances)
+            .zip(par_iter!(node_positions))
+            .for_each(|(distance, pt)| *distance = sdf(pt));
+
+        par_iter_mut!(map.densities)
+            .zip(par_iter!(map.distances))
+            .zip(par_iter!(node_positions))
+            .for_each(|((density, distance), pt)| {
+                let distance = *distance - particle_radius;
+
+                *density = if distance >= kernel_radius {
+                    na::zero::<Real>()
+                } else if distance <= -kernel_radius {
+                    na::one::<Real>()
+                } else {
+                    // Smooth the inside/outside transition over one quadrature spacing to
+                    // avoid staircase artifacts.
+                    let half = na::convert::<_, Real>(0.5);
+                    samples
+                        .iter()
+                        .fold(na::zero::<Real>(), |acc, (offset, weight)| {
+                            let distance = sdf(&(pt + offset)) - particle_radius;
+                            let occupancy = (half - distance / spacing)
+                                .max(na::zero::<Real>())
+                                .min(na::one::<Real>());
+                            acc + *weight * occupancy
+                        })
+                        / total_weight
+                };
+            });
+
+        let gradients: Vec<_> = (0..num_nodes).map(|i| map.node_gradient(i)).collect();
+        map.density_gradients = gradients;
+        map
+    }
+
+    /// Computes the density map of the given shape, in its local-space.
+    ///
+    /// See `Self::new` for details about the parameters.
+    #[cfg(feature = "parry")]
+    pub fn from_shape<K: Kernel>(
+        shape: &dyn Shape,
+        cell_width: Real,
+        particle_radius: Real,
+        kernel_radius: Real,
+    ) -> Self {
+        let aabb = shape.compute_local_aabb();
+        let sdf = |pt: &Point<Real>| {
+            let proj = shape.project_local_point(pt, false);
+            let distance = na::distance(pt, &proj.point);
+
+            if proj.is_inside {
+                -distance
+            } else {
+                distance
+            }
+        };
+
+        Self::new::<K>(
+            &aabb.mins,
+            &aabb.maxs,
+            cell_width,
+            particle_radius,
+            kernel_radius,
+            sdf,
+        )
+    }
+
+    /// The particle radius this map was computed with.
+    pub fn particle_radius(&self) -> Real {
+        self.particle_radius
+    }
+
+    /// The kernel radius this map was computed with.
+    pub fn kernel_radius(&self) -> Real {
+        self.kernel_radius
+    }
+
+    /// The distance between two adjacent nodes of this map.
+    pub fn cell_width(&self) -> Real {
+        self.cell_width
+    }
+
+    /// The boundary density at the given point.
+    ///
+    /// This is zero outside of the domain covered by this map.
+    pub fn density(&self, pt: &Point<Real>) -> Real {
+        self.interpolate(&self.densities, na::zero::<Real>(), pt)
+    }
+
+    /// The gradient of the boundary density at the given point.
+    ///
+    /// This is zero outside of the domain covered by this map.
+    pub fn density_gradient(&self, pt: &Point<Real>) -> Vector<Real> {
+        self.interpolate(&self.density_gradients, Vector::zeros(), pt)
+    }
+
+    /// The signed distance from the given point to the boundary.
+    ///
+    /// Returns `None` outside of the domain covered by this map.
+    pub fn distance(&self, pt: &Point<Real>) -> Option<Real> {
+        self.cell_at(pt)
+            .map(|_| self.interpolate(&self.distances, na::zero::<Real>(), pt))
+    }
+
+    /// An estimate of the point of the boundary surface closest to `pt`.
+    ///
+    /// Returns `None` if `pt` is outside of the domain covered by this map, or too far from the
+    /// surface.
+    pub fn surface_point(&self, pt: &Point<Real>) -> Option<Point<Real>> {
+        let distance = self.distance(pt)?;
+        // The density increases toward the inside of the solid.
+        let normal = -self
+            .density_gradient(pt)
+            .try_normalize(na::zero::<Real>())?;
+        Some(pt - normal * distance)
+    }
+
+    fn node_position(&self, id: usize) -> Point<Real> {
+        let mut pt = self.origin;
+        let mut rest = id;
+
+        for k in 0..DIM {
+            pt[k] += self.cell_width * na::convert::<_, Real>((rest % self.resolution[k]) as f64);
+            rest /= self.resolution[k];
+        }
+
+        pt
+    }
+
+    // The gradient of the density at a node, with finite differences.
+    fn node_gradient(&self, id: usize) -> Vector<Real> {
+        let mut gradient = Vector::zeros();
+        let mut stride = 1;
+        let mut rest = id;
+
+        for k in 0..DIM {
+            let coord = rest % self.resolution[k];
+            let prev = if coord > 0 { id - stride } else { id };
+            let next = if coord + 1 < self.resolution[k] {
+                id + stride
+            } else {
+                id
+            };
+
+            if prev != next {
+                let steps = na::convert::<_, Real>((next - prev) as f64 / stride as f64);
+                gradient[k] =
+                    (self.densities[next] - self.densities[prev]) / (steps * self.cell_width);
+            }
+
+            rest /= self.resolution[k];
+            stride *= self.resolution[k];
+        }
+
+        gradient
+    }
+
+    // The index of the first node of the cell containing `pt`, and the local coordinates of
+    // `pt` in this cell.
+    fn cell_at(&self, pt: &Point<Real>) -> Option<(usize, Vector<Real>)> {
+        let local = (pt - self.origin) / self.cell_width;
+        let mut id = 0;
+        let mut stride = 1;
+        let mut frac = Vector::zeros();
+
+        for k in 0..DIM {
+            if local[k] < na::zero::<Real>() {
+                return None;
+            }
+
+            let coord = na::try_convert::<Real, f64>(local[k]).unwrap().floor() as usize;
+
+            if coord + 1 >= self.resolution[k] {
+                return None;
+            }
+
+            frac[k] = local[k] - na::convert::<_, Real>(coord as f64);
+            id += coord * stride;
+            stride *= self.resolution[k];
+        }
+
+        Some((id, frac))
+    }
+
+    // Multilinear interpolation of the given node values.
+    fn interpolate<T>(&self, values: &[T], zero: T, pt: &Point<Real>) -> T
+    where
+        T: Copy + Add<Output = T> + Mul<Real, Output = T>,
+    {
+        let Some((id, frac)) = self.cell_at(pt) else {
+            return zero;
+        };
+
+        let mut result = zero;
+
+        for corner in 0..(1 << DIM) {
+            let mut corner_id = id;
+            let mut stride = 1;
+            let mut weight = na::one::<Real>();
+
+            for k in 0..DIM {
+                if corner & (1 << k) != 0 {
+                    corner_id += stride;
+                    weight *= frac[k];
+                } else {
+                    weight *= na::one::<Real>() - frac[k];
+                }
+
+                stride *= self.resolution[k];
+            }
+
+            result = result + values[corner_id] * weight;
+        }
+
+        result
+    }
+}
+
+#[test]
+fn half_space_density() {
+    use crate::kernel::CubicSplineKernel;
+
+    let particle_radius = na::convert::<_, Real>(0.05);
+    let kernel_radius = particle_radius * na::convert::<_, Real>(4.0);
+    let map = DensityMap::new::<CubicSplineKernel>(
+        &Point::from(Vector::repeat(na::convert::<_, Real>(-1.0))),
+        &Point::origin(),
+        particle_radius / na::convert::<_, Real>(2.0),
+        particle_radius,
+        kernel_radius,
+        |pt| pt[DIM - 1],
+    );
+
+    let at_height = |h: f64| {
+        let mut pt = Point::from(Vector::repeat(na::convert::<_, Real>(-0.5)));
+        pt[DIM - 1] = na::convert(h);
+        pt
+    };
+    let eps = na::convert::<_, Real>(1.0e-3);
+
+    assert!((map.density(&at_height(0.05)) - na::convert::<_, Real>(0.5)).abs() < eps);
+    assert!((map.density(&at_height(-0.5)) - na::one::<Real>()).abs() < eps);
+    assert_eq!(map.density(&at_height(0.3)), na::zero::<Real>());
+    assert!(map.density_gradient(&at_height(0.05))[DIM - 1] < na::zero::<Real>());
+}
@@ -3,10 +3,15 @@ use fnv::FnvHasher;
 use std::collections::HashMap;
 
 use crate::math::{Point, Real, Vector, DIM};
+use na::RealField;
 
+use std::fmt::{self, Display, Formatter};
 use std::hash::BuildHasher;
 
-#[derive(Copy, Clone, Debug)]
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+#[derive(Copy, Clone, Debug, Default)]
 pub struct DeterministicState;
 
 impl BuildHasher for DeterministicState {
@@ -17,11 +22,75 @@ impl BuildHasher for DeterministicState {
     }
 }
 
+/// Occupancy statistics of a spacial grid.
+#[derive(Copy, Clone, Debug, Default)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub struct HGridStatistics {
+    /// The width of a cell of the grid.
+    pub cell_width: Real,
+    /// The number of non-empty cells.
+    pub num_cells: usize,
+    /// The total number of elements stored in the grid.
+    pub num_elements: usize,
+    /// The average number of elements per non-empty cell.
+    pub avg_elements_per_cell: Real,
+    /// The number of elements in the most populated cell.
+    pub max_elements_per_cell: usize,
+    /// The number of times the underlying hash map had to grow since the grid was created.
+    pub num_rehashes: usize,
+}
+
+impl HGridStatistics {
+    /// Suggests a kernel radius giving `num_neighbors` neighbors to each element, on average.
+    ///
+    /// This assumes that the elements remain distributed with the same density as the one
+    /// measured by these statistics in the non-empty cells. Since the cells of the grids of a
+    /// liquid world are as wide as its kernel radius, this is also the suggested cell width.
+    /// Returns `None` if the grid is empty.
+    pub fn suggested_kernel_radius(&self, num_neighbors: Real) -> Option<Real> {
+        if self.num_cells == 0 || self.cell_width <= na::zero::<Real>() {
+            return None;
+        }
+
+        let elements_per_volume = self.avg_elements_per_cell / self.cell_width.powi(DIM as i32);
+        // The volume of a ball of unit radius.
+        #[cfg(feature = "dim2")]
+        let unit_ball_volume = Real::pi();
+        #[cfg(feature = "dim3")]
+        let unit_ball_volume = Real::pi() * na::convert::<_, Real>(4.0 / 3.0);
+        let ball_volume = num_neighbors / (elements_per_volume * unit_ball_volume);
+
+        Some(ball_volume.powf(na::convert::<_, Real>(1.0 / DIM as f64)))
+    }
+}
+
+impl Display for HGridStatistics {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(f, "Cell width: {}", self.cell_width)?;
+        writeln!(f, "Num cells: {}", self.num_cells)?;
+        writeln!(f, "Num elements: {}", self.num_elements)?;
+        writeln!(f, "Avg elements per cell: {}", self.avg_elements_per_cell)?;
+        writeln!(f, "Max elements per cell: {}", self.max_elements_per_cell)?;
+        writeln!(f, "Num rehashes: {}", self.num_rehashes)
+    }
+}
+
 /// A grid based on spacial hashing.
 #[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+#[cfg_attr(feature = "serde-serialize", serde(bound = ""))]
 pub struct HGrid<T> {
+    // The cells are refilled at each substep, so they don't need to be serialized.
+    #[cfg_attr(feature = "serde-serialize", serde(skip))]
     cells: HashMap<Point<i64>, Vec<T>, DeterministicState>,
     cell_width: Real,
+    num_rehashes: usize,
 }
 
 impl<T> HGrid<T> {
@@ -30,6 +99,7 @@ impl<T> HGrid<T> {
         Self {
             cells: HashMap::with_hasher(DeterministicState),
             cell_width,
+            num_rehashes: 0,
         }
     }
 
@@ -59,7 +129,103 @@ impl<T> HGrid<T> {
     /// Inserts the given `element` into the cell containing the given `point`.
     pub fn insert(&mut self, point: &Point<Real>, element: T) {
         let key = self.key(point);
-        self.cells.entry(key).or_insert(Vec::new()).push(element)
+        let capacity = self.cells.capacity();
+        self.cells.entry(key).or_insert(Vec::new()).push(element);
+
+        if self.cells.capacity() != capacity {
+            self.num_rehashes += 1;
+        }
+    }
+
+    /// Inserts the elements `element(i)` into the cells containing each `points[i]`.
+    ///
+    /// This is equivalent to calling `Self::insert` for each point in order. If the `parallel`
+    /// feature is enabled, each thread buckets a contiguous range of the points into its own
+    /// cells, and these are then merged into this grid.
+    pub fn insert_all(&mut self, points: &[Point<Real>], element: impl Fn(usize) -> T + Sync)
+    where
+        T: Send,
+    {
+        #[cfg(feature = "parallel")]
+        {
+            use std::collections::hash_map::Entry;
+
+            let num_threads = rayon::current_num_threads();
+
+            if num_threads > 1 {
+                let cell_width = self.cell_width;
+                let chunk_size = points.len().div_ceil(num_threads).max(1);
+                let chunk_cells: Vec<HashMap<_, Vec<T>, _>> = points
+                    .par_chunks(chunk_size)
+                    .enumerate()
+                    .map(|(chunk_id, chunk)| {
+                        let mut cells = HashMap::with_hasher(DeterministicState);
+
+                        for (k, point) in chunk.iter().enumerate() {
+                            let key =
+                                Point::from(point.coords.map(|e| Self::quantify(e, cell_width)));
+                            cells
+                                .entry(key)
+                                .or_insert_with(Vec::new)
+                                .push(element(chunk_id * chunk_size + k));
+                        }
+
+                        cells
+                    })
+                    .collect();
+
+                // Merging the chunks in order keeps the elements of each cell in insertion order.
+                for cells in chunk_cells {
+                    for (key, elements) in cells {
+                        let capacity = self.cells.capacity();
+
+                        match self.cells.entry(key) {
+                            Entry::Occupied(entry) => entry.into_mut().extend(elements),
+                            Entry::Vacant(entry) => {
+                                let _ = entry.insert(elements);
+                            }
+                        }
+
+                        if self.cells.capacity() != capacity {
+                            self.num_rehashes += 1;
+                        }
+                    }
+                }
+
+                return;
+            }
+        }
+
+        for (i, point) in points.iter().enumerate() {
+            self.insert(point, element(i));
+        }
+    }
+
+    /// Computes occupancy statistics of this grid.
+    pub fn statistics(&self) -> HGridStatistics {
+        let num_cells = self.cells.len();
+        let mut num_elements = 0;
+        let mut max_elements_per_cell = 0;
+
+        for elements in self.cells.values() {
+            num_elements += elements.len();
+            max_elements_per_cell = max_elements_per_cell.max(elements.len());
+        }
+
+        let avg_elements_per_cell = if num_cells != 0 {
+            na::convert::<_, Real>(num_elements as f64 / num_cells as f64)
+        } else {
+            na::zero::<Real>()
+        };
+
+        HGridStatistics {
+            cell_width: self.cell_width,
+            num_cells,
+            num_elements,
+            avg_elements_per_cell,
+            max_elements_per_cell,
+            num_rehashes: self.num_rehashes,
+        }
     }
 
     /// Returns the element attached to the cell containing the given `point`.
@@ -155,10 +321,10 @@ impl CellRangeIterator {
     }
 
     fn with_center(center: Point<i64>, radius: i64) -> Self {
-        let start = center - Vector::repeat(radius as i64);
+        let start = center - Vector::repeat(radius);
         Self {
             start,
-            end: center + Vector::repeat(radius as i64),
+            end: center + Vector::repeat(radius),
             curr: start,
             done: false,
         }
@@ -229,4 +395,81 @@ mod test {
 
         assert!(iter.zip(expected.iter()).all(|(a, b)| a == *b))
     }
+
+    #[test]
+    fn grid_insert_all_matches_insert() {
+        use super::HGrid;
+        use crate::math::{Point, Real};
+
+        let points: Vec<_> = (0..200)
+            .map(|i| Point::origin() + crate::math::Vector::repeat((i % 37) as Real * 0.13 - 2.0))
+            .collect();
+        let mut expected = HGrid::new(0.5 as Real);
+        let mut grid = HGrid::new(0.5 as Real);
+
+        for (i, pt) in points.iter().enumerate() {
+            expected.insert(pt, i);
+        }
+
+        grid.insert(&points[3], 1000);
+        grid.insert_all(&points, |i| i);
+        expected
+            .cells
+            .get_mut(&expected.key(&points[3]))
+            .unwrap()
+            .insert(0, 1000);
+
+        assert_eq!(grid.cells, expected.cells);
+    }
+
+    #[test]
+    fn grid_statistics() {
+        use super::HGrid;
+        use crate::math::{Point, Real};
+
+        let mut grid = HGrid::new(1.0 as Real);
+        grid.insert(&Point::origin(), 0);
+        grid.insert(&Point::origin(), 1);
+        grid.insert(&(Point::origin() + crate::math::Vector::repeat(1.5)), 2);
+
+        let stats = grid.statistics();
+        assert_eq!(stats.num_cells, 2);
+        assert_eq!(stats.num_elements, 3);
+        assert_eq!(stats.max_elements_per_cell, 2);
+        assert_eq!(stats.avg_elements_per_cell, 1.5);
+    }
+
+    #[test]
+    fn grid_suggested_kernel_radius() {
+        use super::HGrid;
+        use crate::math::{Point, Real, Vector, DIM};
+
+        // A lattice with 4^DIM elements per cell.
+        let mut grid = HGrid::new(1.0 as Real);
+        let n: usize = 16 * 4;
+        let points: Vec<_> = (0..n.pow(DIM as u32))
+            .map(|i| {
+                let coords = Vector::from_fn(|k, _| ((i / n.pow(k as u32)) % n) as Real * 0.25);
+                Point::from(coords)
+            })
+            .collect();
+        grid.insert_all(&points, |i| i);
+
+        let stats = grid.statistics();
+        assert_eq!(stats.avg_elements_per_cell, 4.0f64.powi(DIM as i32) as Real);
+        assert!(HGrid::<usize>::new(1.0)
+            .statistics()
+            .suggested_kernel_radius(30.0)
+            .is_none());
+
+        // A ball of radius `r` contains about `pi * r^DIM * 4^DIM` elements.
+        for r in [0.5, 1.0, 2.0] {
+            let num_neighbors = points
+                .iter()
+                .filter(|p| na::distance(p, &Point::from(Vector::repeat(8.0))) <= r)
+                .count() as Real;
+            let suggested = stats.suggested_kernel_radius(num_neighbors).unwrap();
+            assert!((suggested - r).abs() < r * 0.1, "{} {}", suggested, r);
+        }
+    }
 }
@@ -1,12 +1,25 @@
 //! Acceleration data structures for collision detection.
 
+pub use self::contact_filter::{ContactFilter, ContactModel};
 pub use self::contact_manager::ContactManager;
 pub use self::contacts::{
-    compute_contacts, compute_self_contacts, insert_boundaries_to_grid, insert_fluids_to_grid,
+    compute_contacts, compute_contacts_with_neighbor_search, compute_self_contacts,
+    insert_boundaries_to_grid, insert_fluids_to_grid, max_kernel_radius, Contact, ContactQuery,
     HGridEntry, ParticlesContacts,
 };
-pub use self::hgrid::HGrid;
+pub use self::density_map::DensityMap;
+pub use self::hgrid::{HGrid, HGridStatistics};
+pub use self::multi_resolution_grid::MultiResolutionGrid;
+pub use self::neighbor_search::NeighborSearch;
+pub use self::periodic_domain::PeriodicDomain;
 
+pub(crate) use self::contacts::{compute_contacts_with_margin, filter_neighbor_lists};
+
+mod contact_filter;
 mod contact_manager;
 mod contacts;
+mod density_map;
 mod hgrid;
+mod multi_resolution_grid;
+mod neighbor_search;
+mod periodic_domain;
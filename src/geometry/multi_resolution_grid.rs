@@ -0,0 +1,208 @@
+use crate::counters::Counters;
+use crate::geometry::{
+    self, ContactQuery, HGrid, HGridEntry, HGridStatistics, NeighborSearch, ParticlesContacts,
+};
+use crate::math::{Point, Real};
+use crate::object::{Boundary, Fluid};
+
+/// A hierarchy of spacial grids, with one level per kernel radius.
+///
+/// The particles of each fluid are inserted into the level with cells as wide as the kernel
+/// radius of this fluid (see `Fluid::kernel_radius`), and the boundary particles are inserted into
+/// the level of the kernel radius of the liquid world. This keeps the cells tight around the
+/// particles of every resolution: fine particles are not stored into the large cells needed by
+/// coarse particles, and coarse particles do not fill countless small cells.
+///
+/// If all the particles have the same kernel radius, this is just an `HGrid<HGridEntry>`.
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub struct MultiResolutionGrid {
+    // The first level is the one of the kernel radius of the liquid world.
+    levels: Vec<HGrid<HGridEntry>>,
+}
+
+impl MultiResolutionGrid {
+    /// Creates a grid hierarchy for a liquid world with the kernel radius `h`.
+    pub fn new(h: Real) -> Self {
+        Self {
+            levels: vec![HGrid::new(h)],
+        }
+    }
+
+    /// The kernel radius of the liquid world this hierarchy was created for.
+    pub fn kernel_radius(&self) -> Real {
+        self.levels[0].cell_width()
+    }
+
+    /// The levels of this hierarchy.
+    ///
+    /// The first level has cells as wide as the kernel radius of the liquid world. The other
+    /// levels are created when fluids with other kernel radii are inserted, and are kept (but
+    /// emptied) by `NeighborSearch::clear`.
+    pub fn levels(&self) -> &[HGrid<HGridEntry>] {
+        &self.levels
+    }
+
+    // The index of the level with cells of width `cell_width`, creating it if needed.
+    fn level_index(&mut self, cell_width: Real) -> usize {
+        if let Some(i) = self
+            .levels
+            .iter()
+            .position(|level| level.cell_width() == cell_width)
+        {
+            i
+        } else {
+            self.levels.push(HGrid::new(cell_width));
+            self.levels.len() - 1
+        }
+    }
+}
+
+impl NeighborSearch for MultiResolutionGrid {
+    fn clear(&mut self) {
+        for level in &mut self.levels {
+            level.clear();
+        }
+    }
+
+    fn insert_fluids(&mut self, fluids: &[Fluid]) {
+        let h = self.kernel_radius();
+
+        for (fluid_id, fluid) in fluids.iter().enumerate() {
+            let level = self.level_index(fluid.kernel_radius(h));
+            self.levels[level].insert_all(&fluid.positions, |particle_id| {
+                HGridEntry::FluidParticle(fluid_id, particle_id)
+            });
+        }
+    }
+
+    fn insert_boundaries(&mut self, boundaries: &[Boundary]) {
+        geometry::insert_boundaries_to_grid(boundaries, &mut self.levels[0])
+    }
+
+    fn particles_in_aabb<'a>(
+        &'a self,
+        mins: &Point<Real>,
+        maxs: &Point<Real>,
+    ) -> Box<dyn Iterator<Item = HGridEntry> + 'a> {
+        let (mins, maxs) = (*mins, *maxs);
+        Box::new(self.levels.iter().flat_map(move |level| {
+            level
+                .cells_intersecting_aabb(&mins, &maxs)
+                .flat_map(|(_, particles)| particles.iter().copied())
+        }))
+    }
+
+    fn compute_contacts(
+        &self,
+        counters: &mut Counters,
+        query: &ContactQuery,
+        fluid_fluid_contacts: &mut Vec<ParticlesContacts>,
+        fluid_boundary_contacts: &mut Vec<ParticlesContacts>,
+        boundary_boundary_contacts: &mut Vec<ParticlesContacts>,
+    ) {
+        if self.levels[1..]
+            .iter()
+            .all(|level| level.inner_table().is_empty())
+        {
+            // All the particles have the same kernel radius.
+            self.levels[0].compute_contacts(
+                counters,
+                query,
+                fluid_fluid_contacts,
+                fluid_boundary_contacts,
+                boundary_boundary_contacts,
+            )
+        } else {
+            geometry::compute_contacts_with_neighbor_search(
+                counters,
+                query,
+                fluid_fluid_contacts,
+                fluid_boundary_contacts,
+                boundary_boundary_contacts,
+                self,
+            )
+        }
+    }
+
+    fn statistics(&self) -> Option<HGridStatistics> {
+        Some(self.levels[0].statistics())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::MultiResolutionGrid;
+    use crate::counters::Counters;
+    use crate::geometry::{ContactQuery, NeighborSearch, ParticlesContacts};
+    use crate::math::{Point, Real, Vector};
+    use crate::object::{Boundary, Fluid};
+
+    #[test]
+    fn contacts_use_the_average_kernel_radius() {
+        let h = 0.2;
+        let points = |offset: Real| -> Vec<_> {
+            (0..150)
+                .map(|i| {
+                    let coords =
+                        Vector::from_fn(|k, _| ((i * (k + 3) * 7919) % 101) as Real / 100.0);
+                    Point::from(coords.add_scalar(offset))
+                })
+                .collect()
+        };
+        let mut coarse = Fluid::new(points(0.0), 0.1, 1000.0, Default::default());
+        coarse.kernel_radius = Some(2.0 * h);
+        let fine = Fluid::new(points(0.05), 0.05, 1000.0, Default::default());
+        let fluids = [coarse, fine];
+        let boundaries = [Boundary::new(points(0.1), Default::default())];
+
+        let mut grid = MultiResolutionGrid::new(h);
+        grid.insert_fluids(&fluids);
+        grid.insert_boundaries(&boundaries);
+        assert_eq!(grid.levels().len(), 2);
+
+        let (mut fluid_fluid, mut fluid_boundary) = (Vec::new(), Vec::new());
+        grid.compute_contacts(
+            &mut Counters::new(),
+            &ContactQuery::new(h, &fluids, &boundaries),
+            &mut fluid_fluid,
+            &mut fluid_boundary,
+            &mut Vec::new(),
+        );
+
+        for (fluid_i, fi) in fluids.iter().enumerate() {
+            for (i, pi) in fi.positions.iter().enumerate() {
+                let neighbors = |positions: &[Point<Real>], r: Real| -> Vec<_> {
+                    (0..positions.len())
+                        .filter(|j| na::distance_squared(pi, &positions[*j]) <= r * r)
+                        .collect()
+                };
+                let found = |contacts: &[ParticlesContacts], model: usize| -> Vec<_> {
+                    let mut found: Vec<_> = contacts[fluid_i]
+                        .particle_contacts(i)
+                        .read()
+                        .unwrap()
+                        .iter()
+                        .filter(|c| c.j_model == model)
+                        .map(|c| c.j)
+                        .collect();
+                    found.sort_unstable();
+                    found
+                };
+
+                for (fluid_j, fj) in fluids.iter().enumerate() {
+                    let r = (fi.kernel_radius(h) + fj.kernel_radius(h)) / 2.0;
+                    assert_eq!(found(&fluid_fluid, fluid_j), neighbors(&fj.positions, r));
+                }
+
+                let r = (fi.kernel_radius(h) + h) / 2.0;
+                assert_eq!(
+                    found(&fluid_boundary, 0),
+                    neighbors(&boundaries[0].positions, r)
+                );
+            }
+        }
+    }
+}
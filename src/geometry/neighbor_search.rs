@@ -0,0 +1,201 @@
+use crate::counters::Counters;
+use crate::geometry::{self, ContactQuery, HGrid, HGridEntry, HGridStatistics, ParticlesContacts};
+use crate::math::{Point, Real};
+use crate::object::{Boundary, Fluid};
+
+/// A spacial acceleration structure used to find the particles close to each other.
+///
+/// The liquid world clears it and inserts all its particles at the beginning of each substep. It
+/// is then used to compute the contacts between particles, and to answer the spacial queries of
+/// the couplings, the diffuse particle solver, and the liquid world itself. Different scenes
+/// favor different structures: the default `HGrid<HGridEntry>` is well-suited for most scenes, but
+/// a uniform grid with a fixed domain may be faster for dense tanks, and a tree may use less
+/// memory for sparse splashes.
+pub trait NeighborSearch: Send + Sync {
+    /// Removes all the particles from this structure.
+    fn clear(&mut self);
+
+    /// Inserts all the particles of the given fluids.
+    ///
+    /// The particles are identified by `HGridEntry::FluidParticle` entries, with the index of
+    /// each fluid in `fluids`.
+    fn insert_fluids(&mut self, fluids: &[Fluid]);
+
+    /// Inserts all the particles of the given boundaries.
+    ///
+    /// The particles are identified by `HGridEntry::BoundaryParticle` entries, with the index of
+    /// each boundary in `boundaries`. The virtual particles of boundaries represented by density
+    /// maps must not be inserted: their contacts are generated by `Self::compute_contacts`.
+    fn insert_boundaries(&mut self, boundaries: &[Boundary]);
+
+    /// The particles inserted into this structure that may be inside of the AABB with corners
+    /// `mins` and `maxs`.
+    ///
+    /// All the inserted particles inside of the AABB must be yielded, but some particles outside
+    /// of it may be yielded too.
+    fn particles_in_aabb<'a>(
+        &'a self,
+        mins: &Point<Real>,
+        maxs: &Point<Real>,
+    ) -> Box<dyn Iterator<Item = HGridEntry> + 'a>;
+
+    /// Computes the contacts between all the particles of `query` closer than the average of
+    /// their kernel radii.
+    ///
+    /// See `geometry::compute_contacts` for the meaning of the arguments. The default
+    /// implementation searches the neighbors of each particle with `Self::particles_in_aabb`.
+    fn compute_contacts(
+        &self,
+        counters: &mut Counters,
+        query: &ContactQuery,
+        fluid_fluid_contacts: &mut Vec<ParticlesContacts>,
+        fluid_boundary_contacts: &mut Vec<ParticlesContacts>,
+        boundary_boundary_contacts: &mut Vec<ParticlesContacts>,
+    ) {
+        geometry::compute_contacts_with_neighbor_search(
+            counters,
+            query,
+            fluid_fluid_contacts,
+            fluid_boundary_contacts,
+            boundary_boundary_contacts,
+            self,
+        )
+    }
+
+    /// Occupancy statistics of this structure, if it is a spacial grid.
+    fn statistics(&self) -> Option<HGridStatistics> {
+        None
+    }
+}
+
+impl NeighborSearch for HGrid<HGridEntry> {
+    fn clear(&mut self) {
+        HGrid::clear(self)
+    }
+
+    fn insert_fluids(&mut self, fluids: &[Fluid]) {
+        geometry::insert_fluids_to_grid(fluids, self)
+    }
+
+    fn insert_boundaries(&mut self, boundaries: &[Boundary]) {
+        geometry::insert_boundaries_to_grid(boundaries, self)
+    }
+
+    fn particles_in_aabb<'a>(
+        &'a self,
+        mins: &Point<Real>,
+        maxs: &Point<Real>,
+    ) -> Box<dyn Iterator<Item = HGridEntry> + 'a> {
+        Box::new(
+            self.cells_intersecting_aabb(mins, maxs)
+                .flat_map(|(_, particles)| particles.iter().copied()),
+        )
+    }
+
+    fn compute_contacts(
+        &self,
+        counters: &mut Counters,
+        query: &ContactQuery,
+        fluid_fluid_contacts: &mut Vec<ParticlesContacts>,
+        fluid_boundary_contacts: &mut Vec<ParticlesContacts>,
+        boundary_boundary_contacts: &mut Vec<ParticlesContacts>,
+    ) {
+        if self.cell_width() < geometry::max_kernel_radius(query.h, query.fluids) {
+            // The cells are too small for the neighbors of the coarsest particles to be in
+            // adjacent cells.
+            return geometry::compute_contacts_with_neighbor_search(
+                counters,
+                query,
+                fluid_fluid_contacts,
+                fluid_boundary_contacts,
+                boundary_boundary_contacts,
+                self,
+            );
+        }
+
+        geometry::compute_contacts(
+            counters,
+            query,
+            fluid_fluid_contacts,
+            fluid_boundary_contacts,
+            boundary_boundary_contacts,
+            self,
+        )
+    }
+
+    fn statistics(&self) -> Option<HGridStatistics> {
+        Some(HGrid::statistics(self))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::NeighborSearch;
+    use crate::counters::Counters;
+    use crate::geometry::{
+        self, ContactQuery, HGrid, HGridEntry, ParticlesContacts, PeriodicDomain,
+    };
+    use crate::math::{Point, Real, Vector, DIM};
+    use crate::object::{Boundary, Fluid};
+
+    fn sorted_contacts(contacts: &[ParticlesContacts]) -> Vec<(usize, usize, usize, usize)> {
+        let mut result: Vec<_> = contacts
+            .iter()
+            .flat_map(|c| c.contacts())
+            .flat_map(|c| c.read().unwrap().clone())
+            .map(|c| (c.i_model, c.i, c.j_model, c.j))
+            .collect();
+        result.sort_unstable();
+        result
+    }
+
+    #[test]
+    fn default_contacts_match_grid_contacts() {
+        let h = 0.25;
+        let points = |offset: Real| -> Vec<_> {
+            (0..200)
+                .map(|i| {
+                    let coords =
+                        Vector::from_fn(|k, _| ((i * (k + 3) * 7919) % 101) as Real / 50.0);
+                    Point::from(coords.add_scalar(offset - 1.0))
+                })
+                .collect()
+        };
+        let fluids = [Fluid::new(points(0.0), 0.05, 1000.0, Default::default())];
+        let boundaries = [Boundary::new(points(0.1), Default::default())];
+        let domain = PeriodicDomain::new(Point::from([-1.0; DIM]), Point::from([1.0; DIM]));
+
+        for periodic_domain in [None, Some(&domain)] {
+            let mut grid = HGrid::<HGridEntry>::new(h);
+            grid.insert_fluids(&fluids);
+            grid.insert_boundaries(&boundaries);
+
+            let query = ContactQuery {
+                periodic_domain,
+                ..ContactQuery::new(h, &fluids, &boundaries)
+            };
+            let mut expected = (Vec::new(), Vec::new(), Vec::new());
+            let mut result = (Vec::new(), Vec::new(), Vec::new());
+            grid.compute_contacts(
+                &mut Counters::new(),
+                &query,
+                &mut expected.0,
+                &mut expected.1,
+                &mut expected.2,
+            );
+            geometry::compute_contacts_with_neighbor_search(
+                &mut Counters::new(),
+                &query,
+                &mut result.0,
+                &mut result.1,
+                &mut result.2,
+                &grid,
+            );
+
+            assert!(!sorted_contacts(&expected.0).is_empty());
+            assert_eq!(sorted_contacts(&expected.0), sorted_contacts(&result.0));
+            assert_eq!(sorted_contacts(&expected.1), sorted_contacts(&result.1));
+            assert_eq!(sorted_contacts(&expected.2), sorted_contacts(&result.2));
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use crate::math::{Point, Real, Vector, DIM};
+
+/// An axis-aligned box whose opposite faces are identified along some of its axes.
+///
+/// A particle leaving the box through a face of a periodic axis re-enters it through the opposite
+/// face, and the particles close to opposite faces interact with each other. The box must be at
+/// least twice as large as the kernel radius along each periodic axis.
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub struct PeriodicDomain {
+    /// The corner of the box with the smallest coordinates.
+    pub mins: Point<Real>,
+    /// The corner of the box with the largest coordinates.
+    pub maxs: Point<Real>,
+    /// Whether the domain is periodic along each axis.
+    pub periodic_axes: [bool; DIM],
+}
+
+impl PeriodicDomain {
+    /// A domain periodic along all the axes.
+    pub fn new(mins: Point<Real>, maxs: Point<Real>) -> Self {
+        Self::with_axes(mins, maxs, [true; DIM])
+    }
+
+    /// A domain periodic only along the axes for which `periodic_axes` is `true`.
+    ///
+    /// The coordinates of `mins` and `maxs` along the other axes are ignored.
+    pub fn with_axes(mins: Point<Real>, maxs: Point<Real>, periodic_axes: [bool; DIM]) -> Self {
+        Self {
+            mins,
+            maxs,
+            periodic_axes,
+        }
+    }
+
+    /// The period along each axis, or zero along the non-periodic axes.
+    pub fn period(&self) -> Vector<Real> {
+        let mut period = self.maxs - self.mins;
+
+        for k in 0..DIM {
+            if !self.periodic_axes[k] {
+                period[k] = na::zero::<Real>();
+            }
+        }
+
+        period
+    }
+
+    /// Moves `point` inside of the box along each periodic axis by adding a multiple of the period.
+    pub fn wrap_point(&self, point: &mut Point<Real>) {
+        let period = self.period();
+
+        for k in 0..DIM {
+            if self.periodic_axes[k] {
+                let shift = ((point[k] - self.mins[k]) / period[k]).floor();
+
+                if shift != na::zero::<Real>() {
+                    point[k] -= shift * period[k];
+
+                    // Guard against rounding errors putting the point exactly on the upper face.
+                    if point[k] >= self.maxs[k] {
+                        point[k] = self.mins[k];
+                    }
+                }
+            }
+        }
+    }
+
+    /// The periodic image of `point` closest to `reference`.
+    pub fn closest_image(&self, reference: &Point<Real>, point: &Point<Real>) -> Point<Real> {
+        let period = self.period();
+        let mut result = *point;
+
+        for k in 0..DIM {
+            if self.periodic_axes[k] {
+                let shift = ((point[k] - reference[k]) / period[k]).round();
+                result[k] -= shift * period[k];
+            }
+        }
+
+        result
+    }
+
+    /// The translations from the domain to each of its adjacent periodic images.
+    pub(crate) fn image_shifts(&self) -> Vec<Vector<Real>> {
+        let period = self.period();
+        let mut shifts = Vec::new();
+
+        for id in 0..3usize.pow(DIM as u32) {
+            let mut shift = Vector::zeros();
+            let mut rest = id;
+            let mut valid = true;
+
+            for k in 0..DIM {
+                let coeff = (rest % 3) as i64 - 1;
+                valid = valid && (coeff == 0 || self.periodic_axes[k]);
+                shift[k] = period[k] * na::convert::<_, Real>(coeff as f64);
+                rest /= 3;
+            }
+
+            if valid && shift != Vector::zeros() {
+                shifts.push(shift);
+            }
+        }
+
+        shifts
+    }
+}
@@ -0,0 +1,105 @@
+use std::fmt::{Display, Formatter};
+use std::future::Future;
+use std::pin::pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Waker};
+
+/// An error raised by the GPU backend.
+#[derive(Clone, PartialEq, Debug)]
+pub enum GpuError {
+    /// No GPU adapter is available on this system.
+    NoAdapter,
+    /// The GPU device could not be created.
+    ///
+    /// The string describes the reason of the failure.
+    DeviceCreation(String),
+    /// A buffer could not be read back from the GPU.
+    ///
+    /// The string describes the reason of the failure.
+    BufferMapping(String),
+    /// The data downloaded from the GPU does not match the object it is downloaded into.
+    ///
+    /// The string describes the mismatch.
+    Mismatch(String),
+}
+
+impl Display for GpuError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            GpuError::NoAdapter => write!(f, "no GPU adapter available"),
+            GpuError::DeviceCreation(desc) => {
+                write!(f, "failed to create the GPU device: {}", desc)
+            }
+            GpuError::BufferMapping(desc) => write!(f, "failed to read a GPU buffer: {}", desc),
+            GpuError::Mismatch(desc) => write!(f, "mismatched GPU data: {}", desc),
+        }
+    }
+}
+
+impl std::error::Error for GpuError {}
+
+/// The GPU device and queue used by the GPU solvers.
+#[derive(Clone, Debug)]
+pub struct GpuContext {
+    device: Arc<wgpu::Device>,
+    queue: Arc<wgpu::Queue>,
+}
+
+impl GpuContext {
+    /// Creates a context on the default high-performance GPU adapter of this system.
+    ///
+    /// The adapter is requested synchronously, and may be a software rasterizer if no hardware
+    /// adapter is available.
+    pub fn new() -> Result<Self, GpuError> {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+        let adapter = block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            force_fallback_adapter: false,
+            compatible_surface: None,
+        }))
+        .ok_or(GpuError::NoAdapter)?;
+        // The pressure solve binds more storage buffers than the downlevel limits allow.
+        let (device, queue) = block_on(adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                label: Some("salva"),
+                required_features: wgpu::Features::empty(),
+                required_limits: adapter.limits(),
+                memory_hints: wgpu::MemoryHints::Performance,
+            },
+            None,
+        ))
+        .map_err(|e| GpuError::DeviceCreation(e.to_string()))?;
+
+        Ok(Self::from_device(Arc::new(device), Arc::new(queue)))
+    }
+
+    /// Creates a context sharing an existing device and its queue, e.g., the ones of a renderer.
+    pub fn from_device(device: Arc<wgpu::Device>, queue: Arc<wgpu::Queue>) -> Self {
+        Self { device, queue }
+    }
+
+    /// The GPU device.
+    pub fn device(&self) -> &wgpu::Device {
+        &self.device
+    }
+
+    /// The queue of the GPU device.
+    pub fn queue(&self) -> &wgpu::Queue {
+        &self.queue
+    }
+}
+
+// The futures returned by wgpu on native platforms are completed by polling the device, so they
+// can be busy-polled without any async runtime.
+pub(crate) fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let mut context = Context::from_waker(Waker::noop());
+
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+            return output;
+        }
+
+        std::thread::yield_now();
+    }
+}
@@ -0,0 +1,622 @@
+use std::borrow::Cow;
+use std::sync::mpsc;
+
+use na::RealField;
+use wgpu::util::DeviceExt;
+
+use crate::gpu::{GpuContext, GpuError};
+use crate::math::{Point, Real, Vector, DIM};
+use crate::object::{Boundary, Fluid};
+
+const WORKGROUP_SIZE: u32 = 256;
+
+const PARAMS: u32 = 0;
+const POSITIONS: u32 = 1;
+const VELOCITIES: u32 = 2;
+const VELOCITY_CHANGES: u32 = 3;
+const VOLUMES: u32 = 4;
+const PARTICLE_DATA: u32 = 5;
+const PARTICLE_CELLS: u32 = 6;
+const CELL_COUNTS: u32 = 7;
+const CELL_STARTS: u32 = 8;
+const BLOCK_SUMS: u32 = 9;
+const SORTED_PARTICLES: u32 = 10;
+const NUM_BINDINGS: usize = 11;
+
+/// The compute passes of a step, in execution order, with the bindings used by each of them.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Pass {
+    ClearCells,
+    CountParticles,
+    ScanBlocks,
+    ScanBlockSums,
+    AddBlockOffsets,
+    ScatterParticles,
+    ComputeBoundaryVolumes,
+    ComputeDensities,
+    PredictDensities,
+    UpdateVelocityChanges,
+    Integrate,
+}
+
+impl Pass {
+    const ALL: [Pass; 11] = [
+        Pass::ClearCells,
+        Pass::CountParticles,
+        Pass::ScanBlocks,
+        Pass::ScanBlockSums,
+        Pass::AddBlockOffsets,
+        Pass::ScatterParticles,
+        Pass::ComputeBoundaryVolumes,
+        Pass::ComputeDensities,
+        Pass::PredictDensities,
+        Pass::UpdateVelocityChanges,
+        Pass::Integrate,
+    ];
+
+    fn entry_point(self) -> &'static str {
+        match self {
+            Pass::ClearCells => "clear_cells",
+            Pass::CountParticles => "count_particles",
+            Pass::ScanBlocks => "scan_blocks",
+            Pass::ScanBlockSums => "scan_block_sums",
+            Pass::AddBlockOffsets => "add_block_offsets",
+            Pass::ScatterParticles => "scatter_particles",
+            Pass::ComputeBoundaryVolumes => "compute_boundary_volumes",
+            Pass::ComputeDensities => "compute_densities",
+            Pass::PredictDensities => "predict_densities",
+            Pass::UpdateVelocityChanges => "update_velocity_changes",
+            Pass::Integrate => "integrate",
+        }
+    }
+
+    // The pipeline layouts are derived from the shader, so they only contain the bindings
+    // actually used by each entry point.
+    fn bindings(self) -> &'static [u32] {
+        match self {
+            Pass::ClearCells => &[PARAMS, CELL_COUNTS],
+            Pass::CountParticles => &[PARAMS, POSITIONS, PARTICLE_CELLS, CELL_COUNTS],
+            Pass::ScanBlocks => &[CELL_COUNTS, CELL_STARTS, BLOCK_SUMS],
+            Pass::ScanBlockSums => &[PARAMS, CELL_STARTS, BLOCK_SUMS],
+            Pass::AddBlockOffsets => &[CELL_COUNTS, CELL_STARTS, BLOCK_SUMS],
+            Pass::ScatterParticles => &[PARAMS, PARTICLE_CELLS, CELL_COUNTS, SORTED_PARTICLES],
+            Pass::ComputeBoundaryVolumes => {
+                &[PARAMS, POSITIONS, VOLUMES, CELL_STARTS, SORTED_PARTICLES]
+            }
+            Pass::ComputeDensities => &[
+                PARAMS,
+                POSITIONS,
+                VELOCITIES,
+                VELOCITY_CHANGES,
+                VOLUMES,
+                PARTICLE_DATA,
+                CELL_STARTS,
+                SORTED_PARTICLES,
+            ],
+            Pass::PredictDensities => &[
+                PARAMS,
+                POSITIONS,
+                VELOCITIES,
+                VELOCITY_CHANGES,
+                VOLUMES,
+                PARTICLE_DATA,
+                CELL_STARTS,
+                SORTED_PARTICLES,
+            ],
+            Pass::UpdateVelocityChanges => &[
+                PARAMS,
+                POSITIONS,
+                VELOCITY_CHANGES,
+                VOLUMES,
+                PARTICLE_DATA,
+                CELL_STARTS,
+                SORTED_PARTICLES,
+            ],
+            Pass::Integrate => &[PARAMS, POSITIONS, VELOCITIES, VELOCITY_CHANGES],
+        }
+    }
+}
+
+/// The particle buffers of the fluid and boundaries uploaded to the GPU.
+struct GpuParticles {
+    buffers: Vec<wgpu::Buffer>,
+    bind_groups: Vec<wgpu::BindGroup>,
+    num_fluid: u32,
+    num_total: u32,
+    table_size: u32,
+    density0: Real,
+}
+
+/// A fluid solver running the neighbor search, the density computation, and a DFSPH-style
+/// pressure solve with compute shaders.
+///
+/// The particles of one fluid and of a set of boundaries are uploaded to the GPU with `upload`,
+/// simulated with `step`, and read back with `download`. The particles stay on the GPU between
+/// two steps, so data is only transferred when explicitly requested.
+///
+/// This solver is much more limited than the CPU pipeline of the `LiquidWorld`:
+/// - it uses the cubic spline kernel, without any non-pressure force;
+/// - the boundaries are static: their particles do not move and do not receive any force;
+/// - only the density is enforced, with a fixed number of iterations and no divergence solve.
+pub struct GpuFluidSolver {
+    context: GpuContext,
+    kernel_radius: Real,
+    pressure_iterations: usize,
+    pipelines: Vec<wgpu::ComputePipeline>,
+    particles: Option<GpuParticles>,
+}
+
+impl GpuFluidSolver {
+    /// Creates a GPU solver using the cubic spline kernel with the given radius.
+    ///
+    /// The compute pipelines are compiled on the device of `context`.
+    pub fn new(context: GpuContext, kernel_radius: Real) -> Self {
+        let source = format!(
+            "const NEIGHBOR_RANGE_Z: i32 = {};\n{}",
+            if DIM == 2 { 0 } else { 1 },
+            include_str!("sph.wgsl")
+        );
+        let module = context
+            .device()
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("salva sph"),
+                source: wgpu::ShaderSource::Wgsl(Cow::Owned(source)),
+            });
+        let pipelines = Pass::ALL
+            .iter()
+            .map(|pass| {
+                context
+                    .device()
+                    .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                        label: Some(pass.entry_point()),
+                        layout: None,
+                        module: &module,
+                        entry_point: Some(pass.entry_point()),
+                        compilation_options: Default::default(),
+                        cache: None,
+                    })
+            })
+            .collect();
+
+        Self {
+            context,
+            kernel_radius,
+            pressure_iterations: 4,
+            pipelines,
+            particles: None,
+        }
+    }
+
+    /// Sets the number of iterations of the pressure solve performed at each step.
+    ///
+    /// This defaults to 4.
+    pub fn with_pressure_iterations(mut self, pressure_iterations: usize) -> Self {
+        self.pressure_iterations = pressure_iterations;
+        self
+    }
+
+    /// The context this solver runs on.
+    pub fn context(&self) -> &GpuContext {
+        &self.context
+    }
+
+    /// The kernel radius of this solver.
+    pub fn kernel_radius(&self) -> Real {
+        self.kernel_radius
+    }
+
+    /// The number of iterations of the pressure solve performed at each step.
+    pub fn pressure_iterations(&self) -> usize {
+        self.pressure_iterations
+    }
+
+    /// The number of fluid particles currently on the GPU.
+    pub fn num_particles(&self) -> usize {
+        self.particles
+            .as_ref()
+            .map(|particles| particles.num_fluid as usize)
+            .unwrap_or(0)
+    }
+
+    /// Uploads the particles of `fluid` and `boundaries` to the GPU.
+    ///
+    /// This replaces any particle previously uploaded. The volumes of the boundary particles are
+    /// recomputed on the GPU, so `Boundary::volumes` is ignored. The GPU solver uses the rest
+    /// density `Fluid::density0` for all the fluid particles, so `Fluid::rest_densities` is ignored.
+    pub fn upload(&mut self, fluid: &Fluid, boundaries: &[Boundary]) {
+        let boundary_positions = boundaries.iter().flat_map(|b| b.positions.iter());
+        let boundary_velocities = boundaries.iter().flat_map(|b| b.velocities.iter());
+        let positions: Vec<[f32; 4]> = fluid
+            .positions
+            .iter()
+            .chain(boundary_positions)
+            .map(|p| to_vec4(&p.coords))
+            .collect();
+        let velocities: Vec<[f32; 4]> = fluid
+            .velocities
+            .iter()
+            .chain(boundary_velocities)
+            .map(to_vec4)
+            .collect();
+        let mut volumes: Vec<f32> = fluid.volumes.clone();
+        volumes.resize(positions.len(), 0.0);
+
+        let num_fluid = fluid.num_particles() as u32;
+        let num_total = positions.len() as u32;
+        let table_size = hash_table_size(num_total);
+        let num_blocks = num_workgroups(table_size);
+
+        let device = self.context.device();
+        let init = |label, contents: &[u8], usage| {
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(label),
+                contents,
+                usage,
+            })
+        };
+        let storage = wgpu::BufferUsages::STORAGE
+            | wgpu::BufferUsages::COPY_SRC
+            | wgpu::BufferUsages::COPY_DST;
+        let zeroed = |label, len: u32, elt_size: u32| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                // Empty bindings are not allowed.
+                size: (len.max(1) * elt_size) as u64,
+                usage: storage,
+                mapped_at_creation: false,
+            })
+        };
+        let padded = |data: &[u8]| {
+            if data.is_empty() {
+                vec![0; 16]
+            } else {
+                data.to_vec()
+            }
+        };
+
+        let buffers = vec![
+            init(
+                "params",
+                bytemuck::cast_slice(&[0u32; 12]),
+                wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            ),
+            init(
+                "positions",
+                &padded(bytemuck::cast_slice(&positions)),
+                storage,
+            ),
+            init(
+                "velocities",
+                &padded(bytemuck::cast_slice(&velocities)),
+                storage,
+            ),
+            zeroed("velocity changes", num_fluid, 16),
+            init("volumes", &padded(bytemuck::cast_slice(&volumes)), storage),
+            zeroed("particle data", num_fluid, 16),
+            zeroed("particle cells", num_total, 4),
+            zeroed("cell counts", table_size, 4),
+            zeroed("cell starts", table_size + 1, 4),
+            zeroed("block sums", num_blocks, 4),
+            zeroed("sorted particles", num_total, 4),
+        ];
+        assert_eq!(buffers.len(), NUM_BINDINGS);
+
+        let bind_groups = Pass::ALL
+            .iter()
+            .zip(&self.pipelines)
+            .map(|(pass, pipeline)| {
+                let entries: Vec<_> = pass
+                    .bindings()
+                    .iter()
+                    .map(|binding| wgpu::BindGroupEntry {
+                        binding: *binding,
+                        resource: buffers[*binding as usize].as_entire_binding(),
+                    })
+                    .collect();
+
+                device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some(pass.entry_point()),
+                    layout: &pipeline.get_bind_group_layout(0),
+                    entries: &entries,
+                })
+            })
+            .collect();
+
+        self.particles = Some(GpuParticles {
+            buffers,
+            bind_groups,
+            num_fluid,
+            num_total,
+            table_size,
+            density0: fluid.density0,
+        });
+    }
+
+    /// Advances the uploaded particles by one timestep of length `dt`.
+    ///
+    /// The `gravity` is applied to every fluid particle, e.g., `fluid.effective_gravity(&gravity)`
+    /// for the gravity of a liquid world. This does nothing if no particle has been uploaded, or
+    /// if `dt` is zero.
+    pub fn step(&mut self, dt: Real, gravity: &Vector<Real>) {
+        let particles = match &self.particles {
+            // The pressure solve divides by `dt`.
+            Some(particles) if particles.num_fluid > 0 && dt != 0.0 => particles,
+            _ => return,
+        };
+
+        #[cfg(feature = "dim2")]
+        let normalizer = 40.0 / 7.0 / (Real::pi() * self.kernel_radius.powi(2));
+        #[cfg(feature = "dim3")]
+        let normalizer = 8.0 / (Real::pi() * self.kernel_radius.powi(3));
+
+        let gravity = to_vec4(gravity);
+        let num_boundary = particles.num_total - particles.num_fluid;
+        let params = [
+            gravity[0].to_bits(),
+            gravity[1].to_bits(),
+            gravity[2].to_bits(),
+            0,
+            particles.num_fluid,
+            particles.num_total,
+            particles.table_size,
+            num_workgroups(particles.table_size),
+            self.kernel_radius.to_bits(),
+            normalizer.to_bits(),
+            dt.to_bits(),
+            particles.density0.to_bits(),
+        ];
+        self.context.queue().write_buffer(
+            &particles.buffers[PARAMS as usize],
+            0,
+            bytemuck::cast_slice(&params),
+        );
+
+        let mut passes = vec![
+            (Pass::ClearCells, particles.table_size),
+            (Pass::CountParticles, particles.num_total),
+            (Pass::ScanBlocks, particles.table_size),
+            (Pass::ScanBlockSums, 1),
+            (Pass::AddBlockOffsets, particles.table_size),
+            (Pass::ScatterParticles, particles.num_total),
+            (Pass::ComputeBoundaryVolumes, num_boundary),
+            (Pass::ComputeDensities, particles.num_fluid),
+        ];
+
+        for _ in 0..self.pressure_iterations {
+            passes.push((Pass::PredictDensities, particles.num_fluid));
+            passes.push((Pass::UpdateVelocityChanges, particles.num_fluid));
+        }
+
+        passes.push((Pass::Integrate, particles.num_fluid));
+
+        let mut encoder =
+            self.context
+                .device()
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("salva step"),
+                });
+
+        for (pass, num_threads) in passes {
+            if num_threads == 0 {
+                continue;
+            }
+
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some(pass.entry_point()),
+                timestamp_writes: None,
+            });
+            compute_pass.set_pipeline(&self.pipelines[pass as usize]);
+            compute_pass.set_bind_group(0, &particles.bind_groups[pass as usize], &[]);
+            compute_pass.dispatch_workgroups(num_workgroups(num_threads), 1, 1);
+        }
+
+        let _ = self.context.queue().submit(Some(encoder.finish()));
+    }
+
+    /// Reads the positions and velocities of the fluid particles back from the GPU into `fluid`.
+    ///
+    /// Fails if `fluid` does not have as many particles as the fluid uploaded to the GPU.
+    pub fn download(&self, fluid: &mut Fluid) -> Result<(), GpuError> {
+        let num_fluid = self.num_particles();
+
+        if fluid.num_particles() != num_fluid {
+            return Err(GpuError::Mismatch(format!(
+                "the fluid has {} particles instead of {}",
+                fluid.num_particles(),
+                num_fluid
+            )));
+        }
+
+        if num_fluid == 0 {
+            return Ok(());
+        }
+
+        let positions = self.read_vec4s(POSITIONS, num_fluid)?;
+        let velocities = self.read_vec4s(VELOCITIES, num_fluid)?;
+
+        for (i, (position, velocity)) in positions.iter().zip(&velocities).enumerate() {
+            fluid.positions[i] = Point::from(Vector::from_fn(|k, _| position[k]));
+            fluid.velocities[i] = Vector::from_fn(|k, _| velocity[k]);
+        }
+
+        Ok(())
+    }
+
+    /// Reads the densities of the fluid particles, computed during the last step, back from the
+    /// GPU.
+    pub fn download_densities(&self) -> Result<Vec<Real>, GpuError> {
+        let num_fluid = self.num_particles();
+
+        if num_fluid == 0 {
+            return Ok(Vec::new());
+        }
+
+        let data = self.read_vec4s(PARTICLE_DATA, num_fluid)?;
+        Ok(data.iter().map(|data| data[0]).collect())
+    }
+
+    fn read_vec4s(&self, binding: u32, len: usize) -> Result<Vec<[f32; 4]>, GpuError> {
+        let particles = self
+            .particles
+            .as_ref()
+            .expect("No particle has been uploaded.");
+        let device = self.context.device();
+        let size = (len * 16) as u64;
+        let staging = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("salva staging"),
+            size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("salva download"),
+        });
+        encoder.copy_buffer_to_buffer(&particles.buffers[binding as usize], 0, &staging, 0, size);
+        let _ = self.context.queue().submit(Some(encoder.finish()));
+
+        let (sender, receiver) = mpsc::channel();
+        staging
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                let _ = sender.send(result);
+            });
+        let _ = device.poll(wgpu::Maintain::Wait);
+        receiver
+            .recv()
+            .map_err(|e| GpuError::BufferMapping(e.to_string()))?
+            .map_err(|e| GpuError::BufferMapping(e.to_string()))?;
+
+        let result = bytemuck::cast_slice(&staging.slice(..).get_mapped_range()).to_vec();
+        staging.unmap();
+        Ok(result)
+    }
+}
+
+// The number of cells of the hash table of the neighbor search.
+//
+// A sparse hash table keeps the number of hash collisions between the cells low. Its size is a
+// multiple of the workgroup size so each block of the prefix sum is full.
+fn hash_table_size(num_particles: u32) -> u32 {
+    (2 * num_particles).next_power_of_two().max(WORKGROUP_SIZE)
+}
+
+fn num_workgroups(num_threads: u32) -> u32 {
+    num_threads.div_ceil(WORKGROUP_SIZE)
+}
+
+fn to_vec4(v: &Vector<Real>) -> [f32; 4] {
+    let mut result = [0.0; 4];
+    result[..DIM].copy_from_slice(v.as_slice());
+    result
+}
+
+#[test]
+fn vec4_conversion() {
+    let v = Vector::from_fn(|i, _| (i + 1) as Real);
+    let mut expected = [0.0; 4];
+
+    for (i, x) in expected.iter_mut().take(DIM).enumerate() {
+        *x = (i + 1) as f32;
+    }
+
+    assert_eq!(to_vec4(&v), expected);
+    assert_eq!(to_vec4(&Vector::zeros()), [0.0; 4]);
+}
+
+#[test]
+fn pass_bindings_are_consistent() {
+    let mut used = [false; NUM_BINDINGS];
+
+    for (i, pass) in Pass::ALL.iter().enumerate() {
+        // The pipelines and bind groups are indexed by pass.
+        assert_eq!(*pass as usize, i);
+        let bindings = pass.bindings();
+        assert!(bindings.windows(2).all(|w| w[0] < w[1]), "{:?}", pass);
+
+        for binding in bindings {
+            used[*binding as usize] = true;
+        }
+    }
+
+    assert!(used.iter().all(|used| *used));
+}
+
+#[test]
+fn hash_table_covers_the_particles() {
+    for num_particles in [0, 1, 127, 128, 129, 1000, 65536, 100_000] {
+        let table_size = hash_table_size(num_particles);
+        assert!(table_size.is_power_of_two());
+        assert!(table_size >= 2 * num_particles);
+        assert_eq!(table_size % WORKGROUP_SIZE, 0);
+        assert_eq!(num_workgroups(table_size) * WORKGROUP_SIZE, table_size);
+    }
+
+    assert_eq!(num_workgroups(0), 0);
+    assert_eq!(num_workgroups(1), 1);
+    assert_eq!(num_workgroups(WORKGROUP_SIZE), 1);
+    assert_eq!(num_workgroups(WORKGROUP_SIZE + 1), 2);
+}
+
+#[test]
+fn gpu_step_smoke_test() {
+    use crate::object::interaction_groups::InteractionGroups;
+
+    let context = match GpuContext::new() {
+        Ok(context) => context,
+        Err(GpuError::NoAdapter) => return,
+        Err(e) => panic!("{}", e),
+    };
+
+    let particle_radius: Real = 0.05;
+    let spacing = particle_radius * 2.0;
+    let lattice = |layers: std::ops::Range<i32>| -> Vec<Point<Real>> {
+        let mut points = Vec::new();
+
+        for i in 0..4 {
+            for j in layers.clone() {
+                for k in 0..if DIM == 3 { 4 } else { 1 } {
+                    let mut point = Point::origin();
+                    point[0] = i as Real * spacing;
+                    point[1] = j as Real * spacing;
+                    point[DIM - 1] += k as Real * spacing;
+                    points.push(point);
+                }
+            }
+        }
+
+        points
+    };
+    let mut fluid = Fluid::new(
+        lattice(1..5),
+        particle_radius,
+        1000.0,
+        InteractionGroups::default(),
+    );
+    let floor = Boundary::new(lattice(-1..1), InteractionGroups::default());
+    let initial_positions = fluid.positions.clone();
+
+    let mut solver = GpuFluidSolver::new(context, particle_radius * 4.0);
+    solver.upload(&fluid, &[floor]);
+    assert_eq!(solver.num_particles(), fluid.num_particles());
+
+    solver.step(0.0, &(Vector::y() * -9.81));
+    solver.download(&mut fluid).unwrap();
+    assert_eq!(fluid.positions, initial_positions);
+
+    for _ in 0..5 {
+        solver.step(0.005, &(Vector::y() * -9.81));
+    }
+
+    solver.download(&mut fluid).unwrap();
+    assert!(fluid
+        .positions
+        .iter()
+        .all(|p| p.iter().all(|x| x.is_finite())));
+    assert!(fluid.velocities.iter().any(|v| v.y < 0.0));
+    assert!(solver
+        .download_densities()
+        .unwrap()
+        .iter()
+        .all(|d| *d > 0.0));
+}
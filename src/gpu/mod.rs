@@ -0,0 +1,7 @@
+//! An optional GPU backend running the fluid simulation with wgpu compute shaders.
+
+pub use self::gpu_context::{GpuContext, GpuError};
+pub use self::gpu_fluid_solver::GpuFluidSolver;
+
+mod gpu_context;
+mod gpu_fluid_solver;
@@ -0,0 +1,360 @@
+// Compute shaders of the GPU fluid solver.
+//
+// The particles are sorted into a hashed uniform grid with cells as large as the kernel radius:
+// the particles of each cell are counted, the counts are turned into the first index of each cell
+// with an exclusive prefix sum, and the particles are scattered into `sorted_particles`. The
+// fluid particles come first in the particle buffers, followed by the boundary particles.
+//
+// `NEIGHBOR_RANGE_Z` is prepended by the host: 0 in 2D, 1 in 3D.
+
+struct Params {
+    gravity: vec4<f32>,
+    num_fluid: u32,
+    num_total: u32,
+    table_size: u32,
+    num_blocks: u32,
+    kernel_radius: f32,
+    normalizer: f32,
+    dt: f32,
+    density0: f32,
+}
+
+const BLOCK_SIZE: u32 = 256u;
+const MAX_NEIGHBOR_CELLS: u32 = 27u;
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<storage, read_write> positions: array<vec4<f32>>;
+@group(0) @binding(2) var<storage, read_write> velocities: array<vec4<f32>>;
+@group(0) @binding(3) var<storage, read_write> velocity_changes: array<vec4<f32>>;
+@group(0) @binding(4) var<storage, read_write> volumes: array<f32>;
+// Density, DFSPH factor, predicted density, and pressure coefficient of each fluid particle.
+@group(0) @binding(5) var<storage, read_write> particle_data: array<vec4<f32>>;
+@group(0) @binding(6) var<storage, read_write> particle_cells: array<u32>;
+@group(0) @binding(7) var<storage, read_write> cell_counts: array<atomic<u32>>;
+// The first index of each cell in `sorted_particles`, followed by the total number of particles.
+@group(0) @binding(8) var<storage, read_write> cell_starts: array<u32>;
+@group(0) @binding(9) var<storage, read_write> block_sums: array<u32>;
+@group(0) @binding(10) var<storage, read_write> sorted_particles: array<u32>;
+
+var<workgroup> scan_buffer: array<u32, BLOCK_SIZE>;
+
+// The cubic spline kernel, as `CubicSplineKernel`.
+fn kernel(r: f32) -> f32 {
+    let q = r / params.kernel_radius;
+    var rhs = 0.0;
+
+    if q <= 0.5 {
+        let q2 = q * q;
+        rhs = 1.0 + (q2 * q - q2) * 6.0;
+    } else if q <= 1.0 {
+        let one_q = 1.0 - q;
+        rhs = one_q * one_q * one_q * 2.0;
+    }
+
+    return params.normalizer * rhs;
+}
+
+fn kernel_gradient(dpos: vec3<f32>) -> vec3<f32> {
+    let r = length(dpos);
+    let q = r / params.kernel_radius;
+
+    if q > 1.0 || q <= 1.0e-5 {
+        return vec3<f32>(0.0);
+    }
+
+    var rhs: f32;
+
+    if q <= 0.5 {
+        rhs = (q * 3.0 - 2.0) * q * 6.0;
+    } else {
+        let one_q = 1.0 - q;
+        rhs = -one_q * one_q * 6.0;
+    }
+
+    return dpos / r * (params.normalizer * rhs / params.kernel_radius);
+}
+
+fn cell_hash(cell: vec3<i32>) -> u32 {
+    let c = bitcast<vec3<u32>>(cell);
+    return ((c.x * 73856093u) ^ (c.y * 19349663u) ^ (c.z * 83492791u)) & (params.table_size - 1u);
+}
+
+fn cell_of(position: vec3<f32>) -> vec3<i32> {
+    return vec3<i32>(floor(position / params.kernel_radius));
+}
+
+// Collects the distinct hashes of the cells around `position`. Distinct cells may share the same
+// hash, in which case their particles must only be visited once.
+fn neighbor_cells(position: vec3<f32>, cells: ptr<function, array<u32, MAX_NEIGHBOR_CELLS>>) -> u32 {
+    let center = cell_of(position);
+    var num_cells = 0u;
+
+    for (var dx = -1; dx <= 1; dx++) {
+        for (var dy = -1; dy <= 1; dy++) {
+            for (var dz = -NEIGHBOR_RANGE_Z; dz <= NEIGHBOR_RANGE_Z; dz++) {
+                let hash = cell_hash(center + vec3<i32>(dx, dy, dz));
+                var is_new = true;
+
+                for (var k = 0u; k < num_cells; k++) {
+                    if (*cells)[k] == hash {
+                        is_new = false;
+                    }
+                }
+
+                if is_new {
+                    (*cells)[num_cells] = hash;
+                    num_cells++;
+                }
+            }
+        }
+    }
+
+    return num_cells;
+}
+
+fn particle_mass(j: u32) -> f32 {
+    return volumes[j] * params.density0;
+}
+
+@compute @workgroup_size(256)
+fn clear_cells(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x < params.table_size {
+        atomicStore(&cell_counts[id.x], 0u);
+    }
+}
+
+@compute @workgroup_size(256)
+fn count_particles(@builtin(global_invocation_id) id: vec3<u32>) {
+    let i = id.x;
+
+    if i < params.num_total {
+        let hash = cell_hash(cell_of(positions[i].xyz));
+        particle_cells[i] = hash;
+        atomicAdd(&cell_counts[hash], 1u);
+    }
+}
+
+// Exclusive prefix sum of the cell counts within each block of `BLOCK_SIZE` cells.
+@compute @workgroup_size(256)
+fn scan_blocks(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(local_invocation_id) local_id: vec3<u32>,
+    @builtin(workgroup_id) block_id: vec3<u32>,
+) {
+    let l = local_id.x;
+    let count = atomicLoad(&cell_counts[id.x]);
+    scan_buffer[l] = count;
+    workgroupBarrier();
+
+    for (var offset = 1u; offset < BLOCK_SIZE; offset *= 2u) {
+        var sum = scan_buffer[l];
+
+        if l >= offset {
+            sum += scan_buffer[l - offset];
+        }
+
+        workgroupBarrier();
+        scan_buffer[l] = sum;
+        workgroupBarrier();
+    }
+
+    cell_starts[id.x] = scan_buffer[l] - count;
+
+    if l == BLOCK_SIZE - 1u {
+        block_sums[block_id.x] = scan_buffer[l];
+    }
+}
+
+// Exclusive prefix sum of the block sums.
+@compute @workgroup_size(1)
+fn scan_block_sums() {
+    var total = 0u;
+
+    for (var b = 0u; b < params.num_blocks; b++) {
+        let sum = block_sums[b];
+        block_sums[b] = total;
+        total += sum;
+    }
+
+    cell_starts[params.table_size] = total;
+}
+
+// Completes the prefix sum, and resets the cell counters to the first index of each cell.
+@compute @workgroup_size(256)
+fn add_block_offsets(@builtin(global_invocation_id) id: vec3<u32>) {
+    let start = cell_starts[id.x] + block_sums[id.x / BLOCK_SIZE];
+    cell_starts[id.x] = start;
+    atomicStore(&cell_counts[id.x], start);
+}
+
+@compute @workgroup_size(256)
+fn scatter_particles(@builtin(global_invocation_id) id: vec3<u32>) {
+    let i = id.x;
+
+    if i < params.num_total {
+        let slot = atomicAdd(&cell_counts[particle_cells[i]], 1u);
+        sorted_particles[slot] = i;
+    }
+}
+
+// The volume of each boundary particle is the inverse of the kernel-weighted number of boundary
+// particles around it.
+@compute @workgroup_size(256)
+fn compute_boundary_volumes(@builtin(global_invocation_id) id: vec3<u32>) {
+    let i = params.num_fluid + id.x;
+
+    if i >= params.num_total {
+        return;
+    }
+
+    let pi = positions[i].xyz;
+    var cells: array<u32, MAX_NEIGHBOR_CELLS>;
+    let num_cells = neighbor_cells(pi, &cells);
+    var denominator = 0.0;
+
+    for (var k = 0u; k < num_cells; k++) {
+        let hash = cells[k];
+
+        for (var s = cell_starts[hash]; s < cell_starts[hash + 1u]; s++) {
+            let j = sorted_particles[s];
+
+            if j >= params.num_fluid {
+                denominator += kernel(length(pi - positions[j].xyz));
+            }
+        }
+    }
+
+    if denominator > 0.0 {
+        volumes[i] = 1.0 / denominator;
+    } else {
+        volumes[i] = 0.0;
+    }
+}
+
+// Computes the densities and the DFSPH factors, and applies the gravity.
+@compute @workgroup_size(256)
+fn compute_densities(@builtin(global_invocation_id) id: vec3<u32>) {
+    let i = id.x;
+
+    if i >= params.num_fluid {
+        return;
+    }
+
+    let pi = positions[i].xyz;
+    var cells: array<u32, MAX_NEIGHBOR_CELLS>;
+    let num_cells = neighbor_cells(pi, &cells);
+    var density = 0.0;
+    var grad_sum = vec3<f32>(0.0);
+    var squared_grad_sum = 0.0;
+
+    for (var k = 0u; k < num_cells; k++) {
+        let hash = cells[k];
+
+        for (var s = cell_starts[hash]; s < cell_starts[hash + 1u]; s++) {
+            let j = sorted_particles[s];
+            let dpos = pi - positions[j].xyz;
+            let mj = particle_mass(j);
+            let grad = kernel_gradient(dpos) * mj;
+
+            density += mj * kernel(length(dpos));
+            squared_grad_sum += dot(grad, grad);
+            grad_sum += grad;
+        }
+    }
+
+    // A particle without any neighbor is considered at rest.
+    if density == 0.0 {
+        density = params.density0;
+    }
+
+    let denominator = squared_grad_sum + dot(grad_sum, grad_sum);
+    var factor = 0.0;
+
+    if denominator > 1.0e-5 {
+        factor = 1.0 / denominator;
+    }
+
+    particle_data[i] = vec4<f32>(density, factor, density, 0.0);
+    velocities[i] += params.gravity * params.dt;
+    velocity_changes[i] = vec4<f32>(0.0);
+}
+
+@compute @workgroup_size(256)
+fn predict_densities(@builtin(global_invocation_id) id: vec3<u32>) {
+    let i = id.x;
+
+    if i >= params.num_fluid {
+        return;
+    }
+
+    let pi = positions[i].xyz;
+    let vi = velocities[i].xyz + velocity_changes[i].xyz;
+    var cells: array<u32, MAX_NEIGHBOR_CELLS>;
+    let num_cells = neighbor_cells(pi, &cells);
+    var delta = 0.0;
+
+    for (var k = 0u; k < num_cells; k++) {
+        let hash = cells[k];
+
+        for (var s = cell_starts[hash]; s < cell_starts[hash + 1u]; s++) {
+            let j = sorted_particles[s];
+            var vj = velocities[j].xyz;
+
+            if j < params.num_fluid {
+                vj += velocity_changes[j].xyz;
+            }
+
+            delta += particle_mass(j) * dot(vi - vj, kernel_gradient(pi - positions[j].xyz));
+        }
+    }
+
+    let data = particle_data[i];
+    let predicted_density = data.x + delta * params.dt;
+    let coefficient = max(predicted_density - params.density0, 0.0) * data.y;
+    particle_data[i] = vec4<f32>(data.x, data.y, predicted_density, coefficient);
+}
+
+@compute @workgroup_size(256)
+fn update_velocity_changes(@builtin(global_invocation_id) id: vec3<u32>) {
+    let i = id.x;
+
+    if i >= params.num_fluid {
+        return;
+    }
+
+    let pi = positions[i].xyz;
+    let ki = particle_data[i].w;
+    var cells: array<u32, MAX_NEIGHBOR_CELLS>;
+    let num_cells = neighbor_cells(pi, &cells);
+    var delta = vec3<f32>(0.0);
+
+    for (var k = 0u; k < num_cells; k++) {
+        let hash = cells[k];
+
+        for (var s = cell_starts[hash]; s < cell_starts[hash + 1u]; s++) {
+            let j = sorted_particles[s];
+            var coefficient = ki;
+
+            if j < params.num_fluid {
+                coefficient += particle_data[j].w;
+            }
+
+            delta += kernel_gradient(pi - positions[j].xyz) * (coefficient * particle_mass(j));
+        }
+    }
+
+    velocity_changes[i] -= vec4<f32>(delta / params.dt, 0.0);
+}
+
+@compute @workgroup_size(256)
+fn integrate(@builtin(global_invocation_id) id: vec3<u32>) {
+    let i = id.x;
+
+    if i >= params.num_fluid {
+        return;
+    }
+
+    let velocity = velocities[i] + velocity_changes[i];
+    velocities[i] = velocity;
+    positions[i] += velocity * params.dt;
+}
@@ -1,6 +1,8 @@
 use crate::coupling::CouplingManager;
-use crate::geometry::{HGrid, HGridEntry};
-use crate::object::{BoundaryHandle, BoundarySet, Fluid};
+use crate::geometry::{HGridEntry, NeighborSearch};
+use crate::math::Real;
+use crate::object::interaction_groups::InteractionGroups;
+use crate::object::{Boundary, BoundaryHandle, BoundarySet, Fluid};
 use crate::solver::DFSPHSolver;
 use crate::LiquidWorld;
 use crate::TimestepManager;
@@ -12,7 +14,6 @@ use rapier::math::{Point, Vector};
 use rapier::parry::bounding_volume::BoundingVolume;
 use rapier::parry::shape::FeatureId;
 use std::collections::HashMap;
-use std::sync::RwLock;
 
 /// Pipeline for particle-based fluid simulation.
 pub struct FluidsPipeline {
@@ -40,6 +41,38 @@ impl FluidsPipeline {
         }
     }
 
+    /// Creates a boundary coupled with the given collider.
+    ///
+    /// The boundary particles are sampled from the collider with `sampling_method` and follow its
+    /// motion at each substep. If the collider is attached to a dynamic rigid-body, the forces
+    /// applied by the fluids to the boundary are applied to that rigid-body. If the collider was
+    /// already coupled, its previous boundary is removed from the liquid world.
+    pub fn couple_collider(
+        &mut self,
+        collider: ColliderHandle,
+        sampling_method: ColliderSampling,
+        interaction_groups: InteractionGroups,
+    ) -> BoundaryHandle {
+        let boundary = self
+            .liquid_world
+            .add_boundary(Boundary::new(Vec::new(), interaction_groups));
+
+        if let Some(old) = self
+            .coupling
+            .register_coupling(boundary, collider, sampling_method)
+        {
+            let _ = self.liquid_world.remove_boundary(old);
+        }
+
+        boundary
+    }
+
+    /// Removes the coupling of the given collider, and the boundary it was coupled with.
+    pub fn decouple_collider(&mut self, collider: ColliderHandle) -> Option<Boundary> {
+        let boundary = self.coupling.unregister_coupling(collider)?;
+        self.liquid_world.remove_boundary(boundary)
+    }
+
     /// Advances the fluid simulation by `dt` seconds.
     ///
     /// All the fluid particles will be affected by an acceleration equal to `gravity`.
@@ -74,6 +107,15 @@ pub enum ColliderSampling {
 pub struct ColliderCouplingEntry {
     pub sampling_method: ColliderSampling,
     pub boundary: BoundaryHandle,
+    /// The distance from the collider surface below which fluid particles generate boundary samples
+    /// with `ColliderSampling::DynamicContactSampling`.
+    ///
+    /// If `None`, the SPH kernel radius is used. Setting a smaller value lets thin splashes
+    /// slide through gaps narrower than the kernel radius. Values larger than the kernel radius
+    /// are clamped to it, since the fluid particles further than the kernel radius don't interact
+    /// with the boundary samples anyway. This is ignored with `ColliderSampling::StaticSampling`,
+    /// whose samples are fixed.
+    pub contact_sampling_radius: Option<Real>,
     features: Vec<FeatureId>,
 }
 
@@ -106,6 +148,7 @@ impl ColliderCouplingSet {
             ColliderCouplingEntry {
                 sampling_method,
                 boundary,
+                contact_sampling_radius: None,
                 features: Vec::new(),
             },
         );
@@ -113,6 +156,26 @@ impl ColliderCouplingSet {
         old.map(|e| e.boundary)
     }
 
+    /// Sets the radius used for the dynamic contact sampling of this collider.
+    ///
+    /// With `ColliderSampling::DynamicContactSampling`, fluid particles further than `radius`
+    /// from the collider surface won't generate boundary samples. If `None`, or larger than the
+    /// SPH kernel radius, the kernel radius is used. This has no effect on colliders coupled with
+    /// `ColliderSampling::StaticSampling`. Returns `false` if there is no coupling registered for
+    /// this collider.
+    pub fn set_contact_sampling_radius(
+        &mut self,
+        collider: ColliderHandle,
+        radius: Option<Real>,
+    ) -> bool {
+        if let Some(entry) = self.entries.get_mut(&collider) {
+            entry.contact_sampling_radius = radius;
+            true
+        } else {
+            false
+        }
+    }
+
     /// Unregister a coupling between a boundary and a collider.
     /// Note that this does not remove the boundary itself from the liquid world.
     /// Returns the handle of the boundary this collider was coupled with.
@@ -149,7 +212,7 @@ impl<'a> CouplingManager for ColliderCouplingManager<'a> {
         timestep: &TimestepManager,
         h: f32,
         particle_radius: f32,
-        hgrid: &HGrid<HGridEntry>,
+        neighbor_search: &dyn NeighborSearch,
         fluids: &mut [Fluid],
         boundaries: &mut BoundarySet,
     ) {
@@ -162,12 +225,7 @@ impl<'a> CouplingManager for ColliderCouplingManager<'a> {
                 // Update the boundary's ability to receive forces.
                 let body = collider.parent().and_then(|p| bodies.get(p));
                 if let Some(body) = body {
-                    if !body.is_dynamic() {
-                        boundary.forces = None;
-                    } else {
-                        boundary.forces = Some(RwLock::new(Vec::new()));
-                        boundary.clear_forces(true);
-                    }
+                    boundary.enable_forces(body.is_dynamic());
                 }
 
                 // Update positions and velocities.
@@ -179,8 +237,9 @@ impl<'a> CouplingManager for ColliderCouplingManager<'a> {
                 match &coupling.sampling_method {
                     ColliderSampling::StaticSampling(points) => {
                         for pt in points {
-                            boundary.positions.push(collider.position() * pt);
-                            let velocity = body.map(|b| b.velocity_at_point(pt));
+                            let world_pt = collider.position() * pt;
+                            boundary.positions.push(world_pt);
+                            let velocity = body.map(|b| b.velocity_at_point(&world_pt));
 
                             boundary
                                 .velocities
@@ -192,17 +251,15 @@ impl<'a> CouplingManager for ColliderCouplingManager<'a> {
                     ColliderSampling::DynamicContactSampling => {
                         let prediction = h * na::convert::<_, f32>(0.5);
                         let margin = particle_radius * na::convert::<_, f32>(0.1);
+                        let sampling_radius = coupling.contact_sampling_radius.unwrap_or(h).min(h);
                         let collider_pos = collider.position();
                         let aabb = collider
                             .shape()
                             .compute_aabb(&collider_pos)
-                            .loosened(h + prediction);
+                            .loosened(sampling_radius + prediction);
 
-                        for particle in hgrid
-                            .cells_intersecting_aabb(&aabb.mins, &aabb.maxs)
-                            .flat_map(|e| e.1)
-                        {
-                            match particle {
+                        for particle in neighbor_search.particles_in_aabb(&aabb.mins, &aabb.maxs) {
+                            match &particle {
                                 HGridEntry::FluidParticle(fluid_id, particle_id) => {
                                     let fluid = &mut fluids[*fluid_id];
                                     let particle_pos = fluid.positions[*particle_id]
@@ -231,7 +288,7 @@ impl<'a> CouplingManager for ColliderCouplingManager<'a> {
                                                     fluid.velocities[*particle_id] -=
                                                         *normal * vel_err;
                                                 }
-                                            } else if depth > h + prediction {
+                                            } else if depth > sampling_radius + prediction {
                                                 continue;
                                             }
                                         }
@@ -252,10 +309,12 @@ impl<'a> CouplingManager for ColliderCouplingManager<'a> {
                                 }
                             }
                         }
+
+                        // The samples change at each substep, so the forces accumulated on the
+                        // previous ones can't be attributed to them.
+                        boundary.clear_forces(true);
                     }
                 }
-
-                boundary.clear_forces(true);
             }
         }
     }
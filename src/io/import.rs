@@ -0,0 +1,574 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Error, ErrorKind, Read, Result, Write};
+use std::path::Path;
+
+use crate::math::{Point, Real, Vector, DIM};
+use crate::object::interaction_groups::InteractionGroups;
+use crate::object::{Boundary, Fluid};
+
+const BINARY_MAGIC: &[u8; 4] = b"SLVP";
+const BINARY_VERSION: u32 = 1;
+const BINARY_HAS_VELOCITIES: u32 = 1;
+const BINARY_HAS_VOLUMES: u32 = 2;
+
+/// Particle positions, with optional velocities and volumes, read from a file.
+///
+/// The fields recognized when reading files are the coordinates `x`, `y` (and `z` in 3D), the
+/// velocities `vx`, `vy` (and `vz` in 3D), and the `volume` of the particles. The other fields
+/// are ignored.
+#[derive(Clone, Debug, Default)]
+pub struct ParticleData {
+    /// The positions of the particles.
+    pub positions: Vec<Point<Real>>,
+    /// The velocities of the particles, if they were read.
+    pub velocities: Option<Vec<Vector<Real>>>,
+    /// The volumes of the particles, if they were read.
+    pub volumes: Option<Vec<Real>>,
+}
+
+impl ParticleData {
+    /// Creates a fluid with these particles.
+    ///
+    /// The particles without velocities are at rest, and the particles without volumes are given
+    /// the default volume of `Fluid::new`.
+    pub fn into_fluid(
+        self,
+        particle_radius: Real,
+        density0: Real,
+        interaction_groups: InteractionGroups,
+    ) -> Fluid {
+        let mut fluid = Fluid::new(
+            self.positions,
+            particle_radius,
+            density0,
+            interaction_groups,
+        );
+
+        if let Some(velocities) = self.velocities {
+            fluid.velocities = velocities;
+        }
+
+        if let Some(volumes) = self.volumes {
+            fluid.volumes = volumes;
+        }
+
+        fluid
+    }
+
+    /// Creates a boundary with these particles.
+    ///
+    /// The volumes of the boundary particles are ignored since they are recomputed by the liquid
+    /// world at each substep.
+    pub fn into_boundary(self, interaction_groups: InteractionGroups) -> Boundary {
+        let mut boundary = Boundary::new(self.positions, interaction_groups);
+
+        if let Some(velocities) = self.velocities {
+            boundary.velocities = velocities;
+        }
+
+        boundary
+    }
+}
+
+fn invalid_data(desc: impl Into<String>) -> Error {
+    Error::new(ErrorKind::InvalidData, desc.into())
+}
+
+fn unexpected_eof(desc: impl Into<String>) -> Error {
+    Error::new(ErrorKind::UnexpectedEof, desc.into())
+}
+
+// The column of each recognized field of a file.
+struct Columns {
+    coords: [Option<usize>; 3],
+    velocities: [Option<usize>; 3],
+    volume: Option<usize>,
+}
+
+impl Columns {
+    fn new(names: &[&str]) -> Result<Self> {
+        let find = |name: &str| names.iter().position(|n| *n == name);
+        let columns = Self {
+            coords: [find("x"), find("y"), find("z")],
+            velocities: [find("vx"), find("vy"), find("vz")],
+            volume: find("volume"),
+        };
+
+        if columns.coords[..DIM].iter().any(|c| c.is_none()) {
+            return Err(invalid_data("missing particle coordinates"));
+        }
+
+        Ok(columns)
+    }
+
+    fn has_velocities(&self) -> bool {
+        self.velocities[..DIM].iter().any(|c| c.is_some())
+    }
+
+    fn push(&self, row: &[f64], data: &mut ParticleData) {
+        let value = |column: Option<usize>| {
+            column.map_or(na::zero::<Real>(), |i| na::convert::<_, Real>(row[i]))
+        };
+
+        let mut position = Point::origin();
+        let mut velocity = Vector::zeros();
+
+        for k in 0..DIM {
+            position[k] = value(self.coords[k]);
+            velocity[k] = value(self.velocities[k]);
+        }
+
+        data.positions.push(position);
+
+        if let Some(velocities) = &mut data.velocities {
+            velocities.push(velocity);
+        }
+
+        if let Some(volumes) = &mut data.volumes {
+            volumes.push(value(self.volume));
+        }
+    }
+
+    fn empty_data(&self) -> ParticleData {
+        ParticleData {
+            positions: Vec::new(),
+            velocities: self.has_velocities().then(Vec::new),
+            volumes: self.volume.map(|_| Vec::new()),
+        }
+    }
+}
+
+/// Reads particles from a CSV file.
+///
+/// The first line must contain the names of the columns, e.g., `x,y,z,vx,vy,vz,volume`. Empty
+/// lines and lines starting with `#` are ignored.
+pub fn read_csv(input: impl BufRead) -> Result<ParticleData> {
+    let mut lines = input.lines().filter(|line| {
+        line.as_ref().map_or(true, |l| {
+            !l.trim().is_empty() && !l.trim_start().starts_with('#')
+        })
+    });
+    let header = lines
+        .next()
+        .ok_or_else(|| invalid_data("missing CSV header"))??;
+    let names: Vec<_> = header.split(',').map(|name| name.trim()).collect();
+    let columns = Columns::new(&names)?;
+    let mut data = columns.empty_data();
+    let mut row = Vec::with_capacity(names.len());
+
+    for line in lines {
+        let line = line?;
+        row.clear();
+
+        for value in line.split(',') {
+            row.push(
+                value
+                    .trim()
+                    .parse::<f64>()
+                    .map_err(|_| invalid_data(format!("invalid CSV value: {}", value)))?,
+            );
+        }
+
+        if row.len() != names.len() {
+            return Err(invalid_data(format!("invalid CSV line: {}", line)));
+        }
+
+        columns.push(&row, &mut data);
+    }
+
+    Ok(data)
+}
+
+/// Reads particles from the CSV file at `path`.
+///
+/// See `read_csv` for details about the expected content of the file.
+pub fn load_csv(path: impl AsRef<Path>) -> Result<ParticleData> {
+    read_csv(BufReader::new(File::open(path)?))
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum PlyEncoding {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Copy, Clone)]
+enum PlyScalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl PlyScalar {
+    fn parse(name: &str) -> Result<Self> {
+        match name {
+            "char" | "int8" => Ok(PlyScalar::I8),
+            "uchar" | "uint8" => Ok(PlyScalar::U8),
+            "short" | "int16" => Ok(PlyScalar::I16),
+            "ushort" | "uint16" => Ok(PlyScalar::U16),
+            "int" | "int32" => Ok(PlyScalar::I32),
+            "uint" | "uint32" => Ok(PlyScalar::U32),
+            "float" | "float32" => Ok(PlyScalar::F32),
+            "double" | "float64" => Ok(PlyScalar::F64),
+            _ => Err(invalid_data(format!("unknown PLY type: {}", name))),
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            PlyScalar::I8 | PlyScalar::U8 => 1,
+            PlyScalar::I16 | PlyScalar::U16 => 2,
+            PlyScalar::I32 | PlyScalar::U32 | PlyScalar::F32 => 4,
+            PlyScalar::F64 => 8,
+        }
+    }
+
+    fn read(self, bytes: &[u8], encoding: PlyEncoding) -> f64 {
+        macro_rules! decode {
+            ($t: ty) => {{
+                let bytes = bytes.try_into().unwrap();
+                if encoding == PlyEncoding::BinaryBigEndian {
+                    <$t>::from_be_bytes(bytes) as f64
+                } else {
+                    <$t>::from_le_bytes(bytes) as f64
+                }
+            }};
+        }
+
+        match self {
+            PlyScalar::I8 => decode!(i8),
+            PlyScalar::U8 => decode!(u8),
+            PlyScalar::I16 => decode!(i16),
+            PlyScalar::U16 => decode!(u16),
+            PlyScalar::I32 => decode!(i32),
+            PlyScalar::U32 => decode!(u32),
+            PlyScalar::F32 => decode!(f32),
+            PlyScalar::F64 => decode!(f64),
+        }
+    }
+}
+
+struct PlyElement {
+    name: String,
+    count: usize,
+    properties: Vec<(String, PlyScalar)>,
+    has_lists: bool,
+}
+
+/// Reads the particles from the `vertex` element of a PLY file.
+///
+/// The ASCII and binary encodings are supported. The elements located before the `vertex`
+/// element in the file must not have list properties if the file is binary.
+pub fn read_ply(mut input: impl BufRead) -> Result<ParticleData> {
+    let mut line = String::new();
+    let mut encoding = None;
+    let mut elements: Vec<PlyElement> = Vec::new();
+
+    let _ = input.read_line(&mut line)?;
+    if line.trim() != "ply" {
+        return Err(invalid_data("not a PLY file"));
+    }
+
+    loop {
+        line.clear();
+        if input.read_line(&mut line)? == 0 {
+            return Err(invalid_data("truncated PLY header"));
+        }
+
+        let words: Vec<_> = line.split_whitespace().collect();
+
+        match words.as_slice() {
+            ["end_header"] => break,
+            ["format", format, _] => {
+                encoding = Some(match *format {
+                    "ascii" => PlyEncoding::Ascii,
+                    "binary_little_endian" => PlyEncoding::BinaryLittleEndian,
+                    "binary_big_endian" => PlyEncoding::BinaryBigEndian,
+                    _ => return Err(invalid_data(format!("unknown PLY format: {}", format))),
+                })
+            }
+            ["element", name, count] => elements.push(PlyElement {
+                name: name.to_string(),
+                count: count
+                    .parse()
+                    .map_err(|_| invalid_data("invalid PLY element count"))?,
+                properties: Vec::new(),
+                has_lists: false,
+            }),
+            ["property", "list", ..] => {
+                elements
+                    .last_mut()
+                    .ok_or_else(|| invalid_data("PLY property without element"))?
+                    .has_lists = true
+            }
+            ["property", ty, name] => elements
+                .last_mut()
+                .ok_or_else(|| invalid_data("PLY property without element"))?
+                .properties
+                .push((name.to_string(), PlyScalar::parse(ty)?)),
+            _ => {}
+        }
+    }
+
+    let encoding = encoding.ok_or_else(|| invalid_data("missing PLY format"))?;
+
+    for element in elements {
+        if element.name != "vertex" {
+            // Skip the elements preceding the vertices.
+            if encoding == PlyEncoding::Ascii {
+                for _ in 0..element.count {
+                    line.clear();
+
+                    if input.read_line(&mut line)? == 0 {
+                        return Err(unexpected_eof("truncated PLY element"));
+                    }
+                }
+            } else if element.has_lists {
+                return Err(invalid_data(format!(
+                    "unsupported list properties before the vertices: {}",
+                    element.name
+                )));
+            } else {
+                let size: usize = element.properties.iter().map(|p| p.1.size()).sum();
+                let num_bytes = size
+                    .checked_mul(element.count)
+                    .ok_or_else(|| invalid_data("PLY element too large"))?;
+                let skipped = std::io::copy(
+                    &mut input.by_ref().take(num_bytes as u64),
+                    &mut std::io::sink(),
+                )?;
+
+                if skipped != num_bytes as u64 {
+                    return Err(unexpected_eof("truncated PLY element"));
+                }
+            }
+
+            continue;
+        }
+
+        if element.has_lists {
+            return Err(invalid_data("unsupported list properties in the vertices"));
+        }
+
+        let names: Vec<_> = element.properties.iter().map(|p| p.0.as_str()).collect();
+        let columns = Columns::new(&names)?;
+        let mut data = columns.empty_data();
+        let mut row = vec![0.0; names.len()];
+        let mut bytes = [0; 8];
+
+        for _ in 0..element.count {
+            if encoding == PlyEncoding::Ascii {
+                line.clear();
+                let _ = input.read_line(&mut line)?;
+                let mut values = line.split_whitespace();
+
+                for value in &mut row {
+                    *value = values
+                        .next()
+                        .and_then(|v| v.parse().ok())
+                        .ok_or_else(|| invalid_data("invalid PLY vertex"))?;
+                }
+            } else {
+                for (value, (_, scalar)) in row.iter_mut().zip(element.properties.iter()) {
+                    let bytes = &mut bytes[..scalar.size()];
+                    input.read_exact(bytes)?;
+                    *value = scalar.read(bytes, encoding);
+                }
+            }
+
+            columns.push(&row, &mut data);
+        }
+
+        return Ok(data);
+    }
+
+    Err(invalid_data("missing PLY vertex element"))
+}
+
+/// Reads particles from the PLY file at `path`.
+///
+/// See `read_ply` for details about the expected content of the file.
+pub fn load_ply(path: impl AsRef<Path>) -> Result<ParticleData> {
+    read_ply(BufReader::new(File::open(path)?))
+}
+
+/// Reads particles with the simple binary layout written by `write_particles`.
+pub fn read_particles(mut input: impl Read) -> Result<ParticleData> {
+    let mut header = [0; 20];
+    input.read_exact(&mut header)?;
+
+    if &header[..4] != BINARY_MAGIC {
+        return Err(invalid_data("not a particle file"));
+    }
+
+    let word = |i: usize| u32::from_le_bytes(header[i..i + 4].try_into().unwrap());
+
+    if word(4) != BINARY_VERSION {
+        return Err(invalid_data(format!(
+            "unsupported particle file version: {}",
+            word(4)
+        )));
+    }
+
+    if word(8) as usize != DIM {
+        return Err(invalid_data(format!(
+            "the particle file has dimension {}",
+            word(8)
+        )));
+    }
+
+    let flags = word(12);
+    let num_particles = word(16) as usize;
+    let num_coords = num_particles
+        .checked_mul(DIM)
+        .ok_or_else(|| invalid_data("too many particles"))?;
+    let mut read_reals = |len: usize| -> Result<Vec<Real>> {
+        let num_bytes = len
+            .checked_mul(size_of::<Real>())
+            .ok_or_else(|| invalid_data("too many particles"))?;
+        // The buffer only grows with the data actually read, so a corrupted particle count can't
+        // trigger a huge allocation.
+        let mut bytes = Vec::new();
+        let _ = input
+            .by_ref()
+            .take(num_bytes as u64)
+            .read_to_end(&mut bytes)?;
+
+        if bytes.len() != num_bytes {
+            return Err(unexpected_eof("truncated particle file"));
+        }
+
+        Ok(bytes
+            .chunks_exact(size_of::<Real>())
+            .map(|b| Real::from_le_bytes(b.try_into().unwrap()))
+            .collect())
+    };
+
+    let positions = read_reals(num_coords)?
+        .chunks_exact(DIM)
+        .map(Point::from_slice)
+        .collect();
+    let velocities = if flags & BINARY_HAS_VELOCITIES != 0 {
+        Some(
+            read_reals(num_coords)?
+                .chunks_exact(DIM)
+                .map(Vector::from_column_slice)
+                .collect(),
+        )
+    } else {
+        None
+    };
+    let volumes = if flags & BINARY_HAS_VOLUMES != 0 {
+        Some(read_reals(num_particles)?)
+    } else {
+        None
+    };
+
+    Ok(ParticleData {
+        positions,
+        velocities,
+        volumes,
+    })
+}
+
+/// Writes particles with a simple binary layout, readable with `read_particles`.
+///
+/// All the values are little-endian. The layout is the 4 bytes `SLVP`, followed by four `u32`:
+/// the version of the layout (currently `1`), the dimension, the flags (`1` if the velocities are
+/// present, plus `2` if the volumes are present), and the number of particles `n`. They are
+/// followed by the `n * dimension` coordinates of the particles as `f32`, then by their
+/// `n * dimension` velocities, and their `n` volumes, if present.
+pub fn write_particles(data: &ParticleData, out: impl Write) -> Result<()> {
+    let mut out = std::io::BufWriter::new(out);
+    let mut flags = 0;
+
+    if data.velocities.is_some() {
+        flags |= BINARY_HAS_VELOCITIES;
+    }
+
+    if data.volumes.is_some() {
+        flags |= BINARY_HAS_VOLUMES;
+    }
+
+    out.write_all(BINARY_MAGIC)?;
+    for word in [
+        BINARY_VERSION,
+        DIM as u32,
+        flags,
+        data.positions.len() as u32,
+    ] {
+        out.write_all(&word.to_le_bytes())?;
+    }
+
+    for p in &data.positions {
+        for coord in p.coords.iter() {
+            out.write_all(&coord.to_le_bytes())?;
+        }
+    }
+
+    for v in data.velocities.iter().flatten() {
+        for coord in v.iter() {
+            out.write_all(&coord.to_le_bytes())?;
+        }
+    }
+
+    for volume in data.volumes.iter().flatten() {
+        out.write_all(&volume.to_le_bytes())?;
+    }
+
+    out.flush()
+}
+
+#[test]
+fn csv_and_binary_roundtrip() {
+    let csv = "# packed bed\nx, y, z, vx, vy, vz, volume\n\n1, 2, 3, 4, 5, 6, 7\n-1, -2, -3, 0, 0, 0, 0.5\n";
+    let data = read_csv(csv.as_bytes()).unwrap();
+    assert_eq!(data.positions.len(), 2);
+    assert_eq!(
+        data.positions[1][DIM - 1],
+        na::convert::<_, Real>(-(DIM as f64))
+    );
+    assert_eq!(
+        data.volumes.as_ref().unwrap()[1],
+        na::convert::<_, Real>(0.5)
+    );
+
+    let mut bytes = Vec::new();
+    write_particles(&data, &mut bytes).unwrap();
+    let read = read_particles(&bytes[..]).unwrap();
+    assert_eq!(read.positions, data.positions);
+    assert_eq!(read.velocities, data.velocities);
+    assert_eq!(read.volumes, data.volumes);
+
+    assert!(read_csv("y, z\n1, 2\n".as_bytes()).is_err());
+}
+
+#[test]
+fn truncated_inputs_are_rejected() {
+    let mut header = BINARY_MAGIC.to_vec();
+    for word in [BINARY_VERSION, DIM as u32, 0, u32::MAX] {
+        header.extend_from_slice(&word.to_le_bytes());
+    }
+    header.extend_from_slice(&[0; 12]);
+    let error = read_particles(&header[..]).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+
+    let ply = format!(
+        "ply\nformat binary_little_endian 1.0\nelement face {}\nproperty double area\n\
+         element vertex 1\nproperty float x\nend_header\n",
+        usize::MAX / 4
+    );
+    let error = read_ply(ply.as_bytes()).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidData);
+
+    let ply = "ply\nformat ascii 1.0\nelement face 2\nproperty float area\n\
+               element vertex 1\nproperty float x\nend_header\n1\n";
+    let error = read_ply(ply.as_bytes()).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+}
@@ -0,0 +1,11 @@
+//! Import and export of particle data from and to the file formats of external tools.
+
+pub use self::import::{
+    load_csv, load_ply, read_csv, read_particles, read_ply, write_particles, ParticleData,
+};
+pub use self::ply::{save_ply, write_ply, PlyExportOptions, PlyFormat, PlySeries};
+pub use self::vtk::{save_vtp, write_vtp, VtkSeries};
+
+mod import;
+mod ply;
+mod vtk;
@@ -0,0 +1,234 @@
+use std::fs::File;
+use std::io::{BufWriter, Result, Write};
+use std::path::{Path, PathBuf};
+
+use crate::math::{Real, Vector, DIM};
+use crate::LiquidWorld;
+
+/// The encoding of the elements of a PLY file.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum PlyFormat {
+    /// Human-readable text.
+    Ascii,
+    /// Little-endian binary, much more compact and faster to load.
+    BinaryLittleEndian,
+}
+
+/// The per-particle attributes written to PLY files, in addition to the particle positions.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct PlyExportOptions {
+    /// The encoding of the particle data.
+    ///
+    /// This defaults to `PlyFormat::BinaryLittleEndian`.
+    pub format: PlyFormat,
+    /// Whether the `vx`, `vy`, `vz` velocity properties are written.
+    ///
+    /// This defaults to `true`.
+    pub velocities: bool,
+    /// Whether the `density` property is written.
+    ///
+    /// The densities computed by the pressure solver during the last step are written. The rest
+    /// density of the fluid is written instead if they are not available, e.g., for particles
+    /// added since the last step. This defaults to `false`.
+    pub densities: bool,
+    /// Whether the `user_data0` to `user_data3` properties are written.
+    ///
+    /// The 128 bits of the user data of each particle (see `Fluid::user_data`) are split into four
+    /// unsigned 32-bits integers, from the least to the most significant bits. This defaults to
+    /// `false`.
+    pub user_data: bool,
+    /// Whether the `fluid` property, the index of the fluid of each particle in
+    /// `LiquidWorld::fluids().as_slice()`, is written.
+    ///
+    /// This defaults to `false`.
+    pub fluid_ids: bool,
+}
+
+impl Default for PlyExportOptions {
+    fn default() -> Self {
+        Self {
+            format: PlyFormat::BinaryLittleEndian,
+            velocities: true,
+            densities: false,
+            user_data: false,
+            fluid_ids: false,
+        }
+    }
+}
+
+/// Writes the particles of all the fluids of `world` to `out` as a single PLY point cloud.
+///
+/// Each particle is written as a `vertex` element with the `x`, `y`, `z` properties, and the
+/// optional properties enabled by `options`. In 2D, the `z` coordinates are set to zero.
+pub fn write_ply(world: &LiquidWorld, options: &PlyExportOptions, out: impl Write) -> Result<()> {
+    let mut out = BufWriter::new(out);
+    let fluids = world.fluids().as_slice();
+    let densities = world.particle_densities();
+    let num_particles: usize = fluids.iter().map(|fluid| fluid.num_particles()).sum();
+
+    writeln!(out, "ply")?;
+    match options.format {
+        PlyFormat::Ascii => writeln!(out, "format ascii 1.0")?,
+        PlyFormat::BinaryLittleEndian => writeln!(out, "format binary_little_endian 1.0")?,
+    }
+    writeln!(out, "comment exported by salva")?;
+    writeln!(out, "element vertex {}", num_particles)?;
+    writeln!(out, "property float x")?;
+    writeln!(out, "property float y")?;
+    writeln!(out, "property float z")?;
+
+    if options.velocities {
+        writeln!(out, "property float vx")?;
+        writeln!(out, "property float vy")?;
+        writeln!(out, "property float vz")?;
+    }
+
+    if options.densities {
+        writeln!(out, "property float density")?;
+    }
+
+    if options.user_data {
+        for k in 0..4 {
+            writeln!(out, "property uint user_data{}", k)?;
+        }
+    }
+
+    if options.fluid_ids {
+        writeln!(out, "property uint fluid")?;
+    }
+
+    writeln!(out, "end_header")?;
+
+    let mut writer = PropertyWriter {
+        out: &mut out,
+        format: options.format,
+    };
+
+    for (fluid_id, fluid) in fluids.iter().enumerate() {
+        let fluid_densities = densities
+            .get(fluid_id)
+            .filter(|densities| densities.len() == fluid.num_particles());
+
+        for i in 0..fluid.num_particles() {
+            writer.write_vector(&fluid.positions[i].coords)?;
+
+            if options.velocities {
+                writer.write_vector(&fluid.velocities[i])?;
+            }
+
+            if options.densities {
+                writer.write_real(
+                    fluid_densities.map_or(fluid.rest_density(i), |densities| densities[i]),
+                )?;
+            }
+
+            if options.user_data {
+                let user_data = fluid.particle_user_data(i);
+
+                for k in 0..4 {
+                    writer.write_uint((user_data >> (32 * k)) as u32)?;
+                }
+            }
+
+            if options.fluid_ids {
+                writer.write_uint(fluid_id as u32)?;
+            }
+
+            writer.end_element()?;
+        }
+    }
+
+    out.flush()
+}
+
+/// Writes the particles of all the fluids of `world` to the PLY file at `path`.
+///
+/// See `write_ply` for details about the content of the file.
+pub fn save_ply(
+    world: &LiquidWorld,
+    options: &PlyExportOptions,
+    path: impl AsRef<Path>,
+) -> Result<()> {
+    write_ply(world, options, File::create(path)?)
+}
+
+struct PropertyWriter<'a, W: Write> {
+    out: &'a mut W,
+    format: PlyFormat,
+}
+
+impl<W: Write> PropertyWriter<'_, W> {
+    fn write_real(&mut self, value: Real) -> Result<()> {
+        match self.format {
+            PlyFormat::Ascii => write!(self.out, "{} ", value),
+            PlyFormat::BinaryLittleEndian => self.out.write_all(&value.to_le_bytes()),
+        }
+    }
+
+    fn write_vector(&mut self, v: &Vector<Real>) -> Result<()> {
+        for k in 0..3 {
+            self.write_real(if k < DIM { v[k] } else { na::zero::<Real>() })?;
+        }
+
+        Ok(())
+    }
+
+    fn write_uint(&mut self, value: u32) -> Result<()> {
+        match self.format {
+            PlyFormat::Ascii => write!(self.out, "{} ", value),
+            PlyFormat::BinaryLittleEndian => self.out.write_all(&value.to_le_bytes()),
+        }
+    }
+
+    fn end_element(&mut self) -> Result<()> {
+        match self.format {
+            PlyFormat::Ascii => writeln!(self.out),
+            PlyFormat::BinaryLittleEndian => Ok(()),
+        }
+    }
+}
+
+/// A sequence of PLY files, one per frame, named `{name}_{frame}.ply`.
+///
+/// Such numbered sequences can be loaded as animated point clouds by Blender and Houdini.
+pub struct PlySeries {
+    directory: PathBuf,
+    name: String,
+    options: PlyExportOptions,
+    num_frames: usize,
+}
+
+impl PlySeries {
+    /// Creates a series writing its PLY files into `directory`, with the given options.
+    ///
+    /// The directory must already exist.
+    pub fn new(
+        directory: impl Into<PathBuf>,
+        name: impl Into<String>,
+        options: PlyExportOptions,
+    ) -> Self {
+        Self {
+            directory: directory.into(),
+            name: name.into(),
+            options,
+            num_frames: 0,
+        }
+    }
+
+    /// The number of frames written so far.
+    pub fn num_frames(&self) -> usize {
+        self.num_frames
+    }
+
+    /// Writes the current state of `world` as a new frame.
+    ///
+    /// Returns the path of the PLY file of the frame.
+    pub fn write_frame(&mut self, world: &LiquidWorld) -> Result<PathBuf> {
+        let path = self
+            .directory
+            .join(format!("{}_{:05}.ply", self.name, self.num_frames));
+        save_ply(world, &self.options, &path)?;
+        self.num_frames += 1;
+        Ok(path)
+    }
+}
@@ -0,0 +1,191 @@
+use std::fs::File;
+use std::io::{BufWriter, Result, Write};
+use std::path::{Path, PathBuf};
+
+use crate::math::{Real, Vector, DIM};
+use crate::LiquidWorld;
+
+/// Writes the fluid particles of `world` to `out` with the VTK PolyData XML format (`.vtp`).
+///
+/// Each fluid is written as a separate piece, with its particles as vertices. The point data
+/// contain the `velocity` of the particles, and their `density` and `pressure` if they were
+/// computed by the pressure solver during the last step (see `LiquidWorld::particle_densities` and
+/// `LiquidWorld::particle_pressures`). In 2D, the `z` coordinates are set to zero.
+pub fn write_vtp(world: &LiquidWorld, out: impl Write) -> Result<()> {
+    let mut out = BufWriter::new(out);
+    let densities = world.particle_densities();
+    let pressures = world.particle_pressures();
+
+    writeln!(out, r#"<?xml version="1.0"?>"#)?;
+    writeln!(
+        out,
+        r#"<VTKFile type="PolyData" version="1.0" byte_order="LittleEndian">"#
+    )?;
+    writeln!(out, "<PolyData>")?;
+
+    for (fluid_id, fluid) in world.fluids().as_slice().iter().enumerate() {
+        let num_particles = fluid.num_particles();
+        writeln!(
+            out,
+            r#"<Piece NumberOfPoints="{}" NumberOfVerts="{}">"#,
+            num_particles, num_particles
+        )?;
+
+        writeln!(out, "<Points>")?;
+        write_vectors(
+            &mut out,
+            "position",
+            fluid.positions.iter().map(|pt| pt.coords),
+        )?;
+        writeln!(out, "</Points>")?;
+
+        writeln!(out, "<Verts>")?;
+        writeln!(
+            out,
+            r#"<DataArray type="Int64" Name="connectivity" format="ascii">"#
+        )?;
+        for i in 0..num_particles {
+            write!(out, "{} ", i)?;
+        }
+        writeln!(out, "\n</DataArray>")?;
+        writeln!(
+            out,
+            r#"<DataArray type="Int64" Name="offsets" format="ascii">"#
+        )?;
+        for i in 0..num_particles {
+            write!(out, "{} ", i + 1)?;
+        }
+        writeln!(out, "\n</DataArray>")?;
+        writeln!(out, "</Verts>")?;
+
+        writeln!(out, r#"<PointData Vectors="velocity">"#)?;
+        write_vectors(&mut out, "velocity", fluid.velocities.iter().copied())?;
+
+        if let Some(densities) = fluid_field(densities, fluid_id, num_particles) {
+            write_scalars(&mut out, "density", densities)?;
+        }
+
+        if let Some(pressures) = fluid_field(pressures, fluid_id, num_particles) {
+            write_scalars(&mut out, "pressure", pressures)?;
+        }
+
+        writeln!(out, "</PointData>")?;
+        writeln!(out, "</Piece>")?;
+    }
+
+    writeln!(out, "</PolyData>")?;
+    writeln!(out, "</VTKFile>")?;
+    out.flush()
+}
+
+/// Writes the fluid particles of `world` to the `.vtp` file at `path`.
+///
+/// See `write_vtp` for details about the content of the file.
+pub fn save_vtp(world: &LiquidWorld, path: impl AsRef<Path>) -> Result<()> {
+    write_vtp(world, File::create(path)?)
+}
+
+// The solver buffers don't match the particles if some were added since the last step.
+fn fluid_field(fields: &[Vec<Real>], fluid_id: usize, num_particles: usize) -> Option<&[Real]> {
+    fields
+        .get(fluid_id)
+        .filter(|field| field.len() == num_particles)
+        .map(|field| &field[..])
+}
+
+fn write_vectors(
+    out: &mut impl Write,
+    name: &str,
+    vectors: impl Iterator<Item = Vector<Real>>,
+) -> Result<()> {
+    writeln!(
+        out,
+        r#"<DataArray type="Float32" Name="{}" NumberOfComponents="3" format="ascii">"#,
+        name
+    )?;
+
+    for v in vectors {
+        for k in 0..3 {
+            let coord = if k < DIM { v[k] } else { na::zero::<Real>() };
+            write!(out, "{} ", coord)?;
+        }
+        writeln!(out)?;
+    }
+
+    writeln!(out, "</DataArray>")
+}
+
+fn write_scalars(out: &mut impl Write, name: &str, values: &[Real]) -> Result<()> {
+    writeln!(
+        out,
+        r#"<DataArray type="Float32" Name="{}" format="ascii">"#,
+        name
+    )?;
+
+    for value in values {
+        write!(out, "{} ", value)?;
+    }
+
+    writeln!(out, "\n</DataArray>")
+}
+
+/// A series of `.vtp` files, one per frame, indexed by a ParaView collection file (`.pvd`).
+///
+/// Opening the `.pvd` file in ParaView loads all the frames, with their simulation time.
+pub struct VtkSeries {
+    directory: PathBuf,
+    name: String,
+    frames: Vec<(Real, String)>,
+}
+
+impl VtkSeries {
+    /// Creates a series writing the files `{name}_{frame}.vtp` and `{name}.pvd` into `directory`.
+    ///
+    /// The directory must already exist.
+    pub fn new(directory: impl Into<PathBuf>, name: impl Into<String>) -> Self {
+        Self {
+            directory: directory.into(),
+            name: name.into(),
+            frames: Vec::new(),
+        }
+    }
+
+    /// The number of frames written so far.
+    pub fn num_frames(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// The path of the ParaView collection file of this series.
+    pub fn collection_path(&self) -> PathBuf {
+        self.directory.join(format!("{}.pvd", self.name))
+    }
+
+    /// Writes the current state of `world` as a new frame, and updates the collection file.
+    ///
+    /// Returns the path of the `.vtp` file of the frame.
+    pub fn write_frame(&mut self, world: &LiquidWorld) -> Result<PathBuf> {
+        let file_name = format!("{}_{:05}.vtp", self.name, self.frames.len());
+        let path = self.directory.join(&file_name);
+        save_vtp(world, &path)?;
+        self.frames.push((world.time(), file_name));
+
+        let mut out = BufWriter::new(File::create(self.collection_path())?);
+        writeln!(out, r#"<?xml version="1.0"?>"#)?;
+        writeln!(out, r#"<VTKFile type="Collection" version="1.0">"#)?;
+        writeln!(out, "<Collection>")?;
+
+        for (time, file_name) in &self.frames {
+            writeln!(
+                out,
+                r#"<DataSet timestep="{}" part="0" file="{}"/>"#,
+                time, file_name
+            )?;
+        }
+
+        writeln!(out, "</Collection>")?;
+        writeln!(out, "</VTKFile>")?;
+        out.flush()?;
+
+        Ok(path)
+    }
+}
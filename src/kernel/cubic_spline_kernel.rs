@@ -1,6 +1,11 @@
 use crate::kernel::Kernel;
 use crate::math::Real;
 use na::RealField;
+#[cfg(feature = "simd")]
+use {
+    crate::simd::SimdReal,
+    wide::{CmpGt, CmpLe},
+};
 
 /// The cubic spline smoothing kernel.
 ///
@@ -98,4 +103,45 @@ impl Kernel for CubicSplineKernel {
         normalizer * rhs
         */
     }
+
+    #[cfg(feature = "simd")]
+    fn simd_scalar_apply(r: SimdReal, h: Real) -> SimdReal {
+        #[cfg(feature = "dim2")]
+        let normalizer = na::convert::<_, Real>(40.0 / 7.0) / (Real::pi() * h * h);
+        #[cfg(feature = "dim3")]
+        let normalizer = na::convert::<_, Real>(8.0) / (Real::pi() * h * h * h);
+
+        let q = r / SimdReal::splat(h);
+        let q2 = q * q;
+        let one_q = SimdReal::ONE - q;
+        let near = SimdReal::ONE + (q2 * q - q2) * SimdReal::splat(6.0);
+        let far = one_q * one_q * one_q * SimdReal::splat(2.0);
+
+        let rhs = q
+            .cmp_le(SimdReal::splat(0.5))
+            .blend(near, q.cmp_le(SimdReal::ONE).blend(far, SimdReal::ZERO));
+
+        SimdReal::splat(normalizer) * rhs
+    }
+
+    #[cfg(feature = "simd")]
+    fn simd_scalar_apply_diff(r: SimdReal, h: Real) -> SimdReal {
+        #[cfg(feature = "dim2")]
+        let normalizer = na::convert::<_, Real>(40.0 / 7.0) / (Real::pi() * h * h);
+        #[cfg(feature = "dim3")]
+        let normalizer = na::convert::<_, Real>(8.0) / (Real::pi() * h * h * h);
+
+        let q = r / SimdReal::splat(h);
+        let one_q = SimdReal::ONE - q;
+        let near = (q * SimdReal::splat(3.0) - SimdReal::splat(2.0)) * q * SimdReal::splat(6.0);
+        let far = -one_q * one_q * SimdReal::splat(6.0);
+
+        let outside = q.cmp_gt(SimdReal::ONE) | q.cmp_le(SimdReal::splat(1.0e-5));
+        let rhs = outside.blend(
+            SimdReal::ZERO,
+            q.cmp_le(SimdReal::splat(0.5)).blend(near, far),
+        );
+
+        SimdReal::splat(normalizer) * rhs / SimdReal::splat(h)
+    }
 }
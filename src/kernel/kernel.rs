@@ -1,4 +1,6 @@
 use crate::math::{Point, Real, Vector};
+#[cfg(feature = "simd")]
+use crate::simd::{self, SimdReal};
 use approx::AbsDiffEq;
 use na::Unit;
 
@@ -9,6 +11,22 @@ pub trait Kernel: Send + Sync {
     /// Evaluates the kernel derivative for the given scalar `r` and the reference support length `h`.
     fn scalar_apply_diff(r: Real, h: Real) -> Real;
 
+    /// Evaluates the kernel for `simd::SIMD_WIDTH` values of `r` at once.
+    ///
+    /// The default implementation calls `Self::scalar_apply` on each lane.
+    #[cfg(feature = "simd")]
+    fn simd_scalar_apply(r: SimdReal, h: Real) -> SimdReal {
+        simd::lanewise(r, |r| Self::scalar_apply(r, h))
+    }
+
+    /// Evaluates the kernel derivative for `simd::SIMD_WIDTH` values of `r` at once.
+    ///
+    /// The default implementation calls `Self::scalar_apply_diff` on each lane.
+    #[cfg(feature = "simd")]
+    fn simd_scalar_apply_diff(r: SimdReal, h: Real) -> SimdReal {
+        simd::lanewise(r, |r| Self::scalar_apply_diff(r, h))
+    }
+
     /// Evaluate the kernel for the given vector.
     fn apply(v: Vector<Real>, h: Real) -> Real {
         Self::scalar_apply(v.norm(), h)
@@ -3,11 +3,15 @@
 pub use self::cubic_spline_kernel::CubicSplineKernel;
 pub use self::kernel::Kernel;
 pub use self::poly6_kernel::Poly6Kernel;
+pub use self::quintic_spline_kernel::QuinticSplineKernel;
 pub use self::spiky_kernel::SpikyKernel;
 pub use self::viscosity_kernel::ViscosityKernel;
+pub use self::wendland_c2_kernel::WendlandC2Kernel;
 
 mod cubic_spline_kernel;
 mod kernel;
 mod poly6_kernel;
+mod quintic_spline_kernel;
 mod spiky_kernel;
 mod viscosity_kernel;
+mod wendland_c2_kernel;
@@ -1,6 +1,8 @@
 use crate::kernel::Kernel;
 use crate::math::Real;
 use na::RealField;
+#[cfg(feature = "simd")]
+use {crate::simd::SimdReal, wide::CmpLe};
 
 /// The Poly6 smoothing kernel.
 ///
@@ -38,4 +40,28 @@ impl Kernel for Poly6Kernel {
             na::zero::<Real>()
         }
     }
+
+    #[cfg(feature = "simd")]
+    fn simd_scalar_apply(r: SimdReal, h: Real) -> SimdReal {
+        #[cfg(feature = "dim2")]
+        let normalizer = na::convert::<_, Real>(4.0) / (Real::pi() * h.powi(8));
+        #[cfg(feature = "dim3")]
+        let normalizer = na::convert::<_, Real>(315.0 / 64.0) / (Real::pi() * h.powi(9));
+
+        let d = SimdReal::splat(h * h) - r * r;
+        let value = SimdReal::splat(normalizer) * (d * d * d);
+        r.cmp_le(SimdReal::splat(h)).blend(value, SimdReal::ZERO)
+    }
+
+    #[cfg(feature = "simd")]
+    fn simd_scalar_apply_diff(r: SimdReal, h: Real) -> SimdReal {
+        #[cfg(feature = "dim2")]
+        let normalizer = na::convert::<_, Real>(4.0) / (Real::pi() * h.powi(8));
+        #[cfg(feature = "dim3")]
+        let normalizer = na::convert::<_, Real>(315.0 / 64.0) / (Real::pi() * h.powi(9));
+
+        let d = SimdReal::splat(h * h) - r * r;
+        let value = SimdReal::splat(normalizer) * (d * d) * r * SimdReal::splat(-6.0);
+        r.cmp_le(SimdReal::splat(h)).blend(value, SimdReal::ZERO)
+    }
 }
@@ -0,0 +1,99 @@
+use crate::kernel::Kernel;
+use crate::math::Real;
+use na::RealField;
+#[cfg(feature = "simd")]
+use {crate::simd::SimdReal, wide::CmpGt};
+
+/// The quintic spline smoothing kernel.
+///
+/// Refer to "Modeling low Reynolds number incompressible flows using SPH", Morris et al. 1997.
+/// It is smoother than the cubic spline kernel, at the cost of a more expensive evaluation.
+#[derive(Copy, Clone, Debug)]
+pub struct QuinticSplineKernel;
+
+impl Kernel for QuinticSplineKernel {
+    fn scalar_apply(r: Real, h: Real) -> Real {
+        assert!(r >= na::zero::<Real>());
+
+        #[cfg(feature = "dim2")]
+        let normalizer = na::convert::<_, Real>(63.0 / 478.0) / (Real::pi() * h * h);
+        #[cfg(feature = "dim3")]
+        let normalizer = na::convert::<_, Real>(9.0 / 40.0) / (Real::pi() * h * h * h);
+
+        // The support of the kernel is split into three parts of equal length.
+        let s = r / h * na::convert::<_, Real>(3.0);
+        let mut rhs = na::zero::<Real>();
+
+        for (center, coeff) in [(3.0, 1.0), (2.0, -6.0), (1.0, 15.0)] {
+            let t = na::convert::<_, Real>(center) - s;
+
+            if t > na::zero::<Real>() {
+                rhs += t.powi(5) * na::convert::<_, Real>(coeff);
+            }
+        }
+
+        normalizer * rhs
+    }
+
+    fn scalar_apply_diff(r: Real, h: Real) -> Real {
+        assert!(r >= na::zero::<Real>());
+
+        #[cfg(feature = "dim2")]
+        let normalizer = na::convert::<_, Real>(63.0 / 478.0) / (Real::pi() * h * h);
+        #[cfg(feature = "dim3")]
+        let normalizer = na::convert::<_, Real>(9.0 / 40.0) / (Real::pi() * h * h * h);
+
+        let s = r / h * na::convert::<_, Real>(3.0);
+        let mut rhs = na::zero::<Real>();
+
+        for (center, coeff) in [(3.0, 1.0), (2.0, -6.0), (1.0, 15.0)] {
+            let t = na::convert::<_, Real>(center) - s;
+
+            if t > na::zero::<Real>() {
+                rhs -= t.powi(4) * na::convert::<_, Real>(coeff * 5.0);
+            }
+        }
+
+        normalizer * rhs * na::convert::<_, Real>(3.0) / h
+    }
+
+    #[cfg(feature = "simd")]
+    fn simd_scalar_apply(r: SimdReal, h: Real) -> SimdReal {
+        #[cfg(feature = "dim2")]
+        let normalizer = na::convert::<_, Real>(63.0 / 478.0) / (Real::pi() * h * h);
+        #[cfg(feature = "dim3")]
+        let normalizer = na::convert::<_, Real>(9.0 / 40.0) / (Real::pi() * h * h * h);
+
+        let s = r / SimdReal::splat(h) * SimdReal::splat(3.0);
+        let mut rhs = SimdReal::ZERO;
+
+        for (center, coeff) in [(3.0, 1.0), (2.0, -6.0), (1.0, 15.0)] {
+            let t = SimdReal::splat(center) - s;
+            let t2 = t * t;
+            let term = t2 * t2 * t * SimdReal::splat(coeff);
+            rhs += t.cmp_gt(SimdReal::ZERO).blend(term, SimdReal::ZERO);
+        }
+
+        SimdReal::splat(normalizer) * rhs
+    }
+
+    #[cfg(feature = "simd")]
+    fn simd_scalar_apply_diff(r: SimdReal, h: Real) -> SimdReal {
+        #[cfg(feature = "dim2")]
+        let normalizer = na::convert::<_, Real>(63.0 / 478.0) / (Real::pi() * h * h);
+        #[cfg(feature = "dim3")]
+        let normalizer = na::convert::<_, Real>(9.0 / 40.0) / (Real::pi() * h * h * h);
+
+        let s = r / SimdReal::splat(h) * SimdReal::splat(3.0);
+        let mut rhs = SimdReal::ZERO;
+
+        for (center, coeff) in [(3.0, 1.0), (2.0, -6.0), (1.0, 15.0)] {
+            let t = SimdReal::splat(center) - s;
+            let t2 = t * t;
+            let term = t2 * t2 * SimdReal::splat(coeff * 5.0);
+            rhs -= t.cmp_gt(SimdReal::ZERO).blend(term, SimdReal::ZERO);
+        }
+
+        SimdReal::splat(normalizer) * rhs * SimdReal::splat(3.0) / SimdReal::splat(h)
+    }
+}
@@ -1,10 +1,12 @@
 use crate::kernel::Kernel;
 use crate::math::Real;
 use na::RealField;
+#[cfg(feature = "simd")]
+use {crate::simd::SimdReal, wide::CmpLe};
 
 /// The Spiky smoothing kernel.
 ///
-/// Refer to "Particle-Based Fluid Simulation for Interactive Applications", Müller et al.pub struct SpikyKernel;
+/// Refer to "Particle-Based Fluid Simulation for Interactive Applications", Müller et al.
 #[derive(Copy, Clone, Debug)]
 pub struct SpikyKernel;
 
@@ -38,4 +40,28 @@ impl Kernel for SpikyKernel {
             na::zero::<Real>()
         }
     }
+
+    #[cfg(feature = "simd")]
+    fn simd_scalar_apply(r: SimdReal, h: Real) -> SimdReal {
+        #[cfg(feature = "dim2")]
+        let normalizer = na::convert::<_, Real>(10.0) / (Real::pi() * h.powi(5));
+        #[cfg(feature = "dim3")]
+        let normalizer = na::convert::<_, Real>(15.0) / (Real::pi() * h.powi(6));
+
+        let d = SimdReal::splat(h) - r;
+        let value = SimdReal::splat(normalizer) * (d * d * d);
+        r.cmp_le(SimdReal::splat(h)).blend(value, SimdReal::ZERO)
+    }
+
+    #[cfg(feature = "simd")]
+    fn simd_scalar_apply_diff(r: SimdReal, h: Real) -> SimdReal {
+        #[cfg(feature = "dim2")]
+        let normalizer = na::convert::<_, Real>(10.0) / (Real::pi() * h.powi(5));
+        #[cfg(feature = "dim3")]
+        let normalizer = na::convert::<_, Real>(15.0) / (Real::pi() * h.powi(6));
+
+        let d = SimdReal::splat(h) - r;
+        let value = -SimdReal::splat(normalizer) * (d * d) * SimdReal::splat(3.0);
+        r.cmp_le(SimdReal::splat(h)).blend(value, SimdReal::ZERO)
+    }
 }
@@ -0,0 +1,81 @@
+use crate::kernel::Kernel;
+use crate::math::Real;
+use na::RealField;
+#[cfg(feature = "simd")]
+use {crate::simd::SimdReal, wide::CmpLe};
+
+/// The Wendland C2 smoothing kernel.
+///
+/// Refer to "Piecewise polynomial, positive definite and compactly supported radial functions of
+/// minimal degree", Wendland 1995. Unlike the spline kernels, it does not suffer from the pairing
+/// instability when the number of neighbors is large.
+#[derive(Copy, Clone, Debug)]
+pub struct WendlandC2Kernel;
+
+impl Kernel for WendlandC2Kernel {
+    fn scalar_apply(r: Real, h: Real) -> Real {
+        assert!(r >= na::zero::<Real>());
+
+        #[cfg(feature = "dim2")]
+        let normalizer = na::convert::<_, Real>(7.0) / (Real::pi() * h * h);
+        #[cfg(feature = "dim3")]
+        let normalizer = na::convert::<_, Real>(21.0 / 2.0) / (Real::pi() * h * h * h);
+
+        let q = r / h;
+
+        if q <= na::one::<Real>() {
+            normalizer
+                * (na::one::<Real>() - q).powi(4)
+                * (na::one::<Real>() + q * na::convert::<_, Real>(4.0))
+        } else {
+            na::zero::<Real>()
+        }
+    }
+
+    fn scalar_apply_diff(r: Real, h: Real) -> Real {
+        assert!(r >= na::zero::<Real>());
+
+        #[cfg(feature = "dim2")]
+        let normalizer = na::convert::<_, Real>(7.0) / (Real::pi() * h * h);
+        #[cfg(feature = "dim3")]
+        let normalizer = na::convert::<_, Real>(21.0 / 2.0) / (Real::pi() * h * h * h);
+
+        let q = r / h;
+
+        if q <= na::one::<Real>() {
+            normalizer * (na::one::<Real>() - q).powi(3) * q * na::convert::<_, Real>(-20.0) / h
+        } else {
+            na::zero::<Real>()
+        }
+    }
+
+    #[cfg(feature = "simd")]
+    fn simd_scalar_apply(r: SimdReal, h: Real) -> SimdReal {
+        #[cfg(feature = "dim2")]
+        let normalizer = na::convert::<_, Real>(7.0) / (Real::pi() * h * h);
+        #[cfg(feature = "dim3")]
+        let normalizer = na::convert::<_, Real>(21.0 / 2.0) / (Real::pi() * h * h * h);
+
+        let q = r / SimdReal::splat(h);
+        let one_q2 = (SimdReal::ONE - q) * (SimdReal::ONE - q);
+        let value = SimdReal::splat(normalizer)
+            * (one_q2 * one_q2)
+            * (SimdReal::ONE + q * SimdReal::splat(4.0));
+        q.cmp_le(SimdReal::ONE).blend(value, SimdReal::ZERO)
+    }
+
+    #[cfg(feature = "simd")]
+    fn simd_scalar_apply_diff(r: SimdReal, h: Real) -> SimdReal {
+        #[cfg(feature = "dim2")]
+        let normalizer = na::convert::<_, Real>(7.0) / (Real::pi() * h * h);
+        #[cfg(feature = "dim3")]
+        let normalizer = na::convert::<_, Real>(21.0 / 2.0) / (Real::pi() * h * h * h);
+
+        let q = r / SimdReal::splat(h);
+        let one_q = SimdReal::ONE - q;
+        let value =
+            SimdReal::splat(normalizer) * (one_q * one_q * one_q) * q * SimdReal::splat(-20.0)
+                / SimdReal::splat(h);
+        q.cmp_le(SimdReal::ONE).blend(value, SimdReal::ZERO)
+    }
+}
@@ -15,11 +15,19 @@ The name of this library is inspired from the famous surrealist artist `Salvador
  is inspired from its renown painting [The Persistence of Memory](https://en.wikipedia.org/wiki/The_Persistence_of_Memory).
 
 ## Features
-- **Pressure resolution:** DFSPH and IISPH.
-- **Viscosity:** DFSPH viscosity, Artificial viscosity, and XSPH viscosity.
+- **Pressure resolution:** DFSPH, IISPH, and WCSPH.
+- **Viscosity:** DFSPH viscosity, Artificial viscosity, XSPH viscosity, implicit viscosity, and non-newtonian (power-law and Bingham) viscosity.
 - **Surface tension:** WCSPH surface tension, and methods from He et al. 2014 and Akinci et al. 2013
-- **Elasticity:** method from Becker et al. 2009
-- **Multiphase fluids**: mix several fluids with different characteristics (densities, viscosities, etc.)
+- **Vorticity:** vorticity confinement.
+- **Air drag:** linear and quadratic drag relative to a wind velocity field.
+- **Elasticity:** method from Becker et al. 2009, with optional plasticity
+- **Granular materials:** Drucker-Prager friction.
+- **Heat:** heat diffusion between fluids and boundaries, melting and solidification.
+- **Multiphase fluids**: mix several fluids with very different densities (Solenthaler et al. 2008), viscosities, etc.
+- **Miscible fluids**: diffusion of per-particle concentrations.
+- **Emitters and sinks**: particles spawned over time from points, disks, or meshes, with optional lifetimes, and deleted inside of drain regions.
+- **Diffuse particles**: spray, foam, and bubbles for rendering (Ihmsen et al. 2012).
+- **Surface reconstruction**: marching cubes (3D) and marching squares (2D) meshing of fluids, with optional anisotropic kernels (Yu & Turk 2010), with the `surface` feature.
 - Optional **two-way coupling** with bodies from **rapier**.
 - **WASM** support
 */
@@ -72,12 +80,25 @@ macro_rules! par_iter_mut {
     }};
 }
 
+// The number of consecutive elements summed together by `par_reduce_sum!` before the partial
+// sums are added up. Fixing the partition of the elements, instead of letting rayon split the
+// work depending on the number of threads, makes the result bit-identical whatever the thread
+// count.
+#[cfg(feature = "parallel")]
+const REDUCTION_CHUNK_SIZE: usize = 1024;
+
+// NOTE: with a maximum length of 1, rayon splits every range of partial sums at its middle
+// until it reaches single elements, so the partial sums are always added up along the same
+// binary tree, without allocating them.
 macro_rules! par_reduce_sum {
     ($identity: expr, $t: expr) => {{
         #[cfg(not(feature = "parallel"))]
         let res = $t.fold($identity, |a, b| a + b);
         #[cfg(feature = "parallel")]
-        let res = $t.reduce(|| $identity, |a, b| a + b);
+        let res = $t
+            .fold_chunks(crate::REDUCTION_CHUNK_SIZE, || $identity, |a, b| a + b)
+            .with_max_len(1)
+            .reduce(|| $identity, |a, b| a + b);
         res
     }};
 }
@@ -85,18 +106,33 @@ macro_rules! par_reduce_sum {
 pub mod counters;
 pub mod coupling;
 pub mod geometry;
+#[cfg(feature = "gpu")]
+pub mod gpu;
 pub mod helper;
 pub mod integrations;
+pub mod io;
 pub mod kernel;
 mod liquid_world;
 pub mod object;
+pub(crate) mod random;
+pub mod replay;
 #[cfg(feature = "sampling")]
 pub mod sampling;
+#[cfg(feature = "simd")]
+pub mod simd;
+mod simulation_error;
+mod snapshot;
 pub mod solver;
+#[cfg(feature = "surface")]
+pub mod surface;
 mod timestep_manager;
 pub(crate) mod z_order;
 
-pub use crate::liquid_world::LiquidWorld;
+#[cfg(feature = "parry")]
+pub use crate::liquid_world::BuoyancyEstimate;
+pub use crate::liquid_world::{FluidRayIntersection, LiquidWorld, ParticleDiagnostics};
+pub use crate::simulation_error::{SimulationError, StepReport};
+pub use crate::snapshot::SnapshotError;
 pub use crate::timestep_manager::TimestepManager;
 
 /// Compilation flags dependent aliases for mathematical types.
@@ -260,3 +296,30 @@ pub mod math {
         RowVector2::new(-v.y, v.x)
     }
 }
+
+#[cfg(all(test, feature = "parallel"))]
+mod test {
+    use crate::math::Real;
+    use rayon::prelude::*;
+
+    #[test]
+    fn parallel_sums_do_not_depend_on_the_thread_count() {
+        // Values of various magnitudes, so that the rounding errors depend on the summation order.
+        let values: Vec<Real> = (0..1_000_000)
+            .map(|i| (i as Real * 0.37).sin() * (10.0 as Real).powi(i % 7))
+            .collect();
+        let sum = |num_threads| {
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(num_threads)
+                .build()
+                .unwrap()
+                .install(|| par_reduce_sum!(0.0, par_iter!(values).copied()))
+        };
+
+        let sequential = sum(1);
+
+        for num_threads in 2..=8 {
+            assert_eq!(sum(num_threads).to_bits(), sequential.to_bits());
+        }
+    }
+}
@@ -1,64 +1,248 @@
-use crate::counters::Counters;
+use crate::counters::{ConservationDiagnostics, ConservationHistory, Counters, SolverStatistics};
 use crate::coupling::CouplingManager;
-use crate::geometry::{self, ContactManager, HGrid, HGridEntry};
-use crate::math::{Real, Vector};
-use crate::object::{Boundary, BoundaryHandle, BoundarySet};
-use crate::object::{Fluid, FluidHandle, FluidSet};
-use crate::solver::PressureSolver;
-use crate::TimestepManager;
+use crate::geometry::{
+    ContactFilter, ContactManager, HGridEntry, HGridStatistics, MultiResolutionGrid,
+    NeighborSearch, PeriodicDomain,
+};
+use crate::kernel::{CubicSplineKernel, Kernel};
+use crate::math::{Point, Real, Vector};
+use crate::object::{ActiveRegion, ActiveRegionHandle, ActiveRegionSet, InactiveParticleModel};
+use crate::object::{Boundary, BoundaryHandle, BoundarySet, DiffuseParticles, ForceClearingPolicy};
+use crate::object::{EmissionRequest, Fluid, FluidHandle, FluidSet, ParticleBudget, ParticleId};
+use crate::object::{Emitter, EmitterHandle, EmitterSet, FlowSensor, FlowSensorHandle};
+use crate::object::{FlowSensorSet, ForceField, ForceFieldHandle, ForceFieldSet};
+use crate::object::{OpenBoundary, OpenBoundaryHandle, OpenBoundarySet};
+use crate::object::{RotatingFrame, Sink, SinkHandle, SinkSet};
+use crate::object::{SpongeLayer, SpongeLayerHandle, SpongeLayerSet};
+use crate::object::{SubstepHook, SubstepHookHandle, SubstepHookSet, SubstepStage};
+#[cfg(feature = "serde-serialize")]
+use crate::solver::DFSPHSolver;
+use crate::solver::{
+    ConcentrationSolver, DiffuseParticleContext, DiffuseParticleSolver, DivergenceSolver,
+    HeatSolver, ParticleShifting, PressureSolver, SimulationGuards,
+};
+use crate::{SimulationError, SnapshotError, StepReport, TimestepManager};
+use std::any::Any;
+use std::ops::Range;
 #[cfg(feature = "parry")]
 use {
-    crate::math::Isometry,
-    crate::object::ParticleId,
-    parry::{bounding_volume::Aabb, query::PointQuery, shape::Shape},
+    crate::math::{AngularVector, Isometry},
+    parry::{
+        bounding_volume::{Aabb, BoundingVolume},
+        query::PointQuery,
+        shape::Shape,
+    },
 };
 
+/// The result of a ray cast against the surface of the fluids.
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub struct FluidRayIntersection {
+    /// The time of impact of the ray, i.e., the hit point is at `origin + dir * toi`.
+    pub toi: Real,
+    /// The point where the ray hits the surface of the fluids.
+    pub point: Point<Real>,
+    /// The unit normal of the surface of the fluids at the hit point, pointing outside of the fluids.
+    ///
+    /// This is zero if the ray origin is inside of the fluids and the ray cast is solid.
+    pub normal: Vector<Real>,
+}
+
+/// An estimate of the forces applied by the fluids to a body immersed in them.
+///
+/// See `LiquidWorld::estimate_buoyancy`.
+#[cfg(feature = "parry")]
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct BuoyancyEstimate {
+    /// The volume of the body inside of the fluids.
+    pub submerged_volume: Real,
+    /// The mass of fluid displaced by the body.
+    pub displaced_mass: Real,
+    /// The center of mass of the displaced fluid, where the buoyancy force applies.
+    ///
+    /// This is `None` if the body is not immersed.
+    pub center_of_buoyancy: Option<Point<Real>>,
+    /// The buoyancy force, i.e., the opposite of the weight of the displaced fluid.
+    pub buoyancy_force: Vector<Real>,
+    /// The drag force caused by the velocity of the fluids relative to the body.
+    pub drag_force: Vector<Real>,
+    /// The torque of the buoyancy and drag forces relative to the origin of the body's position.
+    pub torque: AngularVector<Real>,
+}
+
+/// Diagnostics about the state of the particles, collected during the last substep when the
+/// validation is enabled.
+///
+/// See `LiquidWorld::set_validation_enabled`.
+#[derive(Clone, PartialEq, Debug, Default)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub struct ParticleDiagnostics {
+    /// The particles without any neighbor, not even themselves (e.g., because of a non-finite
+    /// position).
+    ///
+    /// The density of such fluid particles can't be estimated so they are considered at rest, and
+    /// such boundary particles are given a zero volume so they are ignored by the fluids.
+    pub isolated_particles: Vec<ParticleId>,
+}
+
 /// The physics world for simulating fluids with boundaries.
+///
+/// With the `serde-serialize` feature, the liquid world can be serialized to save and restore the
+/// state of a simulation. The closures and trait objects it holds can't be serialized and must be
+/// added back after deserialization: the non-pressure forces of the fluids, the force fields, the
+/// substep hooks, the contact filter, the neighbor search structure, the custom inflow profiles,
+/// and the trajectories of the boundaries. The pressure solver is also reset to a default `DFSPHSolver`, so it must be
+/// replaced with `LiquidWorld::set_pressure_solver`. Its own state can be serialized separately
+/// using `LiquidWorld::pressure_solver`.
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
 pub struct LiquidWorld {
     /// Performance counters of the whole fluid simulation engine.
+    ///
+    /// They are disabled by default: call `counters.enable()` to time each stage of the
+    /// following steps, and `counters.disable()` to stop.
+    #[cfg_attr(feature = "serde-serialize", serde(skip, default = "Counters::new"))]
     pub counters: Counters,
     nsubsteps_since_sort: usize,
+    particle_sort_interval: Option<usize>,
+    time: Real,
     particle_radius: Real,
     h: Real,
     fluids: FluidSet,
     boundaries: BoundarySet,
+    flow_sensors: FlowSensorSet,
+    emitters: EmitterSet,
+    sinks: SinkSet,
+    sponge_layers: SpongeLayerSet,
+    open_boundaries: OpenBoundarySet,
+    active_regions: ActiveRegionSet,
+    inactive_particle_model: InactiveParticleModel,
+    #[cfg_attr(feature = "serde-serialize", serde(skip))]
+    inactive_particles: Vec<Vec<InactiveParticle>>,
+    #[cfg_attr(
+        feature = "serde-serialize",
+        serde(skip, default = "ForceFieldSet::new")
+    )]
+    force_fields: ForceFieldSet,
+    #[cfg_attr(
+        feature = "serde-serialize",
+        serde(skip, default = "SubstepHookSet::new")
+    )]
+    substep_hooks: SubstepHookSet,
+    rotating_frame: Option<RotatingFrame>,
+    previous_positions: Vec<Vec<Point<Real>>>,
+    #[cfg_attr(
+        feature = "serde-serialize",
+        serde(skip, default = "LiquidWorld::default_pressure_solver")
+    )]
     solver: Box<dyn PressureSolver + Send + Sync>,
+    divergence_solver: Option<DivergenceSolver>,
+    particle_shifting: Option<ParticleShifting>,
+    simulation_guards: Option<SimulationGuards>,
+    heat_solver: Option<HeatSolver>,
+    concentration_solver: Option<ConcentrationSolver>,
+    diffuse_particle_solver: Option<DiffuseParticleSolver>,
+    diffuse_particles: DiffuseParticles,
+    particle_budget: Option<ParticleBudget>,
     contact_manager: ContactManager,
     timestep_manager: TimestepManager,
-    hgrid: HGrid<HGridEntry>,
+    fixed_timestep: Option<Real>,
+    max_fixed_steps_per_frame: usize,
+    fixed_time_accumulator: Real,
+    grid: MultiResolutionGrid,
+    #[cfg_attr(feature = "serde-serialize", serde(skip))]
+    neighbor_search: Option<Box<dyn NeighborSearch>>,
+    // The bounding box of the fluid particles inserted into the neighbor search structure.
+    #[cfg_attr(feature = "serde-serialize", serde(skip))]
+    fluid_bounds: Option<[Point<Real>; 2]>,
+    validation_enabled: bool,
+    particle_diagnostics: ParticleDiagnostics,
+    conservation_diagnostics_enabled: bool,
+    conservation_diagnostics: Option<ConservationDiagnostics>,
+    conservation_history: ConservationHistory,
 }
 
+// The index, position, and velocity of an inactive particle at the beginning of the substep.
+type InactiveParticle = (usize, Point<Real>, Vector<Real>);
+
 impl LiquidWorld {
     /// Initialize a new liquid world.
     ///
     /// # Parameters
     ///
-    /// - `particle_radius`: the radius of every particle on this world.
+    /// - `particle_radius`: the default radius of the particles on this world.
     /// - `smoothing_factor`: the smoothing factor used to compute the SPH kernel radius.
     ///    The kernel radius will be computed as `particle_radius * smoothing_factor * 2.0.
+    ///
+    /// The boundary particles have the default radius. Fluids with another particle radius get a
+    /// kernel radius scaled by the same ratio (see `Fluid::kernel_radius`).
     pub fn new(
         solver: impl PressureSolver + Send + Sync + 'static,
         particle_radius: Real,
         smoothing_factor: Real,
     ) -> Self {
         let h = particle_radius * smoothing_factor * na::convert::<_, Real>(2.0);
+        let mut timestep_manager = TimestepManager::new(particle_radius);
+        timestep_manager.set_kernel_radius(h);
+
         Self {
             counters: Counters::new(),
             nsubsteps_since_sort: 0,
+            particle_sort_interval: None,
+            time: na::zero::<Real>(),
             particle_radius,
             h,
             fluids: FluidSet::new(),
             boundaries: BoundarySet::new(),
+            flow_sensors: FlowSensorSet::new(),
+            emitters: EmitterSet::new(),
+            sinks: SinkSet::new(),
+            sponge_layers: SpongeLayerSet::new(),
+            open_boundaries: OpenBoundarySet::new(),
+            active_regions: ActiveRegionSet::new(),
+            inactive_particle_model: InactiveParticleModel::Frozen,
+            inactive_particles: Vec::new(),
+            force_fields: ForceFieldSet::new(),
+            substep_hooks: SubstepHookSet::new(),
+            rotating_frame: None,
+            previous_positions: Vec::new(),
             solver: Box::new(solver),
+            divergence_solver: None,
+            particle_shifting: None,
+            simulation_guards: None,
+            heat_solver: None,
+            concentration_solver: None,
+            diffuse_particle_solver: None,
+            diffuse_particles: DiffuseParticles::new(),
+            particle_budget: None,
             contact_manager: ContactManager::new(),
-            timestep_manager: TimestepManager::new(particle_radius),
-            hgrid: HGrid::new(h),
+            timestep_manager,
+            fixed_timestep: None,
+            max_fixed_steps_per_frame: 5,
+            fixed_time_accumulator: na::zero::<Real>(),
+            grid: MultiResolutionGrid::new(h),
+            neighbor_search: None,
+            fluid_bounds: None,
+            validation_enabled: false,
+            particle_diagnostics: ParticleDiagnostics::default(),
+            conservation_diagnostics_enabled: false,
+            conservation_diagnostics: None,
+            conservation_history: ConservationHistory::new(0),
         }
     }
 
     /// Advances the simulation by `dt` seconds.
     ///
-    /// All the fluid particles will be affected by an acceleration equal to `gravity`.
+    /// All the fluid particles will be affected by an acceleration equal to `gravity`, unless their
+    /// fluid overrides it (see `Fluid::gravity` and `Fluid::gravity_scale`).
     pub fn step(&mut self, dt: Real, gravity: &Vector<Real>) {
         self.step_with_coupling(dt, gravity, &mut ())
     }
@@ -74,36 +258,92 @@ impl LiquidWorld {
         self.counters.step_time.start();
         self.timestep_manager.reset(dt);
 
+        for fluid in self.fluids.as_mut_slice() {
+            fluid.age_particles(dt);
+        }
+
+        self.drain_particles(dt);
+        self.emit_particles(dt);
+        self.feed_open_boundaries();
+        self.update_fluid_kernel_radii();
         self.solver.init_with_fluids(self.fluids.as_slice());
+        self.timestep_manager
+            .set_pressure_substep(self.solver.max_substep(self.h, self.fluids.as_slice()));
 
         for fluid in self.fluids.as_mut_slice() {
+            if fluid.num_deleted_particles() != 0 {
+                // The remaining particles are shifted, so the neighbor lists are stale.
+                self.contact_manager.invalidate_neighbor_lists();
+            }
+
             fluid.apply_particles_removal();
         }
 
+        if let Some(interval) = self.particle_sort_interval {
+            if self.nsubsteps_since_sort >= interval {
+                self.nsubsteps_since_sort = 0;
+                self.sort_particles();
+            }
+        }
+
+        for boundary in self.boundaries.as_mut_slice() {
+            if boundary.force_clearing_policy == ForceClearingPolicy::EveryStep {
+                boundary.clear_forces(false);
+            }
+        }
+
+        for sensor in self.flow_sensors.as_mut_slice() {
+            sensor.begin_step();
+        }
+
         // Perform substeps.
         while !self.timestep_manager.is_done() {
             self.nsubsteps_since_sort += 1;
             self.counters.nsubsteps += 1;
 
+            self.wrap_particles();
+            self.update_active_particles();
+
             self.counters.stages.collision_detection_time.resume();
             self.counters.cd.grid_insertion_time.resume();
-            self.hgrid.clear();
-            geometry::insert_fluids_to_grid(self.fluids.as_slice(), &mut self.hgrid);
+            let neighbor_search = self
+                .neighbor_search
+                .as_deref_mut()
+                .unwrap_or(&mut self.grid);
+            neighbor_search.clear();
+            neighbor_search.insert_fluids(self.fluids.as_slice());
+            self.fluid_bounds = fluid_bounds(self.fluids.as_slice());
             self.counters.cd.grid_insertion_time.pause();
 
+            for boundary in self.boundaries.as_mut_slice() {
+                if boundary.force_clearing_policy == ForceClearingPolicy::EverySubstep {
+                    boundary.clear_forces(false);
+                }
+            }
+
             self.counters.cd.boundary_update_time.resume();
             coupling.update_boundaries(
                 &self.timestep_manager,
                 self.h,
                 self.particle_radius,
-                &self.hgrid,
+                self.neighbor_search.as_deref().unwrap_or(&self.grid),
                 self.fluids.as_mut_slice(),
                 &mut self.boundaries,
             );
             self.counters.cd.boundary_update_time.pause();
 
+            for boundary in self.boundaries.as_mut_slice() {
+                boundary.update_motion(self.time, self.timestep_manager.remaining_time());
+                boundary.update_density_map_particles(self.fluids.as_slice());
+                boundary.resize_forces();
+                boundary.notify_substep();
+            }
+
             self.counters.cd.grid_insertion_time.resume();
-            geometry::insert_boundaries_to_grid(self.boundaries.as_slice(), &mut self.hgrid);
+            self.neighbor_search
+                .as_deref_mut()
+                .unwrap_or(&mut self.grid)
+                .insert_boundaries(self.boundaries.as_slice());
             self.counters.cd.grid_insertion_time.pause();
 
             self.solver.init_with_boundaries(self.boundaries.as_slice());
@@ -113,26 +353,76 @@ impl LiquidWorld {
                 self.h,
                 self.fluids.as_slice(),
                 self.boundaries.as_slice(),
-                &self.hgrid,
+                self.neighbor_search.as_deref().unwrap_or(&self.grid),
             );
 
             self.counters.cd.ncontacts = self.contact_manager.ncontacts();
             self.counters.stages.collision_detection_time.pause();
 
             self.counters.stages.solver_time.resume();
+            self.counters.solver.kernel_evaluation_time.resume();
             self.solver.evaluate_kernels(
                 self.h,
                 &mut self.contact_manager,
                 self.fluids.as_slice(),
                 self.boundaries.as_slice(),
             );
+            self.counters.solver.kernel_evaluation_time.pause();
 
+            self.counters.solver.density_computation_time.resume();
             self.solver.compute_densities(
                 &self.contact_manager,
                 self.fluids.as_slice(),
                 self.boundaries.as_mut_slice(),
             );
+            self.counters.solver.density_computation_time.pause();
+
+            if self.validation_enabled {
+                self.collect_particle_diagnostics();
+            }
+
+            if let Some(divergence_solver) = &mut self.divergence_solver {
+                self.counters.solver.divergence_resolution_time.resume();
+                divergence_solver.solve(
+                    &mut self.counters,
+                    &self.timestep_manager,
+                    &self.contact_manager,
+                    self.fluids.as_mut_slice(),
+                    self.boundaries.as_slice(),
+                );
+                self.counters.solver.divergence_resolution_time.pause();
+            }
 
+            if self.flow_sensors.len() != 0
+                || self
+                    .simulation_guards
+                    .is_some_and(|guards| guards.needs_previous_positions())
+            {
+                self.previous_positions
+                    .resize(self.fluids.len(), Vec::new());
+
+                for (fluid, previous_positions) in self
+                    .fluids
+                    .as_slice()
+                    .iter()
+                    .zip(self.previous_positions.iter_mut())
+                {
+                    previous_positions.clear();
+                    previous_positions.extend_from_slice(&fluid.positions);
+                }
+            }
+
+            self.apply_force_fields();
+
+            for open_boundary in self.open_boundaries.as_mut_slice() {
+                if let Some(fluid) = self.fluids.get(open_boundary.fluid) {
+                    open_boundary.collect_buffer_particles(fluid, self.time);
+                }
+            }
+
+            let substep_hooks = &mut self.substep_hooks;
+            self.timestep_manager
+                .predict_substep(self.fluids.as_slice());
             self.solver.step(
                 &mut self.counters,
                 &mut self.timestep_manager,
@@ -141,20 +431,525 @@ impl LiquidWorld {
                 self.h,
                 self.fluids.as_mut_slice(),
                 self.boundaries.as_slice(),
+                &mut |stage, timestep, fluids| substep_hooks.run(stage, timestep, fluids),
+            );
+
+            if let Some(particle_shifting) = &mut self.particle_shifting {
+                self.counters.solver.non_pressure_resolution_time.resume();
+                particle_shifting.solve(
+                    self.h,
+                    self.particle_radius,
+                    &self.contact_manager,
+                    self.fluids.as_mut_slice(),
+                    self.boundaries.as_slice(),
+                );
+                self.counters.solver.non_pressure_resolution_time.pause();
+            }
+
+            self.integrate_inactive_particles(gravity);
+
+            for open_boundary in self.open_boundaries.as_mut_slice() {
+                if let Some(fluid) = self.fluids.get_mut(open_boundary.fluid) {
+                    open_boundary.enforce_buffer_motion(
+                        fluid,
+                        self.time,
+                        self.timestep_manager.dt(),
+                    );
+                }
+            }
+
+            if let Some(guards) = &self.simulation_guards {
+                let (num_nans, num_clamped) = guards.apply(
+                    self.particle_radius,
+                    &self.previous_positions,
+                    self.fluids.as_mut_slice(),
+                );
+                self.counters.stats.num_nan_particles += num_nans;
+                self.counters.stats.num_clamped_particles += num_clamped;
+            }
+
+            self.substep_hooks.run(
+                SubstepStage::AfterIntegration,
+                &self.timestep_manager,
+                self.fluids.as_mut_slice(),
             );
 
+            if let Some(heat_solver) = &mut self.heat_solver {
+                self.counters.solver.non_pressure_resolution_time.resume();
+                heat_solver.solve(
+                    &self.timestep_manager,
+                    self.h,
+                    &self.contact_manager,
+                    self.fluids.as_mut_slice(),
+                    self.boundaries.as_slice(),
+                );
+                self.counters.solver.non_pressure_resolution_time.pause();
+            }
+
+            if let Some(concentration_solver) = &mut self.concentration_solver {
+                self.counters.solver.non_pressure_resolution_time.resume();
+                concentration_solver.solve(
+                    &self.timestep_manager,
+                    self.h,
+                    &self.contact_manager,
+                    self.fluids.as_mut_slice(),
+                );
+                self.counters.solver.non_pressure_resolution_time.pause();
+            }
+
+            if let Some(diffuse_particle_solver) = &mut self.diffuse_particle_solver {
+                self.counters.solver.non_pressure_resolution_time.resume();
+                let ctx = DiffuseParticleContext {
+                    kernel_radius: self.h,
+                    particle_radius: self.particle_radius,
+                    gravity,
+                    contact_manager: &self.contact_manager,
+                    neighbor_search: self.neighbor_search.as_deref().unwrap_or(&self.grid),
+                    fluids: self.fluids.as_slice(),
+                    boundaries: self.boundaries.as_slice(),
+                };
+                diffuse_particle_solver.solve(
+                    &self.timestep_manager,
+                    &ctx,
+                    &mut self.diffuse_particles,
+                );
+                self.counters.solver.non_pressure_resolution_time.pause();
+            }
+
+            for sensor in self.flow_sensors.as_mut_slice() {
+                sensor.measure_substep(&self.previous_positions, self.fluids.as_slice());
+            }
+
             coupling.transmit_forces(&self.timestep_manager, &self.boundaries);
             self.counters.stages.solver_time.pause();
+
+            self.absorb_waves(self.timestep_manager.dt());
+            self.time += self.timestep_manager.dt();
+
+            for boundary in self.boundaries.as_mut_slice() {
+                boundary.integrate_motion(self.timestep_manager.dt());
+            }
+
+            self.counters.stats.update(
+                self.timestep_manager.dt(),
+                self.particle_radius,
+                self.timestep_manager.cfl_coeff(),
+                self.fluids.as_slice(),
+            );
+        }
+
+        for sensor in self.flow_sensors.as_mut_slice() {
+            sensor.end_step(dt);
+        }
+
+        self.wrap_particles();
+
+        // Update the render velocities using the contacts from the last substep.
+        if self.counters.nsubsteps != 0 {
+            for (fluid, fluid_fluid_contacts) in self
+                .fluids
+                .as_mut_slice()
+                .iter_mut()
+                .zip(self.contact_manager.fluid_fluid_contacts.iter())
+            {
+                if let Some(smoothing) = fluid.render_velocity_smoothing {
+                    crate::solver::helper::smooth_render_velocities(
+                        smoothing,
+                        fluid_fluid_contacts,
+                        fluid,
+                    );
+                }
+            }
         }
 
-        //        if self.nsubsteps_since_sort >= 100 {
-        //            self.nsubsteps_since_sort = 0;
-        //            println!("Performing z-sort of particles.");
-        //            par_iter_mut!(self.fluids.as_mut_slice()).for_each(|fluid| fluid.z_sort())
-        //        }
+        // Detect the surface particles using the contacts from the last substep.
+        if self.counters.nsubsteps != 0 {
+            for fluid_id in 0..self.fluids.len() {
+                let fluids = self.fluids.as_mut_slice();
+
+                if let Some(detection) = fluids[fluid_id].surface_detection {
+                    let mut surface_particles =
+                        std::mem::take(&mut fluids[fluid_id].surface_particles);
+                    crate::solver::helper::detect_surface_particles(
+                        detection,
+                        self.h,
+                        &self.contact_manager,
+                        fluid_id,
+                        fluids,
+                        self.boundaries.as_slice(),
+                        &mut surface_particles,
+                    );
+                    fluids[fluid_id].surface_particles = surface_particles;
+                }
+            }
+        }
 
         self.counters.step_time.pause();
-        //        println!("Counters: {}", self.counters);
+
+        if self.conservation_diagnostics_enabled {
+            let diagnostics = ConservationDiagnostics::compute(
+                self.time,
+                gravity,
+                self.fluids.as_slice(),
+                self.solver.densities(),
+            );
+            self.conservation_history.push(diagnostics.clone());
+            self.conservation_diagnostics = Some(diagnostics);
+        }
+
+        #[cfg(feature = "log")]
+        log::trace!("Counters: {}", self.counters);
+    }
+
+    /// Advances the simulation by `dt` seconds, reporting invalid configurations and bad states
+    /// instead of asserting or silently producing garbage.
+    ///
+    /// See `LiquidWorld::try_step_with_coupling`.
+    pub fn try_step(
+        &mut self,
+        dt: Real,
+        gravity: &Vector<Real>,
+    ) -> Result<StepReport, SimulationError> {
+        self.try_step_with_coupling(dt, gravity, &mut ())
+    }
+
+    /// Advances the simulation by `dt` seconds, taking into account coupling with an external
+    /// rigid-body engine, and reporting invalid configurations and bad states.
+    ///
+    /// If the configuration is invalid, `SimulationError::InvalidConfiguration` is returned and
+    /// the step is not performed. Otherwise, the step is performed and an error is returned if a
+    /// fluid particle ends up with a non-finite state, or if the pressure resolution did not
+    /// converge. The world is left as-is in this case: it is up to the caller to decide whether
+    /// to continue, to remove the faulty particles, or to restore a previous state.
+    pub fn try_step_with_coupling(
+        &mut self,
+        dt: Real,
+        gravity: &Vector<Real>,
+        coupling: &mut impl CouplingManager,
+    ) -> Result<StepReport, SimulationError> {
+        self.validate_configuration(dt, gravity)?;
+        self.step_with_coupling(dt, gravity, coupling);
+
+        for (handle, fluid) in self.fluids.iter() {
+            let is_finite = |v: &Vector<Real>| v.iter().all(|e| e.is_finite());
+
+            if let Some(i) = (0..fluid.num_particles()).find(|i| {
+                !is_finite(&fluid.positions[*i].coords) || !is_finite(&fluid.velocities[*i])
+            }) {
+                return Err(SimulationError::NonFiniteParticle(
+                    ParticleId::FluidParticle(handle, i),
+                ));
+            }
+        }
+
+        if self.counters.solver.num_unconverged_pressure_solves != 0 {
+            return Err(SimulationError::NonConvergence {
+                num_unconverged_substeps: self.counters.solver.num_unconverged_pressure_solves,
+                density_error: self.counters.solver.density_error,
+            });
+        }
+
+        Ok(StepReport {
+            num_substeps: self.counters.nsubsteps,
+            solver: self.solver_statistics(),
+            stats: self.counters.stats,
+        })
+    }
+
+    // Scales the kernel radius of the fluids with a particle radius different from the one of this
+    // world by the ratio between their particle radii.
+    fn update_fluid_kernel_radii(&mut self) {
+        for fluid in self.fluids.as_mut_slice() {
+            fluid.kernel_radius = if fluid.particle_radius() == self.particle_radius {
+                None
+            } else {
+                Some(self.h * fluid.particle_radius() / self.particle_radius)
+            };
+        }
+    }
+
+    fn validate_configuration(
+        &self,
+        dt: Real,
+        gravity: &Vector<Real>,
+    ) -> Result<(), SimulationError> {
+        let invalid = |desc: String| Err(SimulationError::InvalidConfiguration(desc));
+
+        if !dt.is_finite() || dt < na::zero::<Real>() {
+            return invalid(format!("the timestep length {} is invalid", dt));
+        }
+
+        if gravity.iter().any(|e| !e.is_finite()) {
+            return invalid(format!("the gravity {:?} is not finite", gravity));
+        }
+
+        for (handle, fluid) in self.fluids.iter() {
+            if !fluid.particle_radius().is_finite() || fluid.particle_radius() <= na::zero::<Real>()
+            {
+                return invalid(format!(
+                    "the particle radius of the fluid {:?} is not positive",
+                    handle
+                ));
+            }
+
+            if !fluid.density0.is_finite() || fluid.density0 <= na::zero::<Real>() {
+                return invalid(format!(
+                    "the rest density of the fluid {:?} is not positive",
+                    handle
+                ));
+            }
+
+            if !fluid.rest_densities.is_empty()
+                && fluid.rest_densities.len() != fluid.num_particles()
+            {
+                return invalid(format!(
+                    "the per-particle rest densities of the fluid {:?} don't match its particles",
+                    handle
+                ));
+            }
+
+            if fluid
+                .rest_densities
+                .iter()
+                .any(|density0| !density0.is_finite() || *density0 <= na::zero::<Real>())
+            {
+                return invalid(format!(
+                    "a per-particle rest density of the fluid {:?} is not positive",
+                    handle
+                ));
+            }
+        }
+
+        for (handle, open_boundary) in self.open_boundaries.iter() {
+            if open_boundary.thickness < self.h {
+                return invalid(format!(
+                    "the buffer of the open boundary {:?} is thinner than the kernel radius",
+                    handle
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Sets the fixed length of the steps performed by `LiquidWorld::step_interpolated`.
+    ///
+    /// If `None` (the default), `step_interpolated` cannot be used. Otherwise, the positions of
+    /// the fluid particles at the beginning of each fixed step are recorded into
+    /// `Fluid::previous_positions`.
+    pub fn set_fixed_timestep(&mut self, fixed_timestep: Option<Real>) {
+        self.fixed_timestep = fixed_timestep;
+        self.fixed_time_accumulator = na::zero::<Real>();
+
+        if fixed_timestep.is_none() {
+            for fluid in self.fluids.as_mut_slice() {
+                fluid.previous_positions = Vec::new();
+            }
+        }
+    }
+
+    /// The fixed length of the steps performed by `LiquidWorld::step_interpolated`, if any.
+    pub fn fixed_timestep(&self) -> Option<Real> {
+        self.fixed_timestep
+    }
+
+    /// Sets the maximum number of fixed steps performed by a single call to
+    /// `LiquidWorld::step_interpolated`.
+    ///
+    /// This prevents the simulation from falling further and further behind when a fixed step
+    /// takes longer to compute than its length. The time exceeding this number of steps is
+    /// dropped. This defaults to `5`.
+    pub fn set_max_fixed_steps_per_frame(&mut self, max_fixed_steps_per_frame: usize) {
+        self.max_fixed_steps_per_frame = max_fixed_steps_per_frame;
+    }
+
+    /// Advances the simulation by as many fixed steps as fit into the time elapsed since the last
+    /// call, for a display frame of length `frame_dt`.
+    ///
+    /// The time that is not enough to perform a complete fixed step is accumulated for the next
+    /// calls. Use `LiquidWorld::interpolated_positions` to render the fluid particles between the
+    /// last two fixed steps, so the rendering is smooth whatever the display frame rate.
+    ///
+    /// Returns the number of fixed steps performed. Panics if no fixed timestep has been set
+    /// with `LiquidWorld::set_fixed_timestep`.
+    pub fn step_interpolated(&mut self, frame_dt: Real, gravity: &Vector<Real>) -> usize {
+        self.step_interpolated_with_coupling(frame_dt, gravity, &mut ())
+    }
+
+    /// Advances the simulation by as many fixed steps as fit into the time elapsed since the last
+    /// call, taking into account coupling with an external rigid-body engine.
+    ///
+    /// See `LiquidWorld::step_interpolated`.
+    pub fn step_interpolated_with_coupling(
+        &mut self,
+        frame_dt: Real,
+        gravity: &Vector<Real>,
+        coupling: &mut impl CouplingManager,
+    ) -> usize {
+        let fixed_timestep = self
+            .fixed_timestep
+            .expect("A fixed timestep must be set before calling `step_interpolated`.");
+        let mut num_steps = 0;
+
+        self.fixed_time_accumulator += frame_dt;
+
+        while self.fixed_time_accumulator >= fixed_timestep {
+            if num_steps == self.max_fixed_steps_per_frame {
+                self.fixed_time_accumulator = na::zero::<Real>();
+                break;
+            }
+
+            for fluid in self.fluids.as_mut_slice() {
+                fluid.previous_positions.clear();
+                fluid.previous_positions.extend_from_slice(&fluid.positions);
+            }
+
+            self.step_with_coupling(fixed_timestep, gravity, coupling);
+            self.fixed_time_accumulator -= fixed_timestep;
+            num_steps += 1;
+        }
+
+        num_steps
+    }
+
+    /// The fraction of the next fixed step covered by the time accumulated by
+    /// `LiquidWorld::step_interpolated`, in `[0, 1)`.
+    ///
+    /// This is zero if no fixed timestep is set.
+    pub fn interpolation_alpha(&self) -> Real {
+        match self.fixed_timestep {
+            Some(fixed_timestep) if fixed_timestep > na::zero::<Real>() => {
+                (self.fixed_time_accumulator / fixed_timestep).min(na::one::<Real>())
+            }
+            _ => na::zero::<Real>(),
+        }
+    }
+
+    /// The positions of the particles of the given fluid, interpolated between the last two fixed
+    /// steps with `LiquidWorld::interpolation_alpha`.
+    ///
+    /// With a periodic domain, the particles are interpolated along the shortest path between
+    /// their previous and current positions. The current positions are returned for the
+    /// particles without previous positions, e.g., if no fixed step has been performed yet.
+    /// Returns `None` if the fluid does not exist.
+    pub fn interpolated_positions(&self, fluid: FluidHandle) -> Option<Vec<Point<Real>>> {
+        let fluid = self.fluids.get(fluid)?;
+        let alpha = self.interpolation_alpha();
+        let periodic_domain = self.contact_manager.periodic_domain();
+
+        if fluid.previous_positions.len() != fluid.positions.len() {
+            return Some(fluid.positions.clone());
+        }
+
+        let result = fluid
+            .positions
+            .iter()
+            .zip(fluid.previous_positions.iter())
+            .map(|(curr, prev)| {
+                let prev = periodic_domain.map_or(*prev, |domain| domain.closest_image(curr, prev));
+                prev + (curr - prev) * alpha
+            })
+            .collect();
+        Some(result)
+    }
+
+    /// Lets the fluids settle under gravity before the simulation starts.
+    ///
+    /// Fluids initialized on a lattice at their rest density have no pressure, so tall columns of
+    /// fluid visibly compress and rebound during the first frames of the simulation. This runs
+    /// `num_iterations` pressure resolutions of length `dt` under `gravity`, zeroing the fluid
+    /// velocities after each of them, so the fluids reach their hydrostatic equilibrium at rest
+    /// before the first step. Warm-started solvers (like `IISPHSolver`) also keep the resulting
+    /// pressures for their first step.
+    ///
+    /// The boundaries are not moved, and the emitters, sinks, and other objects of this world are
+    /// not updated. The time of this world is not advanced. The forces accumulated on the boundaries
+    /// are cleared.
+    pub fn initialize_hydrostatic(
+        &mut self,
+        dt: Real,
+        gravity: &Vector<Real>,
+        num_iterations: usize,
+    ) {
+        self.update_fluid_kernel_radii();
+        self.solver.init_with_fluids(self.fluids.as_slice());
+        self.timestep_manager
+            .set_pressure_substep(self.solver.max_substep(self.h, self.fluids.as_slice()));
+
+        for fluid in self.fluids.as_mut_slice() {
+            fluid.active_particles.clear();
+        }
+
+        for _ in 0..num_iterations {
+            self.timestep_manager.reset(dt);
+            self.wrap_particles();
+
+            let neighbor_search = self
+                .neighbor_search
+                .as_deref_mut()
+                .unwrap_or(&mut self.grid);
+            neighbor_search.clear();
+            neighbor_search.insert_fluids(self.fluids.as_slice());
+            self.fluid_bounds = fluid_bounds(self.fluids.as_slice());
+
+            for boundary in self.boundaries.as_mut_slice() {
+                boundary.update_density_map_particles(self.fluids.as_slice());
+                boundary.resize_forces();
+            }
+
+            self.neighbor_search
+                .as_deref_mut()
+                .unwrap_or(&mut self.grid)
+                .insert_boundaries(self.boundaries.as_slice());
+            self.solver.init_with_boundaries(self.boundaries.as_slice());
+            self.contact_manager.update_contacts(
+                &mut self.counters,
+                self.h,
+                self.fluids.as_slice(),
+                self.boundaries.as_slice(),
+                self.neighbor_search.as_deref().unwrap_or(&self.grid),
+            );
+            self.solver.evaluate_kernels(
+                self.h,
+                &mut self.contact_manager,
+                self.fluids.as_slice(),
+                self.boundaries.as_slice(),
+            );
+            self.solver.compute_densities(
+                &self.contact_manager,
+                self.fluids.as_slice(),
+                self.boundaries.as_mut_slice(),
+            );
+            self.timestep_manager
+                .predict_substep(self.fluids.as_slice());
+            self.solver.step(
+                &mut self.counters,
+                &mut self.timestep_manager,
+                gravity,
+                &mut self.contact_manager,
+                self.h,
+                self.fluids.as_mut_slice(),
+                self.boundaries.as_slice(),
+                &mut |_, _, _| {},
+            );
+
+            for fluid in self.fluids.as_mut_slice() {
+                fluid
+                    .velocities
+                    .iter_mut()
+                    .for_each(|v| v.fill(na::zero::<Real>()));
+                fluid
+                    .render_velocities
+                    .iter_mut()
+                    .for_each(|v| v.fill(na::zero::<Real>()));
+            }
+        }
+
+        for boundary in self.boundaries.as_mut_slice() {
+            boundary.clear_forces(false);
+        }
+
+        self.counters.reset();
     }
 
     /// Add a fluid to the liquid world.
@@ -197,49 +992,1498 @@ impl LiquidWorld {
         &mut self.boundaries
     }
 
-    /// The SPH kernel radius.
-    pub fn h(&self) -> Real {
-        self.h
+    /// Adds particles to the given fluid.
+    ///
+    /// See `Fluid::add_particles` for the meaning of the arguments. Unlike `Fluid::add_particles`,
+    /// the buffers of the pressure solver are resized right away, keeping the state of the existing
+    /// particles (e.g., the pressures used to warm-start the `IISPHSolver`), so they match the
+    /// particles of the fluids before the next step. Particles already marked for deletion are
+    /// removed too.
+    ///
+    /// Returns the indices of the new particles, or `None` if the fluid does not exist.
+    pub fn add_particles(
+        &mut self,
+        fluid: FluidHandle,
+        positions: &[Point<Real>],
+        velocities: Option<&[Vector<Real>]>,
+        volumes: Option<&[Real]>,
+    ) -> Option<Range<usize>> {
+        self.fluids
+            .get_mut(fluid)?
+            .add_particles(positions, velocities, volumes);
+
+        // The solver buffers can only be resized once the particles marked for deletion are
+        // removed, like at the beginning of a step.
+        self.solver.init_with_fluids(self.fluids.as_slice());
+
+        for fluid in self.fluids.as_mut_slice() {
+            if fluid.num_deleted_particles() != 0 {
+                self.contact_manager.invalidate_neighbor_lists();
+            }
+
+            fluid.apply_particles_removal();
+        }
+
+        // The new particles are still the last ones after the removal.
+        let num_particles = self.fluids.get(fluid)?.num_particles();
+        Some(num_particles - positions.len()..num_particles)
     }
 
-    /// The radius of every particle on this liquid world.
-    pub fn particle_radius(&self) -> Real {
-        self.particle_radius
+    /// Removes from the given fluid every particle for which `f` returns `false`.
+    ///
+    /// The closure is given the index, position, and velocity of each particle. Unlike
+    /// `Fluid::retain_particles`, the particles are removed immediately, together with the
+    /// corresponding entries of the solver buffers, so the indices of the remaining particles
+    /// can be used right away. Particles already marked for deletion are removed too.
+    ///
+    /// Returns the number of particles for which `f` returned `false`, or `None` if the fluid
+    /// does not exist.
+    pub fn retain_particles(
+        &mut self,
+        fluid: FluidHandle,
+        f: impl FnMut(usize, &Point<Real>, &Vector<Real>) -> bool,
+    ) -> Option<usize> {
+        let num_deleted = self.fluids.get_mut(fluid)?.retain_particles(f);
+
+        // Compact the solver buffers with the deletion masks before they are cleared.
+        self.solver.init_with_fluids(self.fluids.as_slice());
+
+        for fluid in self.fluids.as_mut_slice() {
+            if fluid.num_deleted_particles() != 0 {
+                // The remaining particles are shifted, so the neighbor lists are stale.
+                self.contact_manager.invalidate_neighbor_lists();
+            }
+
+            fluid.apply_particles_removal();
+        }
+
+        Some(num_deleted)
     }
 
-    /// The set of particles potentially intersecting the given AABB.
-    #[cfg(feature = "parry")]
-    pub fn particles_intersecting_aabb<'a>(
-        &'a self,
-        aabb: Aabb,
-    ) -> impl Iterator<Item = ParticleId> + 'a {
-        self.hgrid
-            .cells_intersecting_aabb(&aabb.mins, &aabb.maxs)
-            .flat_map(|e| e.1)
-            .filter_map(move |entry| match entry {
-                HGridEntry::FluidParticle(fid, pid) => {
-                    let (fluid, handle) = self.fluids.get_from_contiguous_index(*fid)?;
-                    let pt = fluid.positions[*pid];
+    /// The user data attached to the given particle, see `Fluid::user_data`.
+    ///
+    /// Returns `None` for boundary particles and for particles of a fluid that does not exist.
+    /// This is typically used on the results of region queries like `Self::particles_in_aabb`.
+    pub fn particle_user_data(&self, particle: &ParticleId) -> Option<u128> {
+        match particle {
+            ParticleId::FluidParticle(handle, i) => self
+                .fluids
+                .get(*handle)
+                .filter(|fluid| *i < fluid.num_particles())
+                .map(|fluid| fluid.particle_user_data(*i)),
+            ParticleId::BoundaryParticle(..) => None,
+        }
+    }
 
-                    // FIXME: use `distance_to_local_point` once it's supported.
-                    let id = &Isometry::identity();
-                    if aabb.distance_to_point(id, &pt, true) < self.particle_radius {
-                        Some(ParticleId::FluidParticle(handle, *pid))
-                    } else {
-                        None
-                    }
-                }
-                HGridEntry::BoundaryParticle(bid, pid) => {
-                    let (boundary, handle) = self.boundaries.get_from_contiguous_index(*bid)?;
-                    let pt = boundary.positions[*pid]; // FIXME: use `distance_to_local_point` once it's supported.
-                    let id = &Isometry::identity();
-                    if aabb.distance_to_point(id, &pt, true) < self.particle_radius {
-                        Some(ParticleId::BoundaryParticle(handle, *pid))
-                    } else {
-                        None
-                    }
-                }
-            })
+    /// Add a flow sensor to the liquid world.
+    pub fn add_flow_sensor(&mut self, sensor: FlowSensor) -> FlowSensorHandle {
+        self.flow_sensors.insert(sensor)
+    }
+
+    /// Remove a flow sensor from the liquid world.
+    pub fn remove_flow_sensor(&mut self, handle: FlowSensorHandle) -> Option<FlowSensor> {
+        self.flow_sensors.remove(handle)
+    }
+
+    /// The set of flow sensors on this liquid world.
+    pub fn flow_sensors(&self) -> &FlowSensorSet {
+        &self.flow_sensors
+    }
+
+    /// The mutable set of flow sensors on this liquid world.
+    pub fn flow_sensors_mut(&mut self) -> &mut FlowSensorSet {
+        &mut self.flow_sensors
+    }
+
+    /// Add an emitter to the liquid world.
+    pub fn add_emitter(&mut self, emitter: Emitter) -> EmitterHandle {
+        self.emitters.insert(emitter)
+    }
+
+    /// Remove an emitter from the liquid world.
+    ///
+    /// The particles already spawned by this emitter are not removed.
+    pub fn remove_emitter(&mut self, handle: EmitterHandle) -> Option<Emitter> {
+        self.emitters.remove(handle)
+    }
+
+    /// The set of emitters on this liquid world.
+    pub fn emitters(&self) -> &EmitterSet {
+        &self.emitters
+    }
+
+    /// The mutable set of emitters on this liquid world.
+    pub fn emitters_mut(&mut self) -> &mut EmitterSet {
+        &mut self.emitters
+    }
+
+    /// Add a sink to the liquid world.
+    pub fn add_sink(&mut self, sink: Sink) -> SinkHandle {
+        self.sinks.insert(sink)
+    }
+
+    /// Remove a sink from the liquid world.
+    pub fn remove_sink(&mut self, handle: SinkHandle) -> Option<Sink> {
+        self.sinks.remove(handle)
+    }
+
+    /// The set of sinks on this liquid world.
+    pub fn sinks(&self) -> &SinkSet {
+        &self.sinks
+    }
+
+    /// The mutable set of sinks on this liquid world.
+    pub fn sinks_mut(&mut self) -> &mut SinkSet {
+        &mut self.sinks
+    }
+
+    /// Add a sponge layer to the liquid world.
+    pub fn add_sponge_layer(&mut self, layer: SpongeLayer) -> SpongeLayerHandle {
+        self.sponge_layers.insert(layer)
+    }
+
+    /// Remove a sponge layer from the liquid world.
+    pub fn remove_sponge_layer(&mut self, handle: SpongeLayerHandle) -> Option<SpongeLayer> {
+        self.sponge_layers.remove(handle)
+    }
+
+    /// The set of sponge layers on this liquid world.
+    pub fn sponge_layers(&self) -> &SpongeLayerSet {
+        &self.sponge_layers
+    }
+
+    /// The mutable set of sponge layers on this liquid world.
+    pub fn sponge_layers_mut(&mut self) -> &mut SpongeLayerSet {
+        &mut self.sponge_layers
+    }
+
+    /// Add an active region to the liquid world.
+    ///
+    /// As soon as the world has an enabled active region, only the fluid particles inside of the
+    /// active regions are fully simulated (see `ActiveRegion`).
+    pub fn add_active_region(&mut self, region: ActiveRegion) -> ActiveRegionHandle {
+        self.active_regions.insert(region)
+    }
+
+    /// Remove an active region from the liquid world.
+    pub fn remove_active_region(&mut self, handle: ActiveRegionHandle) -> Option<ActiveRegion> {
+        self.active_regions.remove(handle)
+    }
+
+    /// The set of active regions on this liquid world.
+    pub fn active_regions(&self) -> &ActiveRegionSet {
+        &self.active_regions
+    }
+
+    /// The mutable set of active regions on this liquid world.
+    pub fn active_regions_mut(&mut self) -> &mut ActiveRegionSet {
+        &mut self.active_regions
+    }
+
+    /// Sets how the fluid particles outside of all the active regions are simulated.
+    ///
+    /// This defaults to `InactiveParticleModel::Frozen`.
+    pub fn set_inactive_particle_model(&mut self, model: InactiveParticleModel) {
+        self.inactive_particle_model = model;
+    }
+
+    /// How the fluid particles outside of all the active regions are simulated.
+    pub fn inactive_particle_model(&self) -> InactiveParticleModel {
+        self.inactive_particle_model
+    }
+
+    /// Add an open boundary to the liquid world.
+    ///
+    /// The thickness of its buffer zone must be at least as large as the kernel radius.
+    pub fn add_open_boundary(&mut self, open_boundary: OpenBoundary) -> OpenBoundaryHandle {
+        assert!(
+            open_boundary.thickness >= self.h,
+            "The buffer of an open boundary must be at least as thick as the kernel radius."
+        );
+        self.open_boundaries.insert(open_boundary)
+    }
+
+    /// Remove an open boundary from the liquid world.
+    ///
+    /// The particles inside of its buffer zone become regular fluid particles.
+    pub fn remove_open_boundary(&mut self, handle: OpenBoundaryHandle) -> Option<OpenBoundary> {
+        self.open_boundaries.remove(handle)
+    }
+
+    /// The set of open boundaries on this liquid world.
+    pub fn open_boundaries(&self) -> &OpenBoundarySet {
+        &self.open_boundaries
+    }
+
+    /// The mutable set of open boundaries on this liquid world.
+    pub fn open_boundaries_mut(&mut self) -> &mut OpenBoundarySet {
+        &mut self.open_boundaries
+    }
+
+    // Generates the particles of the inflows, and removes the particles that left the outflows.
+    fn feed_open_boundaries(&mut self) {
+        for open_boundary in self.open_boundaries.as_mut_slice() {
+            if let Some(fluid) = self.fluids.get_mut(open_boundary.fluid) {
+                open_boundary.feed(fluid, self.time);
+            }
+        }
+    }
+
+    /// Add a force field to the liquid world.
+    pub fn add_force_field(&mut self, force_field: ForceField) -> ForceFieldHandle {
+        self.force_fields.insert(force_field)
+    }
+
+    /// Remove a force field from the liquid world.
+    pub fn remove_force_field(&mut self, handle: ForceFieldHandle) -> Option<ForceField> {
+        self.force_fields.remove(handle)
+    }
+
+    /// The set of force fields on this liquid world.
+    pub fn force_fields(&self) -> &ForceFieldSet {
+        &self.force_fields
+    }
+
+    /// The mutable set of force fields on this liquid world.
+    pub fn force_fields_mut(&mut self) -> &mut ForceFieldSet {
+        &mut self.force_fields
+    }
+
+    /// Add a hook invoked at each substep to the liquid world.
+    pub fn add_substep_hook(&mut self, hook: SubstepHook) -> SubstepHookHandle {
+        self.substep_hooks.insert(hook)
+    }
+
+    /// Remove a substep hook from the liquid world.
+    pub fn remove_substep_hook(&mut self, handle: SubstepHookHandle) -> Option<SubstepHook> {
+        self.substep_hooks.remove(handle)
+    }
+
+    /// The set of substep hooks on this liquid world.
+    pub fn substep_hooks(&self) -> &SubstepHookSet {
+        &self.substep_hooks
+    }
+
+    /// The mutable set of substep hooks on this liquid world.
+    pub fn substep_hooks_mut(&mut self) -> &mut SubstepHookSet {
+        &mut self.substep_hooks
+    }
+
+    /// Sets the rotating reference frame in which the simulation is carried out.
+    ///
+    /// If `None` (the default), the simulation is carried out in an inertial frame.
+    pub fn set_rotating_frame(&mut self, frame: Option<RotatingFrame>) {
+        self.rotating_frame = frame;
+    }
+
+    /// The rotating reference frame in which the simulation is carried out, if any.
+    pub fn rotating_frame(&self) -> Option<&RotatingFrame> {
+        self.rotating_frame.as_ref()
+    }
+
+    /// The mutable rotating reference frame in which the simulation is carried out, if any.
+    pub fn rotating_frame_mut(&mut self) -> Option<&mut RotatingFrame> {
+        self.rotating_frame.as_mut()
+    }
+
+    // Adds the accelerations of the force fields and of the rotating frame to the fluid particles.
+    fn apply_force_fields(&mut self) {
+        if let Some(frame) = &self.rotating_frame {
+            for fluid in self.fluids.as_mut_slice() {
+                frame.apply(fluid);
+            }
+        }
+
+        for force_field in self.force_fields.as_slice() {
+            for fluid_id in 0..self.fluids.len() {
+                let handle = self.fluids.get_from_contiguous_index(fluid_id).unwrap().1;
+
+                if force_field.affects(handle) {
+                    force_field.apply(&mut self.fluids.as_mut_slice()[fluid_id], self.time);
+                }
+            }
+        }
+    }
+
+    // Marks the fluid particles inside of the active regions, and records the state of the other
+    // particles. All the particles are active if there is no enabled active region.
+    fn update_active_particles(&mut self) {
+        let has_active_regions = self.active_regions.iter().any(|(_, region)| region.enabled);
+        self.inactive_particles
+            .resize(self.fluids.len(), Vec::new());
+
+        for fluid_id in 0..self.fluids.len() {
+            let handle = self.fluids.get_from_contiguous_index(fluid_id).unwrap().1;
+            let fluid = &mut self.fluids.as_mut_slice()[fluid_id];
+            let inactive_particles = &mut self.inactive_particles[fluid_id];
+            fluid.active_particles.clear();
+            inactive_particles.clear();
+
+            if !has_active_regions {
+                continue;
+            }
+
+            let regions: Vec<_> = self
+                .active_regions
+                .iter()
+                .filter(|(_, region)| region.affects(handle))
+                .map(|(_, region)| &region.shape)
+                .collect();
+
+            for (i, pos) in fluid.positions.iter().enumerate() {
+                let is_active = regions.iter().any(|shape| shape.contains_point(pos));
+                fluid.active_particles.push(is_active);
+
+                if !is_active {
+                    inactive_particles.push((i, *pos, fluid.velocities[i]));
+                }
+            }
+        }
+    }
+
+    // Overwrites the motion computed by the solver for the inactive particles with the motion
+    // given by the inactive particle model.
+    fn integrate_inactive_particles(&mut self, gravity: &Vector<Real>) {
+        let dt = self.timestep_manager.dt();
+
+        for (fluid, inactive_particles) in self
+            .fluids
+            .as_mut_slice()
+            .iter_mut()
+            .zip(self.inactive_particles.iter())
+        {
+            let gravity = fluid.effective_gravity(gravity);
+
+            for (i, pos, vel) in inactive_particles {
+                match self.inactive_particle_model {
+                    InactiveParticleModel::Frozen => {
+                        fluid.positions[*i] = *pos;
+                        fluid.velocities[*i] = *vel;
+                    }
+                    InactiveParticleModel::Ballistic => {
+                        fluid.velocities[*i] = vel + gravity * dt;
+                        fluid.positions[*i] = pos + fluid.velocities[*i] * dt;
+                    }
+                }
+            }
+        }
+    }
+
+    // Damps the velocities of the particles inside of the sponge layers.
+    fn absorb_waves(&mut self, dt: Real) {
+        for layer in self.sponge_layers.as_slice() {
+            for fluid_id in 0..self.fluids.len() {
+                let handle = self.fluids.get_from_contiguous_index(fluid_id).unwrap().1;
+
+                if layer.affects(handle) {
+                    layer.apply(&mut self.fluids.as_mut_slice()[fluid_id], dt);
+                }
+            }
+        }
+    }
+
+    // Marks the particles inside of the sinks for deletion.
+    fn drain_particles(&mut self, dt: Real) {
+        for sink in self.sinks.as_mut_slice() {
+            let mut allowed = sink.begin_step(dt);
+
+            for fluid_id in 0..self.fluids.len() {
+                if allowed == 0 {
+                    break;
+                }
+
+                let handle = self.fluids.get_from_contiguous_index(fluid_id).unwrap().1;
+
+                if sink.affects(handle) {
+                    let fluid = &mut self.fluids.as_mut_slice()[fluid_id];
+                    allowed -= sink.remove_particles(fluid, allowed);
+                }
+            }
+        }
+    }
+
+    // Spawns the particles of all the emitters, sharing the particle budget between them.
+    fn emit_particles(&mut self, dt: Real) {
+        if self.emitters.len() == 0 {
+            return;
+        }
+
+        let requests: Vec<_> = self
+            .emitters
+            .as_mut_slice()
+            .iter_mut()
+            .map(|emitter| emitter.request(dt))
+            .collect();
+        let granted = self.arbitrate_emissions(&requests);
+
+        for ((emitter, request), num_granted) in self
+            .emitters
+            .as_mut_slice()
+            .iter_mut()
+            .zip(requests.iter())
+            .zip(granted)
+        {
+            let (positions, velocities) = emitter.emit(dt, request.num_particles, num_granted);
+
+            if let Some(fluid) = self.fluids.get_mut(emitter.fluid) {
+                let first = fluid.num_particles();
+                fluid.add_particles(&positions, Some(&velocities), None);
+
+                if let Some(lifetime) = emitter.lifetime {
+                    fluid.set_particle_lifetimes(first, &vec![lifetime; positions.len()]);
+                }
+
+                if let Some(user_data) = emitter.user_data {
+                    fluid.set_particle_user_data(first, &vec![user_data; positions.len()]);
+                }
+            }
+        }
+    }
+
+    /// Sets the user-defined filter of the contacts between particles.
+    ///
+    /// This is applied in addition to the interaction groups of the fluids and boundaries.
+    /// There is no filter by default.
+    pub fn set_contact_filter(&mut self, filter: Option<Box<dyn ContactFilter>>) {
+        self.contact_manager.set_contact_filter(filter);
+    }
+
+    /// Sets the periodic domain of this liquid world.
+    ///
+    /// The fluid particles leaving the domain along one of its periodic axes re-enter it from the
+    /// opposite face, and the particles close to opposite faces interact with each other. The
+    /// domain must be at least twice as large as the kernel radius along its periodic axes.
+    ///
+    /// The pressure solvers and the forces relying on kernel gradients account for the periodicity.
+    /// Non-pressure forces measuring the distance between two particles (surface tension,
+    /// elasticity, etc.) don't. The domain is not periodic (`None`) by default.
+    pub fn set_periodic_domain(&mut self, domain: Option<PeriodicDomain>) {
+        if let Some(domain) = &domain {
+            let period = domain.period();
+            assert!(
+                (0..period.len()).all(|k| !domain.periodic_axes[k]
+                    || period[k] >= self.h * na::convert::<_, Real>(2.0)),
+                "The periodic domain must be at least twice as large as the kernel radius."
+            );
+        }
+
+        self.contact_manager.set_periodic_domain(domain);
+        self.wrap_particles();
+    }
+
+    /// The periodic domain of this liquid world, if any.
+    pub fn periodic_domain(&self) -> Option<&PeriodicDomain> {
+        self.contact_manager.periodic_domain()
+    }
+
+    /// Enables or disables the deterministic mode of this liquid world.
+    ///
+    /// In deterministic mode, the same initial state and the same sequence of calls yield
+    /// bit-identical fluid states across runs and whatever the number of threads used by the
+    /// `parallel` feature, as required, e.g., by lockstep multiplayer:
+    /// - the contacts of each particle are sorted (see `ContactManager::set_contact_sorting`) so
+    ///   the contributions of the neighbors are accumulated in a fixed order;
+    /// - the parallel reductions, e.g., the error estimates of the pressure solvers, always sum
+    ///   their elements in a fixed order, whether or not this mode is enabled;
+    /// - the random sampling of the emitters and of the diffuse particle solver relies on
+    ///   generators with fixed default seeds, which can be set with `Emitter::with_seed` and
+    ///   `DiffuseParticleSolver::with_seed`.
+    ///
+    /// The forces applied by the fluids to the boundaries are accumulated concurrently by the
+    /// `parallel` feature, so `Boundary::accumulated_forces` and the forces transmitted through a
+    /// coupling manager may differ by rounding errors between runs. Disable the `parallel` feature
+    /// if the coupled bodies must be reproducible too. Results are not reproducible across
+    /// platforms or compiler versions that evaluate floating point operations differently. This
+    /// mode is disabled by default.
+    pub fn set_deterministic(&mut self, enabled: bool) {
+        self.contact_manager.set_contact_sorting(enabled);
+    }
+
+    /// Whether the deterministic mode of this liquid world is enabled.
+    pub fn is_deterministic(&self) -> bool {
+        self.contact_manager.sorts_contacts()
+    }
+
+    /// Sets the spacial acceleration structure used to find the particles close to each other.
+    ///
+    /// The structure is cleared and filled with all the particles at the beginning of each
+    /// substep, so it remains empty until the next substep. `None` restores the default
+    /// `MultiResolutionGrid`, with one grid level per fluid kernel radius. The structure is not serialized with
+    /// this liquid world, and must be set again after deserialization.
+    pub fn set_neighbor_search(&mut self, neighbor_search: Option<Box<dyn NeighborSearch>>) {
+        self.neighbor_search = neighbor_search;
+    }
+
+    /// The spacial acceleration structure used to find the particles close to each other.
+    pub fn neighbor_search(&self) -> &dyn NeighborSearch {
+        self.neighbor_search.as_deref().unwrap_or(&self.grid)
+    }
+
+    /// Sets the skin distance of the neighbor lists reused across substeps.
+    ///
+    /// The neighbor lists are only recomputed when a particle moved by more than half the skin
+    /// distance, or when particles are added or removed. See
+    /// `ContactManager::set_neighbor_list_skin` for details. This is disabled (`None`) by default.
+    pub fn set_neighbor_list_skin(&mut self, skin: Option<Real>) {
+        self.contact_manager.set_neighbor_list_skin(skin);
+    }
+
+    /// The skin distance of the neighbor lists reused across substeps, if enabled.
+    pub fn neighbor_list_skin(&self) -> Option<Real> {
+        self.contact_manager.neighbor_list_skin()
+    }
+
+    /// Sets the number of substeps after which the fluid particles are re-sorted.
+    ///
+    /// As the particles mix, the neighbors of a particle get scattered in memory, which slows down
+    /// the neighbor accesses of large simulations. Periodically sorting the particles of each
+    /// fluid along a Z-order curve keeps the neighbors close in memory. The sort is performed at
+    /// the beginning of the first step following `interval` substeps since the last sort, so the
+    /// particle indices change between two steps: use `Fluid::user_data` to keep track of
+    /// individual particles. This is disabled (`None`) by default.
+    pub fn set_particle_sort_interval(&mut self, interval: Option<usize>) {
+        self.particle_sort_interval = interval;
+    }
+
+    /// The number of substeps after which the fluid particles are re-sorted, if enabled.
+    pub fn particle_sort_interval(&self) -> Option<usize> {
+        self.particle_sort_interval
+    }
+
+    // Sorts the particles of each fluid along a Z-order curve, along with the per-particle state
+    // of the pressure solver.
+    fn sort_particles(&mut self) {
+        self.counters.cd.particle_sorting_time.resume();
+
+        for (fluid_id, fluid) in self.fluids.as_mut_slice().iter_mut().enumerate() {
+            let order = crate::z_order::compute_points_z_order(&fluid.positions);
+            fluid.apply_permutation(&order);
+            self.solver.apply_permutation(fluid_id, &order);
+        }
+
+        self.contact_manager.invalidate_neighbor_lists();
+        self.counters.cd.particle_sorting_time.pause();
+    }
+
+    // Moves the fluid particles back inside of the periodic domain.
+    fn wrap_particles(&mut self) {
+        if let Some(domain) = self.contact_manager.periodic_domain() {
+            for fluid in self.fluids.as_mut_slice() {
+                fluid
+                    .positions
+                    .iter_mut()
+                    .for_each(|pt| domain.wrap_point(pt));
+            }
+        }
+    }
+
+    /// Replaces the pressure solver of this liquid world.
+    pub fn set_pressure_solver(&mut self, solver: impl PressureSolver + Send + Sync + 'static) {
+        self.solver = Box::new(solver);
+    }
+
+    /// The pressure solver of this liquid world, if it has the type `S`.
+    pub fn pressure_solver<S: PressureSolver>(&self) -> Option<&S> {
+        (&*self.solver as &dyn Any).downcast_ref()
+    }
+
+    /// The mutable pressure solver of this liquid world, if it has the type `S`.
+    pub fn pressure_solver_mut<S: PressureSolver>(&mut self) -> Option<&mut S> {
+        (&mut *self.solver as &mut dyn Any).downcast_mut()
+    }
+
+    // The pressure solver of a deserialized liquid world, until it is replaced by the user.
+    #[cfg(feature = "serde-serialize")]
+    fn default_pressure_solver() -> Box<dyn PressureSolver + Send + Sync> {
+        Box::new(DFSPHSolver::<CubicSplineKernel, CubicSplineKernel>::new())
+    }
+
+    /// Takes a binary snapshot of the state of the particles of this liquid world.
+    ///
+    /// The snapshot contains the simulation time, and the per-particle buffers of the fluids, the
+    /// boundaries, and the diffuse particles. These buffers are copied in bulk, making snapshots
+    /// cheap enough for frequent autosaving. The rest of the world (the parameters of the fluids
+    /// and solvers, the emitters, the non-pressure forces, etc.) is not part of the snapshot. Use
+    /// the `serde-serialize` feature to serialize the whole liquid world instead.
+    ///
+    /// The snapshot can be restored with `Self::restore` into this liquid world, or into any
+    /// liquid world with the same fluids and boundaries, in the same order.
+    pub fn snapshot(&self) -> Vec<u8> {
+        crate::snapshot::write(
+            self.time,
+            self.fluids.as_slice(),
+            self.boundaries.as_slice(),
+            &self.diffuse_particles,
+            None,
+        )
+    }
+
+    /// Takes a binary snapshot of the state of the particles of this liquid world, compressed
+    /// with the deflate algorithm.
+    ///
+    /// The compression level ranges from `0` (no compression) to `10` (slowest). See
+    /// `Self::snapshot` for details about the content of the snapshot.
+    #[cfg(feature = "compression")]
+    pub fn compressed_snapshot(&self, level: u8) -> Vec<u8> {
+        crate::snapshot::write(
+            self.time,
+            self.fluids.as_slice(),
+            self.boundaries.as_slice(),
+            &self.diffuse_particles,
+            Some(level),
+        )
+    }
+
+    /// Restores the state of the particles of this liquid world from a snapshot taken with
+    /// `Self::snapshot` or `Self::compressed_snapshot`.
+    ///
+    /// The number of particles of each fluid is restored too. The state of the non-pressure
+    /// forces and of the pressure solver attached to the particles (e.g. the rest shape of an
+    /// elastic fluid) is not part of the snapshot. The liquid world is left unchanged if an error
+    /// is returned.
+    pub fn restore(&mut self, snapshot: &[u8]) -> Result<(), SnapshotError> {
+        crate::snapshot::read(
+            snapshot,
+            &mut self.time,
+            self.fluids.as_mut_slice(),
+            self.boundaries.as_mut_slice(),
+            &mut self.diffuse_particles,
+        )?;
+        self.fixed_time_accumulator = na::zero::<Real>();
+        self.nsubsteps_since_sort = 0;
+        self.contact_manager.invalidate_neighbor_lists();
+        Ok(())
+    }
+
+    /// Sets the solver used to make the fluid velocities divergence-free before each substep.
+    ///
+    /// This is disabled (`None`) by default. Enabling it reduces the volume drift of pressure solvers
+    /// like IISPH or WCSPH over long simulations, at the cost of additional iterations.
+    pub fn set_divergence_solver(&mut self, divergence_solver: Option<DivergenceSolver>) {
+        self.divergence_solver = divergence_solver;
+    }
+
+    /// The solver used to make the fluid velocities divergence-free before each substep, if enabled.
+    pub fn divergence_solver_mut(&mut self) -> Option<&mut DivergenceSolver> {
+        self.divergence_solver.as_mut()
+    }
+
+    /// Sets the particle shifting applied after each substep to keep the particle distribution uniform.
+    ///
+    /// This is disabled (`None`) by default.
+    pub fn set_particle_shifting(&mut self, particle_shifting: Option<ParticleShifting>) {
+        self.particle_shifting = particle_shifting;
+    }
+
+    /// The particle shifting applied after each substep, if enabled.
+    pub fn particle_shifting_mut(&mut self) -> Option<&mut ParticleShifting> {
+        self.particle_shifting.as_mut()
+    }
+
+    /// Sets the guards applied to the fluid particles after the integration of each substep to
+    /// prevent a single unstable particle from blowing up the whole simulation.
+    ///
+    /// This is disabled (`None`) by default.
+    pub fn set_simulation_guards(&mut self, guards: Option<SimulationGuards>) {
+        self.simulation_guards = guards;
+    }
+
+    /// The guards applied to the fluid particles after each substep, if enabled.
+    pub fn simulation_guards(&self) -> Option<&SimulationGuards> {
+        self.simulation_guards.as_ref()
+    }
+
+    /// The mutable guards applied to the fluid particles after each substep, if enabled.
+    pub fn simulation_guards_mut(&mut self) -> Option<&mut SimulationGuards> {
+        self.simulation_guards.as_mut()
+    }
+
+    /// Sets the solver used to diffuse the temperatures of the fluid particles after each substep.
+    ///
+    /// This is disabled (`None`) by default, in which case the temperatures of the fluids never change.
+    pub fn set_heat_solver(&mut self, heat_solver: Option<HeatSolver>) {
+        self.heat_solver = heat_solver;
+    }
+
+    /// The solver used to diffuse the temperatures of the fluid particles, if enabled.
+    pub fn heat_solver_mut(&mut self) -> Option<&mut HeatSolver> {
+        self.heat_solver.as_mut()
+    }
+
+    /// Sets the solver used to diffuse the concentrations of the fluid particles after each substep.
+    ///
+    /// This is disabled (`None`) by default, in which case miscible fluids never mix.
+    pub fn set_concentration_solver(&mut self, concentration_solver: Option<ConcentrationSolver>) {
+        self.concentration_solver = concentration_solver;
+    }
+
+    /// The solver used to diffuse the concentrations of the fluid particles, if enabled.
+    pub fn concentration_solver_mut(&mut self) -> Option<&mut ConcentrationSolver> {
+        self.concentration_solver.as_mut()
+    }
+
+    /// Sets the solver used to generate and advect diffuse particles (spray, foam, and bubbles)
+    /// after each substep.
+    ///
+    /// This is disabled (`None`) by default, in which case no diffuse particle is generated.
+    /// Disabling it does not remove the existing diffuse particles, but they stop moving.
+    pub fn set_diffuse_particle_solver(
+        &mut self,
+        diffuse_particle_solver: Option<DiffuseParticleSolver>,
+    ) {
+        self.diffuse_particle_solver = diffuse_particle_solver;
+    }
+
+    /// The solver used to generate and advect diffuse particles, if enabled.
+    pub fn diffuse_particle_solver_mut(&mut self) -> Option<&mut DiffuseParticleSolver> {
+        self.diffuse_particle_solver.as_mut()
+    }
+
+    /// The diffuse particles (spray, foam, and bubbles) of this world, meant for rendering.
+    pub fn diffuse_particles(&self) -> &DiffuseParticles {
+        &self.diffuse_particles
+    }
+
+    /// Mutable reference to the diffuse particles of this world.
+    pub fn diffuse_particles_mut(&mut self) -> &mut DiffuseParticles {
+        &mut self.diffuse_particles
+    }
+
+    /// Sets the global limit on the number of fluid particles of this world.
+    ///
+    /// The budget is enforced by `Self::arbitrate_emissions`. There is no limit by default.
+    pub fn set_particle_budget(&mut self, budget: Option<ParticleBudget>) {
+        self.particle_budget = budget;
+    }
+
+    /// The global limit on the number of fluid particles of this world, if any.
+    pub fn particle_budget(&self) -> Option<&ParticleBudget> {
+        self.particle_budget.as_ref()
+    }
+
+    /// The total number of fluid particles on this world.
+    pub fn num_fluid_particles(&self) -> usize {
+        self.fluids.iter().map(|f| f.1.num_particles()).sum()
+    }
+
+    /// Computes how many particles each of the given emission requests is allowed to add.
+    ///
+    /// All the emitters of a frame should be arbitrated with a single call so that the
+    /// particle budget is shared according to its arbitration policy, instead of being consumed
+    /// by whichever emitter runs first. Every request is fully granted if there is no budget.
+    pub fn arbitrate_emissions(&self, requests: &[EmissionRequest]) -> Vec<usize> {
+        match &self.particle_budget {
+            Some(budget) => budget.arbitrate(self.num_fluid_particles(), requests),
+            None => requests.iter().map(|r| r.num_particles).collect(),
+        }
+    }
+
+    /// Statistics about the work performed by the solver during the last time step.
+    pub fn solver_statistics(&self) -> SolverStatistics {
+        SolverStatistics::from_counters(&self.counters)
+    }
+
+    /// Enables the validation of the state of the particles at each substep.
+    ///
+    /// The result of the validation of the last substep is given by
+    /// `LiquidWorld::particle_diagnostics`. This is disabled by default.
+    pub fn set_validation_enabled(&mut self, enabled: bool) {
+        self.validation_enabled = enabled;
+
+        if !enabled {
+            self.particle_diagnostics = ParticleDiagnostics::default();
+        }
+    }
+
+    /// Whether the validation of the state of the particles is enabled.
+    pub fn validation_enabled(&self) -> bool {
+        self.validation_enabled
+    }
+
+    /// The diagnostics collected during the last substep, if the validation is enabled.
+    pub fn particle_diagnostics(&self) -> &ParticleDiagnostics {
+        &self.particle_diagnostics
+    }
+
+    fn collect_particle_diagnostics(&mut self) {
+        let diagnostics = &mut self.particle_diagnostics;
+        diagnostics.isolated_particles.clear();
+
+        for (fluid_id, (fluid_fluid_contacts, fluid_boundary_contacts)) in self
+            .contact_manager
+            .fluid_fluid_contacts
+            .iter()
+            .zip(self.contact_manager.fluid_boundary_contacts.iter())
+            .enumerate()
+        {
+            let handle = self.fluids.get_from_contiguous_index(fluid_id).unwrap().1;
+
+            for i in 0..fluid_fluid_contacts.contacts().len() {
+                if fluid_fluid_contacts
+                    .particle_contacts(i)
+                    .read()
+                    .unwrap()
+                    .is_empty()
+                    && fluid_boundary_contacts
+                        .particle_contacts(i)
+                        .read()
+                        .unwrap()
+                        .is_empty()
+                {
+                    diagnostics
+                        .isolated_particles
+                        .push(ParticleId::FluidParticle(handle, i));
+                }
+            }
+        }
+
+        for (boundary_id, contacts) in self
+            .contact_manager
+            .boundary_boundary_contacts
+            .iter()
+            .enumerate()
+        {
+            let (boundary, handle) = self
+                .boundaries
+                .get_from_contiguous_index(boundary_id)
+                .unwrap();
+
+            if boundary.density_map().is_some() {
+                continue;
+            }
+
+            for i in 0..contacts.contacts().len() {
+                if contacts.particle_contacts(i).read().unwrap().is_empty() {
+                    diagnostics
+                        .isolated_particles
+                        .push(ParticleId::BoundaryParticle(handle, i));
+                }
+            }
+        }
+    }
+
+    /// Enables the computation of the energy, mass, momentum, and density errors of the fluids at
+    /// the end of each step.
+    ///
+    /// The result of the last step is given by `LiquidWorld::conservation_diagnostics`, and is
+    /// also recorded into `LiquidWorld::conservation_history` if its capacity is not zero. This
+    /// is disabled by default.
+    pub fn set_conservation_diagnostics_enabled(&mut self, enabled: bool) {
+        self.conservation_diagnostics_enabled = enabled;
+
+        if !enabled {
+            self.conservation_diagnostics = None;
+        }
+    }
+
+    /// Whether the conservation diagnostics are computed at the end of each step.
+    pub fn conservation_diagnostics_enabled(&self) -> bool {
+        self.conservation_diagnostics_enabled
+    }
+
+    /// The conservation diagnostics of the last step, if they are enabled.
+    ///
+    /// The density errors are computed from the densities of the last substep (see
+    /// `LiquidWorld::particle_densities`).
+    pub fn conservation_diagnostics(&self) -> Option<&ConservationDiagnostics> {
+        self.conservation_diagnostics.as_ref()
+    }
+
+    /// Sets the number of steps for which the conservation diagnostics are kept into
+    /// `LiquidWorld::conservation_history`.
+    ///
+    /// The diagnostics are only recorded if they are enabled with
+    /// `LiquidWorld::set_conservation_diagnostics_enabled`. The history is empty by default.
+    pub fn set_conservation_history_capacity(&mut self, capacity: usize) {
+        self.conservation_history.set_capacity(capacity)
+    }
+
+    /// The conservation diagnostics of the last steps.
+    pub fn conservation_history(&self) -> &ConservationHistory {
+        &self.conservation_history
+    }
+
+    /// Mutable reference to the conservation diagnostics of the last steps, e.g., to clear them.
+    pub fn conservation_history_mut(&mut self) -> &mut ConservationHistory {
+        &mut self.conservation_history
+    }
+
+    /// Occupancy statistics of the spacial grid used for neighborhood queries, as filled during
+    /// the last substep.
+    ///
+    /// The cells are as wide as the kernel radius `self.h()`, so a large number of elements per
+    /// cell indicates that the smoothing factor is large for the resolution of the scene. Returns
+    /// `None` if the neighbor search structure set with `Self::set_neighbor_search` is not a grid.
+    pub fn hgrid_statistics(&self) -> Option<HGridStatistics> {
+        self.neighbor_search().statistics()
+    }
+
+    /// Suggests a smoothing factor giving `num_neighbors` neighbors to each particle, on average.
+    ///
+    /// The suggestion is derived from the occupancy of the spacial grid (see
+    /// `HGridStatistics::suggested_kernel_radius`), and can be given to `Self::new`, with the
+    /// particle radius `self.particle_radius()`, to create a world adapted to the resolution of the
+    /// scene. Usual targets are 30 to 40 neighbors in 3D, and 15 to 20 in 2D. Returns `None` if
+    /// the neighbor search structure is not a grid, or if it is empty.
+    pub fn suggested_smoothing_factor(&self, num_neighbors: Real) -> Option<Real> {
+        let kernel_radius = self
+            .hgrid_statistics()?
+            .suggested_kernel_radius(num_neighbors)?;
+        Some(kernel_radius / (self.particle_radius * na::convert::<_, Real>(2.0)))
+    }
+
+    /// The timestep manager splitting each step into substeps.
+    ///
+    /// After a step, this gives the lengths of the substeps actually taken.
+    pub fn timestep_manager(&self) -> &TimestepManager {
+        &self.timestep_manager
+    }
+
+    /// The mutable timestep manager splitting each step into substeps.
+    ///
+    /// This can be used to configure the CFL coefficient and the number of substeps.
+    pub fn timestep_manager_mut(&mut self) -> &mut TimestepManager {
+        &mut self.timestep_manager
+    }
+
+    /// The SPH kernel radius.
+    pub fn h(&self) -> Real {
+        self.h
+    }
+
+    /// The simulation time, i.e., the sum of the lengths of all the steps performed so far.
+    ///
+    /// This is the time given to the trajectories of kinematic boundaries.
+    pub fn time(&self) -> Real {
+        self.time
+    }
+
+    /// The densities of the fluid particles, as computed by the pressure solver during the last
+    /// substep.
+    ///
+    /// The fluids are in the same order as `self.fluids().as_slice()`. The densities of a fluid
+    /// are missing if no step was performed since it was added, and don't match its particles
+    /// anymore if particles were added or removed since the last step.
+    pub fn particle_densities(&self) -> &[Vec<Real>] {
+        self.solver.densities()
+    }
+
+    /// The pressures of the fluid particles, as computed by the pressure solver during the last
+    /// substep.
+    ///
+    /// This is empty if the pressure solver does not compute pressures explicitly, e.g., for the
+    /// `DFSPHSolver`. See `Self::particle_densities` for the order of the fluids.
+    pub fn particle_pressures(&self) -> &[Vec<Real>] {
+        self.solver.pressures()
+    }
+
+    /// The radius of every particle on this liquid world.
+    pub fn particle_radius(&self) -> Real {
+        self.particle_radius
+    }
+
+    /// The density of the fluids at the given point, interpolated from the neighboring fluid
+    /// particles with the cubic spline kernel.
+    ///
+    /// This is zero if `point` is not within the kernel radius `self.h()` of any fluid particle.
+    /// The neighbors are found with the spacial grid filled during the last substep.
+    pub fn interpolate_density(&self, point: &Point<Real>) -> Real {
+        let mut density = na::zero::<Real>();
+        self.for_each_fluid_neighbor(point, |fluid, j, weight| {
+            density += fluid.particle_mass(j) * weight;
+        });
+        density
+    }
+
+    /// The velocity of the fluids at the given point, interpolated from the neighboring fluid
+    /// particles with the cubic spline kernel.
+    ///
+    /// Returns `None` if `point` is not within the kernel radius `self.h()` of any fluid particle.
+    /// The neighbors are found with the spacial grid filled during the last substep.
+    pub fn interpolate_velocity(&self, point: &Point<Real>) -> Option<Vector<Real>> {
+        let mut velocity = Vector::zeros();
+        let mut total_weight = na::zero::<Real>();
+        self.for_each_fluid_neighbor(point, |fluid, j, weight| {
+            velocity += fluid.velocities[j] * (fluid.volumes[j] * weight);
+            total_weight += fluid.volumes[j] * weight;
+        });
+
+        if total_weight > na::zero::<Real>() {
+            Some(velocity / total_weight)
+        } else {
+            None
+        }
+    }
+
+    /// Estimates the buoyancy and drag applied by the fluids to a body that is not coupled with them.
+    ///
+    /// This is a cheap alternative to the two-way coupling of small floating objects: the body
+    /// doesn't need to be sampled with boundary particles, and it doesn't push the fluids away. The
+    /// part of the shape of the body at the position `pos` overlapping the AABB of the fluids is
+    /// sampled with a regular grid with a spacing equal to the particle radius. The samples inside
+    /// of the surface of the fluids (as defined by `Self::cast_ray`) are submerged, and the density
+    /// and the velocity of the fluids there are interpolated from the neighboring fluid particles:
+    /// - the mass of the displaced fluid generates a force opposite to `gravity`;
+    /// - the velocity of the fluids relative to the body (moving with the velocities `linvel` and
+    ///   `angvel`) generates a drag force equal to this relative velocity multiplied by the mass of
+    ///   the displaced fluid and by `drag_coefficient` (in `1/s`).
+    ///
+    /// The neighbors are found with the spacial grid filled during the last substep.
+    #[cfg(feature = "parry")]
+    pub fn estimate_buoyancy<S>(
+        &self,
+        pos: &Isometry<Real>,
+        shape: &S,
+        linvel: &Vector<Real>,
+        angvel: &AngularVector<Real>,
+        gravity: &Vector<Real>,
+        drag_coefficient: Real,
+    ) -> BuoyancyEstimate
+    where
+        S: ?Sized + Shape,
+    {
+        let mut result = BuoyancyEstimate {
+            submerged_volume: na::zero::<Real>(),
+            displaced_mass: na::zero::<Real>(),
+            center_of_buoyancy: None,
+            buoyancy_force: Vector::zeros(),
+            drag_force: Vector::zeros(),
+            torque: AngularVector::zeros(),
+        };
+
+        // Only sample the part of the shape close enough to the fluids to be submerged.
+        let Some([fluid_mins, fluid_maxs]) = self.fluid_bounds else {
+            return result;
+        };
+        let fluid_aabb = Aabb::new(fluid_mins, fluid_maxs).loosened(self.h);
+        let Some(local_aabb) = shape
+            .compute_aabb(pos)
+            .intersection(&fluid_aabb)
+            .and_then(|aabb| {
+                aabb.transform_by(&pos.inverse())
+                    .intersection(&shape.compute_local_aabb())
+            })
+        else {
+            return result;
+        };
+
+        let extents = local_aabb.extents();
+        let counts = extents.map(|e| {
+            na::try_convert::<Real, f64>(e / self.particle_radius)
+                .unwrap()
+                .ceil()
+                .max(1.0) as usize
+        });
+        let cell_widths = extents.zip_map(&counts, |e, n| e / na::convert::<_, Real>(n as f64));
+        let cell_volume = cell_widths.product();
+        let origin = Point::from(pos.translation.vector);
+        let mut weighted_center = Vector::zeros();
+
+        for id in 0..counts.iter().product() {
+            let mut local_point = local_aabb.mins;
+            let mut rest = id;
+
+            for k in 0..local_point.len() {
+                local_point[k] += cell_widths[k]
+                    * (na::convert::<_, Real>((rest % counts[k]) as f64)
+                        + na::convert::<_, Real>(0.5));
+                rest /= counts[k];
+            }
+
+            if !shape.contains_local_point(&local_point) {
+                continue;
+            }
+
+            let point = pos * local_point;
+            let mut density = na::zero::<Real>();
+            let mut volume_fraction = na::zero::<Real>();
+            let mut velocity = Vector::zeros();
+            self.for_each_fluid_neighbor(&point, |fluid, j, weight| {
+                density += fluid.particle_mass(j) * weight;
+                volume_fraction += fluid.volumes[j] * weight;
+                velocity += fluid.velocities[j] * (fluid.volumes[j] * weight);
+            });
+
+            // Same surface definition as `Self::cast_ray`.
+            if volume_fraction < na::convert::<_, Real>(0.5) {
+                continue;
+            }
+
+            let arm = point - origin;
+            #[cfg(feature = "dim2")]
+            let body_velocity = linvel + Vector::new(-arm.y, arm.x) * angvel.x;
+            #[cfg(feature = "dim3")]
+            let body_velocity = linvel + angvel.cross(&arm);
+
+            let mass = density / volume_fraction * cell_volume;
+            let buoyancy = -gravity * mass;
+            let drag = (velocity / volume_fraction - body_velocity) * (mass * drag_coefficient);
+            let force = buoyancy + drag;
+
+            result.submerged_volume += cell_volume;
+            result.displaced_mass += mass;
+            result.buoyancy_force += buoyancy;
+            result.drag_force += drag;
+            weighted_center += point.coords * mass;
+
+            #[cfg(feature = "dim2")]
+            {
+                result.torque += AngularVector::new(arm.perp(&force));
+            }
+
+            #[cfg(feature = "dim3")]
+            {
+                result.torque += arm.cross(&force);
+            }
+        }
+
+        if result.displaced_mass > na::zero::<Real>() {
+            result.center_of_buoyancy = Some(Point::from(weighted_center / result.displaced_mass));
+        }
+
+        result
+    }
+
+    /// Estimates the height of the free surface of the fluids above the given abscissa.
+    ///
+    /// The `y` axis is assumed to point upward. See `Self::surface_height_at_point` for details.
+    #[cfg(feature = "dim2")]
+    pub fn surface_height_at(&self, x: Real) -> Option<Real> {
+        self.surface_height_at_point(&Point::new(x, na::zero::<Real>()))
+    }
+
+    /// Estimates the height of the free surface of the fluids above the given `(x, z)` coordinates.
+    ///
+    /// The `y` axis is assumed to point upward. See `Self::surface_height_at_point` for details.
+    #[cfg(feature = "dim3")]
+    pub fn surface_height_at(&self, x: Real, z: Real) -> Option<Real> {
+        self.surface_height_at_point(&Point::new(x, na::zero::<Real>(), z))
+    }
+
+    /// Estimates the height of the free surface of the fluids on the vertical line passing
+    /// through `point`, the `y` axis being assumed to point upward.
+    ///
+    /// Only the fluid particles within a horizontal distance `self.h()` of the vertical line
+    /// are considered. The highest particles that are not isolated from the rest of the fluid
+    /// by more than a particle diameter define the surface level, which is then smoothed with
+    /// the particles right bellow it. Returns `None` if there is no fluid particle around the
+    /// vertical line.
+    ///
+    /// The particles are found with the neighbor search structure, as filled during the last
+    /// substep, scanning the vertical line from the top until the surface is found. This is much
+    /// cheaper than a full surface reconstruction, but it does not handle overhangs.
+    pub fn surface_height_at_point(&self, point: &Point<Real>) -> Option<Real> {
+        let [fluid_mins, fluid_maxs] = self.fluid_bounds?;
+        let diameter = self.particle_radius * na::convert::<_, Real>(2.0);
+        // The particles may have moved since they were inserted during the last substep.
+        let margin = self.h;
+        // The top of each particle around the vertical line, with its horizontal kernel weight,
+        // sorted from the highest.
+        let mut tops: Vec<(Real, Real)> = Vec::new();
+        let mut slab_top = fluid_maxs.y + margin;
+
+        while slab_top >= fluid_mins.y - margin {
+            let slab_bottom = slab_top - self.h;
+            let mut mins = *point;
+            let mut maxs = *point;
+            mins.coords.add_scalar_mut(-self.h - margin);
+            maxs.coords.add_scalar_mut(self.h + margin);
+            mins.y = slab_bottom - margin;
+            maxs.y = slab_top + margin;
+
+            for entry in self.neighbor_search().particles_in_aabb(&mins, &maxs) {
+                if let HGridEntry::FluidParticle(fid, pid) = entry {
+                    // The neighbor search structure may be outdated if fluids or particles were
+                    // removed since the last substep.
+                    let Some(pos) = self
+                        .fluids
+                        .get_from_contiguous_index(fid)
+                        .and_then(|(fluid, _)| fluid.positions.get(pid))
+                    else {
+                        continue;
+                    };
+
+                    // Each particle is only counted by the slab containing it.
+                    if !(pos.y >= slab_bottom && pos.y < slab_top) {
+                        continue;
+                    }
+
+                    let mut dpos = pos - point;
+                    dpos.y = na::zero::<Real>();
+                    let weight = CubicSplineKernel::apply(dpos, self.h);
+
+                    if weight > na::zero::<Real>() {
+                        tops.push((pos.y + self.particle_radius, weight));
+                    }
+                }
+            }
+
+            tops.sort_unstable_by(|a, b| b.0.total_cmp(&a.0));
+
+            // All the tops above this height are known.
+            let known_height = slab_bottom + self.particle_radius;
+            // Skip the isolated particles (e.g. droplets) above the surface.
+            let first = (0..tops.len()).find(|i| {
+                tops.get(i + 1)
+                    .is_some_and(|next| tops[*i].0 - next.0 <= diameter)
+            });
+
+            if let Some(first) = first {
+                if tops[first].0 - diameter >= known_height {
+                    return Some(Self::smoothed_surface_height(&tops[first..], diameter));
+                }
+            }
+
+            slab_top = slab_bottom;
+        }
+
+        // The whole vertical line was scanned: the lowest particle is the surface if all the
+        // others are isolated.
+        let first = (0..tops.len()).find(|i| {
+            tops.get(i + 1)
+                .is_none_or(|next| tops[*i].0 - next.0 <= diameter)
+        })?;
+        Some(Self::smoothed_surface_height(&tops[first..], diameter))
+    }
+
+    // The average of the tops less than `diameter` bellow the first one, weighted by their
+    // horizontal kernel weights.
+    fn smoothed_surface_height(tops: &[(Real, Real)], diameter: Real) -> Real {
+        let max_height = tops[0].0;
+        let mut height = na::zero::<Real>();
+        let mut total_weight = na::zero::<Real>();
+
+        for (top, weight) in tops {
+            if *top < max_height - diameter {
+                break;
+            }
+
+            height += *top * *weight;
+            total_weight += *weight;
+        }
+
+        height / total_weight
+    }
+
+    /// Casts a ray against the surface of the fluids.
+    ///
+    /// The surface of the fluids is the iso-surface, at the level `0.5`, of the scalar field equal to
+    /// the sum of `V_j W(x - x_j, h)` over the fluid particles `j`, with `V_j` the particle volume and
+    /// `W` the cubic spline kernel. This is the same surface as the default `SurfaceReconstruction`.
+    /// The ray is marched with steps of the size of a particle radius close to the fluids, so very
+    /// thin features may be missed.
+    ///
+    /// If `solid` is `true` and the ray origin is inside of the fluids, the hit is at the ray origin.
+    /// If `solid` is `false` and the ray origin is inside of the fluids, the hit is where the ray
+    /// exits the fluids. Returns `None` if the surface is not hit before `max_toi`.
+    pub fn cast_ray(
+        &self,
+        origin: &Point<Real>,
+        dir: &Vector<Real>,
+        max_toi: Real,
+        solid: bool,
+    ) -> Option<FluidRayIntersection> {
+        let iso_level = na::convert::<_, Real>(0.5);
+        let dir_norm = dir.norm();
+
+        if dir_norm <= na::zero::<Real>() {
+            return None;
+        }
+
+        let is_inside = |toi: Real| self.fluid_field(&(origin + dir * toi)).0 >= iso_level;
+        let start_inside = is_inside(na::zero::<Real>());
+
+        if start_inside && solid {
+            return Some(FluidRayIntersection {
+                toi: na::zero::<Real>(),
+                point: *origin,
+                normal: Vector::zeros(),
+            });
+        }
+
+        let fine_step = self.particle_radius / dir_norm;
+        let coarse_step = self.h / dir_norm;
+        let mut prev_toi = na::zero::<Real>();
+
+        while prev_toi < max_toi {
+            // The field is zero when there is no particle within a kernel radius.
+            let step = if self.fluid_field(&(origin + dir * prev_toi)).0 > na::zero::<Real>() {
+                fine_step
+            } else {
+                coarse_step
+            };
+            let mut toi = (prev_toi + step).min(max_toi);
+
+            if is_inside(toi) != start_inside {
+                // Refine the hit point by bisection.
+                for _ in 0..16 {
+                    let mid = (prev_toi + toi) * na::convert::<_, Real>(0.5);
+
+                    if is_inside(mid) == start_inside {
+                        prev_toi = mid;
+                    } else {
+                        toi = mid;
+                    }
+                }
+
+                let point = origin + dir * toi;
+                let normal = -self.fluid_field(&point).1;
+                return Some(FluidRayIntersection {
+                    toi,
+                    point,
+                    normal: normal
+                        .try_normalize(na::zero::<Real>())
+                        .unwrap_or_else(Vector::zeros),
+                });
+            }
+
+            prev_toi = toi;
+        }
+
+        None
+    }
+
+    // The sum of `V_j W(point - x_j, h)` over the fluid particles, and its gradient.
+    fn fluid_field(&self, point: &Point<Real>) -> (Real, Vector<Real>) {
+        let mut value = na::zero::<Real>();
+        let mut gradient = Vector::zeros();
+        self.for_each_fluid_neighbor(point, |fluid, j, weight| {
+            value += fluid.volumes[j] * weight;
+            gradient += CubicSplineKernel::points_apply_diff1(point, &fluid.positions[j], self.h)
+                * fluid.volumes[j];
+        });
+        (value, gradient)
+    }
+
+    // Calls `f` with each fluid particle within the kernel radius of `point`, and its kernel weight.
+    fn for_each_fluid_neighbor(&self, point: &Point<Real>, mut f: impl FnMut(&Fluid, usize, Real)) {
+        let extents = Vector::repeat(self.h);
+
+        for entry in self
+            .neighbor_search()
+            .particles_in_aabb(&(point - extents), &(point + extents))
+        {
+            if let HGridEntry::FluidParticle(fid, pid) = entry {
+                // The neighbor search structure may be outdated if fluids or particles were
+                // removed since the last substep.
+                if let Some((fluid, _)) = self.fluids.get_from_contiguous_index(fid) {
+                    if let Some(pos) = fluid.positions.get(pid) {
+                        let weight = CubicSplineKernel::points_apply(point, pos, self.h);
+
+                        if weight > na::zero::<Real>() {
+                            f(fluid, pid, weight);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// The set of particles with a center inside of the AABB with corners `mins` and `maxs`.
+    ///
+    /// Unlike `Self::particles_intersecting_aabb`, particles only touching the AABB are excluded.
+    pub fn particles_in_aabb<'a>(
+        &'a self,
+        mins: &Point<Real>,
+        maxs: &Point<Real>,
+    ) -> impl Iterator<Item = ParticleId> + 'a {
+        let (mins, maxs) = (*mins, *maxs);
+        self.particles_in_region(mins, maxs, move |pt| {
+            pt.coords.iter().zip(mins.iter()).all(|(p, m)| p >= m)
+                && pt.coords.iter().zip(maxs.iter()).all(|(p, m)| p <= m)
+        })
+    }
+
+    /// The set of particles with a center inside of the ball with the given center and radius.
+    pub fn particles_in_ball<'a>(
+        &'a self,
+        center: &Point<Real>,
+        radius: Real,
+    ) -> impl Iterator<Item = ParticleId> + 'a {
+        let center = *center;
+        let extents = Vector::repeat(radius);
+        self.particles_in_region(center - extents, center + extents, move |pt| {
+            na::distance_squared(pt, &center) <= radius * radius
+        })
+    }
+
+    /// The set of particles with a center inside of the given shape.
+    ///
+    /// Unlike `Self::particles_intersecting_shape`, particles only touching the shape are excluded.
+    /// This can be used, e.g., to measure the amount of fluid inside of a container by using its
+    /// interior as the shape.
+    #[cfg(feature = "parry")]
+    pub fn particles_in_shape<'a, S>(
+        &'a self,
+        pos: &'a Isometry<Real>,
+        shape: &'a S,
+    ) -> impl Iterator<Item = ParticleId> + 'a
+    where
+        S: ?Sized + Shape,
+    {
+        let aabb = shape.compute_aabb(pos);
+        self.particles_in_region(aabb.mins, aabb.maxs, move |pt| {
+            shape.contains_point(pos, pt)
+        })
+    }
+
+    // The particles within the AABB `[mins, maxs]` for which `contains` returns `true`.
+    fn particles_in_region<'a>(
+        &'a self,
+        mins: Point<Real>,
+        maxs: Point<Real>,
+        contains: impl Fn(&Point<Real>) -> bool + 'a,
+    ) -> impl Iterator<Item = ParticleId> + 'a {
+        // The particles may have moved since they were inserted during the last substep.
+        let margin = Vector::repeat(self.h);
+        self.neighbor_search()
+            .particles_in_aabb(&(mins - margin), &(maxs + margin))
+            .filter_map(move |entry| match &entry {
+                HGridEntry::FluidParticle(fid, pid) => {
+                    let (fluid, handle) = self.fluids.get_from_contiguous_index(*fid)?;
+                    let pt = fluid.positions.get(*pid)?;
+                    contains(pt).then_some(ParticleId::FluidParticle(handle, *pid))
+                }
+                HGridEntry::BoundaryParticle(bid, pid) => {
+                    let (boundary, handle) = self.boundaries.get_from_contiguous_index(*bid)?;
+                    let pt = boundary.positions.get(*pid)?;
+                    contains(pt).then_some(ParticleId::BoundaryParticle(handle, *pid))
+                }
+            })
+    }
+
+    /// The set of particles potentially intersecting the given AABB.
+    #[cfg(feature = "parry")]
+    pub fn particles_intersecting_aabb<'a>(
+        &'a self,
+        aabb: Aabb,
+    ) -> impl Iterator<Item = ParticleId> + 'a {
+        self.neighbor_search()
+            .particles_in_aabb(&aabb.mins, &aabb.maxs)
+            .filter_map(move |entry| match &entry {
+                HGridEntry::FluidParticle(fid, pid) => {
+                    let (fluid, handle) = self.fluids.get_from_contiguous_index(*fid)?;
+                    let pt = fluid.positions[*pid];
+
+                    // FIXME: use `distance_to_local_point` once it's supported.
+                    let id = &Isometry::identity();
+                    if aabb.distance_to_point(id, &pt, true) < self.particle_radius {
+                        Some(ParticleId::FluidParticle(handle, *pid))
+                    } else {
+                        None
+                    }
+                }
+                HGridEntry::BoundaryParticle(bid, pid) => {
+                    let (boundary, handle) = self.boundaries.get_from_contiguous_index(*bid)?;
+                    let pt = boundary.positions[*pid]; // FIXME: use `distance_to_local_point` once it's supported.
+                    let id = &Isometry::identity();
+                    if aabb.distance_to_point(id, &pt, true) < self.particle_radius {
+                        Some(ParticleId::BoundaryParticle(handle, *pid))
+                    } else {
+                        None
+                    }
+                }
+            })
     }
 
     /// The set of particles potentially intersecting the given shape.
@@ -253,10 +2497,9 @@ impl LiquidWorld {
         S: Shape,
     {
         let aabb = shape.compute_aabb(pos);
-        self.hgrid
-            .cells_intersecting_aabb(&aabb.mins, &aabb.maxs)
-            .flat_map(|e| e.1)
-            .filter_map(move |entry| match entry {
+        self.neighbor_search()
+            .particles_in_aabb(&aabb.mins, &aabb.maxs)
+            .filter_map(move |entry| match &entry {
                 HGridEntry::FluidParticle(fid, pid) => {
                     let (fluid, handle) = self.fluids.get_from_contiguous_index(*fid)?;
                     let pt = fluid.positions[*pid];
@@ -280,8 +2523,595 @@ impl LiquidWorld {
     }
 }
 
+// The bounding box of the particles of the given fluids with finite positions.
+fn fluid_bounds(fluids: &[Fluid]) -> Option<[Point<Real>; 2]> {
+    fluids
+        .iter()
+        .flat_map(|fluid| fluid.positions.iter())
+        .filter(|pos| pos.iter().all(|x| x.is_finite()))
+        .fold(None, |bounds, pos| match bounds {
+            Some([mins, maxs]) => Some([mins.inf(pos), maxs.sup(pos)]),
+            None => Some([*pos, *pos]),
+        })
+}
+
+// The points of a lattice of spacing 0.1 with indices in `mins..maxs` along each axis and selected
+// by `keep`. The third axis is ignored in 2D.
+#[cfg(test)]
+pub(crate) fn test_lattice(
+    mins: [i32; 3],
+    maxs: [i32; 3],
+    keep: &dyn Fn([i32; 3]) -> bool,
+) -> Vec<Point<Real>> {
+    use crate::math::DIM;
+    let mut points = Vec::new();
+
+    for i in mins[0]..maxs[0] {
+        for j in mins[1]..maxs[1] {
+            for k in mins[2]..maxs[2] {
+                if keep([i, j, k]) {
+                    let mut point = Point::origin();
+                    point[0] = i as Real * 0.1;
+                    point[1] = j as Real * 0.1;
+
+                    if DIM == 3 {
+                        point[DIM - 1] = k as Real * 0.1;
+                    }
+
+                    points.push(point);
+                }
+            }
+        }
+    }
+
+    points
+}
+
+// A liquid world with an IISPH solver, the given particle radius, and a smoothing factor of 2.
+#[cfg(test)]
+pub(crate) fn test_world(particle_radius: Real) -> LiquidWorld {
+    LiquidWorld::new(
+        crate::solver::IISPHSolver::<CubicSplineKernel, CubicSplineKernel>::new(),
+        particle_radius,
+        2.0,
+    )
+}
+
+// A fluid with a single particle of radius 0.1 at `x` along the first axis, moving at `vx` along
+// this axis.
+#[cfg(test)]
+fn test_particle(x: Real, vx: Real) -> Fluid {
+    let mut fluid = Fluid::new(
+        vec![Point::from(Vector::x() * x)],
+        0.1,
+        1000.0,
+        Default::default(),
+    );
+    fluid.velocities[0] = Vector::x() * vx;
+    fluid
+}
+
+// A fluid with a row of ten particles of radius 0.1 spaced by 0.2 along the first axis.
+#[cfg(test)]
+fn test_row() -> Fluid {
+    let positions: Vec<_> = (0..10)
+        .map(|i| Point::from(Vector::x() * i as Real * 0.2))
+        .collect();
+    Fluid::new(positions, 0.1, 1000.0, Default::default())
+}
+
+// The velocities along the first axis of two single-particle fluids after two steps, starting at
+// the given positions and velocities along this axis, and subject to the forces created by
+// `force`.
+#[cfg(test)]
+fn test_pair_velocities(
+    particles: [(Real, Real); 2],
+    force: &dyn Fn() -> Box<dyn crate::solver::NonPressureForce>,
+) -> [Real; 2] {
+    let mut world = test_world(0.1);
+    let handles = particles.map(|(x, vx)| {
+        let mut fluid = test_particle(x, vx);
+        fluid.nonpressure_forces.push(force());
+        world.add_fluid(fluid)
+    });
+
+    world.step(0.01, &Vector::zeros());
+    world.step(0.01, &Vector::zeros());
+    handles.map(|handle| world.fluids()[handle].velocities[0].x)
+}
+
 #[test]
 fn world_is_send_and_sync() {
     fn check<T: Send + Sync>() {}
     check::<LiquidWorld>();
 }
+
+#[test]
+fn added_particles_resize_the_solver_buffers() {
+    let mut world = test_world(0.1);
+    let handle = world.add_fluid(test_row());
+    world.step(0.01, &Vector::zeros());
+    world.fluids_mut()[handle].delete_particle_at_next_timestep(0);
+    world.fluids_mut()[handle].delete_particle_at_next_timestep(3);
+
+    let new_positions = [Point::from(Vector::y()), Point::from(Vector::y() * 2.0)];
+    let added = world.add_particles(handle, &new_positions, None, Some(&[0.5, 0.5]));
+
+    assert_eq!(added, Some(8..10));
+    let fluid = &world.fluids()[handle];
+    assert_eq!(fluid.num_particles(), 10);
+    assert_eq!(fluid.positions[8], new_positions[0]);
+    assert_eq!(fluid.volumes[9], 0.5);
+    assert_eq!(world.particle_densities()[0].len(), 10);
+    assert_eq!(world.particle_pressures()[0].len(), 10);
+}
+
+#[test]
+fn particles_with_different_rest_densities_are_not_mixed_up() {
+    let mut world = test_world(0.1);
+    let mut fluid = test_row();
+    fluid.set_particle_rest_densities(5, &[2000.0; 5]);
+    let handle = world.add_fluid(fluid);
+    world.step(0.001, &Vector::zeros());
+
+    let fluid = &world.fluids()[handle];
+    let densities = &world.particle_densities()[0];
+    assert_eq!(fluid.rest_density(0), 1000.0);
+    assert_eq!(fluid.rest_density(9), 2000.0);
+    assert!((fluid.particle_mass(9) - fluid.particle_mass(0) * 2.0).abs() < 1.0e-5);
+    assert!((densities[9] / densities[0] - 2.0).abs() < 1.0e-3);
+}
+
+#[test]
+fn nonpressure_forces_apply_impulses_to_boundaries() {
+    use crate::geometry::ParticlesContacts;
+    use crate::solver::{NonPressureForce, OtherFluids};
+
+    struct BoundaryPush;
+
+    impl NonPressureForce for BoundaryPush {
+        fn solve(
+            &mut self,
+            timestep: &TimestepManager,
+            _: Real,
+            _: &ParticlesContacts,
+            fluid_boundaries_contacts: &ParticlesContacts,
+            _: &mut Fluid,
+            _: &OtherFluids,
+            boundaries: &[Boundary],
+            _: &[Real],
+        ) {
+            for contacts in fluid_boundaries_contacts.contacts() {
+                for c in contacts.read().unwrap().iter() {
+                    boundaries[c.j_model].apply_impulse(
+                        c.j,
+                        Vector::x() * timestep.dt(),
+                        timestep.dt(),
+                    );
+                }
+            }
+        }
+    }
+
+    // The average force applied to a boundary particle by a fluid particle, with or without the
+    // custom force.
+    let average_boundary_force = |push: bool| {
+        let mut world = test_world(0.1);
+        let mut fluid = test_particle(0.1, 0.0);
+
+        if push {
+            fluid.nonpressure_forces.push(Box::new(BoundaryPush));
+        }
+
+        let _ = world.add_fluid(fluid);
+        let mut boundary = Boundary::new(vec![Point::origin()], Default::default());
+        boundary.enable_forces(true);
+        boundary.force_clearing_policy = ForceClearingPolicy::EveryStep;
+        let handle = world.add_boundary(boundary);
+        // The non-pressure forces are computed before the first substep length is known.
+        world.step(0.01, &Vector::zeros());
+        world.step(0.01, &Vector::zeros());
+
+        let boundary = &world.boundaries()[handle];
+        assert!(boundary.receives_forces());
+        boundary.accumulated_force().unwrap() / boundary.num_accumulated_substeps() as Real
+    };
+
+    let push = average_boundary_force(true) - average_boundary_force(false);
+    assert!((push - Vector::x()).norm() < 1.0e-3);
+}
+
+#[test]
+fn body_coupling_transmits_each_substep_once() {
+    use crate::coupling::{BodyCouplingSet, BodyState};
+    use crate::math::{Isometry, DIM};
+
+    // The impulse transmitted to a floor by a block of fluid falling on it.
+    let impulse = |policy: ForceClearingPolicy| {
+        let nz = if DIM == 3 { 4 } else { 1 };
+        let mut world = test_world(0.05);
+        world.timestep_manager_mut().set_num_substeps_range(4, 4);
+        let block = test_lattice([0, 1, 0], [4, 4, nz], &|_| true);
+        let floor = test_lattice([-1, 0, -1], [5, 1, nz + 1], &|_| true);
+        let mut fluid = Fluid::new(block, 0.05, 1000.0, Default::default());
+        fluid.velocities.fill(Vector::y() * -1.0);
+        let _ = world.add_fluid(fluid);
+        let mut boundary = Boundary::new(Vec::new(), Default::default());
+        boundary.force_clearing_policy = policy;
+        let boundary = world.add_boundary(boundary);
+
+        let mut state = BodyState::fixed(Isometry::identity());
+        state.is_dynamic = true;
+        let mut coupling = BodyCouplingSet::new();
+        let _ = coupling.register_coupling(0, boundary, floor, state);
+        for _ in 0..5 {
+            world.step_with_coupling(0.01, &(Vector::y() * -9.81), &mut coupling);
+        }
+        coupling.take_impulses(0).unwrap().0
+    };
+
+    let expected = impulse(ForceClearingPolicy::EverySubstep);
+    assert!(expected.y < 0.0, "{}", expected);
+    assert_eq!(impulse(ForceClearingPolicy::EveryStep), expected);
+    assert_eq!(impulse(ForceClearingPolicy::Manual), expected);
+}
+
+#[test]
+fn artificial_viscosity_acts_between_fluids() {
+    use crate::solver::ArtificialViscosity;
+
+    // The relative velocity of two approaching particles of different fluids.
+    let relative_velocity = |interphase_viscosity_coefficient: Real| {
+        let [v1, v2] = test_pair_velocities([(0.0, 1.0), (0.15, 0.0)], &|| {
+            let mut viscosity = ArtificialViscosity::new(0.0, 0.0);
+            viscosity.interphase_viscosity_coefficient = interphase_viscosity_coefficient;
+            Box::new(viscosity)
+        });
+        v1 - v2
+    };
+
+    assert!(relative_velocity(1.0) < relative_velocity(0.0));
+}
+
+#[test]
+fn interphase_drag_exchanges_momentum_between_fluids() {
+    use crate::solver::InterphaseDrag;
+
+    // The velocities of two particles of different fluids moving apart.
+    let velocities = |coefficient: Real| {
+        let drag =
+            InterphaseDrag::new(na::DMatrix::zeros(0, 0)).with_coefficient(0, 1, coefficient);
+        test_pair_velocities([(0.0, 0.0), (0.15, 1.0)], &|| Box::new(drag.clone()))
+    };
+
+    let [v1, v2] = velocities(0.0);
+    let [v1_drag, v2_drag] = velocities(1.0e3);
+    let [v1_stiff, v2_stiff] = velocities(1.0e12);
+    assert!(v1_drag > v1 && v2_drag < v2);
+    // The particles have the same mass, so the momentum exchange conserves their total velocity.
+    assert!((v1_drag + v2_drag - v1 - v2).abs() < 1.0e-5);
+    // The drag never reverses the relative velocity.
+    assert!(v2_stiff - v1_stiff > -1.0e-3);
+}
+
+#[test]
+fn interface_surface_tension_pushes_fluids_apart() {
+    use crate::solver::InterfaceSurfaceTension;
+
+    // The velocities of two particles of different fluids at rest.
+    let velocities = |coefficient: Real| {
+        let tension = InterfaceSurfaceTension::new(na::DMatrix::zeros(0, 0)).with_coefficient(
+            0,
+            1,
+            coefficient,
+        );
+        test_pair_velocities([(0.0, 0.0), (0.15, 0.0)], &|| Box::new(tension.clone()))
+    };
+
+    let [v1, v2] = velocities(0.0);
+    let [v1_tension, v2_tension] = velocities(0.07);
+    assert!(v1_tension < v1 && v2_tension > v2);
+    // The forces are opposite, so the momentum is conserved.
+    assert!((v1_tension + v2_tension - v1 - v2).abs() < 1.0e-5);
+}
+
+#[test]
+fn micropolar_model_transfers_the_vorticity_of_the_flow() {
+    use crate::solver::Bender2017Micropolar;
+
+    // The velocities of a row of particles after a few steps, with an initial vertical velocity
+    // given by `velocity(x)`.
+    let velocities = |micropolar: bool, velocity: fn(Real) -> Real| {
+        let mut world = test_world(0.1);
+        let mut fluid = test_row();
+
+        for (position, vel) in fluid.positions.iter().zip(fluid.velocities.iter_mut()) {
+            vel.y = velocity(position.x);
+        }
+
+        if micropolar {
+            fluid
+                .nonpressure_forces
+                .push(Box::new(Bender2017Micropolar::new(1.0, 100.0, 0.1)));
+        }
+
+        let handle = world.add_fluid(fluid);
+
+        for _ in 0..4 {
+            world.step(0.01, &Vector::zeros());
+        }
+
+        world.fluids()[handle].velocities.clone()
+    };
+
+    // A uniform motion has no vorticity.
+    let uniform = |_| 1.0;
+    let max_difference = |a: &[Vector<Real>], b: &[Vector<Real>]| {
+        a.iter()
+            .zip(b.iter())
+            .map(|(a, b)| (a - b).norm())
+            .fold(0.0, Real::max)
+    };
+    assert!(max_difference(&velocities(false, uniform), &velocities(true, uniform)) < 1.0e-6);
+
+    let shear = |x| x;
+    let sheared = velocities(true, shear);
+    assert!(max_difference(&velocities(false, shear), &sheared) > 1.0e-3);
+    assert!(sheared.iter().all(|v| v.norm() < 10.0));
+}
+
+#[test]
+fn wcsph_column_settles_at_large_steps() {
+    use crate::math::DIM;
+
+    let nz = if DIM == 3 { 4 } else { 1 };
+    let zmin = if DIM == 3 { -1 } else { 0 };
+    let zmax = if DIM == 3 { nz + 1 } else { 1 };
+
+    let mut world = LiquidWorld::new(
+        crate::solver::WCSPHSolver::<CubicSplineKernel, CubicSplineKernel>::new(),
+        0.05,
+        2.0,
+    );
+    let column = test_lattice([0, 0, 0], [4, 8, nz], &|_| true);
+    let mut fluid = Fluid::new(column, 0.05, 1000.0, Default::default());
+    fluid
+        .nonpressure_forces
+        .push(Box::new(crate::solver::ArtificialViscosity::new(0.1, 0.0)));
+    let handle = world.add_fluid(fluid);
+    // A box open at the top, one lattice cell larger than the column.
+    let walls = test_lattice([-1, -1, zmin], [5, 12, zmax], &|[i, j, k]| {
+        i == -1 || i == 4 || j == -1 || (DIM == 3 && (k == -1 || k == nz))
+    });
+    let _ = world.add_boundary(Boundary::new(walls, Default::default()));
+    let gravity = Vector::y() * -9.81;
+
+    for _ in 0..120 {
+        world.step(1.0 / 60.0, &gravity);
+    }
+
+    // With a speed of sound of 20 m/s, the acoustic limit requires several substeps per step.
+    assert!(world.timestep_manager().num_substeps() > 1);
+
+    for (position, velocity) in world.fluids()[handle]
+        .positions
+        .iter()
+        .zip(world.fluids()[handle].velocities.iter())
+    {
+        assert!(velocity.norm() < 0.5, "{}", velocity.norm());
+        assert!(position.y > -0.1 && position.y < 0.8, "{}", position.y);
+    }
+}
+
+#[test]
+fn divergence_solver_removes_the_compression_of_the_velocity_field() {
+    use crate::math::DIM;
+
+    // The contraction rate of a block of particles with a uniformly converging initial velocity
+    // field after a short step, i.e., the least-squares fit of `k` in `v = -k (x - center)`.
+    let contraction_rate = |divergence_solver: bool| {
+        let mut world = LiquidWorld::new(
+            crate::solver::WCSPHSolver::<CubicSplineKernel, CubicSplineKernel>::new(),
+            0.05,
+            2.0,
+        );
+
+        if divergence_solver {
+            world.set_divergence_solver(Some(DivergenceSolver::new()));
+        }
+
+        let nz = if DIM == 3 { 6 } else { 1 };
+        let block = test_lattice([0, 0, 0], [6, 6, nz], &|_| true);
+        let center = block.iter().fold(Point::origin(), |c, p| c + p.coords) / block.len() as Real;
+        let mut fluid = Fluid::new(block, 0.05, 1000.0, Default::default());
+
+        for (position, velocity) in fluid.positions.iter().zip(fluid.velocities.iter_mut()) {
+            *velocity = (center - position) * 0.5;
+        }
+
+        let handle = world.add_fluid(fluid);
+        world.step(1.0e-4, &Vector::zeros());
+
+        let fluid = &world.fluids()[handle];
+        let (num, den) = fluid.positions.iter().zip(fluid.velocities.iter()).fold(
+            (0.0, 0.0),
+            |(num, den), (position, velocity)| {
+                let dpos = position - center;
+                (num - velocity.dot(&dpos), den + dpos.norm_squared())
+            },
+        );
+        num / den
+    };
+
+    let without = contraction_rate(false);
+    let with = contraction_rate(true);
+    assert!((without - 0.5).abs() < 0.05, "{}", without);
+    assert!(with < without * 0.5, "{} {}", with, without);
+}
+
+#[test]
+fn implicit_viscosity_damps_shear_flows_at_large_steps() {
+    use crate::math::DIM;
+    use crate::solver::{ImplicitViscosity, NonPressureForce, XSPHViscosity};
+
+    let viscosity = 10.0;
+    let dt = 0.01;
+
+    // The kinetic energy, divided by the particle volume, of a block of particles with an initial
+    // shear flow, initially and after each step.
+    let kinetic_energies = |force: Box<dyn NonPressureForce>| {
+        let mut world = test_world(0.05);
+        let nz = if DIM == 3 { 4 } else { 1 };
+        let block = test_lattice([0, 0, 0], [8, 8, nz], &|_| true);
+        let mut fluid = Fluid::new(block, 0.05, 1000.0, Default::default());
+
+        for (position, velocity) in fluid.positions.iter().zip(fluid.velocities.iter_mut()) {
+            velocity.x =
+                (position.y * na::convert::<_, Real>(std::f64::consts::PI) / 0.4).sin() * 0.1;
+        }
+
+        fluid.nonpressure_forces.push(force);
+        let handle = world.add_fluid(fluid);
+        let kinetic_energy = |world: &LiquidWorld| {
+            let fluid = &world.fluids()[handle];
+            fluid
+                .velocities
+                .iter()
+                .map(|v| v.norm_squared() * fluid.density0 * 0.5)
+                .sum::<Real>()
+        };
+        let mut energies = vec![kinetic_energy(&world)];
+
+        for _ in 0..20 {
+            world.step(dt, &Vector::zeros());
+            energies.push(kinetic_energy(&world));
+        }
+
+        energies
+    };
+
+    let implicit = kinetic_energies(Box::new(ImplicitViscosity::new(viscosity, 0.0)));
+    assert!(implicit.iter().all(|e| e.is_finite()));
+    assert!(implicit.windows(2).all(|e| e[1] <= e[0] * 1.001));
+    // The viscosity is already applied during the first substep.
+    assert!(implicit[1] < implicit[0] * 0.9);
+    // The shear is damped, only the mean translation of the block remains.
+    assert!(implicit[implicit.len() - 1] < implicit[0] * 0.5);
+
+    // The explicit blending coefficient matching the same viscosity and time step, i.e., the
+    // diffusion number of the particle lattice.
+    let xsph = kinetic_energies(Box::new(XSPHViscosity::new(viscosity * dt / 0.01, 0.0)));
+    assert!(xsph[xsph.len() - 1] > xsph[0] * 10.0);
+}
+
+#[test]
+fn shepard_filter_does_not_depend_on_the_fluid_order() {
+    use crate::math::DIM;
+
+    // The filtered densities of two fluids in contact, added to the world in the given order.
+    let densities = |reversed: bool| {
+        let mut world = test_world(0.05);
+        let nz = if DIM == 3 { 4 } else { 1 };
+        let mut fluids: Vec<_> = [(0, 1000.0), (4, 500.0)]
+            .into_iter()
+            .map(|(x, density)| {
+                let block = test_lattice([x, 0, 0], [x + 4, 4, nz], &|_| true);
+                let mut fluid = Fluid::new(block, 0.05, density, Default::default());
+                fluid.shepard_density_correction = true;
+                fluid
+            })
+            .collect();
+
+        if reversed {
+            fluids.reverse();
+        }
+
+        for fluid in fluids {
+            let _ = world.add_fluid(fluid);
+        }
+
+        world.step(1.0e-4, &Vector::zeros());
+        let mut densities = world.particle_densities().to_vec();
+
+        if reversed {
+            densities.reverse();
+        }
+
+        densities
+    };
+
+    let ordered = densities(false);
+    let reversed = densities(true);
+
+    for (ordered, reversed) in ordered.iter().zip(reversed.iter()) {
+        for (a, b) in ordered.iter().zip(reversed.iter()) {
+            assert!((a - b).abs() < a * 1.0e-4, "{} {}", a, b);
+        }
+    }
+}
+
+#[test]
+fn surface_height_ignores_droplets_and_invalid_positions() {
+    use crate::math::DIM;
+
+    let nz: i32 = if DIM == 3 { 4 } else { 1 };
+    let column = |j: i32| ((4 * 5 + j) * nz) as usize;
+    let mut world = test_world(0.05);
+    let mut block = test_lattice([0, 0, 0], [10, 5, nz], &|_| true);
+    // A droplet and a particle with an invalid position in the queried column.
+    let mut droplet = block[column(0)];
+    droplet.y = 2.0;
+    block.push(droplet);
+    let handle = world.add_fluid(Fluid::new(block, 0.05, 1000.0, Default::default()));
+    world.step(1.0e-4, &Vector::zeros());
+    world.fluids_mut()[handle].positions[column(2)] = Point::from(Vector::repeat(Real::NAN));
+
+    let mut point = Point::from(Vector::repeat(0.15));
+    point.x = 0.45;
+    let height = world.surface_height_at_point(&point).unwrap();
+    assert!((height - 0.45).abs() < 0.1, "{}", height);
+
+    point.x = 5.0;
+    assert!(world.surface_height_at_point(&point).is_none());
+}
+
+#[cfg(feature = "parry")]
+#[test]
+fn buoyancy_only_samples_the_shape_around_the_fluids() {
+    use crate::math::{AngularVector, Isometry, DIM};
+    use parry::shape::Cuboid;
+
+    let nz = if DIM == 3 { 4 } else { 1 };
+    let mut world = test_world(0.05);
+    let block = test_lattice([0, 0, 0], [10, 5, nz], &|_| true);
+    let _ = world.add_fluid(Fluid::new(block, 0.05, 1000.0, Default::default()));
+    world.step(1.0e-4, &Vector::zeros());
+
+    let submerged_volume = |half_extent: Real, height: Real| {
+        let mut pos = Isometry::identity();
+        pos.translation.vector.y = height;
+        world
+            .estimate_buoyancy(
+                &pos,
+                &Cuboid::new(Vector::repeat(half_extent)),
+                &Vector::zeros(),
+                &AngularVector::zeros(),
+                &(Vector::y() * -9.81),
+                0.0,
+            )
+            .submerged_volume
+    };
+
+    // A body much larger than the fluids contains all of them.
+    let mut fluid_volume = 0.5;
+    if DIM == 3 {
+        fluid_volume *= 0.1 * nz as Real;
+    }
+    let volume = submerged_volume(1.0e6, 0.0);
+    assert!(
+        (volume - fluid_volume).abs() < fluid_volume * 0.2,
+        "{}",
+        volume
+    );
+    assert_eq!(submerged_volume(0.1, 10.0), 0.0);
+}
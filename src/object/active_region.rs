@@ -0,0 +1,147 @@
+use crate::math::{Point, Real};
+use crate::object::{ContiguousArena, ContiguousArenaIndex, FluidHandle};
+#[cfg(feature = "parry")]
+use {crate::math::Isometry, parry::shape::SharedShape};
+
+/// The region in which an active region fully simulates particles.
+#[derive(Clone)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub enum ActiveRegionShape {
+    /// An axis-aligned box.
+    Aabb {
+        /// The corner of the box with the smallest coordinates.
+        mins: Point<Real>,
+        /// The corner of the box with the largest coordinates.
+        maxs: Point<Real>,
+    },
+    /// A ball (a disk in 2D).
+    Ball {
+        /// The center of the ball.
+        center: Point<Real>,
+        /// The radius of the ball.
+        radius: Real,
+    },
+    /// An arbitrary shape, e.g., a convex polyhedron approximating the frustum of a camera.
+    #[cfg(feature = "parry")]
+    Shape {
+        /// The position of the shape.
+        position: Isometry<Real>,
+        /// The shape.
+        shape: SharedShape,
+    },
+}
+
+impl ActiveRegionShape {
+    /// Checks if the given point is inside of this shape.
+    pub fn contains_point(&self, point: &Point<Real>) -> bool {
+        match self {
+            ActiveRegionShape::Aabb { mins, maxs } => {
+                point.coords.iter().zip(mins.iter()).all(|(p, m)| p >= m)
+                    && point.coords.iter().zip(maxs.iter()).all(|(p, m)| p <= m)
+            }
+            ActiveRegionShape::Ball { center, radius } => {
+                na::distance_squared(point, center) <= *radius * *radius
+            }
+            #[cfg(feature = "parry")]
+            ActiveRegionShape::Shape { position, shape } => shape.contains_point(position, point),
+        }
+    }
+}
+
+/// How the fluid particles outside of all the active regions are simulated.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub enum InactiveParticleModel {
+    /// The particles don't move, and keep their velocities until they are active again.
+    Frozen,
+    /// The particles move ballistically: they are only subject to the gravity, and ignore the
+    /// boundaries and the other particles.
+    ///
+    /// This is mostly meant for spray and splashes flying far from the active regions.
+    Ballistic,
+}
+
+/// A region in which the fluid particles are fully simulated.
+///
+/// Active regions are added to the liquid world with `LiquidWorld::add_active_region`. If the
+/// world has at least one enabled active region, only the particles inside of an active region
+/// (affecting their fluid) are solved at each substep. The other particles are simulated with the
+/// cheap model set with `LiquidWorld::set_inactive_particle_model`. They still act as neighbors of
+/// the active particles and contribute to their densities, so the fluid inside of an active region
+/// leans on the inactive fluid around it instead of spreading out. The contacts of the inactive
+/// particles are discarded, so their own densities and pressures are not solved. This skips most
+/// of the work of the solver for them, but they are still inserted into the neighbor search
+/// structure.
+///
+/// The regions can be moved at any time, e.g., to follow a camera or dynamic objects, by
+/// changing their `shape`.
+#[derive(Clone)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub struct ActiveRegion {
+    /// The region in which particles are active.
+    pub shape: ActiveRegionShape,
+    /// The fluids affected by this region.
+    ///
+    /// If `None` (the default), all the fluids are affected.
+    pub fluids: Option<Vec<FluidHandle>>,
+    /// Whether this region activates particles.
+    pub enabled: bool,
+}
+
+impl ActiveRegion {
+    /// Creates an active region activating all the particles inside of the given shape.
+    pub fn new(shape: ActiveRegionShape) -> Self {
+        Self {
+            shape,
+            fluids: None,
+            enabled: true,
+        }
+    }
+
+    /// Restricts this region to the given fluids.
+    pub fn with_fluids(mut self, fluids: Vec<FluidHandle>) -> Self {
+        self.fluids = Some(fluids);
+        self
+    }
+
+    pub(crate) fn affects(&self, fluid: FluidHandle) -> bool {
+        self.enabled
+            && self
+                .fluids
+                .as_ref()
+                .is_none_or(|fluids| fluids.contains(&fluid))
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+/// The unique identifier of an active region.
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub struct ActiveRegionHandle(ContiguousArenaIndex);
+/// A set of all active regions.
+pub type ActiveRegionSet = ContiguousArena<ActiveRegionHandle, ActiveRegion>;
+
+impl From<ContiguousArenaIndex> for ActiveRegionHandle {
+    #[inline]
+    fn from(i: ContiguousArenaIndex) -> Self {
+        ActiveRegionHandle(i)
+    }
+}
+
+impl From<ActiveRegionHandle> for ContiguousArenaIndex {
+    #[inline]
+    fn from(handle: ActiveRegionHandle) -> Self {
+        handle.0
+    }
+}
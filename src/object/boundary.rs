@@ -1,13 +1,112 @@
-use crate::math::{Isometry, Point, Real, Vector};
-use crate::object::{ContiguousArena, ContiguousArenaIndex};
+use crate::geometry::DensityMap;
+use crate::math::{AngularVector, Isometry, Point, Real, Rotation, Vector};
+use crate::object::{ContiguousArena, ContiguousArenaIndex, Fluid};
 
-use std::sync::RwLock;
+#[cfg(feature = "sampling")]
+use parry::bounding_volume::Aabb;
+#[cfg(feature = "sampling")]
+use parry::shape::Shape;
+use std::sync::{Arc, RwLock};
 
 use super::interaction_groups::InteractionGroups;
 
+/// Determines when the forces accumulated on the particles of a boundary are reset to zero.
+///
+/// Whatever the policy, the forces are also reset whenever the particles of the boundary are
+/// regenerated (see `Boundary::accumulated_forces`).
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub enum ForceClearingPolicy {
+    /// The forces are cleared at the beginning of each substep.
+    ///
+    /// This is the policy expected by couplings that transmit forces after each substep,
+    /// like the rapier coupling.
+    #[default]
+    EverySubstep,
+    /// The forces are cleared at the beginning of each call to `LiquidWorld::step`.
+    ///
+    /// The forces then accumulate over all the substeps of the step.
+    EveryStep,
+    /// The forces are never cleared automatically.
+    ///
+    /// Use `Boundary::clear_forces` to clear them manually.
+    Manual,
+}
+
+/// The prescribed rigid motion of a kinematic boundary.
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub enum BoundaryMotion {
+    /// The boundary moves with a constant linear velocity, and rotates with a constant angular
+    /// velocity about a pivot.
+    ///
+    /// The `position` is integrated by the liquid world at the end of each substep. The position
+    /// and the velocities can be modified between two steps, e.g., to drive a piston.
+    Velocity {
+        /// The current position of the boundary.
+        position: Isometry<Real>,
+        /// The linear velocity of the boundary.
+        linvel: Vector<Real>,
+        /// The angular velocity of the boundary.
+        angvel: AngularVector<Real>,
+        /// The point the boundary rotates about, in its local-space.
+        local_pivot: Point<Real>,
+    },
+    /// The position of the boundary is a function of the simulation time.
+    ///
+    /// The velocities of the boundary particles are computed by finite differences over
+    /// each substep.
+    #[cfg_attr(feature = "serde-serialize", serde(skip))]
+    Trajectory(Box<dyn Fn(Real) -> Isometry<Real> + Send + Sync>),
+}
+
+impl BoundaryMotion {
+    /// A motion with a constant linear velocity, starting from `position`.
+    pub fn linear(position: Isometry<Real>, linvel: Vector<Real>) -> Self {
+        BoundaryMotion::Velocity {
+            position,
+            linvel,
+            angvel: AngularVector::zeros(),
+            local_pivot: Point::origin(),
+        }
+    }
+
+    /// A rotation with a constant angular velocity about `local_pivot`, starting from `position`.
+    ///
+    /// This is useful for water wheels, propellers, and mixers.
+    pub fn rotating(
+        position: Isometry<Real>,
+        local_pivot: Point<Real>,
+        angvel: AngularVector<Real>,
+    ) -> Self {
+        BoundaryMotion::Velocity {
+            position,
+            linvel: Vector::zeros(),
+            angvel,
+            local_pivot,
+        }
+    }
+
+    fn position_at(&self, time: Real) -> Isometry<Real> {
+        match self {
+            BoundaryMotion::Velocity { position, .. } => *position,
+            BoundaryMotion::Trajectory(f) => f(time),
+        }
+    }
+}
+
 /// A boundary object.
 ///
 /// A boundary object is composed of static particles, or of particles coupled with non-fluid bodies.
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
 pub struct Boundary {
     /// The world-space position of the boundary particles.
     pub positions: Vec<Point<Real>>,
@@ -19,8 +118,33 @@ pub struct Boundary {
     /// If this is set to `None` (which is the default), the boundary won't receive any
     /// force for fluids.
     pub forces: Option<RwLock<Vec<Vector<Real>>>>,
+    /// Determines when the forces applied to this boundary are reset to zero.
+    pub force_clearing_policy: ForceClearingPolicy,
+    num_accumulated_substeps: usize,
+    /// Multiplier applied to the adhesion coefficient of the forces making fluids stick to this boundary.
+    ///
+    /// This defaults to `1.0`. Set it to `0.0` for a boundary that fluids should not stick to.
+    pub adhesion_coefficient: Real,
+    /// The fixed temperature of this boundary.
+    ///
+    /// If `None` (the default), this boundary does not exchange heat with fluids.
+    pub temperature: Option<Real>,
     /// Determines which other particles is allowed to interact with.
     pub interaction_groups: InteractionGroups,
+    density_map: Option<Arc<DensityMap>>,
+    density_map_position: Isometry<Real>,
+    // The fluid and particle index of the fluid particle each virtual particle is generated for.
+    pub(crate) density_map_particles: Vec<(usize, usize)>,
+    motion: Option<BoundaryMotion>,
+    local_positions: Vec<Point<Real>>,
+    local_density_map_position: Isometry<Real>,
+    // The origin, linear velocity, and angular velocity of the motion during the current substep.
+    motion_velocity: (Point<Real>, Vector<Real>, AngularVector<Real>),
+    // The pose of the shape this boundary was sampled from.
+    shape_pose: Isometry<Real>,
+    // The local AABB of the shape, and the particle radius, this boundary was last sampled with.
+    #[cfg(feature = "sampling")]
+    sampled_shape: Option<(Aabb, Real)>,
 }
 
 impl Boundary {
@@ -42,8 +166,269 @@ impl Boundary {
             velocities,
             volumes,
             forces: None,
+            force_clearing_policy: ForceClearingPolicy::default(),
+            num_accumulated_substeps: 0,
+            adhesion_coefficient: na::one::<Real>(),
+            temperature: None,
             interaction_groups,
+            density_map: None,
+            density_map_position: Isometry::identity(),
+            density_map_particles: Vec::new(),
+            motion: None,
+            local_positions: Vec::new(),
+            local_density_map_position: Isometry::identity(),
+            motion_velocity: (Point::origin(), Vector::zeros(), AngularVector::zeros()),
+            shape_pose: Isometry::identity(),
+            #[cfg(feature = "sampling")]
+            sampled_shape: None,
+        }
+    }
+
+    /// Initialize a boundary object covering the surface of `shape` placed at `pose`.
+    ///
+    /// The surface is sampled with `sampling::shape_surface_sample`, which supports all the
+    /// standard parry shapes. The boundary interacts with all the fluids: change its
+    /// `interaction_groups` to restrict them. Use `Self::resample` when the shape or the particle
+    /// radius changes.
+    #[cfg(feature = "sampling")]
+    pub fn from_shape(shape: &dyn Shape, particle_radius: Real, pose: Isometry<Real>) -> Self {
+        let mut boundary = Self::new(Vec::new(), InteractionGroups::default());
+        boundary.shape_pose = pose;
+        let _ = boundary.resample(shape, particle_radius);
+        boundary
+    }
+
+    /// Resamples the particles of this boundary from `shape` if its local-space AABB, or the
+    /// particle radius, changed since the last sampling.
+    ///
+    /// This can be called at each frame to follow a collider whose shape is scaled. The shape is
+    /// placed at the pose given to `Self::from_shape`, moved by the subsequent calls to
+    /// `Self::transform_by`. The particles of a kinematic boundary are placed by its motion at the
+    /// next substep. Returns `true` if the particles were resampled, in which case the forces
+    /// accumulated on the boundary are reset.
+    #[cfg(feature = "sampling")]
+    pub fn resample(&mut self, shape: &dyn Shape, particle_radius: Real) -> bool {
+        let aabb = shape.compute_local_aabb();
+
+        if self.sampled_shape == Some((aabb, particle_radius)) {
+            return false;
         }
+
+        let points = crate::sampling::shape_surface_sample(shape, particle_radius);
+        self.positions.clear();
+        self.positions
+            .extend(points.iter().map(|pt| self.shape_pose * pt));
+
+        if self.motion.is_some() {
+            self.local_positions = self.positions.clone();
+        }
+
+        self.velocities.clear();
+
+        for i in 0..self.positions.len() {
+            let velocity = self.motion_velocity_at(&self.positions[i]);
+            self.velocities.push(velocity);
+        }
+
+        self.volumes.clear();
+        self.volumes
+            .resize(self.positions.len(), na::zero::<Real>());
+        self.clear_forces(true);
+        self.sampled_shape = Some((aabb, particle_radius));
+        true
+    }
+
+    /// Initialize a boundary object represented by a density map instead of particles.
+    ///
+    /// The density map is placed at `position`, and can be moved with `Self::set_motion`. At each substep,
+    /// this boundary gets one virtual particle, on its surface, for each fluid particle closer
+    /// than the kernel radius. The contribution of this virtual particle to the density of the
+    /// fluid particle, and its gradient, are read from the density map, so the particles of this
+    /// boundary must not be modified manually.
+    pub fn with_density_map(
+        density_map: Arc<DensityMap>,
+        position: Isometry<Real>,
+        interaction_groups: InteractionGroups,
+    ) -> Self {
+        let mut boundary = Self::new(Vec::new(), interaction_groups);
+        boundary.density_map = Some(density_map);
+        boundary.density_map_position = position;
+        boundary
+    }
+
+    /// The density map representing this boundary, if any.
+    pub fn density_map(&self) -> Option<&DensityMap> {
+        self.density_map.as_deref()
+    }
+
+    /// The position of the density map representing this boundary.
+    pub fn density_map_position(&self) -> &Isometry<Real> {
+        &self.density_map_position
+    }
+
+    /// Sets the position of the density map representing this boundary.
+    pub fn set_density_map_position(&mut self, position: Isometry<Real>) {
+        self.density_map_position = position;
+    }
+
+    /// The prescribed motion of this boundary, if it is kinematic.
+    pub fn motion(&self) -> Option<&BoundaryMotion> {
+        self.motion.as_ref()
+    }
+
+    /// The mutable prescribed motion of this boundary, if it is kinematic.
+    pub fn motion_mut(&mut self) -> Option<&mut BoundaryMotion> {
+        self.motion.as_mut()
+    }
+
+    /// Sets the prescribed motion of this boundary, or makes it non-kinematic if `motion` is `None`.
+    ///
+    /// The current particle positions, and density map position, are taken as the local-space
+    /// positions of the boundary: at each substep, the liquid world sets them to their image by
+    /// the position of the motion, and sets the particle velocities accordingly.
+    pub fn set_motion(&mut self, motion: Option<BoundaryMotion>) {
+        if self.motion.is_none() {
+            self.local_positions = self.positions.clone();
+            self.local_density_map_position = self.density_map_position;
+        }
+
+        if motion.is_none() {
+            self.local_positions = Vec::new();
+            self.motion_velocity = (Point::origin(), Vector::zeros(), AngularVector::zeros());
+            self.velocities.fill(Vector::zeros());
+        }
+
+        self.motion = motion;
+    }
+
+    /// Moves this boundary to the position of its prescribed motion at `time`.
+    ///
+    /// The particle velocities are set to the velocity of the motion during the next `dt` seconds.
+    pub(crate) fn update_motion(&mut self, time: Real, dt: Real) {
+        let Some(motion) = &self.motion else {
+            return;
+        };
+
+        let position = motion.position_at(time);
+        let mut origin = Point::from(position.translation.vector);
+        let mut linvel = Vector::zeros();
+        let mut angvel = AngularVector::zeros();
+
+        match motion {
+            BoundaryMotion::Velocity {
+                linvel: v,
+                angvel: w,
+                local_pivot,
+                ..
+            } => {
+                origin = position * local_pivot;
+                linvel = *v;
+                angvel = *w;
+            }
+            BoundaryMotion::Trajectory(_) => {
+                if dt > na::zero::<Real>() {
+                    let next_position = motion.position_at(time + dt);
+                    let rotation = next_position.rotation * position.rotation.inverse();
+                    linvel = (next_position.translation.vector - position.translation.vector) / dt;
+
+                    #[cfg(feature = "dim2")]
+                    {
+                        angvel = AngularVector::new(rotation.angle() / dt);
+                    }
+
+                    #[cfg(feature = "dim3")]
+                    {
+                        angvel = rotation.scaled_axis() / dt;
+                    }
+                }
+            }
+        }
+
+        self.motion_velocity = (origin, linvel, angvel);
+        self.density_map_position = position * self.local_density_map_position;
+
+        if self.density_map.is_none() {
+            self.positions.clear();
+            self.positions
+                .extend(self.local_positions.iter().map(|pt| position * pt));
+            self.velocities.clear();
+
+            for i in 0..self.positions.len() {
+                let velocity = self.motion_velocity_at(&self.positions[i]);
+                self.velocities.push(velocity);
+            }
+        }
+    }
+
+    /// Integrates the position of a boundary moving with `BoundaryMotion::Velocity`.
+    pub(crate) fn integrate_motion(&mut self, dt: Real) {
+        if let Some(BoundaryMotion::Velocity {
+            position,
+            linvel,
+            angvel,
+            local_pivot,
+        }) = &mut self.motion
+        {
+            #[cfg(feature = "dim2")]
+            let rotation = Rotation::new(angvel.x * dt);
+            #[cfg(feature = "dim3")]
+            let rotation = Rotation::new(*angvel * dt);
+
+            let pivot = *position * *local_pivot;
+            *position = Isometry::rotation_wrt_point(rotation, pivot) * *position;
+            position.translation.vector += *linvel * dt;
+        }
+    }
+
+    // The velocity, at the given world-space point, of the motion during the current substep.
+    fn motion_velocity_at(&self, pt: &Point<Real>) -> Vector<Real> {
+        let (origin, linvel, angvel) = &self.motion_velocity;
+        let arm = pt - origin;
+
+        #[cfg(feature = "dim2")]
+        {
+            linvel + Vector::new(-arm.y, arm.x) * angvel.x
+        }
+
+        #[cfg(feature = "dim3")]
+        {
+            linvel + angvel.cross(&arm)
+        }
+    }
+
+    /// Regenerates the virtual particles of a boundary represented by a density map.
+    pub(crate) fn update_density_map_particles(&mut self, fluids: &[Fluid]) {
+        let Some(density_map) = &self.density_map else {
+            return;
+        };
+
+        self.positions.clear();
+        self.velocities.clear();
+        self.volumes.clear();
+        self.density_map_particles.clear();
+
+        for (fluid_id, fluid) in fluids.iter().enumerate() {
+            if !self.interaction_groups.test(fluid.interaction_groups) {
+                continue;
+            }
+
+            for (i, pt) in fluid.positions.iter().enumerate() {
+                let local_pt = self.density_map_position.inverse_transform_point(pt);
+
+                if density_map.density(&local_pt) > na::zero::<Real>() {
+                    let surface_pt = density_map.surface_point(&local_pt).unwrap_or(local_pt);
+                    let world_pt = self.density_map_position * surface_pt;
+                    self.positions.push(world_pt);
+                    self.velocities.push(self.motion_velocity_at(&world_pt));
+                    // The density map already accounts for the volume of the boundary.
+                    self.volumes.push(na::one::<Real>());
+                    self.density_map_particles.push((fluid_id, i));
+                }
+            }
+        }
+
+        // The forces accumulated on the previous particles can't be attributed to the new ones.
+        self.clear_forces(true);
     }
 
     /// The number of particles of this boundary object.
@@ -54,6 +439,8 @@ impl Boundary {
     /// Transforms all the particle positions of this boundary by the given isometry.
     pub fn transform_by(&mut self, pose: &Isometry<Real>) {
         self.positions.iter_mut().for_each(|p| *p = pose * *p);
+        self.density_map_position = pose * self.density_map_position;
+        self.shape_pose = pose * self.shape_pose;
     }
 
     /// Apply a force `f` to the `i`-th particle of this boundary object.
@@ -66,8 +453,111 @@ impl Boundary {
         }
     }
 
+    /// Apply an impulse `impulse`, spread over a substep of length `dt`, to the `i`-th particle of
+    /// this boundary object.
+    ///
+    /// This accumulates the force `impulse / dt`, and relies on thread-safe interior mutability.
+    pub fn apply_impulse(&self, i: usize, impulse: Vector<Real>, dt: Real) {
+        if dt > na::zero::<Real>() {
+            self.apply_force(i, impulse / dt);
+        }
+    }
+
+    /// Whether the forces applied to the particles of this boundary are accumulated.
+    ///
+    /// Forces applied to a boundary that doesn't receive forces are ignored, so their computation
+    /// can be skipped.
+    pub fn receives_forces(&self) -> bool {
+        self.forces.is_some()
+    }
+
+    /// The forces accumulated on each particle of this boundary since they were last cleared.
+    ///
+    /// The particles of boundaries represented by a density map, or sampled dynamically by a
+    /// coupling, are regenerated at each substep, with different indices. Their forces are then
+    /// cleared at each substep, whatever the `force_clearing_policy`, so they only contain the
+    /// forces of the last substep. Returns `None` if this boundary does not receive forces.
+    pub fn accumulated_forces(&mut self) -> Option<&[Vector<Real>]> {
+        self.forces.as_mut().map(|f| &f.get_mut().unwrap()[..])
+    }
+
+    /// The sum of the forces accumulated on the particles of this boundary since they were last
+    /// cleared.
+    ///
+    /// This includes the pressure, viscosity, and surface tension forces applied by the fluids.
+    /// Divide it by `Self::num_accumulated_substeps` to obtain the average force over the step,
+    /// e.g., to apply it to a rigid-body simulated by another engine. Returns `None` if this
+    /// boundary does not receive forces.
+    pub fn accumulated_force(&self) -> Option<Vector<Real>> {
+        self.forces.as_ref().map(|f| {
+            f.read()
+                .unwrap()
+                .iter()
+                .fold(Vector::zeros(), |acc, f| acc + f)
+        })
+    }
+
+    /// The sum of the torques, relative to `center`, of the forces accumulated on the particles
+    /// of this boundary since they were last cleared.
+    ///
+    /// Returns `None` if this boundary does not receive forces.
+    pub fn accumulated_torque(&self, center: &Point<Real>) -> Option<AngularVector<Real>> {
+        self.forces.as_ref().map(|f| {
+            self.positions.iter().zip(f.read().unwrap().iter()).fold(
+                AngularVector::zeros(),
+                |acc, (pos, f)| {
+                    let arm = pos - center;
+
+                    #[cfg(feature = "dim2")]
+                    {
+                        acc + AngularVector::new(arm.perp(f))
+                    }
+
+                    #[cfg(feature = "dim3")]
+                    {
+                        acc + arm.cross(f)
+                    }
+                },
+            )
+        })
+    }
+
+    /// The number of substeps executed since the forces of this boundary were last cleared.
+    ///
+    /// This can be used to average the accumulated forces over a frame split into a variable
+    /// number of substeps.
+    pub fn num_accumulated_substeps(&self) -> usize {
+        self.num_accumulated_substeps
+    }
+
+    /// Enables or disables the accumulation of forces applied by fluids to this boundary.
+    pub fn enable_forces(&mut self, enabled: bool) {
+        if !enabled {
+            self.forces = None;
+        } else if self.forces.is_none() {
+            self.forces = Some(RwLock::new(vec![Vector::zeros(); self.positions.len()]));
+            self.num_accumulated_substeps = 0;
+        }
+    }
+
+    /// Resizes the force buffer so it matches the number of particles, without clearing it.
+    pub(crate) fn resize_forces(&mut self) {
+        if let Some(forces) = &mut self.forces {
+            forces
+                .get_mut()
+                .unwrap()
+                .resize(self.positions.len(), Vector::zeros());
+        }
+    }
+
+    pub(crate) fn notify_substep(&mut self) {
+        self.num_accumulated_substeps += 1;
+    }
+
     /// Clears all the forces applied to this boundary object's particles.
     pub fn clear_forces(&mut self, resize_buffer: bool) {
+        self.num_accumulated_substeps = 0;
+
         if let Some(forces) = &mut self.forces {
             let forces = forces.get_mut().unwrap();
 
@@ -84,6 +574,10 @@ impl Boundary {
 
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
 /// The unique identifier of a boundary object.
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
 pub struct BoundaryHandle(ContiguousArenaIndex);
 /// A set of all boundary objects.
 pub type BoundarySet = ContiguousArena<BoundaryHandle, Boundary>;
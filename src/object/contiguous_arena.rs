@@ -9,10 +9,15 @@ pub type ContiguousArenaIndex = Index;
 ///
 /// The goal of this structure is to have unique identifiers for elements
 /// stored contiguously on a `Vec`.
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
 pub struct ContiguousArena<Idx, T> {
     objects: Vec<T>,
     rev_indices: Vec<Index>,
     indices: Arena<usize>,
+    #[cfg_attr(feature = "serde-serialize", serde(skip))]
     phantoms: PhantomData<Idx>,
 }
 
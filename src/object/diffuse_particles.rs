@@ -0,0 +1,79 @@
+use crate::math::{Point, Real, Vector};
+
+/// The kind of a diffuse particle, determined by the number of fluid particles around it.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub enum DiffuseParticleKind {
+    /// A droplet in the air, with almost no fluid particle around it.
+    Spray,
+    /// A particle at the surface of the fluid, advected by the fluid.
+    Foam,
+    /// An air bubble inside of the fluid.
+    Bubble,
+}
+
+/// A set of diffuse particles (spray, foam, and bubbles).
+///
+/// Diffuse particles are generated by the `DiffuseParticleSolver` where the fluid traps air or
+/// forms wave crests. They are advected by the fluid but do not affect it, and are meant for
+/// rendering only.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub struct DiffuseParticles {
+    /// The world-space position of the diffuse particles.
+    pub positions: Vec<Point<Real>>,
+    /// The velocities of the diffuse particles.
+    pub velocities: Vec<Vector<Real>>,
+    /// The remaining lifetime of the diffuse particles, in seconds.
+    pub lifetimes: Vec<Real>,
+    /// The kind of each diffuse particle, as determined during the last substep.
+    pub kinds: Vec<DiffuseParticleKind>,
+}
+
+impl DiffuseParticles {
+    /// Initializes an empty set of diffuse particles.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of diffuse particles.
+    pub fn num_particles(&self) -> usize {
+        self.positions.len()
+    }
+
+    /// Removes all the diffuse particles.
+    pub fn clear(&mut self) {
+        self.positions.clear();
+        self.velocities.clear();
+        self.lifetimes.clear();
+        self.kinds.clear();
+    }
+
+    /// Adds a diffuse particle.
+    pub fn push(
+        &mut self,
+        position: Point<Real>,
+        velocity: Vector<Real>,
+        lifetime: Real,
+        kind: DiffuseParticleKind,
+    ) {
+        self.positions.push(position);
+        self.velocities.push(velocity);
+        self.lifetimes.push(lifetime);
+        self.kinds.push(kind);
+    }
+
+    /// Removes the particles with a `true` value in the given mask.
+    pub(crate) fn remove_from_mask(&mut self, mask: &[bool]) {
+        crate::helper::filter_from_mask(mask, &mut self.positions);
+        crate::helper::filter_from_mask(mask, &mut self.velocities);
+        crate::helper::filter_from_mask(mask, &mut self.lifetimes);
+        crate::helper::filter_from_mask(mask, &mut self.kinds);
+    }
+}
@@ -0,0 +1,308 @@
+use crate::math::{Point, Real, Vector, DIM};
+use crate::object::{ContiguousArena, ContiguousArenaIndex, EmissionRequest, FluidHandle};
+use crate::random::Rng;
+
+/// The region from which an emitter spawns particles.
+#[derive(Clone, Debug)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub enum EmitterShape {
+    /// All the particles are spawned at a single point.
+    Point(Point<Real>),
+    /// The particles are spawned uniformly on a disk.
+    ///
+    /// In 2D, this is the segment of half-length `radius` orthogonal to `normal`.
+    Disk {
+        /// The center of the disk.
+        center: Point<Real>,
+        /// The normal of the disk.
+        normal: Vector<Real>,
+        /// The radius of the disk.
+        radius: Real,
+    },
+    /// The particles are spawned uniformly on the surface of a triangle mesh.
+    ///
+    /// In 2D, this is a polyline and each element of `indices` identifies a segment.
+    Mesh {
+        /// The vertices of the mesh.
+        vertices: Vec<Point<Real>>,
+        /// The triangles (or segments in 2D) of the mesh.
+        indices: Vec<[u32; DIM]>,
+    },
+}
+
+impl EmitterShape {
+    /// The point used to represent the location of this shape, e.g., when arbitrating a
+    /// particle budget.
+    pub fn center(&self) -> Point<Real> {
+        match self {
+            EmitterShape::Point(point) => *point,
+            EmitterShape::Disk { center, .. } => *center,
+            EmitterShape::Mesh { vertices, .. } => {
+                if vertices.is_empty() {
+                    Point::origin()
+                } else {
+                    let sum = vertices
+                        .iter()
+                        .fold(Vector::zeros(), |acc, v| acc + v.coords);
+                    Point::from(sum / na::convert::<_, Real>(vertices.len() as f64))
+                }
+            }
+        }
+    }
+
+    pub(crate) fn element_measure(vertices: &[Point<Real>], element: &[u32; DIM]) -> Real {
+        let a = vertices[element[0] as usize];
+        let b = vertices[element[1] as usize];
+
+        #[cfg(feature = "dim2")]
+        {
+            na::distance(&a, &b)
+        }
+
+        #[cfg(feature = "dim3")]
+        {
+            let c = vertices[element[2] as usize];
+            (b - a).cross(&(c - a)).norm() * na::convert::<_, Real>(0.5)
+        }
+    }
+
+    pub(crate) fn sample_element(
+        rng: &mut Rng,
+        vertices: &[Point<Real>],
+        element: &[u32; DIM],
+    ) -> Point<Real> {
+        let a = vertices[element[0] as usize];
+        let b = vertices[element[1] as usize];
+
+        #[cfg(feature = "dim2")]
+        {
+            a + (b - a) * rng.next_real()
+        }
+
+        #[cfg(feature = "dim3")]
+        {
+            let c = vertices[element[2] as usize];
+            let mut u = rng.next_real();
+            let mut v = rng.next_real();
+
+            if u + v > na::one::<Real>() {
+                u = na::one::<Real>() - u;
+                v = na::one::<Real>() - v;
+            }
+
+            a + (b - a) * u + (c - a) * v
+        }
+    }
+}
+
+/// An object spawning new particles into a fluid over time.
+///
+/// Emitters are added to the world with `LiquidWorld::add_emitter`. At the beginning of each
+/// step, every enabled emitter requests `rate * dt` particles (fractional particles are carried
+/// over to the next steps). The requests are arbitrated with the particle budget of the world,
+/// if any, before the particles are added to the target fluid.
+#[derive(Clone, Debug)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub struct Emitter {
+    /// The fluid the particles are added to.
+    pub fluid: FluidHandle,
+    /// The region from which the particles are spawned.
+    pub shape: EmitterShape,
+    /// The number of particles spawned per second.
+    pub rate: Real,
+    /// The initial velocity of the spawned particles.
+    pub velocity: Vector<Real>,
+    /// The maximum random offset added to each coordinate of the spawned particles' positions.
+    ///
+    /// Defaults to zero.
+    pub jitter: Real,
+    /// The lifetime of the spawned particles, in seconds.
+    ///
+    /// If `None` (the default), the particles live until they are removed explicitly.
+    pub lifetime: Option<Real>,
+    /// The user data attached to the spawned particles, see `Fluid::user_data`.
+    ///
+    /// If `None` (the default), the spawned particles have no user data.
+    pub user_data: Option<u128>,
+    /// Whether this emitter spawns particles.
+    pub enabled: bool,
+    /// The priority of the emission requests of this emitter, see `EmissionRequest::priority`.
+    pub priority: i32,
+    /// The weight of the emission requests of this emitter, see `EmissionRequest::weight`.
+    pub weight: Real,
+    accumulated: Real,
+    rng: Rng,
+}
+
+impl Emitter {
+    /// Creates an emitter spawning `rate` particles per second from `shape` into `fluid`.
+    pub fn new(fluid: FluidHandle, shape: EmitterShape, rate: Real) -> Self {
+        Self {
+            fluid,
+            shape,
+            rate,
+            velocity: Vector::zeros(),
+            jitter: na::zero::<Real>(),
+            lifetime: None,
+            user_data: None,
+            enabled: true,
+            priority: 0,
+            weight: na::one::<Real>(),
+            accumulated: na::zero::<Real>(),
+            rng: Rng::new(0x2545_f491_4f6c_dd1d),
+        }
+    }
+
+    /// Sets the initial velocity of the spawned particles.
+    pub fn with_velocity(mut self, velocity: Vector<Real>) -> Self {
+        self.velocity = velocity;
+        self
+    }
+
+    /// Sets the maximum random offset added to the positions of the spawned particles.
+    pub fn with_jitter(mut self, jitter: Real) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Sets the lifetime of the spawned particles.
+    pub fn with_lifetime(mut self, lifetime: Real) -> Self {
+        self.lifetime = Some(lifetime);
+        self
+    }
+
+    /// Sets the user data attached to the spawned particles.
+    pub fn with_user_data(mut self, user_data: u128) -> Self {
+        self.user_data = Some(user_data);
+        self
+    }
+
+    /// Sets the priority of the emission requests of this emitter.
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Sets the weight of the emission requests of this emitter.
+    pub fn with_weight(mut self, weight: Real) -> Self {
+        self.weight = weight;
+        self
+    }
+
+    /// Sets the seed of the random number generator used to sample the spawned particles.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = Rng::new(seed);
+        self
+    }
+
+    /// Accumulates the particles to spawn during a step of length `dt`, and returns the
+    /// corresponding emission request.
+    pub(crate) fn request(&mut self, dt: Real) -> EmissionRequest {
+        if self.enabled {
+            self.accumulated += self.rate * dt;
+        }
+
+        let num_particles = na::try_convert::<Real, f64>(self.accumulated.max(na::zero::<Real>()))
+            .unwrap()
+            .floor() as usize;
+
+        EmissionRequest::new(num_particles, self.shape.center())
+            .with_priority(self.priority)
+            .with_weight(self.weight)
+    }
+
+    /// Generates the positions and velocities of `num_granted` particles out of the
+    /// `num_requested` ones.
+    ///
+    /// Particles that were not granted are dropped instead of being carried over to the next
+    /// step, to avoid bursts once the particle budget becomes available again.
+    pub(crate) fn emit(
+        &mut self,
+        dt: Real,
+        num_requested: usize,
+        num_granted: usize,
+    ) -> (Vec<Point<Real>>, Vec<Vector<Real>>) {
+        self.accumulated -= na::convert::<_, Real>(num_requested as f64);
+
+        let cumulative_measures = match &self.shape {
+            EmitterShape::Mesh { vertices, indices } => {
+                let mut total = na::zero::<Real>();
+                indices
+                    .iter()
+                    .map(|element| {
+                        total += EmitterShape::element_measure(vertices, element);
+                        total
+                    })
+                    .collect()
+            }
+            _ => Vec::new(),
+        };
+
+        let mut positions = Vec::with_capacity(num_granted);
+
+        for i in 0..num_granted {
+            let base = match &self.shape {
+                EmitterShape::Point(point) => *point,
+                EmitterShape::Disk {
+                    center,
+                    normal,
+                    radius,
+                } => center + self.rng.disk_point(normal, *radius),
+                EmitterShape::Mesh { vertices, indices } => match cumulative_measures.last() {
+                    Some(total) if *total > na::zero::<Real>() => {
+                        let target = self.rng.next_real() * *total;
+                        let element = cumulative_measures
+                            .partition_point(|m| *m <= target)
+                            .min(indices.len() - 1);
+                        EmitterShape::sample_element(&mut self.rng, vertices, &indices[element])
+                    }
+                    _ => self.shape.center(),
+                },
+            };
+
+            let mut jitter = Vector::zeros();
+            for k in 0..DIM {
+                jitter[k] = (self.rng.next_real() * na::convert::<_, Real>(2.0)
+                    - na::one::<Real>())
+                    * self.jitter;
+            }
+
+            // Spread the particles spawned during this step along their velocity, as if they
+            // had been emitted continuously.
+            let age = dt * na::convert::<_, Real>(i as f64 / num_granted as f64);
+            positions.push(base + jitter + self.velocity * age);
+        }
+
+        (positions, vec![self.velocity; num_granted])
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+/// The unique identifier of an emitter.
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub struct EmitterHandle(ContiguousArenaIndex);
+/// A set of all emitters.
+pub type EmitterSet = ContiguousArena<EmitterHandle, Emitter>;
+
+impl From<ContiguousArenaIndex> for EmitterHandle {
+    #[inline]
+    fn from(i: ContiguousArenaIndex) -> Self {
+        EmitterHandle(i)
+    }
+}
+
+impl From<EmitterHandle> for ContiguousArenaIndex {
+    #[inline]
+    fn from(handle: EmitterHandle) -> Self {
+        handle.0
+    }
+}
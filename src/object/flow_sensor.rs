@@ -0,0 +1,204 @@
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use crate::math::{Point, Real, Vector};
+use crate::object::{ContiguousArena, ContiguousArenaIndex, Fluid};
+#[cfg(feature = "parry")]
+use {crate::math::Isometry, parry::shape::SharedShape};
+
+/// The geometry of a flow sensor.
+#[derive(Clone)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub enum FlowSensorShape {
+    /// An infinite plane.
+    ///
+    /// The fluid crossing the plane in the direction of `normal` is counted positively.
+    Plane {
+        /// A point on the plane.
+        point: Point<Real>,
+        /// The normal of the plane.
+        normal: Vector<Real>,
+    },
+    /// A disk (a segment in 2D).
+    ///
+    /// The fluid crossing the disk in the direction of `normal` is counted positively.
+    Disk {
+        /// The center of the disk.
+        center: Point<Real>,
+        /// The normal of the disk.
+        normal: Vector<Real>,
+        /// The radius of the disk (the half-length of the segment in 2D).
+        radius: Real,
+    },
+    /// A closed shape.
+    ///
+    /// The fluid entering the shape is counted positively, and the fluid leaving it negatively.
+    #[cfg(feature = "parry")]
+    Shape {
+        /// The position of the shape.
+        position: Isometry<Real>,
+        /// The shape.
+        shape: SharedShape,
+    },
+}
+
+impl FlowSensorShape {
+    // The signed number of times the segment from `start` to `end` crosses this shape.
+    fn crossing(&self, start: &Point<Real>, end: &Point<Real>) -> Real {
+        match self {
+            FlowSensorShape::Plane { point, normal } => {
+                let d0 = normal.dot(&(start - point));
+                let d1 = normal.dot(&(end - point));
+                plane_crossing(d0, d1)
+            }
+            FlowSensorShape::Disk {
+                center,
+                normal,
+                radius,
+            } => {
+                let d0 = normal.dot(&(start - center));
+                let d1 = normal.dot(&(end - center));
+                let crossing = plane_crossing(d0, d1);
+
+                if crossing != na::zero::<Real>() {
+                    let hit = start + (end - start) * (d0 / (d0 - d1));
+
+                    if na::distance(&hit, center) <= *radius {
+                        return crossing;
+                    }
+                }
+
+                na::zero::<Real>()
+            }
+            #[cfg(feature = "parry")]
+            FlowSensorShape::Shape { position, shape } => {
+                let was_inside = shape.contains_point(position, start);
+                let is_inside = shape.contains_point(position, end);
+
+                match (was_inside, is_inside) {
+                    (false, true) => na::one::<Real>(),
+                    (true, false) => -na::one::<Real>(),
+                    _ => na::zero::<Real>(),
+                }
+            }
+        }
+    }
+}
+
+fn plane_crossing(d0: Real, d1: Real) -> Real {
+    if d0 < na::zero::<Real>() && d1 >= na::zero::<Real>() {
+        na::one::<Real>()
+    } else if d0 >= na::zero::<Real>() && d1 < na::zero::<Real>() {
+        -na::one::<Real>()
+    } else {
+        na::zero::<Real>()
+    }
+}
+
+/// A sensor measuring the mass of fluid crossing a plane, a disk, or the boundary of a shape.
+///
+/// Flow sensors are added to the liquid world with `LiquidWorld::add_flow_sensor` and updated at
+/// each step. They do not affect the fluids.
+#[derive(Clone)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub struct FlowSensor {
+    /// The geometry of this sensor.
+    pub shape: FlowSensorShape,
+    step_mass: Real,
+    mass_flux: Real,
+    flow_rate: Real,
+    total_mass: Real,
+}
+
+impl FlowSensor {
+    /// Initializes a flow sensor with the given geometry.
+    pub fn new(shape: FlowSensorShape) -> Self {
+        Self {
+            shape,
+            step_mass: na::zero::<Real>(),
+            mass_flux: na::zero::<Real>(),
+            flow_rate: na::zero::<Real>(),
+            total_mass: na::zero::<Real>(),
+        }
+    }
+
+    /// The net mass of fluid that crossed this sensor during the last step.
+    pub fn mass_flux(&self) -> Real {
+        self.mass_flux
+    }
+
+    /// The net mass of fluid that crossed this sensor per unit of time during the last step.
+    pub fn flow_rate(&self) -> Real {
+        self.flow_rate
+    }
+
+    /// The net mass of fluid that crossed this sensor since its creation or its last reset.
+    pub fn total_mass(&self) -> Real {
+        self.total_mass
+    }
+
+    /// Resets the accumulated mass of this sensor to zero.
+    pub fn reset(&mut self) {
+        self.total_mass = na::zero::<Real>();
+    }
+
+    pub(crate) fn begin_step(&mut self) {
+        self.step_mass = na::zero::<Real>();
+    }
+
+    pub(crate) fn measure_substep(
+        &mut self,
+        previous_positions: &[Vec<Point<Real>>],
+        fluids: &[Fluid],
+    ) {
+        let shape = &self.shape;
+
+        for (fluid, previous_positions) in fluids.iter().zip(previous_positions.iter()) {
+            let it = par_iter!(fluid.positions)
+                .zip(par_iter!(previous_positions))
+                .enumerate()
+                .map(|(i, (pos, prev_pos))| shape.crossing(prev_pos, pos) * fluid.particle_mass(i));
+            self.step_mass += par_reduce_sum!(na::zero::<Real>(), it);
+        }
+    }
+
+    pub(crate) fn end_step(&mut self, dt: Real) {
+        self.mass_flux = self.step_mass;
+        self.total_mass += self.step_mass;
+        self.flow_rate = if dt > na::zero::<Real>() {
+            self.step_mass / dt
+        } else {
+            na::zero::<Real>()
+        };
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+/// The unique identifier of a flow sensor.
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub struct FlowSensorHandle(ContiguousArenaIndex);
+/// A set of all flow sensors.
+pub type FlowSensorSet = ContiguousArena<FlowSensorHandle, FlowSensor>;
+
+impl From<ContiguousArenaIndex> for FlowSensorHandle {
+    #[inline]
+    fn from(i: ContiguousArenaIndex) -> Self {
+        FlowSensorHandle(i)
+    }
+}
+
+impl From<FlowSensorHandle> for ContiguousArenaIndex {
+    #[inline]
+    fn from(handle: FlowSensorHandle) -> Self {
+        handle.0
+    }
+}
@@ -6,11 +6,56 @@ use num::Zero;
 
 use super::interaction_groups::InteractionGroups;
 
+/// The criterion used to detect the fluid particles located at the free surface.
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub enum SurfaceDetection {
+    /// A particle is at the surface if the norm of the gradient of its color field, multiplied
+    /// by the kernel radius, is greater than `threshold`.
+    ///
+    /// The color field gradient is zero inside of the fluid, and grows where the neighborhood of
+    /// a particle is incomplete.
+    ColorFieldGradient {
+        /// The threshold above which a particle is considered at the surface.
+        threshold: Real,
+    },
+    /// A particle is at the surface if the distance between its position and the center of mass
+    /// of its neighborhood, divided by the kernel radius, is greater than `threshold`.
+    CenterOfMassOffset {
+        /// The threshold above which a particle is considered at the surface.
+        threshold: Real,
+    },
+}
+
+impl SurfaceDetection {
+    /// The color field gradient criterion, with a threshold suitable for most fluids.
+    pub fn color_field_gradient() -> Self {
+        SurfaceDetection::ColorFieldGradient {
+            threshold: na::convert::<_, Real>(0.8),
+        }
+    }
+
+    /// The center of mass offset criterion, with a threshold suitable for most fluids.
+    pub fn center_of_mass_offset() -> Self {
+        SurfaceDetection::CenterOfMassOffset {
+            threshold: na::convert::<_, Real>(0.08),
+        }
+    }
+}
+
 /// A fluid object.
 ///
 /// A fluid object is composed of movable particles with additional properties like viscosity.
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
 pub struct Fluid {
     /// Nonpressure forces this fluid is subject to.
+    #[cfg_attr(feature = "serde-serialize", serde(skip))]
     pub nonpressure_forces: Vec<Box<dyn NonPressureForce>>,
     /// The world-space position of the fluid particles.
     pub positions: Vec<Point<Real>>,
@@ -21,13 +66,120 @@ pub struct Fluid {
     /// The volume of the fluid particles.
     pub volumes: Vec<Real>,
     /// The rest density of this fluid.
+    ///
+    /// This is the rest density of all the particles if `rest_densities` is empty, and the rest
+    /// density given to the particles added to this fluid otherwise.
     pub density0: Real,
+    /// The rest density of each fluid particle.
+    ///
+    /// If empty (the default), all the particles have the rest density `density0`. Otherwise, it
+    /// must contain one density per particle. This allows a single fluid to represent, e.g.,
+    /// stratified brine and fresh water, or a dust-laden flow: the densities of the particles
+    /// are computed as in the density contrast formulation of multiphase fluids, so the interfaces
+    /// between particles with different rest densities remain sharp.
+    pub rest_densities: Vec<Real>,
+    /// Whether the densities of the particles of this fluid are renormalized with a Shepard filter.
+    ///
+    /// This corrects the density underestimation of the particles with a truncated neighborhood,
+    /// e.g., near free surfaces, whatever the `DensityEstimator` of the pressure solver. This
+    /// defaults to `false`.
+    pub shepard_density_correction: bool,
+    /// Whether the kernel gradients of the particles of this fluid are corrected with a
+    /// renormalization matrix.
+    ///
+    /// This makes the pressure, viscosity, and elasticity forces more accurate near free surfaces
+    /// and for disordered particle distributions, at the cost of the exact conservation of linear
+    /// and angular momentum, since the corrected gradients are no longer antisymmetric. This
+    /// defaults to `false`.
+    pub kernel_gradient_correction: bool,
+    /// The gravity applied to this fluid instead of the gravity of the liquid world.
+    ///
+    /// If `None` (the default), the gravity given to `LiquidWorld::step` is used.
+    pub gravity: Option<Vector<Real>>,
+    /// The factor multiplying the gravity applied to this fluid.
+    ///
+    /// This defaults to `1.0`. A negative scale makes the fluid rise, e.g., for gas-like fluids.
+    pub gravity_scale: Real,
+    /// Smoothed velocities of the fluid particles, meant for rendering only.
+    ///
+    /// They are updated at the end of each step if `render_velocity_smoothing` is set, and are
+    /// never used by the simulation itself. This vector is empty otherwise.
+    pub render_velocities: Vec<Vector<Real>>,
+    /// The blending factor between the raw particle velocities (`0.0`) and the velocities averaged
+    /// over their neighborhood (`1.0`) used to compute the `render_velocities`.
+    ///
+    /// If `None` (the default), the render velocities are not computed.
+    pub render_velocity_smoothing: Option<Real>,
+    /// The positions of the fluid particles at the beginning of the last fixed step, meant for
+    /// rendering only.
+    ///
+    /// They are recorded by `LiquidWorld::step_interpolated` to interpolate the particle positions
+    /// between two fixed steps (see `LiquidWorld::interpolated_positions`). This vector is empty if
+    /// the liquid world does not use a fixed timestep.
+    pub previous_positions: Vec<Point<Real>>,
+    /// Indicates, for each particle, whether it is located at the free surface of the fluid.
+    ///
+    /// Particles in contact with a boundary or with another fluid are not at the free surface.
+    /// This is updated at the end of each step if `surface_detection` is set, and is empty otherwise.
+    pub surface_particles: Vec<bool>,
+    /// Indicates, for each particle, whether it was fully simulated during the last substep.
+    ///
+    /// This is updated at the beginning of each substep if the liquid world has enabled active
+    /// regions (see `LiquidWorld::add_active_region`), and is empty otherwise.
+    pub active_particles: Vec<bool>,
+    /// The criterion used to compute the `surface_particles`.
+    ///
+    /// If `None` (the default), the surface particles are not computed.
+    pub surface_detection: Option<SurfaceDetection>,
+    /// The temperature of the fluid particles.
+    ///
+    /// This vector is empty unless temperatures are enabled with `Self::enable_temperatures`.
+    pub temperatures: Vec<Real>,
+    /// The temperature given to the particles added to this fluid.
+    ///
+    /// If `None` (the default), temperatures are not simulated for this fluid.
+    pub default_temperature: Option<Real>,
+    /// The thermal diffusivity of this fluid, in `m²/s`.
+    ///
+    /// This controls how fast heat spreads through the fluid when a `HeatSolver` is enabled.
+    pub thermal_diffusivity: Real,
+    /// The concentration of the fluid particles, i.e., the fraction of each particle made of a
+    /// dissolved substance or of another miscible fluid.
+    ///
+    /// This vector is empty unless concentrations are enabled with `Self::enable_concentrations`.
+    /// It can be used by renderers to color the particles by mixture fraction.
+    pub concentrations: Vec<Real>,
+    /// The concentration given to the particles added to this fluid.
+    ///
+    /// If `None` (the default), concentrations are not simulated for this fluid.
+    pub default_concentration: Option<Real>,
+    /// The diffusion coefficient of the concentration within this fluid, in `m²/s`.
+    ///
+    /// This controls how fast miscible fluids mix when a `ConcentrationSolver` is enabled.
+    pub concentration_diffusivity: Real,
+    /// The remaining lifetime of the fluid particles, in seconds.
+    ///
+    /// Particles are deleted automatically once their lifetime reaches zero. This vector is empty
+    /// unless particles with a limited lifetime were added (see `Emitter::lifetime`), in which
+    /// case the particles without limited lifetime have an infinite lifetime.
+    pub lifetimes: Vec<Real>,
+    /// Arbitrary user data attached to each particle.
+    ///
+    /// This vector is empty unless user data were set with `Fluid::set_particle_user_data`, in
+    /// which case the particles without user data have a user data equal to zero. It is kept
+    /// consistent when particles are added, removed, or reordered.
+    pub user_data: Vec<u128>,
     /// Mask indicating what particles have been deleted.
     deleted_particles: Vec<bool>,
     /// Indicates if a bit of the `deleted_particles` mask has been set.
     num_deleted_particles: usize,
     /// The particles radius.
     particle_radius: Real,
+    /// The kernel radius of this fluid, if it differs from the one of the liquid world.
+    ///
+    /// This is set by the liquid world from the ratio between the particle radius of this fluid
+    /// and the one of the world.
+    pub(crate) kernel_radius: Option<Real>,
     /// The groups controlling which other particles can interact with this fluid.
     /// A fluid always interacts with itself.
     pub interaction_groups: InteractionGroups,
@@ -36,10 +188,13 @@ pub struct Fluid {
 impl Fluid {
     /// Initializes a new fluid object with the given particle positions, particle radius, density, and viscosity.
     ///
-    /// The particle radius should be the same as the radius used to initialize the liquid world.
+    /// The particle radius may differ from the radius used to initialize the liquid world, in
+    /// which case the kernel radius of this fluid is scaled by the same ratio as its particles.
+    /// This allows, e.g., fine particles close to objects of interest, and coarse particles for
+    /// the rest of the scene.
     pub fn new(
         particle_positions: Vec<Point<Real>>,
-        particle_radius: Real, // XXX: remove this parameter since it is already defined by the liquid world.
+        particle_radius: Real,
         density0: Real,
         interaction_groups: InteractionGroups,
     ) -> Self {
@@ -59,14 +214,51 @@ impl Fluid {
             volumes: std::iter::repeat(particle_volume)
                 .take(num_particles)
                 .collect(),
+            render_velocities: Vec::new(),
+            previous_positions: Vec::new(),
+            render_velocity_smoothing: None,
+            surface_particles: Vec::new(),
+            active_particles: Vec::new(),
+            surface_detection: None,
+            temperatures: Vec::new(),
+            default_temperature: None,
+            thermal_diffusivity: na::zero::<Real>(),
+            concentrations: Vec::new(),
+            default_concentration: None,
+            concentration_diffusivity: na::zero::<Real>(),
+            lifetimes: Vec::new(),
+            user_data: Vec::new(),
             interaction_groups,
             deleted_particles: std::iter::repeat(false).take(num_particles).collect(),
             num_deleted_particles: 0,
             density0,
+            rest_densities: Vec::new(),
+            shepard_density_correction: false,
+            kernel_gradient_correction: false,
+            gravity: None,
+            gravity_scale: na::one::<Real>(),
             particle_radius,
+            kernel_radius: None,
         }
     }
 
+    /// Overrides the gravity applied to this fluid.
+    pub fn with_gravity(mut self, gravity: Vector<Real>) -> Self {
+        self.gravity = Some(gravity);
+        self
+    }
+
+    /// Sets the factor multiplying the gravity applied to this fluid.
+    pub fn with_gravity_scale(mut self, gravity_scale: Real) -> Self {
+        self.gravity_scale = gravity_scale;
+        self
+    }
+
+    /// The gravity actually applied to this fluid, given the gravity of the liquid world.
+    pub fn effective_gravity(&self, world_gravity: &Vector<Real>) -> Vector<Real> {
+        self.gravity.unwrap_or(*world_gravity) * self.gravity_scale
+    }
+
     /// Mark the given particle to be deleted at the next timestep.
     pub fn delete_particle_at_next_timestep(&mut self, particle: usize) {
         if !self.deleted_particles[particle] {
@@ -75,6 +267,30 @@ impl Fluid {
         }
     }
 
+    /// Marks for deletion at the next timestep every particle for which `f` returns `false`.
+    ///
+    /// The closure is given the index, position, and velocity of each particle not already marked
+    /// for deletion. The particles are removed at the beginning of the next step, which keeps the
+    /// order of the remaining particles and every per-particle buffer consistent. Use
+    /// `LiquidWorld::retain_particles` to remove them immediately instead.
+    ///
+    /// Returns the number of particles marked for deletion.
+    pub fn retain_particles(
+        &mut self,
+        mut f: impl FnMut(usize, &Point<Real>, &Vector<Real>) -> bool,
+    ) -> usize {
+        let mut num_deleted = 0;
+
+        for i in 0..self.positions.len() {
+            if !self.deleted_particles[i] && !f(i, &self.positions[i], &self.velocities[i]) {
+                self.delete_particle_at_next_timestep(i);
+                num_deleted += 1;
+            }
+        }
+
+        num_deleted
+    }
+
     /// The number of particles that will be deleted at the next timestep.
     pub fn num_deleted_particles(&self) -> usize {
         self.num_deleted_particles
@@ -85,23 +301,163 @@ impl Fluid {
         &self.deleted_particles
     }
 
+    /// Replaces the mask of particles that will be deleted at the next timestep.
+    pub(crate) fn set_deleted_particles_mask(&mut self, mask: Vec<bool>) {
+        self.num_deleted_particles = mask.iter().filter(|deleted| **deleted).count();
+        self.deleted_particles = mask;
+    }
+
     pub(crate) fn apply_particles_removal(&mut self) {
         if self.num_deleted_particles != 0 {
+            let remaining: Vec<_> = (0..self.deleted_particles.len())
+                .filter(|i| !self.deleted_particles[*i])
+                .collect();
+
+            for forces in &mut self.nonpressure_forces {
+                forces.apply_permutation(&remaining);
+            }
+
             crate::helper::filter_from_mask(&self.deleted_particles, &mut self.positions);
             crate::helper::filter_from_mask(&self.deleted_particles, &mut self.velocities);
             crate::helper::filter_from_mask(&self.deleted_particles, &mut self.accelerations);
             crate::helper::filter_from_mask(&self.deleted_particles, &mut self.volumes);
+
+            if self.render_velocities.len() == self.deleted_particles.len() {
+                crate::helper::filter_from_mask(
+                    &self.deleted_particles,
+                    &mut self.render_velocities,
+                );
+            }
+
+            if self.surface_particles.len() == self.deleted_particles.len() {
+                crate::helper::filter_from_mask(
+                    &self.deleted_particles,
+                    &mut self.surface_particles,
+                );
+            }
+
+            if self.active_particles.len() == self.deleted_particles.len() {
+                crate::helper::filter_from_mask(
+                    &self.deleted_particles,
+                    &mut self.active_particles,
+                );
+            }
+
+            if self.previous_positions.len() == self.deleted_particles.len() {
+                crate::helper::filter_from_mask(
+                    &self.deleted_particles,
+                    &mut self.previous_positions,
+                );
+            }
+
+            if self.temperatures.len() == self.deleted_particles.len() {
+                crate::helper::filter_from_mask(&self.deleted_particles, &mut self.temperatures);
+            }
+
+            if self.concentrations.len() == self.deleted_particles.len() {
+                crate::helper::filter_from_mask(&self.deleted_particles, &mut self.concentrations);
+            }
+
+            if self.lifetimes.len() == self.deleted_particles.len() {
+                crate::helper::filter_from_mask(&self.deleted_particles, &mut self.lifetimes);
+            }
+
+            if self.rest_densities.len() == self.deleted_particles.len() {
+                crate::helper::filter_from_mask(&self.deleted_particles, &mut self.rest_densities);
+            }
+
+            if self.user_data.len() == self.deleted_particles.len() {
+                crate::helper::filter_from_mask(&self.deleted_particles, &mut self.user_data);
+            }
+
             self.deleted_particles.truncate(self.positions.len());
             self.deleted_particles.iter_mut().for_each(|i| *i = false);
             self.num_deleted_particles = 0;
         }
     }
 
+    /// Enables the simulation of temperatures, setting the temperature of every particle to
+    /// `temperature`.
+    pub fn enable_temperatures(&mut self, temperature: Real) {
+        self.default_temperature = Some(temperature);
+        self.temperatures = vec![temperature; self.num_particles()];
+    }
+
+    /// Disables the simulation of temperatures, clearing the temperature of every particle.
+    pub fn disable_temperatures(&mut self) {
+        self.default_temperature = None;
+        self.temperatures = Vec::new();
+    }
+
+    /// Enables the simulation of concentrations, setting the concentration of every particle to
+    /// `concentration`.
+    pub fn enable_concentrations(&mut self, concentration: Real) {
+        self.default_concentration = Some(concentration);
+        self.concentrations = vec![concentration; self.num_particles()];
+    }
+
+    /// Disables the simulation of concentrations, clearing the concentration of every particle.
+    pub fn disable_concentrations(&mut self) {
+        self.default_concentration = None;
+        self.concentrations = Vec::new();
+    }
+
+    /// Sets the remaining lifetime, in seconds, of the particles starting at index `first`.
+    ///
+    /// The other particles keep their current lifetime, which is infinite if it was never set.
+    pub fn set_particle_lifetimes(&mut self, first: usize, lifetimes: &[Real]) {
+        self.lifetimes.resize(self.num_particles(), Real::MAX);
+        self.lifetimes[first..first + lifetimes.len()].copy_from_slice(lifetimes);
+    }
+
+    /// Sets the rest density of the particles starting at index `first`.
+    ///
+    /// The other particles keep their current rest density, which is `density0` if it was never
+    /// set. The volumes of the particles are kept, so their masses change accordingly.
+    pub fn set_particle_rest_densities(&mut self, first: usize, rest_densities: &[Real]) {
+        self.rest_densities
+            .resize(self.num_particles(), self.density0);
+        self.rest_densities[first..first + rest_densities.len()].copy_from_slice(rest_densities);
+    }
+
+    /// Sets the user data of the particles starting at index `first`.
+    ///
+    /// The other particles keep their current user data, which is zero if it was never set.
+    pub fn set_particle_user_data(&mut self, first: usize, user_data: &[u128]) {
+        self.user_data.resize(self.num_particles(), 0);
+        self.user_data[first..first + user_data.len()].copy_from_slice(user_data);
+    }
+
+    /// The user data of the `i`-th particle, or zero if it was never set.
+    pub fn particle_user_data(&self, i: usize) -> u128 {
+        self.user_data.get(i).copied().unwrap_or(0)
+    }
+
+    /// Decreases the lifetime of the particles by `dt`, and marks the expired ones for deletion.
+    pub(crate) fn age_particles(&mut self, dt: Real) {
+        for i in 0..self.lifetimes.len() {
+            self.lifetimes[i] -= dt;
+
+            if self.lifetimes[i] <= na::zero::<Real>() {
+                self.delete_particle_at_next_timestep(i);
+            }
+        }
+    }
+
     /// The radius of this fluid's particles.
     pub fn particle_radius(&self) -> Real {
         self.particle_radius
     }
 
+    /// The kernel radius of this fluid, `world_kernel_radius` being the kernel radius of the liquid
+    /// world it belongs to.
+    ///
+    /// This is `world_kernel_radius` unless the particle radius of this fluid differs from the
+    /// particle radius of the world.
+    pub fn kernel_radius(&self, world_kernel_radius: Real) -> Real {
+        self.kernel_radius.unwrap_or(world_kernel_radius)
+    }
+
     /// The default volume given to each of this fluid's particles.
     pub fn default_particle_volume(&self) -> Real {
         Self::particle_volume(self.particle_radius)
@@ -122,11 +478,19 @@ impl Fluid {
     /// Add a set of particles to this fluid.
     ///
     /// If `velocities` is `None` the velocity of each particle will be initialized at zero.
-    /// If it is not `None`, then it must be a slice with the same length than `positions`.
+    /// If `volumes` is `None` each particle will be given the default particle volume (see
+    /// `Fluid::default_particle_volume`). Otherwise, they must be slices with the same length
+    /// than `positions`.
+    ///
+    /// The new particles are appended after the existing ones, which keep their indices. The
+    /// non-pressure forces of this fluid are notified so they can preserve the state of the
+    /// existing particles, and the buffers of the solvers of the liquid world are resized at the
+    /// beginning of the next step (or right away with `LiquidWorld::add_particles`).
     pub fn add_particles(
         &mut self,
         positions: &[Point<Real>],
         velocities: Option<&[Vector<Real>]>,
+        volumes: Option<&[Real]>,
     ) {
         let nparticles = self.positions.len() + positions.len();
         let particle_volume = self.default_particle_volume();
@@ -144,21 +508,132 @@ impl Fluid {
             self.velocities.resize(nparticles, Vector::zeros());
         }
 
+        if let Some(volumes) = volumes {
+            assert_eq!(
+                positions.len(),
+                volumes.len(),
+                "The provided positions and volumes arrays must have the same length."
+            );
+            self.volumes.extend_from_slice(volumes);
+        } else {
+            self.volumes.resize(nparticles, particle_volume);
+        }
+
         self.accelerations.resize(nparticles, Vector::zeros());
-        self.volumes.resize(nparticles, particle_volume);
         self.deleted_particles.resize(nparticles, false);
+
+        if self.render_velocity_smoothing.is_some() {
+            let first_new = self.render_velocities.len();
+            self.render_velocities.resize(nparticles, Vector::zeros());
+            self.render_velocities[first_new..].copy_from_slice(&self.velocities[first_new..]);
+        }
+
+        if self.surface_detection.is_some() {
+            self.surface_particles.resize(nparticles, false);
+        }
+
+        if !self.active_particles.is_empty() {
+            self.active_particles.resize(nparticles, true);
+        }
+
+        if !self.previous_positions.is_empty() {
+            self.previous_positions.extend_from_slice(positions);
+        }
+
+        if let Some(temperature) = self.default_temperature {
+            self.temperatures.resize(nparticles, temperature);
+        }
+
+        if let Some(concentration) = self.default_concentration {
+            self.concentrations.resize(nparticles, concentration);
+        }
+
+        if !self.lifetimes.is_empty() {
+            self.lifetimes.resize(nparticles, Real::MAX);
+        }
+
+        if !self.rest_densities.is_empty() {
+            self.rest_densities.resize(nparticles, self.density0);
+        }
+
+        if !self.user_data.is_empty() {
+            self.user_data.resize(nparticles, 0);
+        }
+
+        for forces in &mut self.nonpressure_forces {
+            forces.add_particles(positions.len(), &self.positions, &self.velocities);
+        }
     }
 
     /// Sorts all the particles of this fluids according to morton order.
     pub fn z_sort(&mut self) {
         let order = crate::z_order::compute_points_z_order(&self.positions);
-        self.positions = crate::z_order::apply_permutation(&order, &self.positions);
-        self.velocities = crate::z_order::apply_permutation(&order, &self.velocities);
-        self.accelerations = crate::z_order::apply_permutation(&order, &self.accelerations);
-        self.volumes = crate::z_order::apply_permutation(&order, self.volumes.as_slice());
+        self.apply_permutation(&order);
+    }
+
+    /// Reorders the particles of this fluid.
+    ///
+    /// After this call, the `k`-th particle is the particle that had the index `permutation[k]`
+    /// before the call. All the per-particle properties, including the state of the non-pressure
+    /// forces, are reordered accordingly. `permutation` must contain every particle index exactly
+    /// once.
+    pub fn apply_permutation(&mut self, permutation: &[usize]) {
+        assert_eq!(
+            permutation.len(),
+            self.num_particles(),
+            "The permutation must contain every particle."
+        );
+        self.positions = crate::z_order::apply_permutation(permutation, &self.positions);
+        self.velocities = crate::z_order::apply_permutation(permutation, &self.velocities);
+        self.accelerations = crate::z_order::apply_permutation(permutation, &self.accelerations);
+        self.volumes = crate::z_order::apply_permutation(permutation, self.volumes.as_slice());
+        self.deleted_particles =
+            crate::z_order::apply_permutation(permutation, &self.deleted_particles);
+
+        if self.render_velocities.len() == permutation.len() {
+            self.render_velocities =
+                crate::z_order::apply_permutation(permutation, &self.render_velocities);
+        }
+
+        if self.surface_particles.len() == permutation.len() {
+            self.surface_particles =
+                crate::z_order::apply_permutation(permutation, &self.surface_particles);
+        }
+
+        if self.active_particles.len() == permutation.len() {
+            self.active_particles =
+                crate::z_order::apply_permutation(permutation, &self.active_particles);
+        }
+
+        if self.previous_positions.len() == permutation.len() {
+            self.previous_positions =
+                crate::z_order::apply_permutation(permutation, &self.previous_positions);
+        }
+
+        if self.temperatures.len() == permutation.len() {
+            self.temperatures = crate::z_order::apply_permutation(permutation, &self.temperatures);
+        }
+
+        if self.concentrations.len() == permutation.len() {
+            self.concentrations =
+                crate::z_order::apply_permutation(permutation, &self.concentrations);
+        }
+
+        if self.lifetimes.len() == permutation.len() {
+            self.lifetimes = crate::z_order::apply_permutation(permutation, &self.lifetimes);
+        }
+
+        if self.rest_densities.len() == permutation.len() {
+            self.rest_densities =
+                crate::z_order::apply_permutation(permutation, &self.rest_densities);
+        }
+
+        if self.user_data.len() == permutation.len() {
+            self.user_data = crate::z_order::apply_permutation(permutation, &self.user_data);
+        }
 
         for forces in &mut self.nonpressure_forces {
-            forces.apply_permutation(&order);
+            forces.apply_permutation(permutation);
         }
     }
 
@@ -179,9 +654,21 @@ impl Fluid {
         local_point_cloud_aabb(&self.positions).loosened(particle_radius)
     }
 
+    /// The rest density of the `i`-th particle of this fluid.
+    ///
+    /// See `Fluid::rest_densities`.
+    #[inline]
+    pub fn rest_density(&self, i: usize) -> Real {
+        if self.rest_densities.is_empty() {
+            self.density0
+        } else {
+            self.rest_densities[i]
+        }
+    }
+
     /// The mass of the `i`-th particle of this fluid.
     pub fn particle_mass(&self, i: usize) -> Real {
-        self.volumes[i] * self.density0
+        self.volumes[i] * self.rest_density(i)
     }
 
     /// The inverse mass of the `i`-th particle of this fluid.
@@ -191,13 +678,17 @@ impl Fluid {
         if self.volumes[i].is_zero() {
             na::zero::<Real>()
         } else {
-            na::one::<Real>() / (self.volumes[i] * self.density0)
+            na::one::<Real>() / (self.volumes[i] * self.rest_density(i))
         }
     }
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
 /// The unique identifier of a boundary object.
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
 pub struct FluidHandle(ContiguousArenaIndex);
 /// The set of all fluid objects.
 pub type FluidSet = ContiguousArena<FluidHandle, Fluid>;
@@ -0,0 +1,170 @@
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use crate::math::{AngularVector, Point, Real, Vector};
+use crate::object::{ContiguousArena, ContiguousArenaIndex, Fluid, FluidHandle};
+
+/// The acceleration applied by a force field, as a function of the position and velocity of a
+/// particle, and of the time.
+pub type ForceFieldFn = dyn Fn(&Point<Real>, &Vector<Real>, Real) -> Vector<Real> + Send + Sync;
+
+/// A global acceleration field applied to the fluid particles.
+///
+/// Force fields are added to the liquid world with `LiquidWorld::add_force_field`. At each
+/// substep, the closure is evaluated (in parallel if the `parallel` feature is enabled) with the
+/// position and velocity of each affected particle, and the current time of the world. The
+/// returned acceleration is added to the one of the particle, in addition to the gravity and to
+/// the non-pressure forces. This can be used to model vortices, point attractors, wind tunnels,
+/// etc.
+pub struct ForceField {
+    /// The acceleration applied to a particle, as a function of its position, its velocity, and
+    /// the time.
+    pub field: Box<ForceFieldFn>,
+    /// The fluids affected by this force field.
+    ///
+    /// If `None` (the default), all the fluids are affected.
+    pub fluids: Option<Vec<FluidHandle>>,
+    /// Whether this force field is applied.
+    pub enabled: bool,
+}
+
+impl ForceField {
+    /// Creates a force field applying the acceleration computed by `field` to all the fluids.
+    pub fn new(
+        field: impl Fn(&Point<Real>, &Vector<Real>, Real) -> Vector<Real> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            field: Box::new(field),
+            fluids: None,
+            enabled: true,
+        }
+    }
+
+    /// Restricts this force field to the given fluids.
+    pub fn with_fluids(mut self, fluids: Vec<FluidHandle>) -> Self {
+        self.fluids = Some(fluids);
+        self
+    }
+
+    pub(crate) fn affects(&self, fluid: FluidHandle) -> bool {
+        self.fluids
+            .as_ref()
+            .is_none_or(|fluids| fluids.contains(&fluid))
+    }
+
+    /// Adds the acceleration of this field to the particles of `fluid`.
+    pub(crate) fn apply(&self, fluid: &mut Fluid, time: Real) {
+        if !self.enabled {
+            return;
+        }
+
+        let field = &self.field;
+        par_iter_mut!(fluid.accelerations)
+            .zip(par_iter!(fluid.positions))
+            .zip(par_iter!(fluid.velocities))
+            .for_each(|((acceleration, position), velocity)| {
+                *acceleration += field(position, velocity, time);
+            });
+    }
+}
+
+/// A rotating reference frame in which the simulation is carried out.
+///
+/// This is set with `LiquidWorld::set_rotating_frame` to simulate fluids inside of a rotating
+/// container (a centrifuge, a spacecraft, etc.) while keeping the boundaries of the container
+/// static. The positions and velocities of the particles are relative to the rotating frame, and
+/// the fictitious Coriolis, centrifugal, and Euler accelerations are applied to all the fluids at
+/// each substep, in addition to the gravity.
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub struct RotatingFrame {
+    /// A point on the rotation axis of the frame (the center of rotation in 2D).
+    pub center: Point<Real>,
+    /// The angular velocity of the frame.
+    pub angvel: AngularVector<Real>,
+    /// The angular acceleration of the frame, generating the Euler acceleration.
+    ///
+    /// This defaults to zero.
+    pub angular_acceleration: AngularVector<Real>,
+}
+
+impl RotatingFrame {
+    /// A frame rotating about `center` with the constant angular velocity `angvel`.
+    pub fn new(center: Point<Real>, angvel: AngularVector<Real>) -> Self {
+        Self {
+            center,
+            angvel,
+            angular_acceleration: AngularVector::zeros(),
+        }
+    }
+
+    /// Sets the angular acceleration of this frame.
+    pub fn with_angular_acceleration(mut self, angular_acceleration: AngularVector<Real>) -> Self {
+        self.angular_acceleration = angular_acceleration;
+        self
+    }
+
+    /// The sum of the Coriolis, centrifugal, and Euler accelerations of a particle with the given
+    /// position and velocity relative to this frame.
+    pub fn fictitious_acceleration(
+        &self,
+        position: &Point<Real>,
+        velocity: &Vector<Real>,
+    ) -> Vector<Real> {
+        let arm = position - self.center;
+        let coriolis = cross(&self.angvel, velocity) * na::convert::<_, Real>(-2.0);
+        let centrifugal = -cross(&self.angvel, &cross(&self.angvel, &arm));
+        let euler = -cross(&self.angular_acceleration, &arm);
+        coriolis + centrifugal + euler
+    }
+
+    /// Adds the fictitious accelerations of this frame to the particles of `fluid`.
+    pub(crate) fn apply(&self, fluid: &mut Fluid) {
+        par_iter_mut!(fluid.accelerations)
+            .zip(par_iter!(fluid.positions))
+            .zip(par_iter!(fluid.velocities))
+            .for_each(|((acceleration, position), velocity)| {
+                *acceleration += self.fictitious_acceleration(position, velocity);
+            });
+    }
+}
+
+// The cross product between an angular vector and a vector.
+fn cross(angvel: &AngularVector<Real>, v: &Vector<Real>) -> Vector<Real> {
+    #[cfg(feature = "dim2")]
+    {
+        Vector::new(-v.y, v.x) * angvel.x
+    }
+
+    #[cfg(feature = "dim3")]
+    {
+        angvel.cross(v)
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+/// The unique identifier of a force field.
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub struct ForceFieldHandle(ContiguousArenaIndex);
+/// A set of all force fields.
+pub type ForceFieldSet = ContiguousArena<ForceFieldHandle, ForceField>;
+
+impl From<ContiguousArenaIndex> for ForceFieldHandle {
+    #[inline]
+    fn from(i: ContiguousArenaIndex) -> Self {
+        ForceFieldHandle(i)
+    }
+}
+
+impl From<ForceFieldHandle> for ContiguousArenaIndex {
+    #[inline]
+    fn from(handle: ForceFieldHandle) -> Self {
+        handle.0
+    }
+}
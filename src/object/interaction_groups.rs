@@ -18,6 +18,10 @@
 /// ```
 #[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
 #[repr(C)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
 pub struct InteractionGroups {
     /// Groups memberships.
     pub memberships: Group,
@@ -81,6 +85,7 @@ impl Default for InteractionGroups {
 bitflags::bitflags! {
     /// A bit mask identifying groups for interaction.
     #[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
+    #[cfg_attr(feature = "serde-serialize", derive(serde::Serialize, serde::Deserialize))]
     pub struct Group: u32 {
         /// The group n°1.
         const GROUP_1 = 1 << 0;
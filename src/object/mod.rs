@@ -1,15 +1,53 @@
 //! Fluid and boundary objects that can be simulated.
 
-pub use self::boundary::{Boundary, BoundaryHandle, BoundarySet};
+pub use self::active_region::{
+    ActiveRegion, ActiveRegionHandle, ActiveRegionSet, ActiveRegionShape, InactiveParticleModel,
+};
+pub use self::boundary::{
+    Boundary, BoundaryHandle, BoundaryMotion, BoundarySet, ForceClearingPolicy,
+};
 pub use self::contiguous_arena::{ContiguousArena, ContiguousArenaIndex};
-pub use self::fluid::{Fluid, FluidHandle, FluidSet};
+pub use self::diffuse_particles::{DiffuseParticleKind, DiffuseParticles};
+pub use self::emitter::{Emitter, EmitterHandle, EmitterSet, EmitterShape};
+pub use self::flow_sensor::{FlowSensor, FlowSensorHandle, FlowSensorSet, FlowSensorShape};
+pub use self::fluid::{Fluid, FluidHandle, FluidSet, SurfaceDetection};
+pub use self::force_field::{
+    ForceField, ForceFieldFn, ForceFieldHandle, ForceFieldSet, RotatingFrame,
+};
+pub use self::open_boundary::{
+    InflowProfile, InflowSpeedFn, OpenBoundary, OpenBoundaryHandle, OpenBoundaryKind,
+    OpenBoundarySet,
+};
+pub use self::particle_budget::{BudgetArbitration, EmissionRequest, ParticleBudget};
+pub use self::sink::{Sink, SinkHandle, SinkSet, SinkShape};
+pub use self::sponge_layer::{SpongeLayer, SpongeLayerHandle, SpongeLayerSet};
+pub use self::substep_hook::{
+    SubstepHook, SubstepHookFn, SubstepHookHandle, SubstepHookSet, SubstepStage,
+};
+pub use self::wavemaker::{WaveTank, Wavemaker, WavemakerKind, WavemakerProfile};
 
+mod active_region;
 mod boundary;
 mod contiguous_arena;
+mod diffuse_particles;
+mod emitter;
+mod flow_sensor;
 mod fluid;
+mod force_field;
 pub mod interaction_groups;
+mod open_boundary;
+mod particle_budget;
+mod sink;
+mod sponge_layer;
+mod substep_hook;
+mod wavemaker;
 
 /// The identifier of a single particle.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
 pub enum ParticleId {
     /// A fluid particle.
     FluidParticle(FluidHandle, usize),
@@ -0,0 +1,339 @@
+use crate::math::{Isometry, Point, Real, Vector, DIM};
+use crate::object::{ContiguousArena, ContiguousArenaIndex, Fluid, FluidHandle};
+
+/// A user-provided inflow speed, as a function of the local-space position on the cross-section
+/// and of the time.
+pub type InflowSpeedFn = dyn Fn(&Point<Real>, Real) -> Real + Send + Sync;
+
+/// The velocity profile imposed by an inflow boundary.
+///
+/// The fluid enters the domain along the local `x` axis of the open boundary. The profiles are
+/// expressed in the local-space of the open boundary, where the center of its open face is at
+/// the origin.
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub enum InflowProfile {
+    /// The same inflow speed on the whole cross-section.
+    Uniform(Real),
+    /// A parabolic (Poiseuille) profile reaching the given speed on the center of the
+    /// cross-section, and zero on its edges.
+    Parabolic(Real),
+    /// A user-provided inflow speed, as a function of the local-space position on the
+    /// cross-section and of the time.
+    #[cfg_attr(feature = "serde-serialize", serde(skip))]
+    Custom(Box<InflowSpeedFn>),
+}
+
+impl InflowProfile {
+    /// The inflow speed at the given local-space point of the cross-section.
+    pub fn speed_at(
+        &self,
+        local_point: &Point<Real>,
+        half_extents: &Vector<Real>,
+        time: Real,
+    ) -> Real {
+        match self {
+            InflowProfile::Uniform(speed) => *speed,
+            InflowProfile::Parabolic(max_speed) => {
+                let mut speed = *max_speed;
+
+                for k in 1..DIM {
+                    let ratio = local_point[k] / half_extents[k];
+                    speed *= (na::one::<Real>() - ratio * ratio).max(na::zero::<Real>());
+                }
+
+                speed
+            }
+            InflowProfile::Custom(f) => f(local_point, time),
+        }
+    }
+}
+
+/// The kind of an open boundary.
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub enum OpenBoundaryKind {
+    /// Fluid particles are generated in the buffer and pushed into the domain with the given
+    /// velocity profile.
+    Inflow(InflowProfile),
+    /// Fluid particles leave the domain through the buffer, where they are deleted.
+    Outflow,
+}
+
+/// A section of the domain through which the fluid enters or leaves the simulation.
+///
+/// Open boundaries are added to the liquid world with `LiquidWorld::add_open_boundary`. The open
+/// face of the boundary is the plane `x = 0` of its local-space, limited to the cross-section
+/// `[-half_extents, half_extents]` along the local `y` (and `z` in 3D) axes. The local `x` axis
+/// points toward the inside of the domain, and the buffer zone is the box of the given
+/// `thickness` behind the open face.
+///
+/// The buffer particles are regular fluid particles, so the particles close to the open face
+/// have a complete neighborhood and don't suffer from the pressure drop of a truncated kernel
+/// support. However, their motion is prescribed instead of being driven by the pressure forces:
+/// - For an inflow, the buffer is initially filled with particles moving with the velocity
+///   profile. New particles are generated at the back of the buffer as the previous ones move
+///   forward, and become regular fluid particles once they cross the open face.
+/// - For an outflow, the particles entering the buffer keep the velocity they had when crossing
+///   the open face, and are deleted once they cross the back of the buffer.
+///
+/// The `thickness` of the buffer must be at least as large as the kernel radius.
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub struct OpenBoundary {
+    /// The fluid flowing through this open boundary.
+    pub fluid: FluidHandle,
+    /// The position of the open boundary.
+    pub position: Isometry<Real>,
+    /// The half-extents of the cross-section of the open boundary along its local `y` (and `z`
+    /// in 3D) axes.
+    ///
+    /// The `x` component is ignored.
+    pub half_extents: Vector<Real>,
+    /// The thickness of the buffer zone, along the local `x` axis.
+    pub thickness: Real,
+    /// Whether this is an inflow or an outflow.
+    pub kind: OpenBoundaryKind,
+    /// Whether this open boundary is active.
+    pub enabled: bool,
+    columns: Vec<Point<Real>>,
+    accumulated: Vec<Real>,
+    buffer_particles: Vec<(usize, Point<Real>, Vector<Real>)>,
+}
+
+impl OpenBoundary {
+    /// Creates an inflow pushing `fluid` into the domain with the given velocity profile.
+    pub fn inflow(
+        fluid: FluidHandle,
+        position: Isometry<Real>,
+        half_extents: Vector<Real>,
+        thickness: Real,
+        profile: InflowProfile,
+    ) -> Self {
+        Self::new(
+            fluid,
+            position,
+            half_extents,
+            thickness,
+            OpenBoundaryKind::Inflow(profile),
+        )
+    }
+
+    /// Creates an outflow through which `fluid` leaves the domain.
+    pub fn outflow(
+        fluid: FluidHandle,
+        position: Isometry<Real>,
+        half_extents: Vector<Real>,
+        thickness: Real,
+    ) -> Self {
+        Self::new(
+            fluid,
+            position,
+            half_extents,
+            thickness,
+            OpenBoundaryKind::Outflow,
+        )
+    }
+
+    fn new(
+        fluid: FluidHandle,
+        position: Isometry<Real>,
+        half_extents: Vector<Real>,
+        thickness: Real,
+        kind: OpenBoundaryKind,
+    ) -> Self {
+        Self {
+            fluid,
+            position,
+            half_extents,
+            thickness,
+            kind,
+            enabled: true,
+            columns: Vec::new(),
+            accumulated: Vec::new(),
+            buffer_particles: Vec::new(),
+        }
+    }
+
+    /// Checks if the given world-space point is inside of the buffer zone.
+    pub fn buffer_contains_point(&self, point: &Point<Real>) -> bool {
+        let local_point = self.position.inverse_transform_point(point);
+        local_point.x >= -self.thickness
+            && local_point.x < na::zero::<Real>()
+            && (1..DIM).all(|k| local_point[k].abs() <= self.half_extents[k])
+    }
+
+    /// Generates the particles of an inflow, and marks for deletion the particles that left an
+    /// outflow.
+    ///
+    /// The inflow particles are generated behind the ones generated previously, once they moved
+    /// far enough.
+    pub(crate) fn feed(&mut self, fluid: &mut Fluid, time: Real) {
+        if !self.enabled {
+            return;
+        }
+
+        let profile = match &self.kind {
+            OpenBoundaryKind::Inflow(profile) => profile,
+            OpenBoundaryKind::Outflow => {
+                for i in 0..fluid.num_particles() {
+                    let local_point = self.position.inverse_transform_point(&fluid.positions[i]);
+
+                    if local_point.x < -self.thickness
+                        && (1..DIM).all(|k| local_point[k].abs() <= self.half_extents[k])
+                    {
+                        fluid.delete_particle_at_next_timestep(i);
+                    }
+                }
+
+                return;
+            }
+        };
+
+        let spacing = fluid.particle_radius() * na::convert::<_, Real>(2.0);
+        let mut positions = Vec::new();
+        let mut velocities = Vec::new();
+
+        if self.columns.is_empty() {
+            // Fill the buffer with a lattice of particles, starting one particle radius behind
+            // the open face.
+            self.columns = cross_section_lattice(&self.half_extents, spacing);
+            let num_layers = na::try_convert::<Real, f64>(self.thickness / spacing)
+                .unwrap()
+                .floor() as usize;
+            let back_layer = -fluid.particle_radius()
+                - spacing * na::convert::<_, Real>(num_layers.max(1) as f64 - 1.0);
+            self.accumulated = vec![back_layer + self.thickness; self.columns.len()];
+
+            for column in &self.columns {
+                let speed = profile.speed_at(column, &self.half_extents, time);
+
+                for layer in 0..num_layers.max(1) {
+                    let mut local_point = *column;
+                    local_point.x = back_layer + spacing * na::convert::<_, Real>(layer as f64);
+                    positions.push(self.position * local_point);
+                    velocities.push(self.position * (Vector::x() * speed));
+                }
+            }
+        } else {
+            for (column, accumulated) in self.columns.iter().zip(self.accumulated.iter_mut()) {
+                let speed = profile.speed_at(column, &self.half_extents, time);
+
+                while *accumulated >= spacing {
+                    *accumulated -= spacing;
+                    let mut local_point = *column;
+                    local_point.x = -self.thickness + *accumulated;
+                    positions.push(self.position * local_point);
+                    velocities.push(self.position * (Vector::x() * speed));
+                }
+            }
+        }
+
+        fluid.add_particles(&positions, Some(&velocities), None);
+    }
+
+    /// Records the particles inside of the buffer zone, and the velocity they must move with
+    /// during the next substep.
+    pub(crate) fn collect_buffer_particles(&mut self, fluid: &Fluid, time: Real) {
+        self.buffer_particles.clear();
+
+        if !self.enabled {
+            return;
+        }
+
+        for i in 0..fluid.num_particles() {
+            if fluid.deleted_particles_mask()[i] || !self.buffer_contains_point(&fluid.positions[i])
+            {
+                continue;
+            }
+
+            let velocity = match &self.kind {
+                OpenBoundaryKind::Inflow(profile) => {
+                    let local_point = self.position.inverse_transform_point(&fluid.positions[i]);
+                    self.position
+                        * (Vector::x() * profile.speed_at(&local_point, &self.half_extents, time))
+                }
+                OpenBoundaryKind::Outflow => fluid.velocities[i],
+            };
+
+            self.buffer_particles
+                .push((i, fluid.positions[i], velocity));
+        }
+    }
+
+    /// Overrides the motion computed by the solver for the particles inside of the buffer
+    /// zone during a substep of length `dt`.
+    pub(crate) fn enforce_buffer_motion(&mut self, fluid: &mut Fluid, time: Real, dt: Real) {
+        for (i, position, velocity) in self.buffer_particles.drain(..) {
+            fluid.positions[i] = position + velocity * dt;
+            fluid.velocities[i] = velocity;
+        }
+
+        if let OpenBoundaryKind::Inflow(profile) = &self.kind {
+            for (column, accumulated) in self.columns.iter().zip(self.accumulated.iter_mut()) {
+                *accumulated += profile.speed_at(column, &self.half_extents, time) * dt;
+            }
+        }
+    }
+}
+
+// The centers of the columns of particles of the cross-section of an open boundary.
+fn cross_section_lattice(half_extents: &Vector<Real>, spacing: Real) -> Vec<Point<Real>> {
+    let mut counts = [1usize; DIM];
+
+    for k in 1..DIM {
+        counts[k] =
+            (na::try_convert::<Real, f64>(half_extents[k] * na::convert::<_, Real>(2.0) / spacing)
+                .unwrap()
+                .floor() as usize)
+                .max(1);
+    }
+
+    let mut points = Vec::new();
+
+    for id in 0..counts.iter().product() {
+        let mut pt = Point::origin();
+        let mut rest = id;
+
+        for k in 1..DIM {
+            let offset =
+                na::convert::<_, Real>((rest % counts[k]) as f64 - (counts[k] as f64 - 1.0) / 2.0);
+            pt[k] = offset * spacing;
+            rest /= counts[k];
+        }
+
+        points.push(pt);
+    }
+
+    points
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+/// The unique identifier of an open boundary.
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub struct OpenBoundaryHandle(ContiguousArenaIndex);
+/// A set of all open boundaries.
+pub type OpenBoundarySet = ContiguousArena<OpenBoundaryHandle, OpenBoundary>;
+
+impl From<ContiguousArenaIndex> for OpenBoundaryHandle {
+    #[inline]
+    fn from(i: ContiguousArenaIndex) -> Self {
+        OpenBoundaryHandle(i)
+    }
+}
+
+impl From<OpenBoundaryHandle> for ContiguousArenaIndex {
+    #[inline]
+    fn from(handle: OpenBoundaryHandle) -> Self {
+        handle.0
+    }
+}
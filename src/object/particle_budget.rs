@@ -0,0 +1,223 @@
+use crate::math::{Point, Real};
+
+/// The policy used to share a particle budget between several emission requests.
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub enum BudgetArbitration {
+    /// Requests with a higher priority are served first.
+    ///
+    /// Requests with the same priority share what remains proportionally to their weight.
+    Priority,
+    /// The available particles are shared proportionally to the weight of each request.
+    Proportional,
+    /// The available particles are shared proportionally to the weight of each request,
+    /// attenuated by the distance between the emission point and the camera.
+    ///
+    /// The weight of a request at a distance `d` from the camera is divided by `1 + (d / falloff)²`.
+    CameraDistance {
+        /// The position of the camera.
+        camera: Point<Real>,
+        /// The distance at which the weight of a request is halved.
+        falloff: Real,
+    },
+}
+
+/// A request for adding new particles to the world, subject to the particle budget.
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub struct EmissionRequest {
+    /// The number of particles the emitter would like to add.
+    pub num_particles: usize,
+    /// The priority of this request, used by `BudgetArbitration::Priority`.
+    pub priority: i32,
+    /// The relative importance of this request when the budget is shared proportionally.
+    pub weight: Real,
+    /// The location where the particles will be emitted, used by `BudgetArbitration::CameraDistance`.
+    pub position: Point<Real>,
+}
+
+impl EmissionRequest {
+    /// Creates a request for `num_particles` particles emitted at `position`, with a zero
+    /// priority and a unit weight.
+    pub fn new(num_particles: usize, position: Point<Real>) -> Self {
+        Self {
+            num_particles,
+            priority: 0,
+            weight: na::one::<Real>(),
+            position,
+        }
+    }
+
+    /// Sets the priority of this request.
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Sets the weight of this request.
+    pub fn with_weight(mut self, weight: Real) -> Self {
+        self.weight = weight;
+        self
+    }
+}
+
+/// A global limit on the number of fluid particles, shared between emitters.
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub struct ParticleBudget {
+    /// The maximum number of fluid particles in the world.
+    pub max_particles: usize,
+    /// The policy used to share the remaining budget between emission requests.
+    pub arbitration: BudgetArbitration,
+}
+
+impl ParticleBudget {
+    /// Creates a budget of `max_particles` particles, shared by priority.
+    pub fn new(max_particles: usize) -> Self {
+        Self {
+            max_particles,
+            arbitration: BudgetArbitration::Priority,
+        }
+    }
+
+    /// Computes how many particles each request is allowed to emit.
+    ///
+    /// The `num_existing_particles` are already in the world, so only the difference with
+    /// `max_particles` is shared. The returned vector contains one entry per request, each
+    /// never greater than the number of particles requested.
+    pub fn arbitrate(
+        &self,
+        num_existing_particles: usize,
+        requests: &[EmissionRequest],
+    ) -> Vec<usize> {
+        let available = self.max_particles.saturating_sub(num_existing_particles);
+        let total_requested: usize = requests.iter().map(|r| r.num_particles).sum();
+
+        if total_requested <= available {
+            return requests.iter().map(|r| r.num_particles).collect();
+        }
+
+        match self.arbitration {
+            BudgetArbitration::Priority => {
+                let mut result = vec![0; requests.len()];
+                let mut priorities: Vec<_> = requests.iter().map(|r| r.priority).collect();
+                priorities.sort_unstable_by(|a, b| b.cmp(a));
+                priorities.dedup();
+
+                let mut available = available;
+
+                for priority in priorities {
+                    let group: Vec<_> = (0..requests.len())
+                        .filter(|i| requests[*i].priority == priority)
+                        .collect();
+                    let group_requests: Vec<_> = group.iter().map(|i| requests[*i]).collect();
+                    let weights: Vec<_> = group_requests.iter().map(|r| r.weight).collect();
+                    let shares = share_proportionally(available, &group_requests, &weights);
+
+                    for (i, share) in group.into_iter().zip(shares) {
+                        result[i] = share;
+                        available -= share;
+                    }
+                }
+
+                result
+            }
+            BudgetArbitration::Proportional => {
+                let weights: Vec<_> = requests.iter().map(|r| r.weight).collect();
+                share_proportionally(available, requests, &weights)
+            }
+            BudgetArbitration::CameraDistance { camera, falloff } => {
+                let weights: Vec<_> = requests
+                    .iter()
+                    .map(|r| {
+                        let d = na::distance(&r.position, &camera) / falloff;
+                        r.weight / (na::one::<Real>() + d * d)
+                    })
+                    .collect();
+                share_proportionally(available, requests, &weights)
+            }
+        }
+    }
+}
+
+// Shares `available` particles between the requests, proportionally to the given weights.
+// The share of requests needing less than their proportional share is redistributed to the others.
+fn share_proportionally(
+    available: usize,
+    requests: &[EmissionRequest],
+    weights: &[Real],
+) -> Vec<usize> {
+    let mut result = vec![0; requests.len()];
+    let mut available = available;
+
+    loop {
+        let unsatisfied: Vec<_> = (0..requests.len())
+            .filter(|i| result[*i] < requests[*i].num_particles && weights[*i] > na::zero::<Real>())
+            .collect();
+        let total_weight: Real = unsatisfied.iter().map(|i| weights[*i]).sum();
+
+        if available == 0 || unsatisfied.is_empty() || total_weight <= na::zero::<Real>() {
+            break;
+        }
+
+        let mut distributed = 0;
+
+        for i in &unsatisfied {
+            let share = na::try_convert::<Real, f64>(
+                weights[*i] * na::convert::<_, Real>(available as f64) / total_weight,
+            )
+            .unwrap() as usize;
+            let share = share.min(requests[*i].num_particles - result[*i]);
+            result[*i] += share;
+            distributed += share;
+        }
+
+        if distributed == 0 {
+            // Rounding prevented any progress: give the remaining particles one by one,
+            // starting with the largest weights.
+            let mut sorted = unsatisfied;
+            sorted.sort_by(|a, b| weights[*b].partial_cmp(&weights[*a]).unwrap());
+
+            for i in sorted.into_iter().take(available) {
+                result[i] += 1;
+                distributed += 1;
+            }
+        }
+
+        available -= distributed;
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::{BudgetArbitration, EmissionRequest, ParticleBudget};
+    use crate::math::Point;
+
+    #[test]
+    fn budget_arbitration() {
+        let requests = [
+            EmissionRequest::new(60, Point::origin()),
+            EmissionRequest::new(60, Point::origin()).with_priority(1),
+            EmissionRequest::new(10, Point::origin()).with_weight(3.0),
+        ];
+
+        let mut budget = ParticleBudget::new(200);
+        assert_eq!(budget.arbitrate(50, &requests), vec![60, 60, 10]);
+        assert_eq!(budget.arbitrate(100, &requests), vec![30, 60, 10]);
+        assert_eq!(budget.arbitrate(300, &requests), vec![0, 0, 0]);
+
+        budget.arbitration = BudgetArbitration::Proportional;
+        assert_eq!(budget.arbitrate(100, &requests), vec![45, 45, 10]);
+    }
+}
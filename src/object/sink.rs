@@ -0,0 +1,207 @@
+use crate::math::{Point, Real, Vector};
+use crate::object::{ContiguousArena, ContiguousArenaIndex, Fluid, FluidHandle};
+#[cfg(feature = "parry")]
+use {crate::math::Isometry, parry::shape::SharedShape};
+
+/// The region in which a sink removes particles.
+#[derive(Clone)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub enum SinkShape {
+    /// An axis-aligned box.
+    Aabb {
+        /// The corner of the box with the smallest coordinates.
+        mins: Point<Real>,
+        /// The corner of the box with the largest coordinates.
+        maxs: Point<Real>,
+    },
+    /// A ball (a disk in 2D).
+    Ball {
+        /// The center of the ball.
+        center: Point<Real>,
+        /// The radius of the ball.
+        radius: Real,
+    },
+    /// The half-space on the side of the plane opposite to its normal.
+    ///
+    /// This is typically used to remove the particles falling off the scene.
+    HalfSpace {
+        /// A point on the plane delimiting the half-space.
+        point: Point<Real>,
+        /// The outward normal of the half-space.
+        normal: Vector<Real>,
+    },
+    /// An arbitrary shape.
+    #[cfg(feature = "parry")]
+    Shape {
+        /// The position of the shape.
+        position: Isometry<Real>,
+        /// The shape.
+        shape: SharedShape,
+    },
+}
+
+impl SinkShape {
+    /// Checks if the given point is inside of this shape.
+    pub fn contains_point(&self, point: &Point<Real>) -> bool {
+        match self {
+            SinkShape::Aabb { mins, maxs } => {
+                point.coords.iter().zip(mins.iter()).all(|(p, m)| p >= m)
+                    && point.coords.iter().zip(maxs.iter()).all(|(p, m)| p <= m)
+            }
+            SinkShape::Ball { center, radius } => {
+                na::distance_squared(point, center) <= *radius * *radius
+            }
+            SinkShape::HalfSpace {
+                point: plane_point,
+                normal,
+            } => normal.dot(&(point - plane_point)) <= na::zero::<Real>(),
+            #[cfg(feature = "parry")]
+            SinkShape::Shape { position, shape } => shape.contains_point(position, point),
+        }
+    }
+}
+
+/// A region deleting the fluid particles entering it.
+///
+/// Sinks are added to the liquid world with `LiquidWorld::add_sink`. At the beginning of each
+/// step, the particles inside of their region are deleted, either all at once or at a limited
+/// rate.
+#[derive(Clone)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub struct Sink {
+    /// The region in which particles are removed.
+    pub shape: SinkShape,
+    /// The maximum number of particles removed per second.
+    ///
+    /// If `None` (the default), every particle inside of the sink is removed.
+    pub rate: Option<Real>,
+    /// The fluids affected by this sink.
+    ///
+    /// If `None` (the default), all the fluids are affected.
+    pub fluids: Option<Vec<FluidHandle>>,
+    /// Whether this sink removes particles.
+    pub enabled: bool,
+    accumulated: Real,
+    total_removed_particles: usize,
+    total_removed_mass: Real,
+}
+
+impl Sink {
+    /// Creates a sink removing all the particles entering the given region.
+    pub fn new(shape: SinkShape) -> Self {
+        Self {
+            shape,
+            rate: None,
+            fluids: None,
+            enabled: true,
+            accumulated: na::zero::<Real>(),
+            total_removed_particles: 0,
+            total_removed_mass: na::zero::<Real>(),
+        }
+    }
+
+    /// Limits the number of particles removed per second.
+    pub fn with_rate(mut self, rate: Real) -> Self {
+        self.rate = Some(rate);
+        self
+    }
+
+    /// Restricts this sink to the given fluids.
+    pub fn with_fluids(mut self, fluids: Vec<FluidHandle>) -> Self {
+        self.fluids = Some(fluids);
+        self
+    }
+
+    /// The total number of particles removed by this sink since its creation or its last reset.
+    pub fn total_removed_particles(&self) -> usize {
+        self.total_removed_particles
+    }
+
+    /// The total mass of fluid removed by this sink since its creation or its last reset.
+    pub fn total_removed_mass(&self) -> Real {
+        self.total_removed_mass
+    }
+
+    /// Resets the totals of removed particles and mass to zero.
+    pub fn reset(&mut self) {
+        self.total_removed_particles = 0;
+        self.total_removed_mass = na::zero::<Real>();
+    }
+
+    pub(crate) fn affects(&self, fluid: FluidHandle) -> bool {
+        self.fluids
+            .as_ref()
+            .is_none_or(|fluids| fluids.contains(&fluid))
+    }
+
+    /// Computes how many particles this sink may remove during a step of length `dt`.
+    pub(crate) fn begin_step(&mut self, dt: Real) -> usize {
+        if !self.enabled {
+            return 0;
+        }
+
+        match self.rate {
+            Some(rate) => {
+                self.accumulated += rate * dt;
+                let allowed = na::try_convert::<Real, f64>(self.accumulated)
+                    .unwrap()
+                    .floor() as usize;
+                self.accumulated -= na::convert::<_, Real>(allowed as f64);
+                allowed
+            }
+            None => usize::MAX,
+        }
+    }
+
+    /// Marks for deletion at most `allowed` particles of `fluid` inside of this sink, and
+    /// returns the number of particles marked.
+    pub(crate) fn remove_particles(&mut self, fluid: &mut Fluid, allowed: usize) -> usize {
+        let mut num_removed = 0;
+
+        for i in 0..fluid.num_particles() {
+            if num_removed == allowed {
+                break;
+            }
+
+            if !fluid.deleted_particles_mask()[i] && self.shape.contains_point(&fluid.positions[i])
+            {
+                fluid.delete_particle_at_next_timestep(i);
+                self.total_removed_mass += fluid.particle_mass(i);
+                num_removed += 1;
+            }
+        }
+
+        self.total_removed_particles += num_removed;
+        num_removed
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+/// The unique identifier of a sink.
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub struct SinkHandle(ContiguousArenaIndex);
+/// A set of all sinks.
+pub type SinkSet = ContiguousArena<SinkHandle, Sink>;
+
+impl From<ContiguousArenaIndex> for SinkHandle {
+    #[inline]
+    fn from(i: ContiguousArenaIndex) -> Self {
+        SinkHandle(i)
+    }
+}
+
+impl From<SinkHandle> for ContiguousArenaIndex {
+    #[inline]
+    fn from(handle: SinkHandle) -> Self {
+        handle.0
+    }
+}
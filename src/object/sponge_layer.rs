@@ -0,0 +1,130 @@
+use crate::math::{Point, Real, Vector};
+use crate::object::{ContiguousArena, ContiguousArenaIndex, Fluid, FluidHandle};
+
+/// A damping zone absorbing the waves reaching an open edge of the domain.
+///
+/// Sponge layers are added to the liquid world with `LiquidWorld::add_sponge_layer`. The layer
+/// is the slab of the given `thickness` behind the plane passing through `point` with the outward
+/// normal `normal`. After each substep, the velocities of the particles inside of the layer are
+/// attenuated by a damping coefficient increasing quadratically from zero on this plane to
+/// `damping` on the outer face of the layer, so the waves are absorbed instead of reflected.
+#[derive(Clone)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub struct SpongeLayer {
+    /// A point on the inner face of the layer.
+    pub point: Point<Real>,
+    /// The unit normal of the inner face of the layer, pointing toward the outside of the domain.
+    pub normal: Vector<Real>,
+    /// The thickness of the layer.
+    pub thickness: Real,
+    /// The damping coefficient, in `1/s`, on the outer face of the layer.
+    pub damping: Real,
+    /// Whether the particles crossing the outer face of the layer are deleted.
+    ///
+    /// This defaults to `false`.
+    pub delete_particles: bool,
+    /// The fluids affected by this layer.
+    ///
+    /// If `None` (the default), all the fluids are affected.
+    pub fluids: Option<Vec<FluidHandle>>,
+    /// Whether this layer damps particles.
+    pub enabled: bool,
+}
+
+impl SpongeLayer {
+    /// Creates a sponge layer behind the plane passing through `point` with the outward `normal`.
+    pub fn new(point: Point<Real>, normal: Vector<Real>, thickness: Real, damping: Real) -> Self {
+        Self {
+            point,
+            normal,
+            thickness,
+            damping,
+            delete_particles: false,
+            fluids: None,
+            enabled: true,
+        }
+    }
+
+    /// Makes this layer delete the particles crossing its outer face.
+    pub fn with_particle_deletion(mut self) -> Self {
+        self.delete_particles = true;
+        self
+    }
+
+    /// Restricts this layer to the given fluids.
+    pub fn with_fluids(mut self, fluids: Vec<FluidHandle>) -> Self {
+        self.fluids = Some(fluids);
+        self
+    }
+
+    /// The damping coefficient at the given point.
+    ///
+    /// This is zero outside of the layer, and `self.damping` beyond its outer face.
+    pub fn damping_at(&self, point: &Point<Real>) -> Real {
+        let depth = self.normal.dot(&(point - self.point));
+
+        if depth <= na::zero::<Real>() {
+            na::zero::<Real>()
+        } else if depth >= self.thickness {
+            self.damping
+        } else {
+            let ratio = depth / self.thickness;
+            self.damping * ratio * ratio
+        }
+    }
+
+    pub(crate) fn affects(&self, fluid: FluidHandle) -> bool {
+        self.fluids
+            .as_ref()
+            .is_none_or(|fluids| fluids.contains(&fluid))
+    }
+
+    /// Damps the velocities of the particles of `fluid` inside of this layer during a substep of
+    /// length `dt`.
+    pub(crate) fn apply(&self, fluid: &mut Fluid, dt: Real) {
+        if !self.enabled {
+            return;
+        }
+
+        for i in 0..fluid.num_particles() {
+            let damping = self.damping_at(&fluid.positions[i]);
+
+            if damping > na::zero::<Real>() {
+                fluid.velocities[i] *= (-damping * dt).exp();
+
+                if self.delete_particles
+                    && self.normal.dot(&(fluid.positions[i] - self.point)) >= self.thickness
+                {
+                    fluid.delete_particle_at_next_timestep(i);
+                }
+            }
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+/// The unique identifier of a sponge layer.
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub struct SpongeLayerHandle(ContiguousArenaIndex);
+/// A set of all sponge layers.
+pub type SpongeLayerSet = ContiguousArena<SpongeLayerHandle, SpongeLayer>;
+
+impl From<ContiguousArenaIndex> for SpongeLayerHandle {
+    #[inline]
+    fn from(i: ContiguousArenaIndex) -> Self {
+        SpongeLayerHandle(i)
+    }
+}
+
+impl From<SpongeLayerHandle> for ContiguousArenaIndex {
+    #[inline]
+    fn from(handle: SpongeLayerHandle) -> Self {
+        handle.0
+    }
+}
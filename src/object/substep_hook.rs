@@ -0,0 +1,98 @@
+use crate::object::{ContiguousArena, ContiguousArenaIndex, Fluid};
+use crate::TimestepManager;
+
+/// A well-defined point of a substep at which the substep hooks are invoked.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub enum SubstepStage {
+    /// After the accelerations due to the gravity, the force fields, and the non-pressure forces
+    /// have been computed, and before the length of the substep is chosen.
+    ///
+    /// The accelerations modified at this stage are integrated during the substep, and are
+    /// taken into account by the CFL condition.
+    AfterAdvectionPrediction,
+    /// After the pressure resolution, once the velocities of the particles at the end of the
+    /// substep are known but before the positions are updated.
+    ///
+    /// The velocities modified at this stage are used to update the positions.
+    AfterPressureSolve,
+    /// After the positions of the particles have been integrated, shifted by the particle
+    /// shifting (if any), and the motion of the open boundary buffers enforced.
+    AfterIntegration,
+}
+
+/// The callback of a substep hook, given the timestep manager and the fluids.
+pub type SubstepHookFn = dyn FnMut(&TimestepManager, &mut [Fluid]) + Send + Sync;
+
+/// A user-defined callback invoked at a given stage of each substep.
+///
+/// Substep hooks are added to the liquid world with `LiquidWorld::add_substep_hook`. They can be
+/// used to inject custom logic (gameplay impulses, constraints, etc.) into the simulation loop.
+/// The callback is given the timestep manager, from which the current substep length can be
+/// read, and a mutable access to the fluids, in the same order as
+/// `LiquidWorld::fluids().as_slice()`. The particles must not be added or removed by the hooks.
+pub struct SubstepHook {
+    /// The stage of the substeps at which this hook is invoked.
+    pub stage: SubstepStage,
+    /// The callback invoked at each substep.
+    pub callback: Box<SubstepHookFn>,
+    /// Whether this hook is invoked.
+    pub enabled: bool,
+}
+
+impl SubstepHook {
+    /// Creates a hook invoking `callback` at the given stage of each substep.
+    pub fn new(
+        stage: SubstepStage,
+        callback: impl FnMut(&TimestepManager, &mut [Fluid]) + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            stage,
+            callback: Box::new(callback),
+            enabled: true,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+/// The unique identifier of a substep hook.
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub struct SubstepHookHandle(ContiguousArenaIndex);
+/// A set of all substep hooks.
+pub type SubstepHookSet = ContiguousArena<SubstepHookHandle, SubstepHook>;
+
+impl SubstepHookSet {
+    /// Invokes all the enabled hooks of the given stage.
+    pub(crate) fn run(
+        &mut self,
+        stage: SubstepStage,
+        timestep: &TimestepManager,
+        fluids: &mut [Fluid],
+    ) {
+        for hook in self.as_mut_slice() {
+            if hook.enabled && hook.stage == stage {
+                (hook.callback)(timestep, fluids);
+            }
+        }
+    }
+}
+
+impl From<ContiguousArenaIndex> for SubstepHookHandle {
+    #[inline]
+    fn from(i: ContiguousArenaIndex) -> Self {
+        SubstepHookHandle(i)
+    }
+}
+
+impl From<SubstepHookHandle> for ContiguousArenaIndex {
+    #[inline]
+    fn from(handle: SubstepHookHandle) -> Self {
+        handle.0
+    }
+}
@@ -0,0 +1,248 @@
+use crate::math::{Isometry, Point, Real, Rotation, Vector, DIM};
+use crate::object::interaction_groups::InteractionGroups;
+use crate::object::{Boundary, BoundaryMotion, SpongeLayer};
+use na::RealField;
+
+/// The stroke of a wavemaker as a function of time.
+///
+/// The stroke is a displacement for a piston wavemaker, and an angle (in radians) for a flap
+/// wavemaker. Positive strokes push the paddle toward the inside of the tank.
+pub enum WavemakerProfile {
+    /// A sinusoidal stroke.
+    Sinusoidal {
+        /// The amplitude of the stroke.
+        amplitude: Real,
+        /// The period of the stroke, in seconds.
+        period: Real,
+        /// The duration during which the amplitude increases linearly from zero.
+        ///
+        /// This avoids the shock of a paddle starting to move at full speed.
+        ramp_duration: Real,
+    },
+    /// A user-provided stroke, as a function of time.
+    Custom(Box<dyn Fn(Real) -> Real + Send + Sync>),
+}
+
+impl WavemakerProfile {
+    /// A sinusoidal stroke ramped up during its first period.
+    pub fn sinusoidal(amplitude: Real, period: Real) -> Self {
+        WavemakerProfile::Sinusoidal {
+            amplitude,
+            period,
+            ramp_duration: period,
+        }
+    }
+
+    /// The stroke at the given time.
+    pub fn stroke(&self, time: Real) -> Real {
+        match self {
+            WavemakerProfile::Sinusoidal {
+                amplitude,
+                period,
+                ramp_duration,
+            } => {
+                let ramp = if *ramp_duration > na::zero::<Real>() {
+                    (time / *ramp_duration).min(na::one::<Real>())
+                } else {
+                    na::one::<Real>()
+                };
+
+                *amplitude * ramp * (Real::two_pi() * time / *period).sin()
+            }
+            WavemakerProfile::Custom(f) => f(time),
+        }
+    }
+}
+
+/// The way the paddle of a wavemaker moves.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum WavemakerKind {
+    /// A vertical paddle translating along the `x` axis.
+    Piston,
+    /// A paddle rotating about an horizontal hinge, perpendicular to the `x` axis.
+    Flap {
+        /// The height of the hinge.
+        hinge_height: Real,
+    },
+}
+
+/// A paddle generating waves by moving a boundary with a prescribed stroke.
+///
+/// At rest, the paddle lies on the plane `x = 0` of its local-space (a line in 2D) and the waves
+/// propagate toward the positive `x` axis. See `WaveTank` to set up a whole wave tank.
+pub struct Wavemaker {
+    /// The way the paddle moves.
+    pub kind: WavemakerKind,
+    /// The stroke of the paddle as a function of time.
+    pub profile: WavemakerProfile,
+}
+
+impl Wavemaker {
+    /// A piston wavemaker.
+    pub fn piston(profile: WavemakerProfile) -> Self {
+        Self {
+            kind: WavemakerKind::Piston,
+            profile,
+        }
+    }
+
+    /// A flap wavemaker hinged at the given height.
+    pub fn flap(hinge_height: Real, profile: WavemakerProfile) -> Self {
+        Self {
+            kind: WavemakerKind::Flap { hinge_height },
+            profile,
+        }
+    }
+
+    /// The position of the paddle at the given time, relative to its rest position.
+    pub fn position_at(&self, time: Real) -> Isometry<Real> {
+        let stroke = self.profile.stroke(time);
+
+        match self.kind {
+            WavemakerKind::Piston => Isometry::from(Vector::x() * stroke),
+            WavemakerKind::Flap { hinge_height } => {
+                // Rotating clockwise about the `z` axis pushes the top of the paddle toward `+x`.
+                #[cfg(feature = "dim2")]
+                let rotation = Rotation::new(-stroke);
+                #[cfg(feature = "dim3")]
+                let rotation = Rotation::new(Vector::z() * -stroke);
+
+                Isometry::rotation_wrt_point(rotation, Point::from(Vector::y() * hinge_height))
+            }
+        }
+    }
+
+    /// Converts this wavemaker into the motion of a boundary sampling its paddle.
+    ///
+    /// The paddle is at `rest_position` when the stroke is zero.
+    pub fn into_motion(self, rest_position: Isometry<Real>) -> BoundaryMotion {
+        BoundaryMotion::Trajectory(Box::new(move |time| rest_position * self.position_at(time)))
+    }
+}
+
+/// The boundaries of an axis-aligned wave tank with a wavemaker at one end.
+///
+/// The tank covers the box from the origin to `extents`, with `y` pointing up. The wavemaker
+/// paddle is at `x = 0`, and the waves propagate toward the wall at `x = extents.x`.
+pub struct WaveTank {
+    /// The dimensions of the tank.
+    pub extents: Vector<Real>,
+    /// The floor of the tank.
+    pub floor: Boundary,
+    /// The wall at the end of the tank, and the side walls in 3D.
+    pub walls: Boundary,
+    /// The moving paddle of the wavemaker.
+    pub wavemaker: Boundary,
+}
+
+impl WaveTank {
+    /// Samples the boundaries of a wave tank with the given dimensions.
+    ///
+    /// The particles are at most `2 * particle_radius` apart.
+    pub fn new(
+        extents: Vector<Real>,
+        particle_radius: Real,
+        wavemaker: Wavemaker,
+        interaction_groups: InteractionGroups,
+    ) -> Self {
+        let spacing = particle_radius * na::convert::<_, Real>(2.0);
+        let floor_points = sample_face(&extents, 1, na::zero::<Real>(), spacing);
+        #[cfg_attr(feature = "dim2", allow(unused_mut))]
+        let mut wall_points = sample_face(&extents, 0, extents.x, spacing);
+        let wavemaker_points = sample_face(&extents, 0, na::zero::<Real>(), spacing);
+
+        #[cfg(feature = "dim3")]
+        {
+            wall_points.extend(sample_face(&extents, 2, na::zero::<Real>(), spacing));
+            wall_points.extend(sample_face(&extents, 2, extents.z, spacing));
+        }
+
+        let mut wavemaker_boundary = Boundary::new(wavemaker_points, interaction_groups);
+        wavemaker_boundary.set_motion(Some(wavemaker.into_motion(Isometry::identity())));
+
+        Self {
+            extents,
+            floor: Boundary::new(floor_points, interaction_groups),
+            walls: Boundary::new(wall_points, interaction_groups),
+            wavemaker: wavemaker_boundary,
+        }
+    }
+
+    /// A sponge layer of the given thickness in front of the wall at the end of the tank.
+    ///
+    /// This absorbs the waves generated by the wavemaker instead of reflecting them.
+    pub fn sponge_layer(&self, thickness: Real, damping: Real) -> SpongeLayer {
+        let mut point = Point::origin();
+        point.x = self.extents.x - thickness;
+        SpongeLayer::new(point, Vector::x(), thickness, damping)
+    }
+
+    /// The positions of fluid particles, arranged on a lattice, filling the tank up to the
+    /// given depth.
+    pub fn water_particles(&self, depth: Real, particle_radius: Real) -> Vec<Point<Real>> {
+        let mut extents = self.extents;
+        extents.y = depth;
+        lattice(&extents, particle_radius)
+    }
+}
+
+// Samples the face of the box `[0, extents]` orthogonal to the `axis`-th axis, at the given
+// coordinate along this axis.
+fn sample_face(
+    extents: &Vector<Real>,
+    axis: usize,
+    coord: Real,
+    spacing: Real,
+) -> Vec<Point<Real>> {
+    let num_subdivs = extents.map(|e| {
+        na::try_convert::<Real, f64>(e / spacing)
+            .unwrap()
+            .ceil()
+            .max(1.0) as usize
+    });
+    let mut counts = num_subdivs.map(|n| n + 1);
+    counts[axis] = 1;
+    let mut points = Vec::new();
+
+    for id in 0..counts.iter().product() {
+        let mut pt = Point::origin();
+        let mut rest = id;
+
+        for k in 0..DIM {
+            pt[k] = if k == axis {
+                coord
+            } else {
+                extents[k] * na::convert::<_, Real>((rest % counts[k]) as f64)
+                    / na::convert::<_, Real>(num_subdivs[k] as f64)
+            };
+            rest /= counts[k];
+        }
+
+        points.push(pt);
+    }
+
+    points
+}
+
+// Fills the box `[0, extents]` with particles spaced by `2 * particle_radius`, one particle
+// radius away from its faces.
+fn lattice(extents: &Vector<Real>, particle_radius: Real) -> Vec<Point<Real>> {
+    let spacing = particle_radius * na::convert::<_, Real>(2.0);
+    let counts =
+        extents.map(|e| na::try_convert::<Real, f64>(e / spacing).unwrap().floor() as usize);
+    let mut points = Vec::new();
+
+    for id in 0..counts.iter().product() {
+        let mut pt = Point::origin();
+        let mut rest = id;
+
+        for k in 0..DIM {
+            pt[k] = particle_radius + spacing * na::convert::<_, Real>((rest % counts[k]) as f64);
+            rest /= counts[k];
+        }
+
+        points.push(pt);
+    }
+
+    points
+}
@@ -0,0 +1,64 @@
+//! Deterministic pseudo-random number generation.
+
+use crate::math::{Real, Vector};
+#[cfg(feature = "dim3")]
+use na::RealField;
+
+/// A xorshift64* pseudo-random number generator.
+///
+/// This is not suitable for cryptography, but is fast and gives the same sequence on every
+/// platform for a given seed.
+#[derive(Copy, Clone, Debug)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub(crate) struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    /// Initializes a generator with the given seed.
+    pub fn new(seed: u64) -> Self {
+        // Xorshift generators must not have a zero state.
+        Self { state: seed.max(1) }
+    }
+
+    /// A random number in `[0, 1)`.
+    pub fn next_real(&mut self) -> Real {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        let bits = self.state.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 11;
+        na::convert::<_, Real>(bits as f64 / (1u64 << 53) as f64)
+    }
+
+    /// A random vector uniformly distributed on the disk with the given radius orthogonal to
+    /// `normal`. In 2D, this is the segment of half-length `radius` orthogonal to `normal`.
+    pub fn disk_point(&mut self, normal: &Vector<Real>, radius: Real) -> Vector<Real> {
+        #[cfg(feature = "dim2")]
+        {
+            let tangent = Vector::new(-normal.y, normal.x)
+                .try_normalize(na::zero::<Real>())
+                .unwrap_or_else(Vector::x);
+            tangent
+                * ((self.next_real() * na::convert::<_, Real>(2.0) - na::one::<Real>()) * radius)
+        }
+
+        #[cfg(feature = "dim3")]
+        {
+            let normal = normal
+                .try_normalize(na::zero::<Real>())
+                .unwrap_or_else(Vector::z);
+            let e1 = if normal.x.abs() > na::convert::<_, Real>(0.5) {
+                Vector::y().cross(&normal).normalize()
+            } else {
+                Vector::x().cross(&normal).normalize()
+            };
+            let e2 = normal.cross(&e1);
+            let r = self.next_real().sqrt() * radius;
+            let angle = self.next_real() * Real::two_pi();
+            e1 * (r * angle.cos()) + e2 * (r * angle.sin())
+        }
+    }
+}
@@ -0,0 +1,565 @@
+//! Recording and deterministic playback of simulations.
+//!
+//! A `ReplayRecorder` captures the initial state of a liquid world and all the inputs given to
+//! it afterward: the step lengths and gravities, the user-defined events applied between steps
+//! (see `ReplayEvent`), and the boundary motions imposed by a coupling manager. The resulting
+//! `Recording` can be replayed on a liquid world built the same way as the recorded one was at
+//! the beginning of the recording, producing the same results. The particle states at the end of
+//! each step can also be recorded, with delta compression, to play them back without simulating.
+
+use crate::coupling::CouplingManager;
+use crate::geometry::NeighborSearch;
+use crate::math::{Point, Real, Vector};
+use crate::object::{BoundarySet, Fluid};
+use crate::{LiquidWorld, SnapshotError, TimestepManager};
+
+const FRAME_DELTA: u8 = 1;
+const FRAME_DEFLATED: u8 = 2;
+
+/// An input of the simulation, applied to the liquid world between two steps.
+///
+/// This is implemented by the user to record any modification of the liquid world which is not
+/// a step: changes of parameters, particles added or removed manually, emitters enabled, etc.
+pub trait ReplayEvent {
+    /// Applies this event to the liquid world.
+    fn apply(&self, world: &mut LiquidWorld);
+}
+
+impl ReplayEvent for () {
+    fn apply(&self, _: &mut LiquidWorld) {}
+}
+
+/// The particles of a boundary, as set by a coupling manager during a substep.
+#[derive(Clone, Debug)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+struct BoundaryState {
+    positions: Vec<Point<Real>>,
+    velocities: Vec<Vector<Real>>,
+}
+
+/// The inputs of a single recorded step.
+#[derive(Clone, Debug)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub struct RecordedStep<E> {
+    /// The length of the step.
+    pub dt: Real,
+    /// The gravity given to the step.
+    pub gravity: Vector<Real>,
+    /// The events applied to the liquid world before the step, in order.
+    pub events: Vec<E>,
+    // The boundaries set by the coupling manager at each substep, if any.
+    boundary_states: Option<Vec<Vec<BoundaryState>>>,
+}
+
+/// The inputs of a simulation recorded by a `ReplayRecorder`.
+#[derive(Clone, Debug)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub struct Recording<E> {
+    initial_state: Vec<u8>,
+    steps: Vec<RecordedStep<E>>,
+    particle_states: Vec<Vec<u8>>,
+}
+
+/// Records the inputs of a simulation so it can be replayed deterministically.
+///
+/// The steps must be performed through the recorder with `Self::step` or
+/// `Self::step_with_coupling`, and the other modifications of the liquid world must be applied
+/// with `Self::apply_event`.
+pub struct ReplayRecorder<E> {
+    recording: Recording<E>,
+    pending_events: Vec<E>,
+    keyframe_interval: Option<usize>,
+    previous_particle_state: Vec<u8>,
+}
+
+impl<E: ReplayEvent> ReplayRecorder<E> {
+    /// Starts recording the simulation of `world` from its current state.
+    pub fn new(world: &LiquidWorld) -> Self {
+        Self {
+            recording: Recording {
+                initial_state: world.snapshot(),
+                steps: Vec::new(),
+                particle_states: Vec::new(),
+            },
+            pending_events: Vec::new(),
+            keyframe_interval: None,
+            previous_particle_state: Vec::new(),
+        }
+    }
+
+    /// Enables the recording of the particle states at the end of each step.
+    ///
+    /// The particle states are stored as differences with the previous step, except for one step
+    /// every `keyframe_interval` steps which is stored entirely, bounding the cost of accessing a
+    /// given step with `Recording::particle_state`.
+    pub fn with_particle_states(mut self, keyframe_interval: usize) -> Self {
+        self.keyframe_interval = Some(keyframe_interval.max(1));
+        self
+    }
+
+    /// Applies `event` to `world`, and records it so it is applied again before the same step
+    /// during replays.
+    pub fn apply_event(&mut self, world: &mut LiquidWorld, event: E) {
+        event.apply(world);
+        self.pending_events.push(event);
+    }
+
+    /// Advances the simulation of `world` by `dt` seconds, and records this step.
+    pub fn step(&mut self, world: &mut LiquidWorld, dt: Real, gravity: &Vector<Real>) {
+        world.step(dt, gravity);
+        self.push_step(world, dt, gravity, None);
+    }
+
+    /// Advances the simulation of `world` by `dt` seconds with coupling, and records this step.
+    ///
+    /// The boundaries set by `coupling` at each substep are recorded, so the coupled bodies are
+    /// not needed to replay this step. The forces applied by the fluids to the coupled bodies
+    /// are not recorded.
+    pub fn step_with_coupling(
+        &mut self,
+        world: &mut LiquidWorld,
+        dt: Real,
+        gravity: &Vector<Real>,
+        coupling: &mut impl CouplingManager,
+    ) {
+        let mut recording_coupling = RecordingCoupling {
+            coupling,
+            boundary_states: Vec::new(),
+        };
+        world.step_with_coupling(dt, gravity, &mut recording_coupling);
+        let boundary_states = recording_coupling.boundary_states;
+        self.push_step(world, dt, gravity, Some(boundary_states));
+    }
+
+    fn push_step(
+        &mut self,
+        world: &LiquidWorld,
+        dt: Real,
+        gravity: &Vector<Real>,
+        boundary_states: Option<Vec<Vec<BoundaryState>>>,
+    ) {
+        self.recording.steps.push(RecordedStep {
+            dt,
+            gravity: *gravity,
+            events: std::mem::take(&mut self.pending_events),
+            boundary_states,
+        });
+
+        if let Some(keyframe_interval) = self.keyframe_interval {
+            let state = world.snapshot();
+            // `usize::is_multiple_of` would require Rust 1.87.
+            #[allow(clippy::manual_is_multiple_of)]
+            let is_keyframe = self.recording.particle_states.len() % keyframe_interval == 0;
+            let previous = Some(&self.previous_particle_state[..])
+                .filter(|previous| !is_keyframe && previous.len() == state.len());
+            self.recording
+                .particle_states
+                .push(encode_frame(previous, &state));
+            self.previous_particle_state = state;
+        }
+    }
+
+    /// The inputs recorded so far.
+    ///
+    /// The events applied since the last step are not part of it.
+    pub fn recording(&self) -> &Recording<E> {
+        &self.recording
+    }
+
+    /// Stops recording, and returns the recorded inputs.
+    pub fn finish(self) -> Recording<E> {
+        self.recording
+    }
+}
+
+impl<E: ReplayEvent> Recording<E> {
+    /// The number of recorded steps.
+    pub fn num_steps(&self) -> usize {
+        self.steps.len()
+    }
+
+    /// The recorded steps.
+    pub fn steps(&self) -> &[RecordedStep<E>] {
+        &self.steps
+    }
+
+    /// Restores the particle state of `world` at the beginning of the recording.
+    pub fn restore_initial_state(&self, world: &mut LiquidWorld) -> Result<(), SnapshotError> {
+        world.restore(&self.initial_state)
+    }
+
+    /// Applies the events of the `i`-th recorded step to `world`, then performs this step.
+    pub fn replay_step(&self, world: &mut LiquidWorld, i: usize) {
+        let step = &self.steps[i];
+
+        for event in &step.events {
+            event.apply(world);
+        }
+
+        match &step.boundary_states {
+            Some(boundary_states) => {
+                let mut coupling = ReplayCoupling {
+                    boundary_states,
+                    substep: 0,
+                };
+                world.step_with_coupling(step.dt, &step.gravity, &mut coupling);
+            }
+            None => world.step(step.dt, &step.gravity),
+        }
+    }
+
+    /// Restores the initial state of the recording into `world`, and replays all the steps.
+    ///
+    /// The liquid world must be set up as the recorded one was at the beginning of the recording
+    /// (same fluids, boundaries, solvers, etc.) for the results to be identical.
+    pub fn replay(&self, world: &mut LiquidWorld) -> Result<(), SnapshotError> {
+        self.restore_initial_state(world)?;
+
+        for i in 0..self.steps.len() {
+            self.replay_step(world, i);
+        }
+
+        Ok(())
+    }
+
+    /// The snapshot of the particle state at the end of the `i`-th step, if the particle states
+    /// were recorded.
+    ///
+    /// The snapshot can be restored with `LiquidWorld::restore`.
+    pub fn particle_state(&self, i: usize) -> Result<Option<Vec<u8>>, SnapshotError> {
+        if i >= self.particle_states.len() {
+            return Ok(None);
+        }
+
+        let keyframe = (0..=i)
+            .rev()
+            .find(|k| {
+                self.particle_states[*k]
+                    .first()
+                    .is_some_and(|flags| flags & FRAME_DELTA == 0)
+            })
+            .ok_or(SnapshotError::InvalidFormat)?;
+        let mut state = decode_frame(None, &self.particle_states[keyframe])?;
+
+        for frame in &self.particle_states[keyframe + 1..=i] {
+            state = decode_frame(Some(&state), frame)?;
+        }
+
+        Ok(Some(state))
+    }
+
+    /// Restores the particle state of `world` at the end of the `i`-th step, without simulating.
+    ///
+    /// Returns `Ok(false)` if the particle states were not recorded.
+    pub fn restore_particle_state(
+        &self,
+        world: &mut LiquidWorld,
+        i: usize,
+    ) -> Result<bool, SnapshotError> {
+        match self.particle_state(i)? {
+            Some(state) => world.restore(&state).map(|_| true),
+            None => Ok(false),
+        }
+    }
+}
+
+struct RecordingCoupling<'a, C> {
+    coupling: &'a mut C,
+    boundary_states: Vec<Vec<BoundaryState>>,
+}
+
+impl<C: CouplingManager> CouplingManager for RecordingCoupling<'_, C> {
+    fn update_boundaries(
+        &mut self,
+        timestep: &TimestepManager,
+        h: Real,
+        particle_radius: Real,
+        neighbor_search: &dyn NeighborSearch,
+        fluids: &mut [Fluid],
+        boundaries: &mut BoundarySet,
+    ) {
+        self.coupling.update_boundaries(
+            timestep,
+            h,
+            particle_radius,
+            neighbor_search,
+            fluids,
+            boundaries,
+        );
+        self.boundary_states.push(
+            boundaries
+                .as_slice()
+                .iter()
+                .map(|boundary| BoundaryState {
+                    positions: boundary.positions.clone(),
+                    velocities: boundary.velocities.clone(),
+                })
+                .collect(),
+        );
+    }
+
+    fn transmit_forces(&mut self, timestep: &TimestepManager, boundaries: &BoundarySet) {
+        self.coupling.transmit_forces(timestep, boundaries);
+    }
+}
+
+struct ReplayCoupling<'a> {
+    boundary_states: &'a [Vec<BoundaryState>],
+    substep: usize,
+}
+
+impl CouplingManager for ReplayCoupling<'_> {
+    fn update_boundaries(
+        &mut self,
+        _: &TimestepManager,
+        _: Real,
+        _: Real,
+        _: &dyn NeighborSearch,
+        _: &mut [Fluid],
+        boundaries: &mut BoundarySet,
+    ) {
+        if let Some(states) = self.boundary_states.get(self.substep) {
+            for (boundary, state) in boundaries.as_mut_slice().iter_mut().zip(states) {
+                boundary.positions.clone_from(&state.positions);
+                boundary.velocities.clone_from(&state.velocities);
+            }
+        }
+
+        self.substep += 1;
+    }
+
+    fn transmit_forces(&mut self, _: &TimestepManager, _: &BoundarySet) {}
+}
+
+// Encodes a snapshot as its difference with the previous one (if any). The runs of zeros of the
+// difference are then run-length encoded, and the result deflated with the `compression` feature.
+fn encode_frame(previous: Option<&[u8]>, state: &[u8]) -> Vec<u8> {
+    let mut flags = 0;
+    let delta: Vec<u8> = match previous {
+        Some(previous) => {
+            flags |= FRAME_DELTA;
+            state.iter().zip(previous).map(|(a, b)| a ^ b).collect()
+        }
+        None => state.to_vec(),
+    };
+
+    let encoded = encode_zero_runs(&delta);
+
+    #[cfg(feature = "compression")]
+    let encoded = {
+        flags |= FRAME_DEFLATED;
+        miniz_oxide::deflate::compress_to_vec(&encoded, 6)
+    };
+
+    let mut result = Vec::with_capacity(encoded.len() + 1);
+    result.push(flags);
+    result.extend_from_slice(&encoded);
+    result
+}
+
+fn decode_frame(previous: Option<&[u8]>, frame: &[u8]) -> Result<Vec<u8>, SnapshotError> {
+    let (flags, encoded) = frame.split_first().ok_or(SnapshotError::InvalidFormat)?;
+
+    #[cfg(feature = "compression")]
+    let inflated;
+    let encoded = if flags & FRAME_DEFLATED != 0 {
+        #[cfg(feature = "compression")]
+        {
+            inflated = miniz_oxide::inflate::decompress_to_vec(encoded)
+                .map_err(|_| SnapshotError::InvalidFormat)?;
+            &inflated[..]
+        }
+        #[cfg(not(feature = "compression"))]
+        {
+            return Err(SnapshotError::CompressionUnsupported);
+        }
+    } else {
+        encoded
+    };
+    let mut state = decode_zero_runs(encoded)?;
+
+    if flags & FRAME_DELTA != 0 {
+        let previous = previous
+            .filter(|previous| previous.len() == state.len())
+            .ok_or(SnapshotError::InvalidFormat)?;
+        state.iter_mut().zip(previous).for_each(|(a, b)| *a ^= b);
+    }
+
+    Ok(state)
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+
+    out.push(value as u8);
+}
+
+fn read_varint(data: &mut &[u8]) -> Result<usize, SnapshotError> {
+    let mut value = 0;
+    let mut shift = 0;
+
+    loop {
+        let (byte, rest) = data.split_first().ok_or(SnapshotError::InvalidFormat)?;
+        *data = rest;
+
+        if shift >= usize::BITS {
+            return Err(SnapshotError::InvalidFormat);
+        }
+
+        value |= ((byte & 0x7f) as usize) << shift;
+        shift += 7;
+
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+}
+
+// Encodes the data as a sequence of (number of zeros, number of literal bytes, literal bytes).
+fn encode_zero_runs(data: &[u8]) -> Vec<u8> {
+    let mut result = Vec::new();
+    let mut i = 0;
+
+    while i < data.len() {
+        let zeros_start = i;
+        while i < data.len() && data[i] == 0 {
+            i += 1;
+        }
+
+        let literals_start = i;
+        // Isolated zeros are kept in the literals.
+        while i < data.len() && !(data[i] == 0 && data.get(i + 1) == Some(&0)) {
+            i += 1;
+        }
+
+        write_varint(&mut result, literals_start - zeros_start);
+        write_varint(&mut result, i - literals_start);
+        result.extend_from_slice(&data[literals_start..i]);
+    }
+
+    result
+}
+
+fn decode_zero_runs(mut data: &[u8]) -> Result<Vec<u8>, SnapshotError> {
+    let mut result = Vec::new();
+
+    while !data.is_empty() {
+        let num_zeros = read_varint(&mut data)?;
+        let num_literals = read_varint(&mut data)?;
+
+        if num_literals > data.len() {
+            return Err(SnapshotError::InvalidFormat);
+        }
+
+        result.resize(result.len() + num_zeros, 0);
+        result.extend_from_slice(&data[..num_literals]);
+        data = &data[num_literals..];
+    }
+
+    Ok(result)
+}
+
+#[test]
+fn zero_runs_roundtrip() {
+    let mut value = &[][..];
+    assert_eq!(read_varint(&mut value), Err(SnapshotError::InvalidFormat));
+
+    for value in [0, 1, 0x7f, 0x80, 0x3fff, 0x4000, usize::MAX] {
+        let mut encoded = Vec::new();
+        write_varint(&mut encoded, value);
+        let mut data = &encoded[..];
+        assert_eq!(read_varint(&mut data), Ok(value));
+        assert!(data.is_empty());
+    }
+
+    let data = [0, 0, 0, 1, 0, 2, 0, 0, 3, 0, 0];
+    let encoded = encode_zero_runs(&data);
+    // Two runs of zeros, the isolated zeros are kept with the literals.
+    assert_eq!(encoded, [3, 3, 1, 0, 2, 2, 1, 3, 2, 0]);
+    assert_eq!(decode_zero_runs(&encoded).unwrap(), data);
+    assert!(encode_zero_runs(&[]).is_empty());
+    assert_eq!(
+        decode_zero_runs(&[0, 3, 1]),
+        Err(SnapshotError::InvalidFormat)
+    );
+}
+
+#[test]
+fn frames_roundtrip() {
+    let previous: Vec<u8> = (0..100).collect();
+    let mut state = previous.clone();
+    state[10] = 255;
+    state[50] = 0;
+
+    let keyframe = encode_frame(None, &state);
+    assert_eq!(keyframe[0] & FRAME_DELTA, 0);
+    assert_eq!(decode_frame(None, &keyframe).unwrap(), state);
+
+    let delta = encode_frame(Some(&previous), &state);
+    assert_ne!(delta[0] & FRAME_DELTA, 0);
+    assert!(delta.len() < keyframe.len());
+    assert_eq!(decode_frame(Some(&previous), &delta).unwrap(), state);
+    assert_eq!(
+        decode_frame(None, &delta),
+        Err(SnapshotError::InvalidFormat)
+    );
+    assert_eq!(decode_frame(None, &[]), Err(SnapshotError::InvalidFormat));
+}
+
+#[test]
+fn replay_reproduces_the_recorded_steps() {
+    use crate::liquid_world::{test_lattice, test_world};
+    use crate::math::DIM;
+
+    let nz = if DIM == 3 { 3 } else { 1 };
+    let new_world = || {
+        let mut world = test_world(0.05);
+        world.set_deterministic(true);
+        let block = test_lattice([0, 0, 0], [6, 4, nz], &|_| true);
+        let _ = world.add_fluid(Fluid::new(block, 0.05, 1000.0, Default::default()));
+        world
+    };
+    let bits = |world: &LiquidWorld| -> Vec<_> {
+        world.fluids().as_slice()[0]
+            .positions
+            .iter()
+            .flat_map(|p| p.iter().map(|x| x.to_bits()))
+            .collect()
+    };
+
+    let mut world = new_world();
+    let mut recorder = ReplayRecorder::<()>::new(&world).with_particle_states(2);
+    let mut positions = Vec::new();
+
+    for _ in 0..5 {
+        recorder.step(&mut world, 0.01, &(Vector::y() * -9.81));
+        positions.push(bits(&world));
+    }
+
+    let recording = recorder.finish();
+    assert_eq!(recording.num_steps(), 5);
+
+    let mut replayed = new_world();
+    replayed.step(0.01, &Vector::zeros());
+    recording.replay(&mut replayed).unwrap();
+    assert_eq!(bits(&replayed), positions[4]);
+
+    for (i, expected) in positions.iter().enumerate() {
+        assert!(recording.restore_particle_state(&mut replayed, i).unwrap());
+        assert_eq!(&bits(&replayed), expected);
+    }
+
+    assert_eq!(recording.particle_state(5), Ok(None));
+}
@@ -0,0 +1,138 @@
+use crate::math::{Isometry, Point, Real, Vector};
+use crate::object::interaction_groups::InteractionGroups;
+use crate::object::Boundary;
+
+use parry::shape::HeightField;
+
+/// Samples the surface of a heightfield, placed at `position`, with evenly spaced particles.
+///
+/// Each cell of the heightfield is sampled independently, with a number of particles adapted to
+/// its slope so that neighbor particles are at most `2 * particle_rad` apart along the surface.
+/// Removed cells are not sampled. Returns the world-space positions of the particles and the
+/// upward unit normals of the heightfield at these positions.
+pub fn heightfield_surface_sample(
+    heightfield: &HeightField,
+    position: &Isometry<Real>,
+    particle_rad: Real,
+) -> (Vec<Point<Real>>, Vec<Vector<Real>>) {
+    let spacing = particle_rad * na::convert::<_, Real>(2.0);
+    let num_subdivs = |len: Real| {
+        na::try_convert::<Real, f64>(len / spacing)
+            .unwrap()
+            .ceil()
+            .max(1.0) as usize
+    };
+    let mut points = Vec::new();
+    let mut normals = Vec::new();
+
+    #[cfg(feature = "dim2")]
+    for segment in heightfield.segments() {
+        let dir = segment.b - segment.a;
+        let normal = Vector::new(-dir.y, dir.x)
+            .try_normalize(na::zero::<Real>())
+            .unwrap_or_else(Vector::y);
+        let n = num_subdivs(dir.norm());
+
+        for k in 0..n {
+            let t = na::convert::<_, Real>((k as f64 + 0.5) / n as f64);
+            points.push(position * (segment.a + dir * t));
+            normals.push(position * normal);
+        }
+    }
+
+    #[cfg(feature = "dim3")]
+    {
+        let (num_rows, num_cols) = heightfield.num_cells_ij();
+        let heights = heightfield.heights();
+        let scale = heightfield.scale();
+        let cell_width = heightfield.cell_width();
+        let cell_height = heightfield.cell_height();
+
+        for i in 0..num_rows {
+            for j in 0..num_cols {
+                let triangles = heightfield.triangles_at(i, j);
+                let triangles = [triangles.0, triangles.1];
+
+                // The longest edges of the cell along the x and z axes, accounting for the slope.
+                let height = |row, col| heights[(row, col)] * scale.y;
+                let edge_len = |width: Real, dy: Real| (width * width + dy * dy).sqrt();
+                let len_x = edge_len(cell_width, height(i, j + 1) - height(i, j)).max(edge_len(
+                    cell_width,
+                    height(i + 1, j + 1) - height(i + 1, j),
+                ));
+                let len_z = edge_len(cell_height, height(i + 1, j) - height(i, j)).max(edge_len(
+                    cell_height,
+                    height(i + 1, j + 1) - height(i, j + 1),
+                ));
+                let (nx, nz) = (num_subdivs(len_x), num_subdivs(len_z));
+                let x0 = heightfield.x_at(j);
+                let z0 = heightfield.z_at(i);
+
+                for k in 0..nx {
+                    for l in 0..nz {
+                        let x =
+                            x0 + cell_width * na::convert::<_, Real>((k as f64 + 0.5) / nx as f64);
+                        let z =
+                            z0 + cell_height * na::convert::<_, Real>((l as f64 + 0.5) / nz as f64);
+
+                        for triangle in triangles.iter().flatten() {
+                            if let Some(point) = project_on_triangle(triangle, x, z) {
+                                let mut normal = triangle
+                                    .normal()
+                                    .map(|n| n.into_inner())
+                                    .unwrap_or_else(Vector::y);
+
+                                if normal.y < na::zero::<Real>() {
+                                    normal = -normal;
+                                }
+
+                                points.push(position * point);
+                                normals.push(position * normal);
+                                break;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    (points, normals)
+}
+
+/// Creates a boundary covering the surface of a heightfield placed at `position`.
+///
+/// See `heightfield_surface_sample` for details about the sampling.
+pub fn heightfield_boundary(
+    heightfield: &HeightField,
+    position: &Isometry<Real>,
+    particle_rad: Real,
+    interaction_groups: InteractionGroups,
+) -> Boundary {
+    let (points, _) = heightfield_surface_sample(heightfield, position, particle_rad);
+    Boundary::new(points, interaction_groups)
+}
+
+// The point of the triangle with the given x and z coordinates, if any.
+#[cfg(feature = "dim3")]
+fn project_on_triangle(triangle: &parry::shape::Triangle, x: Real, z: Real) -> Option<Point<Real>> {
+    let (a, b, c) = (triangle.a, triangle.b, triangle.c);
+    let ab = na::Vector2::new(b.x - a.x, b.z - a.z);
+    let ac = na::Vector2::new(c.x - a.x, c.z - a.z);
+    let ap = na::Vector2::new(x - a.x, z - a.z);
+    let det = ab.perp(&ac);
+
+    if det == na::zero::<Real>() {
+        return None;
+    }
+
+    let u = ap.perp(&ac) / det;
+    let v = ab.perp(&ap) / det;
+    let eps = na::convert::<_, Real>(1.0e-5);
+
+    if u >= -eps && v >= -eps && u + v <= na::one::<Real>() + eps {
+        Some(a + (b - a) * u + (c - a) * v)
+    } else {
+        None
+    }
+}
@@ -1,7 +1,19 @@
 //! Methods for converting shapes from parry to sets of points.
 
+pub use self::heightfield_sampling::{heightfield_boundary, heightfield_surface_sample};
+pub use self::poisson_disk::PoissonDiskSampler;
 pub use self::ray_sampling::{
     shape_surface_ray_sample, shape_volume_ray_sample, surface_ray_sample, volume_ray_sample,
 };
+pub use self::shape_sampling::shape_surface_sample;
+pub use self::trimesh_sampling::trimesh_surface_sample;
+pub use self::volume_sampling::{
+    shape_fluid, shape_volume_sample, trimesh_fluid, trimesh_volume_sample, ParticlePacking,
+};
 
+mod heightfield_sampling;
+mod poisson_disk;
 mod ray_sampling;
+mod shape_sampling;
+mod trimesh_sampling;
+mod volume_sampling;
@@ -0,0 +1,144 @@
+use crate::geometry::HGrid;
+use crate::math::{Point, Real, Vector, DIM};
+use crate::object::EmitterShape;
+use crate::random::Rng;
+
+use parry::bounding_volume::Aabb;
+
+/// A sampler generating points with a Poisson-disk (blue-noise) distribution.
+///
+/// Points are accepted greedily: a candidate point is rejected if it is closer than
+/// `min_dist` to a point accepted previously. Candidates are generated from a random number
+/// generator initialized with a user-provided seed, so the same seed and the same sequence of
+/// calls always yield the same points. Unlike regular grids, the resulting particles have no
+/// preferred direction, which avoids lattice artifacts at the beginning of simulations.
+pub struct PoissonDiskSampler {
+    min_dist: Real,
+    grid: HGrid<usize>,
+    points: Vec<Point<Real>>,
+    rng: Rng,
+}
+
+impl PoissonDiskSampler {
+    /// Creates a sampler rejecting points closer than `min_dist` to each other.
+    pub fn new(min_dist: Real, seed: u64) -> Self {
+        Self {
+            min_dist,
+            grid: HGrid::new(min_dist),
+            points: Vec::new(),
+            rng: Rng::new(seed),
+        }
+    }
+
+    /// The minimum distance between two points of this sampler.
+    pub fn min_dist(&self) -> Real {
+        self.min_dist
+    }
+
+    /// The points accepted so far.
+    pub fn points(&self) -> &[Point<Real>] {
+        &self.points
+    }
+
+    /// Consumes this sampler and returns the points accepted so far.
+    pub fn into_points(self) -> Vec<Point<Real>> {
+        self.points
+    }
+
+    /// Adds `point` to this sampler if it is not too close to the points accepted so far.
+    ///
+    /// Returns `true` if the point was accepted.
+    pub fn try_insert(&mut self, point: Point<Real>) -> bool {
+        let extents = Vector::repeat(self.min_dist);
+        let too_close = self
+            .grid
+            .cells_intersecting_aabb(&(point - extents), &(point + extents))
+            .flat_map(|(_, ids)| ids.iter())
+            .any(|id| {
+                na::distance_squared(&self.points[*id], &point) < self.min_dist * self.min_dist
+            });
+
+        if !too_close {
+            self.grid.insert(&point, self.points.len());
+            self.points.push(point);
+        }
+
+        !too_close
+    }
+
+    /// Tries to insert the given candidates in a random order.
+    ///
+    /// Returns the number of accepted candidates.
+    pub fn insert_shuffled(&mut self, mut candidates: Vec<Point<Real>>) -> usize {
+        for i in (1..candidates.len()).rev() {
+            let j = na::try_convert::<Real, f64>(
+                self.rng.next_real() * na::convert::<_, Real>((i + 1) as f64),
+            )
+            .unwrap() as usize;
+            candidates.swap(i, j.min(i));
+        }
+
+        candidates
+            .into_iter()
+            .filter(|candidate| self.try_insert(*candidate))
+            .count()
+    }
+
+    /// Samples the surface of a triangle mesh (a polyline in 2D).
+    ///
+    /// About 16 (4 in 2D) random candidates per area covered by a disk of diameter `min_dist`
+    /// are generated uniformly on the mesh, so the surface ends up densely covered.
+    /// Returns the number of accepted points.
+    pub fn sample_surface(&mut self, vertices: &[Point<Real>], indices: &[[u32; DIM]]) -> usize {
+        let candidate_spacing = self.min_dist / na::convert::<_, Real>(4.0);
+        let mut candidates = Vec::new();
+
+        for element in indices {
+            let measure = EmitterShape::element_measure(vertices, element);
+            let num_candidates =
+                na::try_convert::<Real, f64>(measure / candidate_spacing.powi(DIM as i32 - 1))
+                    .unwrap()
+                    .ceil() as usize;
+
+            for _ in 0..num_candidates {
+                candidates.push(EmitterShape::sample_element(
+                    &mut self.rng,
+                    vertices,
+                    element,
+                ));
+            }
+        }
+
+        self.insert_shuffled(candidates)
+    }
+
+    /// Samples the region of `aabb` for which `contains` returns `true`.
+    ///
+    /// Random candidates are drawn uniformly in the AABB until `max_points` points have been
+    /// accepted, or until `max_attempts` candidates have been drawn. Returns the number of
+    /// accepted points.
+    pub fn sample_volume(
+        &mut self,
+        aabb: &Aabb,
+        max_points: usize,
+        max_attempts: usize,
+        contains: impl Fn(&Point<Real>) -> bool,
+    ) -> usize {
+        let extents = aabb.extents();
+        let mut num_accepted = 0;
+
+        for _ in 0..max_attempts {
+            if num_accepted == max_points {
+                break;
+            }
+
+            let point = aabb.mins + extents.map(|e| e * self.rng.next_real());
+
+            if contains(&point) && self.try_insert(point) {
+                num_accepted += 1;
+            }
+        }
+
+        num_accepted
+    }
+}
@@ -0,0 +1,165 @@
+use super::{heightfield_surface_sample, shape_surface_ray_sample, trimesh_surface_sample};
+use crate::math::{Isometry, Point, Real, DIM};
+
+use na::RealField;
+use parry::shape::{Shape, TypedShape};
+
+/// Samples the surface of `shape`, in its local-space, with evenly distributed particles.
+///
+/// The standard parry shapes (balls, cuboids, capsules, convex shapes, triangle meshes, and, in
+/// 3D, cylinders and cones) are converted to a triangle mesh (a polyline in 2D) sampled with
+/// `trimesh_surface_sample`. Their curved parts are subdivided into elements about as long as
+/// `particle_rad`. Heightfields are sampled with `heightfield_surface_sample`, and the sub-shapes
+/// of compound shapes are sampled independently. The other shapes, e.g., the shapes with rounded
+/// borders, are sampled with `shape_surface_ray_sample`. Half-spaces are infinite so they are not
+/// sampled at all.
+pub fn shape_surface_sample(shape: &dyn Shape, particle_rad: Real) -> Vec<Point<Real>> {
+    let mut points = Vec::new();
+    sample_shape(shape, &Isometry::identity(), particle_rad, &mut points);
+    points
+}
+
+fn sample_shape(
+    shape: &dyn Shape,
+    pose: &Isometry<Real>,
+    particle_rad: Real,
+    out: &mut Vec<Point<Real>>,
+) {
+    let sample = match shape.as_typed_shape() {
+        TypedShape::HalfSpace(_) => Vec::new(),
+        TypedShape::HeightField(heightfield) => {
+            heightfield_surface_sample(heightfield, &Isometry::identity(), particle_rad).0
+        }
+        TypedShape::Compound(compound) => {
+            for (sub_pose, sub_shape) in compound.shapes() {
+                sample_shape(&**sub_shape, &(pose * sub_pose), particle_rad, out);
+            }
+
+            return;
+        }
+        typed_shape => match shape_mesh(typed_shape, particle_rad) {
+            Some((vertices, indices)) => {
+                trimesh_surface_sample(&vertices, &indices, particle_rad, Real::frac_pi_4())
+            }
+            None => shape_surface_ray_sample(shape, particle_rad).unwrap_or_default(),
+        },
+    };
+
+    out.extend(sample.into_iter().map(|pt| pose * pt));
+}
+
+// The vertices and the elements of a triangle mesh (a polyline in 2D).
+type Mesh = (Vec<Point<Real>>, Vec<[u32; DIM]>);
+
+// The number of subdivisions of a circle of the given radius into arcs about as long as
+// `particle_rad`.
+fn num_subdivisions(radius: Real, particle_rad: Real) -> u32 {
+    ((Real::two_pi() * radius / particle_rad).ceil() as u32).max(8)
+}
+
+#[cfg(feature = "dim2")]
+fn shape_mesh(shape: TypedShape, particle_rad: Real) -> Option<Mesh> {
+    match shape {
+        TypedShape::Ball(ball) => Some(closed_polyline(
+            ball.to_polyline(num_subdivisions(ball.radius, particle_rad)),
+        )),
+        TypedShape::Cuboid(cuboid) => Some(closed_polyline(cuboid.to_polyline())),
+        TypedShape::Capsule(capsule) => Some(closed_polyline(
+            capsule.to_polyline(num_subdivisions(capsule.radius, particle_rad) / 2),
+        )),
+        TypedShape::ConvexPolygon(polygon) => Some(closed_polyline(polygon.points().to_vec())),
+        TypedShape::Triangle(triangle) => Some(closed_polyline(triangle.vertices().to_vec())),
+        TypedShape::Segment(segment) => Some((vec![segment.a, segment.b], vec![[0, 1]])),
+        TypedShape::Polyline(polyline) => {
+            Some((polyline.vertices().to_vec(), polyline.indices().to_vec()))
+        }
+        TypedShape::TriMesh(trimesh) => {
+            // The boundary of a 2D triangle mesh is made of the edges belonging to a single triangle.
+            let mut edges = std::collections::HashMap::new();
+
+            for triangle in trimesh.indices() {
+                for k in 0..3 {
+                    let (a, b) = (triangle[k], triangle[(k + 1) % 3]);
+                    *edges.entry((a.min(b), a.max(b))).or_insert(0) += 1;
+                }
+            }
+
+            let indices = edges
+                .into_iter()
+                .filter(|(_, count)| *count == 1)
+                .map(|((a, b), _)| [a, b])
+                .collect();
+            Some((trimesh.vertices().to_vec(), indices))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(feature = "dim2")]
+fn closed_polyline(vertices: Vec<Point<Real>>) -> Mesh {
+    let n = vertices.len() as u32;
+    let indices = (0..n).map(|i| [i, (i + 1) % n]).collect();
+    (vertices, indices)
+}
+
+#[cfg(feature = "dim3")]
+fn shape_mesh(shape: TypedShape, particle_rad: Real) -> Option<Mesh> {
+    match shape {
+        TypedShape::Ball(ball) => {
+            let n = num_subdivisions(ball.radius, particle_rad);
+            Some(ball.to_trimesh(n, n / 2))
+        }
+        TypedShape::Cuboid(cuboid) => Some(cuboid.to_trimesh()),
+        TypedShape::Capsule(capsule) => {
+            let n = num_subdivisions(capsule.radius, particle_rad);
+            Some(capsule.to_trimesh(n, n / 2))
+        }
+        TypedShape::Cylinder(cylinder) => {
+            Some(cylinder.to_trimesh(num_subdivisions(cylinder.radius, particle_rad)))
+        }
+        TypedShape::Cone(cone) => {
+            Some(cone.to_trimesh(num_subdivisions(cone.radius, particle_rad)))
+        }
+        TypedShape::ConvexPolyhedron(polyhedron) => Some(polyhedron.to_trimesh()),
+        TypedShape::Triangle(triangle) => Some((triangle.vertices().to_vec(), vec![[0, 1, 2]])),
+        TypedShape::TriMesh(trimesh) => {
+            Some((trimesh.vertices().to_vec(), trimesh.indices().to_vec()))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::shape_surface_sample;
+    use crate::math::{Isometry, Real, Vector};
+    use parry::shape::{Ball, Compound, Cuboid, SharedShape};
+
+    #[test]
+    fn compound_samples_are_on_the_surface_of_their_sub_shapes() {
+        let particle_rad = 0.05;
+        let compound = Compound::new(vec![
+            (
+                Isometry::new(Vector::x() * 2.0, na::zero()),
+                SharedShape::new(Ball::new(0.5)),
+            ),
+            (
+                Isometry::identity(),
+                SharedShape::new(Cuboid::new(Vector::repeat(0.5))),
+            ),
+        ]);
+        let points = shape_surface_sample(&compound, particle_rad);
+        let num_ball_points = points.iter().filter(|pt| pt.x > 1.0).count();
+
+        assert!(num_ball_points > 0 && num_ball_points < points.len());
+
+        for pt in &points {
+            let dist: Real = if pt.x > 1.0 {
+                ((pt - Vector::x() * 2.0).coords.norm() - 0.5).abs()
+            } else {
+                (pt.coords.amax() - 0.5).abs()
+            };
+            assert!(dist < particle_rad, "{:?}", pt);
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use super::PoissonDiskSampler;
+use crate::math::{Point, Real, Vector, DIM};
+#[cfg(feature = "dim3")]
+use std::collections::HashMap;
+
+/// Samples the surface of a triangle mesh (a polyline in 2D) with evenly distributed particles.
+///
+/// The particles follow a blue-noise distribution: no two particles are closer than
+/// `1.6 * particle_rad`, and every point of the surface is close to a particle. Sharp features are
+/// sampled first so they are always covered: the corners of the mesh, then, in 3D, its sharp
+/// edges. A vertex (or an edge in 3D) is sharp if the angle between the normals of its adjacent
+/// segments (or triangles) exceeds `sharp_angle`, or if it is not shared by exactly two of them.
+///
+/// In 2D, each element of `indices` identifies a segment of the polyline.
+pub fn trimesh_surface_sample(
+    vertices: &[Point<Real>],
+    indices: &[[u32; DIM]],
+    particle_rad: Real,
+    sharp_angle: Real,
+) -> Vec<Point<Real>> {
+    let min_dist = particle_rad * na::convert::<_, Real>(1.6);
+    let cos_sharp_angle = sharp_angle.cos();
+    let mut sampler = PoissonDiskSampler::new(min_dist, 0x9e37_79b9_7f4a_7c15);
+
+    #[cfg(feature = "dim2")]
+    {
+        let mut adjacent_segments = vec![Vec::new(); vertices.len()];
+
+        for (i, segment) in indices.iter().enumerate() {
+            adjacent_segments[segment[0] as usize].push(i);
+            adjacent_segments[segment[1] as usize].push(i);
+        }
+
+        for (i, segments) in adjacent_segments.iter().enumerate() {
+            let is_corner = match segments[..] {
+                [] => false,
+                [s1, s2] => {
+                    // Both tangents are oriented from the second segment toward the first one.
+                    let t1 = element_direction(vertices, &indices[s1], i);
+                    let t2 = -element_direction(vertices, &indices[s2], i);
+                    t1.dot(&t2) < cos_sharp_angle
+                }
+                _ => true,
+            };
+
+            if is_corner {
+                let _ = sampler.try_insert(vertices[i]);
+            }
+        }
+    }
+
+    #[cfg(feature = "dim3")]
+    {
+        let mut adjacent_triangles: HashMap<(u32, u32), Vec<usize>> = HashMap::new();
+
+        for (i, triangle) in indices.iter().enumerate() {
+            for k in 0..3 {
+                let (a, b) = (triangle[k], triangle[(k + 1) % 3]);
+                adjacent_triangles
+                    .entry((a.min(b), a.max(b)))
+                    .or_default()
+                    .push(i);
+            }
+        }
+
+        let normals: Vec<_> = indices
+            .iter()
+            .map(|t| {
+                let (a, b, c) = (
+                    vertices[t[0] as usize],
+                    vertices[t[1] as usize],
+                    vertices[t[2] as usize],
+                );
+                (b - a)
+                    .cross(&(c - a))
+                    .try_normalize(na::zero::<Real>())
+                    .unwrap_or_else(Vector::zeros)
+            })
+            .collect();
+
+        // Sort the edges so the sampling does not depend on the hash map iteration order.
+        let mut sharp_edges: Vec<_> = adjacent_triangles
+            .iter()
+            .filter(|(_, triangles)| match triangles[..] {
+                [t1, t2] => normals[t1].dot(&normals[t2]) < cos_sharp_angle,
+                _ => true,
+            })
+            .map(|(edge, _)| *edge)
+            .collect();
+        sharp_edges.sort_unstable();
+
+        let mut num_sharp_edges = vec![0; vertices.len()];
+
+        for (a, b) in &sharp_edges {
+            num_sharp_edges[*a as usize] += 1;
+            num_sharp_edges[*b as usize] += 1;
+        }
+
+        for (i, num) in num_sharp_edges.iter().enumerate() {
+            if *num != 0 && *num != 2 {
+                let _ = sampler.try_insert(vertices[i]);
+            }
+        }
+
+        let candidate_spacing = min_dist / na::convert::<_, Real>(4.0);
+
+        for (a, b) in &sharp_edges {
+            let (a, b) = (vertices[*a as usize], vertices[*b as usize]);
+            let num_samples = na::try_convert::<Real, f64>(na::distance(&a, &b) / candidate_spacing)
+                .unwrap()
+                .ceil() as usize;
+
+            for k in 0..=num_samples {
+                let t = na::convert::<_, Real>(k as f64 / num_samples.max(1) as f64);
+                let _ = sampler.try_insert(a + (b - a) * t);
+            }
+        }
+    }
+
+    // Sample the interior of the elements with random candidates processed in random order.
+    let _ = sampler.sample_surface(vertices, indices);
+    sampler.into_points()
+}
+
+#[cfg(feature = "dim2")]
+// The unit direction of the segment, pointing away from its vertex `vertex`.
+fn element_direction(
+    vertices: &[Point<Real>],
+    segment: &[u32; DIM],
+    vertex: usize,
+) -> Vector<Real> {
+    let (a, b) = (vertices[segment[0] as usize], vertices[segment[1] as usize]);
+    let dir = (b - a)
+        .try_normalize(na::zero::<Real>())
+        .unwrap_or_else(Vector::zeros);
+
+    if segment[0] as usize == vertex {
+        dir
+    } else {
+        -dir
+    }
+}
@@ -0,0 +1,221 @@
+use super::PoissonDiskSampler;
+use crate::math::{Isometry, Point, Real, Vector, DIM};
+use crate::object::interaction_groups::InteractionGroups;
+use crate::object::Fluid;
+
+use parry::bounding_volume::Aabb;
+use parry::query::{Ray, RayCast};
+#[cfg(feature = "dim2")]
+use parry::shape::Polyline;
+#[cfg(feature = "dim3")]
+use parry::shape::TriMesh;
+use rapier::geometry::Shape;
+
+/// The arrangement of the particles generated by the volume sampling methods.
+///
+/// All the arrangements have the same number of particles per unit volume, so the particles
+/// start at the rest density of the fluid.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ParticlePacking {
+    /// The particles are placed on a regular grid with a spacing of twice the particle radius.
+    Lattice,
+    /// The particles are placed on a hexagonal lattice in 2D, and on a hexagonal close packing
+    /// in 3D.
+    Hexagonal,
+    /// The particles are placed randomly, without any pair closer than about 0.75 times the
+    /// lattice spacing.
+    PoissonDisk,
+}
+
+/// Fills the interior of `shape`, placed at `position`, with particles.
+///
+/// This can be used for boxes, balls, capsules, convex hulls, or any other solid shape.
+pub fn shape_volume_sample<S: ?Sized + Shape>(
+    shape: &S,
+    position: &Isometry<Real>,
+    particle_rad: Real,
+    packing: ParticlePacking,
+) -> Vec<Point<Real>> {
+    let aabb = shape.compute_aabb(position);
+    packed_volume_sample(&aabb, particle_rad, packing, |pt| {
+        shape.contains_point(position, pt)
+    })
+}
+
+/// Fills the interior of a closed triangle mesh (a closed polyline in 2D) with particles.
+///
+/// The interior is determined by counting the intersections with the mesh of a ray cast from
+/// each candidate point, so the orientation of the triangles does not matter.
+pub fn trimesh_volume_sample(
+    vertices: &[Point<Real>],
+    indices: &[[u32; DIM]],
+    particle_rad: Real,
+    packing: ParticlePacking,
+) -> Vec<Point<Real>> {
+    #[cfg(feature = "dim2")]
+    let mesh = Polyline::new(vertices.to_vec(), Some(indices.to_vec()));
+    #[cfg(feature = "dim3")]
+    let mesh = match TriMesh::new(vertices.to_vec(), indices.to_vec()) {
+        Ok(mesh) => mesh,
+        Err(_) => return Vec::new(),
+    };
+
+    // A direction unlikely to be aligned with the edges of the mesh.
+    #[cfg(feature = "dim2")]
+    let dir = Vector::new(0.8191, 0.5736);
+    #[cfg(feature = "dim3")]
+    let dir = Vector::new(0.6, 0.64, 0.48);
+    let dir = dir.map(na::convert::<_, Real>).normalize();
+    let margin = particle_rad * na::convert::<_, Real>(1.0e-3);
+
+    packed_volume_sample(mesh.local_aabb(), particle_rad, packing, |pt| {
+        let mut ray = Ray::new(*pt, dir);
+        let mut num_crossings = 0;
+
+        while let Some(toi) = mesh.cast_local_ray(&ray, Real::MAX, false) {
+            num_crossings += 1;
+            ray.origin += dir * (toi + margin);
+        }
+
+        num_crossings % 2 == 1
+    })
+}
+
+/// Creates a fluid filling the interior of `shape`, placed at `position`.
+///
+/// See `shape_volume_sample` and `Fluid::new` for details about the parameters.
+pub fn shape_fluid<S: ?Sized + Shape>(
+    shape: &S,
+    position: &Isometry<Real>,
+    particle_rad: Real,
+    density0: Real,
+    interaction_groups: InteractionGroups,
+    packing: ParticlePacking,
+) -> Fluid {
+    let points = shape_volume_sample(shape, position, particle_rad, packing);
+    Fluid::new(points, particle_rad, density0, interaction_groups)
+}
+
+/// Creates a fluid filling the interior of a closed triangle mesh (a closed polyline in 2D).
+///
+/// See `trimesh_volume_sample` and `Fluid::new` for details about the parameters.
+pub fn trimesh_fluid(
+    vertices: &[Point<Real>],
+    indices: &[[u32; DIM]],
+    particle_rad: Real,
+    density0: Real,
+    interaction_groups: InteractionGroups,
+    packing: ParticlePacking,
+) -> Fluid {
+    let points = trimesh_volume_sample(vertices, indices, particle_rad, packing);
+    Fluid::new(points, particle_rad, density0, interaction_groups)
+}
+
+fn packed_volume_sample(
+    aabb: &Aabb,
+    particle_rad: Real,
+    packing: ParticlePacking,
+    contains: impl Fn(&Point<Real>) -> bool,
+) -> Vec<Point<Real>> {
+    let spacing = particle_rad * na::convert::<_, Real>(2.0);
+
+    match packing {
+        ParticlePacking::Lattice => lattice_points(
+            aabb,
+            particle_rad,
+            &[Vector::zeros()],
+            &Vector::repeat(spacing),
+        )
+        .filter(|pt| contains(pt))
+        .collect(),
+        ParticlePacking::Hexagonal => {
+            // Scale the lattice so it has the same number of particles per unit volume as a
+            // regular grid with the given spacing.
+            #[cfg(feature = "dim2")]
+            let (offsets, cell) = {
+                let d = spacing * na::convert::<_, Real>((2.0 / 3.0f64.sqrt()).sqrt());
+                let row = d * na::convert::<_, Real>(3.0f64.sqrt() / 2.0);
+                let half = na::convert::<_, Real>(0.5);
+                (
+                    [Vector::zeros(), Vector::new(d * half, row)],
+                    Vector::new(d, row * na::convert::<_, Real>(2.0)),
+                )
+            };
+            #[cfg(feature = "dim3")]
+            let (offsets, cell) = {
+                let d = spacing * na::convert::<_, Real>(2.0f64.powf(1.0 / 6.0));
+                let row = d * na::convert::<_, Real>(3.0f64.sqrt() / 2.0);
+                let layer = d * na::convert::<_, Real>((2.0f64 / 3.0).sqrt());
+                let half = na::convert::<_, Real>(0.5);
+                let layer_shift = d * na::convert::<_, Real>(3.0f64.sqrt() / 6.0);
+                (
+                    [
+                        Vector::zeros(),
+                        Vector::new(d * half, row, na::zero::<Real>()),
+                        Vector::new(d * half, layer_shift, layer),
+                        Vector::new(na::zero::<Real>(), row + layer_shift, layer),
+                    ],
+                    Vector::new(
+                        d,
+                        row * na::convert::<_, Real>(2.0),
+                        layer * na::convert::<_, Real>(2.0),
+                    ),
+                )
+            };
+
+            lattice_points(aabb, particle_rad, &offsets, &cell)
+                .filter(|pt| contains(pt))
+                .collect()
+        }
+        ParticlePacking::PoissonDisk => {
+            // Throw random darts until there are as many particles as with the regular lattice.
+            let num_particles = lattice_points(
+                aabb,
+                particle_rad,
+                &[Vector::zeros()],
+                &Vector::repeat(spacing),
+            )
+            .filter(|pt| contains(pt))
+            .count();
+            let mut sampler = PoissonDiskSampler::new(
+                spacing * na::convert::<_, Real>(0.75),
+                0x853c_49e6_748f_ea9b,
+            );
+            let _ = sampler.sample_volume(aabb, num_particles, num_particles * 50, contains);
+            sampler.into_points()
+        }
+    }
+}
+
+// The points `aabb.mins + particle_rad + cell * i + offset` inside of the AABB, for every
+// integer vector `i` and every offset.
+fn lattice_points<'a>(
+    aabb: &'a Aabb,
+    particle_rad: Real,
+    offsets: &'a [Vector<Real>],
+    cell: &'a Vector<Real>,
+) -> impl Iterator<Item = Point<Real>> + 'a {
+    let origin = aabb.mins + Vector::repeat(particle_rad);
+    let counts = (aabb.maxs - origin).component_div(cell).map(|e| {
+        na::try_convert::<Real, f64>(e.max(na::zero::<Real>()))
+            .unwrap()
+            .floor() as usize
+            + 1
+    });
+    let num_cells = counts.iter().product::<usize>();
+
+    (0..num_cells).flat_map(move |id| {
+        let mut corner = origin;
+        let mut rest = id;
+
+        for k in 0..DIM {
+            corner[k] += cell[k] * na::convert::<_, Real>((rest % counts[k]) as f64);
+            rest /= counts[k];
+        }
+
+        offsets
+            .iter()
+            .map(move |offset| corner + offset)
+            .filter(move |pt| pt.coords.iter().zip(aabb.maxs.iter()).all(|(p, m)| p <= m))
+    })
+}
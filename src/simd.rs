@@ -0,0 +1,156 @@
+//! SIMD evaluation of the kernels and of the accumulations over the contacts of a particle.
+//!
+//! The contacts of a particle are processed `SIMD_WIDTH` at a time. Because the lanes are summed
+//! separately, the accumulated values may differ from the scalar path by rounding errors.
+
+use crate::geometry::Contact;
+use crate::kernel::Kernel;
+use crate::math::{Point, Real, Vector, DIM};
+use approx::AbsDiffEq;
+use wide::CmpGt;
+
+/// The SIMD vector of scalars processed by the SIMD code path.
+pub type SimdReal = wide::f32x4;
+
+/// The number of lanes of `SimdReal`.
+pub const SIMD_WIDTH: usize = 4;
+
+/// Evaluates the kernel values and gradients of the `contacts` of the particle at `pi`.
+///
+/// The weight of each contact is set with `KernelDensity` and its gradient with
+/// `KernelGradient`, both evaluated at `pi - neighbor_position(contact)`.
+pub(crate) fn evaluate_kernels<KernelDensity: Kernel, KernelGradient: Kernel>(
+    pi: &Point<Real>,
+    contacts: &mut [Contact],
+    kernel_radius: Real,
+    neighbor_position: impl Fn(&Contact) -> Point<Real>,
+) {
+    let eps = SimdReal::splat(Real::default_epsilon());
+
+    for chunk in contacts.chunks_mut(SIMD_WIDTH) {
+        // The unused lanes of the last chunk are evaluated at zero and discarded.
+        let mut dpos = [[na::zero::<Real>(); SIMD_WIDTH]; DIM];
+
+        for (lane, c) in chunk.iter().enumerate() {
+            let dpos_ij = pi - neighbor_position(c);
+
+            for k in 0..DIM {
+                dpos[k][lane] = dpos_ij[k];
+            }
+        }
+
+        let dpos = dpos.map(SimdReal::new);
+        let mut norm_squared = SimdReal::ZERO;
+
+        for dpos_k in &dpos {
+            norm_squared += *dpos_k * *dpos_k;
+        }
+
+        let norm = norm_squared.sqrt();
+        let weights = KernelDensity::simd_scalar_apply(norm, kernel_radius).to_array();
+        let diff = KernelGradient::simd_scalar_apply_diff(norm, kernel_radius);
+        // The gradient is zero if the particles are at the same position.
+        let is_finite_dir = norm.cmp_gt(eps);
+        let gradients = dpos.map(|dpos_k| {
+            is_finite_dir
+                .blend(dpos_k / norm * diff, SimdReal::ZERO)
+                .to_array()
+        });
+
+        for (lane, c) in chunk.iter_mut().enumerate() {
+            c.weight = weights[lane];
+
+            for (k, gradients_k) in gradients.iter().enumerate() {
+                c.gradient[k] = gradients_k[lane];
+            }
+        }
+    }
+}
+
+/// Computes the sum of the products `a * b` of the pairs returned by `f` for each contact.
+pub(crate) fn sum_products(contacts: &[Contact], f: impl Fn(&Contact) -> (Real, Real)) -> Real {
+    let mut sum = SimdReal::ZERO;
+
+    for chunk in contacts.chunks(SIMD_WIDTH) {
+        let mut a = [na::zero::<Real>(); SIMD_WIDTH];
+        let mut b = [na::zero::<Real>(); SIMD_WIDTH];
+
+        for (lane, c) in chunk.iter().enumerate() {
+            (a[lane], b[lane]) = f(c);
+        }
+
+        sum += SimdReal::new(a) * SimdReal::new(b);
+    }
+
+    sum.reduce_add()
+}
+
+/// Computes the sum of the gradients of the contacts weighted by the scalars returned by `f`.
+pub(crate) fn weighted_gradient_sum(
+    contacts: &[Contact],
+    f: impl Fn(&Contact) -> Real,
+) -> Vector<Real> {
+    let mut sum = [SimdReal::ZERO; DIM];
+
+    for chunk in contacts.chunks(SIMD_WIDTH) {
+        let mut weights = [na::zero::<Real>(); SIMD_WIDTH];
+        let mut gradients = [[na::zero::<Real>(); SIMD_WIDTH]; DIM];
+
+        for (lane, c) in chunk.iter().enumerate() {
+            weights[lane] = f(c);
+
+            for (k, gradients_k) in gradients.iter_mut().enumerate() {
+                gradients_k[lane] = c.gradient[k];
+            }
+        }
+
+        let weights = SimdReal::new(weights);
+
+        for (sum_k, gradients_k) in sum.iter_mut().zip(gradients) {
+            *sum_k += SimdReal::new(gradients_k) * weights;
+        }
+    }
+
+    Vector::from_fn(|k, _| sum[k].reduce_add())
+}
+
+// Evaluates a scalar kernel function independently on each lane of `r`.
+pub(crate) fn lanewise(r: SimdReal, f: impl Fn(Real) -> Real) -> SimdReal {
+    SimdReal::new(r.to_array().map(f))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{SimdReal, SIMD_WIDTH};
+    use crate::kernel::{
+        CubicSplineKernel, Kernel, Poly6Kernel, QuinticSplineKernel, SpikyKernel, WendlandC2Kernel,
+    };
+    use crate::math::Real;
+
+    fn check_kernel<K: Kernel>() {
+        let h = 0.2;
+
+        for i in 0..64 {
+            let r: [Real; SIMD_WIDTH] =
+                std::array::from_fn(|lane| (i * SIMD_WIDTH + lane) as Real * h / 200.0);
+            let values = K::simd_scalar_apply(SimdReal::new(r), h).to_array();
+            let diffs = K::simd_scalar_apply_diff(SimdReal::new(r), h).to_array();
+
+            for lane in 0..SIMD_WIDTH {
+                let value = K::scalar_apply(r[lane], h);
+                let diff = K::scalar_apply_diff(r[lane], h);
+                assert!((values[lane] - value).abs() <= value.abs() * 1.0e-5 + 1.0e-5);
+                assert!((diffs[lane] - diff).abs() <= diff.abs() * 1.0e-5 + 1.0e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn simd_kernels_match_scalar_kernels() {
+        check_kernel::<CubicSplineKernel>();
+        check_kernel::<Poly6Kernel>();
+        check_kernel::<QuinticSplineKernel>();
+        check_kernel::<SpikyKernel>();
+        check_kernel::<WendlandC2Kernel>();
+    }
+}
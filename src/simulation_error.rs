@@ -0,0 +1,60 @@
+use std::fmt::{Display, Formatter, Result};
+
+use crate::counters::{SolverStatistics, StepStatistics};
+use crate::math::Real;
+use crate::object::ParticleId;
+
+/// An error detected by `LiquidWorld::try_step`.
+#[derive(Clone, PartialEq, Debug)]
+pub enum SimulationError {
+    /// The world or the step parameters are invalid, so the step was not performed.
+    ///
+    /// The string describes the invalid parameter.
+    InvalidConfiguration(String),
+    /// A fluid particle has a non-finite position or velocity at the end of the step.
+    ///
+    /// Only the first such particle is reported.
+    NonFiniteParticle(ParticleId),
+    /// The pressure resolution reached its maximum number of iterations without meeting its error
+    /// tolerance during at least one substep.
+    NonConvergence {
+        /// The number of substeps during which the pressure resolution did not converge.
+        num_unconverged_substeps: usize,
+        /// The average density error after the pressure resolution of the last substep.
+        density_error: Real,
+    },
+}
+
+impl Display for SimulationError {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        match self {
+            SimulationError::InvalidConfiguration(desc) => {
+                write!(f, "invalid simulation configuration: {}", desc)
+            }
+            SimulationError::NonFiniteParticle(particle) => {
+                write!(f, "non-finite particle state: {:?}", particle)
+            }
+            SimulationError::NonConvergence {
+                num_unconverged_substeps,
+                density_error,
+            } => write!(
+                f,
+                "the pressure resolution did not converge during {} substep(s), density error: {}",
+                num_unconverged_substeps, density_error
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SimulationError {}
+
+/// A summary of a step performed successfully by `LiquidWorld::try_step`.
+#[derive(Clone, Copy, Debug)]
+pub struct StepReport {
+    /// The number of substeps performed.
+    pub num_substeps: usize,
+    /// Statistics about the work performed by the solver.
+    pub solver: SolverStatistics,
+    /// Statistics about the motion of the fluid particles.
+    pub stats: StepStatistics,
+}
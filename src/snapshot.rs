@@ -0,0 +1,515 @@
+use std::fmt::{Display, Formatter};
+
+use bytemuck::Pod;
+
+use crate::math::{Point, Real, Vector, DIM};
+use crate::object::{Boundary, DiffuseParticleKind, DiffuseParticles, Fluid};
+
+const MAGIC: &[u8; 4] = b"SLVS";
+const VERSION: u32 = 2;
+const HEADER_LEN: usize = 12;
+const FLAG_COMPRESSED: u8 = 1;
+const FLAG_BIG_ENDIAN: u8 = 2;
+
+/// An error detected by `LiquidWorld::restore`.
+#[derive(Clone, PartialEq, Debug)]
+pub enum SnapshotError {
+    /// The data is not a snapshot, or is truncated.
+    InvalidFormat,
+    /// The snapshot was written by an incompatible version of the snapshot format.
+    UnsupportedVersion(u32),
+    /// The snapshot is compressed but the `compression` feature is not enabled.
+    CompressionUnsupported,
+    /// The snapshot was not taken from a liquid world with the same layout as the one it is
+    /// restored into.
+    ///
+    /// The string describes the mismatch.
+    IncompatibleWorld(String),
+}
+
+impl Display for SnapshotError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            SnapshotError::InvalidFormat => write!(f, "invalid or truncated snapshot"),
+            SnapshotError::UnsupportedVersion(version) => {
+                write!(f, "unsupported snapshot version: {}", version)
+            }
+            SnapshotError::CompressionUnsupported => write!(
+                f,
+                "the snapshot is compressed but the `compression` feature is disabled"
+            ),
+            SnapshotError::IncompatibleWorld(desc) => {
+                write!(f, "incompatible snapshot: {}", desc)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+/// The state of the particles of a fluid, as stored in a snapshot.
+#[derive(Default)]
+struct FluidState {
+    positions: Vec<Point<Real>>,
+    velocities: Vec<Vector<Real>>,
+    accelerations: Vec<Vector<Real>>,
+    volumes: Vec<Real>,
+    deleted_particles: Vec<u8>,
+    render_velocities: Vec<Vector<Real>>,
+    previous_positions: Vec<Point<Real>>,
+    surface_particles: Vec<u8>,
+    temperatures: Vec<Real>,
+    concentrations: Vec<Real>,
+    lifetimes: Vec<Real>,
+    user_data: Vec<u128>,
+    rest_densities: Vec<Real>,
+}
+
+/// The state of the particles of a boundary, as stored in a snapshot.
+#[derive(Default)]
+struct BoundaryState {
+    positions: Vec<Point<Real>>,
+    velocities: Vec<Vector<Real>>,
+    volumes: Vec<Real>,
+}
+
+struct Writer {
+    data: Vec<u8>,
+}
+
+impl Writer {
+    fn write_len(&mut self, len: usize) {
+        self.data.extend_from_slice(&(len as u64).to_le_bytes());
+    }
+
+    fn write_real(&mut self, value: Real) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    // Buffers are copied as-is, in the native endianness, to keep the snapshots fast.
+    fn write_slice<T: Pod>(&mut self, values: &[T]) {
+        self.write_len(values.len());
+        self.data.extend_from_slice(bytemuck::cast_slice(values));
+    }
+
+    fn write_mask(&mut self, values: &[bool]) {
+        self.write_len(values.len());
+        self.data.extend(values.iter().map(|b| *b as u8));
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl Reader<'_> {
+    fn read_bytes(&mut self, len: usize) -> Result<&[u8], SnapshotError> {
+        if self.data.len() < len {
+            return Err(SnapshotError::InvalidFormat);
+        }
+
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    fn read_len(&mut self) -> Result<usize, SnapshotError> {
+        let bytes = self.read_bytes(8)?;
+        Ok(u64::from_le_bytes(bytes.try_into().unwrap()) as usize)
+    }
+
+    fn read_real(&mut self) -> Result<Real, SnapshotError> {
+        let bytes = self.read_bytes(size_of::<Real>())?;
+        Ok(Real::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    fn read_slice<T: Pod>(&mut self, out: &mut Vec<T>) -> Result<(), SnapshotError> {
+        let len = self.read_len()?;
+        let num_bytes = len
+            .checked_mul(size_of::<T>())
+            .ok_or(SnapshotError::InvalidFormat)?;
+        let bytes = self.read_bytes(num_bytes)?;
+        out.clear();
+        out.resize(len, T::zeroed());
+        bytemuck::cast_slice_mut(out).copy_from_slice(bytes);
+        Ok(())
+    }
+}
+
+/// Writes a snapshot of the given simulation state.
+pub(crate) fn write(
+    time: Real,
+    fluids: &[Fluid],
+    boundaries: &[Boundary],
+    diffuse_particles: &DiffuseParticles,
+    compression_level: Option<u8>,
+) -> Vec<u8> {
+    let mut writer = Writer { data: Vec::new() };
+    writer.write_real(time);
+    writer.write_len(fluids.len());
+
+    for fluid in fluids {
+        writer.write_slice(&fluid.positions);
+        writer.write_slice(&fluid.velocities);
+        writer.write_slice(&fluid.accelerations);
+        writer.write_slice(&fluid.volumes);
+        writer.write_mask(fluid.deleted_particles_mask());
+        writer.write_slice(&fluid.render_velocities);
+        writer.write_slice(&fluid.previous_positions);
+        writer.write_mask(&fluid.surface_particles);
+        writer.write_slice(&fluid.temperatures);
+        writer.write_slice(&fluid.concentrations);
+        writer.write_slice(&fluid.lifetimes);
+        writer.write_slice(&fluid.user_data);
+        writer.write_slice(&fluid.rest_densities);
+    }
+
+    writer.write_len(boundaries.len());
+
+    for boundary in boundaries {
+        writer.write_slice(&boundary.positions);
+        writer.write_slice(&boundary.velocities);
+        writer.write_slice(&boundary.volumes);
+    }
+
+    writer.write_slice(&diffuse_particles.positions);
+    writer.write_slice(&diffuse_particles.velocities);
+    writer.write_slice(&diffuse_particles.lifetimes);
+    writer.write_len(diffuse_particles.kinds.len());
+    writer
+        .data
+        .extend(diffuse_particles.kinds.iter().map(|kind| *kind as u8));
+
+    let mut flags = 0;
+
+    if cfg!(target_endian = "big") {
+        flags |= FLAG_BIG_ENDIAN;
+    }
+
+    let payload = match compression_level {
+        #[cfg(feature = "compression")]
+        Some(level) => {
+            flags |= FLAG_COMPRESSED;
+            miniz_oxide::deflate::compress_to_vec(&writer.data, level)
+        }
+        _ => writer.data,
+    };
+
+    let mut result = Vec::with_capacity(HEADER_LEN + payload.len());
+    result.extend_from_slice(MAGIC);
+    result.extend_from_slice(&VERSION.to_le_bytes());
+    result.extend_from_slice(&[DIM as u8, flags, 0, 0]);
+    result.extend_from_slice(&payload);
+    result
+}
+
+/// Restores the simulation state from a snapshot written by `write`.
+///
+/// Nothing is modified if an error is returned.
+pub(crate) fn read(
+    data: &[u8],
+    time: &mut Real,
+    fluids: &mut [Fluid],
+    boundaries: &mut [Boundary],
+    diffuse_particles: &mut DiffuseParticles,
+) -> Result<(), SnapshotError> {
+    if data.len() < HEADER_LEN || &data[..4] != MAGIC {
+        return Err(SnapshotError::InvalidFormat);
+    }
+
+    let version = u32::from_le_bytes(data[4..8].try_into().unwrap());
+
+    if version != VERSION {
+        return Err(SnapshotError::UnsupportedVersion(version));
+    }
+
+    let dim = data[8] as usize;
+    let flags = data[9];
+
+    if dim != DIM {
+        return Err(SnapshotError::IncompatibleWorld(format!(
+            "the snapshot was taken in dimension {}",
+            dim
+        )));
+    }
+
+    if (flags & FLAG_BIG_ENDIAN != 0) != cfg!(target_endian = "big") {
+        return Err(SnapshotError::IncompatibleWorld(
+            "the snapshot was taken on a platform with a different endianness".to_string(),
+        ));
+    }
+
+    #[cfg(feature = "compression")]
+    let decompressed;
+    let payload = if flags & FLAG_COMPRESSED != 0 {
+        #[cfg(feature = "compression")]
+        {
+            decompressed = miniz_oxide::inflate::decompress_to_vec(&data[HEADER_LEN..])
+                .map_err(|_| SnapshotError::InvalidFormat)?;
+            &decompressed[..]
+        }
+        #[cfg(not(feature = "compression"))]
+        {
+            return Err(SnapshotError::CompressionUnsupported);
+        }
+    } else {
+        &data[HEADER_LEN..]
+    };
+
+    let mut reader = Reader { data: payload };
+    let new_time = reader.read_real()?;
+    let num_fluids = reader.read_len()?;
+
+    if num_fluids != fluids.len() {
+        return Err(SnapshotError::IncompatibleWorld(format!(
+            "the snapshot has {} fluids instead of {}",
+            num_fluids,
+            fluids.len()
+        )));
+    }
+
+    let mut fluid_states = Vec::with_capacity(num_fluids);
+
+    for _ in 0..num_fluids {
+        let mut state = FluidState::default();
+        reader.read_slice(&mut state.positions)?;
+        reader.read_slice(&mut state.velocities)?;
+        reader.read_slice(&mut state.accelerations)?;
+        reader.read_slice(&mut state.volumes)?;
+        reader.read_slice(&mut state.deleted_particles)?;
+        reader.read_slice(&mut state.render_velocities)?;
+        reader.read_slice(&mut state.previous_positions)?;
+        reader.read_slice(&mut state.surface_particles)?;
+        reader.read_slice(&mut state.temperatures)?;
+        reader.read_slice(&mut state.concentrations)?;
+        reader.read_slice(&mut state.lifetimes)?;
+        reader.read_slice(&mut state.user_data)?;
+        reader.read_slice(&mut state.rest_densities)?;
+
+        let num_particles = state.positions.len();
+        // The optional buffers are either empty or contain one element per particle.
+        let optional_lens = [
+            state.render_velocities.len(),
+            state.previous_positions.len(),
+            state.surface_particles.len(),
+            state.temperatures.len(),
+            state.concentrations.len(),
+            state.lifetimes.len(),
+            state.user_data.len(),
+            state.rest_densities.len(),
+        ];
+
+        if state.velocities.len() != num_particles
+            || state.accelerations.len() != num_particles
+            || state.volumes.len() != num_particles
+            || state.deleted_particles.len() != num_particles
+            || optional_lens
+                .iter()
+                .any(|len| *len != 0 && *len != num_particles)
+        {
+            return Err(SnapshotError::InvalidFormat);
+        }
+
+        fluid_states.push(state);
+    }
+
+    let num_boundaries = reader.read_len()?;
+
+    if num_boundaries != boundaries.len() {
+        return Err(SnapshotError::IncompatibleWorld(format!(
+            "the snapshot has {} boundaries instead of {}",
+            num_boundaries,
+            boundaries.len()
+        )));
+    }
+
+    let mut boundary_states = Vec::with_capacity(num_boundaries);
+
+    for _ in 0..num_boundaries {
+        let mut state = BoundaryState::default();
+        reader.read_slice(&mut state.positions)?;
+        reader.read_slice(&mut state.velocities)?;
+        reader.read_slice(&mut state.volumes)?;
+
+        if state.velocities.len() != state.positions.len()
+            || state.volumes.len() != state.positions.len()
+        {
+            return Err(SnapshotError::InvalidFormat);
+        }
+
+        boundary_states.push(state);
+    }
+
+    let mut diffuse = DiffuseParticles::new();
+    let mut kinds: Vec<u8> = Vec::new();
+    reader.read_slice(&mut diffuse.positions)?;
+    reader.read_slice(&mut diffuse.velocities)?;
+    reader.read_slice(&mut diffuse.lifetimes)?;
+    reader.read_slice(&mut kinds)?;
+
+    let num_diffuse_particles = diffuse.positions.len();
+
+    if diffuse.velocities.len() != num_diffuse_particles
+        || diffuse.lifetimes.len() != num_diffuse_particles
+        || kinds.len() != num_diffuse_particles
+    {
+        return Err(SnapshotError::InvalidFormat);
+    }
+
+    for kind in kinds {
+        diffuse.kinds.push(match kind {
+            0 => DiffuseParticleKind::Spray,
+            1 => DiffuseParticleKind::Foam,
+            2 => DiffuseParticleKind::Bubble,
+            _ => return Err(SnapshotError::InvalidFormat),
+        });
+    }
+
+    // Everything was decoded successfully, so the world can be modified now.
+    *time = new_time;
+
+    for (fluid, state) in fluids.iter_mut().zip(fluid_states) {
+        fluid.positions = state.positions;
+        fluid.velocities = state.velocities;
+        fluid.accelerations = state.accelerations;
+        fluid.volumes = state.volumes;
+        fluid.set_deleted_particles_mask(state.deleted_particles.iter().map(|b| *b != 0).collect());
+        fluid.render_velocities = state.render_velocities;
+        fluid.previous_positions = state.previous_positions;
+        fluid.surface_particles = state.surface_particles.iter().map(|b| *b != 0).collect();
+        fluid.temperatures = state.temperatures;
+        fluid.concentrations = state.concentrations;
+        fluid.lifetimes = state.lifetimes;
+        fluid.user_data = state.user_data;
+        fluid.rest_densities = state.rest_densities;
+        // Recomputed at the beginning of the next substep.
+        fluid.active_particles.clear();
+    }
+
+    for (boundary, state) in boundaries.iter_mut().zip(boundary_states) {
+        boundary.positions = state.positions;
+        boundary.velocities = state.velocities;
+        boundary.volumes = state.volumes;
+    }
+
+    *diffuse_particles = diffuse;
+
+    Ok(())
+}
+
+#[test]
+fn snapshot_roundtrip() {
+    use crate::object::interaction_groups::InteractionGroups;
+
+    let positions = (0..10)
+        .map(|i| Point::from(Vector::repeat(na::convert::<_, Real>(i as f64))))
+        .collect();
+    let mut fluids = vec![Fluid::new(
+        positions,
+        na::convert::<_, Real>(0.05),
+        na::convert::<_, Real>(1000.0),
+        InteractionGroups::default(),
+    )];
+    fluids[0].velocities[3] = Vector::repeat(na::one::<Real>());
+    fluids[0].rest_densities = vec![na::convert::<_, Real>(1000.0); 10];
+    fluids[0].rest_densities[4] = na::convert::<_, Real>(1200.0);
+    fluids[0].delete_particle_at_next_timestep(5);
+    let mut diffuse_particles = DiffuseParticles::new();
+    let data = write(na::one::<Real>(), &fluids, &[], &diffuse_particles, None);
+
+    let mut time = na::zero::<Real>();
+    let expected_positions = fluids[0].positions.clone();
+    let expected_rest_densities = fluids[0].rest_densities.clone();
+    fluids[0].positions.truncate(2);
+    fluids[0].velocities[3] = Vector::zeros();
+    fluids[0].rest_densities.clear();
+
+    assert_eq!(
+        read(
+            &data[..data.len() - 1],
+            &mut time,
+            &mut fluids,
+            &mut [],
+            &mut diffuse_particles
+        ),
+        Err(SnapshotError::InvalidFormat)
+    );
+    assert_eq!(time, na::zero::<Real>());
+
+    read(
+        &data,
+        &mut time,
+        &mut fluids,
+        &mut [],
+        &mut diffuse_particles,
+    )
+    .unwrap();
+    assert_eq!(time, na::one::<Real>());
+    assert_eq!(fluids[0].positions, expected_positions);
+    assert_eq!(fluids[0].velocities[3], Vector::repeat(na::one::<Real>()));
+    assert_eq!(fluids[0].rest_densities, expected_rest_densities);
+    assert_eq!(fluids[0].num_deleted_particles(), 1);
+}
+
+#[test]
+fn snapshot_rejects_inconsistent_lengths() {
+    use crate::object::interaction_groups::InteractionGroups;
+
+    let new_fluid = || {
+        let positions = (0..10)
+            .map(|i| Point::from(Vector::repeat(na::convert::<_, Real>(i as f64))))
+            .collect();
+        Fluid::new(
+            positions,
+            na::convert::<_, Real>(0.05),
+            na::convert::<_, Real>(1000.0),
+            InteractionGroups::default(),
+        )
+    };
+    let new_boundary = || Boundary::new(vec![Point::origin(); 4], InteractionGroups::default());
+    // Writes a snapshot of the given state and restores it into a valid world.
+    let restore = |fluid: Fluid, boundary: Boundary, diffuse_particles: DiffuseParticles| {
+        let data = write(
+            na::zero::<Real>(),
+            &[fluid],
+            &[boundary],
+            &diffuse_particles,
+            None,
+        );
+        read(
+            &data,
+            &mut na::zero::<Real>(),
+            &mut [new_fluid()],
+            &mut [new_boundary()],
+            &mut DiffuseParticles::new(),
+        )
+    };
+
+    assert_eq!(
+        restore(new_fluid(), new_boundary(), DiffuseParticles::new()),
+        Ok(())
+    );
+
+    let mut invalid_fluid = new_fluid();
+    invalid_fluid.temperatures = vec![na::zero::<Real>(); 3];
+    assert_eq!(
+        restore(invalid_fluid, new_boundary(), DiffuseParticles::new()),
+        Err(SnapshotError::InvalidFormat)
+    );
+
+    let mut invalid_boundary = new_boundary();
+    invalid_boundary.volumes.push(na::zero::<Real>());
+    assert_eq!(
+        restore(new_fluid(), invalid_boundary, DiffuseParticles::new()),
+        Err(SnapshotError::InvalidFormat)
+    );
+
+    let mut invalid_diffuse_particles = DiffuseParticles::new();
+    invalid_diffuse_particles.positions.push(Point::origin());
+    invalid_diffuse_particles.velocities.push(Vector::zeros());
+    invalid_diffuse_particles.lifetimes.push(na::one::<Real>());
+    assert_eq!(
+        restore(new_fluid(), new_boundary(), invalid_diffuse_particles),
+        Err(SnapshotError::InvalidFormat)
+    );
+}
@@ -0,0 +1,31 @@
+use crate::geometry::ParticlesContacts;
+use crate::math::Real;
+use crate::object::{Boundary, Fluid};
+#[cfg(feature = "serde-serialize")]
+use crate::solver::SummationDensity;
+
+/// Trait implemented by methods for estimating the density of fluid particles.
+///
+/// The density estimator is shared by all the pressure solvers so that any improvement
+/// of the density estimation benefits all of them.
+pub trait DensityEstimator: Send + Sync {
+    /// Computes the density of every particle of every fluid.
+    ///
+    /// The kernels of all the contacts must have been evaluated before calling this method.
+    /// The boundary volumes must have been computed too. The result is written into
+    /// `densities[fluid_id][particle_id]`.
+    fn compute_densities(
+        &mut self,
+        fluid_fluid_contacts: &[ParticlesContacts],
+        fluid_boundary_contacts: &[ParticlesContacts],
+        fluids: &[Fluid],
+        boundaries: &[Boundary],
+        densities: &mut [Vec<Real>],
+    );
+}
+
+// The density estimator given to the deserialized pressure solvers.
+#[cfg(feature = "serde-serialize")]
+pub(crate) fn default_density_estimator() -> Box<dyn DensityEstimator> {
+    Box::new(SummationDensity::new())
+}
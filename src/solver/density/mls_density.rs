@@ -0,0 +1,147 @@
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use crate::geometry::ParticlesContacts;
+use crate::math::{Real, Vector, DIM};
+use crate::object::{Boundary, Fluid};
+use crate::solver::{helper, DensityEstimator, SummationDensity};
+
+#[cfg(feature = "dim2")]
+type MLSVector = na::Vector3<Real>;
+#[cfg(feature = "dim3")]
+type MLSVector = na::Vector4<Real>;
+#[cfg(feature = "dim2")]
+type MLSMatrix = na::Matrix3<Real>;
+#[cfg(feature = "dim3")]
+type MLSMatrix = na::Matrix4<Real>;
+
+fn mls_basis(dpos: &Vector<Real>) -> MLSVector {
+    let mut basis = MLSVector::zeros();
+    basis[0] = na::one::<Real>();
+    basis.fixed_rows_mut::<DIM>(1).copy_from(dpos);
+    basis
+}
+
+/// Density estimation with a first-order Moving Least Squares (MLS) kernel correction.
+///
+/// The kernel is corrected so that linear density fields are reproduced exactly. The fluid
+/// volumes required by the correction are deduced from the uncorrected summed densities. If the
+/// correction matrix of a particle is singular (e.g. because it has too few
+/// neighbors), the standard summation is used for this particle.
+#[derive(Clone, Debug, Default)]
+pub struct MLSDensity {
+    summed_densities: Vec<Vec<Real>>,
+}
+
+impl MLSDensity {
+    /// Initializes the MLS-corrected density estimator.
+    pub fn new() -> Self {
+        Self {
+            summed_densities: Vec::new(),
+        }
+    }
+}
+
+impl DensityEstimator for MLSDensity {
+    fn compute_densities(
+        &mut self,
+        fluid_fluid_contacts: &[ParticlesContacts],
+        fluid_boundary_contacts: &[ParticlesContacts],
+        fluids: &[Fluid],
+        boundaries: &[Boundary],
+        densities: &mut [Vec<Real>],
+    ) {
+        self.summed_densities.resize(densities.len(), Vec::new());
+        for (summed, densities) in self.summed_densities.iter_mut().zip(densities.iter()) {
+            summed.resize(densities.len(), na::zero::<Real>());
+        }
+
+        SummationDensity.compute_densities(
+            fluid_fluid_contacts,
+            fluid_boundary_contacts,
+            fluids,
+            boundaries,
+            &mut self.summed_densities,
+        );
+        let summed_densities = &self.summed_densities;
+
+        for fluid_id in 0..fluids.len() {
+            let fluid_i = &fluids[fluid_id];
+
+            par_iter_mut!(densities[fluid_id])
+                .enumerate()
+                .for_each(|(i, density)| {
+                    let pi = fluid_i.positions[i];
+                    let density0 = fluid_i.rest_density(i);
+                    let mut moment_matrix = MLSMatrix::zeros();
+                    *density = summed_densities[fluid_id][i];
+
+                    // First pass: assemble the moment matrix.
+                    for c in fluid_fluid_contacts[fluid_id]
+                        .particle_contacts(i)
+                        .read()
+                        .unwrap()
+                        .iter()
+                    {
+                        let fluid_j = &fluids[c.j_model];
+                        let mj = fluid_j.particle_mass(c.j);
+                        let rhoj = summed_densities[c.j_model][c.j];
+                        let basis = mls_basis(&(pi - fluid_j.positions[c.j]));
+                        moment_matrix += basis * basis.transpose() * (c.weight * mj / rhoj);
+                    }
+
+                    for c in fluid_boundary_contacts[fluid_id]
+                        .particle_contacts(i)
+                        .read()
+                        .unwrap()
+                        .iter()
+                    {
+                        let boundary = &boundaries[c.j_model];
+                        let volume = boundary.volumes[c.j];
+                        let basis = mls_basis(&(pi - boundary.positions[c.j]));
+                        moment_matrix += basis * basis.transpose() * (c.weight * volume);
+                    }
+
+                    let beta = if let Some(inv) = moment_matrix.try_inverse() {
+                        inv.column(0).into_owned()
+                    } else {
+                        return;
+                    };
+
+                    // Second pass: sum the corrected kernel contributions.
+                    let mut corrected_density = na::zero::<Real>();
+
+                    for c in fluid_fluid_contacts[fluid_id]
+                        .particle_contacts(i)
+                        .read()
+                        .unwrap()
+                        .iter()
+                    {
+                        let fluid_j = &fluids[c.j_model];
+                        let basis = mls_basis(&(pi - fluid_j.positions[c.j]));
+                        corrected_density += helper::adapted_mass(fluid_i, c.i, fluid_j, c.j)
+                            * c.weight
+                            * beta.dot(&basis);
+                    }
+
+                    for c in fluid_boundary_contacts[fluid_id]
+                        .particle_contacts(i)
+                        .read()
+                        .unwrap()
+                        .iter()
+                    {
+                        let boundary = &boundaries[c.j_model];
+                        let basis = mls_basis(&(pi - boundary.positions[c.j]));
+                        corrected_density +=
+                            boundary.volumes[c.j] * density0 * c.weight * beta.dot(&basis);
+                    }
+
+                    // The corrected kernel may take negative values, so keep the uncorrected
+                    // density if the correction yields a nonsensical result.
+                    if corrected_density > na::zero::<Real>() {
+                        *density = corrected_density;
+                    }
+                })
+        }
+    }
+}
@@ -0,0 +1,12 @@
+#[cfg(feature = "serde-serialize")]
+pub(crate) use self::density_estimator::default_density_estimator;
+pub use self::density_estimator::DensityEstimator;
+pub use self::mls_density::MLSDensity;
+pub use self::shepard_density::ShepardDensity;
+pub(crate) use self::shepard_density::ShepardFilter;
+pub use self::summation_density::SummationDensity;
+
+mod density_estimator;
+mod mls_density;
+mod shepard_density;
+mod summation_density;
@@ -0,0 +1,154 @@
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use num::Zero;
+
+use crate::geometry::{ContactManager, ParticlesContacts};
+use crate::math::Real;
+use crate::object::{Boundary, Fluid};
+use crate::solver::{DensityEstimator, SummationDensity};
+
+/// Density estimation with a Shepard (zeroth-order) kernel correction.
+///
+/// The summed density is divided by the sum of the kernel weights multiplied by the neighbor
+/// volumes, where the fluid volumes are deduced from the uncorrected summed densities. This
+/// removes most of the density underestimation near free surfaces.
+#[derive(Clone, Debug, Default)]
+pub struct ShepardDensity {
+    summed_densities: Vec<Vec<Real>>,
+}
+
+impl ShepardDensity {
+    /// Initializes the Shepard-corrected density estimator.
+    pub fn new() -> Self {
+        Self {
+            summed_densities: Vec::new(),
+        }
+    }
+}
+
+impl DensityEstimator for ShepardDensity {
+    fn compute_densities(
+        &mut self,
+        fluid_fluid_contacts: &[ParticlesContacts],
+        fluid_boundary_contacts: &[ParticlesContacts],
+        fluids: &[Fluid],
+        boundaries: &[Boundary],
+        densities: &mut [Vec<Real>],
+    ) {
+        self.summed_densities.resize(densities.len(), Vec::new());
+        for (summed, densities) in self.summed_densities.iter_mut().zip(densities.iter()) {
+            summed.resize(densities.len(), na::zero::<Real>());
+        }
+
+        SummationDensity.compute_densities(
+            fluid_fluid_contacts,
+            fluid_boundary_contacts,
+            fluids,
+            boundaries,
+            &mut self.summed_densities,
+        );
+
+        for (fluid_id, densities) in densities.iter_mut().enumerate() {
+            normalize_densities(
+                fluid_id,
+                fluid_fluid_contacts,
+                fluid_boundary_contacts,
+                fluids,
+                boundaries,
+                &self.summed_densities,
+                densities,
+            );
+        }
+    }
+}
+
+/// Applies a Shepard correction to the densities of the fluids with
+/// `Fluid::shepard_density_correction` enabled, whatever the density estimator used.
+///
+/// The densities are divided by the sum of the kernel weights multiplied by the neighbor volumes,
+/// the fluid volumes being deduced from the densities before correction. The corrected densities
+/// are written into a separate buffer so that the result does not depend on the order of the
+/// fluids.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ShepardFilter {
+    filtered_densities: Vec<Vec<Real>>,
+}
+
+impl ShepardFilter {
+    pub fn apply(
+        &mut self,
+        contact_manager: &ContactManager,
+        fluids: &[Fluid],
+        boundaries: &[Boundary],
+        densities: &mut Vec<Vec<Real>>,
+    ) {
+        if fluids.iter().all(|fluid| !fluid.shepard_density_correction) {
+            return;
+        }
+
+        self.filtered_densities.resize(densities.len(), Vec::new());
+
+        for (fluid_id, filtered) in self.filtered_densities.iter_mut().enumerate() {
+            filtered.resize(densities[fluid_id].len(), na::zero::<Real>());
+
+            if fluids[fluid_id].shepard_density_correction {
+                normalize_densities(
+                    fluid_id,
+                    &contact_manager.fluid_fluid_contacts,
+                    &contact_manager.fluid_boundary_contacts,
+                    fluids,
+                    boundaries,
+                    densities,
+                    filtered,
+                );
+            } else {
+                filtered.copy_from_slice(&densities[fluid_id]);
+            }
+        }
+
+        std::mem::swap(densities, &mut self.filtered_densities);
+    }
+}
+
+// Writes into `out` the densities of the particles of the `fluid_id`-th fluid divided by the sum
+// of the kernel weights multiplied by their neighbor volumes. The fluid volumes are deduced from
+// `densities`.
+fn normalize_densities(
+    fluid_id: usize,
+    fluid_fluid_contacts: &[ParticlesContacts],
+    fluid_boundary_contacts: &[ParticlesContacts],
+    fluids: &[Fluid],
+    boundaries: &[Boundary],
+    densities: &[Vec<Real>],
+    out: &mut [Real],
+) {
+    par_iter_mut!(out).enumerate().for_each(|(i, density)| {
+        let mut normalization = na::zero::<Real>();
+
+        for c in fluid_fluid_contacts[fluid_id]
+            .particle_contacts(i)
+            .read()
+            .unwrap()
+            .iter()
+        {
+            let mj = fluids[c.j_model].particle_mass(c.j);
+            normalization += mj / densities[c.j_model][c.j] * c.weight;
+        }
+
+        for c in fluid_boundary_contacts[fluid_id]
+            .particle_contacts(i)
+            .read()
+            .unwrap()
+            .iter()
+        {
+            normalization += boundaries[c.j_model].volumes[c.j] * c.weight;
+        }
+
+        *density = densities[fluid_id][i];
+
+        if !normalization.is_zero() {
+            *density /= normalization;
+        }
+    })
+}
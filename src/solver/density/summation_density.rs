@@ -0,0 +1,91 @@
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use num::Zero;
+
+use crate::geometry::ParticlesContacts;
+use crate::math::Real;
+use crate::object::{Boundary, Fluid};
+#[cfg(feature = "simd")]
+use crate::simd;
+use crate::solver::{helper, DensityEstimator};
+
+/// The standard SPH density estimation by summation of the kernel weights of the neighbors.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SummationDensity;
+
+impl SummationDensity {
+    /// Initializes the standard SPH density estimator.
+    pub fn new() -> Self {
+        SummationDensity
+    }
+}
+
+impl DensityEstimator for SummationDensity {
+    fn compute_densities(
+        &mut self,
+        fluid_fluid_contacts: &[ParticlesContacts],
+        fluid_boundary_contacts: &[ParticlesContacts],
+        fluids: &[Fluid],
+        boundaries: &[Boundary],
+        densities: &mut [Vec<Real>],
+    ) {
+        for fluid_id in 0..fluids.len() {
+            par_iter_mut!(densities[fluid_id])
+                .enumerate()
+                .for_each(|(i, density)| {
+                    let fluid_contacts = fluid_fluid_contacts[fluid_id]
+                        .particle_contacts(i)
+                        .read()
+                        .unwrap();
+                    let boundary_contacts = fluid_boundary_contacts[fluid_id]
+                        .particle_contacts(i)
+                        .read()
+                        .unwrap();
+
+                    #[cfg(feature = "simd")]
+                    {
+                        *density = simd::sum_products(&fluid_contacts, |c| {
+                            let mj = helper::adapted_mass(
+                                &fluids[c.i_model],
+                                c.i,
+                                &fluids[c.j_model],
+                                c.j,
+                            );
+                            (mj, c.weight)
+                        }) + simd::sum_products(&boundary_contacts, |c| {
+                            let mj = boundaries[c.j_model].volumes[c.j]
+                                * fluids[c.i_model].rest_density(c.i);
+                            (mj, c.weight)
+                        });
+                    }
+
+                    #[cfg(not(feature = "simd"))]
+                    {
+                        *density = na::zero::<Real>();
+
+                        for c in fluid_contacts.iter() {
+                            *density += helper::adapted_mass(
+                                &fluids[c.i_model],
+                                c.i,
+                                &fluids[c.j_model],
+                                c.j,
+                            ) * c.weight;
+                        }
+
+                        for c in boundary_contacts.iter() {
+                            *density += boundaries[c.j_model].volumes[c.j]
+                                * fluids[c.i_model].rest_density(c.i)
+                                * c.weight;
+                        }
+                    }
+
+                    // A particle without any neighbor (not even itself, e.g., because of a
+                    // non-finite position) is considered at rest.
+                    if density.is_zero() {
+                        *density = fluids[fluid_id].rest_density(i);
+                    }
+                })
+        }
+    }
+}
@@ -0,0 +1,394 @@
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use crate::geometry::{ContactManager, HGridEntry, NeighborSearch};
+use crate::math::{Real, Vector, DIM};
+use crate::object::{Boundary, DiffuseParticleKind, DiffuseParticles, Fluid};
+use crate::random::Rng;
+use crate::TimestepManager;
+
+/// The state of the simulation a `DiffuseParticleSolver` reads from.
+///
+/// The contacts of `contact_manager` must have been computed, and the particles inserted into
+/// `neighbor_search`, during the current substep.
+#[derive(Copy, Clone)]
+pub struct DiffuseParticleContext<'a> {
+    /// The kernel radius of the fluids.
+    pub kernel_radius: Real,
+    /// The radius of the fluid particles.
+    pub particle_radius: Real,
+    /// The gravity applied to the spray particles and, in the opposite direction, to bubbles.
+    pub gravity: &'a Vector<Real>,
+    /// The contacts between the fluid particles.
+    pub contact_manager: &'a ContactManager,
+    /// The neighbor search structure containing the fluid and boundary particles.
+    pub neighbor_search: &'a dyn NeighborSearch,
+    /// The fluids generating the diffuse particles.
+    pub fluids: &'a [Fluid],
+    /// The boundaries deleting the diffuse particles touching them.
+    pub boundaries: &'a [Boundary],
+}
+
+/// A solver generating and advecting diffuse particles (spray, foam, and bubbles).
+///
+/// This implements the method from "Unified Spray, Foam and Bubbles for Particle-Based Fluids",
+/// Ihmsen et al. 2012. Diffuse particles are emitted by the fluid particles likely to trap air or
+/// located at wave crests, proportionally to their kinetic energy. They are then classified by
+/// the number of fluid particles around them:
+///
+/// - spray particles, with less than `spray_max_neighbors` neighbors, only follow gravity;
+/// - bubbles, with more than `bubble_min_neighbors` neighbors, rise by buoyancy and are dragged
+///   by the fluid;
+/// - the others are foam particles, advected by the fluid.
+///
+/// Diffuse particles are deleted when their lifetime expires or when they touch a boundary.
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub struct DiffuseParticleSolver {
+    /// Trapped air potential bellow which no diffuse particle is generated.
+    pub min_trapped_air: Real,
+    /// Trapped air potential above which the trapped air generation rate is maximal.
+    pub max_trapped_air: Real,
+    /// Wave crest potential bellow which no diffuse particle is generated.
+    pub min_wave_crest: Real,
+    /// Wave crest potential above which the wave crest generation rate is maximal.
+    pub max_wave_crest: Real,
+    /// Kinetic energy per unit mass bellow which no diffuse particle is generated.
+    pub min_kinetic_energy: Real,
+    /// Kinetic energy per unit mass above which the generation rate is maximal.
+    pub max_kinetic_energy: Real,
+    /// Maximum number of diffuse particles generated per second by a single fluid particle trapping air.
+    pub trapped_air_rate: Real,
+    /// Maximum number of diffuse particles generated per second by a single fluid particle at a wave crest.
+    pub wave_crest_rate: Real,
+    /// Minimum lifetime, in seconds, of a generated diffuse particle.
+    pub min_lifetime: Real,
+    /// Maximum lifetime, in seconds, of a generated diffuse particle.
+    pub max_lifetime: Real,
+    /// Diffuse particles with less fluid neighbors than this are spray particles.
+    pub spray_max_neighbors: usize,
+    /// Diffuse particles with more fluid neighbors than this are bubbles.
+    pub bubble_min_neighbors: usize,
+    /// Multiplier of the gravity applied, in the opposite direction, to bubbles.
+    pub buoyancy_coefficient: Real,
+    /// Fraction of the velocity difference between a bubble and the fluid removed at each substep.
+    pub drag_coefficient: Real,
+    /// The maximum number of diffuse particles. No diffuse particle is generated above this limit.
+    pub max_diffuse_particles: usize,
+    rng: Rng,
+    normals: Vec<Vec<Vector<Real>>>,
+    emission_rates: Vec<Vec<Real>>,
+}
+
+impl DiffuseParticleSolver {
+    /// Initialize a new diffuse particle solver.
+    pub fn new() -> Self {
+        Self {
+            min_trapped_air: na::convert::<_, Real>(5.0),
+            max_trapped_air: na::convert::<_, Real>(20.0),
+            min_wave_crest: na::convert::<_, Real>(2.0),
+            max_wave_crest: na::convert::<_, Real>(8.0),
+            min_kinetic_energy: na::convert::<_, Real>(0.5),
+            max_kinetic_energy: na::convert::<_, Real>(5.0),
+            trapped_air_rate: na::convert::<_, Real>(100.0),
+            wave_crest_rate: na::convert::<_, Real>(100.0),
+            min_lifetime: na::convert::<_, Real>(2.0),
+            max_lifetime: na::convert::<_, Real>(5.0),
+            spray_max_neighbors: if DIM == 2 { 3 } else { 6 },
+            bubble_min_neighbors: if DIM == 2 { 10 } else { 20 },
+            buoyancy_coefficient: na::convert::<_, Real>(2.0),
+            drag_coefficient: na::convert::<_, Real>(0.5),
+            max_diffuse_particles: 100_000,
+            rng: Rng::new(0x853c_49e6_748f_ea9b),
+            normals: Vec::new(),
+            emission_rates: Vec::new(),
+        }
+    }
+
+    /// Sets the seed of the random number generator used to place the generated diffuse particles.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = Rng::new(seed);
+        self
+    }
+
+    fn init_with_fluids(&mut self, fluids: &[Fluid]) {
+        self.normals.resize(fluids.len(), Vec::new());
+        self.emission_rates.resize(fluids.len(), Vec::new());
+
+        for (fluid, normals, emission_rates) in itertools::multizip((
+            fluids.iter(),
+            self.normals.iter_mut(),
+            self.emission_rates.iter_mut(),
+        )) {
+            normals.resize(fluid.num_particles(), Vector::zeros());
+            emission_rates.resize(fluid.num_particles(), na::zero::<Real>());
+        }
+    }
+
+    fn compute_normals(&mut self, contact_manager: &ContactManager, fluids: &[Fluid]) {
+        for (fluid_id, normals) in self.normals.iter_mut().enumerate() {
+            let fluid_fluid_contacts = &contact_manager.fluid_fluid_contacts[fluid_id];
+
+            par_iter_mut!(normals).enumerate().for_each(|(i, normal)| {
+                *normal = Vector::zeros();
+
+                for c in fluid_fluid_contacts
+                    .particle_contacts(i)
+                    .read()
+                    .unwrap()
+                    .iter()
+                {
+                    *normal -= c.gradient * fluids[c.j_model].volumes[c.j];
+                }
+            })
+        }
+    }
+
+    // Computes the number of diffuse particles each fluid particle generates per second.
+    fn compute_emission_rates(
+        &mut self,
+        kernel_radius: Real,
+        contact_manager: &ContactManager,
+        fluids: &[Fluid],
+    ) {
+        let half = na::convert::<_, Real>(0.5);
+        let min_normal_norm = na::convert::<_, Real>(0.1) / kernel_radius;
+        let min_velocity_alignment = na::convert::<_, Real>(0.6);
+        let eps = na::convert::<_, Real>(1.0e-6);
+        let (min_kinetic_energy, max_kinetic_energy) =
+            (self.min_kinetic_energy, self.max_kinetic_energy);
+        let (min_trapped_air, max_trapped_air) = (self.min_trapped_air, self.max_trapped_air);
+        let (min_wave_crest, max_wave_crest) = (self.min_wave_crest, self.max_wave_crest);
+        let (trapped_air_rate, wave_crest_rate) = (self.trapped_air_rate, self.wave_crest_rate);
+        let normals = &self.normals;
+
+        for (fluid_id, emission_rates) in self.emission_rates.iter_mut().enumerate() {
+            let fluid_fluid_contacts = &contact_manager.fluid_fluid_contacts[fluid_id];
+            let fluid_i = &fluids[fluid_id];
+
+            par_iter_mut!(emission_rates)
+                .enumerate()
+                .for_each(|(i, emission_rate)| {
+                    let v_i = fluid_i.velocities[i];
+                    let kinetic_energy = half * v_i.norm_squared();
+                    let energy_potential =
+                        clamp_potential(kinetic_energy, min_kinetic_energy, max_kinetic_energy);
+
+                    if energy_potential <= na::zero::<Real>() {
+                        *emission_rate = na::zero::<Real>();
+                        return;
+                    }
+
+                    let normal_i = normals[fluid_id][i];
+                    let is_at_surface = normal_i.norm() > min_normal_norm
+                        && normal_i.normalize().dot(&v_i.normalize()) >= min_velocity_alignment;
+                    let mut trapped_air = na::zero::<Real>();
+                    let mut wave_crest = na::zero::<Real>();
+
+                    for c in fluid_fluid_contacts
+                        .particle_contacts(i)
+                        .read()
+                        .unwrap()
+                        .iter()
+                    {
+                        let fluid_j = &fluids[c.j_model];
+                        let x_ij = fluid_i.positions[c.i] - fluid_j.positions[c.j];
+                        let dist = x_ij.norm();
+
+                        if dist <= eps || dist >= kernel_radius {
+                            continue;
+                        }
+
+                        let weight = na::one::<Real>() - dist / kernel_radius;
+                        let v_ij = v_i - fluid_j.velocities[c.j];
+                        let v_ij_norm = v_ij.norm();
+
+                        if v_ij_norm > eps {
+                            trapped_air += v_ij_norm
+                                * (na::one::<Real>() - (v_ij / v_ij_norm).dot(&(x_ij / dist)))
+                                * weight;
+                        }
+
+                        let normal_j = normals[c.j_model][c.j];
+
+                        if is_at_surface
+                            && normal_j.norm() > eps
+                            && x_ij.dot(&normal_i) > na::zero::<Real>()
+                        {
+                            wave_crest += (na::one::<Real>()
+                                - normal_i.normalize().dot(&normal_j.normalize()))
+                                * weight;
+                        }
+                    }
+
+                    let trapped_air_potential =
+                        clamp_potential(trapped_air, min_trapped_air, max_trapped_air);
+                    let wave_crest_potential =
+                        clamp_potential(wave_crest, min_wave_crest, max_wave_crest);
+
+                    *emission_rate = energy_potential
+                        * (trapped_air_rate * trapped_air_potential
+                            + wave_crest_rate * wave_crest_potential);
+                })
+        }
+    }
+
+    fn generate_particles(
+        &mut self,
+        dt: Real,
+        particle_radius: Real,
+        fluids: &[Fluid],
+        diffuse_particles: &mut DiffuseParticles,
+    ) {
+        let emission_rates = std::mem::take(&mut self.emission_rates);
+
+        'outer: for (fluid, emission_rates) in fluids.iter().zip(emission_rates.iter()) {
+            for (i, emission_rate) in emission_rates.iter().enumerate() {
+                if *emission_rate <= na::zero::<Real>() {
+                    continue;
+                }
+
+                // Round randomly so that small emission rates still generate particles on average.
+                let num_particles = *emission_rate * dt + self.rng.next_real();
+                let num_particles = na::try_convert::<Real, f64>(num_particles).unwrap() as usize;
+
+                let x_i = fluid.positions[i];
+                let v_i = fluid.velocities[i];
+                let v_norm = v_i.norm();
+                let dir = v_i / v_norm;
+
+                for _ in 0..num_particles {
+                    if diffuse_particles.num_particles() >= self.max_diffuse_particles {
+                        break 'outer;
+                    }
+
+                    // Sample a point in the cylinder spanned by the particle moving during `dt`.
+                    let radial_offset = self.rng.disk_point(&dir, particle_radius);
+                    let axial_offset = dir * (self.rng.next_real() * v_norm * dt);
+                    let lifetime = self.min_lifetime
+                        + self.rng.next_real() * (self.max_lifetime - self.min_lifetime);
+
+                    diffuse_particles.push(
+                        x_i + radial_offset + axial_offset,
+                        v_i + radial_offset,
+                        lifetime,
+                        DiffuseParticleKind::Foam,
+                    );
+                }
+            }
+        }
+
+        self.emission_rates = emission_rates;
+    }
+
+    fn advect_particles(
+        &self,
+        dt: Real,
+        ctx: &DiffuseParticleContext,
+        diffuse_particles: &mut DiffuseParticles,
+    ) {
+        let DiffuseParticleContext {
+            kernel_radius,
+            particle_radius,
+            gravity,
+            neighbor_search,
+            fluids,
+            boundaries,
+            ..
+        } = *ctx;
+        let mut to_remove = vec![false; diffuse_particles.num_particles()];
+        let extents = Vector::repeat(kernel_radius);
+
+        par_iter_mut!(diffuse_particles.positions)
+            .zip(par_iter_mut!(diffuse_particles.velocities))
+            .zip(par_iter_mut!(diffuse_particles.lifetimes))
+            .zip(par_iter_mut!(diffuse_particles.kinds))
+            .zip(par_iter_mut!(to_remove))
+            .for_each(|((((position, velocity), lifetime), kind), to_remove)| {
+                let mut num_neighbors = 0;
+                let mut fluid_velocity = Vector::zeros();
+                let mut total_weight = na::zero::<Real>();
+
+                for entry in neighbor_search
+                    .particles_in_aabb(&(*position - extents), &(*position + extents))
+                {
+                    match entry {
+                        HGridEntry::FluidParticle(fluid_id, j) => {
+                            let fluid = &fluids[fluid_id];
+                            let dist = na::distance(position, &fluid.positions[j]);
+
+                            if dist < kernel_radius {
+                                let weight = na::one::<Real>() - dist / kernel_radius;
+                                num_neighbors += 1;
+                                fluid_velocity += fluid.velocities[j] * weight;
+                                total_weight += weight;
+                            }
+                        }
+                        HGridEntry::BoundaryParticle(boundary_id, j) => {
+                            let boundary = &boundaries[boundary_id];
+
+                            if na::distance(position, &boundary.positions[j]) < particle_radius {
+                                *to_remove = true;
+                            }
+                        }
+                    }
+                }
+
+                if total_weight > na::zero::<Real>() {
+                    fluid_velocity /= total_weight;
+                }
+
+                if num_neighbors < self.spray_max_neighbors {
+                    *kind = DiffuseParticleKind::Spray;
+                    *velocity += gravity * dt;
+                    *position += *velocity * dt;
+                } else if num_neighbors > self.bubble_min_neighbors {
+                    *kind = DiffuseParticleKind::Bubble;
+                    *velocity += gravity * (-self.buoyancy_coefficient * dt)
+                        + (fluid_velocity - *velocity) * self.drag_coefficient;
+                    *position += *velocity * dt;
+                } else {
+                    *kind = DiffuseParticleKind::Foam;
+                    *velocity = fluid_velocity;
+                    *position += fluid_velocity * dt;
+                }
+
+                *lifetime -= dt;
+                *to_remove = *to_remove || *lifetime <= na::zero::<Real>();
+            });
+
+        if to_remove.iter().any(|r| *r) {
+            diffuse_particles.remove_from_mask(&to_remove);
+        }
+    }
+
+    /// Advects the existing diffuse particles and generates new ones.
+    pub fn solve(
+        &mut self,
+        timestep: &TimestepManager,
+        ctx: &DiffuseParticleContext,
+        diffuse_particles: &mut DiffuseParticles,
+    ) {
+        let dt = timestep.dt();
+
+        self.advect_particles(dt, ctx, diffuse_particles);
+
+        self.init_with_fluids(ctx.fluids);
+        self.compute_normals(ctx.contact_manager, ctx.fluids);
+        self.compute_emission_rates(ctx.kernel_radius, ctx.contact_manager, ctx.fluids);
+        self.generate_particles(dt, ctx.particle_radius, ctx.fluids, diffuse_particles);
+    }
+}
+
+impl Default for DiffuseParticleSolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Maps `value` linearly from `[min, max]` to `[0, 1]`, clamping values outside of this range.
+fn clamp_potential(value: Real, min: Real, max: Real) -> Real {
+    (value.min(max) - value.min(min)) / (max - min)
+}
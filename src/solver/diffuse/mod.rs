@@ -0,0 +1,3 @@
+pub use self::diffuse_particle_solver::{DiffuseParticleContext, DiffuseParticleSolver};
+
+mod diffuse_particle_solver;
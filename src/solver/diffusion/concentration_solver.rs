@@ -0,0 +1,172 @@
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use crate::geometry::{ContactManager, ParticlesContacts};
+use crate::math::Real;
+use crate::object::Fluid;
+use crate::TimestepManager;
+
+/// A solver for the diffusion of concentrations between fluid particles, making miscible fluids mix.
+///
+/// Only the fluids with concentrations enabled (see `Fluid::enable_concentrations`) are affected.
+/// Concentrations are exchanged between particles of the same fluid or of different fluids with
+/// concentrations. Since the particles move with the fluid, the advection of the concentrations is
+/// implicit. Boundaries are impermeable, and the total amount of dissolved substance is preserved.
+///
+/// The diffusion is integrated explicitly, with as many sub-iterations as needed to remain stable
+/// with large diffusion coefficients. If more than `max_diffusion_iter` sub-iterations would be
+/// needed, the concentrations diffuse slower than the diffusion coefficients would imply.
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub struct ConcentrationSolver {
+    /// The maximum number of sub-iterations performed at each substep.
+    pub max_diffusion_iter: usize,
+    concentration_changes: Vec<Vec<Real>>,
+}
+
+impl ConcentrationSolver {
+    /// Initialize a new concentration solver.
+    pub fn new() -> Self {
+        Self {
+            max_diffusion_iter: 10,
+            concentration_changes: Vec::new(),
+        }
+    }
+
+    fn init_with_fluids(&mut self, fluids: &mut [Fluid]) {
+        self.concentration_changes.resize(fluids.len(), Vec::new());
+
+        for (fluid, concentration_changes) in
+            fluids.iter_mut().zip(self.concentration_changes.iter_mut())
+        {
+            if let Some(concentration) = fluid.default_concentration {
+                let num_particles = fluid.num_particles();
+                fluid.concentrations.resize(num_particles, concentration);
+                concentration_changes.resize(num_particles, na::zero::<Real>());
+            } else {
+                concentration_changes.clear();
+            }
+        }
+    }
+
+    fn compute_concentration_changes(
+        &mut self,
+        dt: Real,
+        kernel_radius: Real,
+        fluid_fluid_contacts: &[ParticlesContacts],
+        fluids: &[Fluid],
+    ) {
+        let two: Real = na::convert::<_, Real>(2.0);
+        let eps = kernel_radius * kernel_radius * na::convert::<_, Real>(0.01);
+
+        for fluid_id in 0..fluids.len() {
+            let fluid_i = &fluids[fluid_id];
+
+            if fluid_i.default_concentration.is_none() {
+                continue;
+            }
+
+            let fluid_fluid_contacts = &fluid_fluid_contacts[fluid_id];
+            let diffusivity_i = fluid_i.concentration_diffusivity;
+
+            par_iter_mut!(self.concentration_changes[fluid_id])
+                .enumerate()
+                .for_each(|(i, concentration_change)| {
+                    let concentration_i = fluid_i.concentrations[i];
+                    let mut laplacian = na::zero::<Real>();
+
+                    for c in fluid_fluid_contacts
+                        .particle_contacts(i)
+                        .read()
+                        .unwrap()
+                        .iter()
+                    {
+                        let fluid_j = &fluids[c.j_model];
+
+                        if fluid_j.default_concentration.is_none() {
+                            continue;
+                        }
+
+                        // Harmonic mean, so that exchanges are symmetric between fluids.
+                        let diffusivity_j = fluid_j.concentration_diffusivity;
+                        let diffusivity_sum = diffusivity_i + diffusivity_j;
+
+                        if diffusivity_sum <= na::zero::<Real>() {
+                            continue;
+                        }
+
+                        let diffusivity_ij = two * diffusivity_i * diffusivity_j / diffusivity_sum;
+                        let dpos = fluid_i.positions[c.i] - fluid_j.positions[c.j];
+                        let dconcentration = concentration_i - fluid_j.concentrations[c.j];
+                        laplacian += diffusivity_ij
+                            * fluid_j.volumes[c.j]
+                            * dconcentration
+                            * dpos.dot(&c.gradient)
+                            / (dpos.norm_squared() + eps);
+                    }
+
+                    *concentration_change = laplacian * two * dt;
+                })
+        }
+    }
+
+    /// Diffuses the concentrations of the fluid particles during a substep of length `timestep.dt()`.
+    ///
+    /// The contacts of `contact_manager` must have been computed by the pressure solver.
+    pub fn solve(
+        &mut self,
+        timestep: &TimestepManager,
+        kernel_radius: Real,
+        contact_manager: &ContactManager,
+        fluids: &mut [Fluid],
+    ) {
+        self.init_with_fluids(fluids);
+
+        // The explicit integration is stable if `diffusivity * dt / h²` remains small enough.
+        let max_diffusivity = fluids
+            .iter()
+            .filter(|f| f.default_concentration.is_some())
+            .map(|f| f.concentration_diffusivity)
+            .fold(na::zero::<Real>(), |a, b| a.max(b));
+
+        if max_diffusivity <= na::zero::<Real>() {
+            return;
+        }
+
+        let max_dt = kernel_radius * kernel_radius * na::convert::<_, Real>(0.05) / max_diffusivity;
+        let niters =
+            na::try_convert::<Real, f64>((timestep.dt() / max_dt).ceil()).unwrap() as usize;
+        let niters = niters.max(1).min(self.max_diffusion_iter.max(1));
+        // If the number of iterations is limited, the diffusion is slowed down instead of diverging.
+        let dt = (timestep.dt() / na::convert::<_, Real>(niters as f64)).min(max_dt);
+
+        for _ in 0..niters {
+            self.compute_concentration_changes(
+                dt,
+                kernel_radius,
+                &contact_manager.fluid_fluid_contacts,
+                fluids,
+            );
+
+            for (fluid, concentration_changes) in
+                fluids.iter_mut().zip(self.concentration_changes.iter())
+            {
+                if fluid.default_concentration.is_some() {
+                    par_iter_mut!(fluid.concentrations)
+                        .zip(par_iter!(concentration_changes))
+                        .for_each(|(concentration, change)| {
+                            *concentration += *change;
+                        })
+                }
+            }
+        }
+    }
+}
+
+impl Default for ConcentrationSolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
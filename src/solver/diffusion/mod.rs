@@ -0,0 +1,3 @@
+pub use self::concentration_solver::ConcentrationSolver;
+
+mod concentration_solver;
@@ -0,0 +1,99 @@
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use crate::geometry::ParticlesContacts;
+use crate::math::{Point, Real, Vector};
+use crate::object::{Boundary, Fluid};
+use crate::solver::{NonPressureForce, OtherFluids};
+use crate::TimestepManager;
+
+/// A function returning the wind velocity at a given world-space point.
+pub type WindField = dyn Fn(&Point<Real>) -> Vector<Real> + Send + Sync;
+
+/// The velocity of the air surrounding a fluid.
+pub enum Wind {
+    /// The same wind velocity everywhere.
+    Constant(Vector<Real>),
+    /// A wind velocity depending on the world-space position of each particle.
+    Field(Box<WindField>),
+}
+
+impl Wind {
+    /// The wind velocity at the given point.
+    pub fn velocity_at(&self, point: &Point<Real>) -> Vector<Real> {
+        match self {
+            Wind::Constant(velocity) => *velocity,
+            Wind::Field(field) => field(point),
+        }
+    }
+}
+
+/// A drag force damping the velocities of the particles relative to the surrounding air.
+///
+/// The drag acceleration of a particle moving at the velocity `v` relative to the wind is
+/// `-(linear_coefficient + quadratic_coefficient * |v|) * v`. It is integrated implicitly, so the
+/// particles never move slower than the wind because of large coefficients or timesteps.
+pub struct DragForce {
+    /// The drag coefficient proportional to the relative velocity, in `1/s`.
+    pub linear_coefficient: Real,
+    /// The drag coefficient proportional to the square of the relative velocity, in `1/m`.
+    pub quadratic_coefficient: Real,
+    /// The velocity of the air.
+    pub wind: Wind,
+}
+
+impl DragForce {
+    /// Initializes a linear drag force with the given coefficient, without wind.
+    pub fn new(linear_coefficient: Real) -> Self {
+        Self {
+            linear_coefficient,
+            quadratic_coefficient: na::zero::<Real>(),
+            wind: Wind::Constant(Vector::zeros()),
+        }
+    }
+
+    /// Sets the drag coefficient proportional to the square of the relative velocity.
+    pub fn with_quadratic_coefficient(mut self, quadratic_coefficient: Real) -> Self {
+        self.quadratic_coefficient = quadratic_coefficient;
+        self
+    }
+
+    /// Sets the velocity of the air.
+    pub fn with_wind(mut self, wind: Wind) -> Self {
+        self.wind = wind;
+        self
+    }
+}
+
+impl NonPressureForce for DragForce {
+    fn solve(
+        &mut self,
+        timestep: &TimestepManager,
+        _kernel_radius: Real,
+        _fluid_fluid_contacts: &ParticlesContacts,
+        _fluid_boundaries_contacts: &ParticlesContacts,
+        fluid: &mut Fluid,
+        _other_fluids: &OtherFluids,
+        _boundaries: &[Boundary],
+        _densities: &[Real],
+    ) {
+        let dt = timestep.dt();
+        let inv_dt = timestep.inv_dt();
+        let linear_coefficient = self.linear_coefficient;
+        let quadratic_coefficient = self.quadratic_coefficient;
+        let wind = &self.wind;
+        let positions = &fluid.positions;
+        let velocities = &fluid.velocities;
+
+        par_iter_mut!(fluid.accelerations)
+            .enumerate()
+            .for_each(|(i, acceleration)| {
+                let relative_velocity = velocities[i] - wind.velocity_at(&positions[i]);
+                let coefficient =
+                    linear_coefficient + quadratic_coefficient * relative_velocity.norm();
+                // Exact integration of `dv/dt = -coefficient * v` over the timestep.
+                let damping = na::one::<Real>() - (-coefficient * dt).exp();
+                *acceleration -= relative_velocity * (damping * inv_dt);
+            })
+    }
+}
@@ -0,0 +1,141 @@
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use na::DMatrix;
+
+use crate::geometry::ParticlesContacts;
+use crate::math::{Real, Vector};
+use crate::object::{Boundary, Fluid};
+use crate::solver::{helper, NonPressureForce, OtherFluids};
+use crate::TimestepManager;
+
+/// A drag force exchanging momentum between the particles of immiscible fluids in contact.
+///
+/// The force applied to a particle `i` by a neighbor `j` of another fluid is
+/// `K * V_i * V_j * W_ij * (v_j - v_i)`, where `K` is the drag coefficient between both fluids,
+/// in `kg/(m³·s)`, `V` the particle volumes, `W_ij` the kernel, and `v` the velocities. This lets
+/// air push water spray, or oil slow down the water underneath. The drag is integrated
+/// implicitly: the velocity of each pair of particles relaxes toward the velocity of its center
+/// of mass, so the drag never reverses the relative velocity of the fluids because of large
+/// coefficients or timesteps.
+///
+/// This force must be added to each fluid of the pairs with a non-zero coefficient, with the
+/// same coefficients, for the momentum exchange to be symmetric.
+#[derive(Clone)]
+pub struct InterphaseDrag {
+    /// The drag coefficients between each pair of fluids, in `kg/(m³·s)`.
+    ///
+    /// The coefficient between the `i`-th and `j`-th fluids of `LiquidWorld::fluids().as_slice()`
+    /// is `coefficients[(i, j)]`. This matrix should be symmetric, and the coefficients missing
+    /// from it are zero.
+    pub coefficients: DMatrix<Real>,
+}
+
+impl InterphaseDrag {
+    /// Initializes an inter-phase drag force with the given matrix of drag coefficients.
+    pub fn new(coefficients: DMatrix<Real>) -> Self {
+        Self { coefficients }
+    }
+
+    /// Sets the drag coefficient between the `fluid1`-th and the `fluid2`-th fluids.
+    ///
+    /// Both symmetric entries of the coefficient matrix are set, and the matrix is grown if
+    /// needed.
+    pub fn with_coefficient(mut self, fluid1: usize, fluid2: usize, coefficient: Real) -> Self {
+        let size = self.coefficients.nrows().max(fluid1 + 1).max(fluid2 + 1);
+
+        if size > self.coefficients.nrows() || size > self.coefficients.ncols() {
+            let coefficients = std::mem::replace(&mut self.coefficients, DMatrix::zeros(0, 0));
+            self.coefficients = coefficients.resize(size, size, na::zero::<Real>());
+        }
+
+        self.coefficients[(fluid1, fluid2)] = coefficient;
+        self.coefficients[(fluid2, fluid1)] = coefficient;
+        self
+    }
+
+    /// The drag coefficient between the `fluid1`-th and the `fluid2`-th fluids.
+    pub fn coefficient(&self, fluid1: usize, fluid2: usize) -> Real {
+        self.coefficients
+            .get((fluid1, fluid2))
+            .copied()
+            .unwrap_or_else(na::zero::<Real>)
+    }
+}
+
+impl NonPressureForce for InterphaseDrag {
+    fn solve(
+        &mut self,
+        timestep: &TimestepManager,
+        _kernel_radius: Real,
+        fluid_fluid_contacts: &ParticlesContacts,
+        _fluid_boundaries_contacts: &ParticlesContacts,
+        fluid: &mut Fluid,
+        other_fluids: &OtherFluids,
+        _boundaries: &[Boundary],
+        _densities: &[Real],
+    ) {
+        let fluid_id = other_fluids.fluid_id();
+
+        if (0..other_fluids.num_fluids())
+            .all(|j| self.coefficient(fluid_id, j) == na::zero::<Real>())
+        {
+            return;
+        }
+
+        let dt = timestep.dt();
+        let inv_dt = timestep.inv_dt();
+        let velocities = &fluid.velocities;
+        let volumes = &fluid.volumes;
+        let rest_densities = &fluid.rest_densities;
+        let density0 = fluid.density0;
+        let rest_density = |k| helper::rest_density(rest_densities, density0, k);
+        let coefficient = |model| self.coefficient(fluid_id, model);
+
+        par_iter_mut!(fluid.accelerations)
+            .enumerate()
+            .for_each(|(i, acceleration)| {
+                let mi = volumes[i] * rest_density(i);
+
+                if mi == na::zero::<Real>() {
+                    return;
+                }
+
+                // Each pair of particles relaxes toward the velocity of its center of mass. The
+                // rates, in `1/s`, of all the pairs are combined into a single exact relaxation
+                // so the particle never overshoots the velocities of its neighbors.
+                let mut total_rate = na::zero::<Real>();
+                let mut weighted_velocity = Vector::zeros();
+
+                for c in fluid_fluid_contacts
+                    .particle_contacts(i)
+                    .read()
+                    .unwrap()
+                    .iter()
+                {
+                    let Some(fluid_j) = other_fluids.fluid(c.j_model) else {
+                        continue;
+                    };
+                    let coefficient = coefficient(c.j_model);
+                    let mj = fluid_j.particle_mass(c.j);
+
+                    if coefficient != na::zero::<Real>() && mj != na::zero::<Real>() {
+                        let center_velocity =
+                            (velocities[i] * mi + fluid_j.velocities[c.j] * mj) / (mi + mj);
+                        let rate =
+                            coefficient * volumes[i] * fluid_j.volumes[c.j] * c.weight * (mi + mj)
+                                / (mi * mj);
+                        total_rate += rate;
+                        weighted_velocity += center_velocity * rate;
+                    }
+                }
+
+                if total_rate > na::zero::<Real>() {
+                    let relative_velocity = weighted_velocity / total_rate - velocities[i];
+                    // Exact integration of `dv/dt = total_rate * relative_velocity`.
+                    let damping = na::one::<Real>() - (-total_rate * dt).exp();
+                    *acceleration += relative_velocity * (damping * inv_dt);
+                }
+            })
+    }
+}
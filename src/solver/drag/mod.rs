@@ -0,0 +1,5 @@
+pub use self::drag_force::{DragForce, Wind, WindField};
+pub use self::interphase_drag::InterphaseDrag;
+
+mod drag_force;
+mod interphase_drag;
@@ -9,7 +9,7 @@ use crate::geometry::{self, ParticlesContacts};
 use crate::kernel::{CubicSplineKernel, Kernel};
 use crate::math::{Matrix, Point, Real, RotationMatrix, SpatialVector, Vector};
 use crate::object::{Boundary, Fluid};
-use crate::solver::NonPressureForce;
+use crate::solver::{helper, NonPressureForce, OtherFluids};
 use crate::TimestepManager;
 
 fn elasticity_coefficients(young_modulus: Real, poisson_ratio: Real) -> (Real, Real, Real) {
@@ -36,8 +36,38 @@ fn sym_mat_mul_vec(mat: &SpatialVector<Real>, v: &Vector<Real>) -> Vector<Real>
     );
 }
 
+/// Parameters of the plastic behavior of an elastic material.
+///
+/// The strain in excess of `yield_strain` is progressively transferred to a plastic strain that
+/// is subtracted from the total strain before computing the stress. The rest configuration of the
+/// material thus deforms permanently.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Plasticity {
+    /// The norm of the elastic strain above which the material starts deforming plastically.
+    pub yield_strain: Real,
+    /// The fraction, in `[0.0, 1.0]`, of the elastic strain converted to plastic strain at
+    /// each substep once the yield strain is exceeded.
+    pub creep: Real,
+    /// The maximum norm of the plastic strain, after which the material stops deforming permanently.
+    pub max_plastic_strain: Real,
+}
+
+impl Plasticity {
+    /// Initializes plasticity parameters.
+    pub fn new(yield_strain: Real, creep: Real, max_plastic_strain: Real) -> Self {
+        Self {
+            yield_strain,
+            creep,
+            max_plastic_strain,
+        }
+    }
+}
+
 // https://cg.informatik.uni-freiburg.de/publications/2009_NP_corotatedSPH.pdf
 /// Elasticity based on the method from Becker et al. 2009.
+///
+/// If `Fluid::kernel_gradient_correction` is enabled, the kernel gradients of the rest
+/// configuration are corrected with the renormalization matrix of each particle.
 pub struct Becker2009Elasticity<
     KernelDensity: Kernel = CubicSplineKernel,
     KernelGradient: Kernel = CubicSplineKernel,
@@ -46,12 +76,15 @@ pub struct Becker2009Elasticity<
     d1: Real,
     d2: Real,
     nonlinear_strain: bool,
+    plasticity: Option<Plasticity>,
     volumes0: Vec<Real>,
     positions0: Vec<Point<Real>>,
     contacts0: ParticlesContacts,
+    gradient_corrections0: Vec<Matrix<Real>>,
     rotations: Vec<RotationMatrix<Real>>,
     deformation_gradient_tr: Vec<Matrix<Real>>,
     stress: Vec<SpatialVector<Real>>,
+    plastic_strains: Vec<SpatialVector<Real>>,
     phantom: PhantomData<(KernelDensity, KernelGradient)>,
 }
 
@@ -71,27 +104,71 @@ impl<KernelDensity: Kernel, KernelGradient: Kernel>
             d1,
             d2,
             nonlinear_strain,
+            plasticity: None,
             volumes0: Vec::new(),
             positions0: Vec::new(),
             contacts0: ParticlesContacts::new(),
+            gradient_corrections0: Vec::new(),
             rotations: Vec::new(),
             deformation_gradient_tr: Vec::new(),
             stress: Vec::new(),
+            plastic_strains: Vec::new(),
             phantom: PhantomData,
         }
     }
 
+    /// Changes the young modulus and poisson ratio of this elastic material.
+    pub fn set_material(&mut self, young_modulus: Real, poisson_ratio: Real) {
+        let (d0, d1, d2) = elasticity_coefficients(young_modulus, poisson_ratio);
+        self.d0 = d0;
+        self.d1 = d1;
+        self.d2 = d2;
+    }
+
+    /// Makes this material deform plastically with the given parameters.
+    pub fn with_plasticity(mut self, plasticity: Plasticity) -> Self {
+        self.plasticity = Some(plasticity);
+        self
+    }
+
+    /// The plastic behavior of this material, if any.
+    pub fn plasticity(&self) -> Option<&Plasticity> {
+        self.plasticity.as_ref()
+    }
+
+    /// Sets the plastic behavior of this material.
+    ///
+    /// If `None`, the material becomes purely elastic, but the plastic deformations
+    /// accumulated so far are kept.
+    pub fn set_plasticity(&mut self, plasticity: Option<Plasticity>) {
+        self.plasticity = plasticity;
+    }
+
+    /// Makes the current particle positions the new rest configuration.
+    ///
+    /// The rest configuration is otherwise captured the first time this force is applied,
+    /// and whenever the number of particles changes.
+    ///
+    /// This also discards all the plastic deformations.
+    pub fn reset_rest_configuration(&mut self) {
+        self.positions0.clear();
+    }
+
     fn init(&mut self, kernel_radius: Real, fluid: &Fluid) {
         let nparticles = fluid.positions.len();
 
         if self.positions0.len() != nparticles {
             self.positions0 = fluid.positions.clone();
+            self.volumes0.clear();
             self.volumes0.resize(nparticles, na::zero::<Real>());
             self.rotations
                 .resize(nparticles, RotationMatrix::identity());
             self.deformation_gradient_tr
                 .resize(nparticles, Matrix::identity());
             self.stress.resize(nparticles, SpatialVector::zeros());
+            self.plastic_strains.clear();
+            self.plastic_strains
+                .resize(nparticles, SpatialVector::zeros());
             geometry::compute_self_contacts(kernel_radius, fluid, &mut self.contacts0);
 
             for contacts in self.contacts0.contacts_mut() {
@@ -109,6 +186,26 @@ impl<KernelDensity: Kernel, KernelGradient: Kernel>
             for i in 0..nparticles {
                 self.volumes0[i] = fluid.particle_mass(i) / self.volumes0[i];
             }
+
+            // The kernel gradient correction matrices of the rest configuration.
+            self.gradient_corrections0.clear();
+            self.gradient_corrections0
+                .resize(nparticles, Matrix::identity());
+
+            if fluid.kernel_gradient_correction {
+                for i in 0..nparticles {
+                    let mut moment = Matrix::zeros();
+
+                    for c in self.contacts0.particle_contacts(i).read().unwrap().iter() {
+                        let p0_ji = self.positions0[c.j] - self.positions0[c.i];
+                        moment += (c.gradient * self.volumes0[c.j]) * p0_ji.transpose();
+                    }
+
+                    if let Some(correction) = moment.try_inverse() {
+                        self.gradient_corrections0[i] = correction;
+                    }
+                }
+            }
         }
     }
 
@@ -143,6 +240,7 @@ impl<KernelDensity: Kernel, KernelGradient: Kernel>
         let contacts0 = &self.contacts0;
         let rotations = &self.rotations;
         let positions0 = &self.positions0;
+        let gradient_corrections0 = &self.gradient_corrections0;
 
         // let _0 = na::zero::<Real>();
         // let c = Matrix::new(
@@ -169,94 +267,98 @@ impl<KernelDensity: Kernel, KernelGradient: Kernel>
         let d2 = self.d2;
 
         let nonlinear_strain = self.nonlinear_strain;
+        let plasticity = self.plasticity;
         let volumes0 = &self.volumes0;
 
         par_iter_mut!(&mut self.deformation_gradient_tr)
             .zip(&mut self.stress)
+            .zip(&mut self.plastic_strains)
             .enumerate()
-            .for_each(|(i, (deformation_grad_tr, stress))| {
+            .for_each(|(i, ((deformation_grad_tr, stress), plastic_strain))| {
                 let mut grad_tr = Matrix::zeros();
+                let correction = &gradient_corrections0[i];
 
                 for c in contacts0.particle_contacts(i).read().unwrap().iter() {
                     let p_ji = fluid.positions[c.j] - fluid.positions[c.i];
                     let p0_ji = positions0[c.j] - positions0[c.i];
                     let u_ji = rotations[c.i].inverse_transform_vector(&(p_ji)) - p0_ji;
-                    grad_tr += (c.gradient * volumes0[c.j]) * u_ji.transpose();
+                    grad_tr += (correction * c.gradient * volumes0[c.j]) * u_ji.transpose();
                 }
 
                 *deformation_grad_tr = grad_tr;
 
+                // The strain, with the diagonal components first.
                 #[cfg(feature = "dim3")]
-                {
-                    if nonlinear_strain {
-                        let j = grad_tr + Matrix::identity();
-                        let jjt = j * j.transpose();
-
-                        let stress012 = c_top_left
-                            * Vector::new(
-                                jjt.m11 - na::one::<Real>(),
-                                jjt.m22 - na::one::<Real>(),
-                                jjt.m33 - na::one::<Real>(),
-                            )
-                            * _0_5;
-                        *stress = SpatialVector::new(
-                            stress012.x,
-                            stress012.y,
-                            stress012.z,
-                            jjt.m21 * _0_5 * d2,
-                            jjt.m31 * _0_5 * d2,
-                            jjt.m32 * _0_5 * d2,
-                        );
-                    } else {
-                        // let strain = Vector::new(
-                        //     grad_tr.m11,
-                        //     grad_tr.m22,
-                        //     grad_tr.m33,
-                        //     (grad_tr.m21 + grad_tr.m12) * _0_5,
-                        //     (grad_tr.m31 + grad_tr.m13) * _0_5,
-                        //     (grad_tr.m23 + grad_tr.m32) * _0_5,
-                        // );
-
-                        let stress012 =
-                            c_top_left * Vector::new(grad_tr.m11, grad_tr.m22, grad_tr.m33);
-                        *stress = SpatialVector::new(
-                            stress012.x,
-                            stress012.y,
-                            stress012.z,
-                            (grad_tr.m21 + grad_tr.m12) * _0_5 * d2,
-                            (grad_tr.m31 + grad_tr.m13) * _0_5 * d2,
-                            (grad_tr.m23 + grad_tr.m32) * _0_5 * d2,
-                        );
+                let strain = if nonlinear_strain {
+                    let j = grad_tr + Matrix::identity();
+                    let jjt = j * j.transpose();
+
+                    SpatialVector::new(
+                        jjt.m11 - na::one::<Real>(),
+                        jjt.m22 - na::one::<Real>(),
+                        jjt.m33 - na::one::<Real>(),
+                        jjt.m21,
+                        jjt.m31,
+                        jjt.m32,
+                    ) * _0_5
+                } else {
+                    SpatialVector::new(
+                        grad_tr.m11,
+                        grad_tr.m22,
+                        grad_tr.m33,
+                        (grad_tr.m21 + grad_tr.m12) * _0_5,
+                        (grad_tr.m31 + grad_tr.m13) * _0_5,
+                        (grad_tr.m23 + grad_tr.m32) * _0_5,
+                    )
+                };
+
+                #[cfg(feature = "dim2")]
+                let strain = if nonlinear_strain {
+                    let j = grad_tr + Matrix::identity();
+                    let jjt = j * j.transpose();
+
+                    SpatialVector::new(
+                        jjt.m11 - na::one::<Real>(),
+                        jjt.m22 - na::one::<Real>(),
+                        jjt.m21,
+                    ) * _0_5
+                } else {
+                    SpatialVector::new(grad_tr.m11, grad_tr.m22, (grad_tr.m21 + grad_tr.m12) * _0_5)
+                };
+
+                let mut elastic_strain = strain - *plastic_strain;
+
+                if let Some(plasticity) = &plasticity {
+                    if elastic_strain.norm() > plasticity.yield_strain {
+                        *plastic_strain += elastic_strain * plasticity.creep;
+
+                        let plastic_norm = plastic_strain.norm();
+                        if plastic_norm > plasticity.max_plastic_strain {
+                            *plastic_strain *= plasticity.max_plastic_strain / plastic_norm;
+                        }
+
+                        elastic_strain = strain - *plastic_strain;
                     }
                 }
 
+                #[cfg(feature = "dim3")]
+                {
+                    let stress012 = c_top_left
+                        * Vector::new(elastic_strain[0], elastic_strain[1], elastic_strain[2]);
+                    *stress = SpatialVector::new(
+                        stress012.x,
+                        stress012.y,
+                        stress012.z,
+                        elastic_strain[3] * d2,
+                        elastic_strain[4] * d2,
+                        elastic_strain[5] * d2,
+                    );
+                }
+
                 #[cfg(feature = "dim2")]
                 {
-                    if nonlinear_strain {
-                        let j = grad_tr + Matrix::identity();
-                        let jjt = j * j.transpose();
-
-                        let stress01 = c_top_left
-                            * Vector::new(jjt.m11 - na::one::<Real>(), jjt.m22 - na::one::<Real>())
-                            * _0_5;
-                        *stress = SpatialVector::new(stress01.x, stress01.y, jjt.m21 * _0_5 * d2);
-                    } else {
-                        // let strain = Vector::new(
-                        //     grad_tr.m11,
-                        //     grad_tr.m22,
-                        //     grad_tr.m33,
-                        //     (grad_tr.m21 + grad_tr.m12) * _0_5,
-                        //     (grad_tr.m31 + grad_tr.m13) * _0_5,
-                        //     (grad_tr.m23 + grad_tr.m32) * _0_5,
-                        // );
-
-                        let stress01 = c_top_left * Vector::new(grad_tr.m11, grad_tr.m22);
-                        *stress = SpatialVector::new(
-                            stress01.x,
-                            stress01.y,
-                            (grad_tr.m21 + grad_tr.m12) * _0_5 * d2,
-                        );
-                    }
+                    let stress01 = c_top_left * Vector::new(elastic_strain[0], elastic_strain[1]);
+                    *stress = SpatialVector::new(stress01.x, stress01.y, elastic_strain[2] * d2);
                 }
             })
     }
@@ -272,6 +374,7 @@ impl<KernelDensity: Kernel, KernelGradient: Kernel> NonPressureForce
         _fluid_fluid_contacts: &ParticlesContacts,
         _fluid_boundaries_contacts: &ParticlesContacts,
         fluid: &mut Fluid,
+        _other_fluids: &OtherFluids,
         _boundaries: &[Boundary],
         _densities: &[Real],
     ) {
@@ -284,11 +387,14 @@ impl<KernelDensity: Kernel, KernelGradient: Kernel> NonPressureForce
         // Compute and apply forces.
         let contacts0 = &self.contacts0;
         let volumes0 = &self.volumes0;
+        let gradient_corrections0 = &self.gradient_corrections0;
         let deformation_gradient_tr = &self.deformation_gradient_tr;
         let rotations = &self.rotations;
         let stress = &self.stress;
         let volumes = &fluid.volumes;
+        let rest_densities = &fluid.rest_densities;
         let density0 = fluid.density0;
+        let rest_density = |k| helper::rest_density(rest_densities, density0, k);
 
         if self.nonlinear_strain {
             par_iter_mut!(fluid.accelerations)
@@ -298,18 +404,18 @@ impl<KernelDensity: Kernel, KernelGradient: Kernel> NonPressureForce
                         let mut force = Vector::zeros();
 
                         let grad_tr_i = &deformation_gradient_tr[c.i];
-                        let d_ij = c.gradient * volumes0[c.j];
+                        let d_ij = gradient_corrections0[c.i] * c.gradient * volumes0[c.j];
                         let sigma_d_ij = sym_mat_mul_vec(&stress[c.i], &d_ij);
                         let f_ji = (sigma_d_ij + grad_tr_i * sigma_d_ij) * -volumes0[c.i];
 
                         let grad_tr_j = &deformation_gradient_tr[c.j];
-                        let d_ji = c.gradient * (-volumes0[c.i]);
+                        let d_ji = gradient_corrections0[c.j] * c.gradient * (-volumes0[c.i]);
                         let sigma_d_ji = sym_mat_mul_vec(&stress[c.j], &d_ji);
                         let f_ij = (sigma_d_ji + grad_tr_j * sigma_d_ji) * -volumes0[c.j];
 
                         force += (rotations[c.j] * f_ij - (rotations[c.i] * f_ji)) * _0_5;
 
-                        *acceleration += force / (volumes[i] * density0);
+                        *acceleration += force / (volumes[i] * rest_density(i));
                     }
                 })
         } else {
@@ -319,24 +425,32 @@ impl<KernelDensity: Kernel, KernelGradient: Kernel> NonPressureForce
                     for c in contacts0.particle_contacts(i).read().unwrap().iter() {
                         let mut force = Vector::zeros();
 
-                        let d_ij = c.gradient * volumes0[c.j];
+                        let d_ij = gradient_corrections0[c.i] * c.gradient * volumes0[c.j];
                         let f_ji = sym_mat_mul_vec(&stress[c.i], &d_ij) * -volumes0[c.i];
 
-                        let d_ji = c.gradient * (-volumes0[c.i]);
+                        let d_ji = gradient_corrections0[c.j] * c.gradient * (-volumes0[c.i]);
                         let f_ij = sym_mat_mul_vec(&stress[c.j], &d_ji) * -volumes0[c.j];
 
                         force += (rotations[c.j] * f_ij - (rotations[c.i] * f_ji)) * _0_5;
 
-                        *acceleration += force / (volumes[i] * density0);
+                        *acceleration += force / (volumes[i] * rest_density(i));
                     }
                 })
         }
     }
 
     fn apply_permutation(&mut self, permutation: &[usize]) {
+        if permutation.iter().any(|i| *i >= self.positions0.len()) {
+            // Some particles were added since the rest configuration was captured.
+            self.reset_rest_configuration();
+            return;
+        }
+
         self.volumes0 = crate::z_order::apply_permutation(permutation, &self.volumes0);
         self.positions0 = crate::z_order::apply_permutation(permutation, &self.positions0);
         self.rotations = crate::z_order::apply_permutation(permutation, &self.rotations);
+        self.plastic_strains =
+            crate::z_order::apply_permutation(permutation, &self.plastic_strains);
         self.contacts0.apply_permutation(permutation);
     }
 }
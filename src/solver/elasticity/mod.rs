@@ -1,3 +1,3 @@
-pub use self::becker2009_elasticity::Becker2009Elasticity;
+pub use self::becker2009_elasticity::{Becker2009Elasticity, Plasticity};
 
 mod becker2009_elasticity;
@@ -0,0 +1,175 @@
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use crate::geometry::ParticlesContacts;
+use crate::math::{Point, Real, Vector};
+use crate::object::{Boundary, Fluid};
+use crate::solver::{helper, NonPressureForce, OtherFluids};
+use crate::TimestepManager;
+
+#[derive(Clone)]
+/// Internal friction for granular materials like sand, gravel, or debris.
+///
+/// The relative motion between each particle and its neighborhood is resisted by a friction
+/// bounded by a Drucker-Prager yield criterion: the maximum frictional stress is
+/// `cohesion + pressure * tan(friction_angle)`. The pressure is estimated from the pressure
+/// forces applied to each particle during the previous substep, so this force should be the last
+/// of the non-pressure forces of the fluid.
+///
+/// With this force, piles of particles settle at an angle of repose close to the friction angle
+/// instead of flowing like a liquid.
+pub struct DruckerPragerFriction {
+    /// The internal friction angle of the material, in radians (typically around 30 degrees for sand).
+    pub friction_angle: Real,
+    /// The cohesion of the material, in Pascal (zero for dry sand).
+    pub cohesion: Real,
+    /// The friction with boundaries, relative to the friction inside of the material.
+    pub boundary_friction_coefficient: Real,
+    predicted_velocities: Vec<Vector<Real>>,
+    prev_inv_dt: Real,
+}
+
+impl DruckerPragerFriction {
+    /// Initializes the friction of a granular material with its internal friction angle (in radians)
+    /// and cohesion.
+    pub fn new(friction_angle: Real, cohesion: Real) -> Self {
+        Self {
+            friction_angle,
+            cohesion,
+            boundary_friction_coefficient: na::one::<Real>(),
+            predicted_velocities: Vec::new(),
+            prev_inv_dt: na::zero::<Real>(),
+        }
+    }
+
+    fn init(&mut self, fluid: &Fluid) {
+        if self.predicted_velocities.len() != fluid.num_particles() {
+            // We don't know the pressure forces of the previous substep.
+            self.predicted_velocities = fluid.velocities.clone();
+        }
+    }
+}
+
+impl NonPressureForce for DruckerPragerFriction {
+    fn solve(
+        &mut self,
+        timestep: &TimestepManager,
+        _kernel_radius: Real,
+        fluid_fluid_contacts: &ParticlesContacts,
+        fluid_boundaries_contacts: &ParticlesContacts,
+        fluid: &mut Fluid,
+        _other_fluids: &OtherFluids,
+        boundaries: &[Boundary],
+        densities: &[Real],
+    ) {
+        self.init(fluid);
+
+        let tan_friction_angle = self.friction_angle.tan();
+        let boundary_friction_coefficient = self.boundary_friction_coefficient;
+        let cohesion_acc = self.cohesion
+            / (fluid.density0 * fluid.particle_radius() * na::convert::<_, Real>(2.0));
+        let prev_inv_dt = self.prev_inv_dt;
+        let predicted_velocities = &self.predicted_velocities;
+        let velocities = &fluid.velocities;
+        let volumes = &fluid.volumes;
+        let rest_densities = &fluid.rest_densities;
+        let density0 = fluid.density0;
+        let rest_density = |k| helper::rest_density(rest_densities, density0, k);
+
+        par_iter_mut!(fluid.accelerations)
+            .enumerate()
+            .for_each(|(i, acceleration)| {
+                // The acceleration due to pressure during the previous substep.
+                let pressure_acc = (velocities[i] - predicted_velocities[i]) * prev_inv_dt;
+                let max_friction_acc = cohesion_acc + pressure_acc.norm() * tan_friction_angle;
+
+                let mut avg_vel = Vector::zeros();
+                let mut total_weight = na::zero::<Real>();
+
+                for c in fluid_fluid_contacts
+                    .particle_contacts(i)
+                    .read()
+                    .unwrap()
+                    .iter()
+                {
+                    if c.i_model == c.j_model {
+                        let w = c.weight * volumes[c.j] * rest_density(c.j) / densities[c.j];
+                        avg_vel += velocities[c.j] * w;
+                        total_weight += w;
+                    }
+                }
+
+                for c in fluid_boundaries_contacts
+                    .particle_contacts(i)
+                    .read()
+                    .unwrap()
+                    .iter()
+                {
+                    let boundary = &boundaries[c.j_model];
+                    let w = c.weight * boundary.volumes[c.j] * boundary_friction_coefficient;
+                    avg_vel += boundary.velocities[c.j] * w;
+                    total_weight += w;
+                }
+
+                if total_weight <= na::zero::<Real>() {
+                    return;
+                }
+
+                // The acceleration that would cancel the relative motion of this particle
+                // wrt. its neighborhood during this substep.
+                let predicted_vel = velocities[i] + *acceleration * timestep.dt();
+                let sticking_acc = (avg_vel / total_weight - predicted_vel) * timestep.inv_dt();
+                let sticking_acc_norm = sticking_acc.norm();
+
+                let scale = if sticking_acc_norm <= max_friction_acc {
+                    na::one::<Real>()
+                } else {
+                    max_friction_acc / sticking_acc_norm
+                };
+                *acceleration += sticking_acc * scale;
+
+                // Apply the reaction of the friction to the boundaries.
+                let mi = volumes[i] * rest_density(i);
+
+                for c in fluid_boundaries_contacts
+                    .particle_contacts(i)
+                    .read()
+                    .unwrap()
+                    .iter()
+                {
+                    let boundary = &boundaries[c.j_model];
+
+                    if boundary.receives_forces() {
+                        let w = c.weight * boundary.volumes[c.j] * boundary_friction_coefficient;
+                        let acc = (boundary.velocities[c.j] - predicted_vel)
+                            * (w / total_weight * timestep.inv_dt() * scale);
+                        boundary.apply_force(c.j, acc * -mi);
+                    }
+                }
+            });
+
+        par_iter_mut!(self.predicted_velocities)
+            .zip(par_iter!(fluid.velocities))
+            .zip(par_iter!(fluid.accelerations))
+            .for_each(|((predicted, vel), acc)| *predicted = *vel + *acc * timestep.dt());
+        self.prev_inv_dt = timestep.inv_dt();
+    }
+
+    fn apply_permutation(&mut self, permutation: &[usize]) {
+        self.predicted_velocities =
+            crate::z_order::apply_permutation(permutation, &self.predicted_velocities);
+    }
+
+    fn add_particles(
+        &mut self,
+        num_added: usize,
+        positions: &[Point<Real>],
+        velocities: &[Vector<Real>],
+    ) {
+        if self.predicted_velocities.len() + num_added == positions.len() {
+            let first_new = self.predicted_velocities.len();
+            self.predicted_velocities
+                .extend_from_slice(&velocities[first_new..]);
+        }
+    }
+}
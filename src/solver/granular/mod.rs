@@ -0,0 +1,3 @@
+pub use self::drucker_prager_friction::DruckerPragerFriction;
+
+mod drucker_prager_friction;
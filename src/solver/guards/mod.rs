@@ -0,0 +1,3 @@
+pub use self::simulation_guards::SimulationGuards;
+
+mod simulation_guards;
@@ -0,0 +1,151 @@
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use crate::math::{Point, Real, Vector};
+use crate::object::Fluid;
+
+/// Safeguards applied to the fluid particles after the integration of each substep.
+///
+/// A single particle getting a huge or non-finite velocity (because of a too large timestep, an
+/// overlapping boundary, etc.) corrupts the spacial grid and the neighborhoods of all the other
+/// particles, which quickly blows up the whole simulation. These guards detect such particles
+/// and bring them back to a sane state:
+/// - Particles with a non-finite position or velocity are moved back to their position at the
+///   beginning of the substep, and their velocity is set to zero.
+/// - The speed of the particles is clamped to `max_speed`.
+/// - The distance traveled by the particles during the substep is clamped to `max_displacement`
+///   times the particle radius. Their velocity is left unchanged.
+///
+/// All the guards are disabled by default. The number of particles affected by the guards is
+/// reported by `Counters::stats`.
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub struct SimulationGuards {
+    /// The maximum speed of the fluid particles, if any.
+    pub max_speed: Option<Real>,
+    /// The maximum distance traveled by a fluid particle during a substep, as a fraction of the
+    /// particle radius, if any.
+    pub max_displacement: Option<Real>,
+    /// Whether the particles with a non-finite position or velocity are reset.
+    pub replace_nans: bool,
+}
+
+impl SimulationGuards {
+    /// Initializes the simulation guards, all disabled.
+    pub fn new() -> Self {
+        Self {
+            max_speed: None,
+            max_displacement: None,
+            replace_nans: false,
+        }
+    }
+
+    /// Sets the maximum speed of the fluid particles.
+    pub fn with_max_speed(mut self, max_speed: Real) -> Self {
+        self.max_speed = Some(max_speed);
+        self
+    }
+
+    /// Sets the maximum distance traveled by a fluid particle during a substep, as a fraction of
+    /// the particle radius.
+    pub fn with_max_displacement(mut self, max_displacement: Real) -> Self {
+        self.max_displacement = Some(max_displacement);
+        self
+    }
+
+    /// Enables the reset of the particles with a non-finite position or velocity.
+    pub fn with_nan_replacement(mut self) -> Self {
+        self.replace_nans = true;
+        self
+    }
+
+    /// Whether these guards need the positions of the particles at the beginning of the substep.
+    pub(crate) fn needs_previous_positions(&self) -> bool {
+        self.replace_nans || self.max_displacement.is_some()
+    }
+
+    /// Applies the guards to the fluid particles.
+    ///
+    /// The `previous_positions` are the positions of the particles of each fluid at the beginning
+    /// of the substep. They are ignored if they don't match the number of particles of a fluid.
+    /// Returns the number of particles with a non-finite state, and the number of particles
+    /// which had their speed or displacement clamped.
+    pub fn apply(
+        &self,
+        particle_radius: Real,
+        previous_positions: &[Vec<Point<Real>>],
+        fluids: &mut [Fluid],
+    ) -> (usize, usize) {
+        let mut num_nans = 0;
+        let mut num_clamped = 0;
+        let max_displacement = self.max_displacement.map(|d| d * particle_radius);
+
+        for (fluid_id, fluid) in fluids.iter_mut().enumerate() {
+            let previous_positions = previous_positions
+                .get(fluid_id)
+                .filter(|p| p.len() == fluid.num_particles());
+
+            let guard = |(i, (pos, vel)): (usize, (&mut Point<Real>, &mut Vector<Real>))| {
+                let previous = previous_positions.map(|p| p[i]);
+
+                if self.replace_nans && !(is_finite(&pos.coords) && is_finite(vel)) {
+                    if let Some(previous) = previous {
+                        *pos = previous;
+                    }
+
+                    vel.fill(na::zero::<Real>());
+                    return na::Vector2::new(1, 0);
+                }
+
+                let mut clamped = 0;
+
+                if let Some(max_speed) = self.max_speed {
+                    let speed = vel.norm();
+
+                    if speed > max_speed {
+                        *vel *= max_speed / speed;
+                        clamped = 1;
+                    }
+                }
+
+                if let (Some(max_displacement), Some(previous)) = (max_displacement, previous) {
+                    let displacement = *pos - previous;
+                    let distance = displacement.norm();
+
+                    if distance > max_displacement {
+                        *pos = previous + displacement * (max_displacement / distance);
+                        clamped = 1;
+                    }
+                }
+
+                na::Vector2::new(0, clamped)
+            };
+
+            let counts = par_reduce_sum!(
+                na::Vector2::zeros(),
+                par_iter_mut!(fluid.positions)
+                    .zip(par_iter_mut!(fluid.velocities))
+                    .enumerate()
+                    .map(guard)
+            );
+
+            num_nans += counts.x;
+            num_clamped += counts.y;
+        }
+
+        (num_nans, num_clamped)
+    }
+}
+
+impl Default for SimulationGuards {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn is_finite(v: &Vector<Real>) -> bool {
+    v.iter().all(|e| e.is_finite())
+}
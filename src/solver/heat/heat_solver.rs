@@ -0,0 +1,198 @@
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use crate::geometry::{ContactManager, ParticlesContacts};
+use crate::math::Real;
+use crate::object::{Boundary, Fluid};
+use crate::TimestepManager;
+
+/// A solver for the diffusion of heat between fluid particles, and with boundaries.
+///
+/// Only the fluids with temperatures enabled (see `Fluid::enable_temperatures`) are affected.
+/// Heat is exchanged between particles of the same fluid or of different fluids with
+/// temperatures, as well as with the boundaries having a fixed `Boundary::temperature`.
+///
+/// The diffusion is integrated explicitly, with as many sub-iterations as needed to remain stable
+/// with large thermal diffusivities. If more than `max_diffusion_iter` sub-iterations would be
+/// needed, the heat diffuses slower than the thermal diffusivity would imply.
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub struct HeatSolver {
+    /// Multiplier applied to the thermal diffusivity of a fluid for its heat exchanges with boundaries.
+    pub boundary_diffusivity_coefficient: Real,
+    /// The maximum number of sub-iterations performed at each substep.
+    pub max_diffusion_iter: usize,
+    temperature_changes: Vec<Vec<Real>>,
+}
+
+impl HeatSolver {
+    /// Initialize a new heat solver.
+    pub fn new() -> Self {
+        Self {
+            boundary_diffusivity_coefficient: na::one::<Real>(),
+            max_diffusion_iter: 10,
+            temperature_changes: Vec::new(),
+        }
+    }
+
+    fn init_with_fluids(&mut self, fluids: &mut [Fluid]) {
+        self.temperature_changes.resize(fluids.len(), Vec::new());
+
+        for (fluid, temperature_changes) in
+            fluids.iter_mut().zip(self.temperature_changes.iter_mut())
+        {
+            if let Some(temperature) = fluid.default_temperature {
+                let num_particles = fluid.num_particles();
+                fluid.temperatures.resize(num_particles, temperature);
+                temperature_changes.resize(num_particles, na::zero::<Real>());
+            } else {
+                temperature_changes.clear();
+            }
+        }
+    }
+
+    fn compute_temperature_changes(
+        &mut self,
+        dt: Real,
+        kernel_radius: Real,
+        fluid_fluid_contacts: &[ParticlesContacts],
+        fluid_boundary_contacts: &[ParticlesContacts],
+        fluids: &[Fluid],
+        boundaries: &[Boundary],
+    ) {
+        let two: Real = na::convert::<_, Real>(2.0);
+        let eps = kernel_radius * kernel_radius * na::convert::<_, Real>(0.01);
+        let boundary_diffusivity_coefficient = self.boundary_diffusivity_coefficient;
+
+        for fluid_id in 0..fluids.len() {
+            let fluid_i = &fluids[fluid_id];
+
+            if fluid_i.default_temperature.is_none() {
+                continue;
+            }
+
+            let fluid_fluid_contacts = &fluid_fluid_contacts[fluid_id];
+            let fluid_boundary_contacts = &fluid_boundary_contacts[fluid_id];
+            let alpha_i = fluid_i.thermal_diffusivity;
+
+            par_iter_mut!(self.temperature_changes[fluid_id])
+                .enumerate()
+                .for_each(|(i, temperature_change)| {
+                    let temperature_i = fluid_i.temperatures[i];
+                    let mut laplacian = na::zero::<Real>();
+
+                    for c in fluid_fluid_contacts
+                        .particle_contacts(i)
+                        .read()
+                        .unwrap()
+                        .iter()
+                    {
+                        let fluid_j = &fluids[c.j_model];
+
+                        if fluid_j.default_temperature.is_none() {
+                            continue;
+                        }
+
+                        // Harmonic mean, so that heat exchanges are symmetric between fluids.
+                        let alpha_j = fluid_j.thermal_diffusivity;
+                        let alpha_sum = alpha_i + alpha_j;
+
+                        if alpha_sum <= na::zero::<Real>() {
+                            continue;
+                        }
+
+                        let alpha_ij = two * alpha_i * alpha_j / alpha_sum;
+                        let dpos = fluid_i.positions[c.i] - fluid_j.positions[c.j];
+                        let dtemp = temperature_i - fluid_j.temperatures[c.j];
+                        laplacian +=
+                            alpha_ij * fluid_j.volumes[c.j] * dtemp * dpos.dot(&c.gradient)
+                                / (dpos.norm_squared() + eps);
+                    }
+
+                    for c in fluid_boundary_contacts
+                        .particle_contacts(i)
+                        .read()
+                        .unwrap()
+                        .iter()
+                    {
+                        let boundary_j = &boundaries[c.j_model];
+
+                        if let Some(temperature_j) = boundary_j.temperature {
+                            let alpha_ij = alpha_i * boundary_diffusivity_coefficient;
+                            let dpos = fluid_i.positions[c.i] - boundary_j.positions[c.j];
+                            let dtemp = temperature_i - temperature_j;
+                            laplacian +=
+                                alpha_ij * boundary_j.volumes[c.j] * dtemp * dpos.dot(&c.gradient)
+                                    / (dpos.norm_squared() + eps);
+                        }
+                    }
+
+                    *temperature_change = laplacian * two * dt;
+                })
+        }
+    }
+
+    /// Diffuses the temperatures of the fluid particles during a substep of length `timestep.dt()`.
+    ///
+    /// The contacts of `contact_manager` must have been computed by the pressure solver.
+    pub fn solve(
+        &mut self,
+        timestep: &TimestepManager,
+        kernel_radius: Real,
+        contact_manager: &ContactManager,
+        fluids: &mut [Fluid],
+        boundaries: &[Boundary],
+    ) {
+        self.init_with_fluids(fluids);
+
+        // The explicit integration is stable if `alpha * dt / h²` remains small enough.
+        let max_alpha = fluids
+            .iter()
+            .filter(|f| f.default_temperature.is_some())
+            .map(|f| f.thermal_diffusivity)
+            .fold(na::zero::<Real>(), |a, b| a.max(b));
+        let max_alpha = max_alpha.max(max_alpha * self.boundary_diffusivity_coefficient);
+
+        if max_alpha <= na::zero::<Real>() {
+            return;
+        }
+
+        let max_dt = kernel_radius * kernel_radius * na::convert::<_, Real>(0.05) / max_alpha;
+        let niters =
+            na::try_convert::<Real, f64>((timestep.dt() / max_dt).ceil()).unwrap() as usize;
+        let niters = niters.max(1).min(self.max_diffusion_iter.max(1));
+        // If the number of iterations is limited, the diffusion is slowed down instead of diverging.
+        let dt = (timestep.dt() / na::convert::<_, Real>(niters as f64)).min(max_dt);
+
+        for _ in 0..niters {
+            self.compute_temperature_changes(
+                dt,
+                kernel_radius,
+                &contact_manager.fluid_fluid_contacts,
+                &contact_manager.fluid_boundary_contacts,
+                fluids,
+                boundaries,
+            );
+
+            for (fluid, temperature_changes) in
+                fluids.iter_mut().zip(self.temperature_changes.iter())
+            {
+                if fluid.default_temperature.is_some() {
+                    par_iter_mut!(fluid.temperatures)
+                        .zip(par_iter!(temperature_changes))
+                        .for_each(|(temperature, change)| {
+                            *temperature += *change;
+                        })
+                }
+            }
+        }
+    }
+}
+
+impl Default for HeatSolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
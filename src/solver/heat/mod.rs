@@ -0,0 +1,5 @@
+pub use self::heat_solver::HeatSolver;
+pub use self::phase_change::PhaseChange;
+
+mod heat_solver;
+mod phase_change;
@@ -0,0 +1,323 @@
+use num::Zero;
+
+use crate::geometry::ParticlesContacts;
+use crate::math::{Matrix, Point, Real, Vector, DIM};
+use crate::object::{Boundary, Fluid};
+use crate::solver::{ImplicitViscosity, NonPressureForce, OtherFluids, ViscosityContext};
+use crate::TimestepManager;
+
+// The rotational part of the polar decomposition of `mat`.
+fn extract_rotation(mat: &Matrix<Real>) -> Matrix<Real> {
+    let svd = mat.svd(true, true);
+    let mut u = svd.u.unwrap();
+    let v_t = svd.v_t.unwrap();
+
+    if (u * v_t).determinant() < na::zero::<Real>() {
+        let mut last = u.column_mut(DIM - 1);
+        last *= -na::one::<Real>();
+    }
+
+    u * v_t
+}
+
+fn find_root(parents: &mut [usize], mut i: usize) -> usize {
+    while parents[i] != i {
+        parents[i] = parents[parents[i]];
+        i = parents[i];
+    }
+
+    i
+}
+
+// The mass properties and best-fit rotation of a cluster of solid particles.
+#[derive(Copy, Clone)]
+struct Cluster {
+    mass: Real,
+    center: Point<Real>,
+    rest_center: Point<Real>,
+    covariance: Matrix<Real>,
+    rotation: Matrix<Real>,
+}
+
+impl Cluster {
+    fn new() -> Self {
+        Self {
+            mass: na::zero::<Real>(),
+            center: Point::origin(),
+            rest_center: Point::origin(),
+            covariance: Matrix::zeros(),
+            rotation: Matrix::identity(),
+        }
+    }
+}
+
+/// Melting and solidification of a fluid, depending on the temperatures of its particles.
+///
+/// The fluid must have temperatures enabled (see `Fluid::enable_temperatures`), and a
+/// `HeatSolver` should be set on the `LiquidWorld` so that the temperatures evolve. Particles
+/// colder than the `freezing_temperature` are solid: connected groups of solid particles keep the
+/// shape they had when they froze, and move together as rigid bodies. Particles warmer than the `melting_temperature` are liquid.
+/// In-between, the viscosity is interpolated between the viscosities of the solid and of the liquid.
+///
+/// This force replaces the viscosity of the fluid, so it should not be combined with another
+/// viscosity force.
+pub struct PhaseChange {
+    /// The temperature below which particles are solid.
+    pub freezing_temperature: Real,
+    /// The temperature above which particles are liquid.
+    ///
+    /// This must not be smaller than the `freezing_temperature`.
+    pub melting_temperature: Real,
+    /// The kinematic viscosity of the liquid phase.
+    pub liquid_viscosity: Real,
+    /// The kinematic viscosity of the solid phase.
+    pub solid_viscosity: Real,
+    /// If `true` (the default), connected solid particles are constrained to move rigidly.
+    pub rigidify_solid: bool,
+    solver: ImplicitViscosity,
+    liquid_fractions: Vec<Real>,
+    cluster_ids: Vec<usize>,
+    // The position of each solid particle in the rest frame of its cluster.
+    rest_positions: Vec<Point<Real>>,
+    has_rest_position: Vec<bool>,
+}
+
+impl PhaseChange {
+    /// Initializes a phase change between the given freezing and melting temperatures.
+    pub fn new(freezing_temperature: Real, melting_temperature: Real) -> Self {
+        Self {
+            freezing_temperature,
+            melting_temperature,
+            liquid_viscosity: na::convert::<_, Real>(0.01),
+            solid_viscosity: na::convert::<_, Real>(100.0),
+            rigidify_solid: true,
+            solver: ImplicitViscosity::new(na::zero::<Real>(), na::zero::<Real>()),
+            liquid_fractions: Vec::new(),
+            cluster_ids: Vec::new(),
+            rest_positions: Vec::new(),
+            has_rest_position: Vec::new(),
+        }
+    }
+
+    /// The fraction of each particle that is liquid, as computed during the last substep.
+    ///
+    /// This is `0.0` for solid particles and `1.0` for liquid particles.
+    pub fn liquid_fractions(&self) -> &[Real] {
+        &self.liquid_fractions
+    }
+
+    /// The implicit solver used to resolve the viscosity.
+    ///
+    /// This can be used to change its number of iterations or the boundary viscosity.
+    pub fn solver_mut(&mut self) -> &mut ImplicitViscosity {
+        &mut self.solver
+    }
+
+    fn compute_liquid_fractions(&mut self, fluid: &Fluid) {
+        let freezing = self.freezing_temperature;
+        let range = self.melting_temperature - freezing;
+
+        self.liquid_fractions.clear();
+
+        if fluid.temperatures.len() != fluid.num_particles() {
+            // Temperatures are not simulated: everything is liquid.
+            self.liquid_fractions
+                .resize(fluid.num_particles(), na::one::<Real>());
+            return;
+        }
+
+        self.liquid_fractions
+            .extend(fluid.temperatures.iter().map(|t| {
+                if *t <= freezing {
+                    na::zero::<Real>()
+                } else if *t >= freezing + range {
+                    na::one::<Real>()
+                } else {
+                    (*t - freezing) / range
+                }
+            }));
+    }
+
+    // Groups the solid particles into connected clusters.
+    fn compute_clusters(&mut self, fluid_fluid_contacts: &ParticlesContacts) {
+        let nparticles = self.liquid_fractions.len();
+        self.cluster_ids.clear();
+        self.cluster_ids.extend(0..nparticles);
+
+        for i in 0..nparticles {
+            if !self.liquid_fractions[i].is_zero() {
+                continue;
+            }
+
+            for c in fluid_fluid_contacts
+                .particle_contacts(i)
+                .read()
+                .unwrap()
+                .iter()
+            {
+                if c.i_model == c.j_model && self.liquid_fractions[c.j].is_zero() {
+                    let root_i = find_root(&mut self.cluster_ids, c.i);
+                    let root_j = find_root(&mut self.cluster_ids, c.j);
+                    self.cluster_ids[root_i] = root_j;
+                }
+            }
+        }
+
+        for i in 0..nparticles {
+            self.cluster_ids[i] = find_root(&mut self.cluster_ids, i);
+        }
+    }
+
+    // Modifies the accelerations of the solid particles so that each cluster moves rigidly,
+    // matching the shape of its rest configuration.
+    fn rigidify_clusters(&mut self, timestep: &TimestepManager, fluid: &mut Fluid) {
+        let nparticles = fluid.num_particles();
+        let dt = timestep.dt();
+        let inv_dt = timestep.inv_dt();
+
+        if self.rest_positions.len() != nparticles {
+            self.rest_positions = fluid.positions.clone();
+            self.has_rest_position.clear();
+            self.has_rest_position.resize(nparticles, false);
+        }
+
+        let predicted_velocities: Vec<_> = fluid
+            .velocities
+            .iter()
+            .zip(fluid.accelerations.iter())
+            .map(|(v, a)| *v + *a * dt)
+            .collect();
+        let predicted_positions: Vec<_> = fluid
+            .positions
+            .iter()
+            .zip(predicted_velocities.iter())
+            .map(|(x, v)| *x + *v * dt)
+            .collect();
+
+        // Fit the rest configuration of each cluster to its predicted configuration.
+        // Particles that just froze are ignored until they are given a rest position.
+        let mut clusters = vec![Cluster::new(); nparticles];
+
+        for i in 0..nparticles {
+            if !self.liquid_fractions[i].is_zero() {
+                self.has_rest_position[i] = false;
+            } else if self.has_rest_position[i] {
+                let cluster = &mut clusters[self.cluster_ids[i]];
+                let mass = fluid.particle_mass(i);
+                cluster.mass += mass;
+                cluster.center += predicted_positions[i].coords * mass;
+                cluster.rest_center += self.rest_positions[i].coords * mass;
+            }
+        }
+
+        for cluster in &mut clusters {
+            if !cluster.mass.is_zero() {
+                cluster.center /= cluster.mass;
+                cluster.rest_center /= cluster.mass;
+            }
+        }
+
+        for i in 0..nparticles {
+            if self.has_rest_position[i] {
+                let cluster = &mut clusters[self.cluster_ids[i]];
+                let dpos = predicted_positions[i] - cluster.center;
+                let dpos0 = self.rest_positions[i] - cluster.rest_center;
+                cluster.covariance += dpos * dpos0.transpose() * fluid.particle_mass(i);
+            }
+        }
+
+        for cluster in &mut clusters {
+            if !cluster.mass.is_zero() {
+                cluster.rotation = extract_rotation(&cluster.covariance);
+            }
+        }
+
+        for i in 0..nparticles {
+            if !self.liquid_fractions[i].is_zero() {
+                continue;
+            }
+
+            let cluster = &clusters[self.cluster_ids[i]];
+
+            if self.has_rest_position[i] {
+                let goal = cluster.center
+                    + cluster.rotation * (self.rest_positions[i] - cluster.rest_center);
+                let velocity = (goal - fluid.positions[i]) * inv_dt;
+                fluid.accelerations[i] += (velocity - predicted_velocities[i]) * inv_dt;
+            } else {
+                // Express the position of the newly frozen particle in the rest frame of its cluster.
+                let dpos = predicted_positions[i] - cluster.center;
+                self.rest_positions[i] = cluster.rest_center + cluster.rotation.transpose() * dpos;
+                self.has_rest_position[i] = true;
+            }
+        }
+    }
+}
+
+impl NonPressureForce for PhaseChange {
+    fn solve(
+        &mut self,
+        timestep: &TimestepManager,
+        kernel_radius: Real,
+        fluid_fluid_contacts: &ParticlesContacts,
+        fluid_boundaries_contacts: &ParticlesContacts,
+        fluid: &mut Fluid,
+        _other_fluids: &OtherFluids,
+        boundaries: &[Boundary],
+        densities: &[Real],
+    ) {
+        self.compute_liquid_fractions(fluid);
+
+        let liquid_viscosity = self.liquid_viscosity;
+        let solid_viscosity = self.solid_viscosity;
+        self.solver.particle_viscosities.clear();
+        self.solver.particle_viscosities.extend(
+            self.liquid_fractions
+                .iter()
+                .map(|fraction| solid_viscosity + (liquid_viscosity - solid_viscosity) * *fraction),
+        );
+
+        let ctx = ViscosityContext {
+            dt: timestep.predicted_dt(),
+            kernel_radius,
+            fluid_fluid_contacts,
+            fluid_boundaries_contacts,
+            boundaries,
+            densities,
+        };
+        self.solver.solve_with_particle_viscosities(&ctx, fluid);
+
+        if self.rigidify_solid {
+            self.compute_clusters(fluid_fluid_contacts);
+            self.rigidify_clusters(timestep, fluid);
+        }
+    }
+
+    fn apply_permutation(&mut self, permutation: &[usize]) {
+        if permutation.iter().any(|i| *i >= self.rest_positions.len()) {
+            // Some particles were added since the rest positions were last resized.
+            self.rest_positions.clear();
+            self.has_rest_position.clear();
+            return;
+        }
+
+        self.rest_positions = crate::z_order::apply_permutation(permutation, &self.rest_positions);
+        self.has_rest_position =
+            crate::z_order::apply_permutation(permutation, &self.has_rest_position);
+    }
+
+    fn add_particles(
+        &mut self,
+        num_added: usize,
+        positions: &[Point<Real>],
+        _velocities: &[Vector<Real>],
+    ) {
+        if self.rest_positions.len() + num_added == positions.len() {
+            // The new particles get a rest position when they freeze.
+            let first_new = self.rest_positions.len();
+            self.rest_positions
+                .extend_from_slice(&positions[first_new..]);
+            self.has_rest_position.resize(positions.len(), false);
+        }
+    }
+}
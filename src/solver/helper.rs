@@ -1,43 +1,212 @@
-use crate::geometry::ParticlesContacts;
+use crate::geometry::{ContactManager, ParticlesContacts, PeriodicDomain};
 use crate::kernel::Kernel;
-use crate::math::Real;
-use crate::object::{Boundary, Fluid};
+use crate::math::{Matrix, Point, Real, Vector};
+use crate::object::{Boundary, Fluid, SurfaceDetection};
+#[cfg(feature = "simd")]
+use crate::simd;
 
 #[cfg(feature = "parallel")]
 use rayon::prelude::*;
 
+/// The rest density of the `i`-th particle of a fluid, given its `Fluid::rest_densities` and
+/// `Fluid::density0`.
+///
+/// This is `Fluid::rest_density` for when the fields of the fluid are borrowed separately.
+#[inline]
+pub fn rest_density(rest_densities: &[Real], density0: Real, i: usize) -> Real {
+    if rest_densities.is_empty() {
+        density0
+    } else {
+        rest_densities[i]
+    }
+}
+
+// See "Solenthaler & Pajarola 2008, Density Contrast SPH Interfaces".
+/// The mass of the `j`-th particle of `fluid_j` as seen by the `i`-th particle of `fluid_i` when
+/// computing its density.
+///
+/// Neighbors are given the rest density of the `i`-th particle so that the density of a particle
+/// close to particles with a different rest density is not under- or over-estimated. This is the
+/// particle mass if both particles have the same rest density.
+#[inline]
+pub fn adapted_mass(fluid_i: &Fluid, i: usize, fluid_j: &Fluid, j: usize) -> Real {
+    fluid_j.volumes[j] * fluid_i.rest_density(i)
+}
+
+/// The mass weighting the pressure of the `j`-th particle of `fluid_j` in the pressure acceleration
+/// of the `i`-th particle of `fluid_i`.
+///
+/// Combined with `adapted_mass` for the pressure of the `i`-th particle itself, this keeps the
+/// pressure forces symmetric between particles with different rest densities. This is the particle
+/// mass if both particles have the same rest density.
+#[inline]
+pub fn pressure_mass(fluid_i: &Fluid, i: usize, fluid_j: &Fluid, j: usize) -> Real {
+    let rest_density_j = fluid_j.rest_density(j);
+    fluid_j.volumes[j] * rest_density_j * rest_density_j / fluid_i.rest_density(i)
+}
+
+// The periodic image of `pj` closest to `pi`, if the domain is periodic.
+fn closest_image(
+    periodic_domain: Option<&PeriodicDomain>,
+    pi: &Point<Real>,
+    pj: &Point<Real>,
+) -> Point<Real> {
+    periodic_domain.map_or(*pj, |domain| domain.closest_image(pi, pj))
+}
+
+/// Evaluates the kernels and kernel gradients of the contacts of the fluid particles.
+///
+/// The kernels of each contact are evaluated with the symmetrized kernel radius `(h_i + h_j) / 2`,
+/// `h_i` and `h_j` being the kernel radii of the fluids or boundaries of its particles (see
+/// `Fluid::kernel_radius`). The boundaries have the kernel radius `kernel_radius` of the liquid
+/// world.
 pub fn update_fluid_contacts<KernelDensity: Kernel, KernelGradient: Kernel>(
     kernel_radius: Real,
     fluid_fluid_contacts: &mut [ParticlesContacts],
     fluid_boundary_contacts: &mut [ParticlesContacts],
     fluids: &[Fluid],
     boundaries: &[Boundary],
+    periodic_domain: Option<&PeriodicDomain>,
 ) {
+    let half = na::convert::<_, Real>(0.5);
+    // The SIMD kernel evaluation requires the same kernel radius for all the contacts.
+    let is_uniform = fluids
+        .iter()
+        .all(|fluid| fluid.kernel_radius(kernel_radius) == kernel_radius);
+
     for contacts in fluid_fluid_contacts.iter_mut() {
         par_iter_mut!(contacts.contacts_mut()).for_each(|contacts| {
-            for c in contacts.get_mut().unwrap() {
+            let contacts = contacts.get_mut().unwrap();
+
+            #[cfg(feature = "simd")]
+            if is_uniform {
+                if let Some(pi) = contacts.first().map(|c| fluids[c.i_model].positions[c.i]) {
+                    simd::evaluate_kernels::<KernelDensity, KernelGradient>(
+                        &pi,
+                        contacts,
+                        kernel_radius,
+                        |c| closest_image(periodic_domain, &pi, &fluids[c.j_model].positions[c.j]),
+                    );
+                }
+
+                return;
+            }
+
+            for c in contacts {
                 let fluid1 = &fluids[c.i_model];
                 let fluid2 = &fluids[c.j_model];
                 let pi = fluid1.positions[c.i];
-                let pj = fluid2.positions[c.j];
+                let pj = closest_image(periodic_domain, &pi, &fluid2.positions[c.j]);
+                let h = (fluid1.kernel_radius(kernel_radius) + fluid2.kernel_radius(kernel_radius))
+                    * half;
 
-                c.weight = KernelDensity::points_apply(&pi, &pj, kernel_radius);
-                c.gradient = KernelGradient::points_apply_diff1(&pi, &pj, kernel_radius);
+                c.weight = KernelDensity::points_apply(&pi, &pj, h);
+                c.gradient = KernelGradient::points_apply_diff1(&pi, &pj, h);
             }
         })
     }
 
     for contacts in fluid_boundary_contacts.iter_mut() {
         par_iter_mut!(contacts.contacts_mut()).for_each(|contacts| {
-            for c in contacts.get_mut().unwrap() {
+            let contacts = contacts.get_mut().unwrap();
+            let simd_evaluated = cfg!(feature = "simd") && is_uniform;
+
+            // The contacts with density map boundaries are overwritten below.
+            #[cfg(feature = "simd")]
+            if is_uniform {
+                if let Some(pi) = contacts.first().map(|c| fluids[c.i_model].positions[c.i]) {
+                    simd::evaluate_kernels::<KernelDensity, KernelGradient>(
+                        &pi,
+                        contacts,
+                        kernel_radius,
+                        |c| {
+                            closest_image(
+                                periodic_domain,
+                                &pi,
+                                &boundaries[c.j_model].positions[c.j],
+                            )
+                        },
+                    );
+                }
+            }
+
+            for c in contacts {
                 let fluid1 = &fluids[c.i_model];
                 let bound2 = &boundaries[c.j_model];
 
                 let pi = fluid1.positions[c.i];
-                let pj = bound2.positions[c.j];
 
-                c.weight = KernelDensity::points_apply(&pi, &pj, kernel_radius);
-                c.gradient = KernelGradient::points_apply_diff1(&pi, &pj, kernel_radius);
+                if let Some(density_map) = bound2.density_map() {
+                    let position = bound2.density_map_position();
+                    let local_pi = position.inverse_transform_point(&pi);
+                    c.weight = density_map.density(&local_pi);
+                    c.gradient = position * density_map.density_gradient(&local_pi);
+                } else if !simd_evaluated {
+                    let pj = closest_image(periodic_domain, &pi, &bound2.positions[c.j]);
+                    let h = (fluid1.kernel_radius(kernel_radius) + kernel_radius) * half;
+                    c.weight = KernelDensity::points_apply(&pi, &pj, h);
+                    c.gradient = KernelGradient::points_apply_diff1(&pi, &pj, h);
+                }
+            }
+        })
+    }
+}
+
+/// Replaces the kernel gradients of the contacts of the fluids with
+/// `Fluid::kernel_gradient_correction` enabled by their renormalized version `L_i ∇W_ij`.
+///
+/// The correction matrix of each particle is `L_i = (Σ_j V_j ∇W_ij ⊗ (x_j - x_i))⁻¹`, the sum
+/// ranging over its fluid and boundary neighbors. With the corrected gradients, the gradient of
+/// any linear field is estimated exactly, even with an irregular or truncated neighborhood. The
+/// correction matrix is replaced by the identity if it is singular, e.g., for isolated particles.
+/// The gradients of the contacts with boundaries using a density map are left unchanged.
+///
+/// The contacts must have been updated with `update_fluid_contacts`, and the boundary volumes
+/// must have been computed.
+pub fn correct_kernel_gradients(
+    fluid_fluid_contacts: &[ParticlesContacts],
+    fluid_boundary_contacts: &[ParticlesContacts],
+    fluids: &[Fluid],
+    boundaries: &[Boundary],
+    periodic_domain: Option<&PeriodicDomain>,
+) {
+    for fluid_id in 0..fluids.len() {
+        let fluid = &fluids[fluid_id];
+
+        if !fluid.kernel_gradient_correction {
+            continue;
+        }
+
+        par_iter!(fluid.positions).enumerate().for_each(|(i, pi)| {
+            let mut moment = Matrix::zeros();
+            let ff_contacts = fluid_fluid_contacts[fluid_id].particle_contacts(i);
+            let fb_contacts = fluid_boundary_contacts[fluid_id].particle_contacts(i);
+
+            for c in ff_contacts.read().unwrap().iter() {
+                let fluid_j = &fluids[c.j_model];
+                let pj = closest_image(periodic_domain, pi, &fluid_j.positions[c.j]);
+                moment += (c.gradient * fluid_j.volumes[c.j]) * (pj - pi).transpose();
+            }
+
+            for c in fb_contacts.read().unwrap().iter() {
+                let boundary = &boundaries[c.j_model];
+
+                if boundary.density_map().is_none() {
+                    let pj = closest_image(periodic_domain, pi, &boundary.positions[c.j]);
+                    moment += (c.gradient * boundary.volumes[c.j]) * (pj - pi).transpose();
+                }
+            }
+
+            let correction = moment.try_inverse().unwrap_or_else(Matrix::identity);
+
+            for c in ff_contacts.write().unwrap().iter_mut() {
+                c.gradient = correction * c.gradient;
+            }
+
+            for c in fb_contacts.write().unwrap().iter_mut() {
+                if boundaries[c.j_model].density_map().is_none() {
+                    c.gradient = correction * c.gradient;
+                }
             }
         })
     }
@@ -47,15 +216,32 @@ pub fn update_boundary_contacts<KernelDensity: Kernel, KernelGradient: Kernel>(
     kernel_radius: Real,
     boundary_boundary_contacts: &mut [ParticlesContacts],
     boundaries: &[Boundary],
+    periodic_domain: Option<&PeriodicDomain>,
 ) {
     for contacts in boundary_boundary_contacts.iter_mut() {
         par_iter_mut!(contacts.contacts_mut()).for_each(|contacts| {
-            for c in contacts.get_mut().unwrap() {
+            let contacts = contacts.get_mut().unwrap();
+
+            #[cfg(feature = "simd")]
+            if let Some(pi) = contacts
+                .first()
+                .map(|c| boundaries[c.i_model].positions[c.i])
+            {
+                simd::evaluate_kernels::<KernelDensity, KernelGradient>(
+                    &pi,
+                    contacts,
+                    kernel_radius,
+                    |c| closest_image(periodic_domain, &pi, &boundaries[c.j_model].positions[c.j]),
+                );
+            }
+
+            #[cfg(not(feature = "simd"))]
+            for c in contacts {
                 let bound1 = &boundaries[c.i_model];
                 let bound2 = &boundaries[c.j_model];
 
                 let pi = bound1.positions[c.i];
-                let pj = bound2.positions[c.j];
+                let pj = closest_image(periodic_domain, &pi, &bound2.positions[c.j]);
 
                 c.weight = KernelDensity::points_apply(&pi, &pj, kernel_radius);
                 c.gradient = KernelGradient::points_apply_diff1(&pi, &pj, kernel_radius);
@@ -63,3 +249,114 @@ pub fn update_boundary_contacts<KernelDensity: Kernel, KernelGradient: Kernel>(
         })
     }
 }
+
+/// Computes the render velocities of `fluid` by blending each particle velocity with the
+/// kernel-weighted average velocity of its neighbors from the same fluid.
+pub fn smooth_render_velocities(
+    smoothing: Real,
+    fluid_fluid_contacts: &ParticlesContacts,
+    fluid: &mut Fluid,
+) {
+    let velocities = &fluid.velocities;
+    let volumes = &fluid.volumes;
+
+    fluid
+        .render_velocities
+        .resize(velocities.len(), Vector::zeros());
+
+    par_iter_mut!(fluid.render_velocities)
+        .enumerate()
+        .for_each(|(i, render_vel)| {
+            let mut avg_vel = Vector::zeros();
+            let mut total_weight = na::zero::<Real>();
+
+            for c in fluid_fluid_contacts
+                .particle_contacts(i)
+                .read()
+                .unwrap()
+                .iter()
+            {
+                if c.i_model == c.j_model {
+                    let w = c.weight * volumes[c.j];
+                    avg_vel += velocities[c.j] * w;
+                    total_weight += w;
+                }
+            }
+
+            *render_vel = if total_weight > na::zero::<Real>() {
+                velocities[i].lerp(&(avg_vel / total_weight), smoothing)
+            } else {
+                velocities[i]
+            };
+        })
+}
+
+/// Computes which particles of the `fluid_id`-th fluid are located at the free surface.
+///
+/// Neighbors from other fluids and from boundaries complete the neighborhood of a particle, so
+/// the particles at the interface with them are not at the free surface.
+pub fn detect_surface_particles(
+    detection: SurfaceDetection,
+    kernel_radius: Real,
+    contact_manager: &ContactManager,
+    fluid_id: usize,
+    fluids: &[Fluid],
+    boundaries: &[Boundary],
+    surface_particles: &mut Vec<bool>,
+) {
+    let fluid_fluid_contacts = &contact_manager.fluid_fluid_contacts[fluid_id];
+    let fluid_boundary_contacts = &contact_manager.fluid_boundary_contacts[fluid_id];
+    let fluid = &fluids[fluid_id];
+    surface_particles.resize(fluid.num_particles(), false);
+
+    par_iter_mut!(surface_particles)
+        .enumerate()
+        .for_each(|(i, is_at_surface)| {
+            let fluid_contacts = fluid_fluid_contacts.particle_contacts(i).read().unwrap();
+            let boundary_contacts = fluid_boundary_contacts.particle_contacts(i).read().unwrap();
+
+            // Isolated particles only have a contact with themselves.
+            if fluid_contacts.len() + boundary_contacts.len() <= 1 {
+                *is_at_surface = true;
+                return;
+            }
+
+            match detection {
+                SurfaceDetection::ColorFieldGradient { threshold } => {
+                    let mut gradient = Vector::zeros();
+
+                    for c in fluid_contacts.iter() {
+                        gradient += c.gradient * fluids[c.j_model].volumes[c.j];
+                    }
+
+                    for c in boundary_contacts.iter() {
+                        gradient += c.gradient * boundaries[c.j_model].volumes[c.j];
+                    }
+
+                    *is_at_surface = gradient.norm() * kernel_radius > threshold;
+                }
+                SurfaceDetection::CenterOfMassOffset { threshold } => {
+                    let mut weighted_sum = Vector::zeros();
+                    let mut total_weight = na::zero::<Real>();
+
+                    for c in fluid_contacts.iter() {
+                        let fluid_j = &fluids[c.j_model];
+                        let w = c.weight * fluid_j.volumes[c.j];
+                        weighted_sum += fluid_j.positions[c.j].coords * w;
+                        total_weight += w;
+                    }
+
+                    for c in boundary_contacts.iter() {
+                        let boundary_j = &boundaries[c.j_model];
+                        let w = c.weight * boundary_j.volumes[c.j];
+                        weighted_sum += boundary_j.positions[c.j].coords * w;
+                        total_weight += w;
+                    }
+
+                    *is_at_surface = total_weight > na::zero::<Real>()
+                        && (fluid.positions[i].coords - weighted_sum / total_weight).norm()
+                            > threshold * kernel_radius;
+                }
+            }
+        })
+}
@@ -1,14 +1,34 @@
 //! Algorithms for solving pressure, viscosity, surface tension, etc.
 
+pub use self::density::*;
+pub use self::diffuse::*;
+pub use self::diffusion::*;
+pub use self::drag::*;
 pub use self::elasticity::*;
-pub use self::nonpressure_force::NonPressureForce;
+pub use self::granular::*;
+pub use self::guards::*;
+pub use self::heat::*;
+pub use self::nonpressure_force::{NonPressureForce, OtherFluids};
 pub use self::pressure::*;
+pub use self::shifting::*;
 pub use self::surface_tension::*;
+pub use self::tensile::*;
 pub use self::viscosity::*;
+pub use self::vorticity::*;
 
+mod density;
+mod diffuse;
+mod diffusion;
+mod drag;
 mod elasticity;
+mod granular;
+mod guards;
+mod heat;
 pub(crate) mod helper;
 mod nonpressure_force;
 mod pressure;
+mod shifting;
 mod surface_tension;
+mod tensile;
 mod viscosity;
+mod vorticity;
@@ -1,8 +1,72 @@
 use crate::geometry::ParticlesContacts;
-use crate::math::Real;
+use crate::math::{Point, Real, Vector};
 use crate::object::{Boundary, Fluid};
 use crate::TimestepManager;
 
+/// Read-only access to the fluids a non-pressure force is not applied to.
+///
+/// The fluid-fluid contacts of a particle include its neighbors from the other fluids: the
+/// `j_model` of such a contact is the index of the fluid of the neighbor, which can be given to
+/// `Self::fluid` and `Self::densities` to read its positions, velocities, and densities.
+#[derive(Copy, Clone)]
+pub struct OtherFluids<'a> {
+    fluid_id: usize,
+    fluids_before: &'a [Fluid],
+    fluids_after: &'a [Fluid],
+    densities: &'a [Vec<Real>],
+}
+
+impl<'a> OtherFluids<'a> {
+    /// Splits `fluids` into its `fluid_id`-th fluid and the other fluids.
+    ///
+    /// The `densities` are the densities of the particles of each fluid.
+    pub fn split(
+        fluids: &'a mut [Fluid],
+        fluid_id: usize,
+        densities: &'a [Vec<Real>],
+    ) -> (&'a mut Fluid, Self) {
+        let (fluids_before, fluids) = fluids.split_at_mut(fluid_id);
+        let (fluid, fluids_after) = fluids
+            .split_first_mut()
+            .expect("The fluid index is out of bounds.");
+        let others = Self {
+            fluid_id,
+            fluids_before,
+            fluids_after,
+            densities,
+        };
+        (fluid, others)
+    }
+
+    /// The index of the fluid the non-pressure force is applied to.
+    pub fn fluid_id(&self) -> usize {
+        self.fluid_id
+    }
+
+    /// The total number of fluids, including the fluid the non-pressure force is applied to.
+    pub fn num_fluids(&self) -> usize {
+        self.fluids_before.len() + self.fluids_after.len() + 1
+    }
+
+    /// The `model`-th fluid, or `None` if it is the fluid the non-pressure force is applied to.
+    pub fn fluid(&self, model: usize) -> Option<&'a Fluid> {
+        if model < self.fluid_id {
+            self.fluids_before.get(model)
+        } else if model > self.fluid_id {
+            self.fluids_after.get(model - self.fluid_id - 1)
+        } else {
+            None
+        }
+    }
+
+    /// The densities of the particles of the `model`-th fluid.
+    ///
+    /// This includes the fluid the non-pressure force is applied to.
+    pub fn densities(&self, model: usize) -> &'a [Real] {
+        &self.densities[model]
+    }
+}
+
 /// Trait implemented by non-pressure forces.
 ///
 /// This includes all non-pressure forces internal to a same fluid, or acting
@@ -12,6 +76,16 @@ pub trait NonPressureForce: Send + Sync {
     ///
     /// The force application should result in adding accelerations to the
     /// `fluid.accelerations` field.
+    ///
+    /// The velocities of the boundary particles can be read from `Boundary::velocities`. For the
+    /// boundaries to be two-way coupled with the fluid, the reaction of the forces they apply to
+    /// the fluid should be accumulated onto them with `Boundary::apply_force` or
+    /// `Boundary::apply_impulse`. These are thread-safe, and ignored by the boundaries that don't
+    /// receive forces (see `Boundary::receives_forces`).
+    ///
+    /// The contacts with the particles of the other fluids are included in `fluid_fluid_contacts`,
+    /// and the state of these particles can be read from `other_fluids`. The `densities` are the
+    /// densities of the particles of `fluid`.
     fn solve(
         &mut self,
         timestep: &TimestepManager,
@@ -19,12 +93,39 @@ pub trait NonPressureForce: Send + Sync {
         fluid_fluid_contacts: &ParticlesContacts,
         fluid_boundaries_contacts: &ParticlesContacts,
         fluid: &mut Fluid,
+        other_fluids: &OtherFluids,
         boundaries: &[Boundary],
         densities: &[Real],
     );
 
+    /// The largest substep length for which this force remains stable on `fluid`, if any.
+    ///
+    /// The timestep manager limits each substep to the smallest of the limits of all the
+    /// non-pressure forces of all the fluids, in addition to the CFL condition. This is typically
+    /// the diffusion limit of an explicit viscosity or the capillary limit of an explicit surface
+    /// tension. This defaults to `None`, i.e., no limit.
+    fn max_substep(&self, _kernel_radius: Real, _fluid: &Fluid) -> Option<Real> {
+        None
+    }
+
     /// Apply the given permutation to all relevant field of this non-pressure force.
     ///
-    /// This is currently not used so it can be left empty.
+    /// This is called whenever the particles of the fluid are reordered or removed: the
+    /// particle `k` after this call was the particle `permutation[k]` before. When particles
+    /// are removed, the permutation is shorter than the per-particle buffers and only lists
+    /// the remaining particles.
     fn apply_permutation(&mut self, _permutation: &[usize]) {}
+
+    /// Resizes all relevant field of this non-pressure force after particles were added to the fluid.
+    ///
+    /// This is called by `Fluid::add_particles` with the positions and velocities of all the
+    /// particles of the fluid, the last `num_added` of them being the new ones. The other particles
+    /// keep their indices, so their state should be preserved.
+    fn add_particles(
+        &mut self,
+        _num_added: usize,
+        _positions: &[Point<Real>],
+        _velocities: &[Vector<Real>],
+    ) {
+    }
 }
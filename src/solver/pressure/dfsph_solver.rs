@@ -6,14 +6,23 @@ use rayon::prelude::*;
 use num::Zero;
 
 use crate::counters::Counters;
-use crate::geometry::{ContactManager, ParticlesContacts};
+use crate::geometry::{Contact, ContactManager, ParticlesContacts};
 use crate::kernel::{CubicSplineKernel, Kernel};
 use crate::math::{Real, Vector, DIM};
-use crate::object::{Boundary, Fluid};
-use crate::solver::{helper, PressureSolver};
+use crate::object::{Boundary, Fluid, SubstepStage};
+#[cfg(feature = "simd")]
+use crate::simd;
+use crate::solver::density::ShepardFilter;
+use crate::solver::{helper, DensityEstimator, OtherFluids, PressureSolver, SummationDensity};
 use crate::TimestepManager;
 
+use super::divergence_solver::{DivergenceBuffers, DivergenceCriteria};
+
 /// A DFSPH (Divergence Free Smoothed Particle Hydrodynamics) pressure solver.
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
 pub struct DFSPHSolver<
     KernelDensity: Kernel = CubicSplineKernel,
     KernelGradient: Kernel = CubicSplineKernel,
@@ -36,12 +45,19 @@ pub struct DFSPHSolver<
     /// The pressure solver will continue iterating until the divergence error drops bellow this
     /// threshold, or until the maximum number of pressure iterations is reached.
     pub max_divergence_error: Real,
+    /// The method used to estimate the density of the fluid particles.
+    #[cfg_attr(
+        feature = "serde-serialize",
+        serde(skip, default = "crate::solver::density::default_density_estimator")
+    )]
+    pub density_estimator: Box<dyn DensityEstimator>,
     min_neighbors_for_divergence_solve: usize,
-    alphas: Vec<Vec<Real>>,
     densities: Vec<Vec<Real>>,
+    #[cfg_attr(feature = "serde-serialize", serde(skip))]
+    shepard_filter: ShepardFilter,
     predicted_densities: Vec<Vec<Real>>,
-    divergences: Vec<Vec<Real>>,
-    velocity_changes: Vec<Vec<Vector<Real>>>,
+    divergence: DivergenceBuffers,
+    #[cfg_attr(feature = "serde-serialize", serde(skip))]
     phantoms: PhantomData<(KernelDensity, KernelGradient)>,
 }
 
@@ -60,11 +76,11 @@ where
             max_divergence_iter: 50,
             max_divergence_error: na::convert::<_, Real>(0.1),
             min_neighbors_for_divergence_solve: if DIM == 2 { 6 } else { 20 },
-            alphas: Vec::new(),
+            density_estimator: Box::new(SummationDensity::new()),
             densities: Vec::new(),
+            shepard_filter: ShepardFilter::default(),
             predicted_densities: Vec::new(),
-            divergences: Vec::new(),
-            velocity_changes: Vec::new(),
+            divergence: DivergenceBuffers::default(),
             phantoms: PhantomData,
         }
     }
@@ -75,6 +91,11 @@ where
         boundaries: &mut [Boundary],
     ) {
         for boundary_id in 0..boundaries.len() {
+            // The volumes of density map boundaries are already accounted for by the map.
+            if boundaries[boundary_id].density_map().is_some() {
+                continue;
+            }
+
             par_iter_mut!(boundaries[boundary_id].volumes)
                 .enumerate()
                 .for_each(|(i, volume)| {
@@ -89,8 +110,13 @@ where
                         denominator += c.weight;
                     }
 
-                    assert!(!denominator.is_zero());
-                    *volume = na::one::<Real>() / denominator;
+                    // A boundary particle without any neighbor (not even itself, e.g., because
+                    // of a non-finite position) doesn't contribute to the fluid densities.
+                    *volume = if denominator.is_zero() {
+                        na::zero::<Real>()
+                    } else {
+                        na::one::<Real>() / denominator
+                    };
                 })
         }
     }
@@ -103,7 +129,7 @@ where
         fluids: &[Fluid],
         boundaries: &[Boundary],
     ) -> Real {
-        let velocity_changes = &self.velocity_changes;
+        let velocity_changes = &self.divergence.velocity_changes;
         let densities = &self.densities;
         let mut max_error = na::zero::<Real>();
 
@@ -124,7 +150,8 @@ where
                         let vi = fluid_i.velocities[c.i] + velocity_changes[c.i_model][c.i];
                         let vj = fluid_j.velocities[c.j] + velocity_changes[c.j_model][c.j];
 
-                        delta += fluids[c.j_model].particle_mass(c.j) * (vi - vj).dot(&c.gradient);
+                        delta += helper::adapted_mass(fluid_i, c.i, fluid_j, c.j)
+                            * (vi - vj).dot(&c.gradient);
                     }
 
                     for c in fluid_boundary_contacts[fluid_id]
@@ -137,17 +164,16 @@ where
                         let vj = boundaries[c.j_model].velocities[c.j];
 
                         delta += boundaries[c.j_model].volumes[c.j]
-                            * fluid_i.density0
+                            * fluid_i.rest_density(i)
                             * (vi - vj).dot(&c.gradient);
                     }
 
                     *predicted_density = densities[fluid_id][i] + delta * timestep.dt();
-                    assert!(!predicted_density.is_zero());
 
-                    if *predicted_density < fluid_i.density0 {
+                    if *predicted_density < fluid_i.rest_density(i) {
                         na::zero::<Real>()
                     } else {
-                        *predicted_density / fluid_i.density0 - na::one::<Real>()
+                        *predicted_density / fluid_i.rest_density(i) - na::one::<Real>()
                     }
                 });
             let err = par_reduce_sum!(na::zero::<Real>(), it);
@@ -161,60 +187,6 @@ where
         max_error
     }
 
-    // NOTE: this actually computes alpha_i / density_i
-    fn compute_alphas(
-        &mut self,
-        fluid_fluid_contacts: &[ParticlesContacts],
-        fluid_boundary_contacts: &[ParticlesContacts],
-        fluids: &[Fluid],
-        boundaries: &[Boundary],
-    ) {
-        for fluid_id in 0..fluids.len() {
-            let fluid_fluid_contacts = &fluid_fluid_contacts[fluid_id];
-            let fluid_boundary_contacts = &fluid_boundary_contacts[fluid_id];
-            let alphas_i = &mut self.alphas[fluid_id];
-            let fluid_i = &fluids[fluid_id];
-
-            par_iter_mut!(alphas_i)
-                .enumerate()
-                .for_each(|(i, alpha_i)| {
-                    let mut grad_sum = Vector::zeros();
-                    let mut squared_grad_sum = na::zero::<Real>();
-
-                    for c in fluid_fluid_contacts
-                        .particle_contacts(i)
-                        .read()
-                        .unwrap()
-                        .iter()
-                    {
-                        let grad_i = c.gradient * fluids[c.j_model].particle_mass(c.j);
-                        squared_grad_sum += grad_i.norm_squared();
-                        grad_sum += grad_i;
-                    }
-
-                    for c in fluid_boundary_contacts
-                        .particle_contacts(i)
-                        .read()
-                        .unwrap()
-                        .iter()
-                    {
-                        let grad_i =
-                            c.gradient * boundaries[c.j_model].volumes[c.j] * fluid_i.density0;
-                        squared_grad_sum += grad_i.norm_squared();
-                        grad_sum += grad_i;
-                    }
-
-                    let denominator = squared_grad_sum + grad_sum.norm_squared();
-
-                    if denominator <= na::convert::<_, Real>(1.0e-5) {
-                        *alpha_i = na::zero::<Real>();
-                    } else {
-                        *alpha_i = na::one::<Real>() / denominator;
-                    }
-                })
-        }
-    }
-
     fn compute_velocity_changes(
         &mut self,
         timestep: &TimestepManager,
@@ -223,33 +195,47 @@ where
         fluids: &[Fluid],
         boundaries: &[Boundary],
     ) {
-        let alphas = &self.alphas;
+        let alphas = &self.divergence.alphas;
         let predicted_densities = &self.predicted_densities;
 
         for (fluid_id, _fluid1) in fluids.iter().enumerate() {
-            par_iter_mut!(self.velocity_changes[fluid_id])
+            par_iter_mut!(self.divergence.velocity_changes[fluid_id])
                 .enumerate()
                 .for_each(|(i, velocity_change)| {
                     let fluid1 = &fluids[fluid_id];
-                    let ki =
-                        (predicted_densities[fluid_id][i] - fluid1.density0) * alphas[fluid_id][i];
+                    let ki = (predicted_densities[fluid_id][i] - fluid1.rest_density(i))
+                        * alphas[fluid_id][i];
 
-                    for c in fluid_fluid_contacts[fluid_id]
+                    let fluid_contacts = fluid_fluid_contacts[fluid_id]
                         .particle_contacts(i)
                         .read()
-                        .unwrap()
-                        .iter()
-                    {
+                        .unwrap();
+                    let coeff = |c: &Contact| {
                         let fluid2 = &fluids[c.j_model];
 
-                        let kj = (predicted_densities[c.j_model][c.j] - fluid2.density0)
+                        let kj = (predicted_densities[c.j_model][c.j] - fluid2.rest_density(c.j))
                             * alphas[c.j_model][c.j];
 
-                        let kij = ki.max(na::zero::<Real>()) + kj.max(na::zero::<Real>());
+                        ki.max(na::zero::<Real>()) * helper::adapted_mass(fluid1, c.i, fluid2, c.j)
+                            + kj.max(na::zero::<Real>())
+                                * helper::pressure_mass(fluid1, c.i, fluid2, c.j)
+                    };
+
+                    // The coefficients are non-negative, so the contacts with a zero
+                    // coefficient don't contribute to the sum.
+                    #[cfg(feature = "simd")]
+                    {
+                        *velocity_change -= simd::weighted_gradient_sum(&fluid_contacts, |c| {
+                            coeff(c) * timestep.inv_dt()
+                        });
+                    }
+
+                    #[cfg(not(feature = "simd"))]
+                    for c in fluid_contacts.iter() {
+                        let coeff = coeff(c);
 
                         // Compute velocity change.
-                        if kij > na::zero::<Real>() {
-                            let coeff = kij * fluid2.particle_mass(c.j);
+                        if coeff > na::zero::<Real>() {
                             *velocity_change -= c.gradient * (coeff * timestep.inv_dt());
                         }
                     }
@@ -261,7 +247,8 @@ where
                             .unwrap()
                             .iter()
                         {
-                            let coeff = ki * boundaries[c.j_model].volumes[c.j] * fluid1.density0;
+                            let coeff =
+                                ki * boundaries[c.j_model].volumes[c.j] * fluid1.rest_density(i);
                             let delta = c.gradient * (coeff * timestep.inv_dt());
 
                             *velocity_change -= delta;
@@ -276,166 +263,26 @@ where
         }
     }
 
-    fn compute_divergences(
-        &mut self,
-        fluid_fluid_contacts: &[ParticlesContacts],
-        fluid_boundary_contacts: &[ParticlesContacts],
-        fluids: &[Fluid],
-        boundaries: &[Boundary],
-    ) -> Real {
-        let velocity_changes = &self.velocity_changes;
-        let min_neighbors_for_divergence_solve = self.min_neighbors_for_divergence_solve;
-        let mut max_error = na::zero::<Real>();
-
-        for fluid_id in 0..fluids.len() {
-            let fluid_fluid_contacts = &fluid_fluid_contacts[fluid_id];
-            let fluid_boundary_contacts = &fluid_boundary_contacts[fluid_id];
-            let divergences_i = &mut self.divergences[fluid_id];
-            let fluid_i = &fluids[fluid_id];
-
-            let it = par_iter_mut!(divergences_i)
-                .enumerate()
-                .map(|(i, divergence_i)| {
-                    *divergence_i = na::zero::<Real>();
-
-                    if fluid_fluid_contacts
-                        .particle_contacts(i)
-                        .read()
-                        .unwrap()
-                        .len()
-                        + fluid_boundary_contacts
-                            .particle_contacts(i)
-                            .read()
-                            .unwrap()
-                            .len()
-                        < min_neighbors_for_divergence_solve
-                    {
-                        return na::zero::<Real>();
-                    }
-
-                    for c in fluid_fluid_contacts
-                        .particle_contacts(i)
-                        .read()
-                        .unwrap()
-                        .iter()
-                    {
-                        let fluid_j = &fluids[c.j_model];
-                        let v_i = fluid_i.velocities[c.i] + velocity_changes[c.i_model][c.i];
-                        let v_j = fluid_j.velocities[c.j] + velocity_changes[c.j_model][c.j];
-                        let dvel = v_i - v_j;
-                        *divergence_i += dvel.dot(&c.gradient) * fluid_j.particle_mass(c.j);
-                    }
-
-                    for c in fluid_boundary_contacts
-                        .particle_contacts(i)
-                        .read()
-                        .unwrap()
-                        .iter()
-                    {
-                        let v_i = fluid_i.velocities[c.i] + velocity_changes[c.i_model][c.i];
-                        // FIXME: take the velocity of j too?
-
-                        let dvel = v_i;
-                        *divergence_i += dvel.dot(&c.gradient)
-                            * boundaries[c.j_model].volumes[c.j]
-                            * fluid_i.density0;
-                    }
-
-                    *divergence_i = divergence_i.max(na::zero::<Real>());
-                    *divergence_i / fluid_i.density0
-                });
-            let err = par_reduce_sum!(na::zero::<Real>(), it);
-
-            let nparts = fluids[fluid_id].num_particles();
-            if nparts != 0 {
-                max_error = max_error.max(err / na::convert::<_, Real>(nparts as f64));
-            }
-        }
-
-        max_error
-    }
-
-    fn compute_velocity_changes_for_divergence(
-        &mut self,
-        timestep: &TimestepManager,
-        fluid_fluid_contacts: &[ParticlesContacts],
-        fluid_boundary_contacts: &[ParticlesContacts],
-        fluids: &[Fluid],
-        boundaries: &[Boundary],
-    ) {
-        let alphas = &self.alphas;
-        let divergences = &self.divergences;
-
-        for (fluid_id, _fluid1) in fluids.iter().enumerate() {
-            par_iter_mut!(self.velocity_changes[fluid_id])
-                .enumerate()
-                .for_each(|(i, velocity_change)| {
-                    let fluid1 = &fluids[fluid_id];
-                    let ki = divergences[fluid_id][i] * alphas[fluid_id][i];
-
-                    for c in fluid_fluid_contacts[fluid_id]
-                        .particle_contacts(i)
-                        .read()
-                        .unwrap()
-                        .iter()
-                    {
-                        let fluid2 = &fluids[c.j_model];
-                        let kj = divergences[c.j_model][c.j] * alphas[c.j_model][c.j];
-
-                        // Compute velocity change.
-                        let coeff = -(ki + kj) * fluid2.particle_mass(c.j);
-                        *velocity_change += c.gradient * coeff;
-                    }
-
-                    for c in fluid_boundary_contacts[fluid_id]
-                        .particle_contacts(i)
-                        .read()
-                        .unwrap()
-                        .iter()
-                    {
-                        let boundary2 = &boundaries[c.j_model];
-
-                        // Compute velocity change.
-                        let coeff = -ki * boundaries[c.j_model].volumes[c.j] * fluid1.density0;
-                        let delta = c.gradient * coeff;
-                        *velocity_change += delta;
-
-                        // Apply the force to the boundary too.
-                        let particle_mass = fluid1.particle_mass(c.i);
-                        boundary2.apply_force(c.j, delta * (-timestep.inv_dt() * particle_mass));
-                    }
-                })
-        }
-    }
-
     fn update_positions(&mut self, timestep: &TimestepManager, fluids: &mut [Fluid]) {
-        for (fluid, velocity_changes) in fluids.iter_mut().zip(self.velocity_changes.iter()) {
+        for fluid in fluids.iter_mut() {
             par_iter_mut!(fluid.positions)
                 .zip(par_iter!(fluid.velocities))
-                .zip(par_iter!(velocity_changes))
-                .for_each(|((pos, vel), delta)| {
-                    *pos += (*vel + delta) * timestep.dt();
-                })
-        }
-    }
-
-    fn update_velocities(&mut self, fluids: &mut [Fluid]) {
-        for (fluid, delta) in fluids.iter_mut().zip(self.velocity_changes.iter()) {
-            par_iter_mut!(fluid.velocities)
-                .zip(par_iter!(delta))
-                .for_each(|(vel, delta)| {
-                    *vel += delta;
+                .for_each(|(pos, vel)| {
+                    *pos += *vel * timestep.dt();
                 })
         }
     }
 
     fn pressure_solve(
         &mut self,
+        counters: &mut Counters,
         timestep: &TimestepManager,
         contact_manager: &mut ContactManager,
         fluids: &mut [Fluid],
         boundaries: &[Boundary],
     ) {
+        let mut converged = false;
+
         for i in 0..self.max_pressure_iter {
             let avg_err = self.compute_predicted_densities(
                 timestep,
@@ -445,11 +292,17 @@ where
                 boundaries,
             );
 
+            counters.solver.num_pressure_iterations += 1;
+            counters.solver.density_error = avg_err;
+
             if avg_err <= self.max_density_error && i >= self.min_pressure_iter {
-                //                println!(
-                //                    "Average density error: {}, break after niters: {}",
-                //                    avg_err, i
-                //                );
+                #[cfg(feature = "log")]
+                log::debug!(
+                    "Average density error: {}, break after niters: {}",
+                    avg_err,
+                    i
+                );
+                converged = true;
                 break;
             }
 
@@ -461,44 +314,9 @@ where
                 boundaries,
             );
         }
-    }
-
-    fn divergence_solve(
-        &mut self,
-        counters: &mut Counters,
-        timestep: &TimestepManager,
-        contact_manager: &mut ContactManager,
-        fluids: &mut [Fluid],
-        boundaries: &[Boundary],
-    ) {
-        for i in 0..self.max_divergence_iter {
-            let avg_err = self.compute_divergences(
-                &contact_manager.fluid_fluid_contacts,
-                &contact_manager.fluid_boundary_contacts,
-                fluids,
-                boundaries,
-            );
-
-            let max_err =
-                self.max_divergence_error * timestep.inv_dt() * na::convert::<_, Real>(0.01);
-            if avg_err <= max_err && i >= self.min_divergence_iter {
-                //                println!(
-                //                    "Average divergence error: {} <= {}, break after niters: {}",
-                //                    avg_err, max_err, i
-                //                );
-                break;
-            }
 
-            counters.custom.resume();
-
-            self.compute_velocity_changes_for_divergence(
-                timestep,
-                &contact_manager.fluid_fluid_contacts,
-                &contact_manager.fluid_boundary_contacts,
-                fluids,
-                boundaries,
-            );
-            counters.custom.pause();
+        if !converged {
+            counters.solver.num_unconverged_pressure_solves += 1;
         }
     }
 
@@ -507,7 +325,12 @@ where
         timestep: &TimestepManager,
         fluids: &mut [Fluid],
     ) {
-        for (velocity_changes, fluid) in self.velocity_changes.iter_mut().zip(fluids.iter_mut()) {
+        for (velocity_changes, fluid) in self
+            .divergence
+            .velocity_changes
+            .iter_mut()
+            .zip(fluids.iter_mut())
+        {
             par_iter_mut!(velocity_changes)
                 .zip(par_iter_mut!(fluid.accelerations))
                 .for_each(|(velocity_change, acceleration)| {
@@ -520,42 +343,29 @@ where
 
 impl<KernelDensity, KernelGradient> PressureSolver for DFSPHSolver<KernelDensity, KernelGradient>
 where
-    KernelDensity: Kernel,
-    KernelGradient: Kernel,
+    KernelDensity: Kernel + 'static,
+    KernelGradient: Kernel + 'static,
 {
     fn init_with_fluids(&mut self, fluids: &[Fluid]) {
         // Resize every buffer.
-        self.alphas.resize(fluids.len(), Vec::new());
         self.densities.resize(fluids.len(), Vec::new());
         self.predicted_densities.resize(fluids.len(), Vec::new());
-        self.divergences.resize(fluids.len(), Vec::new());
-        self.velocity_changes.resize(fluids.len(), Vec::new());
-
-        for (fluid, alphas, densities, predicted_densities, divergences, velocity_changes) in
-            itertools::multizip((
-                fluids.iter(),
-                self.alphas.iter_mut(),
-                self.densities.iter_mut(),
-                self.predicted_densities.iter_mut(),
-                self.divergences.iter_mut(),
-                self.velocity_changes.iter_mut(),
-            ))
-        {
-            alphas.resize(fluid.num_particles(), na::zero::<Real>());
+        self.divergence.resize(fluids);
+
+        for (fluid, densities, predicted_densities) in itertools::multizip((
+            fluids.iter(),
+            self.densities.iter_mut(),
+            self.predicted_densities.iter_mut(),
+        )) {
             densities.resize(fluid.num_particles(), na::zero::<Real>());
             predicted_densities.resize(fluid.num_particles(), na::zero::<Real>());
-            divergences.resize(fluid.num_particles(), na::zero::<Real>());
-            velocity_changes.resize(fluid.num_particles(), Vector::zeros());
 
             if fluid.num_deleted_particles() != 0 {
-                crate::helper::filter_from_mask(fluid.deleted_particles_mask(), alphas);
                 crate::helper::filter_from_mask(fluid.deleted_particles_mask(), densities);
                 crate::helper::filter_from_mask(
                     fluid.deleted_particles_mask(),
                     predicted_densities,
                 );
-                crate::helper::filter_from_mask(fluid.deleted_particles_mask(), divergences);
-                crate::helper::filter_from_mask(fluid.deleted_particles_mask(), velocity_changes);
             }
         }
     }
@@ -572,30 +382,26 @@ where
         boundaries: &[Boundary],
     ) {
         for fluid in fluids.iter_mut() {
+            let gravity = fluid.effective_gravity(gravity);
             par_iter_mut!(fluid.accelerations).for_each(|acceleration| {
                 *acceleration += gravity;
             })
         }
 
-        for (fluid, fluid_fluid_contacts, fluid_boundary_contacts, densities) in
-            itertools::multizip((
-                &mut *fluids,
-                &contact_manager.fluid_fluid_contacts,
-                &contact_manager.fluid_boundary_contacts,
-                &self.densities,
-            ))
-        {
+        for fluid_id in 0..fluids.len() {
+            let (fluid, other_fluids) = OtherFluids::split(fluids, fluid_id, &self.densities);
             let mut forces = std::mem::replace(&mut fluid.nonpressure_forces, Vec::new());
 
             for np_force in &mut forces {
                 np_force.solve(
                     timestep,
                     kernel_radius,
-                    fluid_fluid_contacts,
-                    fluid_boundary_contacts,
+                    &contact_manager.fluid_fluid_contacts[fluid_id],
+                    &contact_manager.fluid_boundary_contacts[fluid_id],
                     fluid,
+                    &other_fluids,
                     boundaries,
-                    densities,
+                    &self.densities[fluid_id],
                 );
             }
 
@@ -610,18 +416,22 @@ where
         fluids: &[Fluid],
         boundaries: &[Boundary],
     ) {
+        let periodic_domain = contact_manager.periodic_domain().copied();
+
         helper::update_fluid_contacts::<KernelDensity, KernelGradient>(
             kernel_radius,
             &mut contact_manager.fluid_fluid_contacts,
             &mut contact_manager.fluid_boundary_contacts,
             fluids,
             boundaries,
+            periodic_domain.as_ref(),
         );
 
         helper::update_boundary_contacts::<KernelDensity, KernelGradient>(
             kernel_radius,
             &mut contact_manager.boundary_boundary_contacts,
             boundaries,
+            periodic_domain.as_ref(),
         );
     }
 
@@ -632,36 +442,22 @@ where
         boundaries: &mut [Boundary],
     ) {
         self.compute_boundary_volumes(&contact_manager.boundary_boundary_contacts, boundaries);
-
-        for fluid_id in 0..fluids.len() {
-            par_iter_mut!(self.densities[fluid_id])
-                .enumerate()
-                .for_each(|(i, density)| {
-                    *density = na::zero::<Real>();
-
-                    for c in contact_manager.fluid_fluid_contacts[fluid_id]
-                        .particle_contacts(i)
-                        .read()
-                        .unwrap()
-                        .iter()
-                    {
-                        *density += fluids[c.j_model].particle_mass(c.j) * c.weight;
-                    }
-
-                    for c in contact_manager.fluid_boundary_contacts[fluid_id]
-                        .particle_contacts(i)
-                        .read()
-                        .unwrap()
-                        .iter()
-                    {
-                        *density += boundaries[c.j_model].volumes[c.j]
-                            * fluids[c.i_model].density0
-                            * c.weight;
-                    }
-
-                    assert!(!density.is_zero());
-                })
-        }
+        helper::correct_kernel_gradients(
+            &contact_manager.fluid_fluid_contacts,
+            &contact_manager.fluid_boundary_contacts,
+            fluids,
+            boundaries,
+            contact_manager.periodic_domain(),
+        );
+        self.density_estimator.compute_densities(
+            &contact_manager.fluid_fluid_contacts,
+            &contact_manager.fluid_boundary_contacts,
+            fluids,
+            boundaries,
+            &mut self.densities,
+        );
+        self.shepard_filter
+            .apply(contact_manager, fluids, boundaries, &mut self.densities);
     }
 
     fn step(
@@ -673,23 +469,30 @@ where
         kernel_radius: Real,
         fluids: &mut [Fluid],
         boundaries: &[Boundary],
+        hooks: &mut dyn FnMut(SubstepStage, &TimestepManager, &mut [Fluid]),
     ) {
-        counters.solver.pressure_resolution_time.resume();
-
-        self.compute_alphas(
-            &contact_manager.fluid_fluid_contacts,
-            &contact_manager.fluid_boundary_contacts,
+        counters.solver.divergence_resolution_time.resume();
+
+        let criteria = DivergenceCriteria {
+            min_iter: self.min_divergence_iter,
+            max_iter: self.max_divergence_iter,
+            max_error: self.max_divergence_error,
+            min_neighbors: self.min_neighbors_for_divergence_solve,
+        };
+        self.divergence
+            .compute_alphas(contact_manager, fluids, boundaries);
+        self.divergence.solve(
+            &criteria,
+            counters,
+            timestep,
+            contact_manager,
             fluids,
             boundaries,
         );
+        self.divergence.apply_velocity_changes(fluids);
 
-        self.divergence_solve(counters, timestep, contact_manager, fluids, boundaries);
-
-        self.update_velocities(fluids);
-        self.velocity_changes
-            .iter_mut()
-            .for_each(|vs| vs.iter_mut().for_each(|v| v.fill(na::zero::<Real>())));
-
+        counters.solver.divergence_resolution_time.pause();
+        counters.solver.non_pressure_resolution_time.resume();
         self.predict_advection(
             timestep,
             kernel_radius,
@@ -698,12 +501,29 @@ where
             fluids,
             boundaries,
         );
+        counters.solver.non_pressure_resolution_time.pause();
+        hooks(SubstepStage::AfterAdvectionPrediction, timestep, fluids);
 
+        counters.solver.integration_time.resume();
         timestep.advance(fluids);
-
         self.integrate_and_clear_accelerations(timestep, fluids);
-        self.pressure_solve(timestep, contact_manager, fluids, boundaries);
-        self.update_positions(timestep, fluids);
+        counters.solver.integration_time.pause();
+
+        counters.solver.pressure_resolution_time.resume();
+        self.pressure_solve(counters, timestep, contact_manager, fluids, boundaries);
+
+        // Apply the velocity changes right away so the velocities of the fluids are up-to-date
+        // at the end of the substep.
+        self.divergence.apply_velocity_changes(fluids);
         counters.solver.pressure_resolution_time.pause();
+
+        hooks(SubstepStage::AfterPressureSolve, timestep, fluids);
+        counters.solver.integration_time.resume();
+        self.update_positions(timestep, fluids);
+        counters.solver.integration_time.pause();
+    }
+
+    fn densities(&self) -> &[Vec<Real>] {
+        &self.densities
     }
 }
@@ -0,0 +1,375 @@
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use crate::counters::Counters;
+use crate::geometry::ContactManager;
+use crate::math::{Real, Vector, DIM};
+use crate::object::{Boundary, Fluid};
+use crate::solver::helper;
+use crate::TimestepManager;
+
+/// A solver that corrects the fluid velocities so that their divergence becomes zero.
+///
+/// This is the divergence-free stage of the DFSPH method. It can be enabled on the `LiquidWorld`
+/// with `LiquidWorld::set_divergence_solver` to reduce the volume drift of pressure solvers that
+/// only enforce a constant density, like IISPH or WCSPH. The `DFSPHSolver` already performs this
+/// stage and does not need it.
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub struct DivergenceSolver {
+    /// Minimum number of iterations that must be executed for divergence resolution.
+    pub min_divergence_iter: usize,
+    /// Maximum number of iterations that must be executed for divergence resolution.
+    pub max_divergence_iter: usize,
+    /// Maximum acceptable divergence error (in percents).
+    ///
+    /// The solver will continue iterating until the divergence error drops bellow this
+    /// threshold, or until the maximum number of divergence iterations is reached.
+    pub max_divergence_error: Real,
+    /// Particles with less neighbors than this are ignored by the divergence resolution.
+    ///
+    /// This avoids velocity artifacts for isolated particles and particles at the free surface.
+    pub min_neighbors_for_divergence_solve: usize,
+    buffers: DivergenceBuffers,
+}
+
+impl DivergenceSolver {
+    /// Initialize a new divergence solver.
+    pub fn new() -> Self {
+        Self {
+            min_divergence_iter: 1,
+            max_divergence_iter: 50,
+            max_divergence_error: na::convert::<_, Real>(0.1),
+            min_neighbors_for_divergence_solve: if DIM == 2 { 6 } else { 20 },
+            buffers: DivergenceBuffers::default(),
+        }
+    }
+
+    /// Corrects the velocities of the fluid particles so that their divergence becomes zero.
+    ///
+    /// The contacts of `contact_manager` must be up-to-date with the positions of the particles.
+    pub fn solve(
+        &mut self,
+        counters: &mut Counters,
+        timestep: &TimestepManager,
+        contact_manager: &ContactManager,
+        fluids: &mut [Fluid],
+        boundaries: &[Boundary],
+    ) {
+        let criteria = DivergenceCriteria {
+            min_iter: self.min_divergence_iter,
+            max_iter: self.max_divergence_iter,
+            max_error: self.max_divergence_error,
+            min_neighbors: self.min_neighbors_for_divergence_solve,
+        };
+
+        self.buffers.resize(fluids);
+        self.buffers
+            .compute_alphas(contact_manager, fluids, boundaries);
+        self.buffers.solve(
+            &criteria,
+            counters,
+            timestep,
+            contact_manager,
+            fluids,
+            boundaries,
+        );
+        self.buffers.apply_velocity_changes(fluids);
+    }
+}
+
+impl Default for DivergenceSolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// The convergence criteria of a divergence-free solve.
+pub(super) struct DivergenceCriteria {
+    pub min_iter: usize,
+    pub max_iter: usize,
+    // In percents.
+    pub max_error: Real,
+    pub min_neighbors: usize,
+}
+
+// The per-particle buffers of the divergence-free solve, shared by the `DivergenceSolver` and the
+// `DFSPHSolver`. The velocity changes are also used by the density-invariant solve of DFSPH.
+#[derive(Clone, Default)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub(super) struct DivergenceBuffers {
+    pub alphas: Vec<Vec<Real>>,
+    pub divergences: Vec<Vec<Real>>,
+    pub velocity_changes: Vec<Vec<Vector<Real>>>,
+}
+
+impl DivergenceBuffers {
+    pub fn resize(&mut self, fluids: &[Fluid]) {
+        self.alphas.resize(fluids.len(), Vec::new());
+        self.divergences.resize(fluids.len(), Vec::new());
+        self.velocity_changes.resize(fluids.len(), Vec::new());
+
+        for (fluid, alphas, divergences, velocity_changes) in itertools::multizip((
+            fluids.iter(),
+            self.alphas.iter_mut(),
+            self.divergences.iter_mut(),
+            self.velocity_changes.iter_mut(),
+        )) {
+            alphas.resize(fluid.num_particles(), na::zero::<Real>());
+            divergences.resize(fluid.num_particles(), na::zero::<Real>());
+            velocity_changes.resize(fluid.num_particles(), Vector::zeros());
+
+            if fluid.num_deleted_particles() != 0 {
+                crate::helper::filter_from_mask(fluid.deleted_particles_mask(), alphas);
+                crate::helper::filter_from_mask(fluid.deleted_particles_mask(), divergences);
+                crate::helper::filter_from_mask(fluid.deleted_particles_mask(), velocity_changes);
+            }
+        }
+    }
+
+    // NOTE: this actually computes alpha_i / density_i
+    pub fn compute_alphas(
+        &mut self,
+        contact_manager: &ContactManager,
+        fluids: &[Fluid],
+        boundaries: &[Boundary],
+    ) {
+        for fluid_id in 0..fluids.len() {
+            let fluid_fluid_contacts = &contact_manager.fluid_fluid_contacts[fluid_id];
+            let fluid_boundary_contacts = &contact_manager.fluid_boundary_contacts[fluid_id];
+            let fluid_i = &fluids[fluid_id];
+
+            par_iter_mut!(self.alphas[fluid_id])
+                .enumerate()
+                .for_each(|(i, alpha_i)| {
+                    let mut grad_sum = Vector::zeros();
+                    let mut squared_grad_sum = na::zero::<Real>();
+
+                    for c in fluid_fluid_contacts
+                        .particle_contacts(i)
+                        .read()
+                        .unwrap()
+                        .iter()
+                    {
+                        let fluid_j = &fluids[c.j_model];
+                        let grad_i = c.gradient * helper::adapted_mass(fluid_i, c.i, fluid_j, c.j);
+                        let grad_j = c.gradient * helper::pressure_mass(fluid_j, c.j, fluid_i, c.i);
+                        squared_grad_sum += grad_i.dot(&grad_j);
+                        grad_sum += grad_i;
+                    }
+
+                    for c in fluid_boundary_contacts
+                        .particle_contacts(i)
+                        .read()
+                        .unwrap()
+                        .iter()
+                    {
+                        let grad_i = c.gradient
+                            * boundaries[c.j_model].volumes[c.j]
+                            * fluid_i.rest_density(i);
+                        // A density map boundary contributes a single virtual particle, which
+                        // stands for a whole neighborhood of boundary particles.
+                        if boundaries[c.j_model].density_map().is_none() {
+                            squared_grad_sum += grad_i.norm_squared();
+                        }
+                        grad_sum += grad_i;
+                    }
+
+                    let denominator = squared_grad_sum + grad_sum.norm_squared();
+
+                    if denominator <= na::convert::<_, Real>(1.0e-5) {
+                        *alpha_i = na::zero::<Real>();
+                    } else {
+                        *alpha_i = na::one::<Real>() / denominator;
+                    }
+                })
+        }
+    }
+
+    // Returns the average divergence error.
+    fn compute_divergences(
+        &mut self,
+        min_neighbors: usize,
+        contact_manager: &ContactManager,
+        fluids: &[Fluid],
+        boundaries: &[Boundary],
+    ) -> Real {
+        let velocity_changes = &self.velocity_changes;
+        let mut max_error = na::zero::<Real>();
+
+        for fluid_id in 0..fluids.len() {
+            let fluid_fluid_contacts = &contact_manager.fluid_fluid_contacts[fluid_id];
+            let fluid_boundary_contacts = &contact_manager.fluid_boundary_contacts[fluid_id];
+            let fluid_i = &fluids[fluid_id];
+
+            let it =
+                par_iter_mut!(self.divergences[fluid_id])
+                    .enumerate()
+                    .map(|(i, divergence_i)| {
+                        *divergence_i = na::zero::<Real>();
+
+                        if fluid_fluid_contacts
+                            .particle_contacts(i)
+                            .read()
+                            .unwrap()
+                            .len()
+                            + fluid_boundary_contacts
+                                .particle_contacts(i)
+                                .read()
+                                .unwrap()
+                                .len()
+                            < min_neighbors
+                        {
+                            return na::zero::<Real>();
+                        }
+
+                        for c in fluid_fluid_contacts
+                            .particle_contacts(i)
+                            .read()
+                            .unwrap()
+                            .iter()
+                        {
+                            let fluid_j = &fluids[c.j_model];
+                            let v_i = fluid_i.velocities[c.i] + velocity_changes[c.i_model][c.i];
+                            let v_j = fluid_j.velocities[c.j] + velocity_changes[c.j_model][c.j];
+                            let dvel = v_i - v_j;
+                            *divergence_i += dvel.dot(&c.gradient)
+                                * helper::adapted_mass(fluid_i, c.i, fluid_j, c.j);
+                        }
+
+                        for c in fluid_boundary_contacts
+                            .particle_contacts(i)
+                            .read()
+                            .unwrap()
+                            .iter()
+                        {
+                            let boundary_j = &boundaries[c.j_model];
+                            let v_i = fluid_i.velocities[c.i] + velocity_changes[c.i_model][c.i];
+                            let dvel = v_i - boundary_j.velocities[c.j];
+                            *divergence_i += dvel.dot(&c.gradient)
+                                * boundary_j.volumes[c.j]
+                                * fluid_i.rest_density(i);
+                        }
+
+                        *divergence_i = divergence_i.max(na::zero::<Real>());
+                        *divergence_i / fluid_i.rest_density(i)
+                    });
+            let err = par_reduce_sum!(na::zero::<Real>(), it);
+
+            let nparts = fluids[fluid_id].num_particles();
+            if nparts != 0 {
+                max_error = max_error.max(err / na::convert::<_, Real>(nparts as f64));
+            }
+        }
+
+        max_error
+    }
+
+    fn compute_velocity_changes(
+        &mut self,
+        timestep: &TimestepManager,
+        contact_manager: &ContactManager,
+        fluids: &[Fluid],
+        boundaries: &[Boundary],
+    ) {
+        let alphas = &self.alphas;
+        let divergences = &self.divergences;
+
+        for fluid_id in 0..fluids.len() {
+            par_iter_mut!(self.velocity_changes[fluid_id])
+                .enumerate()
+                .for_each(|(i, velocity_change)| {
+                    let fluid1 = &fluids[fluid_id];
+                    let ki = divergences[fluid_id][i] * alphas[fluid_id][i];
+
+                    for c in contact_manager.fluid_fluid_contacts[fluid_id]
+                        .particle_contacts(i)
+                        .read()
+                        .unwrap()
+                        .iter()
+                    {
+                        let fluid2 = &fluids[c.j_model];
+                        let kj = divergences[c.j_model][c.j] * alphas[c.j_model][c.j];
+
+                        let coeff = -(ki * helper::adapted_mass(fluid1, c.i, fluid2, c.j)
+                            + kj * helper::pressure_mass(fluid1, c.i, fluid2, c.j));
+                        *velocity_change += c.gradient * coeff;
+                    }
+
+                    for c in contact_manager.fluid_boundary_contacts[fluid_id]
+                        .particle_contacts(i)
+                        .read()
+                        .unwrap()
+                        .iter()
+                    {
+                        let boundary2 = &boundaries[c.j_model];
+
+                        let coeff = -ki * boundary2.volumes[c.j] * fluid1.rest_density(i);
+                        let delta = c.gradient * coeff;
+                        *velocity_change += delta;
+
+                        // Apply the force to the boundary too.
+                        let particle_mass = fluid1.particle_mass(c.i);
+                        boundary2.apply_force(c.j, delta * (-timestep.inv_dt() * particle_mass));
+                    }
+                })
+        }
+    }
+
+    // Accumulates into the velocity changes the corrections cancelling the divergence of the
+    // velocity field. The alphas must have been computed first.
+    pub fn solve(
+        &mut self,
+        criteria: &DivergenceCriteria,
+        counters: &mut Counters,
+        timestep: &TimestepManager,
+        contact_manager: &ContactManager,
+        fluids: &[Fluid],
+        boundaries: &[Boundary],
+    ) {
+        for i in 0..criteria.max_iter {
+            let avg_err = self.compute_divergences(
+                criteria.min_neighbors,
+                contact_manager,
+                fluids,
+                boundaries,
+            );
+
+            let max_err = criteria.max_error * timestep.inv_dt() * na::convert::<_, Real>(0.01);
+            counters.solver.num_divergence_iterations += 1;
+            counters.solver.divergence_error = avg_err;
+
+            if avg_err <= max_err && i >= criteria.min_iter {
+                #[cfg(feature = "log")]
+                log::debug!(
+                    "Average divergence error: {} <= {}, break after niters: {}",
+                    avg_err,
+                    max_err,
+                    i
+                );
+                break;
+            }
+
+            counters.custom.resume();
+            self.compute_velocity_changes(timestep, contact_manager, fluids, boundaries);
+            counters.custom.pause();
+        }
+    }
+
+    // Adds the velocity changes to the velocities of the fluids, and resets them to zero.
+    pub fn apply_velocity_changes(&mut self, fluids: &mut [Fluid]) {
+        for (fluid, velocity_changes) in fluids.iter_mut().zip(self.velocity_changes.iter_mut()) {
+            par_iter_mut!(fluid.velocities)
+                .zip(par_iter_mut!(velocity_changes))
+                .for_each(|(vel, delta)| {
+                    *vel += *delta;
+                    delta.fill(na::zero::<Real>());
+                })
+        }
+    }
+}
@@ -9,11 +9,16 @@ use crate::counters::Counters;
 use crate::geometry::{ContactManager, ParticlesContacts};
 use crate::kernel::{CubicSplineKernel, Kernel};
 use crate::math::{Real, Vector};
-use crate::object::{Boundary, Fluid};
-use crate::solver::{helper, PressureSolver};
+use crate::object::{Boundary, Fluid, SubstepStage};
+use crate::solver::density::ShepardFilter;
+use crate::solver::{helper, DensityEstimator, OtherFluids, PressureSolver, SummationDensity};
 use crate::TimestepManager;
 
 /// A IISPH (Implicit Incompressible Smoothed Particle Hydrodynamics) pressure solver.
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
 pub struct IISPHSolver<
     KernelDensity: Kernel = CubicSplineKernel,
     KernelGradient: Kernel = CubicSplineKernel,
@@ -27,8 +32,22 @@ pub struct IISPHSolver<
     /// The pressure solver will continue iterating until the density error drops bellow this
     /// threshold, or until the maximum number of pressure iterations is reached.
     pub max_density_error: Real,
+    /// The method used to estimate the density of the fluid particles.
+    #[cfg_attr(
+        feature = "serde-serialize",
+        serde(skip, default = "crate::solver::density::default_density_estimator")
+    )]
+    pub density_estimator: Box<dyn DensityEstimator>,
+    /// The factor applied to the pressures of the previous substep to initialize the pressure resolution.
+    ///
+    /// Warm-starting with the previous pressures reduces the number of iterations needed to converge.
+    /// Set this to `0.0` to disable warm-starting.
+    pub warm_start_factor: Real,
     omega: Real,
+    num_pressure_iter: usize,
     densities: Vec<Vec<Real>>,
+    #[cfg_attr(feature = "serde-serialize", serde(skip))]
+    shepard_filter: ShepardFilter,
     aii: Vec<Vec<Real>>,
     dii: Vec<Vec<Vector<Real>>>,
     dij_pjl: Vec<Vec<Vector<Real>>>,
@@ -36,6 +55,7 @@ pub struct IISPHSolver<
     next_pressures: Vec<Vec<Real>>,
     predicted_densities: Vec<Vec<Real>>,
     velocity_changes: Vec<Vec<Vector<Real>>>,
+    #[cfg_attr(feature = "serde-serialize", serde(skip))]
     phantoms: PhantomData<(KernelDensity, KernelGradient)>,
 }
 
@@ -51,7 +71,11 @@ where
             max_pressure_iter: 50,
             max_density_error: na::convert::<_, Real>(0.05),
             omega: na::convert::<_, Real>(0.5),
+            num_pressure_iter: 0,
+            density_estimator: Box::new(SummationDensity::new()),
+            warm_start_factor: na::convert::<_, Real>(0.5),
             densities: Vec::new(),
+            shepard_filter: ShepardFilter::default(),
             dii: Vec::new(),
             aii: Vec::new(),
             dij_pjl: Vec::new(),
@@ -63,12 +87,73 @@ where
         }
     }
 
+    /// Sets the minimum and maximum number of iterations executed for pressure resolution.
+    pub fn with_pressure_iterations(
+        mut self,
+        min_pressure_iter: usize,
+        max_pressure_iter: usize,
+    ) -> Self {
+        assert!(
+            min_pressure_iter <= max_pressure_iter,
+            "The minimum number of pressure iterations must not exceed the maximum."
+        );
+        self.min_pressure_iter = min_pressure_iter;
+        self.max_pressure_iter = max_pressure_iter;
+        self
+    }
+
+    /// Sets the maximum acceptable density error (in percents).
+    pub fn with_max_density_error(mut self, max_density_error: Real) -> Self {
+        self.max_density_error = max_density_error;
+        self
+    }
+
+    /// Sets the factor applied to the previous pressures for warm-starting.
+    ///
+    /// Set this to `0.0` to disable warm-starting.
+    pub fn with_warm_start_factor(mut self, warm_start_factor: Real) -> Self {
+        self.warm_start_factor = warm_start_factor;
+        self
+    }
+
+    /// Sets the relaxation factor of the relaxed Jacobi iterations.
+    pub fn with_omega(mut self, omega: Real) -> Self {
+        self.set_omega(omega);
+        self
+    }
+
+    /// The relaxation factor of the relaxed Jacobi iterations.
+    pub fn omega(&self) -> Real {
+        self.omega
+    }
+
+    /// Sets the relaxation factor of the relaxed Jacobi iterations.
+    ///
+    /// This must be in `]0.0, 1.0]`. Smaller values converge more slowly but are more stable.
+    pub fn set_omega(&mut self, omega: Real) {
+        assert!(
+            omega > na::zero::<Real>() && omega <= na::one::<Real>(),
+            "The relaxation factor must be in ]0.0, 1.0]."
+        );
+        self.omega = omega;
+    }
+
+    /// The number of pressure iterations executed during the last substep.
+    pub fn num_pressure_iterations(&self) -> usize {
+        self.num_pressure_iter
+    }
+
     fn compute_boundary_volumes(
         &mut self,
         boundary_boundary_contacts: &[ParticlesContacts],
         boundaries: &mut [Boundary],
     ) {
         for boundary_id in 0..boundaries.len() {
+            // The volumes of density map boundaries are already accounted for by the map.
+            if boundaries[boundary_id].density_map().is_some() {
+                continue;
+            }
+
             par_iter_mut!(boundaries[boundary_id].volumes)
                 .enumerate()
                 .for_each(|(i, volume)| {
@@ -83,8 +168,13 @@ where
                         denominator += c.weight;
                     }
 
-                    assert!(!denominator.is_zero());
-                    *volume = na::one::<Real>() / denominator;
+                    // A boundary particle without any neighbor (not even itself, e.g., because
+                    // of a non-finite position) doesn't contribute to the fluid densities.
+                    *volume = if denominator.is_zero() {
+                        na::zero::<Real>()
+                    } else {
+                        na::one::<Real>() / denominator
+                    };
                 })
         }
     }
@@ -99,13 +189,15 @@ where
     ) {
         let velocity_changes = &self.velocity_changes;
         let densities = &self.densities;
-        let _max_error = na::zero::<Real>();
+        let dt = timestep.dt();
 
         for fluid_id in 0..fluids.len() {
-            let _it = par_iter_mut!(self.predicted_densities[fluid_id])
+            let fluid_i = &fluids[fluid_id];
+
+            par_iter_mut!(self.predicted_densities[fluid_id])
                 .enumerate()
                 .for_each(|(i, predicted_density)| {
-                    let fluid_i = &fluids[fluid_id];
+                    let vi = fluid_i.velocities[i] + velocity_changes[fluid_id][i];
                     let mut delta = na::zero::<Real>();
 
                     for c in fluid_fluid_contacts[fluid_id]
@@ -115,10 +207,10 @@ where
                         .iter()
                     {
                         let fluid_j = &fluids[c.j_model];
-                        let vi = fluid_i.velocities[c.i] + velocity_changes[c.i_model][c.i];
                         let vj = fluid_j.velocities[c.j] + velocity_changes[c.j_model][c.j];
 
-                        delta += fluids[c.j_model].particle_mass(c.j) * (vi - vj).dot(&c.gradient);
+                        delta += helper::adapted_mass(fluid_i, c.i, fluid_j, c.j)
+                            * (vi - vj).dot(&c.gradient);
                     }
 
                     for c in fluid_boundary_contacts[fluid_id]
@@ -127,16 +219,15 @@ where
                         .unwrap()
                         .iter()
                     {
-                        let vi = fluid_i.velocities[c.i] + velocity_changes[c.i_model][c.i];
-                        let vj = boundaries[c.j_model].velocities[c.j];
+                        let boundary = &boundaries[c.j_model];
+                        let vj = boundary.velocities[c.j];
 
-                        delta += boundaries[c.j_model].volumes[c.j]
-                            * fluid_i.density0
+                        delta += boundary.volumes[c.j]
+                            * fluid_i.rest_density(i)
                             * (vi - vj).dot(&c.gradient);
                     }
 
-                    *predicted_density = densities[fluid_id][i] + delta * timestep.dt();
-                    assert!(!predicted_density.is_zero());
+                    *predicted_density = densities[fluid_id][i] + delta * dt;
                 });
         }
     }
@@ -155,12 +246,13 @@ where
             let dii = &mut self.dii[fluid_id];
             let fluid_i = &fluids[fluid_id];
             let densities = &self.densities;
+            let dt = timestep.dt();
 
             par_iter_mut!(dii).enumerate().for_each(|(i, dii)| {
                 dii.fill(na::zero::<Real>());
 
                 let rhoi = densities[fluid_id][i];
-                let factor = -timestep.dt() * timestep.dt() / (rhoi * rhoi);
+                let factor = -dt * dt / (rhoi * rhoi);
 
                 for c in fluid_fluid_contacts
                     .particle_contacts(i)
@@ -168,7 +260,7 @@ where
                     .unwrap()
                     .iter()
                 {
-                    let mj = fluids[c.j_model].particle_mass(c.j);
+                    let mj = helper::adapted_mass(fluid_i, c.i, &fluids[c.j_model], c.j);
                     *dii += c.gradient * (mj * factor);
                 }
 
@@ -178,7 +270,7 @@ where
                     .unwrap()
                     .iter()
                 {
-                    let mj = boundaries[c.j_model].volumes[c.j] * fluid_i.density0;
+                    let mj = boundaries[c.j_model].volumes[c.j] * fluid_i.rest_density(i);
                     *dii += c.gradient * (mj * factor);
                 }
             })
@@ -200,12 +292,14 @@ where
             let dii = &self.dii[fluid_id];
             let fluid_i = &fluids[fluid_id];
             let densities = &self.densities;
+            let dt2 = timestep.dt() * timestep.dt();
 
             par_iter_mut!(aii).enumerate().for_each(|(i, aii)| {
                 *aii = na::zero::<Real>();
                 let rhoi = densities[fluid_id][i];
-                let mi = fluids[fluid_id].particle_mass(i);
-                let factor = timestep.dt() * timestep.dt() * mi / (rhoi * rhoi);
+                let mi = fluid_i.particle_mass(i);
+                let dii_i = dii[i];
+                let factor = dt2 / (rhoi * rhoi);
 
                 for c in fluid_fluid_contacts
                     .particle_contacts(i)
@@ -213,9 +307,11 @@ where
                     .unwrap()
                     .iter()
                 {
-                    let mj = fluids[c.j_model].particle_mass(c.j);
-                    let dji = c.gradient * factor;
-                    *aii += mj * (dii[c.i] - dji).dot(&c.gradient);
+                    let fluid_j = &fluids[c.j_model];
+                    let mj = helper::adapted_mass(fluid_i, c.i, fluid_j, c.j);
+                    let dji =
+                        c.gradient * (helper::pressure_mass(fluid_j, c.j, fluid_i, c.i) * factor);
+                    *aii += mj * (dii_i - dji).dot(&c.gradient);
                 }
 
                 for c in fluid_boundary_contacts
@@ -224,9 +320,9 @@ where
                     .unwrap()
                     .iter()
                 {
-                    let mj = boundaries[c.j_model].volumes[c.j] * fluid_i.density0;
-                    let dji = c.gradient * factor;
-                    *aii += mj * (dii[c.i] - dji).dot(&c.gradient);
+                    let mj = boundaries[c.j_model].volumes[c.j] * fluid_i.rest_density(i);
+                    let dji = c.gradient * (mi * factor);
+                    *aii += mj * (dii_i - dji).dot(&c.gradient);
                 }
             })
         }
@@ -243,9 +339,10 @@ where
             let fluid_fluid_contacts = &fluid_fluid_contacts[fluid_id];
             let _fluid_boundary_contacts = &fluid_boundary_contacts[fluid_id];
             let dij_pjl = &mut self.dij_pjl[fluid_id];
-            let _fluid_i = &fluids[fluid_id];
+            let fluid_i = &fluids[fluid_id];
             let densities = &self.densities;
             let pressures = &self.pressures;
+            let dt2 = timestep.dt() * timestep.dt();
 
             par_iter_mut!(dij_pjl).enumerate().for_each(|(i, dij_pjl)| {
                 dij_pjl.fill(na::zero::<Real>());
@@ -257,12 +354,12 @@ where
                     .iter()
                 {
                     let rhoj = densities[c.j_model][c.j];
-                    let mj = fluids[c.j_model].particle_mass(c.j);
+                    let mj = helper::pressure_mass(fluid_i, c.i, &fluids[c.j_model], c.j);
                     let p_jl = pressures[c.j_model][c.j];
                     *dij_pjl += c.gradient * (-mj * p_jl / (rhoj * rhoj));
                 }
 
-                *dij_pjl *= timestep.dt() * timestep.dt();
+                *dij_pjl *= dt2;
             })
         }
     }
@@ -289,16 +386,18 @@ where
             let aii = &self.aii[fluid_id];
             let dij_pjl = &self.dij_pjl;
             let dii = &self.dii;
+            let dt2 = timestep.dt() * timestep.dt();
+            let min_aii = na::convert::<_, Real>(1.0e-9);
 
             let it = par_iter_mut!(next_pressures)
                 .enumerate()
                 .map(|(i, next_pressure)| {
-                    if aii[i].abs() > na::convert::<_, Real>(1.0e-9) {
+                    if aii[i].abs() > min_aii {
                         let mut sum = na::zero::<Real>();
                         let pi = pressures[fluid_id][i];
-                        let mi = fluid_i.particle_mass(i);
-                        let rhoi = densities[fluid_id][i];
-                        let derr = fluid_i.density0 - predicted_densities[fluid_id][i];
+                        let rhoi2 = densities[fluid_id][i] * densities[fluid_id][i];
+                        let dij_pjl_i = dij_pjl[fluid_id][i];
+                        let derr = fluid_i.rest_density(i) - predicted_densities[fluid_id][i];
 
                         for c in fluid_fluid_contacts
                             .particle_contacts(i)
@@ -306,10 +405,11 @@ where
                             .unwrap()
                             .iter()
                         {
-                            let mj = fluids[c.j_model].particle_mass(c.j);
-                            let dji =
-                                c.gradient * (timestep.dt() * timestep.dt() * mi / (rhoi * rhoi));
-                            let factor = dij_pjl[c.i_model][c.i]
+                            let fluid_j = &fluids[c.j_model];
+                            let mj = helper::adapted_mass(fluid_i, c.i, fluid_j, c.j);
+                            let mi = helper::pressure_mass(fluid_j, c.j, fluid_i, c.i);
+                            let dji = c.gradient * (dt2 * mi / rhoi2);
+                            let factor = dij_pjl_i
                                 - dii[c.j_model][c.j] * pressures[c.j_model][c.j]
                                 - (dij_pjl[c.j_model][c.j] - dji * pi);
                             sum += mj * factor.dot(&c.gradient);
@@ -321,8 +421,8 @@ where
                             .unwrap()
                             .iter()
                         {
-                            let mj = boundaries[c.j_model].volumes[c.j] * fluid_i.density0;
-                            sum += mj * dij_pjl[c.i_model][c.i].dot(&c.gradient);
+                            let mj = boundaries[c.j_model].volumes[c.j] * fluid_i.rest_density(i);
+                            sum += mj * dij_pjl_i.dot(&c.gradient);
                         }
 
                         *next_pressure =
@@ -330,7 +430,7 @@ where
 
                         if *next_pressure > na::zero::<Real>() {
                             *next_pressure = next_pressure.max(na::zero::<Real>());
-                            (-sum - aii[i] * *next_pressure) / fluid_i.density0
+                            (-sum - aii[i] * *next_pressure) / fluid_i.rest_density(i)
                         } else {
                             // Clamp negative pressures.
                             *next_pressure = na::zero::<Real>();
@@ -362,14 +462,16 @@ where
     ) {
         let densities = &self.densities;
         let pressures = &self.pressures;
+        let dt = timestep.dt();
 
-        for (fluid_id, _fluid1) in fluids.iter().enumerate() {
+        for (fluid_id, fluid_i) in fluids.iter().enumerate() {
             par_iter_mut!(self.velocity_changes[fluid_id])
                 .enumerate()
                 .for_each(|(i, velocity_change)| {
-                    let fluid_i = &fluids[fluid_id];
                     let pi = pressures[fluid_id][i];
                     let rhoi = densities[fluid_id][i];
+                    let rhoi2 = rhoi * rhoi;
+                    let mass_i = fluid_i.particle_mass(i);
 
                     for c in fluid_fluid_contacts[fluid_id]
                         .particle_contacts(i)
@@ -377,12 +479,14 @@ where
                         .unwrap()
                         .iter()
                     {
-                        let mj = fluids[c.j_model].particle_mass(c.j);
+                        let fluid_j = &fluids[c.j_model];
+                        let mi = helper::adapted_mass(fluid_i, c.i, fluid_j, c.j);
+                        let mj = helper::pressure_mass(fluid_i, c.i, fluid_j, c.j);
                         let pj = pressures[c.j_model][c.j];
                         let rhoj = densities[c.j_model][c.j];
 
-                        *velocity_change -= c.gradient
-                            * (timestep.dt() * mj * (pi / (rhoi * rhoi) + pj / (rhoj * rhoj)));
+                        *velocity_change -=
+                            c.gradient * (dt * (mi * pi / rhoi2 + mj * pj / (rhoj * rhoj)));
                     }
 
                     for c in fluid_boundary_contacts[fluid_id]
@@ -391,29 +495,33 @@ where
                         .unwrap()
                         .iter()
                     {
-                        let mj = boundaries[c.j_model].volumes[c.j] * fluid_i.density0;
-                        let acc = c.gradient * (mj * pi / (rhoi * rhoi));
-                        *velocity_change -= acc * timestep.dt();
+                        let boundary = &boundaries[c.j_model];
+                        let mj = boundary.volumes[c.j] * fluid_i.rest_density(i);
+                        let acc = c.gradient * (mj * pi / rhoi2);
+                        *velocity_change -= acc * dt;
 
                         // Apply the force to the boundary too.
-                        let mi = fluid_i.particle_mass(c.i);
-                        boundaries[c.j_model].apply_force(c.j, acc * mi);
+                        boundary.apply_force(c.j, acc * mass_i);
                     }
                 })
         }
     }
 
-    fn update_velocities_and_positions(
-        &mut self,
-        timestep: &TimestepManager,
-        fluids: &mut [Fluid],
-    ) {
+    fn update_velocities(&mut self, fluids: &mut [Fluid]) {
         for (fluid, delta) in fluids.iter_mut().zip(self.velocity_changes.iter()) {
-            par_iter_mut!(fluid.positions)
-                .zip(par_iter_mut!(fluid.velocities))
+            par_iter_mut!(fluid.velocities)
                 .zip(par_iter!(delta))
-                .for_each(|((pos, vel), delta)| {
+                .for_each(|(vel, delta)| {
                     *vel += delta;
+                })
+        }
+    }
+
+    fn update_positions(&mut self, timestep: &TimestepManager, fluids: &mut [Fluid]) {
+        for fluid in fluids.iter_mut() {
+            par_iter_mut!(fluid.positions)
+                .zip(par_iter!(fluid.velocities))
+                .for_each(|(pos, vel)| {
                     *pos += *vel * timestep.dt();
                 })
         }
@@ -421,13 +529,19 @@ where
 
     fn pressure_solve(
         &mut self,
+        counters: &mut Counters,
         timestep: &TimestepManager,
         _kernel_radius: Real,
         contact_manager: &mut ContactManager,
         fluids: &mut [Fluid],
         boundaries: &[Boundary],
     ) {
+        self.num_pressure_iter = 0;
+
+        let mut converged = false;
+
         for i in 0..self.max_pressure_iter {
+            self.num_pressure_iter += 1;
             self.compute_dij_pjl(
                 timestep,
                 &contact_manager.fluid_fluid_contacts,
@@ -444,15 +558,24 @@ where
             );
 
             std::mem::swap(&mut self.pressures, &mut self.next_pressures);
+            counters.solver.num_pressure_iterations += 1;
+            counters.solver.density_error = avg_err;
 
             if avg_err <= self.max_density_error && i >= self.min_pressure_iter {
-                //                println!(
-                //                    "Average density error: {}, break after niters: {}",
-                //                    avg_err, i
-                //                );
+                #[cfg(feature = "log")]
+                log::debug!(
+                    "Average density error: {}, break after niters: {}",
+                    avg_err,
+                    i
+                );
+                converged = true;
                 break;
             }
         }
+
+        if !converged {
+            counters.solver.num_unconverged_pressure_solves += 1;
+        }
     }
 
     fn integrate_and_clear_accelerations(
@@ -473,11 +596,18 @@ where
 
 impl<KernelDensity, KernelGradient> PressureSolver for IISPHSolver<KernelDensity, KernelGradient>
 where
-    KernelDensity: Kernel,
-    KernelGradient: Kernel,
+    KernelDensity: Kernel + 'static,
+    KernelGradient: Kernel + 'static,
 {
     fn init_with_fluids(&mut self, fluids: &[Fluid]) {
+        if fluids.len() < self.pressures.len() {
+            // A fluid has been removed, so the remaining fluids may have been reordered.
+            // The previous pressures can't be matched with their particles anymore.
+            self.pressures.iter_mut().for_each(|p| p.clear());
+        }
+
         // Resize every buffer.
+        // Particles added since the last step start with a zero pressure.
         self.densities.resize(fluids.len(), Vec::new());
         self.predicted_densities.resize(fluids.len(), Vec::new());
         self.velocity_changes.resize(fluids.len(), Vec::new());
@@ -548,30 +678,26 @@ where
         boundaries: &[Boundary],
     ) {
         for fluid in fluids.iter_mut() {
+            let gravity = fluid.effective_gravity(gravity);
             par_iter_mut!(fluid.accelerations).for_each(|acceleration| {
                 *acceleration += gravity;
             })
         }
 
-        for (fluid, fluid_fluid_contacts, fluid_boundary_contacts, densities) in
-            itertools::multizip((
-                &mut *fluids,
-                &contact_manager.fluid_fluid_contacts,
-                &contact_manager.fluid_boundary_contacts,
-                &self.densities,
-            ))
-        {
+        for fluid_id in 0..fluids.len() {
+            let (fluid, other_fluids) = OtherFluids::split(fluids, fluid_id, &self.densities);
             let mut forces = std::mem::replace(&mut fluid.nonpressure_forces, Vec::new());
 
             for np_force in &mut forces {
                 np_force.solve(
                     timestep,
                     kernel_radius,
-                    fluid_fluid_contacts,
-                    fluid_boundary_contacts,
+                    &contact_manager.fluid_fluid_contacts[fluid_id],
+                    &contact_manager.fluid_boundary_contacts[fluid_id],
                     fluid,
+                    &other_fluids,
                     boundaries,
-                    densities,
+                    &self.densities[fluid_id],
                 );
             }
 
@@ -586,18 +712,22 @@ where
         fluids: &[Fluid],
         boundaries: &[Boundary],
     ) {
+        let periodic_domain = contact_manager.periodic_domain().copied();
+
         helper::update_fluid_contacts::<KernelDensity, KernelGradient>(
             kernel_radius,
             &mut contact_manager.fluid_fluid_contacts,
             &mut contact_manager.fluid_boundary_contacts,
             fluids,
             boundaries,
+            periodic_domain.as_ref(),
         );
 
         helper::update_boundary_contacts::<KernelDensity, KernelGradient>(
             kernel_radius,
             &mut contact_manager.boundary_boundary_contacts,
             boundaries,
+            periodic_domain.as_ref(),
         );
     }
 
@@ -608,36 +738,22 @@ where
         boundaries: &mut [Boundary],
     ) {
         self.compute_boundary_volumes(&contact_manager.boundary_boundary_contacts, boundaries);
-
-        for fluid_id in 0..fluids.len() {
-            par_iter_mut!(self.densities[fluid_id])
-                .enumerate()
-                .for_each(|(i, density)| {
-                    *density = na::zero::<Real>();
-
-                    for c in contact_manager.fluid_fluid_contacts[fluid_id]
-                        .particle_contacts(i)
-                        .read()
-                        .unwrap()
-                        .iter()
-                    {
-                        *density += fluids[c.j_model].particle_mass(c.j) * c.weight;
-                    }
-
-                    for c in contact_manager.fluid_boundary_contacts[fluid_id]
-                        .particle_contacts(i)
-                        .read()
-                        .unwrap()
-                        .iter()
-                    {
-                        *density += boundaries[c.j_model].volumes[c.j]
-                            * fluids[c.i_model].density0
-                            * c.weight;
-                    }
-
-                    assert!(!density.is_zero());
-                })
-        }
+        helper::correct_kernel_gradients(
+            &contact_manager.fluid_fluid_contacts,
+            &contact_manager.fluid_boundary_contacts,
+            fluids,
+            boundaries,
+            contact_manager.periodic_domain(),
+        );
+        self.density_estimator.compute_densities(
+            &contact_manager.fluid_fluid_contacts,
+            &contact_manager.fluid_boundary_contacts,
+            fluids,
+            boundaries,
+            &mut self.densities,
+        );
+        self.shepard_filter
+            .apply(contact_manager, fluids, boundaries, &mut self.densities);
     }
 
     fn step(
@@ -649,7 +765,9 @@ where
         kernel_radius: Real,
         fluids: &mut [Fluid],
         boundaries: &[Boundary],
+        hooks: &mut dyn FnMut(SubstepStage, &TimestepManager, &mut [Fluid]),
     ) {
+        counters.solver.non_pressure_resolution_time.resume();
         self.predict_advection(
             timestep,
             kernel_radius,
@@ -658,8 +776,12 @@ where
             fluids,
             boundaries,
         );
+        counters.solver.non_pressure_resolution_time.pause();
+        hooks(SubstepStage::AfterAdvectionPrediction, timestep, fluids);
+        counters.solver.integration_time.resume();
         timestep.advance(fluids);
         self.integrate_and_clear_accelerations(timestep, fluids);
+        counters.solver.integration_time.pause();
 
         counters.solver.pressure_resolution_time.resume();
         self.compute_dii(
@@ -670,11 +792,11 @@ where
             boundaries,
         );
 
-        let _0_5: Real = na::convert::<_, Real>(0.5);
+        let warm_start_factor = self.warm_start_factor;
         self.pressures
             .iter_mut()
             .flat_map(|v| v.iter_mut())
-            .for_each(|p| *p *= _0_5);
+            .for_each(|p| *p *= warm_start_factor);
 
         let _ = self.compute_predicted_densities(
             timestep,
@@ -692,7 +814,14 @@ where
             boundaries,
         );
 
-        self.pressure_solve(timestep, kernel_radius, contact_manager, fluids, boundaries);
+        self.pressure_solve(
+            counters,
+            timestep,
+            kernel_radius,
+            contact_manager,
+            fluids,
+            boundaries,
+        );
 
         self.compute_velocity_changes(
             timestep,
@@ -702,11 +831,35 @@ where
             boundaries,
         );
 
-        self.update_velocities_and_positions(timestep, fluids);
+        self.update_velocities(fluids);
 
         self.velocity_changes
             .iter_mut()
             .for_each(|vs| vs.iter_mut().for_each(|v| v.fill(na::zero::<Real>())));
         counters.solver.pressure_resolution_time.pause();
+
+        hooks(SubstepStage::AfterPressureSolve, timestep, fluids);
+        counters.solver.integration_time.resume();
+        self.update_positions(timestep, fluids);
+        counters.solver.integration_time.pause();
+    }
+
+    fn densities(&self) -> &[Vec<Real>] {
+        &self.densities
+    }
+
+    fn pressures(&self) -> &[Vec<Real>] {
+        &self.pressures
+    }
+
+    fn apply_permutation(&mut self, fluid_id: usize, permutation: &[usize]) {
+        // The pressures are used for warm-starting, and the densities are reported to the user.
+        for buffer in [&mut self.pressures, &mut self.densities] {
+            if let Some(values) = buffer.get_mut(fluid_id) {
+                if values.len() == permutation.len() {
+                    *values = crate::z_order::apply_permutation(permutation, values);
+                }
+            }
+        }
     }
 }
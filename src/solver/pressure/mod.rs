@@ -1,7 +1,11 @@
 pub use self::dfsph_solver::DFSPHSolver;
+pub use self::divergence_solver::DivergenceSolver;
 pub use self::iisph_solver::IISPHSolver;
 pub use self::pressure_solver::PressureSolver;
+pub use self::wcsph_solver::WCSPHSolver;
 
 mod dfsph_solver;
+mod divergence_solver;
 mod iisph_solver;
 mod pressure_solver;
+mod wcsph_solver;
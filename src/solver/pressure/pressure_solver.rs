@@ -1,11 +1,13 @@
+use std::any::Any;
+
 use crate::counters::Counters;
 use crate::geometry::ContactManager;
 use crate::math::{Real, Vector};
-use crate::object::{Boundary, Fluid};
+use crate::object::{Boundary, Fluid, SubstepStage};
 use crate::TimestepManager;
 
 /// Trait implemented by pressure solvers.
-pub trait PressureSolver {
+pub trait PressureSolver: Any {
     /// Initialize this solver with the given fluids.
     fn init_with_fluids(&mut self, fluids: &[Fluid]);
 
@@ -13,6 +15,8 @@ pub trait PressureSolver {
     fn init_with_boundaries(&mut self, boundaries: &[Boundary]);
 
     /// Predicts advection with the given gravity.
+    ///
+    /// The gravity of the fluids overriding it (see `Fluid::effective_gravity`) must be used instead.
     fn predict_advection(
         &mut self,
         timestep: &TimestepManager,
@@ -43,7 +47,11 @@ pub trait PressureSolver {
     /// Solves pressure and non-pressure force for the given fluids and boundaries.
     ///
     /// Both `self.init_with_fluids` and `self.init_with_boundaries` must be called before this
-    /// method.
+    /// method. The `hooks` must be invoked with `SubstepStage::AfterAdvectionPrediction` right
+    /// after the advection prediction (before `timestep.advance`), and with
+    /// `SubstepStage::AfterPressureSolve` once the final velocities of the particles are known,
+    /// right before their positions are updated. The time spent in each stage is accumulated into
+    /// the timers of `counters.solver`.
     fn step(
         &mut self,
         counters: &mut Counters,
@@ -53,5 +61,37 @@ pub trait PressureSolver {
         kernel_radius: Real,
         fluids: &mut [Fluid],
         boundaries: &[Boundary],
+        hooks: &mut dyn FnMut(SubstepStage, &TimestepManager, &mut [Fluid]),
     );
+
+    /// The largest substep length for which this solver remains stable on the given fluids, if any.
+    ///
+    /// The timestep manager limits each substep to this length, in addition to the CFL condition
+    /// and the limits of the non-pressure forces. This is typically the acoustic limit of an
+    /// explicit solver. The fluids with their own resolution have their own kernel radius (see
+    /// `Fluid::kernel_radius`). This defaults to `None`, i.e., no limit.
+    fn max_substep(&self, _kernel_radius: Real, _fluids: &[Fluid]) -> Option<Real> {
+        None
+    }
+
+    /// The densities of the particles of each fluid, as computed during the last substep.
+    ///
+    /// The fluids are in the same order as the ones given to `Self::init_with_fluids`.
+    fn densities(&self) -> &[Vec<Real>] {
+        &[]
+    }
+
+    /// The pressures of the particles of each fluid, as computed during the last substep.
+    ///
+    /// This is empty if this solver does not compute pressures explicitly.
+    fn pressures(&self) -> &[Vec<Real>] {
+        &[]
+    }
+
+    /// Apply the given permutation to the per-particle state of the `fluid_id`-th fluid.
+    ///
+    /// This is called whenever the particles of that fluid are reordered between two steps: the
+    /// particle `k` after this call was the particle `permutation[k]` before. Only the state
+    /// kept from one step to the next needs to be reordered.
+    fn apply_permutation(&mut self, _fluid_id: usize, _permutation: &[usize]) {}
 }
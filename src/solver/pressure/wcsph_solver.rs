@@ -0,0 +1,615 @@
+use std::marker::PhantomData;
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use num::Zero;
+
+use crate::counters::Counters;
+use crate::geometry::{ContactManager, ParticlesContacts};
+use crate::kernel::{CubicSplineKernel, Kernel};
+use crate::math::{Matrix, Point, Real, Vector};
+use crate::object::{Boundary, Fluid, SubstepStage};
+use crate::solver::density::ShepardFilter;
+use crate::solver::{helper, DensityEstimator, OtherFluids, PressureSolver, SummationDensity};
+use crate::TimestepManager;
+
+/// A WCSPH (Weakly Compressible Smoothed Particle Hydrodynamics) pressure solver.
+///
+/// Pressures are computed explicitly from the densities using the Tait equation of state.
+/// This is cheap per substep but requires small timesteps to remain stable, especially with
+/// a high speed of sound: the substeps are limited by the acoustic CFL condition (see
+/// `TimestepManager::acoustic_substep_limit`), so the maximum number of substeps of the timestep
+/// manager may need to be increased.
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub struct WCSPHSolver<
+    KernelDensity: Kernel = CubicSplineKernel,
+    KernelGradient: Kernel = CubicSplineKernel,
+> {
+    /// The numerical speed of sound of the fluids.
+    ///
+    /// Higher values make the fluids less compressible, but require smaller timesteps.
+    pub speed_of_sound: Real,
+    /// The exponent of the Tait equation of state (typically 7.0 for water).
+    pub exponent: Real,
+    /// The method used to estimate the density of the fluid particles.
+    #[cfg_attr(
+        feature = "serde-serialize",
+        serde(skip, default = "crate::solver::density::default_density_estimator")
+    )]
+    pub density_estimator: Box<dyn DensityEstimator>,
+    /// The coefficient of the density diffusion term of δ-SPH, if enabled.
+    ///
+    /// See `WCSPHSolver::with_delta_sph`. This defaults to `None`.
+    pub delta_sph: Option<Real>,
+    densities: Vec<Vec<Real>>,
+    #[cfg_attr(feature = "serde-serialize", serde(skip))]
+    shepard_filter: ShepardFilter,
+    continuity_densities: Vec<Vec<Real>>,
+    density_gradients: Vec<Vec<Vector<Real>>>,
+    pressures: Vec<Vec<Real>>,
+    #[cfg_attr(feature = "serde-serialize", serde(skip))]
+    phantoms: PhantomData<(KernelDensity, KernelGradient)>,
+}
+
+impl<KernelDensity, KernelGradient> WCSPHSolver<KernelDensity, KernelGradient>
+where
+    KernelDensity: Kernel,
+    KernelGradient: Kernel,
+{
+    /// Initialize a new WCSPH pressure solver.
+    pub fn new() -> Self {
+        Self {
+            speed_of_sound: na::convert::<_, Real>(20.0),
+            exponent: na::convert::<_, Real>(7.0),
+            density_estimator: Box::new(SummationDensity::new()),
+            delta_sph: None,
+            densities: Vec::new(),
+            shepard_filter: ShepardFilter::default(),
+            continuity_densities: Vec::new(),
+            density_gradients: Vec::new(),
+            pressures: Vec::new(),
+            phantoms: PhantomData,
+        }
+    }
+
+    // See "Antuono et al. 2010, Free-surface flows solved by means of SPH schemes with numerical
+    // diffusive terms".
+    /// Enables δ-SPH with the given density diffusion coefficient (typically 0.1).
+    ///
+    /// The densities are then integrated with the continuity equation instead of being estimated
+    /// from scratch at each substep, and a diffusive term `δ h c₀ Σ_j ψ_ij · ∇W_ij V_j` is added to
+    /// the density rate of change, where `h` is the kernel radius and `c₀` the speed of sound.
+    /// This smoothes out the spurious high-frequency pressure oscillations of explicit SPH. The
+    /// renormalized density gradients are subtracted from `ψ_ij` so the hydrostatic density
+    /// profile is not diffused.
+    ///
+    /// The density estimator is only used to initialize the density of new particles. The
+    /// diffusion only occurs between particles of the same fluid.
+    pub fn with_delta_sph(mut self, delta: Real) -> Self {
+        self.delta_sph = Some(delta);
+        self
+    }
+
+    fn compute_boundary_volumes(
+        &mut self,
+        boundary_boundary_contacts: &[ParticlesContacts],
+        boundaries: &mut [Boundary],
+    ) {
+        for boundary_id in 0..boundaries.len() {
+            // The volumes of density map boundaries are already accounted for by the map.
+            if boundaries[boundary_id].density_map().is_some() {
+                continue;
+            }
+
+            par_iter_mut!(boundaries[boundary_id].volumes)
+                .enumerate()
+                .for_each(|(i, volume)| {
+                    let mut denominator = na::zero::<Real>();
+
+                    for c in boundary_boundary_contacts[boundary_id]
+                        .particle_contacts(i)
+                        .read()
+                        .unwrap()
+                        .iter()
+                    {
+                        denominator += c.weight;
+                    }
+
+                    // A boundary particle without any neighbor (not even itself, e.g., because
+                    // of a non-finite position) doesn't contribute to the fluid densities.
+                    *volume = if denominator.is_zero() {
+                        na::zero::<Real>()
+                    } else {
+                        na::one::<Real>() / denominator
+                    };
+                })
+        }
+    }
+
+    // Integrates the densities with the continuity equation and the δ-SPH diffusive term.
+    fn integrate_densities(
+        &mut self,
+        dt: Real,
+        kernel_radius: Real,
+        contact_manager: &ContactManager,
+        fluids: &[Fluid],
+        boundaries: &[Boundary],
+    ) {
+        let delta = match self.delta_sph {
+            Some(delta) => delta,
+            None => return,
+        };
+
+        let diffusion = delta * kernel_radius * self.speed_of_sound;
+        let eps = kernel_radius * kernel_radius * na::convert::<_, Real>(0.01);
+        let two = na::convert::<_, Real>(2.0);
+        let fluid_fluid_contacts = &contact_manager.fluid_fluid_contacts;
+        let fluid_boundary_contacts = &contact_manager.fluid_boundary_contacts;
+        let periodic_domain = contact_manager.periodic_domain();
+        let densities = &self.densities;
+        let closest_image = |pi: &Point<Real>, pj: &Point<Real>| {
+            periodic_domain.map_or(*pj, |domain| domain.closest_image(pi, pj))
+        };
+
+        self.density_gradients.resize(fluids.len(), Vec::new());
+
+        for fluid_id in 0..fluids.len() {
+            let fluid_i = &fluids[fluid_id];
+            let density_gradients = &mut self.density_gradients[fluid_id];
+            density_gradients.resize(fluid_i.num_particles(), Vector::zeros());
+
+            // The renormalized density gradients.
+            par_iter_mut!(density_gradients)
+                .enumerate()
+                .for_each(|(i, density_gradient)| {
+                    let rhoi = densities[fluid_id][i];
+                    let xi = fluid_i.positions[i];
+                    let mut gradient = Vector::zeros();
+                    let mut moment = Matrix::zeros();
+
+                    for c in fluid_fluid_contacts[fluid_id]
+                        .particle_contacts(i)
+                        .read()
+                        .unwrap()
+                        .iter()
+                    {
+                        // The density is not diffused across interfaces between particles with
+                        // different rest densities.
+                        if c.j_model == fluid_id
+                            && fluid_i.rest_density(c.j) == fluid_i.rest_density(i)
+                        {
+                            let rhoj = densities[c.j_model][c.j];
+                            let xji = closest_image(&xi, &fluid_i.positions[c.j]) - xi;
+                            gradient += c.gradient * ((rhoj - rhoi) * fluid_i.volumes[c.j]);
+                            moment += (c.gradient * fluid_i.volumes[c.j]) * xji.transpose();
+                        }
+                    }
+
+                    *density_gradient = moment
+                        .try_inverse()
+                        .map_or(gradient, |correction| correction * gradient);
+                });
+        }
+
+        let density_gradients = &self.density_gradients;
+
+        for fluid_id in 0..fluids.len() {
+            let fluid_i = &fluids[fluid_id];
+
+            par_iter_mut!(self.continuity_densities[fluid_id])
+                .enumerate()
+                .for_each(|(i, continuity_density)| {
+                    let rhoi = densities[fluid_id][i];
+                    let xi = fluid_i.positions[i];
+                    let vi = fluid_i.velocities[i];
+                    let mut divergence = na::zero::<Real>();
+                    let mut density_laplacian = na::zero::<Real>();
+
+                    for c in fluid_fluid_contacts[fluid_id]
+                        .particle_contacts(i)
+                        .read()
+                        .unwrap()
+                        .iter()
+                    {
+                        let fluid_j = &fluids[c.j_model];
+                        let vj = fluid_j.velocities[c.j];
+                        divergence += (vi - vj).dot(&c.gradient) * fluid_j.volumes[c.j];
+
+                        if c.j_model == fluid_id
+                            && fluid_i.rest_density(c.j) == fluid_i.rest_density(i)
+                        {
+                            let rhoj = densities[c.j_model][c.j];
+                            let xji = closest_image(&xi, &fluid_j.positions[c.j]) - xi;
+                            let psi = xji * (two * (rhoj - rhoi) / (xji.norm_squared() + eps))
+                                - (density_gradients[fluid_id][i]
+                                    + density_gradients[fluid_id][c.j]);
+                            density_laplacian += psi.dot(&c.gradient) * fluid_j.volumes[c.j];
+                        }
+                    }
+
+                    for c in fluid_boundary_contacts[fluid_id]
+                        .particle_contacts(i)
+                        .read()
+                        .unwrap()
+                        .iter()
+                    {
+                        let boundary = &boundaries[c.j_model];
+                        let vj = boundary.velocities[c.j];
+                        divergence += (vi - vj).dot(&c.gradient) * boundary.volumes[c.j];
+                    }
+
+                    *continuity_density =
+                        rhoi + (rhoi * divergence + diffusion * density_laplacian) * dt;
+                });
+        }
+
+        for (densities, continuity_densities) in
+            self.densities.iter_mut().zip(&self.continuity_densities)
+        {
+            densities.copy_from_slice(continuity_densities);
+        }
+    }
+
+    // Returns the average density error.
+    fn compute_pressures(&mut self, fluids: &[Fluid]) -> Real {
+        let speed_of_sound = self.speed_of_sound;
+        let exponent = self.exponent;
+        let mut max_error = na::zero::<Real>();
+
+        for (fluid, densities, pressures) in
+            itertools::multizip((fluids, &self.densities, &mut self.pressures))
+        {
+            // Tait equation of state.
+            let stiffness = speed_of_sound * speed_of_sound / exponent;
+
+            par_iter_mut!(pressures)
+                .zip(par_iter!(densities))
+                .enumerate()
+                .for_each(|(i, (pressure, density))| {
+                    let density0 = fluid.rest_density(i);
+                    let ratio = *density / density0;
+                    // Clamp negative pressures to avoid particle clustering at the free surface.
+                    *pressure = (density0 * stiffness * (ratio.powf(exponent) - na::one::<Real>()))
+                        .max(na::zero::<Real>());
+                });
+
+            let it = par_iter!(densities).enumerate().map(|(i, density)| {
+                (*density / fluid.rest_density(i) - na::one::<Real>()).max(na::zero::<Real>())
+            });
+            let err = par_reduce_sum!(na::zero::<Real>(), it);
+
+            if !densities.is_empty() {
+                max_error = max_error.max(err / na::convert::<_, Real>(densities.len() as f64));
+            }
+        }
+
+        max_error
+    }
+
+    fn compute_pressure_accelerations(
+        &mut self,
+        fluid_fluid_contacts: &[ParticlesContacts],
+        fluid_boundary_contacts: &[ParticlesContacts],
+        fluids: &mut [Fluid],
+        boundaries: &[Boundary],
+    ) {
+        let densities = &self.densities;
+        let pressures = &self.pressures;
+
+        for fluid_id in 0..fluids.len() {
+            let mut accelerations = std::mem::take(&mut fluids[fluid_id].accelerations);
+            let fluids_ref = &*fluids;
+
+            par_iter_mut!(accelerations)
+                .enumerate()
+                .for_each(|(i, acceleration)| {
+                    let fluid_i = &fluids_ref[fluid_id];
+                    let pi = pressures[fluid_id][i];
+                    let rhoi = densities[fluid_id][i];
+
+                    for c in fluid_fluid_contacts[fluid_id]
+                        .particle_contacts(i)
+                        .read()
+                        .unwrap()
+                        .iter()
+                    {
+                        let fluid_j = &fluids_ref[c.j_model];
+                        let mi = helper::adapted_mass(fluid_i, c.i, fluid_j, c.j);
+                        let mj = helper::pressure_mass(fluid_i, c.i, fluid_j, c.j);
+                        let pj = pressures[c.j_model][c.j];
+                        let rhoj = densities[c.j_model][c.j];
+
+                        *acceleration -=
+                            c.gradient * (mi * pi / (rhoi * rhoi) + mj * pj / (rhoj * rhoj));
+                    }
+
+                    for c in fluid_boundary_contacts[fluid_id]
+                        .particle_contacts(i)
+                        .read()
+                        .unwrap()
+                        .iter()
+                    {
+                        let mj = boundaries[c.j_model].volumes[c.j] * fluid_i.rest_density(i);
+                        let acc = c.gradient * (mj * pi / (rhoi * rhoi));
+                        *acceleration -= acc;
+
+                        // Apply the force to the boundary too.
+                        let mi = fluid_i.particle_mass(c.i);
+                        boundaries[c.j_model].apply_force(c.j, acc * mi);
+                    }
+                });
+
+            fluids[fluid_id].accelerations = accelerations;
+        }
+    }
+
+    fn integrate_and_clear_accelerations(
+        &mut self,
+        timestep: &TimestepManager,
+        fluids: &mut [Fluid],
+    ) {
+        for fluid in fluids.iter_mut() {
+            par_iter_mut!(fluid.velocities)
+                .zip(par_iter_mut!(fluid.accelerations))
+                .for_each(|(vel, acceleration)| {
+                    *vel += *acceleration * timestep.dt();
+                    acceleration.fill(na::zero::<Real>());
+                })
+        }
+    }
+
+    fn integrate_positions(&mut self, timestep: &TimestepManager, fluids: &mut [Fluid]) {
+        for fluid in fluids.iter_mut() {
+            par_iter_mut!(fluid.positions)
+                .zip(par_iter!(fluid.velocities))
+                .for_each(|(pos, vel)| {
+                    *pos += *vel * timestep.dt();
+                })
+        }
+    }
+}
+
+impl<KernelDensity, KernelGradient> Default for WCSPHSolver<KernelDensity, KernelGradient>
+where
+    KernelDensity: Kernel,
+    KernelGradient: Kernel,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<KernelDensity, KernelGradient> PressureSolver for WCSPHSolver<KernelDensity, KernelGradient>
+where
+    KernelDensity: Kernel + 'static,
+    KernelGradient: Kernel + 'static,
+{
+    fn init_with_fluids(&mut self, fluids: &[Fluid]) {
+        // Resize every buffer.
+        self.densities.resize(fluids.len(), Vec::new());
+        self.pressures.resize(fluids.len(), Vec::new());
+        self.continuity_densities.resize(fluids.len(), Vec::new());
+
+        for (fluid, densities, pressures, continuity_densities) in itertools::multizip((
+            fluids.iter(),
+            self.densities.iter_mut(),
+            self.pressures.iter_mut(),
+            self.continuity_densities.iter_mut(),
+        )) {
+            densities.resize(fluid.num_particles(), na::zero::<Real>());
+            pressures.resize(fluid.num_particles(), na::zero::<Real>());
+            // A zero density marks the particles that are not initialized yet.
+            continuity_densities.resize(fluid.num_particles(), na::zero::<Real>());
+
+            if fluid.num_deleted_particles() != 0 {
+                crate::helper::filter_from_mask(fluid.deleted_particles_mask(), densities);
+                crate::helper::filter_from_mask(fluid.deleted_particles_mask(), pressures);
+                crate::helper::filter_from_mask(
+                    fluid.deleted_particles_mask(),
+                    continuity_densities,
+                );
+            }
+        }
+    }
+
+    fn init_with_boundaries(&mut self, _boundaries: &[Boundary]) {}
+
+    fn predict_advection(
+        &mut self,
+        timestep: &TimestepManager,
+        kernel_radius: Real,
+        contact_manager: &ContactManager,
+        gravity: &Vector<Real>,
+        fluids: &mut [Fluid],
+        boundaries: &[Boundary],
+    ) {
+        for fluid in fluids.iter_mut() {
+            let gravity = fluid.effective_gravity(gravity);
+            par_iter_mut!(fluid.accelerations).for_each(|acceleration| {
+                *acceleration += gravity;
+            })
+        }
+
+        for fluid_id in 0..fluids.len() {
+            let (fluid, other_fluids) = OtherFluids::split(fluids, fluid_id, &self.densities);
+            let mut forces = std::mem::take(&mut fluid.nonpressure_forces);
+
+            for np_force in &mut forces {
+                np_force.solve(
+                    timestep,
+                    kernel_radius,
+                    &contact_manager.fluid_fluid_contacts[fluid_id],
+                    &contact_manager.fluid_boundary_contacts[fluid_id],
+                    fluid,
+                    &other_fluids,
+                    boundaries,
+                    &self.densities[fluid_id],
+                );
+            }
+
+            fluid.nonpressure_forces = forces;
+        }
+    }
+
+    fn evaluate_kernels(
+        &mut self,
+        kernel_radius: Real,
+        contact_manager: &mut ContactManager,
+        fluids: &[Fluid],
+        boundaries: &[Boundary],
+    ) {
+        let periodic_domain = contact_manager.periodic_domain().copied();
+
+        helper::update_fluid_contacts::<KernelDensity, KernelGradient>(
+            kernel_radius,
+            &mut contact_manager.fluid_fluid_contacts,
+            &mut contact_manager.fluid_boundary_contacts,
+            fluids,
+            boundaries,
+            periodic_domain.as_ref(),
+        );
+
+        helper::update_boundary_contacts::<KernelDensity, KernelGradient>(
+            kernel_radius,
+            &mut contact_manager.boundary_boundary_contacts,
+            boundaries,
+            periodic_domain.as_ref(),
+        );
+    }
+
+    fn compute_densities(
+        &mut self,
+        contact_manager: &ContactManager,
+        fluids: &[Fluid],
+        boundaries: &mut [Boundary],
+    ) {
+        self.compute_boundary_volumes(&contact_manager.boundary_boundary_contacts, boundaries);
+        helper::correct_kernel_gradients(
+            &contact_manager.fluid_fluid_contacts,
+            &contact_manager.fluid_boundary_contacts,
+            fluids,
+            boundaries,
+            contact_manager.periodic_domain(),
+        );
+        self.density_estimator.compute_densities(
+            &contact_manager.fluid_fluid_contacts,
+            &contact_manager.fluid_boundary_contacts,
+            fluids,
+            boundaries,
+            &mut self.densities,
+        );
+        self.shepard_filter
+            .apply(contact_manager, fluids, boundaries, &mut self.densities);
+
+        if self.delta_sph.is_some() {
+            // The estimated densities are only used for the new particles.
+            for (densities, continuity_densities) in self
+                .densities
+                .iter_mut()
+                .zip(self.continuity_densities.iter_mut())
+            {
+                par_iter_mut!(densities)
+                    .zip(par_iter_mut!(continuity_densities))
+                    .for_each(|(density, continuity_density)| {
+                        if continuity_density.is_zero() {
+                            *continuity_density = *density;
+                        } else {
+                            *density = *continuity_density;
+                        }
+                    });
+            }
+        }
+    }
+
+    fn step(
+        &mut self,
+        counters: &mut Counters,
+        timestep: &mut TimestepManager,
+        gravity: &Vector<Real>,
+        contact_manager: &mut ContactManager,
+        kernel_radius: Real,
+        fluids: &mut [Fluid],
+        boundaries: &[Boundary],
+        hooks: &mut dyn FnMut(SubstepStage, &TimestepManager, &mut [Fluid]),
+    ) {
+        counters.solver.non_pressure_resolution_time.resume();
+        self.predict_advection(
+            timestep,
+            kernel_radius,
+            contact_manager,
+            gravity,
+            fluids,
+            boundaries,
+        );
+        counters.solver.non_pressure_resolution_time.pause();
+        hooks(SubstepStage::AfterAdvectionPrediction, timestep, fluids);
+        counters.solver.integration_time.resume();
+        timestep.advance(fluids);
+        counters.solver.integration_time.pause();
+
+        counters.solver.pressure_resolution_time.resume();
+        self.integrate_densities(
+            timestep.dt(),
+            kernel_radius,
+            contact_manager,
+            fluids,
+            boundaries,
+        );
+        counters.solver.density_error = self.compute_pressures(fluids);
+        self.compute_pressure_accelerations(
+            &contact_manager.fluid_fluid_contacts,
+            &contact_manager.fluid_boundary_contacts,
+            fluids,
+            boundaries,
+        );
+        counters.solver.pressure_resolution_time.pause();
+
+        counters.solver.integration_time.resume();
+        self.integrate_and_clear_accelerations(timestep, fluids);
+        counters.solver.integration_time.pause();
+
+        hooks(SubstepStage::AfterPressureSolve, timestep, fluids);
+        counters.solver.integration_time.resume();
+        self.integrate_positions(timestep, fluids);
+        counters.solver.integration_time.pause();
+    }
+
+    fn max_substep(&self, kernel_radius: Real, fluids: &[Fluid]) -> Option<Real> {
+        let min_kernel_radius = fluids
+            .iter()
+            .map(|fluid| fluid.kernel_radius(kernel_radius))
+            .fold(kernel_radius, Real::min);
+
+        Some(TimestepManager::acoustic_substep_limit(
+            self.speed_of_sound,
+            min_kernel_radius,
+        ))
+    }
+
+    fn densities(&self) -> &[Vec<Real>] {
+        &self.densities
+    }
+
+    fn pressures(&self) -> &[Vec<Real>] {
+        &self.pressures
+    }
+
+    fn apply_permutation(&mut self, fluid_id: usize, permutation: &[usize]) {
+        // The continuity densities are integrated from one step to the next.
+        for buffer in [
+            &mut self.continuity_densities,
+            &mut self.densities,
+            &mut self.pressures,
+        ] {
+            if let Some(values) = buffer.get_mut(fluid_id) {
+                if values.len() == permutation.len() {
+                    *values = crate::z_order::apply_permutation(permutation, values);
+                }
+            }
+        }
+    }
+}
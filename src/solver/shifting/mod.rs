@@ -0,0 +1,3 @@
+pub use self::particle_shifting::ParticleShifting;
+
+mod particle_shifting;
@@ -0,0 +1,186 @@
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use crate::geometry::ContactManager;
+use crate::kernel::{CubicSplineKernel, Kernel};
+use crate::math::{Point, Real, Vector, DIM};
+use crate::object::{Boundary, Fluid};
+
+/// The particle shifting technique, nudging the fluid particles toward a more uniform distribution.
+///
+/// Long simulations tend to develop anisotropic particle clusters and voids which degrade the
+/// accuracy of the pressure resolution. After each substep, each particle is shifted by
+/// `-coefficient * h² * ∇C`, where `∇C` is the gradient of the particle concentration
+/// (Lind et al. 2012), including the tensile correction term of Monaghan 2000. This moves the
+/// particles from high to low concentration regions. Boundary particles contribute to the
+/// concentration, so the particles are also kept away from the boundaries.
+///
+/// Near the free surface, the concentration gradient is dominated by the missing neighbors, so
+/// only the component of the shift tangent to the surface is kept. The particles are considered
+/// to be near the free surface if the divergence of their position (equal to the dimension in the
+/// bulk of the fluid) is smaller than `free_surface_threshold` times the dimension. The
+/// velocities of the particles are not changed.
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub struct ParticleShifting {
+    /// The shifting coefficient, multiplied by the squared kernel radius to obtain the diffusion
+    /// coefficient of the shifting.
+    pub coefficient: Real,
+    /// The maximum shift applied to a particle at each substep, as a fraction of the particle radius.
+    pub max_displacement: Real,
+    /// The fraction of the dimension under which the divergence of the position of a particle
+    /// indicates it is near the free surface.
+    pub free_surface_threshold: Real,
+    /// The coefficient of the tensile correction term of the concentration gradient.
+    pub tensile_coefficient: Real,
+    /// The exponent of the tensile correction term of the concentration gradient.
+    pub tensile_exponent: i32,
+    shifts: Vec<Vec<Vector<Real>>>,
+}
+
+impl ParticleShifting {
+    /// Initializes the particle shifting with its default parameters.
+    pub fn new() -> Self {
+        Self {
+            coefficient: na::convert::<_, Real>(0.5),
+            max_displacement: na::convert::<_, Real>(0.2),
+            free_surface_threshold: na::convert::<_, Real>(0.75),
+            tensile_coefficient: na::convert::<_, Real>(0.2),
+            tensile_exponent: 4,
+            shifts: Vec::new(),
+        }
+    }
+
+    /// Sets the shifting coefficient.
+    pub fn with_coefficient(mut self, coefficient: Real) -> Self {
+        self.coefficient = coefficient;
+        self
+    }
+
+    /// Sets the maximum shift of a particle at each substep, as a fraction of the particle radius.
+    pub fn with_max_displacement(mut self, max_displacement: Real) -> Self {
+        self.max_displacement = max_displacement;
+        self
+    }
+
+    /// Sets the fraction of the dimension used to detect the particles near the free surface.
+    pub fn with_free_surface_threshold(mut self, free_surface_threshold: Real) -> Self {
+        self.free_surface_threshold = free_surface_threshold;
+        self
+    }
+
+    /// Shifts the fluid particles.
+    ///
+    /// The contacts of `contact_manager` must have been computed during the current substep. The
+    /// kernel is re-evaluated with the current particle positions.
+    pub fn solve(
+        &mut self,
+        kernel_radius: Real,
+        particle_radius: Real,
+        contact_manager: &ContactManager,
+        fluids: &mut [Fluid],
+        boundaries: &[Boundary],
+    ) {
+        self.shifts.resize(fluids.len(), Vec::new());
+
+        let diffusion = self.coefficient * kernel_radius * kernel_radius;
+        let max_shift = self.max_displacement * particle_radius;
+        let reference_weight = CubicSplineKernel::scalar_apply(
+            particle_radius * na::convert::<_, Real>(2.0),
+            kernel_radius,
+        );
+        let surface_divergence = self.free_surface_threshold * na::convert::<_, Real>(DIM as f64);
+        let tensile_coefficient = self.tensile_coefficient;
+        let tensile_exponent = self.tensile_exponent;
+        let periodic_domain = contact_manager.periodic_domain();
+
+        for (fluid_id, shifts) in self.shifts.iter_mut().enumerate() {
+            let fluids = &*fluids;
+            let fluid_i = &fluids[fluid_id];
+            let fluid_fluid_contacts = &contact_manager.fluid_fluid_contacts[fluid_id];
+            let fluid_boundary_contacts = &contact_manager.fluid_boundary_contacts[fluid_id];
+            shifts.resize(fluid_i.num_particles(), Vector::zeros());
+
+            par_iter_mut!(shifts).enumerate().for_each(|(i, shift)| {
+                let pi = fluid_i.positions[i];
+                let mut concentration_gradient = Vector::zeros();
+                let mut position_divergence = na::zero::<Real>();
+                let mut num_neighbors = 0;
+
+                let mut add_neighbor = |pj: &Point<Real>, volume_j: Real| {
+                    let pj = periodic_domain.map_or(*pj, |domain| domain.closest_image(&pi, pj));
+                    let pj = &pj;
+                    let weight = CubicSplineKernel::points_apply(&pi, pj, kernel_radius);
+                    let gradient = CubicSplineKernel::points_apply_diff1(&pi, pj, kernel_radius);
+                    let tensile = if reference_weight > na::zero::<Real>() {
+                        tensile_coefficient * (weight / reference_weight).powi(tensile_exponent)
+                    } else {
+                        na::zero::<Real>()
+                    };
+
+                    concentration_gradient += gradient * (volume_j * (na::one::<Real>() + tensile));
+                    position_divergence -= (pi - pj).dot(&gradient) * volume_j;
+                    num_neighbors += 1;
+                };
+
+                for c in fluid_fluid_contacts
+                    .particle_contacts(i)
+                    .read()
+                    .unwrap()
+                    .iter()
+                {
+                    if c.j_model != fluid_id || c.j != i {
+                        let fluid_j = &fluids[c.j_model];
+                        add_neighbor(&fluid_j.positions[c.j], fluid_j.volumes[c.j]);
+                    }
+                }
+
+                for c in fluid_boundary_contacts
+                    .particle_contacts(i)
+                    .read()
+                    .unwrap()
+                    .iter()
+                {
+                    let boundary = &boundaries[c.j_model];
+                    add_neighbor(&boundary.positions[c.j], boundary.volumes[c.j]);
+                }
+
+                // Isolated particles are not shifted.
+                if num_neighbors == 0 {
+                    *shift = Vector::zeros();
+                    return;
+                }
+
+                let mut delta = -concentration_gradient * diffusion;
+
+                if position_divergence < surface_divergence {
+                    // Only keep the tangential component near the free surface.
+                    if let Some(normal) = concentration_gradient.try_normalize(Real::EPSILON) {
+                        delta -= normal * delta.dot(&normal);
+                    }
+                }
+
+                let norm = delta.norm();
+                *shift = if norm > max_shift {
+                    delta * (max_shift / norm)
+                } else {
+                    delta
+                };
+            });
+        }
+
+        for (fluid, shifts) in fluids.iter_mut().zip(self.shifts.iter()) {
+            par_iter_mut!(fluid.positions)
+                .zip(par_iter!(shifts))
+                .for_each(|(position, shift)| *position += shift);
+        }
+    }
+}
+
+impl Default for ParticleShifting {
+    fn default() -> Self {
+        Self::new()
+    }
+}
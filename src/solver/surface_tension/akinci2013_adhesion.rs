@@ -0,0 +1,98 @@
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use approx::AbsDiffEq;
+use na::Unit;
+
+use crate::geometry::ParticlesContacts;
+
+use crate::math::{Real, Vector};
+use crate::object::{Boundary, Fluid};
+use crate::solver::{helper, NonPressureForce, OtherFluids};
+use crate::TimestepManager;
+
+use super::akinci2013_surface_tension::adhesion_kernel;
+
+#[derive(Clone)]
+/// Adhesion forces introduced by Akinci et al. 2013
+///
+/// This attracts fluid particles towards nearby boundary particles, so fluids stick
+/// to and flow along boundary surfaces. The adhesion coefficient is multiplied by the
+/// `Boundary::adhesion_coefficient` of each boundary, allowing per-boundary wetting behaviors.
+///
+/// This is the adhesion model already included in `Akinci2013SurfaceTension`. Use it alone or
+/// combined with another surface tension model, to avoid applying the adhesion twice.
+pub struct Akinci2013Adhesion {
+    /// The adhesion coefficient, typically in [0.0, 1.0].
+    pub adhesion_coefficient: Real,
+}
+
+impl Akinci2013Adhesion {
+    /// Initializes an adhesion force with the given adhesion coefficient.
+    pub fn new(adhesion_coefficient: Real) -> Self {
+        Self {
+            adhesion_coefficient,
+        }
+    }
+}
+
+impl NonPressureForce for Akinci2013Adhesion {
+    fn solve(
+        &mut self,
+        _timestep: &TimestepManager,
+        kernel_radius: Real,
+        _fluid_fluid_contacts: &ParticlesContacts,
+        fluid_boundaries_contacts: &ParticlesContacts,
+        fluid: &mut Fluid,
+        _other_fluids: &OtherFluids,
+        boundaries: &[Boundary],
+        _densities: &[Real],
+    ) {
+        if self.adhesion_coefficient == na::zero::<Real>() {
+            return;
+        }
+
+        let adhesion_coefficient = self.adhesion_coefficient;
+        let volumes = &fluid.volumes;
+        let rest_densities = &fluid.rest_densities;
+        let density0 = fluid.density0;
+        let rest_density = |k| helper::rest_density(rest_densities, density0, k);
+        let positions = &fluid.positions;
+
+        par_iter_mut!(fluid.accelerations)
+            .enumerate()
+            .for_each(|(i, acceleration_i)| {
+                for c in fluid_boundaries_contacts
+                    .particle_contacts(i)
+                    .read()
+                    .unwrap()
+                    .iter()
+                {
+                    let boundary = &boundaries[c.j_model];
+
+                    if boundary.adhesion_coefficient == na::zero::<Real>() {
+                        continue;
+                    }
+
+                    let dpos = positions[c.i] - boundary.positions[c.j];
+                    let adhesion_vec = if let Some((dir, dist)) =
+                        Unit::try_new_and_get(dpos, Real::default_epsilon())
+                    {
+                        *dir * adhesion_kernel(dist, kernel_radius)
+                    } else {
+                        Vector::zeros()
+                    };
+
+                    let mi = volumes[c.i] * rest_density(c.i);
+                    let mj = boundary.volumes[c.j] * rest_density(c.i);
+                    let adhesion_acc =
+                        adhesion_vec * (adhesion_coefficient * boundary.adhesion_coefficient * mj);
+                    *acceleration_i -= adhesion_acc;
+
+                    boundary.apply_force(c.j, adhesion_acc * mi);
+                }
+            })
+    }
+
+    fn apply_permutation(&mut self, _: &[usize]) {}
+}
@@ -8,7 +8,7 @@ use crate::geometry::ParticlesContacts;
 
 use crate::math::{Real, Vector};
 use crate::object::{Boundary, Fluid};
-use crate::solver::NonPressureForce;
+use crate::solver::{helper, NonPressureForce, OtherFluids};
 use crate::TimestepManager;
 
 #[derive(Clone)]
@@ -17,8 +17,10 @@ use crate::TimestepManager;
 /// This combines both cohesion forces as well as curvature minimization forces.
 /// This also includes adhesion forces for fluid/boundary interactions.
 pub struct Akinci2013SurfaceTension {
-    fluid_tension_coefficient: Real,
-    boundary_adhesion_coefficient: Real,
+    /// The surface tension coefficient, controlling both cohesion and curvature minimization.
+    pub fluid_tension_coefficient: Real,
+    /// The adhesion coefficient when interacting with boundaries.
+    pub boundary_adhesion_coefficient: Real,
     normals: Vec<Vector<Real>>,
 }
 
@@ -87,7 +89,7 @@ fn cohesion_kernel(r: Real, h: Real) -> Real {
     normalizer * coeff
 }
 
-fn adhesion_kernel(r: Real, h: Real) -> Real {
+pub(super) fn adhesion_kernel(r: Real, h: Real) -> Real {
     let _2: Real = na::convert::<_, Real>(2.0f64);
 
     if r > h / _2 && r <= h {
@@ -118,6 +120,7 @@ impl NonPressureForce for Akinci2013SurfaceTension {
         fluid_fluid_contacts: &ParticlesContacts,
         fluid_boundaries_contacts: &ParticlesContacts,
         fluid: &mut Fluid,
+        _other_fluids: &OtherFluids,
         boundaries: &[Boundary],
         densities: &[Real],
     ) {
@@ -131,7 +134,9 @@ impl NonPressureForce for Akinci2013SurfaceTension {
         let fluid_tension_coefficient = self.fluid_tension_coefficient;
         let boundary_adhesion_coefficient = self.boundary_adhesion_coefficient;
         let volumes = &mut fluid.volumes;
+        let rest_densities = &fluid.rest_densities;
         let density0 = fluid.density0;
+        let rest_density = |k| helper::rest_density(rest_densities, density0, k);
         let positions = &fluid.positions;
 
         par_iter_mut!(fluid.accelerations)
@@ -155,10 +160,10 @@ impl NonPressureForce for Akinci2013SurfaceTension {
                             };
 
                             let cohesion_acc = cohesion_vec
-                                * (-fluid_tension_coefficient * volumes[c.j] * density0);
+                                * (-fluid_tension_coefficient * volumes[c.j] * rest_density(c.j));
                             let curvature_acc =
                                 (normals[c.i] - normals[c.j]) * -fluid_tension_coefficient;
-                            let kij = _2 * density0 / (densities[c.i] + densities[c.j]);
+                            let kij = _2 * rest_density(c.i) / (densities[c.i] + densities[c.j]);
                             *acceleration_i += (curvature_acc + cohesion_acc) * kij;
                         }
                     }
@@ -180,9 +185,12 @@ impl NonPressureForce for Akinci2013SurfaceTension {
                             Vector::zeros()
                         };
 
-                        let mi = volumes[c.i] * density0;
-                        let mj = boundaries[c.j_model].volumes[c.j] * density0;
-                        let adhesion_acc = adhesion_vec * (boundary_adhesion_coefficient * mj);
+                        let mi = volumes[c.i] * rest_density(c.i);
+                        let mj = boundaries[c.j_model].volumes[c.j] * rest_density(c.i);
+                        let adhesion_acc = adhesion_vec
+                            * (boundary_adhesion_coefficient
+                                * boundaries[c.j_model].adhesion_coefficient
+                                * mj);
                         *acceleration_i -= adhesion_acc;
 
                         boundaries[c.j_model].apply_force(c.j, adhesion_acc * mi);
@@ -191,5 +199,14 @@ impl NonPressureForce for Akinci2013SurfaceTension {
             })
     }
 
+    fn max_substep(&self, kernel_radius: Real, fluid: &Fluid) -> Option<Real> {
+        // The capillary limit, using the tension coefficient as the surface tension.
+        Some(TimestepManager::surface_tension_substep_limit(
+            self.fluid_tension_coefficient,
+            fluid.density0,
+            kernel_radius,
+        ))
+    }
+
     fn apply_permutation(&mut self, _: &[usize]) {}
 }
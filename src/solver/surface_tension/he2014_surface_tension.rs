@@ -5,7 +5,7 @@ use crate::geometry::ParticlesContacts;
 
 use crate::math::{Real, Vector};
 use crate::object::{Boundary, Fluid};
-use crate::solver::NonPressureForce;
+use crate::solver::{helper, NonPressureForce, OtherFluids};
 use crate::TimestepManager;
 
 // http://peridynamics.com/publications/2014-He-RSS.pdf
@@ -113,6 +113,7 @@ impl NonPressureForce for He2014SurfaceTension {
         fluid_fluid_contacts: &ParticlesContacts,
         fluid_boundary_contacts: &ParticlesContacts,
         fluid: &mut Fluid,
+        _other_fluids: &OtherFluids,
         boundaries: &[Boundary],
         densities: &[Real],
     ) {
@@ -132,13 +133,15 @@ impl NonPressureForce for He2014SurfaceTension {
         let gradcs = &self.gradcs;
         let fluid_tension_coefficient = self.fluid_tension_coefficient;
         let boundary_tension_coefficient = self.boundary_tension_coefficient;
+        let rest_densities = &fluid.rest_densities;
         let density0 = fluid.density0;
+        let rest_density = |k| helper::rest_density(rest_densities, density0, k);
         let volumes = &fluid.volumes;
 
         par_iter_mut!(fluid.accelerations)
             .enumerate()
             .for_each(|(i, acceleration_i)| {
-                let mi = volumes[i] * density0;
+                let mi = volumes[i] * rest_density(i);
 
                 if fluid_tension_coefficient != na::zero::<Real>() {
                     for c in fluid_fluid_contacts
@@ -148,7 +151,7 @@ impl NonPressureForce for He2014SurfaceTension {
                         .iter()
                     {
                         if c.i_model == c.j_model {
-                            let mj = volumes[c.j] * density0;
+                            let mj = volumes[c.j] * rest_density(c.j);
                             let gradsum = gradcs[c.i] + gradcs[c.j];
                             let f = c.gradient
                                 * (mi / densities[c.i] * mj / densities[c.j] * gradsum / _2);
@@ -164,10 +167,10 @@ impl NonPressureForce for He2014SurfaceTension {
                         .unwrap()
                         .iter()
                     {
-                        let mj = boundaries[c.j_model].volumes[c.j] * density0;
+                        let mj = boundaries[c.j_model].volumes[c.j] * rest_density(i);
                         let gradsum = gradcs[c.i];
                         let f = c.gradient
-                            * (mi / densities[c.i] * mj / density0
+                            * (mi / densities[c.i] * mj / rest_density(i)
                                 * gradsum
                                 * boundary_tension_coefficient
                                 * na::convert::<_, Real>(0.25));
@@ -179,5 +182,14 @@ impl NonPressureForce for He2014SurfaceTension {
             })
     }
 
+    fn max_substep(&self, kernel_radius: Real, fluid: &Fluid) -> Option<Real> {
+        // The capillary limit, using the tension coefficient as the surface tension.
+        Some(TimestepManager::surface_tension_substep_limit(
+            self.fluid_tension_coefficient,
+            fluid.density0,
+            kernel_radius,
+        ))
+    }
+
     fn apply_permutation(&mut self, _: &[usize]) {}
 }
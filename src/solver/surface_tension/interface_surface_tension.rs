@@ -0,0 +1,163 @@
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use na::DMatrix;
+#[cfg(feature = "dim3")]
+use na::RealField;
+
+use crate::geometry::ParticlesContacts;
+use crate::kernel::{CubicSplineKernel, Kernel};
+use crate::math::Real;
+use crate::object::{Boundary, Fluid};
+use crate::solver::{helper, NonPressureForce, OtherFluids};
+use crate::TimestepManager;
+
+/// An interfacial tension between the particles of immiscible fluids in contact.
+///
+/// Each pair of particles `i` and `j` of two different fluids stores the interface energy
+/// `σ / M * V_i * V_j * W_ij`, where `σ` is the interfacial tension between both fluids, in `N/m`,
+/// `V` the particle volumes, and `W_ij` the kernel. `M` is the first moment of the kernel over a
+/// half-space, so the energy per unit area of a flat interface between two fluids with uniform
+/// particle distributions is `σ`. The resulting pairwise forces push both fluids apart along
+/// their interface, which minimizes its area, e.g., makes oil droplets and air bubbles rounded,
+/// and makes the droplets of the same fluid merge. Unlike the free-surface tensions, this only
+/// acts at the interface between two fluids, so it can be combined with them. The forces of each
+/// pair are opposite so the momentum is conserved.
+///
+/// The normalization `M` assumes the `CubicSplineKernel` is used by the pressure solver. With
+/// another kernel, the interfacial tension is scaled by a constant factor.
+///
+/// This force must be added to each fluid of the pairs with a non-zero tension, with the same
+/// coefficients, for the forces to be symmetric.
+#[derive(Clone)]
+pub struct InterfaceSurfaceTension {
+    /// The interfacial tensions between each pair of fluids, in `N/m`.
+    ///
+    /// The tension between the `i`-th and `j`-th fluids of `LiquidWorld::fluids().as_slice()` is
+    /// `coefficients[(i, j)]`. This matrix should be symmetric, and the coefficients missing from
+    /// it are zero.
+    pub coefficients: DMatrix<Real>,
+}
+
+impl InterfaceSurfaceTension {
+    /// Initializes an interfacial tension with the given matrix of tension coefficients.
+    pub fn new(coefficients: DMatrix<Real>) -> Self {
+        Self { coefficients }
+    }
+
+    /// Sets the interfacial tension between the `fluid1`-th and the `fluid2`-th fluids.
+    ///
+    /// Both symmetric entries of the coefficient matrix are set, and the matrix is grown if
+    /// needed.
+    pub fn with_coefficient(mut self, fluid1: usize, fluid2: usize, coefficient: Real) -> Self {
+        let size = self.coefficients.nrows().max(fluid1 + 1).max(fluid2 + 1);
+
+        if size > self.coefficients.nrows() || size > self.coefficients.ncols() {
+            let coefficients = std::mem::replace(&mut self.coefficients, DMatrix::zeros(0, 0));
+            self.coefficients = coefficients.resize(size, size, na::zero::<Real>());
+        }
+
+        self.coefficients[(fluid1, fluid2)] = coefficient;
+        self.coefficients[(fluid2, fluid1)] = coefficient;
+        self
+    }
+
+    /// The interfacial tension between the `fluid1`-th and the `fluid2`-th fluids.
+    pub fn coefficient(&self, fluid1: usize, fluid2: usize) -> Real {
+        self.coefficients
+            .get((fluid1, fluid2))
+            .copied()
+            .unwrap_or_else(na::zero::<Real>)
+    }
+}
+
+// The first moment `∫_{z > 0} z W(x) dx` of the cubic spline kernel over a half-space, i.e.,
+// the interface energy per unit area of two half-spaces with uniform unit volume densities.
+fn half_space_first_moment(kernel_radius: Real) -> Real {
+    const NUM_SAMPLES: usize = 64;
+    let dr = kernel_radius / na::convert::<_, Real>(NUM_SAMPLES as f64);
+    let mut moment = na::zero::<Real>();
+
+    for k in 0..NUM_SAMPLES {
+        let r = (na::convert::<_, Real>(k as f64) + na::convert::<_, Real>(0.5)) * dr;
+        let w = CubicSplineKernel::scalar_apply(r, kernel_radius);
+
+        // The average of `|cos θ|` is `2/π` on a circle and `1/2` on a sphere.
+        #[cfg(feature = "dim2")]
+        {
+            moment += w * r * r * na::convert::<_, Real>(2.0);
+        }
+        #[cfg(feature = "dim3")]
+        {
+            moment += w * r * r * r * Real::pi();
+        }
+    }
+
+    moment * dr
+}
+
+impl NonPressureForce for InterfaceSurfaceTension {
+    fn solve(
+        &mut self,
+        _timestep: &TimestepManager,
+        kernel_radius: Real,
+        fluid_fluid_contacts: &ParticlesContacts,
+        _fluid_boundaries_contacts: &ParticlesContacts,
+        fluid: &mut Fluid,
+        other_fluids: &OtherFluids,
+        _boundaries: &[Boundary],
+        _densities: &[Real],
+    ) {
+        let fluid_id = other_fluids.fluid_id();
+
+        if (0..other_fluids.num_fluids())
+            .all(|j| self.coefficient(fluid_id, j) == na::zero::<Real>())
+        {
+            return;
+        }
+
+        let inv_moment = na::one::<Real>() / half_space_first_moment(kernel_radius);
+        let rest_densities = &fluid.rest_densities;
+        let density0 = fluid.density0;
+        let rest_density = |k| helper::rest_density(rest_densities, density0, k);
+        let coefficient = |model| self.coefficient(fluid_id, model);
+
+        par_iter_mut!(fluid.accelerations)
+            .enumerate()
+            .for_each(|(i, acceleration)| {
+                for c in fluid_fluid_contacts
+                    .particle_contacts(i)
+                    .read()
+                    .unwrap()
+                    .iter()
+                {
+                    let Some(fluid_j) = other_fluids.fluid(c.j_model) else {
+                        continue;
+                    };
+                    let coefficient = coefficient(c.j_model);
+
+                    if coefficient != na::zero::<Real>() {
+                        // The force `-σ / M * V_i * V_j * ∇W_ij` divided by the mass
+                        // `V_i * ρ_i` of the particle.
+                        *acceleration -= c.gradient
+                            * (coefficient * inv_moment * fluid_j.volumes[c.j] / rest_density(i));
+                    }
+                }
+            })
+    }
+
+    fn max_substep(&self, kernel_radius: Real, fluid: &Fluid) -> Option<Real> {
+        let max_coefficient = self
+            .coefficients
+            .iter()
+            .copied()
+            .fold(na::zero::<Real>(), Real::max);
+
+        // The capillary limit of the strongest interfacial tension.
+        Some(TimestepManager::surface_tension_substep_limit(
+            max_coefficient,
+            fluid.density0,
+            kernel_radius,
+        ))
+    }
+}
@@ -1,7 +1,11 @@
+pub use self::akinci2013_adhesion::Akinci2013Adhesion;
 pub use self::akinci2013_surface_tension::Akinci2013SurfaceTension;
 pub use self::he2014_surface_tension::He2014SurfaceTension;
+pub use self::interface_surface_tension::InterfaceSurfaceTension;
 pub use self::wcsph_surface_tension::WCSPHSurfaceTension;
 
+mod akinci2013_adhesion;
 mod akinci2013_surface_tension;
 mod he2014_surface_tension;
+mod interface_surface_tension;
 mod wcsph_surface_tension;
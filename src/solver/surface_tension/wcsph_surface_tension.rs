@@ -5,7 +5,7 @@ use crate::geometry::ParticlesContacts;
 
 use crate::math::Real;
 use crate::object::{Boundary, Fluid};
-use crate::solver::NonPressureForce;
+use crate::solver::{helper, NonPressureForce, OtherFluids};
 use crate::TimestepManager;
 
 // Surface tension of water: 0.01
@@ -36,6 +36,7 @@ impl NonPressureForce for WCSPHSurfaceTension {
         fluid_fluid_contacts: &ParticlesContacts,
         _fluid_boundaries_contacts: &ParticlesContacts,
         fluid: &mut Fluid,
+        _other_fluids: &OtherFluids,
         boundaries: &[Boundary],
         _densities: &[Real],
     ) {
@@ -43,7 +44,9 @@ impl NonPressureForce for WCSPHSurfaceTension {
         let boundary_tension_coefficient = self.boundary_tension_coefficient;
         let positions = &fluid.positions;
         let volumes = &fluid.volumes;
+        let rest_densities = &fluid.rest_densities;
         let density0 = fluid.density0;
+        let rest_density = |k| helper::rest_density(rest_densities, density0, k);
 
         par_iter_mut!(fluid.accelerations)
             .enumerate()
@@ -58,8 +61,11 @@ impl NonPressureForce for WCSPHSurfaceTension {
                         if c.i_model == c.j_model {
                             let dpos = positions[c.i] - positions[c.j];
                             let cohesion_acc = dpos
-                                * (-fluid_tension_coefficient * c.weight * volumes[c.j] * density0
-                                    / (volumes[c.i] * density0));
+                                * (-fluid_tension_coefficient
+                                    * c.weight
+                                    * volumes[c.j]
+                                    * rest_density(c.j)
+                                    / (volumes[c.i] * rest_density(c.i)));
                             *acceleration_i += cohesion_acc;
                         }
                     }
@@ -73,12 +79,12 @@ impl NonPressureForce for WCSPHSurfaceTension {
                         .iter()
                     {
                         let dpos = positions[c.i] - boundaries[c.j_model].positions[c.j];
-                        let mi = volumes[c.i] * density0;
+                        let mi = volumes[c.i] * rest_density(c.i);
                         let cohesion_force = dpos
                             * (boundary_tension_coefficient
                                 * c.weight
                                 * boundaries[c.j_model].volumes[c.j]
-                                * density0);
+                                * rest_density(c.i));
                         *acceleration_i -= cohesion_force / mi;
                         boundaries[c.j_model].apply_force(c.j, cohesion_force);
                     }
@@ -86,5 +92,14 @@ impl NonPressureForce for WCSPHSurfaceTension {
             })
     }
 
+    fn max_substep(&self, kernel_radius: Real, fluid: &Fluid) -> Option<Real> {
+        // The capillary limit, using the tension coefficient as the surface tension.
+        Some(TimestepManager::surface_tension_substep_limit(
+            self.fluid_tension_coefficient,
+            fluid.density0,
+            kernel_radius,
+        ))
+    }
+
     fn apply_permutation(&mut self, _: &[usize]) {}
 }
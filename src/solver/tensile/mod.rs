@@ -0,0 +1,3 @@
+pub use self::artificial_pressure::ArtificialPressure;
+
+mod artificial_pressure;
@@ -3,9 +3,9 @@ use rayon::prelude::*;
 
 use crate::geometry::ParticlesContacts;
 
-use crate::math::{Real, Vector};
+use crate::math::{Real, Vector, DIM};
 use crate::object::{Boundary, Fluid};
-use crate::solver::NonPressureForce;
+use crate::solver::{helper, NonPressureForce, OtherFluids};
 use crate::TimestepManager;
 
 // See http://www.astro.lu.se/~david/teaching/SPH/notes/annurev.aa.30.090192.pdf
@@ -20,6 +20,11 @@ pub struct ArtificialViscosity {
     pub speed_of_sound: Real,
     /// The fluid viscosity coefficient.
     pub fluid_viscosity_coefficient: Real,
+    /// The viscosity coefficient when interacting with the particles of other fluids.
+    ///
+    /// This defaults to zero. Give the same coefficient to the artificial viscosities of both
+    /// fluids so that the viscous forces between them are symmetric.
+    pub interphase_viscosity_coefficient: Real,
     /// The viscosity coefficient when interacting with boundaries.
     pub boundary_viscosity_coefficient: Real,
 }
@@ -32,6 +37,7 @@ impl ArtificialViscosity {
             beta: na::convert::<_, Real>(0.0),
             speed_of_sound: na::convert::<_, Real>(10.0),
             fluid_viscosity_coefficient,
+            interphase_viscosity_coefficient: na::zero::<Real>(),
             boundary_viscosity_coefficient,
         }
     }
@@ -45,76 +51,103 @@ impl NonPressureForce for ArtificialViscosity {
         fluid_fluid_contacts: &ParticlesContacts,
         fluid_boundaries_contacts: &ParticlesContacts,
         fluid: &mut Fluid,
+        other_fluids: &OtherFluids,
         boundaries: &[Boundary],
         densities: &[Real],
     ) {
         let fluid_viscosity_coefficient = self.fluid_viscosity_coefficient;
+        let interphase_viscosity_coefficient = self.interphase_viscosity_coefficient;
         let boundary_viscosity_coefficient = self.boundary_viscosity_coefficient;
-        let speed_of_sound = self.speed_of_sound;
-        let alpha = self.alpha;
+        // The linear term of the viscosity is `speed_of_sound * alpha * mu_ij`.
+        let linear_coefficient = self.speed_of_sound * self.alpha;
         let beta = self.beta;
+        let rest_densities = &fluid.rest_densities;
         let density0 = fluid.density0;
+        let rest_density = |k| helper::rest_density(rest_densities, density0, k);
         let volumes = &fluid.volumes;
         let positions = &fluid.positions;
         let velocities = &fluid.velocities;
         let _0_5: Real = na::convert::<_, Real>(0.5);
+        // Avoids singularities when two particles are very close.
+        let eta2 = kernel_radius * kernel_radius * na::convert::<_, Real>(0.01);
 
         par_iter_mut!(fluid.accelerations)
             .enumerate()
             .for_each(|(i, acceleration)| {
                 let mut fluid_acc = Vector::zeros();
                 let mut boundary_acc = Vector::zeros();
+                let pi = positions[i];
+                let vi = velocities[i];
 
-                if self.fluid_viscosity_coefficient != na::zero::<Real>() {
+                if fluid_viscosity_coefficient != na::zero::<Real>()
+                    || interphase_viscosity_coefficient != na::zero::<Real>()
+                {
                     for c in fluid_fluid_contacts
                         .particle_contacts(i)
                         .read()
                         .unwrap()
                         .iter()
                     {
-                        if c.i_model == c.j_model {
-                            let r_ij = positions[c.i] - positions[c.j];
-                            let v_ij = velocities[c.i] - velocities[c.j];
-                            let vr = r_ij.dot(&v_ij);
-
-                            if vr < na::zero::<Real>() {
-                                let density_average = (densities[c.i] + densities[c.j]) * _0_5;
-                                let eta2 =
-                                    kernel_radius * kernel_radius * na::convert::<_, Real>(0.01);
-                                let mu_ij = kernel_radius * vr / (r_ij.norm_squared() + eta2);
-
-                                fluid_acc += c.gradient
-                                    * (fluid_viscosity_coefficient
-                                        * (speed_of_sound * alpha * mu_ij - beta * mu_ij * mu_ij)
-                                        * (volumes[c.j] * density0 / density_average));
-                            }
+                        let (pj, vj, mj, rhoj, coefficient) = if c.i_model == c.j_model {
+                            (
+                                positions[c.j],
+                                velocities[c.j],
+                                volumes[c.j] * rest_density(c.j),
+                                densities[c.j],
+                                fluid_viscosity_coefficient,
+                            )
+                        } else if let Some(fluid_j) = other_fluids.fluid(c.j_model) {
+                            (
+                                fluid_j.positions[c.j],
+                                fluid_j.velocities[c.j],
+                                fluid_j.particle_mass(c.j),
+                                other_fluids.densities(c.j_model)[c.j],
+                                interphase_viscosity_coefficient,
+                            )
+                        } else {
+                            continue;
+                        };
+
+                        let r_ij = pi - pj;
+                        let v_ij = vi - vj;
+                        let vr = r_ij.dot(&v_ij);
+
+                        if vr < na::zero::<Real>() && coefficient != na::zero::<Real>() {
+                            let density_average = (densities[c.i] + rhoj) * _0_5;
+                            let mu_ij = kernel_radius * vr / (r_ij.norm_squared() + eta2);
+
+                            fluid_acc += c.gradient
+                                * (coefficient
+                                    * (linear_coefficient * mu_ij - beta * mu_ij * mu_ij)
+                                    * (mj / density_average));
                         }
                     }
                 }
 
-                if self.boundary_viscosity_coefficient != na::zero::<Real>() {
+                if boundary_viscosity_coefficient != na::zero::<Real>() {
+                    let density_average = densities[i];
+                    let mi = volumes[i] * rest_density(i);
+
                     for c in fluid_boundaries_contacts
                         .particle_contacts(i)
                         .read()
                         .unwrap()
                         .iter()
                     {
-                        let r_ij = positions[c.i] - boundaries[c.j_model].positions[c.j];
-                        let v_ij = velocities[c.i] - boundaries[c.j_model].velocities[c.j];
+                        let boundary = &boundaries[c.j_model];
+                        let r_ij = pi - boundary.positions[c.j];
+                        let v_ij = vi - boundary.velocities[c.j];
                         let vr = r_ij.dot(&v_ij);
 
                         if vr < na::zero::<Real>() {
-                            let density_average = densities[c.i];
-                            let eta2 = kernel_radius * kernel_radius * na::convert::<_, Real>(0.01);
                             let mu_ij = kernel_radius * vr / (r_ij.norm_squared() + eta2);
 
-                            boundary_acc += c.gradient
+                            let acc = c.gradient
                                 * (boundary_viscosity_coefficient
-                                    * (speed_of_sound * alpha * mu_ij - beta * mu_ij * mu_ij)
-                                    * (boundaries[c.j_model].volumes[c.j] * density0
-                                        / density_average));
-                            let mi = volumes[c.i] * density0;
-                            boundaries[c.j_model].apply_force(c.j, boundary_acc * -mi);
+                                    * (linear_coefficient * mu_ij - beta * mu_ij * mu_ij)
+                                    * (boundary.volumes[c.j] * rest_density(i) / density_average));
+                            boundary_acc += acc;
+                            boundary.apply_force(c.j, acc * -mi);
                         }
                     }
                 }
@@ -123,5 +156,16 @@ impl NonPressureForce for ArtificialViscosity {
             })
     }
 
+    fn max_substep(&self, kernel_radius: Real, _fluid: &Fluid) -> Option<Real> {
+        // The kinematic viscosity equivalent to the linear part of the artificial viscosity.
+        let viscosity =
+            self.fluid_viscosity_coefficient * self.alpha * self.speed_of_sound * kernel_radius
+                / na::convert::<_, Real>(2.0 * (DIM as f64 + 2.0));
+        Some(TimestepManager::viscosity_substep_limit(
+            viscosity,
+            kernel_radius,
+        ))
+    }
+
     fn apply_permutation(&mut self, _: &[usize]) {}
 }
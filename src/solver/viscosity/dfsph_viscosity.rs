@@ -4,7 +4,7 @@ use rayon::prelude::*;
 use crate::geometry::ParticlesContacts;
 use crate::math::{Real, Vector, SPATIAL_DIM};
 use crate::object::{Boundary, Fluid};
-use crate::solver::NonPressureForce;
+use crate::solver::{helper, NonPressureForce, OtherFluids};
 use crate::TimestepManager;
 
 #[cfg(feature = "dim2")]
@@ -259,7 +259,9 @@ impl DFSPHViscosity {
         let strain_rates = &self.strain_rates;
         let betas = &self.betas;
         let volumes = &fluid.volumes;
+        let rest_densities = &fluid.rest_densities;
         let density0 = fluid.density0;
+        let rest_density = |k| helper::rest_density(rest_densities, density0, k);
         let _2: Real = na::convert::<_, Real>(2.0);
 
         par_iter_mut!(fluid.accelerations)
@@ -279,9 +281,9 @@ impl DFSPHViscosity {
                         let gradient = compute_gradient_matrix(&c.gradient);
 
                         // Compute velocity change.
-                        let coeff = (ui + uj) * (volumes[c.j] * density0 / _2);
-                        *acceleration +=
-                            gradient.tr_mul(&coeff) * (volumes[c.i] * density0 * timestep.inv_dt());
+                        let coeff = (ui + uj) * (volumes[c.j] * rest_density(c.j) / _2);
+                        *acceleration += gradient.tr_mul(&coeff)
+                            * (volumes[c.i] * rest_density(c.i) * timestep.inv_dt());
                     }
                 }
             })
@@ -296,6 +298,7 @@ impl NonPressureForce for DFSPHViscosity {
         fluid_fluid_contacts: &ParticlesContacts,
         _fluid_boundaries_contacts: &ParticlesContacts,
         fluid: &mut Fluid,
+        _other_fluids: &OtherFluids,
         _boundaries: &[Boundary],
         densities: &[Real],
     ) {
@@ -310,12 +313,12 @@ impl NonPressureForce for DFSPHViscosity {
                 self.compute_strain_rates(timestep, fluid_fluid_contacts, fluid, densities, true);
 
             if avg_err <= self.max_viscosity_error && i >= self.min_viscosity_iter {
-                //                println!(
-                //                    "Average viscosity error: {}, break after niters: {}, unstable: {}",
-                //                    avg_err,
-                //                    i,
-                //                    avg_err > last_err
-                //                );
+                #[cfg(feature = "log")]
+                log::debug!(
+                    "Average viscosity error: {}, break after niters: {}",
+                    avg_err,
+                    i
+                );
                 break;
             }
 
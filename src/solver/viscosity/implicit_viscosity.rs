@@ -0,0 +1,424 @@
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use crate::geometry::ParticlesContacts;
+use crate::math::{Matrix, Real, Vector, DIM};
+use crate::object::{Boundary, Fluid};
+use crate::solver::{NonPressureForce, OtherFluids};
+use crate::TimestepManager;
+
+// See "Weiler et al. 2018, A Physically Consistent Implicit Viscosity Solver for SPH Fluids".
+/// Implements an implicit viscosity model, stable even for very viscous fluids.
+///
+/// The viscous velocities are obtained by solving a linear system with a matrix-free
+/// stabilized bi-conjugate gradient (BiCGSTAB) preconditioned with the inverse of the diagonal
+/// blocks of the system. This system isn't symmetric when the neighbor particles have different
+/// volumes, or when the kernel gradients are corrected, so a plain conjugate gradient may not
+/// converge. The system is integrated over the predicted length of the upcoming substep (see
+/// `TimestepManager::predicted_dt`).
+#[derive(Clone)]
+pub struct ImplicitViscosity {
+    /// The kinematic viscosity of the fluid.
+    pub fluid_viscosity_coefficient: Real,
+    /// The kinematic viscosity used when interacting with boundaries.
+    pub boundary_viscosity_coefficient: Real,
+    /// Maximum number of BiCGSTAB iterations.
+    pub max_viscosity_iter: usize,
+    /// Maximum acceptable residual of the linear system, relative to the norm of its right-hand-side.
+    ///
+    /// The solver will continue iterating until the residual drops bellow this threshold,
+    /// or until the maximum number of iterations is reached.
+    pub max_viscosity_error: Real,
+    // The kinematic viscosity of each fluid particle.
+    pub(crate) particle_viscosities: Vec<Real>,
+    inv_diagonals: Vec<Matrix<Real>>,
+    predicted_velocities: Vec<Vector<Real>>,
+    solution: Vec<Vector<Real>>,
+    residuals: Vec<Vector<Real>>,
+    shadow_residuals: Vec<Vector<Real>>,
+    directions: Vec<Vector<Real>>,
+    preconditioned: Vec<Vector<Real>>,
+    products: Vec<Vector<Real>>,
+    stabilizer_products: Vec<Vector<Real>>,
+}
+
+// The inputs of the viscosity solve of a single fluid.
+pub(crate) struct ViscosityContext<'a> {
+    // The length of the substep the viscosity is integrated over.
+    pub dt: Real,
+    pub kernel_radius: Real,
+    pub fluid_fluid_contacts: &'a ParticlesContacts,
+    pub fluid_boundaries_contacts: &'a ParticlesContacts,
+    pub boundaries: &'a [Boundary],
+    pub densities: &'a [Real],
+}
+
+// The matrix `M` such that the contribution of the pair `ij` to the velocity laplacian
+// is `M * (v_i - v_j)`.
+fn laplacian_matrix(
+    gradient: &Vector<Real>,
+    r_ij: &Vector<Real>,
+    volume_j: Real,
+    kernel_radius: Real,
+) -> Matrix<Real> {
+    let coeff = na::convert::<_, Real>(2.0 * (DIM as f64 + 2.0)) * volume_j
+        / (r_ij.norm_squared() + kernel_radius * kernel_radius * na::convert::<_, Real>(0.01));
+    gradient * r_ij.transpose() * coeff
+}
+
+fn dot(a: &[Vector<Real>], b: &[Vector<Real>]) -> Real {
+    let it = par_iter!(a).zip(par_iter!(b)).map(|(a, b)| a.dot(b));
+    par_reduce_sum!(na::zero::<Real>(), it)
+}
+
+impl ImplicitViscosity {
+    /// Initializes the implicit viscosity with the given kinematic viscosity coefficients.
+    pub fn new(fluid_viscosity_coefficient: Real, boundary_viscosity_coefficient: Real) -> Self {
+        Self {
+            fluid_viscosity_coefficient,
+            boundary_viscosity_coefficient,
+            max_viscosity_iter: 100,
+            max_viscosity_error: na::convert::<_, Real>(0.01),
+            particle_viscosities: Vec::new(),
+            inv_diagonals: Vec::new(),
+            predicted_velocities: Vec::new(),
+            solution: Vec::new(),
+            residuals: Vec::new(),
+            shadow_residuals: Vec::new(),
+            directions: Vec::new(),
+            preconditioned: Vec::new(),
+            products: Vec::new(),
+            stabilizer_products: Vec::new(),
+        }
+    }
+
+    fn init(&mut self, dt: Real, fluid: &Fluid) {
+        let nparticles = fluid.num_particles();
+        self.inv_diagonals.resize(nparticles, Matrix::identity());
+        self.predicted_velocities
+            .resize(nparticles, Vector::zeros());
+        self.solution.resize(nparticles, Vector::zeros());
+        self.residuals.resize(nparticles, Vector::zeros());
+        self.shadow_residuals.resize(nparticles, Vector::zeros());
+        self.directions.resize(nparticles, Vector::zeros());
+        self.preconditioned.resize(nparticles, Vector::zeros());
+        self.products.resize(nparticles, Vector::zeros());
+        self.stabilizer_products.resize(nparticles, Vector::zeros());
+
+        par_iter_mut!(self.predicted_velocities)
+            .zip(par_iter!(fluid.velocities))
+            .zip(par_iter!(fluid.accelerations))
+            .for_each(|((predicted, vel), acc)| *predicted = *vel + *acc * dt);
+    }
+
+    fn compute_inv_diagonals(&mut self, ctx: &ViscosityContext, fluid: &Fluid) {
+        let ViscosityContext {
+            dt,
+            kernel_radius,
+            fluid_fluid_contacts,
+            fluid_boundaries_contacts,
+            boundaries,
+            densities,
+        } = *ctx;
+        let particle_viscosities = &self.particle_viscosities;
+        let half: Real = na::convert::<_, Real>(0.5);
+        let boundary_viscosity_coefficient = self.boundary_viscosity_coefficient;
+
+        par_iter_mut!(self.inv_diagonals)
+            .enumerate()
+            .for_each(|(i, inv_diagonal)| {
+                let mut laplacian = Matrix::zeros();
+
+                for c in fluid_fluid_contacts
+                    .particle_contacts(i)
+                    .read()
+                    .unwrap()
+                    .iter()
+                {
+                    if c.i_model == c.j_model && c.i != c.j {
+                        let r_ij = fluid.positions[c.i] - fluid.positions[c.j];
+                        let volume_j = fluid.particle_mass(c.j) / densities[c.j];
+                        let viscosity_ij =
+                            (particle_viscosities[c.i] + particle_viscosities[c.j]) * half;
+                        laplacian += laplacian_matrix(&c.gradient, &r_ij, volume_j, kernel_radius)
+                            * viscosity_ij;
+                    }
+                }
+
+                for c in fluid_boundaries_contacts
+                    .particle_contacts(i)
+                    .read()
+                    .unwrap()
+                    .iter()
+                {
+                    let boundary = &boundaries[c.j_model];
+                    let r_ij = fluid.positions[c.i] - boundary.positions[c.j];
+                    laplacian +=
+                        laplacian_matrix(&c.gradient, &r_ij, boundary.volumes[c.j], kernel_radius)
+                            * boundary_viscosity_coefficient;
+                }
+
+                let diagonal = Matrix::identity() - laplacian * dt;
+                *inv_diagonal = diagonal.try_inverse().unwrap_or_else(Matrix::identity);
+            })
+    }
+
+    // Computes `out = A * x` where `A` is the matrix of the linear system.
+    fn apply_operator(
+        &self,
+        ctx: &ViscosityContext,
+        fluid: &Fluid,
+        x: &[Vector<Real>],
+        out: &mut [Vector<Real>],
+    ) {
+        let ViscosityContext {
+            dt,
+            kernel_radius,
+            fluid_fluid_contacts,
+            fluid_boundaries_contacts,
+            boundaries,
+            densities,
+        } = *ctx;
+        let particle_viscosities = &self.particle_viscosities;
+        let half: Real = na::convert::<_, Real>(0.5);
+        let boundary_viscosity_coefficient = self.boundary_viscosity_coefficient;
+
+        par_iter_mut!(out).enumerate().for_each(|(i, out_i)| {
+            let mut laplacian = Vector::zeros();
+
+            for c in fluid_fluid_contacts
+                .particle_contacts(i)
+                .read()
+                .unwrap()
+                .iter()
+            {
+                if c.i_model == c.j_model && c.i != c.j {
+                    let r_ij = fluid.positions[c.i] - fluid.positions[c.j];
+                    let volume_j = fluid.particle_mass(c.j) / densities[c.j];
+                    let viscosity_ij =
+                        (particle_viscosities[c.i] + particle_viscosities[c.j]) * half;
+                    laplacian += laplacian_matrix(&c.gradient, &r_ij, volume_j, kernel_radius)
+                        * (x[c.i] - x[c.j])
+                        * viscosity_ij;
+                }
+            }
+
+            for c in fluid_boundaries_contacts
+                .particle_contacts(i)
+                .read()
+                .unwrap()
+                .iter()
+            {
+                let boundary = &boundaries[c.j_model];
+                let r_ij = fluid.positions[c.i] - boundary.positions[c.j];
+                laplacian +=
+                    laplacian_matrix(&c.gradient, &r_ij, boundary.volumes[c.j], kernel_radius)
+                        * x[c.i]
+                        * boundary_viscosity_coefficient;
+            }
+
+            *out_i = x[i] - laplacian * dt;
+        })
+    }
+
+    fn compute_rhs(&mut self, ctx: &ViscosityContext, fluid: &Fluid) {
+        let dt = ctx.dt;
+        let kernel_radius = ctx.kernel_radius;
+        let fluid_boundaries_contacts = ctx.fluid_boundaries_contacts;
+        let boundaries = ctx.boundaries;
+        let boundary_viscosity_coefficient = self.boundary_viscosity_coefficient;
+        let predicted_velocities = &self.predicted_velocities;
+
+        // The residuals are initialized with the right-hand-side.
+        par_iter_mut!(self.residuals)
+            .enumerate()
+            .for_each(|(i, rhs_i)| {
+                let mut laplacian = Vector::zeros();
+
+                for c in fluid_boundaries_contacts
+                    .particle_contacts(i)
+                    .read()
+                    .unwrap()
+                    .iter()
+                {
+                    let boundary = &boundaries[c.j_model];
+                    let r_ij = fluid.positions[c.i] - boundary.positions[c.j];
+                    laplacian +=
+                        laplacian_matrix(&c.gradient, &r_ij, boundary.volumes[c.j], kernel_radius)
+                            * boundary.velocities[c.j]
+                            * boundary_viscosity_coefficient;
+                }
+
+                *rhs_i = predicted_velocities[i] - laplacian * dt;
+            })
+    }
+
+    // Computes `out = P⁻¹ * x` where `P` is the block-diagonal preconditioner.
+    fn precondition(inv_diagonals: &[Matrix<Real>], x: &[Vector<Real>], out: &mut [Vector<Real>]) {
+        par_iter_mut!(out)
+            .enumerate()
+            .for_each(|(i, out_i)| *out_i = inv_diagonals[i] * x[i]);
+    }
+
+    fn apply_boundary_forces(&self, ctx: &ViscosityContext, fluid: &Fluid) {
+        let kernel_radius = ctx.kernel_radius;
+        let boundaries = ctx.boundaries;
+        let boundary_viscosity_coefficient = self.boundary_viscosity_coefficient;
+        let solution = &self.solution;
+
+        par_iter!(ctx.fluid_boundaries_contacts.contacts()).for_each(|contacts| {
+            for c in contacts.read().unwrap().iter() {
+                let boundary = &boundaries[c.j_model];
+                let r_ij = fluid.positions[c.i] - boundary.positions[c.j];
+                let acc =
+                    laplacian_matrix(&c.gradient, &r_ij, boundary.volumes[c.j], kernel_radius)
+                        * (solution[c.i] - boundary.velocities[c.j])
+                        * boundary_viscosity_coefficient;
+                boundary.apply_force(c.j, acc * -fluid.particle_mass(c.i));
+            }
+        })
+    }
+
+    // Solves the viscosity using the current `particle_viscosities`.
+    pub(crate) fn solve_with_particle_viscosities(
+        &mut self,
+        ctx: &ViscosityContext,
+        fluid: &mut Fluid,
+    ) {
+        if ctx.dt <= na::zero::<Real>() {
+            return;
+        }
+
+        self.init(ctx.dt, fluid);
+        self.compute_inv_diagonals(ctx, fluid);
+
+        // Initial guess: the velocities without viscosity.
+        self.solution.copy_from_slice(&self.predicted_velocities);
+
+        // r = b - A * x
+        self.compute_rhs(ctx, fluid);
+        let rhs_norm = dot(&self.residuals, &self.residuals).sqrt();
+
+        let mut products = std::mem::take(&mut self.products);
+        let mut stabilizer_products = std::mem::take(&mut self.stabilizer_products);
+        let mut preconditioned = std::mem::take(&mut self.preconditioned);
+        self.apply_operator(ctx, fluid, &self.solution, &mut products);
+        par_iter_mut!(self.residuals)
+            .zip(par_iter!(products))
+            .for_each(|(r, ax)| *r -= *ax);
+
+        self.shadow_residuals.copy_from_slice(&self.residuals);
+        self.directions.fill(Vector::zeros());
+        products.fill(Vector::zeros());
+        let (mut rho, mut alpha, mut omega) =
+            (na::one::<Real>(), na::one::<Real>(), na::one::<Real>());
+
+        for _ in 0..self.max_viscosity_iter {
+            let err = dot(&self.residuals, &self.residuals).sqrt();
+
+            if err <= self.max_viscosity_error * rhs_norm {
+                #[cfg(feature = "log")]
+                log::debug!("Implicit viscosity residual: {}", err);
+                break;
+            }
+
+            let new_rho = dot(&self.shadow_residuals, &self.residuals);
+
+            if new_rho == na::zero::<Real>() {
+                break;
+            }
+
+            // p = r + beta * (p - omega * v)
+            let beta = new_rho / rho * alpha / omega;
+            rho = new_rho;
+            let residuals = &self.residuals;
+            par_iter_mut!(self.directions)
+                .zip(par_iter!(residuals))
+                .zip(par_iter!(products))
+                .for_each(|((p, r), v)| *p = *r + (*p - *v * omega) * beta);
+
+            // v = A * P⁻¹ * p
+            Self::precondition(&self.inv_diagonals, &self.directions, &mut preconditioned);
+            self.apply_operator(ctx, fluid, &preconditioned, &mut products);
+            let shadow_v = dot(&self.shadow_residuals, &products);
+
+            if shadow_v == na::zero::<Real>() {
+                break;
+            }
+
+            alpha = rho / shadow_v;
+            par_iter_mut!(self.solution)
+                .zip(par_iter!(preconditioned))
+                .for_each(|(x, y)| *x += *y * alpha);
+            par_iter_mut!(self.residuals)
+                .zip(par_iter!(products))
+                .for_each(|(r, v)| *r -= *v * alpha);
+
+            // t = A * P⁻¹ * s, where the residuals are now `s`.
+            Self::precondition(&self.inv_diagonals, &self.residuals, &mut preconditioned);
+            self.apply_operator(ctx, fluid, &preconditioned, &mut stabilizer_products);
+            let tt = dot(&stabilizer_products, &stabilizer_products);
+
+            if tt == na::zero::<Real>() {
+                break;
+            }
+
+            omega = dot(&stabilizer_products, &self.residuals) / tt;
+            par_iter_mut!(self.solution)
+                .zip(par_iter!(preconditioned))
+                .for_each(|(x, z)| *x += *z * omega);
+            par_iter_mut!(self.residuals)
+                .zip(par_iter!(stabilizer_products))
+                .for_each(|(r, t)| *r -= *t * omega);
+
+            if omega == na::zero::<Real>() {
+                break;
+            }
+        }
+
+        self.products = products;
+        self.stabilizer_products = stabilizer_products;
+        self.preconditioned = preconditioned;
+
+        self.apply_boundary_forces(ctx, fluid);
+
+        let inv_dt = na::one::<Real>() / ctx.dt;
+        let solution = &self.solution;
+        let predicted_velocities = &self.predicted_velocities;
+        par_iter_mut!(fluid.accelerations)
+            .enumerate()
+            .for_each(|(i, acceleration)| {
+                *acceleration += (solution[i] - predicted_velocities[i]) * inv_dt;
+            })
+    }
+}
+
+impl NonPressureForce for ImplicitViscosity {
+    fn solve(
+        &mut self,
+        timestep: &TimestepManager,
+        kernel_radius: Real,
+        fluid_fluid_contacts: &ParticlesContacts,
+        fluid_boundaries_contacts: &ParticlesContacts,
+        fluid: &mut Fluid,
+        _other_fluids: &OtherFluids,
+        boundaries: &[Boundary],
+        densities: &[Real],
+    ) {
+        self.particle_viscosities.clear();
+        self.particle_viscosities
+            .resize(fluid.num_particles(), self.fluid_viscosity_coefficient);
+
+        let ctx = ViscosityContext {
+            dt: timestep.predicted_dt(),
+            kernel_radius,
+            fluid_fluid_contacts,
+            fluid_boundaries_contacts,
+            boundaries,
+            densities,
+        };
+        self.solve_with_particle_viscosities(&ctx, fluid);
+    }
+
+    fn apply_permutation(&mut self, _: &[usize]) {}
+}
@@ -1,7 +1,12 @@
 pub use self::artificial_viscosity::ArtificialViscosity;
 pub use self::dfsph_viscosity::DFSPHViscosity;
+pub use self::implicit_viscosity::ImplicitViscosity;
+pub(crate) use self::implicit_viscosity::ViscosityContext;
+pub use self::non_newtonian_viscosity::{NonNewtonianViscosity, Rheology};
 pub use self::xsph_viscosity::XSPHViscosity;
 
 mod artificial_viscosity;
 mod dfsph_viscosity;
+mod implicit_viscosity;
+mod non_newtonian_viscosity;
 mod xsph_viscosity;
@@ -0,0 +1,192 @@
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use crate::geometry::ParticlesContacts;
+use crate::math::{Matrix, Real};
+use crate::object::{Boundary, Fluid};
+use crate::solver::{ImplicitViscosity, NonPressureForce, OtherFluids, ViscosityContext};
+use crate::TimestepManager;
+
+/// A model relating the shear rate of a fluid to its dynamic viscosity.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Rheology {
+    /// A constant viscosity.
+    Newtonian {
+        /// The dynamic viscosity.
+        viscosity: Real,
+    },
+    /// A power-law fluid, with viscosity `consistency * shear_rate^(flow_index - 1)`.
+    ///
+    /// The fluid is shear-thinning if `flow_index < 1.0`, and shear-thickening if `flow_index > 1.0`.
+    PowerLaw {
+        /// The flow consistency index.
+        consistency: Real,
+        /// The flow behavior index.
+        flow_index: Real,
+    },
+    /// A Bingham plastic, which behaves like a solid until its yield stress is exceeded, and
+    /// like a fluid with the given plastic viscosity afterwards.
+    ///
+    /// This uses the Papanastasiou regularization: the viscosity is
+    /// `plastic_viscosity + yield_stress * (1 - exp(-regularization * shear_rate)) / shear_rate`.
+    Bingham {
+        /// The dynamic viscosity after yield.
+        plastic_viscosity: Real,
+        /// The stress that must be exceeded for the material to flow.
+        yield_stress: Real,
+        /// Controls the sharpness of the transition at the yield stress.
+        regularization: Real,
+    },
+}
+
+impl Rheology {
+    /// The dynamic viscosity of this model for the given shear rate.
+    pub fn viscosity(&self, shear_rate: Real) -> Real {
+        match *self {
+            Rheology::Newtonian { viscosity } => viscosity,
+            Rheology::PowerLaw {
+                consistency,
+                flow_index,
+            } => {
+                // Avoid infinite viscosities for shear-thinning fluids at rest.
+                let shear_rate = shear_rate.max(na::convert::<_, Real>(1.0e-6));
+                consistency * shear_rate.powf(flow_index - na::one::<Real>())
+            }
+            Rheology::Bingham {
+                plastic_viscosity,
+                yield_stress,
+                regularization,
+            } => {
+                let yield_part = if shear_rate > na::convert::<_, Real>(1.0e-6) {
+                    (na::one::<Real>() - (-regularization * shear_rate).exp()) / shear_rate
+                } else {
+                    regularization
+                };
+                plastic_viscosity + yield_stress * yield_part
+            }
+        }
+    }
+}
+
+/// A viscosity depending on the local shear rate of the fluid.
+///
+/// The strain-rate tensor of each particle is estimated from the velocities of its neighbors, and
+/// the resulting effective viscosity is resolved implicitly, so high viscosities (like those of a
+/// Bingham plastic at rest) remain stable.
+pub struct NonNewtonianViscosity {
+    /// The model relating the shear rate to the viscosity.
+    pub rheology: Rheology,
+    /// The minimum kinematic viscosity of the fluid.
+    pub min_viscosity: Real,
+    /// The maximum kinematic viscosity of the fluid.
+    pub max_viscosity: Real,
+    solver: ImplicitViscosity,
+    shear_rates: Vec<Real>,
+}
+
+impl NonNewtonianViscosity {
+    /// Initializes a non-newtonian viscosity with the given rheology and kinematic viscosity
+    /// with boundaries.
+    pub fn new(rheology: Rheology, boundary_viscosity_coefficient: Real) -> Self {
+        Self {
+            rheology,
+            min_viscosity: na::zero::<Real>(),
+            max_viscosity: na::convert::<_, Real>(1000.0),
+            solver: ImplicitViscosity::new(na::zero::<Real>(), boundary_viscosity_coefficient),
+            shear_rates: Vec::new(),
+        }
+    }
+
+    /// The shear rate of each particle, as computed during the last substep.
+    pub fn shear_rates(&self) -> &[Real] {
+        &self.shear_rates
+    }
+
+    /// The implicit solver used to resolve the viscosity.
+    ///
+    /// This can be used to change its number of iterations or the boundary viscosity.
+    pub fn solver_mut(&mut self) -> &mut ImplicitViscosity {
+        &mut self.solver
+    }
+
+    fn compute_shear_rates(
+        &mut self,
+        timestep: &TimestepManager,
+        fluid_fluid_contacts: &ParticlesContacts,
+        fluid: &Fluid,
+        densities: &[Real],
+    ) {
+        let half: Real = na::convert::<_, Real>(0.5);
+        let two: Real = na::convert::<_, Real>(2.0);
+
+        self.shear_rates
+            .resize(fluid.num_particles(), na::zero::<Real>());
+
+        par_iter_mut!(self.shear_rates)
+            .enumerate()
+            .for_each(|(i, shear_rate)| {
+                let mut velocity_gradient = Matrix::zeros();
+                let v_i = fluid.velocities[i] + fluid.accelerations[i] * timestep.dt();
+
+                for c in fluid_fluid_contacts
+                    .particle_contacts(i)
+                    .read()
+                    .unwrap()
+                    .iter()
+                {
+                    if c.i_model == c.j_model {
+                        let v_j = fluid.velocities[c.j] + fluid.accelerations[c.j] * timestep.dt();
+                        let volume_j = fluid.particle_mass(c.j) / densities[c.j];
+                        velocity_gradient += (v_j - v_i) * (c.gradient * volume_j).transpose();
+                    }
+                }
+
+                let strain_rate = (velocity_gradient + velocity_gradient.transpose()) * half;
+                *shear_rate = (strain_rate.norm_squared() * two).sqrt();
+            })
+    }
+}
+
+impl NonPressureForce for NonNewtonianViscosity {
+    fn solve(
+        &mut self,
+        timestep: &TimestepManager,
+        kernel_radius: Real,
+        fluid_fluid_contacts: &ParticlesContacts,
+        fluid_boundaries_contacts: &ParticlesContacts,
+        fluid: &mut Fluid,
+        _other_fluids: &OtherFluids,
+        boundaries: &[Boundary],
+        densities: &[Real],
+    ) {
+        self.compute_shear_rates(timestep, fluid_fluid_contacts, fluid, densities);
+
+        let rheology = self.rheology;
+        let min_viscosity = self.min_viscosity;
+        let max_viscosity = self.max_viscosity;
+        let density0 = fluid.density0;
+
+        self.solver
+            .particle_viscosities
+            .resize(fluid.num_particles(), na::zero::<Real>());
+        par_iter_mut!(self.solver.particle_viscosities)
+            .zip(par_iter!(self.shear_rates))
+            .for_each(|(viscosity, shear_rate)| {
+                *viscosity = (rheology.viscosity(*shear_rate) / density0)
+                    .max(min_viscosity)
+                    .min(max_viscosity);
+            });
+
+        let ctx = ViscosityContext {
+            dt: timestep.predicted_dt(),
+            kernel_radius,
+            fluid_fluid_contacts,
+            fluid_boundaries_contacts,
+            boundaries,
+            densities,
+        };
+        self.solver.solve_with_particle_viscosities(&ctx, fluid);
+    }
+
+    fn apply_permutation(&mut self, _: &[usize]) {}
+}
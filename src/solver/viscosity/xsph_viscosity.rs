@@ -5,11 +5,15 @@ use crate::geometry::ParticlesContacts;
 
 use crate::math::{Real, Vector};
 use crate::object::{Boundary, Fluid};
-use crate::solver::NonPressureForce;
+use crate::solver::{helper, NonPressureForce, OtherFluids};
 use crate::TimestepManager;
 
 #[derive(Clone)]
 /// Implements the viscosity model introduced with the XSPH method.
+///
+/// Each particle velocity is blended with the average velocity of its neighborhood. This is cheap
+/// and removes particle jitter, which makes it a good choice for low-viscosity liquids like water.
+/// Use `ImplicitViscosity` for highly viscous fluids instead.
 pub struct XSPHViscosity {
     /// The viscosity coefficient when interacting with boundaries.
     pub boundary_viscosity_coefficient: Real,
@@ -35,6 +39,7 @@ impl NonPressureForce for XSPHViscosity {
         fluid_fluid_contacts: &ParticlesContacts,
         fluid_boundaries_contacts: &ParticlesContacts,
         fluid: &mut Fluid,
+        _other_fluids: &OtherFluids,
         boundaries: &[Boundary],
         densities: &[Real],
     ) {
@@ -42,7 +47,9 @@ impl NonPressureForce for XSPHViscosity {
         let fluid_viscosity_coefficient = self.fluid_viscosity_coefficient;
         let velocities = &fluid.velocities;
         let volumes = &fluid.volumes;
+        let rest_densities = &fluid.rest_densities;
         let density0 = fluid.density0;
+        let rest_density = |k| helper::rest_density(rest_densities, density0, k);
 
         par_iter_mut!(fluid.accelerations)
             .enumerate()
@@ -63,7 +70,7 @@ impl NonPressureForce for XSPHViscosity {
                                 * (fluid_viscosity_coefficient
                                     * c.weight
                                     * volumes[c.j]
-                                    * density0
+                                    * rest_density(c.j)
                                     / densities[c.j]);
                         }
                     }
@@ -80,11 +87,11 @@ impl NonPressureForce for XSPHViscosity {
                             * (boundary_viscosity_coefficient
                                 * c.weight
                                 * boundaries[c.j_model].volumes[c.j]
-                                * density0
+                                * rest_density(c.i)
                                 / densities[c.i]);
                         added_boundary_vel += delta;
 
-                        let mi = volumes[c.i] * density0;
+                        let mi = volumes[c.i] * rest_density(c.i);
                         boundaries[c.j_model].apply_force(c.j, delta * (-mi * timestep.inv_dt()));
                     }
                 }
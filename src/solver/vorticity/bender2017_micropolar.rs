@@ -0,0 +1,232 @@
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use super::vorticity_confinement::{cross, cross_angular};
+use crate::geometry::ParticlesContacts;
+use crate::math::{AngularVector, Point, Real, Vector};
+use crate::object::{Boundary, Fluid};
+use crate::solver::{helper, NonPressureForce, OtherFluids};
+use crate::TimestepManager;
+
+#[derive(Clone)]
+/// Micropolar turbulence model introduced by Bender et al. 2017.
+///
+/// Each particle carries an angular velocity `ω`, the micro-rotation of the fluid around it,
+/// which is transferred to and from the linear velocity field:
+/// - the angular velocities are accelerated by
+///   `transfer_coefficient * inverse_microinertia * (∇×v - 2ω)`, so they follow the vorticity of
+///   the flow;
+/// - the linear velocities are accelerated by `transfer_coefficient * ∇×ω`, which reinjects the
+///   rotational motion lost because of numerical dissipation.
+///
+/// Unlike `VorticityConfinement`, the vorticity is stored instead of being estimated from the
+/// velocities of each step, so it is preserved while being advected by the particles. This gives
+/// more detailed energetic splashes and breaking waves. The boundaries have no angular velocity
+/// and apply the vorticity caused by their relative velocity to the fluid.
+pub struct Bender2017Micropolar {
+    /// The kinematic vortex viscosity `ν_t`, in `m²/s`, controlling the transfer between the
+    /// angular and linear velocities.
+    ///
+    /// Large values will add energy to the system and may cause instabilities.
+    pub transfer_coefficient: Real,
+    /// The inverse of the microinertia `Θ` of the particles, in `1/m²`.
+    ///
+    /// Large values make the angular velocities follow the vorticity of the flow more closely.
+    pub inverse_microinertia: Real,
+    /// The XSPH-like viscosity smoothing the angular velocities of neighboring particles.
+    ///
+    /// This is typically in [0.0, 1.0].
+    pub angular_viscosity: Real,
+    angular_velocities: Vec<AngularVector<Real>>,
+    next_angular_velocities: Vec<AngularVector<Real>>,
+}
+
+impl Bender2017Micropolar {
+    /// Initializes a micropolar model with the given transfer coefficient, inverse microinertia,
+    /// and angular viscosity.
+    ///
+    /// The angular velocities of all the particles are initially zero.
+    pub fn new(
+        transfer_coefficient: Real,
+        inverse_microinertia: Real,
+        angular_viscosity: Real,
+    ) -> Self {
+        Self {
+            transfer_coefficient,
+            inverse_microinertia,
+            angular_viscosity,
+            angular_velocities: Vec::new(),
+            next_angular_velocities: Vec::new(),
+        }
+    }
+
+    /// The angular velocities of the particles of the fluid, as of the last step.
+    ///
+    /// This is empty before the first step.
+    pub fn angular_velocities(&self) -> &[AngularVector<Real>] {
+        &self.angular_velocities
+    }
+
+    fn init(&mut self, fluid: &Fluid) {
+        if self.angular_velocities.len() != fluid.num_particles() {
+            self.angular_velocities
+                .resize(fluid.num_particles(), AngularVector::zeros());
+        }
+
+        if self.next_angular_velocities.len() != fluid.num_particles() {
+            self.next_angular_velocities
+                .resize(fluid.num_particles(), AngularVector::zeros());
+        }
+    }
+
+    fn integrate_angular_velocities(
+        &mut self,
+        dt: Real,
+        fluid_fluid_contacts: &ParticlesContacts,
+        fluid_boundaries_contacts: &ParticlesContacts,
+        fluid: &Fluid,
+        boundaries: &[Boundary],
+        densities: &[Real],
+    ) {
+        let angular_velocities = &self.angular_velocities;
+        let velocities = &fluid.velocities;
+        let rate = self.transfer_coefficient * self.inverse_microinertia * dt;
+        let angular_viscosity = self.angular_viscosity;
+
+        par_iter_mut!(self.next_angular_velocities)
+            .enumerate()
+            .for_each(|(i, next_angular_velocity)| {
+                let mut vorticity = AngularVector::zeros();
+                let mut smoothing = AngularVector::zeros();
+
+                for c in fluid_fluid_contacts
+                    .particle_contacts(i)
+                    .read()
+                    .unwrap()
+                    .iter()
+                {
+                    if c.i_model == c.j_model {
+                        let volume_j = fluid.particle_mass(c.j) / densities[c.j];
+                        let v_ji = velocities[c.j] - velocities[c.i];
+                        vorticity += cross(&c.gradient, &v_ji) * volume_j;
+                        smoothing += (angular_velocities[c.j] - angular_velocities[c.i])
+                            * (volume_j * c.weight);
+                    }
+                }
+
+                for c in fluid_boundaries_contacts
+                    .particle_contacts(i)
+                    .read()
+                    .unwrap()
+                    .iter()
+                {
+                    let boundary = &boundaries[c.j_model];
+                    let v_ji = boundary.velocities[c.j] - velocities[c.i];
+                    vorticity += cross(&c.gradient, &v_ji) * boundary.volumes[c.j];
+                }
+
+                // The decay `-2ω` is integrated implicitly so it remains stable with large
+                // coefficients.
+                *next_angular_velocity =
+                    (angular_velocities[i] + vorticity * rate + smoothing * angular_viscosity)
+                        / (na::one::<Real>() + rate * na::convert::<_, Real>(2.0));
+            });
+
+        std::mem::swap(
+            &mut self.angular_velocities,
+            &mut self.next_angular_velocities,
+        );
+    }
+}
+
+impl NonPressureForce for Bender2017Micropolar {
+    fn solve(
+        &mut self,
+        timestep: &TimestepManager,
+        _kernel_radius: Real,
+        fluid_fluid_contacts: &ParticlesContacts,
+        fluid_boundaries_contacts: &ParticlesContacts,
+        fluid: &mut Fluid,
+        _other_fluids: &OtherFluids,
+        boundaries: &[Boundary],
+        densities: &[Real],
+    ) {
+        if self.transfer_coefficient == na::zero::<Real>() {
+            return;
+        }
+
+        self.init(fluid);
+
+        let angular_velocities = &self.angular_velocities;
+        let transfer_coefficient = self.transfer_coefficient;
+        let volumes = &fluid.volumes;
+        let rest_densities = &fluid.rest_densities;
+        let density0 = fluid.density0;
+        let rest_density = |k| helper::rest_density(rest_densities, density0, k);
+
+        par_iter_mut!(fluid.accelerations)
+            .enumerate()
+            .for_each(|(i, acceleration_i)| {
+                // Curl of the angular velocities.
+                let mut curl = Vector::zeros();
+
+                for c in fluid_fluid_contacts
+                    .particle_contacts(i)
+                    .read()
+                    .unwrap()
+                    .iter()
+                {
+                    if c.i_model == c.j_model {
+                        let w_ji = angular_velocities[c.j] - angular_velocities[c.i];
+                        curl += cross_angular(&c.gradient, &w_ji)
+                            * (volumes[c.j] * rest_density(c.j) / densities[c.i]);
+                    }
+                }
+
+                *acceleration_i += curl * transfer_coefficient;
+
+                for c in fluid_boundaries_contacts
+                    .particle_contacts(i)
+                    .read()
+                    .unwrap()
+                    .iter()
+                {
+                    let boundary = &boundaries[c.j_model];
+                    let acc = cross_angular(&c.gradient, &-angular_velocities[i])
+                        * (transfer_coefficient * boundary.volumes[c.j] * rest_density(i)
+                            / densities[i]);
+                    *acceleration_i += acc;
+
+                    boundary.apply_force(c.j, acc * (-volumes[i] * rest_density(i)));
+                }
+            });
+
+        self.integrate_angular_velocities(
+            timestep.dt(),
+            fluid_fluid_contacts,
+            fluid_boundaries_contacts,
+            fluid,
+            boundaries,
+            densities,
+        );
+    }
+
+    fn apply_permutation(&mut self, permutation: &[usize]) {
+        self.angular_velocities =
+            crate::z_order::apply_permutation(permutation, &self.angular_velocities);
+        self.next_angular_velocities
+            .truncate(self.angular_velocities.len());
+    }
+
+    fn add_particles(
+        &mut self,
+        num_added: usize,
+        positions: &[Point<Real>],
+        _velocities: &[Vector<Real>],
+    ) {
+        if self.angular_velocities.len() + num_added == positions.len() {
+            self.angular_velocities
+                .resize(positions.len(), AngularVector::zeros());
+        }
+    }
+}
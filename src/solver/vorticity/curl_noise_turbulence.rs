@@ -0,0 +1,244 @@
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use na::RealField;
+
+use super::vorticity_confinement::cross_angular;
+use crate::geometry::ParticlesContacts;
+use crate::math::{AngularVector, Matrix, Point, Real, Vector};
+use crate::object::{Boundary, Fluid};
+use crate::random::Rng;
+use crate::solver::{helper, NonPressureForce, OtherFluids};
+use crate::TimestepManager;
+
+// The number of waves summed to generate the noise.
+const NUM_MODES: usize = 16;
+
+// A wave `a * sin(k·x + phase + frequency * t)` of the vector potential of the noise, its curl
+// being `(k × a) * cos(k·x + phase + frequency * t)`.
+#[derive(Copy, Clone, Debug)]
+struct NoiseMode {
+    direction: Vector<Real>,
+    potential: AngularVector<Real>,
+    phase: Real,
+    frequency: Real,
+}
+
+impl NoiseMode {
+    fn new(rng: &mut Rng) -> Self {
+        let angle = rng.next_real() * Real::two_pi();
+
+        #[cfg(feature = "dim2")]
+        let (direction, potential) = {
+            let sign = if rng.next_real() < na::convert::<_, Real>(0.5) {
+                -na::one::<Real>()
+            } else {
+                na::one::<Real>()
+            };
+            (
+                Vector::new(angle.cos(), angle.sin()),
+                AngularVector::new(sign),
+            )
+        };
+
+        #[cfg(feature = "dim3")]
+        let (direction, potential) = {
+            let z = rng.next_real() * na::convert::<_, Real>(2.0) - na::one::<Real>();
+            let r = (na::one::<Real>() - z * z).max(na::zero::<Real>()).sqrt();
+            let direction = Vector::new(r * angle.cos(), r * angle.sin(), z);
+            // A potential orthogonal to the direction gives a wave with a unit amplitude.
+            let potential = rng
+                .disk_point(&direction, na::one::<Real>())
+                .try_normalize(na::convert::<_, Real>(1.0e-6))
+                .unwrap_or_else(|| direction.cross(&Vector::x()).normalize());
+            (direction, potential)
+        };
+
+        Self {
+            direction,
+            potential,
+            phase: rng.next_real() * Real::two_pi(),
+            // Vary the frequencies so the noise doesn't loop.
+            frequency: (na::convert::<_, Real>(0.5) + rng.next_real()) * Real::two_pi(),
+        }
+    }
+}
+
+/// A procedural turbulence perturbing the fluid with a divergence-free curl noise.
+///
+/// The acceleration field is the curl of a smooth, time-varying, pseudo-random vector potential
+/// (a stream function in 2D), so it adds swirling small-scale motion without compressing the
+/// fluid, i.e., without fighting the pressure solver. This is much cheaper than resolving the
+/// same details with a finer particle resolution.
+///
+/// The noise is modulated by:
+/// - the distance to the free surface, estimated from the magnitude of the color field gradient,
+///   which vanishes deeper than a kernel radius below the surface;
+/// - the local shear rate of the fluid, so calm regions are not perturbed.
+#[derive(Clone)]
+pub struct CurlNoiseTurbulence {
+    /// The typical magnitude of the accelerations applied to the particles, in `m/s²`.
+    pub amplitude: Real,
+    /// The typical size of the eddies, in meters.
+    pub length_scale: Real,
+    /// The typical lifetime of the eddies, in seconds.
+    pub time_scale: Real,
+    /// The strength of the noise, relative to `amplitude`, deeper than a kernel radius below the
+    /// free surface.
+    ///
+    /// This defaults to zero, i.e., only the particles close to the free surface are perturbed.
+    /// Set it to `1.0` to perturb the whole fluid uniformly.
+    pub interior_factor: Real,
+    /// The shear rate, in `1/s`, above which the noise reaches its full strength.
+    ///
+    /// The noise is scaled down linearly for smaller shear rates. This defaults to zero, i.e.,
+    /// the noise does not depend on the shear rate.
+    pub strain_threshold: Real,
+    modes: Vec<NoiseMode>,
+    time: Real,
+}
+
+impl CurlNoiseTurbulence {
+    /// Initializes a curl noise turbulence with the given amplitude (in `m/s²`), eddy size (in
+    /// meters), and eddy lifetime (in seconds).
+    ///
+    /// The `seed` initializes the pseudo-random generator of the noise, so the same seed always
+    /// gives the same simulation.
+    pub fn new(amplitude: Real, length_scale: Real, time_scale: Real, seed: u64) -> Self {
+        let mut rng = Rng::new(seed);
+
+        Self {
+            amplitude,
+            length_scale,
+            time_scale,
+            interior_factor: na::zero::<Real>(),
+            strain_threshold: na::zero::<Real>(),
+            modes: (0..NUM_MODES).map(|_| NoiseMode::new(&mut rng)).collect(),
+            time: na::zero::<Real>(),
+        }
+    }
+
+    /// The divergence-free noise, with a root mean square magnitude close to one, at the given
+    /// point and the current time.
+    pub fn noise(&self, point: &Point<Real>) -> Vector<Real> {
+        let wave_number = Real::two_pi() / self.length_scale;
+        let time = self.time / self.time_scale;
+        // The sum of `NUM_MODES` waves with random phases and unit amplitudes.
+        let normalization = (na::convert::<_, Real>(2.0 / NUM_MODES as f64)).sqrt();
+
+        self.modes.iter().fold(Vector::zeros(), |acc, mode| {
+            let angle = mode.direction.dot(&point.coords) * wave_number
+                + mode.phase
+                + mode.frequency * time;
+            acc + cross_angular(&mode.direction, &mode.potential) * angle.cos()
+        }) * normalization
+    }
+}
+
+impl NonPressureForce for CurlNoiseTurbulence {
+    fn solve(
+        &mut self,
+        timestep: &TimestepManager,
+        kernel_radius: Real,
+        fluid_fluid_contacts: &ParticlesContacts,
+        fluid_boundaries_contacts: &ParticlesContacts,
+        fluid: &mut Fluid,
+        other_fluids: &OtherFluids,
+        boundaries: &[Boundary],
+        densities: &[Real],
+    ) {
+        self.time += timestep.dt();
+
+        if self.amplitude == na::zero::<Real>() {
+            return;
+        }
+
+        let half: Real = na::convert::<_, Real>(0.5);
+        let two: Real = na::convert::<_, Real>(2.0);
+        let velocities = &fluid.velocities;
+        let positions = &fluid.positions;
+        let volumes = &fluid.volumes;
+        let rest_densities = &fluid.rest_densities;
+        let density0 = fluid.density0;
+        let rest_density = |k| helper::rest_density(rest_densities, density0, k);
+
+        par_iter_mut!(fluid.accelerations)
+            .enumerate()
+            .for_each(|(i, acceleration_i)| {
+                let mut color_gradient = Vector::zeros();
+                let mut velocity_gradient = Matrix::zeros();
+
+                for c in fluid_fluid_contacts
+                    .particle_contacts(i)
+                    .read()
+                    .unwrap()
+                    .iter()
+                {
+                    if c.i_model == c.j_model {
+                        color_gradient += c.gradient * volumes[c.j];
+
+                        let volume_j = volumes[c.j] * rest_density(c.j) / densities[c.j];
+                        velocity_gradient += (velocities[c.j] - velocities[c.i])
+                            * (c.gradient * volume_j).transpose();
+                    } else if let Some(fluid_j) = other_fluids.fluid(c.j_model) {
+                        color_gradient += c.gradient * fluid_j.volumes[c.j];
+                    }
+                }
+
+                for c in fluid_boundaries_contacts
+                    .particle_contacts(i)
+                    .read()
+                    .unwrap()
+                    .iter()
+                {
+                    color_gradient += c.gradient * boundaries[c.j_model].volumes[c.j];
+                }
+
+                let surface_factor = (color_gradient.norm() * kernel_radius).min(na::one::<Real>());
+                let mut weight = self.interior_factor
+                    + (na::one::<Real>() - self.interior_factor) * surface_factor;
+
+                if self.strain_threshold > na::zero::<Real>() {
+                    let strain_rate = (velocity_gradient + velocity_gradient.transpose()) * half;
+                    let shear_rate = (strain_rate.norm_squared() * two).sqrt();
+                    weight *= (shear_rate / self.strain_threshold).min(na::one::<Real>());
+                }
+
+                if weight > na::zero::<Real>() {
+                    *acceleration_i += self.noise(&positions[i]) * (self.amplitude * weight);
+                }
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::CurlNoiseTurbulence;
+    use crate::math::{Point, Real, Vector, DIM};
+
+    #[test]
+    fn noise_is_divergence_free() {
+        let turbulence = CurlNoiseTurbulence::new(1.0, 0.5, 1.0, 42);
+        let eps = 1.0e-3;
+        let mut mean_squared_norm = 0.0;
+
+        for k in 0..100 {
+            let point = Point::from(Vector::repeat(k as Real * 0.037));
+            let mut divergence = 0.0;
+
+            for d in 0..DIM {
+                let mut offset = Vector::zeros();
+                offset[d] = eps;
+                divergence += (turbulence.noise(&(point + offset))[d]
+                    - turbulence.noise(&(point - offset))[d])
+                    / (2.0 * eps);
+            }
+
+            // The derivatives of the noise are about `2π / length_scale ≈ 12.6`.
+            assert!(divergence.abs() < 1.0e-2, "{}", divergence);
+            mean_squared_norm += turbulence.noise(&point).norm_squared() / 100.0;
+        }
+
+        assert!(mean_squared_norm > 0.2 && mean_squared_norm < 5.0);
+    }
+}
@@ -0,0 +1,7 @@
+pub use self::bender2017_micropolar::Bender2017Micropolar;
+pub use self::curl_noise_turbulence::CurlNoiseTurbulence;
+pub use self::vorticity_confinement::VorticityConfinement;
+
+mod bender2017_micropolar;
+mod curl_noise_turbulence;
+mod vorticity_confinement;
@@ -0,0 +1,147 @@
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use crate::geometry::ParticlesContacts;
+use crate::math::{AngularVector, Real, Vector};
+use crate::object::{Boundary, Fluid};
+use crate::solver::{helper, NonPressureForce, OtherFluids};
+use crate::TimestepManager;
+
+#[cfg(feature = "dim2")]
+pub(super) fn cross(a: &Vector<Real>, b: &Vector<Real>) -> AngularVector<Real> {
+    AngularVector::new(a.perp(b))
+}
+
+#[cfg(feature = "dim3")]
+pub(super) fn cross(a: &Vector<Real>, b: &Vector<Real>) -> AngularVector<Real> {
+    a.cross(b)
+}
+
+// Computes `n × w` where `w` is an angular vector.
+#[cfg(feature = "dim2")]
+pub(super) fn cross_angular(n: &Vector<Real>, w: &AngularVector<Real>) -> Vector<Real> {
+    Vector::new(n.y * w.x, -n.x * w.x)
+}
+
+#[cfg(feature = "dim3")]
+pub(super) fn cross_angular(n: &Vector<Real>, w: &AngularVector<Real>) -> Vector<Real> {
+    n.cross(w)
+}
+
+#[derive(Clone)]
+/// Vorticity confinement, re-injecting the rotational energy lost because of numerical dissipation.
+///
+/// The vorticity of each particle is estimated from the velocities of its neighbors, and a force
+/// pushing the particles around the locations of highest vorticity is applied.
+pub struct VorticityConfinement {
+    /// The strength of the vorticity confinement.
+    ///
+    /// Large values will add energy to the system and may cause instabilities.
+    pub vorticity_coefficient: Real,
+    vorticities: Vec<AngularVector<Real>>,
+}
+
+impl VorticityConfinement {
+    /// Initializes a vorticity confinement force with the given strength.
+    pub fn new(vorticity_coefficient: Real) -> Self {
+        Self {
+            vorticity_coefficient,
+            vorticities: Vec::new(),
+        }
+    }
+
+    fn init(&mut self, fluid: &Fluid) {
+        if self.vorticities.len() != fluid.num_particles() {
+            self.vorticities
+                .resize(fluid.num_particles(), AngularVector::zeros());
+        }
+    }
+
+    fn compute_vorticities(
+        &mut self,
+        fluid_fluid_contacts: &ParticlesContacts,
+        fluid: &Fluid,
+        densities: &[Real],
+    ) {
+        let velocities = &fluid.velocities;
+
+        par_iter_mut!(self.vorticities)
+            .enumerate()
+            .for_each(|(i, vorticity_i)| {
+                let mut vorticity = AngularVector::zeros();
+
+                for c in fluid_fluid_contacts
+                    .particle_contacts(i)
+                    .read()
+                    .unwrap()
+                    .iter()
+                {
+                    if c.i_model == c.j_model {
+                        let v_ji = velocities[c.j] - velocities[c.i];
+                        vorticity +=
+                            cross(&v_ji, &c.gradient) * (fluid.particle_mass(c.j) / densities[c.j]);
+                    }
+                }
+
+                *vorticity_i = vorticity;
+            })
+    }
+}
+
+impl NonPressureForce for VorticityConfinement {
+    fn solve(
+        &mut self,
+        _timestep: &TimestepManager,
+        _kernel_radius: Real,
+        fluid_fluid_contacts: &ParticlesContacts,
+        _fluid_boundaries_contacts: &ParticlesContacts,
+        fluid: &mut Fluid,
+        _other_fluids: &OtherFluids,
+        _boundaries: &[Boundary],
+        densities: &[Real],
+    ) {
+        if self.vorticity_coefficient == na::zero::<Real>() {
+            return;
+        }
+
+        self.init(fluid);
+        self.compute_vorticities(fluid_fluid_contacts, fluid, densities);
+
+        let vorticities = &self.vorticities;
+        let vorticity_coefficient = self.vorticity_coefficient;
+        let volumes = &fluid.volumes;
+        let rest_densities = &fluid.rest_densities;
+        let density0 = fluid.density0;
+        let rest_density = |k| helper::rest_density(rest_densities, density0, k);
+
+        par_iter_mut!(fluid.accelerations)
+            .enumerate()
+            .for_each(|(i, acceleration_i)| {
+                // Gradient of the vorticity magnitude.
+                let mut eta = Vector::zeros();
+                let vorticity_norm_i = vorticities[i].norm();
+
+                for c in fluid_fluid_contacts
+                    .particle_contacts(i)
+                    .read()
+                    .unwrap()
+                    .iter()
+                {
+                    if c.i_model == c.j_model {
+                        eta += c.gradient
+                            * ((vorticities[c.j].norm() - vorticity_norm_i)
+                                * volumes[c.j]
+                                * rest_density(c.j)
+                                / densities[c.j]);
+                    }
+                }
+
+                if let Some(normal) = eta.try_normalize(na::convert::<_, Real>(1.0e-6)) {
+                    *acceleration_i +=
+                        cross_angular(&normal, &vorticities[i]) * vorticity_coefficient;
+                }
+            })
+    }
+
+    fn apply_permutation(&mut self, _: &[usize]) {}
+}
@@ -0,0 +1,128 @@
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use crate::geometry::HGrid;
+use crate::math::{Matrix, Point, Real, Vector, DIM};
+
+/// Parameters of the anisotropic kernels from "Reconstructing Surfaces of Particle-Based Fluids
+/// Using Anisotropic Kernels", Yu & Turk 2010.
+///
+/// The kernel of each particle is stretched along the principal axes of the distribution of its
+/// neighbors, so that thin sheets and flat surfaces are reconstructed without bumps.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Anisotropy {
+    /// The radius of the neighborhood used to compute the principal axes of each particle,
+    /// relative to the kernel radius of the surface reconstruction.
+    pub neighborhood_scale: Real,
+    /// The maximum ratio between the largest and the smallest stretch of a kernel.
+    pub max_stretch: Real,
+    /// Particles with less neighbors than this keep an isotropic kernel.
+    pub min_neighbors: usize,
+    /// How much the kernel centers are moved toward the weighted average of their neighbors
+    /// positions, between `0.0` (not at all) and `1.0`.
+    pub smoothing: Real,
+}
+
+/// The anisotropic kernel of each particle.
+///
+/// The value of the kernel of the `i`-th particle at the point `x` is `W(matrices[i] * (x - centers[i]), h)`
+/// with `W` the isotropic kernel of radius `h`. This can be used by renderers splatting particles
+/// as ellipsoids: the inverse of each matrix maps the unit ball to the particle ellipsoid.
+#[derive(Clone, Debug, Default)]
+pub struct ParticleAnisotropy {
+    /// The smoothed center of the kernel of each particle.
+    pub centers: Vec<Point<Real>>,
+    /// The symmetric matrix, with a unit determinant, transforming the kernel of each particle.
+    pub matrices: Vec<Matrix<Real>>,
+}
+
+impl Anisotropy {
+    /// Initializes the parameters of anisotropic kernels with the values suggested by Yu & Turk.
+    pub fn new() -> Self {
+        Self {
+            neighborhood_scale: na::convert::<_, Real>(2.0),
+            max_stretch: na::convert::<_, Real>(4.0),
+            min_neighbors: if DIM == 2 { 6 } else { 25 },
+            smoothing: na::convert::<_, Real>(0.9),
+        }
+    }
+
+    /// Computes the anisotropic kernel of each particle, for a surface reconstruction with the
+    /// given kernel radius.
+    pub fn compute(&self, kernel_radius: Real, positions: &[Point<Real>]) -> ParticleAnisotropy {
+        let radius = kernel_radius * self.neighborhood_scale;
+        let mut grid = HGrid::new(radius);
+
+        for (i, position) in positions.iter().enumerate() {
+            grid.insert(position, i);
+        }
+
+        let (centers, matrices) = par_iter!(positions)
+            .map(|position| {
+                let mut mean = Vector::zeros();
+                let mut total_weight = na::zero::<Real>();
+                let mut neighbors = Vec::new();
+
+                for (_, cell) in grid.neighbor_cells(&grid.key(position), radius) {
+                    for j in cell {
+                        let dist = na::distance(position, &positions[*j]) / radius;
+
+                        if dist < na::one::<Real>() {
+                            let weight = na::one::<Real>() - dist * dist * dist;
+                            mean += positions[*j].coords * weight;
+                            total_weight += weight;
+                            neighbors.push((*j, weight));
+                        }
+                    }
+                }
+
+                // The particle is always its own neighbor, so `total_weight` is not zero.
+                mean /= total_weight;
+                let center = position.coords.lerp(&mean, self.smoothing);
+
+                if neighbors.len() < self.min_neighbors {
+                    return (Point::from(center), Matrix::identity());
+                }
+
+                let mut covariance = Matrix::zeros();
+
+                for (j, weight) in neighbors {
+                    let dpos = positions[j].coords - mean;
+                    covariance += dpos * dpos.transpose() * weight;
+                }
+
+                covariance /= total_weight;
+                (Point::from(center), self.anisotropy_matrix(covariance))
+            })
+            .unzip();
+
+        ParticleAnisotropy { centers, matrices }
+    }
+
+    // Computes the unit-determinant matrix shrinking space along the principal axes of `covariance`
+    // with the largest variance, which stretches the kernel along these axes.
+    fn anisotropy_matrix(&self, covariance: Matrix<Real>) -> Matrix<Real> {
+        let eigen = covariance.symmetric_eigen();
+        let max_variance = eigen.eigenvalues.max();
+
+        if max_variance <= Real::EPSILON {
+            return Matrix::identity();
+        }
+
+        let variances = eigen
+            .eigenvalues
+            .map(|v| v.max(max_variance / self.max_stretch));
+        let geometric_mean = variances
+            .product()
+            .powf(na::one::<Real>() / na::convert::<_, Real>(DIM as f64));
+        let scales = variances.map(|v| geometric_mean / v);
+
+        eigen.eigenvectors * Matrix::from_diagonal(&scales) * eigen.eigenvectors.transpose()
+    }
+}
+
+impl Default for Anisotropy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
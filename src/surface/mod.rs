@@ -0,0 +1,7 @@
+//! Reconstruction of the surface of fluids as meshes, for rendering.
+
+pub use self::anisotropy::{Anisotropy, ParticleAnisotropy};
+pub use self::surface_reconstruction::{SurfaceMesh, SurfaceReconstruction};
+
+mod anisotropy;
+mod surface_reconstruction;
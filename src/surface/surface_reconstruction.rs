@@ -0,0 +1,342 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::kernel::{CubicSplineKernel, Kernel};
+use crate::math::{Matrix, Point, Real, Vector, DIM};
+use crate::object::Fluid;
+use crate::surface::Anisotropy;
+
+/// An indexed mesh approximating the surface of a fluid.
+///
+/// In 3D, this is a triangle mesh. In 2D, this is a set of segments.
+#[derive(Clone, Debug, Default)]
+pub struct SurfaceMesh {
+    /// The vertices of the mesh.
+    pub vertices: Vec<Point<Real>>,
+    /// The unit normal at each vertex, pointing outside of the fluid.
+    pub normals: Vec<Vector<Real>>,
+    /// The indices of the vertices of each triangle (in 3D) or segment (in 2D).
+    ///
+    /// Triangles are oriented counterclockwise when seen from outside of the fluid. Segments are
+    /// oriented such that the outside of the fluid is on their right.
+    pub indices: Vec<[u32; DIM]>,
+}
+
+/// Reconstructs the surface of a fluid as the iso-surface of a scalar field sampled on a grid.
+///
+/// The scalar field at a point `x` is the sum over all particles `j` of `V_j W(x - x_j, h)`, with
+/// `V_j` the particle volume and `W` the cubic spline kernel of radius `h`. It is close to 1 inside
+/// of the fluid and drops to 0 outside of it.
+///
+/// The iso-surface is extracted with marching cubes in 3D, and marching squares in 2D. Each grid
+/// cell is split into simplices (six tetrahedra in 3D, two triangles in 2D) which avoids the
+/// ambiguous cases of the classic lookup tables and makes the resulting mesh watertight.
+///
+/// The kernels of the particles can optionally be made anisotropic (see `Anisotropy`) to avoid
+/// the blobby look of isotropic kernels on flat surfaces and thin sheets.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct SurfaceReconstruction {
+    /// The radius of the kernel used to compute the scalar field.
+    ///
+    /// Larger values give smoother surfaces, but round off the details of the fluid.
+    pub kernel_radius: Real,
+    /// The width of the grid cells the scalar field is sampled on.
+    ///
+    /// Smaller values give finer meshes, at a higher computational cost.
+    pub cell_width: Real,
+    /// The value of the scalar field on the reconstructed surface.
+    pub iso_level: Real,
+    /// The parameters of the anisotropic kernels of the particles.
+    ///
+    /// If `None` (the default), the kernels are isotropic.
+    pub anisotropy: Option<Anisotropy>,
+}
+
+impl SurfaceReconstruction {
+    /// Initializes a surface reconstruction with the given kernel radius and grid cell width,
+    /// and an iso-level of `0.5`.
+    ///
+    /// The kernel radius of the `LiquidWorld` (`LiquidWorld::h`) and a cell width equal to the
+    /// particle radius are good starting points.
+    pub fn new(kernel_radius: Real, cell_width: Real) -> Self {
+        Self {
+            kernel_radius,
+            cell_width,
+            iso_level: na::convert::<_, Real>(0.5),
+            anisotropy: None,
+        }
+    }
+
+    /// Sets the value of the scalar field on the reconstructed surface.
+    pub fn with_iso_level(mut self, iso_level: Real) -> Self {
+        self.iso_level = iso_level;
+        self
+    }
+
+    /// Sets the parameters of the anisotropic kernels of the particles.
+    pub fn with_anisotropy(mut self, anisotropy: Anisotropy) -> Self {
+        self.anisotropy = Some(anisotropy);
+        self
+    }
+
+    /// Reconstructs the surface of the given fluid.
+    pub fn reconstruct_fluid(&self, fluid: &Fluid) -> SurfaceMesh {
+        self.reconstruct(&fluid.positions, &fluid.volumes)
+    }
+
+    /// Reconstructs the surface of a set of particles with the given positions and volumes.
+    pub fn reconstruct(&self, positions: &[Point<Real>], volumes: &[Real]) -> SurfaceMesh {
+        assert_eq!(
+            positions.len(),
+            volumes.len(),
+            "The provided positions and volumes arrays must have the same length."
+        );
+
+        let field = if let Some(anisotropy) = &self.anisotropy {
+            let kernels = anisotropy.compute(self.kernel_radius, positions);
+            self.sample_field(&kernels.centers, volumes, Some(&kernels.matrices))
+        } else {
+            self.sample_field(positions, volumes, None)
+        };
+        let mut builder = MeshBuilder {
+            reconstruction: self,
+            field: &field,
+            vertex_ids: HashMap::new(),
+            mesh: SurfaceMesh::default(),
+        };
+
+        // Every cell having at least one node with a non-zero field.
+        let mut cells: Vec<_> = field
+            .keys()
+            .flat_map(|node| (0..1 << DIM).map(move |corner| node - corner_offset(corner)))
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        // Sort the cells so that the output does not depend on the hash map ordering.
+        cells.sort_unstable_by(|a, b| a.coords.as_slice().cmp(b.coords.as_slice()));
+
+        for cell in cells {
+            for simplex in SIMPLICES {
+                builder.polygonize_simplex(&cell, simplex);
+            }
+        }
+
+        builder.mesh
+    }
+
+    // Computes the scalar field and its gradient at every grid node within the kernel radius
+    // of at least one particle. The kernel of the `i`-th particle is transformed by `matrices[i]`,
+    // if provided.
+    fn sample_field(
+        &self,
+        positions: &[Point<Real>],
+        volumes: &[Real],
+        matrices: Option<&[Matrix<Real>]>,
+    ) -> HashMap<Point<i64>, (Real, Vector<Real>)> {
+        let mut field = HashMap::new();
+
+        for (i, (position, volume)) in positions.iter().zip(volumes.iter()).enumerate() {
+            let matrix = matrices.map(|m| m[i]).unwrap_or_else(Matrix::identity);
+            // The norm of the inverse bounds the distance the kernel is stretched to.
+            let reach = self.kernel_radius
+                * matrix
+                    .try_inverse()
+                    .map(|m| m.norm())
+                    .unwrap_or_else(na::one::<Real>);
+            let mins = self.node_key(&(position - Vector::repeat(reach)), true);
+            let maxs = self.node_key(&(position + Vector::repeat(reach)), false);
+
+            for node in NodeRange::new(mins, maxs) {
+                let dpos = matrix * (self.node_position(&node) - position);
+                let weight = CubicSplineKernel::apply(dpos, self.kernel_radius);
+
+                if weight > na::zero::<Real>() {
+                    let gradient = matrix * CubicSplineKernel::apply_diff(dpos, self.kernel_radius);
+                    let value = field
+                        .entry(node)
+                        .or_insert((na::zero::<Real>(), Vector::zeros()));
+                    value.0 += weight * *volume;
+                    value.1 += gradient * *volume;
+                }
+            }
+        }
+
+        field
+    }
+
+    fn node_key(&self, point: &Point<Real>, floor: bool) -> Point<i64> {
+        point.map(|e| {
+            let e = e / self.cell_width;
+            let e = if floor { e.floor() } else { e.ceil() };
+            na::try_convert::<Real, f64>(e).unwrap() as i64
+        })
+    }
+
+    fn node_position(&self, node: &Point<i64>) -> Point<Real> {
+        node.map(|e| na::convert::<_, Real>(e as f64) * self.cell_width)
+    }
+}
+
+// The offset of the `corner`-th corner of a cell, with one bit per axis.
+fn corner_offset(corner: usize) -> Vector<i64> {
+    Vector::from_fn(|axis, _| ((corner >> axis) & 1) as i64)
+}
+
+// The decomposition of a cell into simplices sharing its main diagonal, given as cell corners.
+// Neighboring cells split their shared faces the same way, so the mesh has no cracks.
+#[cfg(feature = "dim2")]
+const SIMPLICES: [[usize; 3]; 2] = [[0, 1, 3], [0, 2, 3]];
+#[cfg(feature = "dim3")]
+const SIMPLICES: [[usize; 4]; 6] = [
+    [0, 1, 3, 7],
+    [0, 1, 5, 7],
+    [0, 2, 3, 7],
+    [0, 2, 6, 7],
+    [0, 4, 5, 7],
+    [0, 4, 6, 7],
+];
+
+struct MeshBuilder<'a> {
+    reconstruction: &'a SurfaceReconstruction,
+    field: &'a HashMap<Point<i64>, (Real, Vector<Real>)>,
+    // Maps a grid edge to the vertex placed on it, so that vertices are shared between elements.
+    vertex_ids: HashMap<(Point<i64>, Point<i64>), u32>,
+    mesh: SurfaceMesh,
+}
+
+impl<'a> MeshBuilder<'a> {
+    fn field_at(&self, node: &Point<i64>) -> (Real, Vector<Real>) {
+        self.field
+            .get(node)
+            .copied()
+            .unwrap_or((na::zero::<Real>(), Vector::zeros()))
+    }
+
+    // The index of the vertex where the iso-surface crosses the edge between the
+    // nodes `a` (inside) and `b` (outside).
+    fn edge_vertex(&mut self, a: Point<i64>, b: Point<i64>) -> u32 {
+        let key = if a.coords.as_slice() < b.coords.as_slice() {
+            (a, b)
+        } else {
+            (b, a)
+        };
+
+        if let Some(id) = self.vertex_ids.get(&key) {
+            return *id;
+        }
+
+        let (value_a, gradient_a) = self.field_at(&a);
+        let (value_b, gradient_b) = self.field_at(&b);
+        let t = ((self.reconstruction.iso_level - value_a) / (value_b - value_a))
+            .max(na::zero::<Real>())
+            .min(na::one::<Real>());
+        let pos_a = self.reconstruction.node_position(&a);
+        let pos_b = self.reconstruction.node_position(&b);
+        // The field decreases outward, so the normal is opposite to its gradient.
+        let normal = -gradient_a.lerp(&gradient_b, t);
+
+        let id = self.mesh.vertices.len() as u32;
+        self.mesh.vertices.push(pos_a + (pos_b - pos_a) * t);
+        self.mesh.normals.push(
+            normal
+                .try_normalize(Real::EPSILON)
+                .unwrap_or_else(Vector::zeros),
+        );
+        let _ = self.vertex_ids.insert(key, id);
+        id
+    }
+
+    fn polygonize_simplex(&mut self, cell: &Point<i64>, simplex: [usize; DIM + 1]) {
+        let nodes = simplex.map(|corner| cell + corner_offset(corner));
+        let inside = nodes.map(|node| self.field_at(&node).0 >= self.reconstruction.iso_level);
+        let inside_nodes: Vec<_> = (0..DIM + 1).filter(|i| inside[*i]).collect();
+        let outside_nodes: Vec<_> = (0..DIM + 1).filter(|i| !inside[*i]).collect();
+
+        if inside_nodes.is_empty() || outside_nodes.is_empty() {
+            return;
+        }
+
+        let mut crossings = Vec::new();
+
+        for i in &inside_nodes {
+            for o in &outside_nodes {
+                crossings.push(self.edge_vertex(nodes[*i], nodes[*o]));
+            }
+        }
+
+        #[cfg(feature = "dim2")]
+        self.push_element([crossings[0], crossings[1]]);
+
+        #[cfg(feature = "dim3")]
+        if crossings.len() == 3 {
+            self.push_element([crossings[0], crossings[1], crossings[2]]);
+        } else {
+            // Two nodes inside and two outside: the crossings (i0o0, i0o1, i1o0, i1o1) form a quad.
+            self.push_element([crossings[0], crossings[1], crossings[3]]);
+            self.push_element([crossings[0], crossings[3], crossings[2]]);
+        }
+    }
+
+    // Adds the element to the mesh, flipping it if needed so it faces outside of the fluid.
+    fn push_element(&mut self, mut element: [u32; DIM]) {
+        let vertex = |i: u32| self.mesh.vertices[i as usize];
+        let normal: Vector<Real> = element.iter().map(|i| self.mesh.normals[*i as usize]).sum();
+
+        #[cfg(feature = "dim2")]
+        let element_normal = {
+            let dir = vertex(element[1]) - vertex(element[0]);
+            Vector::new(dir.y, -dir.x)
+        };
+        #[cfg(feature = "dim3")]
+        let element_normal = (vertex(element[1]) - vertex(element[0]))
+            .cross(&(vertex(element[2]) - vertex(element[0])));
+
+        if element_normal.dot(&normal) < na::zero::<Real>() {
+            element.swap(0, 1);
+        }
+
+        self.mesh.indices.push(element);
+    }
+}
+
+// Iterator through all the grid nodes in the given inclusive range.
+struct NodeRange {
+    mins: Point<i64>,
+    maxs: Point<i64>,
+    curr: Point<i64>,
+    done: bool,
+}
+
+impl NodeRange {
+    fn new(mins: Point<i64>, maxs: Point<i64>) -> Self {
+        Self {
+            mins,
+            maxs,
+            curr: mins,
+            done: false,
+        }
+    }
+}
+
+impl Iterator for NodeRange {
+    type Item = Point<i64>;
+
+    fn next(&mut self) -> Option<Point<i64>> {
+        if self.done {
+            return None;
+        }
+
+        let result = self.curr;
+
+        for i in 0..DIM {
+            if self.curr[i] < self.maxs[i] {
+                self.curr[i] += 1;
+                return Some(result);
+            }
+
+            self.curr[i] = self.mins[i];
+        }
+
+        self.done = true;
+        Some(result)
+    }
+}
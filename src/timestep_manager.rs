@@ -1,21 +1,37 @@
-#![allow(dead_code)] // NOTE: keep this until we fix CFL
-
 use approx::AbsDiffEq;
+use na::RealField;
 use num::Zero;
 
 use crate::math::Real;
 use crate::object::Fluid;
 
 /// Structure responsible for regulating the timestep length of the simulation.
+///
+/// Each step given to `LiquidWorld::step` is split into substeps whose length satisfies the CFL
+/// condition: no particle may travel more than `cfl_coeff` times the particle diameter during a
+/// single substep. The accelerations of the particles (gravity and non-pressure forces) limit the
+/// substep length too. The number of substeps is clamped between `min_num_substeps` and
+/// `max_num_substeps`, so the CFL condition may be violated if the particles move too fast for the
+/// maximum number of substeps. The pressure solver and the non-pressure forces may limit the
+/// substep length further (see `PressureSolver::max_substep` and `NonPressureForce::max_substep`).
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
 pub struct TimestepManager {
     cfl_coeff: Real,
     min_num_substeps: u32,
     max_num_substeps: u32,
+    max_substep: Option<Real>,
+    pressure_substep: Option<Real>,
     dt: Real,
     inv_dt: Real,
+    predicted_dt: Real,
     total_step_size: Real,
     remaining_time: Real,
     particle_radius: Real,
+    kernel_radius: Real,
+    substeps: Vec<Real>,
 }
 
 impl TimestepManager {
@@ -25,30 +41,143 @@ impl TimestepManager {
             cfl_coeff: na::convert::<_, Real>(0.4),
             min_num_substeps: 1,
             max_num_substeps: 10,
+            max_substep: None,
+            pressure_substep: None,
             particle_radius,
+            kernel_radius: particle_radius * na::convert::<_, Real>(4.0),
             dt: na::zero::<Real>(),
             inv_dt: na::zero::<Real>(),
+            predicted_dt: na::zero::<Real>(),
             total_step_size: na::zero::<Real>(),
             remaining_time: na::zero::<Real>(),
+            substeps: Vec::new(),
         }
     }
 
-    fn max_substep(&self, fluids: &[Fluid]) -> Real {
-        let mut max_sq_vel = na::zero::<Real>();
-        for (v, a) in fluids
+    /// Sets the kernel radius given to the non-pressure forces to compute their substep limits.
+    pub(crate) fn set_kernel_radius(&mut self, kernel_radius: Real) {
+        self.kernel_radius = kernel_radius;
+    }
+
+    /// Sets the substep limit of the pressure solver.
+    pub(crate) fn set_pressure_substep(&mut self, pressure_substep: Option<Real>) {
+        self.pressure_substep = pressure_substep;
+    }
+
+    // The smallest substep limit of the non-pressure forces of all the fluids.
+    fn max_nonpressure_substep(&self, fluids: &[Fluid]) -> Option<Real> {
+        fluids
             .iter()
-            .flat_map(|f| f.velocities.iter().zip(f.accelerations.iter()))
-        {
-            max_sq_vel = max_sq_vel.max((v + a * self.remaining_time).norm_squared());
+            .flat_map(|fluid| {
+                fluid.nonpressure_forces.iter().filter_map(move |force| {
+                    force.max_substep(fluid.kernel_radius(self.kernel_radius), fluid)
+                })
+            })
+            .reduce(|a, b| a.min(b))
+    }
+
+    /// The largest substep length satisfying the CFL condition for the current velocities and
+    /// accelerations of the fluid particles.
+    ///
+    /// The distance travelled by the particles of each fluid is compared to their own diameter,
+    /// so fine particles get shorter substeps than coarse ones.
+    fn max_cfl_substep(&self, fluids: &[Fluid]) -> Real {
+        let mut substep = Real::max_value().unwrap();
+
+        for fluid in fluids {
+            let mut max_sq_vel = na::zero::<Real>();
+            let mut max_sq_acc = na::zero::<Real>();
+
+            for v in &fluid.velocities {
+                max_sq_vel = max_sq_vel.max(v.norm_squared());
+            }
+
+            for a in &fluid.accelerations {
+                max_sq_acc = max_sq_acc.max(a.norm_squared());
+            }
+
+            let diameter = self.fluid_particle_radius(fluid) * na::convert::<_, Real>(2.0);
+
+            if !max_sq_vel.is_zero() {
+                substep = substep.min(self.cfl_coeff * diameter / max_sq_vel.sqrt());
+            }
+
+            if !max_sq_acc.is_zero() {
+                // The distance travelled due to the acceleration alone is `a * dt² / 2`.
+                substep = substep.min((self.cfl_coeff * diameter / max_sq_acc.sqrt()).sqrt());
+            }
+        }
+
+        substep
+    }
+
+    // The particle radius of the given fluid, or the one of the liquid world if the fluid has
+    // the same resolution.
+    fn fluid_particle_radius(&self, fluid: &Fluid) -> Real {
+        if fluid.kernel_radius.is_some() {
+            fluid.particle_radius()
+        } else {
+            self.particle_radius
+        }
+    }
+
+    /// The largest stable substep length for an explicit viscosity with the given kinematic
+    /// viscosity (the dynamic viscosity divided by the density).
+    ///
+    /// This is `0.125 h² / ν`, where `h` is the smoothing length, i.e., half of the kernel radius.
+    pub fn viscosity_substep_limit(kinematic_viscosity: Real, kernel_radius: Real) -> Real {
+        let smoothing_length = kernel_radius * na::convert::<_, Real>(0.5);
+
+        if kinematic_viscosity <= na::zero::<Real>() {
+            Real::max_value().unwrap()
+        } else {
+            na::convert::<_, Real>(0.125) * smoothing_length * smoothing_length
+                / kinematic_viscosity
         }
+    }
 
-        self.particle_radius * na::convert::<_, Real>(2.0) / max_sq_vel.sqrt() * self.cfl_coeff
+    /// The largest stable substep length for an explicit surface tension with the given surface
+    /// tension coefficient and fluid density.
+    ///
+    /// This is the capillary limit `0.25 sqrt(ρ h³ / (2π σ))` of Morris 2000, where `h` is the
+    /// smoothing length, i.e., half of the kernel radius.
+    pub fn surface_tension_substep_limit(
+        surface_tension: Real,
+        density: Real,
+        kernel_radius: Real,
+    ) -> Real {
+        let smoothing_length = kernel_radius * na::convert::<_, Real>(0.5);
+
+        if surface_tension <= na::zero::<Real>() {
+            Real::max_value().unwrap()
+        } else {
+            na::convert::<_, Real>(0.25)
+                * (density * smoothing_length.powi(3) / (Real::two_pi() * surface_tension)).sqrt()
+        }
+    }
+
+    /// The largest stable substep length for an explicit pressure solver with the given speed of
+    /// sound.
+    ///
+    /// This is the acoustic limit `0.4 h / c` of Monaghan 1992, where `h` is the smoothing length,
+    /// i.e., half of the kernel radius.
+    pub fn acoustic_substep_limit(speed_of_sound: Real, kernel_radius: Real) -> Real {
+        let smoothing_length = kernel_radius * na::convert::<_, Real>(0.5);
+
+        if speed_of_sound <= na::zero::<Real>() {
+            Real::max_value().unwrap()
+        } else {
+            na::convert::<_, Real>(0.4) * smoothing_length / speed_of_sound
+        }
     }
 
     /// Resets the remaining time of the timestep manager.
+    ///
+    /// This also clears the list of substeps taken.
     pub fn reset(&mut self, total_step_size: Real) {
         self.total_step_size = total_step_size;
         self.remaining_time = total_step_size;
+        self.substeps.clear();
     }
 
     /// Checks if all the time of this timestep has been consumed.
@@ -57,12 +186,90 @@ impl TimestepManager {
         self.remaining_time <= Real::default_epsilon()
     }
 
+    /// The time remaining before the end of the current step.
+    #[inline]
+    pub fn remaining_time(&self) -> Real {
+        self.remaining_time
+    }
+
     /// The current substep length.
     #[inline]
     pub fn dt(&self) -> Real {
         self.dt
     }
 
+    /// An estimate of the length of the upcoming substep.
+    ///
+    /// During the advection prediction, `Self::dt` is still the length of the previous substep
+    /// (zero at the first substep), since the length of a substep depends on the accelerations
+    /// computed by the non-pressure forces. This estimate is computed at the beginning of each
+    /// substep, from the accelerations of the particles before the non-pressure forces are
+    /// applied, so the actual substep may be shorter. Implicit non-pressure forces should use it
+    /// as the length of the substep they are integrated over.
+    #[inline]
+    pub fn predicted_dt(&self) -> Real {
+        self.predicted_dt
+    }
+
+    /// Estimates the length of the upcoming substep (see `Self::predicted_dt`).
+    pub(crate) fn predict_substep(&mut self, fluids: &[Fluid]) {
+        self.predicted_dt = self.compute_substep(fluids);
+    }
+
+    /// The CFL coefficient, i.e., the fraction of the particle diameter a particle
+    /// is allowed to travel during a single substep.
+    #[inline]
+    pub fn cfl_coeff(&self) -> Real {
+        self.cfl_coeff
+    }
+
+    /// Sets the CFL coefficient.
+    ///
+    /// This defaults to `0.4`.
+    pub fn set_cfl_coeff(&mut self, cfl_coeff: Real) {
+        self.cfl_coeff = cfl_coeff;
+    }
+
+    /// The minimum number of substeps per step.
+    #[inline]
+    pub fn min_num_substeps(&self) -> u32 {
+        self.min_num_substeps
+    }
+
+    /// The maximum number of substeps per step.
+    #[inline]
+    pub fn max_num_substeps(&self) -> u32 {
+        self.max_num_substeps
+    }
+
+    /// Sets the minimum and maximum number of substeps per step.
+    ///
+    /// These default to `1` and `10`. Setting both to the same value disables adaptive
+    /// substepping.
+    pub fn set_num_substeps_range(&mut self, min_num_substeps: u32, max_num_substeps: u32) {
+        assert!(
+            min_num_substeps >= 1 && min_num_substeps <= max_num_substeps,
+            "Invalid range of number of substeps."
+        );
+        self.min_num_substeps = min_num_substeps;
+        self.max_num_substeps = max_num_substeps;
+    }
+
+    /// The user-defined upper bound of the substep length, if any.
+    #[inline]
+    pub fn max_substep(&self) -> Option<Real> {
+        self.max_substep
+    }
+
+    /// Sets an upper bound of the substep length, in addition to the CFL condition, e.g., one of
+    /// `TimestepManager::viscosity_substep_limit` or
+    /// `TimestepManager::surface_tension_substep_limit`.
+    ///
+    /// This bound is still subject to the maximum number of substeps.
+    pub fn set_max_substep(&mut self, max_substep: Option<Real>) {
+        self.max_substep = max_substep;
+    }
+
     /// The inverse of the current substep length.
     ///
     /// If the substep length is zero, this inverse is also zero.
@@ -71,6 +278,18 @@ impl TimestepManager {
         self.inv_dt
     }
 
+    /// The lengths of the substeps taken since the beginning of the current step.
+    #[inline]
+    pub fn substeps(&self) -> &[Real] {
+        &self.substeps
+    }
+
+    /// The number of substeps taken since the beginning of the current step.
+    #[inline]
+    pub fn num_substeps(&self) -> usize {
+        self.substeps.len()
+    }
+
     /// Advance to the next substep.
     #[inline]
     pub fn advance(&mut self, fluids: &[Fluid]) {
@@ -82,14 +301,74 @@ impl TimestepManager {
             na::one::<Real>() / substep
         };
         self.remaining_time -= self.dt;
+        self.substeps.push(self.dt);
     }
 
-    fn compute_substep(&self, _fluids: &[Fluid]) -> Real {
-        return self.total_step_size;
-        // FIXME
-        //        let min_substep = self.total_step_size / na::convert::<_, Real>(self.max_num_substeps as f64);
-        //        let max_substep = self.total_step_size / na::convert::<_, Real>(self.min_num_substeps as f64);
-        //        let computed_substep = self.max_substep(fluids);
-        //        na::clamp(computed_substep, min_substep, max_substep)
+    fn compute_substep(&self, fluids: &[Fluid]) -> Real {
+        let min_substep =
+            self.total_step_size / na::convert::<_, Real>(self.max_num_substeps as f64);
+        let max_substep =
+            self.total_step_size / na::convert::<_, Real>(self.min_num_substeps as f64);
+        let mut computed_substep = self.max_cfl_substep(fluids);
+
+        if let Some(limit) = self.max_substep {
+            computed_substep = computed_substep.min(limit);
+        }
+
+        if let Some(limit) = self.pressure_substep {
+            computed_substep = computed_substep.min(limit);
+        }
+
+        if let Some(limit) = self.max_nonpressure_substep(fluids) {
+            computed_substep = computed_substep.min(limit);
+        }
+
+        let substep = na::clamp(computed_substep, min_substep, max_substep);
+        // Rounding errors may leave some time after `max_num_substeps` substeps of length
+        // `min_substep`, so the last allowed substep always finishes the step.
+        let is_last_substep = self.substeps.len() + 1 >= self.max_num_substeps as usize;
+
+        if is_last_substep || substep >= self.remaining_time - Real::default_epsilon() {
+            self.remaining_time
+        } else if substep * na::convert::<_, Real>(2.0) > self.remaining_time {
+            // Split the remaining time evenly to avoid a tiny last substep.
+            self.remaining_time * na::convert::<_, Real>(0.5)
+        } else {
+            substep
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::TimestepManager;
+    use crate::math::Real;
+
+    #[test]
+    fn substeps_respect_the_maximum_count() {
+        let mut timestep = TimestepManager::new(0.1);
+
+        for max_num_substeps in 1..=12 {
+            timestep.set_num_substeps_range(1, max_num_substeps);
+
+            for total in [0.01, 0.3, 1.0, 3.7, 100.0, 1234.5, 1.0e5] {
+                for fraction in 0..=40 {
+                    timestep.set_max_substep(Some(total * fraction as Real / 37.0));
+                    timestep.reset(total);
+
+                    while !timestep.is_done() {
+                        timestep.advance(&[]);
+                    }
+
+                    assert!(
+                        timestep.num_substeps() <= max_num_substeps as usize,
+                        "{:?}",
+                        timestep.substeps()
+                    );
+                    let covered: Real = timestep.substeps().iter().sum();
+                    assert!((covered - total).abs() <= total * 1.0e-5);
+                }
+            }
+        }
     }
 }
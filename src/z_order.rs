@@ -1,63 +1,97 @@
-use crate::math::{Point, Real};
-use num_traits::float::FloatCore;
-use std::cmp::Ordering;
+use crate::math::{Point, Real, DIM};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+// The number of bits of each coordinate in a Morton code.
+const BITS_PER_DIM: u32 = 64 / DIM as u32;
 
 pub fn apply_permutation<T: Clone>(permutation: &[usize], data: &[T]) -> Vec<T> {
     permutation.iter().map(|i| data[*i].clone()).collect()
 }
 
+/// Computes the permutation sorting the given points along a Z-order curve.
+///
+/// The points are quantized on a regular grid spanning their bounding box, and sorted by the
+/// Morton codes of their grid cells. Points with the same code keep their relative order, and
+/// non-finite coordinates are mapped to the first cell.
 pub fn compute_points_z_order(points: &[Point<Real>]) -> Vec<usize> {
-    let mut indices: Vec<_> = (0..points.len()).collect();
-    indices.sort_unstable_by(|i, j| {
-        z_order_floats(points[*i].coords.as_slice(), points[*j].coords.as_slice())
-            .unwrap_or(Ordering::Equal)
-    });
-    indices
-}
+    let mut mins = Point::from([Real::MAX; DIM]);
+    let mut maxs = Point::from([-Real::MAX; DIM]);
 
-// Fast construction of k-Nearest Neighbor Graphs for Point Clouds
-// Michael Connor, Piyush Kumar
-// Algorithm 1
-//
-// http://compgeom.com/~piyush/papers/tvcg_stann.pdf
-pub fn z_order_floats(p1: &[Real], p2: &[Real]) -> Option<Ordering> {
-    assert_eq!(
-        p1.len(),
-        p2.len(),
-        "Cannot compare array with different lengths."
-    );
-    let mut x = 0;
-    let mut dim = 0;
-
-    for j in 0..p1.len() {
-        let y = xor_msb_float(p1[j], p2[j]);
-        if x < y {
-            x = y;
-            dim = j;
+    for pt in points {
+        for k in 0..DIM {
+            if pt[k].is_finite() {
+                mins[k] = mins[k].min(pt[k]);
+                maxs[k] = maxs[k].max(pt[k]);
+            }
         }
     }
 
-    p1[dim].partial_cmp(&p2[dim])
+    let extent = (maxs - mins).max();
+    let max_cell = (1u64 << BITS_PER_DIM) - 1;
+    let scale = if extent > 0.0 {
+        max_cell as Real / extent
+    } else {
+        0.0
+    };
+
+    let mut keys: Vec<_> = points
+        .iter()
+        .enumerate()
+        .map(|(i, pt)| {
+            let mut cell = [0; DIM];
+
+            for k in 0..DIM {
+                // `as` saturates, and maps NaN to zero.
+                cell[k] = (((pt[k] - mins[k]) * scale) as u64).min(max_cell);
+            }
+
+            (morton_code(cell), i)
+        })
+        .collect();
+
+    #[cfg(not(feature = "parallel"))]
+    keys.sort_unstable();
+    #[cfg(feature = "parallel")]
+    keys.par_sort_unstable();
+
+    keys.into_iter().map(|(_, i)| i).collect()
 }
 
-fn xor_msb_float(a: Real, b: Real) -> i16 {
-    let fa = na::try_convert::<_, f64>(a).unwrap();
-    let fb = na::try_convert::<_, f64>(b).unwrap();
-    let (mantissa1, exponent1, _sign1) = fa.integer_decode();
-    let (mantissa2, exponent2, _sign2) = fb.integer_decode();
-    let x = exponent1; // To keep the same notation as the paper.
-    let y = exponent2; // To keep the same notation as the paper.
-
-    if x == y {
-        let z = msdb(mantissa1, mantissa2);
-        x - z
-    } else if y < x {
-        x
-    } else {
-        y
+// Interleaves the bits of the cell coordinates, the most significant bit of the first coordinate
+// being the most significant bit of the code.
+fn morton_code(cell: [u64; DIM]) -> u64 {
+    let mut code = 0;
+
+    for bit in (0..BITS_PER_DIM).rev() {
+        for coord in cell {
+            code = (code << 1) | ((coord >> bit) & 1);
+        }
     }
+
+    code
 }
 
-fn msdb(x: u64, y: u64) -> i16 {
-    64i16 - (x ^ y).leading_zeros() as i16
+#[cfg(test)]
+mod test {
+    use super::compute_points_z_order;
+    use crate::math::{Point, Real, DIM};
+
+    #[test]
+    fn z_order_visits_quadrants_contiguously() {
+        let n = 8;
+        let mut points = Vec::new();
+
+        for i in 0..n * n {
+            let (x, y) = (i / n, i % n);
+            points.push(Point::from_slice(&[x as Real, y as Real, 0.0][..DIM]));
+        }
+
+        let order = compute_points_z_order(&points);
+        let quadrant = |i: usize| (points[i][0] >= 4.0, points[i][1] >= 4.0);
+
+        for quarter in order.chunks(order.len() / 4) {
+            assert!(quarter.iter().all(|i| quadrant(*i) == quadrant(quarter[0])));
+        }
+    }
 }